@@ -11,8 +11,10 @@ $input = $input | Out-String
 try {
     $hookData = $input | ConvertFrom-Json
     $stopHookActive = $hookData.stop_hook_active
+    $sessionId = $hookData.session_id
 } catch {
     $stopHookActive = $false
+    $sessionId = ""
 }
 
 # Check if this is already a continuation (prevent infinite loops)
@@ -22,6 +24,16 @@ if ($stopHookActive -eq $true) {
     exit 0
 }
 
+# Signal ralph-tui through the control file (authoritative; output scraping is the fallback)
+# --parallel lanes each get their own control file name via RALPH_SIGNAL_FILE
+# The session id lets continueConversation resume this conversation next iteration
+if ($env:RALPH_TASK_DIR -and (Test-Path $env:RALPH_TASK_DIR)) {
+    $timestamp = [DateTimeOffset]::UtcNow.ToUnixTimeSeconds()
+    $signalName = if ($env:RALPH_SIGNAL_FILE) { $env:RALPH_SIGNAL_FILE } else { ".ralph-signal" }
+    $signalPath = Join-Path $env:RALPH_TASK_DIR $signalName
+    Set-Content -Path $signalPath -Value "{`"signal`": `"iteration-complete`", `"timestamp`": $timestamp, `"session_id`": `"$sessionId`"}" -Encoding UTF8
+}
+
 # Exit Claude to trigger next iteration
 # ralph-tui will detect child_exited and restart
 Write-Output '{"continue": false, "stopReason": "Iteration complete - ralph-tui will start next iteration"}'
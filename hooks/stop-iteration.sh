@@ -17,6 +17,14 @@ if [ "$STOP_HOOK_ACTIVE" = "true" ]; then
   exit 0
 fi
 
+# Signal ralph-tui through the control file (authoritative; output scraping is the fallback)
+# --parallel lanes each get their own control file name via RALPH_SIGNAL_FILE
+# The session id lets continueConversation resume this conversation next iteration
+if [ -n "$RALPH_TASK_DIR" ] && [ -d "$RALPH_TASK_DIR" ]; then
+  SESSION_ID=$(echo "$INPUT" | jq -r '.session_id // empty')
+  echo "{\"signal\": \"iteration-complete\", \"timestamp\": $(date +%s), \"session_id\": \"$SESSION_ID\"}" > "$RALPH_TASK_DIR/${RALPH_SIGNAL_FILE:-.ralph-signal}"
+fi
+
 # Exit Claude to trigger next iteration
 # ralph-tui will detect child_exited and restart
 echo '{"continue": false, "stopReason": "Iteration complete - ralph-tui will start next iteration"}'
@@ -0,0 +1,149 @@
+//! UI cost while the agent floods its terminal
+//!
+//! A writer thread feeds the App's PTY state as fast as it can, like the reader
+//! thread during a long test run, while a loop shaped like `run` (50ms input
+//! poll) draws the running screen on a TestBackend: once drawing every pass, as
+//! before, and once only when the `RedrawSchedule` says so. Prints frames drawn
+//! and the share of wall time the UI thread spent building and drawing them.
+//!
+//!   cargo bench --bench output_flood
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use ralph_tui::agents::AgentProfile;
+use ralph_tui::app::{App, DEFAULT_NUDGE_MESSAGE};
+use ralph_tui::git::SyncStrategy;
+use ralph_tui::keymap::KeyMap;
+use ralph_tui::pty::{lock_pty, DEFAULT_ERROR_PATTERNS, DEFAULT_PROMPT_PATTERN};
+use ralph_tui::{render, CliConfig, ValueSource};
+use ratatui::{backend::TestBackend, Terminal};
+use regex::Regex;
+
+const ROWS: u16 = 48;
+const COLS: u16 = 160;
+const RUN_FOR: Duration = Duration::from_secs(3);
+
+/// The event poll timeout of the running loop
+const POLL: Duration = Duration::from_millis(50);
+
+fn bench_app(task_dir: PathBuf) -> App {
+    let prd_path = task_dir.join("prd.json");
+    std::fs::write(
+        &prd_path,
+        r#"{"project": "bench", "taskDir": "tasks/bench", "type": "feature", "description": "",
+            "userStories": [{"id": "US-001", "title": "Flood", "description": "", "acceptanceCriteria": ["a"], "priority": 1, "passes": false, "notes": ""}]}"#,
+    )
+    .unwrap();
+    App::new(ROWS - 3, COLS * 7 / 10 - 2, CliConfig {
+        task_dir,
+        prd_path,
+        max_iterations: 10,
+        max_iterations_source: ValueSource::Default,
+        rotate_threshold: 300,
+        rotate_threshold_source: ValueSource::Default,
+        skip_prompts: true,
+        events_socket: None,
+        metrics_addr: None,
+        once: false,
+        stuck_threshold: 3,
+        flat_after: 1200,
+        min_free_mb: 500,
+        behind_warn: 20,
+        sync_strategy: SyncStrategy::Merge,
+        idle_after: 5,
+        prompt_pattern: Regex::new(DEFAULT_PROMPT_PATTERN).unwrap(),
+        nudge_after: 0,
+        prd_idle_after: 0,
+        linger_ms: 0,
+        nudge_message: DEFAULT_NUDGE_MESSAGE.to_string(),
+        end_on_compaction: false,
+        squash_on_complete: false,
+        agent_args: Vec::new(),
+        model: None,
+        agent: AgentProfile::claude(),
+        agent_version: None,
+        keymap: KeyMap::default(),
+        targeted_stories: None,
+        kitty_keys: false,
+        key_guard: true,
+        terminal_title: true,
+        progress_strip: false,
+        no_sidebar: false,
+        parallel: None,
+        max_runtime: None,
+        error_patterns: DEFAULT_ERROR_PATTERNS.iter().map(|p| p.to_string()).collect(),
+        error_bell: false,
+        dangerous_commands: Vec::new(),
+        pause_on_prd_churn: false,
+        show_pty_size: false,
+        verify_criteria: false,
+        verify_timeout_secs: ralph_tui::verify::DEFAULT_VERIFY_TIMEOUT_SECS,
+        verified_match: None,
+        footer_tips: false,
+        prompt_via_stdin: false,
+        env: Default::default(),
+        on_iteration_end: None,
+        on_complete: None,
+    })
+}
+
+/// Flood the App's output for RUN_FOR, drawing every pass or only when due: (frames, UI busy time)
+fn flood(app: &mut App, terminal: &mut Terminal<TestBackend>, scheduled: bool) -> (u32, Duration) {
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let (pty_state, redraw, stop) = (Arc::clone(&app.pty_state), app.redraw.flag(), Arc::clone(&stop));
+        std::thread::spawn(move || {
+            let mut n = 0u64;
+            while !stop.load(Ordering::Relaxed) {
+                let line = format!("test tests::case_{} ... ok\r\n", n);
+                let mut state = lock_pty(&pty_state);
+                state.parser.process(line.as_bytes());
+                state.append_output(line.as_bytes());
+                drop(state);
+                redraw.mark();
+                n += 1;
+            }
+        })
+    };
+
+    let (mut frames, mut busy) = (0, Duration::ZERO);
+    let start = Instant::now();
+    while start.elapsed() < RUN_FOR {
+        let pass = Instant::now();
+        if !scheduled || app.redraw.due() {
+            let snapshot = app.snapshot();
+            let mut offset = snapshot.story_scroll_offset;
+            terminal.draw(|frame| offset = render::draw_running(frame, &snapshot)).unwrap();
+            app.story_scroll_offset = offset;
+            frames += 1;
+        }
+        busy += pass.elapsed();
+        // No input arrives: the loop waits out the poll timeout
+        std::thread::sleep(POLL);
+    }
+    stop.store(true, Ordering::Relaxed);
+    writer.join().unwrap();
+    (frames, busy)
+}
+
+fn main() {
+    let task_dir = std::env::temp_dir().join(format!("ralph-bench-flood-{}", std::process::id()));
+    std::fs::create_dir_all(&task_dir).unwrap();
+    let mut app = bench_app(task_dir.clone());
+    let mut terminal = Terminal::new(TestBackend::new(COLS, ROWS)).unwrap();
+
+    let (before_frames, before_busy) = flood(&mut app, &mut terminal, false);
+    let (after_frames, after_busy) = flood(&mut app, &mut terminal, true);
+    let share = |busy: Duration| busy.as_secs_f64() / RUN_FOR.as_secs_f64() * 100.0;
+    println!(
+        "output_flood: every pass {} frames, UI busy {:.1}% | scheduled {} frames, UI busy {:.1}%",
+        before_frames,
+        share(before_busy),
+        after_frames,
+        share(after_busy)
+    );
+    let _ = std::fs::remove_dir_all(&task_dir);
+}
@@ -0,0 +1,145 @@
+//! Frame time with a very large PRD
+//!
+//! Builds an App over a 500-story prd.json and times `App::snapshot` plus
+//! `render::draw_running` on a 200-row TestBackend, the work done every frame.
+//!
+//!   cargo bench --bench story_list
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use ralph_tui::agents::AgentProfile;
+use ralph_tui::app::{App, DEFAULT_NUDGE_MESSAGE};
+use ralph_tui::keymap::KeyMap;
+use ralph_tui::git::SyncStrategy;
+use ralph_tui::pty::{DEFAULT_ERROR_PATTERNS, DEFAULT_PROMPT_PATTERN};
+use ralph_tui::{render, CliConfig, ValueSource};
+use ratatui::{backend::TestBackend, Terminal};
+use regex::Regex;
+
+const STORIES: usize = 500;
+const ROWS: u16 = 200;
+const COLS: u16 = 120;
+const FRAMES: u32 = 200;
+
+/// Frame time the story list has to stay under
+const TARGET: Duration = Duration::from_millis(3);
+
+/// prd.json with `count` stories of five criteria each, the first third passing
+fn large_prd(count: usize) -> String {
+    let stories: Vec<String> = (0..count)
+        .map(|i| {
+            let passes = i < count / 3;
+            let criteria: Vec<String> = (0..5)
+                .map(|c| format!(r#"{{"description": "criterion {} of story {}", "passes": {}}}"#, c, i, passes || c < 2))
+                .collect();
+            format!(
+                r#"{{"id": "US-{:03}", "title": "Story number {}", "description": "", "acceptanceCriteria": [{}], "priority": {}, "passes": {}, "notes": ""}}"#,
+                i + 1,
+                i + 1,
+                criteria.join(", "),
+                count - i, // reverse order so sorting has work to do
+                passes
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"project": "bench", "taskDir": "tasks/bench", "type": "feature", "description": "", "userStories": [{}]}}"#,
+        stories.join(",\n")
+    )
+}
+
+fn bench_app(task_dir: PathBuf) -> App {
+    let prd_path = task_dir.join("prd.json");
+    std::fs::write(&prd_path, large_prd(STORIES)).unwrap();
+    // PTY sized like main does for the Claude panel (70% of the width, minus borders and footer)
+    App::new(ROWS - 3, COLS * 7 / 10 - 2, CliConfig {
+        task_dir,
+        prd_path,
+        max_iterations: 10,
+        max_iterations_source: ValueSource::Default,
+        rotate_threshold: 300,
+        rotate_threshold_source: ValueSource::Default,
+        skip_prompts: true,
+        events_socket: None,
+        metrics_addr: None,
+        once: false,
+        stuck_threshold: 3,
+        flat_after: 1200,
+        min_free_mb: 500,
+        behind_warn: 20,
+        sync_strategy: SyncStrategy::Merge,
+        idle_after: 5,
+        prompt_pattern: Regex::new(DEFAULT_PROMPT_PATTERN).unwrap(),
+        nudge_after: 0,
+        prd_idle_after: 0,
+        linger_ms: 0,
+        nudge_message: DEFAULT_NUDGE_MESSAGE.to_string(),
+        end_on_compaction: false,
+        squash_on_complete: false,
+        agent_args: Vec::new(),
+        model: None,
+        agent: AgentProfile::claude(),
+        agent_version: None,
+        keymap: KeyMap::default(),
+        targeted_stories: None,
+        kitty_keys: false,
+        key_guard: true,
+        terminal_title: true,
+        progress_strip: false,
+        no_sidebar: false,
+        parallel: None,
+        max_runtime: None,
+        error_patterns: DEFAULT_ERROR_PATTERNS.iter().map(|p| p.to_string()).collect(),
+        error_bell: false,
+        dangerous_commands: Vec::new(),
+        pause_on_prd_churn: false,
+        show_pty_size: false,
+        verify_criteria: false,
+        verify_timeout_secs: ralph_tui::verify::DEFAULT_VERIFY_TIMEOUT_SECS,
+        verified_match: None,
+        footer_tips: false,
+        prompt_via_stdin: false,
+        env: Default::default(),
+        on_iteration_end: None,
+        on_complete: None,
+    })
+}
+
+fn main() {
+    let task_dir = std::env::temp_dir().join(format!("ralph-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&task_dir).unwrap();
+    let mut app = bench_app(task_dir.clone());
+    let mut terminal = Terminal::new(TestBackend::new(COLS, ROWS)).unwrap();
+
+    // Scroll through the middle of the list so visible cards aren't just the first ones
+    app.selected_story_index = STORIES / 2;
+    let mut frame = || {
+        let snapshot = app.snapshot();
+        let mut offset = snapshot.story_scroll_offset;
+        terminal.draw(|frame| offset = render::draw_running(frame, &snapshot)).unwrap();
+        app.story_scroll_offset = offset;
+    };
+    for _ in 0..20 {
+        frame(); // warm up
+    }
+    let mut times: Vec<Duration> = (0..FRAMES)
+        .map(|_| {
+            let start = Instant::now();
+            frame();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / FRAMES;
+    let p95 = times[(FRAMES as usize * 95) / 100];
+    println!(
+        "story_list: {} stories, {}x{}: mean {:.2?}, p95 {:.2?} per frame (target < {:?})",
+        STORIES, COLS, ROWS, mean, p95, TARGET
+    );
+    let _ = std::fs::remove_dir_all(&task_dir);
+    if mean > TARGET {
+        eprintln!("story_list: mean frame time is over target");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,39 @@
+//! Minimal consumer for the ralph-tui events socket
+//!
+//! Usage:
+//!   ralph-tui tasks/my-feature --events-socket /tmp/ralph.sock
+//!   cargo run --example events_consumer -- /tmp/ralph.sock
+//!
+//! Prints one summary line per newline-delimited JSON event.
+
+#[cfg(unix)]
+fn main() -> std::io::Result<()> {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/tmp/ralph.sock".to_string());
+    let stream = UnixStream::connect(&path)?;
+    eprintln!("Connected to {}", path);
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(event) => {
+                let name = event["event"].as_str().unwrap_or("unknown");
+                let timestamp = event["timestamp"].as_u64().unwrap_or(0);
+                println!("[{}] {:<16} {}", timestamp, name, line);
+            }
+            Err(e) => eprintln!("Invalid event ({}): {}", e, line),
+        }
+    }
+
+    eprintln!("ralph-tui closed the events socket");
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("The events socket requires Unix domain sockets");
+}
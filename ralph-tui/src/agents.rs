@@ -0,0 +1,358 @@
+//! Agent profiles: which coding agent ralph-tui launches, and how
+//!
+//! Built-in profiles cover claude, opencode and aider. More can be defined as
+//! `[agents.<name>]` tables in `~/.config/ralph/ralph.toml`; a profile named like
+//! a built-in replaces it.
+//!
+//! ```toml
+//! [agents.goose]
+//! binary = "goose"
+//! args = ["session", "{extra_args}"]
+//! prompt_via = "stdin"
+//! stop_sentinels = ["turn complete"]
+//! env = { GOOSE_MODE = "auto" }
+//! ```
+//!
+//! Argument templates use placeholders. `{prompt}`, `{prompt_file}`, `{model}` and
+//! `{settings}` are substituted inside an argument, and the whole argument is left
+//! out when the value is missing (no model chosen, settings.json not installed).
+//! An argument that is exactly `{extra_args}` expands to the --agent-args list, and
+//! one that is exactly `{resume_args}` to the profile's `resume_args` or
+//! `continue_args` when an iteration picks up the previous conversation
+//! (`continueConversation` in prd.json); `{session_id}` in `resume_args` is the id
+//! the stop hook passed on.
+//!
+//! A `{prompt}` argument longer than ARGV_PROMPT_LIMIT is left out and the prompt is
+//! pasted into the agent's terminal instead (pty::PendingPrompt), so a long
+//! PRD can't push the command line past the OS limit (`prompt_via_stdin = true` in
+//! ralph.toml does this for every prompt).
+
+use std::collections::BTreeMap;
+use std::io;
+
+use serde::Deserialize;
+
+use crate::pty::{CLAUDE_COMPACTION_SENTINELS, CLAUDE_STOP_SENTINELS, COMPLETION_SENTINEL};
+
+/// Profile used when none is chosen
+pub const DEFAULT_AGENT: &str = "claude";
+
+/// Longest prompt passed as a `{prompt}` argument, in bytes (well under the 128 KiB one
+/// argument may take on Linux and the 32 KiB command line on Windows)
+pub const ARGV_PROMPT_LIMIT: usize = 16 * 1024;
+
+/// How the prompt reaches the agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptDelivery {
+    #[default]
+    Argv,  // Through the argument template ({prompt} or {prompt_file})
+    Stdin, // Typed into the agent's terminal once it starts
+}
+
+/// How to launch one coding agent
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentProfile {
+    /// Taken from the table name in ralph.toml
+    #[serde(default)]
+    pub name: String,
+    pub binary: String,
+    /// Argument template (see the module docs for placeholders)
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub prompt_via: PromptDelivery,
+    /// Output text meaning every story is done
+    #[serde(default = "default_completion_sentinel")]
+    pub completion_sentinel: String,
+    /// Output text (matched case-insensitively) meaning the agent ended its turn
+    #[serde(default)]
+    pub stop_sentinels: Vec<String>,
+    /// Output text (matched case-insensitively) meaning the agent compacted its context
+    #[serde(default)]
+    pub compaction_sentinels: Vec<String>,
+    /// Extra environment variables (the RALPH_* variables always win)
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Arguments that resume the conversation with id `{session_id}`
+    #[serde(default)]
+    pub resume_args: Vec<String>,
+    /// Arguments that continue the latest conversation (when no id was passed on)
+    #[serde(default)]
+    pub continue_args: Vec<String>,
+}
+
+fn default_completion_sentinel() -> String {
+    COMPLETION_SENTINEL.to_string()
+}
+
+/// Values substituted into an argument template
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArgValues<'a> {
+    // None when the prompt is typed in instead
+    pub prompt: Option<&'a str>,
+    pub prompt_file: Option<&'a str>,
+    pub model: Option<&'a str>,
+    pub settings: Option<&'a str>,
+    pub extra_args: &'a [String],
+    pub resume_args: &'a [String],
+}
+
+impl AgentProfile {
+    /// Claude Code with the stop hook settings (the default agent)
+    pub fn claude() -> Self {
+        Self {
+            name: "claude".to_string(),
+            binary: "claude".to_string(),
+            args: template(&[
+                "--dangerously-skip-permissions",
+                "--settings={settings}",
+                "--model={model}",
+                "{resume_args}",
+                "{extra_args}",
+                "{prompt}",
+            ]),
+            prompt_via: PromptDelivery::Argv,
+            completion_sentinel: default_completion_sentinel(),
+            stop_sentinels: CLAUDE_STOP_SENTINELS.iter().map(|s| s.to_string()).collect(),
+            compaction_sentinels: CLAUDE_COMPACTION_SENTINELS.iter().map(|s| s.to_string()).collect(),
+            env: BTreeMap::new(),
+            resume_args: template(&["--resume", "{session_id}"]),
+            continue_args: template(&["--continue"]),
+        }
+    }
+
+    /// The argument list for one spawn
+    pub fn expand_args(&self, values: &ArgValues) -> Vec<String> {
+        let mut args = Vec::new();
+        for arg in &self.args {
+            if arg == "{extra_args}" {
+                args.extend(values.extra_args.iter().cloned());
+            } else if arg == "{resume_args}" {
+                args.extend(values.resume_args.iter().cloned());
+            } else if let Some(expanded) = substitute(arg, values) {
+                args.push(expanded);
+            }
+        }
+        args
+    }
+
+    /// Arguments that pick up the previous conversation: resume_args for a known session id,
+    /// else continue_args (None when the agent has neither)
+    pub fn conversation_args(&self, session_id: Option<&str>) -> Option<Vec<String>> {
+        match session_id {
+            Some(id) if !self.resume_args.is_empty() => {
+                Some(self.resume_args.iter().map(|arg| arg.replace("{session_id}", id)).collect())
+            }
+            _ if !self.continue_args.is_empty() => Some(self.continue_args.clone()),
+            _ => None,
+        }
+    }
+
+    /// How `prompt` reaches the agent on one spawn: a `{prompt}` argument becomes typed
+    /// input when the prompt is over ARGV_PROMPT_LIMIT or `always_stdin` is set
+    pub fn prompt_delivery(&self, prompt: &str, always_stdin: bool) -> PromptDelivery {
+        let inline = self.args.iter().any(|arg| arg.contains("{prompt}"));
+        if inline && (always_stdin || prompt.len() > ARGV_PROMPT_LIMIT) {
+            PromptDelivery::Stdin
+        } else {
+            self.prompt_via
+        }
+    }
+
+    /// Whether the prompt has to be written to a file for this agent
+    pub fn uses_prompt_file(&self) -> bool {
+        self.args.iter().any(|arg| arg.contains("{prompt_file}"))
+    }
+}
+
+fn template(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Replace the placeholders in one template argument (None drops the argument)
+/// Substituted values are never scanned again, so a prompt containing `{model}` stays as is.
+fn substitute(arg: &str, values: &ArgValues) -> Option<String> {
+    let mut out = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let placeholder = ["{prompt}", "{prompt_file}", "{model}", "{settings}"]
+            .into_iter()
+            .find(|p| after.starts_with(p));
+        let Some(placeholder) = placeholder else {
+            out.push('{');
+            rest = &after[1..];
+            continue;
+        };
+        let value = match placeholder {
+            "{prompt}" => values.prompt,
+            "{prompt_file}" => values.prompt_file,
+            "{model}" => values.model,
+            _ => values.settings,
+        };
+        out.push_str(value?);
+        rest = &after[placeholder.len()..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+/// The built-in profiles (claude first)
+pub fn builtin_profiles() -> Vec<AgentProfile> {
+    let plain = |name: &str, args: &[&str]| AgentProfile {
+        name: name.to_string(),
+        binary: name.to_string(),
+        args: template(args),
+        prompt_via: PromptDelivery::Argv,
+        completion_sentinel: default_completion_sentinel(),
+        stop_sentinels: Vec::new(),
+        compaction_sentinels: Vec::new(),
+        env: BTreeMap::new(),
+        resume_args: Vec::new(),
+        continue_args: Vec::new(),
+    };
+    vec![
+        AgentProfile::claude(),
+        // Runs one non-interactive turn; the iteration ends when it exits
+        plain("opencode", &["run", "--model={model}", "{extra_args}", "{prompt}"]),
+        // Reads the prompt from a file, answers yes to its confirmations and exits
+        plain("aider", &["--yes-always", "--model={model}", "{extra_args}", "--message-file={prompt_file}"]),
+    ]
+}
+
+/// Built-in profiles followed by the user's `[agents.<name>]` tables from ralph.toml
+pub fn profiles(user: &BTreeMap<String, AgentProfile>) -> io::Result<Vec<AgentProfile>> {
+    let mut profiles = builtin_profiles();
+    for (name, profile) in user {
+        if profile.binary.trim().is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Agent profile [agents.{}] needs a binary", name),
+            ));
+        }
+        let profile = AgentProfile { name: name.clone(), ..profile.clone() };
+        match profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => profiles.push(profile),
+        }
+    }
+    Ok(profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claude_template_expands() {
+        let extra = vec!["--verbose".to_string()];
+        let values = ArgValues {
+            prompt: Some("Do the {model} thing"),
+            model: Some("opus"),
+            settings: Some("/home/u/.config/ralph/settings.json"),
+            extra_args: &extra,
+            ..ArgValues::default()
+        };
+        assert_eq!(
+            AgentProfile::claude().expand_args(&values),
+            [
+                "--dangerously-skip-permissions",
+                "--settings=/home/u/.config/ralph/settings.json",
+                "--model=opus",
+                "--verbose",
+                "Do the {model} thing",
+            ]
+        );
+
+        // Missing values drop their argument
+        let values = ArgValues { prompt: Some("p"), ..ArgValues::default() };
+        assert_eq!(AgentProfile::claude().expand_args(&values), ["--dangerously-skip-permissions", "p"]);
+        // A prompt typed in instead drops the {prompt} argument
+        assert_eq!(AgentProfile::claude().expand_args(&ArgValues::default()), ["--dangerously-skip-permissions"]);
+    }
+
+    #[test]
+    fn long_prompts_are_typed_in() {
+        let claude = AgentProfile::claude();
+        let short = "x".repeat(ARGV_PROMPT_LIMIT);
+        let long = "x".repeat(ARGV_PROMPT_LIMIT + 1);
+        assert_eq!(claude.prompt_delivery(&short, false), PromptDelivery::Argv);
+        assert_eq!(claude.prompt_delivery(&long, false), PromptDelivery::Stdin);
+        assert_eq!(claude.prompt_delivery("short", true), PromptDelivery::Stdin);
+        // A prompt file has no argv limit to worry about
+        let aider = builtin_profiles().remove(2);
+        assert_eq!(aider.prompt_delivery(&long, true), PromptDelivery::Argv);
+    }
+
+    #[test]
+    fn conversation_args_resume_a_known_session_or_continue() {
+        let claude = AgentProfile::claude();
+        let resume = claude.conversation_args(Some("0b6e")).unwrap();
+        assert_eq!(resume, ["--resume", "0b6e"]);
+        let values = ArgValues { prompt: Some("p"), resume_args: &resume, ..ArgValues::default() };
+        assert_eq!(claude.expand_args(&values), ["--dangerously-skip-permissions", "--resume", "0b6e", "p"]);
+        assert_eq!(claude.conversation_args(None).unwrap(), ["--continue"]);
+        // Agents without either can't pick up a conversation
+        assert_eq!(builtin_profiles()[1].conversation_args(Some("0b6e")), None);
+    }
+
+    #[test]
+    fn builtin_templates_expand() {
+        let profiles = builtin_profiles();
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["claude", "opencode", "aider"]);
+
+        let values = ArgValues {
+            prompt: Some("go"),
+            prompt_file: Some("/tmp/ralph_prompt.txt"),
+            model: Some("gpt-4o"),
+            ..ArgValues::default()
+        };
+        assert_eq!(profiles[1].expand_args(&values), ["run", "--model=gpt-4o", "go"]);
+        assert_eq!(
+            profiles[2].expand_args(&values),
+            ["--yes-always", "--model=gpt-4o", "--message-file=/tmp/ralph_prompt.txt"]
+        );
+        assert!(profiles[2].uses_prompt_file() && !profiles[0].uses_prompt_file());
+        // Unknown braces are kept literally
+        let odd = AgentProfile { args: template(&["{x}{prompt}{"]), ..profiles[0].clone() };
+        assert_eq!(odd.expand_args(&values), ["{x}go{"]);
+    }
+
+    #[test]
+    fn user_profiles_extend_and_replace_builtins() {
+        let config = crate::config::parse_config(
+            r#"
+            [agents.aider]
+            binary = "/opt/aider/bin/aider"
+            args = ["{prompt_file}"]
+
+            [agents.goose]
+            binary = "goose"
+            args = ["session"]
+            prompt_via = "stdin"
+            stop_sentinels = ["turn complete"]
+            env = { GOOSE_MODE = "auto" }
+            "#,
+        )
+        .unwrap();
+
+        let profiles = profiles(&config.agents).unwrap();
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["claude", "opencode", "aider", "goose"]);
+        assert_eq!(profiles[2].binary, "/opt/aider/bin/aider");
+        let goose = &profiles[3];
+        assert_eq!(goose.prompt_via, PromptDelivery::Stdin);
+        assert_eq!(goose.completion_sentinel, COMPLETION_SENTINEL);
+        assert_eq!(goose.stop_sentinels, ["turn complete"]);
+        assert_eq!(goose.env.get("GOOSE_MODE").map(String::as_str), Some("auto"));
+
+        let config = crate::config::parse_config("[agents.x]\nbinary = \"\"").unwrap();
+        assert_eq!(super::profiles(&config.agents).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(crate::config::parse_config("[agents.x]\nbinary = \"x\"\npromptVia = \"stdin\"").is_err());
+        assert_eq!(super::profiles(&BTreeMap::new()).unwrap(), builtin_profiles());
+    }
+}
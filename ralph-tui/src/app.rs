@@ -0,0 +1,4486 @@
+//! Application state: the App struct, iteration bookkeeping, and the
+//! render snapshot
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use portable_pty::PtySize;
+use ratatui::text::Line;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::backup::{latest_backup, restore_latest};
+use crate::journal::{self, PrdEdit};
+use crate::keymap::{KeyAction, KeyMap};
+use crate::desktop;
+use crate::disk::{self, DiskPressure, DiskSpace, HARD_FLOOR_MB};
+use crate::changes::{FileDiff, IterationChanges};
+use crate::editor::extract_file_path;
+use crate::agents::AgentProfile;
+use crate::compat::AgentVersion;
+use crate::events::{EventSink, SessionEvent, TimelineEntry, append_timeline, now_millis};
+use crate::digest::format_datetime_utc;
+use crate::hooks::{DEFAULT_HOOK_TIMEOUT, HookContext, HookEvent, HookJob, HookStatus};
+use crate::git::{
+    BranchDrift, CommitOutcome, DriftMonitor, IterationMarker, SyncJob, SyncStrategy, commit_file,
+    create_iteration_marker, remove_iteration_markers, repo_root, rollback_to_marker, tree_snapshot,
+};
+use crate::health::Health;
+use crate::lanes::{Lane, LaneSnapshot};
+use crate::metrics::MetricsServer;
+use crate::prd::{Blocker, Burndown, Prd, StoryIndex, UserStory, open_blockers, progress_line_count, story_progress_lines};
+use crate::prd_diff::{self, PrdChurn, StoryDiff};
+use crate::conversation::{Conversation, ConversationStart};
+use crate::crosscheck::unverified_criteria;
+use crate::redraw::{OUTPUT_FRAME, RedrawSchedule};
+use crate::scope::{Scope, WRITE_ACTIONS};
+use crate::status::TaskStatus;
+use crate::tips::{self, TipContext};
+use crate::tripwire::{Tripwire, TripwireAlert, TripwireAnswer};
+use crate::verify::{verify_targets, VerifyJob, VerifyResult, VerifyTarget};
+use crate::preview::FilePreview;
+use crate::pty::{
+    Activity, AgentActivity, ErrorMatch, OutputSummary, PendingPrompt, PtyState, PtyWriter, ResizeDebounce, build_paste_payload, detect_agent_activity, lock_pty,
+};
+use crate::render::terminal::render_vt100_screen;
+use crate::testrun::TestResult;
+use crate::CliConfig;
+
+/// Entries of the quick actions menu opened with Enter on a story card
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoryAction {
+    ViewDetails,
+    AddNote,
+    TogglePassed,
+    FocusCriterion,
+    Skip,
+    CopyId,
+}
+
+impl StoryAction {
+    /// Menu order
+    pub const ALL: [StoryAction; 6] = [
+        StoryAction::ViewDetails,
+        StoryAction::AddNote,
+        StoryAction::TogglePassed,
+        StoryAction::FocusCriterion,
+        StoryAction::Skip,
+        StoryAction::CopyId,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            StoryAction::ViewDetails => "View details",
+            StoryAction::AddNote => "Add note",
+            StoryAction::TogglePassed => "Toggle passed",
+            StoryAction::FocusCriterion => "Set focus criterion",
+            StoryAction::Skip => "Skip for this session",
+            StoryAction::CopyId => "Copy id",
+        }
+    }
+}
+
+/// Mode for modal input system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Ralph,  // Default mode - focus on left panel
+    Claude, // Claude mode - focus on right panel, forward input to PTY
+}
+
+/// View mode for Ralph terminal panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RalphViewMode {
+    #[default]
+    Normal,       // Default: show minimal ralph output or ASCII logo
+    StoryDetails, // Show selected story details from prd.json
+    Progress,     // Show progress.txt entries for selected story
+    Requirements, // Show requirements from prd.md for selected story
+    Blockers,     // Show open blockers/questions from progress.txt with context
+    Errors,       // Show error lines matched in the agent's output this iteration
+    Journal,      // Show the prd.json edits made from the TUI this session
+    Iterations,   // Show the iteration start markers (snapshotEachIteration) for rollback
+    Evidence,     // Show the full evidence recorded for the selected criterion (from StoryDetails)
+    Activity,     // Show the agent's recent activities, one of them picked
+    FilePreview,  // Show the file named by the picked activity (from Activity)
+    Links,        // Show the links seen in the agent's output, one of them picked
+    Diff,         // Show each story at session start next to now
+    Changes,      // Show the files changed since the iteration started, one of them picked
+    ChangeDiff,   // Show the picked file's diff since the iteration started (from Changes)
+    Health,       // Show ralph-tui's own counters (PRD reloads, PTY reader, frame times)
+}
+
+/// Footer prompt after Ctrl+Q in a batch run
+pub const BATCH_QUIT_PROMPT: &str = "s: Skip this task | q: Quit batch | any other key: Cancel";
+
+/// Footer note while an ended iteration lingers on screen
+pub const ITERATION_ENDING_NOTE: &str = "Iteration ended — capturing final output…";
+
+/// Height of the Ralph output terminal (+/- cycles it, remembered per task)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RalphTerminalSize {
+    Collapsed, // 2 lines
+    #[default]
+    Normal,    // 5 lines
+    Large,     // 12 lines
+}
+
+impl RalphTerminalSize {
+    /// Content lines inside the border
+    pub fn lines(self) -> u16 {
+        match self {
+            RalphTerminalSize::Collapsed => 2,
+            RalphTerminalSize::Normal => 5,
+            RalphTerminalSize::Large => 12,
+        }
+    }
+
+    /// Rows taken on screen, border included
+    pub fn height(self) -> u16 {
+        self.lines() + 2
+    }
+
+    /// Next size in the cycle (`larger` wraps from Large to Collapsed, and back the other way)
+    pub fn cycled(self, larger: bool) -> Self {
+        match (self, larger) {
+            (RalphTerminalSize::Collapsed, true) | (RalphTerminalSize::Large, false) => RalphTerminalSize::Normal,
+            (RalphTerminalSize::Normal, true) | (RalphTerminalSize::Collapsed, false) => RalphTerminalSize::Large,
+            (RalphTerminalSize::Large, true) | (RalphTerminalSize::Normal, false) => RalphTerminalSize::Collapsed,
+        }
+    }
+}
+
+/// Whether the story selection tracks the active story across PRD reloads (F)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FollowMode {
+    #[default]
+    Off,
+    Following, // Selection moves to the active story on every reload
+    Suspended, // Following, until the user navigated away (F resumes)
+}
+
+/// Where a task stands in a batch run (several task directories or --queue)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueStatus {
+    #[default]
+    Pending,
+    Running,
+    Done,    // Every story passes
+    Failed,  // Ran out of iterations or time, or could not run
+    Skipped, // Left early with Ctrl+Q
+}
+
+impl QueueStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            QueueStatus::Pending => "pending",
+            QueueStatus::Running => "running",
+            QueueStatus::Done => "done",
+            QueueStatus::Failed => "failed",
+            QueueStatus::Skipped => "skipped",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            QueueStatus::Pending => "○",
+            QueueStatus::Running => "▶",
+            QueueStatus::Done => "✓",
+            QueueStatus::Failed => "✗",
+            QueueStatus::Skipped => "»",
+        }
+    }
+}
+
+/// One task of a batch run and how it went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueEntry {
+    pub task_dir: PathBuf,
+    pub status: QueueStatus,
+    pub detail: String, // e.g. "4/4 stories, 6 iterations", or the error
+    pub chained: bool,  // Named by the previous task's nextTask (only runs once that one is done)
+}
+
+impl QueueEntry {
+    pub fn new(task_dir: PathBuf) -> Self {
+        Self { task_dir, status: QueueStatus::Pending, detail: String::new(), chained: false }
+    }
+
+    /// A task started by the previous one's nextTask
+    pub fn chained(task_dir: PathBuf) -> Self {
+        Self { chained: true, ..Self::new(task_dir) }
+    }
+
+    /// Short name for the queue list (the task directory's name)
+    pub fn name(&self) -> String {
+        self.task_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.task_dir.display().to_string())
+    }
+}
+
+/// Iteration state for tracking progress across Claude restarts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IterationState {
+    #[default]
+    Running,       // Claude is currently running
+    Completed,     // All stories complete (<promise>COMPLETE</promise> found)
+    NeedsRestart,  // Iteration finished but more work remains
+    WaitingDelay,  // Waiting before starting next iteration
+    Aborted,       // Stopped by the user (Ctrl+Q) or a signal before the stories were done
+}
+
+/// Session state file inside the task directory (survives restarts of ralph-tui)
+pub const SESSION_STATE_FILE: &str = ".ralph-session.json";
+
+/// Default number of stalled iterations before a story is flagged as stuck
+pub const DEFAULT_STUCK_THRESHOLD: u32 = 3;
+
+/// PRD reloads remembered per story for the criteria sparkline
+pub const CRITERIA_HISTORY_LEN: usize = 12;
+
+/// Default seconds the active story's criteria count can stay flat before its sparkline turns amber
+pub const DEFAULT_FLAT_AFTER: u64 = 20 * 60;
+
+/// Variables ralph-tui sets for every iteration (prd.json `env` can't override them)
+pub const AUTOMATIC_ENV_VARS: [&str; 4] = ["RALPH_ITERATION", "RALPH_TASK_DIR", "RALPH_SESSION_ID", "RALPH_MAX_ITERATIONS"];
+
+/// Default commits behind the merge target before the drift badge turns amber
+pub const DEFAULT_BEHIND_WARN: u32 = 20;
+
+/// Message sent to an idle agent by --nudge-after
+pub const DEFAULT_NUDGE_MESSAGE: &str = "Please continue with the task.";
+
+/// Message asking the agent to finish up once --max-runtime is spent
+pub const RUNTIME_WRAP_UP_MESSAGE: &str = "The session's runtime limit has been reached. Wrap up now: \
+     commit finished work, update prd.json and progress.txt, then stop.";
+
+/// Message asking the agent to finish up after compacting its context (--end-on-compaction)
+pub const COMPACTION_WRAP_UP_MESSAGE: &str = "Your context was just compacted. Wrap up now: commit finished \
+     work, update prd.json and progress.txt, then stop so the next iteration starts with a fresh context.";
+
+/// Time the agent gets to wrap up after --max-runtime before it is stopped
+pub const RUNTIME_WRAP_UP_GRACE: Duration = Duration::from_secs(120);
+
+/// How often free space is re-measured while the loop is paused for it
+const DISK_RECHECK: Duration = Duration::from_secs(2);
+
+/// How often prd.json is re-read while it is missing or doesn't parse
+const PRD_RECHECK: Duration = Duration::from_secs(1);
+
+/// Why the last prd.json reload failed (the last good PRD stays on screen, marked stale)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrdProblem {
+    Missing,         // The file is gone (e.g. the agent renamed it)
+    Invalid(String), // The file doesn't parse; the parse error
+}
+
+/// Per-task session state persisted across ralph-tui runs so a resumed session keeps it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionState {
+    /// Consecutive iterations each story was active without a criterion passing (by story id)
+    #[serde(default)]
+    pub story_attempts: HashMap<String, u32>,
+    /// Iterations each story was the active one, in total (by story id)
+    #[serde(default)]
+    pub story_iterations: HashMap<String, u32>,
+    /// Seconds of agent time each story was the active one, in total (by story id)
+    #[serde(default)]
+    pub story_seconds: HashMap<String, u64>,
+    /// When each story flipped to passing during a ralph-tui session (by story id)
+    #[serde(default)]
+    pub story_completions: HashMap<String, StoryCompletion>,
+    /// Stories the run is limited to (empty = all stories)
+    #[serde(default)]
+    pub targeted_stories: BTreeSet<String>,
+    /// Criterion the agent is told to prioritize (F in the story details view)
+    #[serde(default)]
+    pub focused_criterion: Option<CriterionFocus>,
+    /// Ralph output terminal height chosen with +/-
+    #[serde(default)]
+    pub ralph_terminal_size: RalphTerminalSize,
+    /// Ralph status panel hidden (--no-sidebar or b)
+    #[serde(default)]
+    pub sidebar_hidden: bool,
+    /// Story list layout chosen with g
+    #[serde(default)]
+    pub story_list_layout: StoryListLayout,
+    /// Completed stories group expanded (Enter on its summary row)
+    #[serde(default)]
+    pub completed_group_expanded: bool,
+    /// The running session, written while the loop runs and cleared on a clean exit
+    /// (still present at startup = the last session crashed; see recovery.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery: Option<RecoverySnapshot>,
+}
+
+/// The parts of a running App that a crashed session can be resumed from
+/// Instants are stored as Unix timestamps (seconds).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoverySnapshot {
+    pub session_id: String,
+    pub current_iteration: u32,
+    pub max_iterations: u32,
+    #[serde(default)]
+    pub iterations_reduced_from: Option<u32>,
+    pub session_elapsed_secs: u64,
+    /// Stories skipped from the quick actions menu
+    #[serde(default)]
+    pub skipped_stories: BTreeSet<String>,
+    /// Criteria passed by each unfinished story at recent PRD loads: (timestamp, passed)
+    #[serde(default)]
+    pub criteria_history: HashMap<String, Vec<(u64, usize)>>,
+    /// Unix timestamp in seconds
+    pub saved_at: u64,
+}
+
+/// Acceptance criterion the next iterations' prompt asks the agent to work on first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CriterionFocus {
+    pub story_id: String,
+    /// Index into the story's acceptanceCriteria
+    pub criterion: usize,
+}
+
+/// Iteration and time at which a story was seen flipping to passing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryCompletion {
+    pub iteration: u32,
+    /// Unix timestamp in seconds
+    pub completed_at: u64,
+}
+
+impl SessionState {
+    /// Load session state from the task directory (default if missing or unreadable)
+    pub fn load(task_dir: &Path) -> Self {
+        std::fs::read_to_string(task_dir.join(SESSION_STATE_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save session state to the task directory
+    pub fn save(&self, task_dir: &Path) {
+        if let Ok(content) = serde_json::to_string_pretty(self) {
+            let _ = std::fs::write(task_dir.join(SESSION_STATE_FILE), content);
+        }
+    }
+
+    /// Stalled iteration count for a story
+    pub fn stalled_attempts(&self, story_id: &str) -> u32 {
+        self.story_attempts.get(story_id).copied().unwrap_or(0)
+    }
+
+    /// Iteration and time a story was completed (None if it passed before any recorded session)
+    pub fn completion(&self, story_id: &str) -> Option<StoryCompletion> {
+        self.story_completions.get(story_id).copied()
+    }
+}
+
+/// Control file written by the stop hook to signal the end of an iteration
+pub const SIGNAL_FILE: &str = ".ralph-signal";
+
+/// Time allowed between the two presses of a guarded chord (Ctrl+C/D/Z in Claude mode)
+pub const KEY_GUARD_WINDOW: Duration = Duration::from_secs(1);
+
+/// Lines of progress.txt shown in the tail strip
+pub const PROGRESS_STRIP_LINES: usize = 4;
+
+/// How long lines newly appended to progress.txt stay highlighted in the tail strip
+const PROGRESS_HIGHLIGHT: Duration = Duration::from_secs(3);
+
+/// PRD churn entries shown in the status panel
+pub const PRD_CHURN_SHOWN: usize = 3;
+
+/// How long the Claude panel border flashes red after a new error line
+const ERROR_FLASH: Duration = Duration::from_secs(1);
+
+/// Iteration log file (one line per finished iteration) inside the task directory
+pub const ITERATION_LOG_FILE: &str = ".ralph-iterations.log";
+
+/// Signal read from the control file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlSignal {
+    IterationComplete, // Agent finished its response, start the next iteration
+    Complete,          // All stories complete
+}
+
+/// JSON payload of the control file:
+/// {"signal": "iteration-complete", "timestamp": 1700000000, "session_id": "0b6e..."}
+#[derive(Debug, Deserialize)]
+struct SignalPayload {
+    signal: String,
+    #[allow(dead_code)]
+    #[serde(default)]
+    timestamp: Option<u64>,
+    #[serde(default)]
+    session_id: Option<String>,
+}
+
+/// A consumed control file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlMessage {
+    pub signal: ControlSignal,
+    /// The agent's conversation id, when the stop hook passed it on
+    pub session_id: Option<String>,
+}
+
+impl ControlSignal {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "iteration-complete" => Some(ControlSignal::IterationComplete),
+            "complete" => Some(ControlSignal::Complete),
+            _ => None,
+        }
+    }
+}
+
+/// Consume a control file (SIGNAL_FILE, or a --parallel lane's) in the task directory, if present
+/// The file is deleted after reading so each signal is only acted on once.
+/// Accepts the JSON payload written by the stop hook, or a bare signal word.
+pub fn take_control_signal(task_dir: &Path, file_name: &str) -> Option<ControlMessage> {
+    let signal_path = task_dir.join(file_name);
+    let content = std::fs::read_to_string(&signal_path).ok()?;
+    let _ = std::fs::remove_file(&signal_path);
+
+    // PowerShell's Set-Content may prepend a UTF-8 BOM
+    let content = content.trim_start_matches('\u{feff}');
+    match serde_json::from_str::<SignalPayload>(content) {
+        Ok(payload) => Some(ControlMessage {
+            signal: ControlSignal::parse(&payload.signal)?,
+            session_id: payload.session_id.filter(|id| !id.trim().is_empty()),
+        }),
+        Err(_) => Some(ControlMessage { signal: ControlSignal::parse(content)?, session_id: None }),
+    }
+}
+
+/// Mechanism that ended an iteration (recorded in the iteration log and the timeline)
+/// A panic in the output reader never ends one: `lock_pty` recovers the poisoned
+/// state and the panic is reported as a reader error while the agent keeps running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IterationEndSource {
+    ControlFile,  // Stop hook wrote the .ralph-signal control file
+    OutputScrape, // Stop hook message detected in Claude's output (fallback)
+    ChildExited,  // Claude process exited on its own
+    ReadError,    // Reading Claude's output failed, so it was treated as exited
+    Signal,       // ralph-tui received SIGTERM/SIGHUP/SIGINT
+    RuntimeLimit, // --max-runtime was spent: the session ends with this iteration
+    PrdProgress,  // The iteration's story passes in prd.json and the agent went quiet (--prd-idle)
+}
+
+/// An iteration that ended, kept on screen for the linger period so its last output
+/// is drawn before the loop moves on; input (Ctrl+Q included) is handled meanwhile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IterationEnding {
+    pub source: IterationEndSource,
+    // The completion sentinel or a COMPLETE control signal was seen
+    pub complete: bool,
+    pub read_failure: Option<String>,
+    pub at: Instant,
+}
+
+impl IterationEndSource {
+    pub fn label(self) -> &'static str {
+        match self {
+            IterationEndSource::ControlFile => "control-file",
+            IterationEndSource::OutputScrape => "output-scrape",
+            IterationEndSource::ChildExited => "child-exited",
+            IterationEndSource::ReadError => "read-error",
+            IterationEndSource::Signal => "signal",
+            IterationEndSource::RuntimeLimit => "runtime-limit",
+            IterationEndSource::PrdProgress => "prd-progress+idle",
+        }
+    }
+
+    /// For the delay screen and the Iterations view
+    pub fn description(self) -> &'static str {
+        match self {
+            IterationEndSource::ControlFile => "stop hook signal",
+            IterationEndSource::OutputScrape => "stop hook message in the output",
+            IterationEndSource::ChildExited => "agent exited",
+            IterationEndSource::ReadError => "agent output unreadable",
+            IterationEndSource::Signal => "ralph-tui was signalled",
+            IterationEndSource::RuntimeLimit => "--max-runtime spent",
+            IterationEndSource::PrdProgress => "story passed in prd.json, agent quiet",
+        }
+    }
+}
+
+/// Append a line to the task's iteration log recording how the iteration ended
+/// Test counts from the iteration's last test run are appended when one was seen, the
+/// number of context compactions when there were any, and the model when one was chosen,
+/// so spend can be attributed per model. The agent's release is appended when known, to
+/// tie odd behaviour to an agent upgrade.
+#[allow(clippy::too_many_arguments)]
+pub fn append_iteration_log(
+    task_dir: &Path,
+    iteration: u32,
+    source: IterationEndSource,
+    state: IterationState,
+    duration: Duration,
+    output: OutputSummary,
+    model: Option<&str>,
+    agent_version: Option<&AgentVersion>,
+) {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut tests = output
+        .tests
+        .map(|t| format!(" tests_passed={} tests_failed={}", t.passed, t.failed))
+        .unwrap_or_default();
+    if output.compactions > 0 {
+        tests.push_str(&format!(" compactions={}", output.compactions));
+    }
+    let model = model.map(|m| format!(" model={}", m)).unwrap_or_default();
+    let release = agent_version.and_then(|v| v.release).map(|r| format!(" agent_version={}", r)).unwrap_or_default();
+    let line = format!(
+        "{} iteration={} source={} state={:?} duration_secs={}{}{}{}\n",
+        timestamp,
+        iteration,
+        source.label(),
+        state,
+        duration.as_secs(),
+        tests,
+        model,
+        release
+    );
+    if let Ok(mut file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(task_dir.join(ITERATION_LOG_FILE))
+    {
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+/// Seconds since the Unix epoch (0 if the clock is before it)
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Append one line (without timestamp) to the task's iteration log
+pub fn append_log_line(task_dir: &Path, text: &str) {
+    let timestamp = unix_now();
+    if let Ok(mut file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(task_dir.join(ITERATION_LOG_FILE))
+    {
+        let _ = file.write_all(format!("{} {}\n", timestamp, text).as_bytes());
+    }
+}
+
+/// Application state
+pub struct App {
+    pub pty_state: Arc<Mutex<PtyState>>,
+    pub master_pty: Option<Box<dyn portable_pty::MasterPty + Send>>,
+    pub pty_writer: Option<PtyWriter>,
+    // A prompt too long for argv, typed in once the agent is ready for a paste
+    pub pending_prompt: Option<PendingPrompt>,
+    // Panel size waiting to settle before the PTY is resized to it
+    pub pty_resize: ResizeDebounce,
+    // Ctrl+L: re-send the panel size to the agent on the next frame
+    pub pty_resync: bool,
+    // Show the agent's PTY size in the Claude panel title (show_pty_size in ralph.toml)
+    pub show_pty_size: bool,
+    // --parallel lanes, each with its own agent (empty in the normal single-agent mode)
+    pub lanes: Vec<Lane>,
+    // Lane receiving keyboard input in Claude mode (Tab cycles)
+    pub focused_lane: usize,
+    pub mode: Mode,
+    pub task_dir: PathBuf,
+    pub prd_path: PathBuf,
+    pub prd: Option<Prd>,
+    // prd.json as it was when the session started (for the session diff)
+    pub prd_at_start: Option<Prd>,
+    // Priority order, current story and criteria counts of prd (rebuilt when it or the targets change)
+    pub story_index: Arc<StoryIndex>,
+    pub prd_needs_reload: Arc<Mutex<bool>>,
+    // Why the last reload failed (prd then holds the last good PRD), and when it was last tried
+    pub prd_problem: Option<PrdProblem>,
+    pub prd_checked_at: Option<Instant>,
+    // progress.txt contents, re-read only when the file watcher flags a change
+    pub progress: Option<String>,
+    pub progress_loaded: bool,
+    pub progress_needs_reload: Arc<Mutex<bool>>,
+    // Open BLOCKER:/QUESTION: lines from progress.txt
+    pub blockers: Vec<Blocker>,
+    // Whether the "Needs attention" section (rather than a story row) is selected
+    pub attention_selected: bool,
+    // Whether the progress.txt tail strip is shown above the footer (P)
+    pub progress_strip: bool,
+    // Whether the Ralph status panel is hidden, giving Claude the full width (--no-sidebar, b)
+    pub sidebar_hidden: bool,
+    // Lines appended to progress.txt by the last change, and when it was seen (for highlighting)
+    pub progress_new_lines: usize,
+    pub progress_changed_at: Option<Instant>,
+    // progress.txt's line count, and what it was when the iteration started
+    pub progress_lines: usize,
+    pub progress_lines_at_iteration_start: Option<usize>,
+    // Iteration loop state
+    pub current_iteration: u32,
+    pub max_iterations: u32,
+    // Budget before the user wound the run down to the running iteration (_)
+    pub iterations_reduced_from: Option<u32>,
+    pub iteration_state: IterationState,
+    pub delay_start: Option<Instant>,
+    // Elapsed time tracking
+    pub session_start: Instant,
+    pub iteration_start: Instant,
+    // Line count at which progress.txt is due for rotation (shown against its size)
+    pub rotate_threshold: u32,
+    #[allow(dead_code)]
+    pub skip_prompts: bool,
+    // Animation state
+    pub animation_tick: u64,
+    pub last_animation_update: Instant,
+    // Output, input and heartbeat triggers for the next frame of the running screen
+    pub redraw: RedrawSchedule,
+    // PRD reloads, PTY reader and frame counters for the Health view
+    pub health: Health,
+    // Session identification
+    pub session_id: String,
+    // Story list scroll offset (for arrow key navigation)
+    pub story_scroll_offset: usize,
+    // Currently selected story index (for detail views)
+    pub selected_story_index: usize,
+    // Criterion cursor in the story details view (h/l, F focuses it)
+    pub selected_criterion: usize,
+    // Whether the selection follows the active story (F; manual navigation suspends it)
+    pub follow: FollowMode,
+    // Ralph terminal view mode (what content to show)
+    pub ralph_view_mode: RalphViewMode,
+    // Ralph terminal height (collapsed, normal or large; +/- cycles it)
+    pub ralph_size: RalphTerminalSize,
+    // Scroll offset for Ralph terminal content (when viewing details)
+    pub ralph_scroll_offset: usize,
+    // Scroll offset for Claude terminal (0 = at bottom, >0 = scrolled up into history)
+    pub claude_scroll_offset: usize,
+    // Story list layout (flat or grouped by status), kept for the whole session
+    pub story_list_layout: StoryListLayout,
+    // Whether the completed-stories group is expanded in the grouped layout
+    pub completed_group_expanded: bool,
+    // Whether the completed-stories summary row is selected (grouped layout)
+    pub completed_group_selected: bool,
+    // Machine-readable event stream (--events-socket)
+    pub events: Option<EventSink>,
+    // Metrics and status served over HTTP (--metrics-port)
+    pub metrics: Option<MetricsServer>,
+    // Run exactly one iteration and exit with a status code (--once)
+    pub once: bool,
+    // Batch run: every task and its status (empty for a single task)
+    pub queue: Vec<QueueEntry>,
+    // Ctrl+Q in a batch asks whether to skip this task or quit the batch; the answer
+    pub batch_quit_prompt: bool,
+    pub quit_batch: bool,
+    // Persisted per-task session state (story attempt counters)
+    pub session_state: SessionState,
+    // When the crash-recovery snapshot was last handed to the writer
+    pub recovery_published_at: Option<Instant>,
+    // Stalled iterations before the active story is flagged as stuck (red at 2x)
+    pub stuck_threshold: u32,
+    // Criteria passed by each unfinished story at recent PRD loads, oldest first (capped at CRITERIA_HISTORY_LEN)
+    pub criteria_history: HashMap<String, VecDeque<(Instant, usize)>>,
+    // How long the active story's criteria count can stay flat before its sparkline turns amber (--flat-after)
+    pub flat_after: Duration,
+    // Active story at iteration start: (story id, criteria passed)
+    pub iteration_story: Option<(String, usize)>,
+    // Story IDs whose cards are expanded to show acceptance criteria (x key)
+    pub expanded_stories: HashSet<String>,
+    // Top-level directory of the git repository ralph-tui runs in (None outside one)
+    pub repo_root: Option<PathBuf>,
+    // Whether git features (drift, sync, iteration snapshots, rollback) can be used
+    pub git_available: bool,
+    // Background ahead/behind tracking against the PRD's mergeTarget
+    pub drift: Option<DriftMonitor>,
+    // Commits behind the merge target before the drift badge turns amber
+    pub behind_warn: u32,
+    // Merge or rebase when syncing with the merge target
+    pub sync_strategy: SyncStrategy,
+    // Sync requested with m; runs at the next iteration boundary
+    pub sync_requested: bool,
+    // Merge/rebase currently running between iterations
+    pub sync_job: Option<SyncJob>,
+    // onIterationEnd / onComplete commands still running
+    pub hook_jobs: Vec<HookJob>,
+    // ralph.toml's hook commands, for when prd.json doesn't set its own
+    pub on_iteration_end: Option<String>,
+    pub on_complete: Option<String>,
+    // Error from the last sync (shown until dismissed; holds the next iteration)
+    pub sync_error: Option<String>,
+    // Free space on the task directory's filesystem at the last check, and how tight it is
+    pub disk_space: Option<DiskSpace>,
+    pub disk_pressure: DiskPressure,
+    // Free space (MB) below which the low-space banner shows (--min-free)
+    pub min_free_mb: u64,
+    // Loop paused below the hard floor (held between iterations until space is freed)
+    pub disk_error: Option<String>,
+    // Write/Edit targets checked against the scope: target -> out of scope
+    pub scope_checked: HashMap<String, bool>,
+    // Resolved paths written outside the scope this session, first seen first
+    pub scope_violations: Vec<PathBuf>,
+    // Violations already shown on a pause screen (pauseOnOutOfScope)
+    pub scope_acknowledged: usize,
+    // Loop held at the boundary until dismissed with Esc
+    pub scope_hold: Option<String>,
+    // Dangerous command patterns matched against Bash activities (ralph.toml)
+    pub tripwire: Tripwire,
+    // Commands that tripped the wire, oldest first; the first one is in the modal
+    pub tripwire_alerts: VecDeque<TripwireAlert>,
+    // Claude screen when the modal opened, shown instead of new output while it's up
+    pub tripwire_frozen: Option<Vec<Line<'static>>>,
+    // Loop held at the boundary after "pause loop" in the modal, until dismissed with Esc
+    pub tripwire_hold: Option<String>,
+    // Stories the agent added, removed or rewrote in prd.json this session, oldest first
+    pub prd_churn: Vec<PrdChurn>,
+    // Churn already shown on a pause screen (pause_on_prd_churn)
+    pub churn_acknowledged: usize,
+    // Hold the loop after suspicious churn (pause_on_prd_churn in ralph.toml)
+    pub pause_on_churn: bool,
+    // Loop held at the boundary until dismissed with Esc
+    pub churn_hold: Option<String>,
+    // Run unpassed criteria's verify commands at each iteration boundary (verify_criteria in ralph.toml)
+    pub verify_criteria: bool,
+    // Timeout for one verify command
+    pub verify_timeout: Duration,
+    // Share of a criterion's words a `Verified:` note must contain (None = no cross-check,
+    // check_verified_notes in ralph.toml)
+    pub verified_match: Option<f64>,
+    // Passed criteria (by index) without a matching `Verified:` note, by story
+    pub unverified: HashMap<String, BTreeSet<usize>>,
+    // Verify commands running in the background (V or the iteration boundary)
+    pub verify_job: Option<VerifyJob>,
+    // (passed, failures) of the running verify job so far, for its summary toast
+    pub verify_outcome: (usize, Vec<String>),
+    // When free space was last measured
+    pub disk_checked_at: Option<Instant>,
+    // Short-lived message shown in the footer in place of the mode text
+    pub toast: Option<(String, Instant)>,
+    // Tips for unused features in the footer (hide_tips in ralph.toml turns them off)
+    pub footer_tips: bool,
+    // Actions whose key was pressed this session, retiring their tips
+    pub used_keys: HashSet<KeyAction>,
+    // Type every prompt in, not only those over agents::ARGV_PROMPT_LIMIT
+    pub prompt_via_stdin: bool,
+    // ralph.toml's `env`, under prd.json's
+    pub config_env: BTreeMap<String, String>,
+    // Whether Claude is working or waiting at its input prompt
+    pub agent_activity: AgentActivity,
+    // Quiet time at the prompt before Claude counts as idle
+    pub idle_after: Duration,
+    // Pattern matching Claude's input prompt on the last visible row
+    pub prompt_pattern: Regex,
+    // Idle time before sending nudge_message (None = never nudge)
+    pub nudge_after: Option<Duration>,
+    pub nudge_message: String,
+    // Quiet time after the iteration's story passes in prd.json before the iteration is
+    // ended without a stop hook signal (None = wait for the signal)
+    pub prd_idle_after: Option<Duration>,
+    // Whether the current idle period has already been nudged
+    pub nudge_sent: bool,
+    // Ask the agent to wrap up once it compacts its context (--end-on-compaction)
+    pub end_on_compaction: bool,
+    // Iteration whose agent was already asked to wrap up after a compaction
+    pub compaction_wrap_up_iteration: Option<u32>,
+    // Squash the agent's commits when the task completes (--squash-on-complete)
+    pub squash_on_complete: bool,
+    // What --squash-on-complete did, for the completion summary
+    pub squash_outcome: Option<String>,
+    // Wall-clock limit for the whole session (--max-runtime)
+    pub max_runtime: Option<Duration>,
+    // When the agent was asked to wrap up after max_runtime ran out
+    pub wrap_up_requested_at: Option<Instant>,
+    // The session ended because max_runtime ran out
+    pub runtime_limit_hit: bool,
+    // How long an ended iteration stays on screen (--linger; zero moves on at once)
+    pub linger: Duration,
+    // The iteration that ended, while it lingers
+    pub iteration_ending: Option<IterationEnding>,
+    // Extra arguments appended to the agent command line
+    pub agent_args: Vec<String>,
+    // Claude model passed as --model (None = the claude CLI's default)
+    pub model: Option<String>,
+    // How the agent is launched (--agent / prd.json agent)
+    pub agent: AgentProfile,
+    // What `claude --version` reported at startup (None for other agents)
+    pub agent_version: Option<AgentVersion>,
+    // prompt.md the current iteration's prompt came from (None = the embedded default)
+    pub prompt_source: Option<String>,
+    // Remappable key bindings (defaults plus ralph.toml's [keys] table)
+    pub keymap: Arc<KeyMap>,
+    // Latest test-run summary from a finished iteration
+    pub last_test_result: Option<TestResult>,
+    // u was pressed: the next key picks y (restore), z (undo last edit) or l (edit log)
+    pub undo_prefix: bool,
+    // Backup awaiting confirmation to be restored over prd.json (u, then y)
+    pub pending_restore: Option<PathBuf>,
+    // prd.json edits made from the TUI this session, oldest first
+    pub prd_journal: Vec<PrdEdit>,
+    // Quick actions menu on the selected story (Enter): highlighted entry while open
+    pub story_menu: Option<usize>,
+    // Stories skipped for this session from the quick actions menu (not persisted)
+    pub skipped_stories: BTreeSet<String>,
+    // Panic caught in the agent output reader this iteration (red banner)
+    pub reader_error: Option<String>,
+    // Tree state at each iteration start this session (snapshotEachIteration), oldest first
+    pub iteration_markers: Vec<IterationMarker>,
+    // How each iteration ended this session, by iteration number
+    pub iteration_ends: BTreeMap<u32, IterationEndSource>,
+    // Whether the next iteration resumes the agent's conversation (continueConversation)
+    pub conversation: Conversation,
+    // Marker picked in the iterations view
+    pub selected_marker: usize,
+    // R was pressed in the iterations view: Y rolls back to the selected marker
+    pub rollback_armed: bool,
+    // Activity picked in the activity view (0 = newest)
+    pub selected_activity: usize,
+    // File opened from the activity view, and the list's scroll position to return to
+    pub file_preview: Option<Arc<FilePreview>>,
+    pub activity_scroll_offset: usize,
+    // Link picked in the links view (0 = newest)
+    pub selected_link: usize,
+    // Tree when the running iteration started, which the changes view (v) diffs against
+    pub iteration_base: Option<String>,
+    // Changes view: files changed this iteration and the picked one, loaded when the view opens
+    // and kept (with the diffs opened from it) until the next iteration starts
+    pub iteration_changes: Option<Arc<IterationChanges>>,
+    pub selected_change: usize,
+    pub change_diffs: HashMap<String, Arc<FileDiff>>,
+    // Diff opened from the changes view, and the list's scroll position to return to
+    pub change_diff: Option<Arc<FileDiff>>,
+    pub changes_scroll_offset: usize,
+    // Use kitty keyboard protocol encodings when the agent enables them (--kitty-keys)
+    pub kitty_keys: bool,
+    // Ctrl+C/D/Z need a second press within KEY_GUARD_WINDOW in Claude mode (--no-key-guard disables)
+    pub key_guard: bool,
+    // Whether to show progress in the terminal title (--no-title disables), and the title last set
+    pub terminal_title: bool,
+    pub title_shown: Option<String>,
+    // Guarded chord awaiting its second press, and when it was first pressed
+    pub pending_chord: Option<(char, Instant)>,
+    // Ring the terminal bell on new error lines (--error-bell)
+    pub error_bell: bool,
+    // Error lines already announced this iteration
+    pub errors_seen: usize,
+}
+
+impl App {
+    pub fn new(rows: u16, cols: u16, config: CliConfig) -> Self {
+        let prd_path = config.prd_path;
+        let mut prd = Prd::load(&prd_path).ok();
+        let now = Instant::now();
+        // Generate session ID from process ID (format: RL-XXXXX)
+        let session_id = format!("RL-{:05}", std::process::id() % 100000);
+        // Find first incomplete story before moving prd
+        let selected_story_index = Self::find_first_incomplete_story(&prd);
+        let mut session_state = SessionState::load(&config.task_dir);
+        // --stories replaces the selection remembered from the last session
+        if let Some(targeted) = config.targeted_stories {
+            session_state.targeted_stories = targeted;
+            session_state.save(&config.task_dir);
+        }
+        if let Some(ref mut prd) = prd {
+            prd.targeted_stories = session_state.targeted_stories.clone();
+        }
+        // Detect the repository once; git features stay off outside one
+        let repo_root = std::env::current_dir().ok().and_then(|dir| repo_root(&dir));
+        // Track drift from the merge target (git runs on the monitor's thread)
+        let drift = prd
+            .as_ref()
+            .and_then(|p| p.merge_target.clone())
+            .filter(|target| !target.is_empty())
+            .and_then(|target| Some(DriftMonitor::spawn(repo_root.clone()?, target)));
+
+        let mut app = Self {
+            pty_state: Arc::new(Mutex::new(PtyState::with_error_patterns(rows, cols, config.error_patterns))),
+            master_pty: None,
+            pty_writer: None,
+            pending_prompt: None,
+            pty_resize: ResizeDebounce::default(),
+            pty_resync: false,
+            show_pty_size: config.show_pty_size,
+            lanes: Vec::new(),
+            focused_lane: 0,
+            mode: Mode::Ralph, // Default to Ralph mode
+            task_dir: config.task_dir,
+            prd_path,
+            story_index: Arc::new(prd.as_ref().map(StoryIndex::new).unwrap_or_default()),
+            prd_at_start: prd.clone(),
+            prd,
+            prd_needs_reload: Arc::new(Mutex::new(false)),
+            prd_problem: None,
+            prd_checked_at: None,
+            progress: None,
+            progress_loaded: false,
+            progress_needs_reload: Arc::new(Mutex::new(true)), // Initial load
+            blockers: Vec::new(),
+            attention_selected: false,
+            progress_strip: config.progress_strip,
+            sidebar_hidden: config.no_sidebar || session_state.sidebar_hidden,
+            progress_new_lines: 0,
+            progress_changed_at: None,
+            progress_lines: 0,
+            progress_lines_at_iteration_start: None,
+            current_iteration: 1,
+            max_iterations: config.max_iterations,
+            iterations_reduced_from: None,
+            iteration_state: IterationState::Running,
+            delay_start: None,
+            session_start: now,
+            iteration_start: now,
+            rotate_threshold: config.rotate_threshold,
+            skip_prompts: config.skip_prompts,
+            animation_tick: 0,
+            last_animation_update: now,
+            redraw: RedrawSchedule::default(),
+            health: Health::default(),
+            session_id,
+            story_scroll_offset: 0,
+            selected_story_index,
+            selected_criterion: 0,
+            ralph_view_mode: RalphViewMode::Normal,
+            ralph_size: session_state.ralph_terminal_size,
+            ralph_scroll_offset: 0,
+            claude_scroll_offset: 0,
+            story_list_layout: session_state.story_list_layout,
+            completed_group_expanded: session_state.completed_group_expanded,
+            completed_group_selected: false,
+            follow: FollowMode::Off,
+            events: None,
+            metrics: None,
+            once: config.once,
+            queue: Vec::new(),
+            batch_quit_prompt: false,
+            quit_batch: false,
+            session_state,
+            recovery_published_at: None,
+            stuck_threshold: config.stuck_threshold,
+            criteria_history: HashMap::new(),
+            flat_after: Duration::from_secs(config.flat_after),
+            iteration_story: None,
+            expanded_stories: HashSet::new(),
+            git_available: repo_root.is_some(),
+            repo_root,
+            drift,
+            behind_warn: config.behind_warn,
+            sync_strategy: config.sync_strategy,
+            sync_requested: false,
+            sync_job: None,
+            hook_jobs: Vec::new(),
+            sync_error: None,
+            disk_space: None,
+            disk_pressure: DiskPressure::Ok,
+            min_free_mb: config.min_free_mb,
+            disk_error: None,
+            scope_checked: HashMap::new(),
+            scope_violations: Vec::new(),
+            scope_acknowledged: 0,
+            scope_hold: None,
+            tripwire: Tripwire::new(&config.dangerous_commands),
+            tripwire_alerts: VecDeque::new(),
+            tripwire_frozen: None,
+            tripwire_hold: None,
+            prd_churn: Vec::new(),
+            churn_acknowledged: 0,
+            pause_on_churn: config.pause_on_prd_churn,
+            churn_hold: None,
+            verify_criteria: config.verify_criteria,
+            verify_timeout: Duration::from_secs(config.verify_timeout_secs),
+            verified_match: config.verified_match,
+            unverified: HashMap::new(),
+            verify_job: None,
+            verify_outcome: (0, Vec::new()),
+            disk_checked_at: None,
+            toast: None,
+            footer_tips: config.footer_tips,
+            used_keys: HashSet::new(),
+            prompt_via_stdin: config.prompt_via_stdin,
+            config_env: config.env,
+            on_iteration_end: config.on_iteration_end,
+            on_complete: config.on_complete,
+            agent_activity: AgentActivity::Starting,
+            idle_after: Duration::from_secs(config.idle_after),
+            prompt_pattern: config.prompt_pattern,
+            nudge_after: (config.nudge_after > 0).then(|| Duration::from_secs(config.nudge_after)),
+            nudge_message: config.nudge_message,
+            prd_idle_after: (config.prd_idle_after > 0).then(|| Duration::from_secs(config.prd_idle_after)),
+            nudge_sent: false,
+            end_on_compaction: config.end_on_compaction,
+            squash_on_complete: config.squash_on_complete,
+            squash_outcome: None,
+            compaction_wrap_up_iteration: None,
+            max_runtime: config.max_runtime.map(Duration::from_secs),
+            wrap_up_requested_at: None,
+            runtime_limit_hit: false,
+            linger: Duration::from_millis(config.linger_ms),
+            iteration_ending: None,
+            agent_args: config.agent_args,
+            model: config.model,
+            agent: config.agent,
+            agent_version: config.agent_version,
+            prompt_source: None,
+            keymap: Arc::new(config.keymap),
+            last_test_result: None,
+            undo_prefix: false,
+            pending_restore: None,
+            prd_journal: Vec::new(),
+            story_menu: None,
+            skipped_stories: BTreeSet::new(),
+            reader_error: None,
+            iteration_markers: Vec::new(),
+            iteration_ends: BTreeMap::new(),
+            conversation: Conversation::default(),
+            selected_marker: 0,
+            selected_activity: 0,
+            file_preview: None,
+            activity_scroll_offset: 0,
+            selected_link: 0,
+            iteration_base: None,
+            iteration_changes: None,
+            selected_change: 0,
+            change_diffs: HashMap::new(),
+            change_diff: None,
+            changes_scroll_offset: 0,
+            rollback_armed: false,
+            kitty_keys: config.kitty_keys,
+            key_guard: config.key_guard,
+            terminal_title: config.terminal_title,
+            title_shown: None,
+            pending_chord: None,
+            error_bell: config.error_bell,
+            errors_seen: 0,
+        };
+        // A snapshot left behind by a crash (main asked whether to resume it)
+        if let Some(recovery) = app.session_state.recovery.take() {
+            app.restore_recovery(recovery);
+        }
+        app.record_criteria_history();
+        app.check_disk_space();
+        app
+    }
+
+    /// Durable state of the running session, for resuming it after a crash
+    pub fn recovery_snapshot(&self) -> RecoverySnapshot {
+        let now = unix_now();
+        let to_unix = |at: Instant| now.saturating_sub(at.elapsed().as_secs());
+        // Stopped between iterations: the one that just ran is finished, resume with the next
+        let between_iterations = self.delay_start.is_some() && self.iteration_state != IterationState::Running;
+        RecoverySnapshot {
+            session_id: self.session_id.clone(),
+            current_iteration: self.current_iteration + u32::from(between_iterations),
+            max_iterations: self.max_iterations,
+            iterations_reduced_from: self.iterations_reduced_from,
+            session_elapsed_secs: self.session_start.elapsed().as_secs(),
+            skipped_stories: self.skipped_stories.clone(),
+            criteria_history: self
+                .criteria_history
+                .iter()
+                .map(|(id, history)| (id.clone(), history.iter().map(|&(at, passed)| (to_unix(at), passed)).collect()))
+                .collect(),
+            saved_at: now,
+        }
+    }
+
+    /// Pick a crashed session back up: the iteration it was on, its clock, skips and history
+    /// The budget grows to cover the resumed iteration if it was already spent.
+    pub fn restore_recovery(&mut self, recovery: RecoverySnapshot) {
+        let now = unix_now();
+        let to_instant = |at: u64| {
+            Instant::now()
+                .checked_sub(Duration::from_secs(now.saturating_sub(at)))
+                .unwrap_or_else(Instant::now)
+        };
+        self.current_iteration = recovery.current_iteration.max(1);
+        self.max_iterations = self.max_iterations.max(self.current_iteration);
+        self.iterations_reduced_from = recovery.iterations_reduced_from;
+        if let Some(start) = Instant::now().checked_sub(Duration::from_secs(recovery.session_elapsed_secs)) {
+            self.session_start = start;
+        }
+        self.skipped_stories = recovery.skipped_stories;
+        self.criteria_history = recovery
+            .criteria_history
+            .into_iter()
+            .map(|(id, history)| (id, history.into_iter().map(|(at, passed)| (to_instant(at), passed)).collect()))
+            .collect();
+        append_log_line(
+            &self.task_dir,
+            &format!("recovered iteration={} session={}", self.current_iteration, recovery.session_id),
+        );
+    }
+
+    /// Each story as it was at session start next to how it is now
+    pub fn session_diff(&self) -> Vec<StoryDiff> {
+        match (&self.prd_at_start, &self.prd) {
+            (Some(start), Some(now)) => prd_diff::diff(start, now),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Hand the current state to the crash-recovery writer (at most once a second)
+    pub fn publish_recovery(&mut self) {
+        if self.recovery_published_at.is_some_and(|at| at.elapsed() < Duration::from_secs(1)) {
+            return;
+        }
+        self.recovery_published_at = Some(Instant::now());
+        self.session_state.recovery = Some(self.recovery_snapshot());
+        crate::recovery::publish(self.task_dir.clone(), self.session_state.clone());
+        // The metrics server's /status follows on the same schedule
+        if let (Some(metrics), Some(prd)) = (self.metrics.as_ref(), self.prd.as_ref()) {
+            let task = self.task_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let status = TaskStatus::new(task, prd, Some(self.current_iteration), Some(std::process::id()));
+            metrics.update(|metrics| metrics.status = Some(status));
+        }
+    }
+
+    /// Count a finished iteration (of the loop or a lane) in the metrics
+    pub fn observe_iteration(&self, duration: Duration) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.update(|metrics| metrics.observe_iteration(duration));
+        }
+    }
+
+    /// Remember how an iteration ended; a read failure's message goes to the iteration log
+    pub fn record_iteration_end(&mut self, iteration: u32, source: IterationEndSource, read_failure: Option<String>) {
+        if let (IterationEndSource::ReadError, Some(message)) = (source, read_failure) {
+            append_log_line(
+                &self.task_dir,
+                &format!("read_error iteration={} message={}", iteration, serde_json::Value::String(message)),
+            );
+        }
+        self.iteration_ends.insert(iteration, source);
+        if self.conversation.iteration_ended(source, self.iteration_start.elapsed()) {
+            append_log_line(&self.task_dir, &format!("resume_failed iteration={}", iteration));
+            self.show_toast("The agent couldn't resume its conversation - the next iteration starts fresh");
+        }
+    }
+
+    /// Whether iterations pick up the previous conversation (prd.json continueConversation;
+    /// never for --parallel lanes)
+    fn continues_conversation(&self) -> bool {
+        self.lanes.is_empty() && self.prd.as_ref().is_some_and(|prd| prd.continue_conversation)
+    }
+
+    /// Decide how the next iteration's conversation starts, returning the agent's `{resume_args}`
+    /// With continueConversation on, the choice is logged as a `conversation` line.
+    pub fn next_conversation_args(&mut self) -> Vec<String> {
+        let enabled = self.continues_conversation();
+        let (start, args) = self.conversation.next_start(enabled, &self.agent);
+        if enabled {
+            let session = match (start, &self.conversation.session_id) {
+                (ConversationStart::Continued, Some(id)) => format!(" session={}", id),
+                _ => String::new(),
+            };
+            append_log_line(
+                &self.task_dir,
+                &format!("conversation iteration={} start={}{}", self.current_iteration, start.label(), session),
+            );
+        }
+        args
+    }
+
+    /// N: start the next iteration in a fresh conversation (again: continue after all)
+    pub fn toggle_fresh_session(&mut self) {
+        if !self.continues_conversation() {
+            self.show_toast("Every iteration starts fresh (continueConversation is off)");
+        } else if self.conversation.fresh_requested {
+            self.conversation.fresh_requested = false;
+            self.show_toast("Next iteration continues the conversation");
+        } else {
+            self.conversation.fresh_requested = true;
+            self.show_toast("Next iteration starts a fresh conversation");
+        }
+    }
+
+    /// Publish and write the snapshot now (iteration boundaries)
+    pub fn save_recovery(&mut self) {
+        self.recovery_published_at = None;
+        self.publish_recovery();
+        crate::recovery::write_latest();
+    }
+
+    /// Find the index of the first incomplete story (or 0 if all complete)
+    pub fn find_first_incomplete_story(prd: &Option<Prd>) -> usize {
+        if let Some(prd) = prd {
+            prd.user_stories
+                .iter()
+                .position(|s| !s.passes)
+                .unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    /// Reload PRD from disk if flagged
+    pub fn reload_prd_if_needed(&mut self) {
+        let needs_reload = {
+            let Ok(mut flag) = self.prd_needs_reload.lock() else {
+                return;
+            };
+            if *flag {
+                *flag = false;
+                true
+            } else {
+                false
+            }
+        };
+
+        // While the file is missing or broken, keep trying in case the watcher misses its return
+        let recheck = self.prd_problem.is_some() && self.prd_checked_at.is_none_or(|at| at.elapsed() >= PRD_RECHECK);
+        if needs_reload || recheck {
+            self.reload_prd();
+        }
+    }
+
+    /// Re-read prd.json, returning whether a fresh PRD was loaded
+    /// When the file is missing or doesn't parse, the last good PRD is kept (shown as stale)
+    /// and the problem is logged once; the file coming back is logged too.
+    pub fn reload_prd(&mut self) -> bool {
+        self.prd_checked_at = Some(Instant::now());
+        let problem = match Prd::load(&self.prd_path) {
+            Ok(prd) => {
+                self.set_prd(prd);
+                None
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Some(PrdProblem::Missing),
+            Err(e) => Some(PrdProblem::Invalid(e.to_string())),
+        };
+        if problem.is_none() {
+            self.health.prd_reloads += 1;
+            self.health.prd_reloaded_at = Some(now_millis() / 1000);
+        } else {
+            self.health.prd_reload_failures += 1;
+        }
+        if problem != self.prd_problem {
+            let event = match &problem {
+                None => "prd_restored".to_string(),
+                Some(PrdProblem::Missing) => format!("prd_missing path={}", self.prd_path.display()),
+                Some(PrdProblem::Invalid(message)) => format!("prd_invalid message={:?}", message),
+            };
+            append_log_line(&self.task_dir, &format!("{} iteration={}", event, self.current_iteration));
+            if problem.is_none() {
+                self.show_toast(format!("{} is back - reloaded", self.prd_file_name()));
+            }
+        }
+        let loaded = problem.is_none();
+        self.prd_problem = problem;
+        loaded
+    }
+
+    /// prd.json's file name, for messages
+    fn prd_file_name(&self) -> String {
+        self.prd_path.file_name().map_or_else(|| "prd.json".to_string(), |n| n.to_string_lossy().into_owned())
+    }
+
+    /// Header banner while prd.json is missing or doesn't parse
+    fn prd_warning(&self) -> Option<String> {
+        let name = self.prd_file_name();
+        match self.prd_problem.as_ref()? {
+            PrdProblem::Missing => Some(format!("{} was deleted — waiting for it to reappear", name)),
+            PrdProblem::Invalid(message) => Some(format!("{} doesn't parse: {}", name, message)),
+        }
+    }
+
+    /// Re-read progress.txt if the watcher flagged a change (or on first use)
+    /// Blockers that weren't open before trigger a desktop notification.
+    pub fn reload_progress_if_needed(&mut self) {
+        let needs_reload = {
+            let Ok(mut flag) = self.progress_needs_reload.lock() else {
+                return;
+            };
+            std::mem::take(&mut *flag)
+        };
+        if !needs_reload {
+            return;
+        }
+
+        // Blockers already open at startup don't notify
+        let initial_load = !std::mem::replace(&mut self.progress_loaded, true);
+        let old_lines = self.progress.as_deref().map_or(0, progress_line_count);
+        self.progress = std::fs::read_to_string(self.task_dir.join("progress.txt")).ok();
+        let new_lines = self.progress.as_deref().map_or(0, progress_line_count);
+        if !initial_load && new_lines > old_lines {
+            self.progress_new_lines = new_lines - old_lines;
+            self.progress_changed_at = Some(Instant::now());
+        }
+        self.progress_lines = new_lines;
+        let blockers = self.progress.as_deref().map(open_blockers).unwrap_or_default();
+        if !initial_load {
+            for blocker in &blockers {
+                if !self.blockers.iter().any(|b| b.text == blocker.text) {
+                    desktop::notify(&format!("Ralph needs attention ({})", blocker.label), &blocker.text);
+                }
+            }
+        }
+        self.blockers = blockers;
+        if self.blockers.is_empty() {
+            self.attention_selected = false;
+        }
+        self.refresh_unverified();
+    }
+
+    /// Cross-check passed criteria against the `Verified:` notes in progress.txt
+    /// (check_verified_notes)
+    pub fn refresh_unverified(&mut self) {
+        self.unverified = match (self.verified_match, self.prd.as_ref()) {
+            (Some(threshold), Some(prd)) => {
+                unverified_criteria(prd, self.progress.as_deref().unwrap_or_default(), threshold)
+            }
+            _ => HashMap::new(),
+        };
+    }
+
+    /// Replace the loaded PRD, publishing events for newly passed stories and criteria
+    pub fn set_prd(&mut self, mut prd: Prd) {
+        if let Some(old) = self.prd.as_ref() {
+            let churn = prd_diff::churn(old, &prd);
+            for change in &churn {
+                append_log_line(
+                    &self.task_dir,
+                    &format!(
+                        "prd_churn iteration={} story={} change={} suspicious={}",
+                        self.current_iteration,
+                        change.story_id,
+                        change.kind.name(),
+                        change.suspicious
+                    ),
+                );
+            }
+            self.prd_churn.extend(churn);
+            let mut criteria = Vec::new();
+            let mut completed = Vec::new();
+            for story in &prd.user_stories {
+                let Some(old_story) = old.user_stories.iter().find(|s| s.id == story.id) else {
+                    continue;
+                };
+                for (i, criterion) in story.acceptance_criteria.iter().enumerate() {
+                    let was_passing = old_story.acceptance_criteria.get(i).is_some_and(|c| c.passes);
+                    if criterion.passes && !was_passing {
+                        criteria.push(SessionEvent::CriterionPassed {
+                            story_id: story.id.clone(),
+                            criterion: i + 1,
+                            description: criterion.description.clone(),
+                        });
+                    }
+                }
+                if story.passes && !old_story.passes {
+                    completed.push(story.id.clone());
+                }
+            }
+            for event in criteria {
+                self.emit_event(event);
+            }
+            for story_id in completed {
+                self.record_story_completion(&story_id);
+                self.emit_event(SessionEvent::StoryPassed { story_id });
+            }
+        }
+        // Expanded cards refer to story IDs; drop them when the set of stories changes
+        let story_ids = |p: &Prd| p.user_stories.iter().map(|s| s.id.clone()).collect::<HashSet<_>>();
+        if self.prd.as_ref().map(story_ids) != Some(story_ids(&prd)) {
+            self.expanded_stories.clear();
+        }
+        // Drop the criterion focus once it passes (or its story or criterion is gone)
+        if let Some(focus) = self.session_state.focused_criterion.as_ref() {
+            let open = prd
+                .user_stories
+                .iter()
+                .find(|s| s.id == focus.story_id)
+                .and_then(|s| s.acceptance_criteria.get(focus.criterion))
+                .is_some_and(|c| !c.passes);
+            if !open {
+                self.session_state.focused_criterion = None;
+                self.session_state.save(&self.task_dir);
+            }
+        }
+        // Forget targets for stories that no longer exist
+        let targeted = &mut self.session_state.targeted_stories;
+        let before = targeted.len();
+        targeted.retain(|id| prd.user_stories.iter().any(|s| &s.id == id));
+        if targeted.len() != before {
+            self.session_state.save(&self.task_dir);
+        }
+        prd.targeted_stories = self.session_state.targeted_stories.clone();
+        self.skipped_stories.retain(|id| prd.user_stories.iter().any(|s| &s.id == id));
+        prd.skipped_stories = self.skipped_stories.clone();
+        self.prd = Some(prd);
+        self.refresh_story_index();
+        self.record_criteria_history();
+        self.refresh_unverified();
+        if self.follow == FollowMode::Following {
+            self.select_current_story();
+        }
+    }
+
+    /// Note each unfinished story's criteria count after a PRD load (for the sparkline)
+    /// Completed and removed stories are dropped from the history.
+    fn record_criteria_history(&mut self) {
+        let Some(prd) = self.prd.as_ref() else {
+            return;
+        };
+        let now = Instant::now();
+        self.criteria_history
+            .retain(|id, _| prd.user_stories.iter().any(|s| &s.id == id && !s.passes));
+        for story in prd.user_stories.iter().filter(|s| !s.passes) {
+            let passed = story.acceptance_criteria.iter().filter(|c| c.passes).count();
+            let history = self.criteria_history.entry(story.id.clone()).or_default();
+            history.push_back((now, passed));
+            if history.len() > CRITERIA_HISTORY_LEN {
+                history.pop_front();
+            }
+        }
+    }
+
+    /// Rebuild the story index after the PRD or the targeted stories change
+    pub fn refresh_story_index(&mut self) {
+        self.story_index = Arc::new(self.prd.as_ref().map(StoryIndex::new).unwrap_or_default());
+    }
+
+    /// Start merging/rebasing onto the merge target on a background thread
+    /// Only called between iterations, when no Claude process is touching the tree.
+    pub fn start_sync(&mut self) {
+        self.sync_requested = false;
+        if self.sync_job.is_some() {
+            return;
+        }
+        let (Some(drift), Some(repo)) = (self.drift.as_ref(), self.repo_root.clone()) else {
+            return;
+        };
+        self.sync_job = Some(SyncJob::start(repo, drift.target.clone(), self.sync_strategy));
+    }
+
+    /// Measure free space on the task directory's filesystem (startup and iteration boundaries)
+    pub fn check_disk_space(&mut self) -> DiskPressure {
+        self.disk_checked_at = Some(Instant::now());
+        self.apply_disk_space(disk::free_space(&self.task_dir).ok())
+    }
+
+    /// While paused for disk space, re-measure every few seconds so the loop resumes once space is freed
+    pub fn poll_disk_space(&mut self) {
+        if self.disk_error.is_some() && self.disk_checked_at.is_none_or(|at| at.elapsed() >= DISK_RECHECK) {
+            self.check_disk_space();
+        }
+    }
+
+    /// Record a free space measurement (None when it couldn't be taken)
+    /// Below --min-free the timeline stops recording; below the hard floor the loop pauses.
+    pub fn apply_disk_space(&mut self, space: Option<DiskSpace>) -> DiskPressure {
+        self.disk_space = space;
+        self.disk_pressure = space.map_or(DiskPressure::Ok, |space| disk::assess(space, self.min_free_mb));
+        self.disk_error = match space {
+            Some(space) if self.disk_pressure == DiskPressure::Critical => Some(format!(
+                "Only {} on the task directory's filesystem; the loop pauses below {} MB\n\
+                 so the agent's writes don't start failing halfway.\n\n\
+                 Free some space: the loop resumes on its own.",
+                disk::describe(space),
+                HARD_FLOOR_MB
+            )),
+            _ => None,
+        };
+        self.disk_pressure
+    }
+
+    /// Header banner while disk space is low
+    fn disk_warning(&self) -> Option<String> {
+        let space = self.disk_space?;
+        match self.disk_pressure {
+            DiskPressure::Ok => None,
+            DiskPressure::Low => Some(format!("Low disk space: {} — timeline recording paused", disk::describe(space))),
+            DiskPressure::Critical => Some(format!("Disk almost full: {} — loop paused", disk::describe(space))),
+        }
+    }
+
+    /// Start the hook for `event` (prd.json's, else ralph.toml's) on a background thread
+    pub fn run_hook(&mut self, event: HookEvent, iteration: u32) {
+        let Some(prd) = self.prd.as_ref() else {
+            return;
+        };
+        let command = match event {
+            HookEvent::IterationEnd => prd.on_iteration_end.as_deref().or(self.on_iteration_end.as_deref()),
+            HookEvent::Complete => prd.on_complete.as_deref().or(self.on_complete.as_deref()),
+        };
+        let Some(command) = command.filter(|command| !command.trim().is_empty()) else {
+            return;
+        };
+        let context = HookContext {
+            task: self.task_dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+            iteration,
+            stories_done: prd.completed_count(),
+        };
+        let command = crate::hooks::expand(command, &context);
+        let timeout = prd.hook_timeout_secs.map(Duration::from_secs).unwrap_or(DEFAULT_HOOK_TIMEOUT);
+        let stories_total = prd.story_total();
+        let mut env = self.iteration_env();
+        for (name, value) in env.iter_mut() {
+            if name == "RALPH_ITERATION" {
+                *value = iteration.to_string();
+            }
+        }
+        env.push(("RALPH_EVENT".to_string(), event.name().to_string()));
+        env.push(("RALPH_TASK".to_string(), context.task));
+        env.push(("RALPH_STORIES_DONE".to_string(), context.stories_done.to_string()));
+        env.push(("RALPH_STORIES_TOTAL".to_string(), stories_total.to_string()));
+        self.hook_jobs.push(HookJob::start(event, iteration, command, env, timeout));
+    }
+
+    /// Log the hooks that have finished (a failure also gets a toast)
+    pub fn poll_hook_jobs(&mut self) {
+        let mut finished = Vec::new();
+        self.hook_jobs.retain(|job| match job.try_finish() {
+            Some((status, ran)) => {
+                finished.push((job.event, job.iteration, status, ran));
+                false
+            }
+            None => true,
+        });
+        for (event, iteration, status, ran) in finished {
+            self.record_hook(event, iteration, status, ran);
+        }
+    }
+
+    /// Wait for the hooks still running (each is bounded by its timeout) and log them
+    pub fn finish_hook_jobs(&mut self) {
+        for job in std::mem::take(&mut self.hook_jobs) {
+            let (event, iteration) = (job.event, job.iteration);
+            let (status, ran) = job.wait();
+            self.record_hook(event, iteration, status, ran);
+        }
+    }
+
+    fn record_hook(&mut self, event: HookEvent, iteration: u32, status: HookStatus, ran: Duration) {
+        append_log_line(
+            &self.task_dir,
+            &format!(
+                "hook event={} iteration={} status={} duration_secs={}",
+                event.name(),
+                iteration,
+                status.label(),
+                ran.as_secs()
+            ),
+        );
+        match status {
+            HookStatus::Exited(Some(0)) => {}
+            HookStatus::Exited(_) => self.show_toast(format!("{} hook failed (exit {})", event.field(), status.label())),
+            HookStatus::TimedOut => self.show_toast(format!("{} hook timed out and was stopped", event.field())),
+            HookStatus::Failed(message) => self.show_toast(format!("{} hook could not run: {}", event.field(), message)),
+        }
+    }
+
+    /// Run the selected story's verify commands in the background (V in the story details)
+    pub fn verify_selected_story(&mut self) {
+        let Some(story_id) = self.selected_story().map(|story| story.id.clone()) else {
+            return;
+        };
+        let targets = self.prd.as_ref().map(|prd| verify_targets(prd, Some(&story_id), false)).unwrap_or_default();
+        if targets.is_empty() {
+            self.show_toast(format!("{} has no verify commands", story_id));
+        } else if self.verify_job.is_some() {
+            self.show_toast("Verify commands are already running");
+        } else {
+            self.start_verify(targets);
+        }
+    }
+
+    /// At an iteration boundary: run the verify commands of every unpassed criterion (verify_criteria)
+    pub fn verify_at_boundary(&mut self) {
+        if !self.verify_criteria || self.verify_job.is_some() {
+            return;
+        }
+        let targets = self.prd.as_ref().map(|prd| verify_targets(prd, None, true)).unwrap_or_default();
+        if !targets.is_empty() {
+            self.start_verify(targets);
+        }
+    }
+
+    fn start_verify(&mut self, targets: Vec<VerifyTarget>) {
+        let repo = self.repo_root.clone().unwrap_or_else(|| PathBuf::from("."));
+        let noun = if targets.len() == 1 { "command" } else { "commands" };
+        self.show_toast(format!("Running {} verify {}…", targets.len(), noun));
+        self.verify_outcome = (0, Vec::new());
+        self.verify_job = Some(VerifyJob::start(targets, repo, self.iteration_env(), self.verify_timeout));
+    }
+
+    /// Record the verify results that came in; a summary toast shows once all are in
+    pub fn poll_verify_job(&mut self) {
+        let Some(job) = self.verify_job.as_mut() else {
+            return;
+        };
+        let results = job.try_results();
+        let done = job.is_done();
+        for result in results {
+            self.record_verify(result);
+        }
+        if done {
+            self.verify_job = None;
+            let (passed, failures) = std::mem::take(&mut self.verify_outcome);
+            if failures.is_empty() {
+                self.show_toast(format!("Verified {} {}", passed, if passed == 1 { "criterion" } else { "criteria" }));
+            } else {
+                self.show_toast(format!("Verify: {} passed, {} failed ({})", passed, failures.len(), failures.join(", ")));
+            }
+        }
+    }
+
+    fn record_verify(&mut self, result: VerifyResult) {
+        let target = &result.target;
+        append_log_line(
+            &self.task_dir,
+            &format!(
+                "verify story={} criterion={} status={} duration_secs={}",
+                target.story_id,
+                target.label,
+                result.status.label(),
+                result.ran.as_secs()
+            ),
+        );
+        let name = format!("{} {}", target.story_id, target.label);
+        // A failing command leaves the criterion as it is, passed or not
+        let failure = match result.status {
+            HookStatus::Exited(Some(0)) => match self.mark_criterion_verified(&result) {
+                Ok(()) => None,
+                Err(e) => Some(format!("{}: {}", name, e)),
+            },
+            HookStatus::Exited(_) => Some(format!("{} exit {}", name, result.status.label())),
+            HookStatus::TimedOut => Some(format!("{} timed out", name)),
+            HookStatus::Failed(ref message) => Some(format!("{} could not run: {}", name, message)),
+        };
+        match failure {
+            Some(failure) => self.verify_outcome.1.push(failure),
+            None => self.verify_outcome.0 += 1,
+        }
+    }
+
+    /// Mark a criterion passed in prd.json with its command's output as the evidence
+    /// (journaled, so u z undoes it). Refused when prd.json no longer has the command there.
+    fn mark_criterion_verified(&mut self, result: &VerifyResult) -> Result<(), String> {
+        let target = &result.target;
+        let doc = journal::read_prd_value(&self.prd_path).map_err(|e| e.to_string())?;
+        let position = doc["userStories"]
+            .as_array()
+            .and_then(|stories| stories.iter().position(|story| story["id"] == target.story_id.as_str()))
+            .ok_or("story is gone from prd.json")?;
+        let pointer = format!("/userStories/{}/acceptanceCriteria/{}", position, target.criterion);
+        let mut criterion = doc
+            .pointer(&pointer)
+            .filter(|criterion| criterion["verify"] == target.command.as_str())
+            .cloned()
+            .ok_or("criterion changed while its command ran")?;
+        criterion["passes"] = serde_json::Value::Bool(true);
+        criterion["evidence"] = serde_json::Value::String(result.evidence());
+        criterion["verifiedAt"] = serde_json::Value::String(format!("{} UTC", format_datetime_utc(unix_now())));
+        let label = format!("{} {} verified", target.story_id, target.label);
+        self.edit_prd_value(label, pointer, criterion).map(|_| ())
+    }
+
+    /// Check new Write/Edit activities (single agent and lanes) against the repository
+    /// and allowedPaths, flagging the ones outside in the feed; new violations are logged
+    pub fn check_activity_scope(&mut self) {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let Ok(cwd) = std::env::current_dir() else {
+            return;
+        };
+        let mut scope: Option<Scope> = None;
+        let states: Vec<Arc<Mutex<PtyState>>> =
+            std::iter::once(&self.pty_state).chain(self.lanes.iter().map(|lane| &lane.pty_state)).cloned().collect();
+        for pty_state in states {
+            let mut state = lock_pty(&pty_state);
+            for activity in state.activities.iter_mut().filter(|a| WRITE_ACTIONS.contains(&a.action_type.as_str())) {
+                if let Some(&out_of_scope) = self.scope_checked.get(&activity.target) {
+                    activity.out_of_scope = out_of_scope;
+                    continue;
+                }
+                let scope = scope.get_or_insert_with(|| {
+                    let root = self.repo_root.clone().unwrap_or_else(|| cwd.clone());
+                    let allowed = self.prd.as_ref().map(|prd| prd.allowed_paths.clone()).unwrap_or_default();
+                    Scope::new(&root, &allowed, home.as_deref())
+                });
+                let outside = scope.outside(&activity.target, &cwd, home.as_deref());
+                activity.out_of_scope = outside.is_some();
+                self.scope_checked.insert(activity.target.clone(), outside.is_some());
+                if let Some(path) = outside
+                    && !self.scope_violations.contains(&path)
+                {
+                    append_log_line(
+                        &self.task_dir,
+                        &format!("scope_violation action={} path={}", activity.action_type, path.display()),
+                    );
+                    self.scope_violations.push(path);
+                }
+            }
+        }
+    }
+
+    /// Banner text while the session has out-of-scope writes
+    fn scope_warning(&self) -> Option<String> {
+        let first = self.scope_violations.first()?;
+        let more = match self.scope_violations.len() - 1 {
+            0 => String::new(),
+            n => format!(" (+{} more)", n),
+        };
+        Some(format!("Agent wrote outside the repository: {}{}", first.display(), more))
+    }
+
+    /// At an iteration boundary: hold the loop if there are new out-of-scope writes (pauseOnOutOfScope)
+    pub fn check_scope_pause(&mut self) {
+        if !self.prd.as_ref().is_some_and(|prd| prd.pause_on_out_of_scope)
+            || self.scope_violations.len() <= self.scope_acknowledged
+        {
+            return;
+        }
+        let paths: Vec<String> =
+            self.scope_violations[self.scope_acknowledged..].iter().map(|path| path.display().to_string()).collect();
+        self.scope_hold = Some(format!(
+            "The agent wrote outside the repository and allowedPaths:\n  {}\n\nCheck these files before the loop goes on.",
+            paths.join("\n  ")
+        ));
+    }
+
+    /// At an iteration boundary: hold the loop if the agent made new suspicious PRD changes (pause_on_prd_churn)
+    pub fn check_churn_pause(&mut self) {
+        let new: Vec<String> = self.prd_churn[self.churn_acknowledged..]
+            .iter()
+            .filter(|change| change.suspicious)
+            .map(PrdChurn::message)
+            .collect();
+        if !self.pause_on_churn || new.is_empty() {
+            return;
+        }
+        self.churn_hold = Some(format!(
+            "The agent changed the stories in prd.json:\n  {}\n\nCheck prd.json before the loop goes on ({} shows the diff since session start).",
+            new.join("\n  "),
+            self.keymap.label(KeyAction::ToggleDiff)
+        ));
+    }
+
+    /// Let the loop go on after a PRD churn pause
+    pub fn dismiss_churn_hold(&mut self) {
+        self.churn_hold = None;
+        self.churn_acknowledged = self.prd_churn.len();
+        self.delay_start = Some(Instant::now());
+    }
+
+    /// Let the loop go on after an out-of-scope pause
+    pub fn dismiss_scope_hold(&mut self) {
+        self.scope_hold = None;
+        self.scope_acknowledged = self.scope_violations.len();
+        self.delay_start = Some(Instant::now());
+    }
+
+    /// Match new Bash activities (single agent and lanes) against the dangerous command
+    /// patterns; a match opens the tripwire modal and freezes the Claude panel
+    pub fn check_tripwire(&mut self) {
+        let states: Vec<(Option<usize>, Arc<Mutex<PtyState>>)> = std::iter::once((None, Arc::clone(&self.pty_state)))
+            .chain(self.lanes.iter().enumerate().map(|(i, lane)| (Some(i), Arc::clone(&lane.pty_state))))
+            .collect();
+        for (lane, pty_state) in states {
+            let mut state = lock_pty(&pty_state);
+            for activity in state.activities.iter_mut().filter(|a| a.action_type == "Bash" && !a.tripwire_checked) {
+                activity.tripwire_checked = true;
+                if let Some(pattern) = self.tripwire.check(&activity.target) {
+                    self.tripwire_alerts.push_back(TripwireAlert {
+                        command: activity.target.clone(),
+                        pattern: pattern.to_string(),
+                        lane,
+                    });
+                }
+            }
+            if self.tripwire_frozen.is_none() && !self.tripwire_alerts.is_empty() && lane.is_none() {
+                self.tripwire_frozen = Some(render_vt100_screen(state.parser.screen()));
+            }
+        }
+        if !self.tripwire_alerts.is_empty() {
+            self.redraw.request();
+        }
+    }
+
+    /// Close the tripwire modal with the user's answer (logged with the command)
+    pub fn answer_tripwire(&mut self, answer: TripwireAnswer) {
+        let Some(alert) = self.tripwire_alerts.pop_front() else {
+            return;
+        };
+        let label = match answer {
+            TripwireAnswer::LetItRun => "let_it_run",
+            TripwireAnswer::Interrupt => "interrupt",
+            TripwireAnswer::PauseLoop => "pause_loop",
+        };
+        append_log_line(
+            &self.task_dir,
+            &format!(
+                "tripwire iteration={} answer={} pattern={:?} command={:?}",
+                self.current_iteration, label, alert.pattern, alert.command
+            ),
+        );
+        match answer {
+            TripwireAnswer::LetItRun => {}
+            TripwireAnswer::Interrupt => {
+                match alert.lane.and_then(|i| self.lanes.get(i)) {
+                    Some(lane) => lane.write(&[0x03]),
+                    None => {
+                        if let Some(ref writer) = self.pty_writer {
+                            writer.send(&[0x03]);
+                        }
+                    }
+                }
+                self.show_toast("Sent Ctrl+C to the agent");
+            }
+            TripwireAnswer::PauseLoop => {
+                self.tripwire_hold = Some(format!(
+                    "The agent ran a command matching \"{}\":\n  {}\n\nCheck its effects before the loop goes on.",
+                    alert.pattern, alert.command
+                ));
+                self.show_toast("Loop will pause after this iteration");
+            }
+        }
+        if self.tripwire_alerts.is_empty() {
+            self.tripwire_frozen = None;
+        }
+    }
+
+    /// Let the loop go on after a tripwire pause
+    pub fn dismiss_tripwire_hold(&mut self) {
+        self.tripwire_hold = None;
+        self.delay_start = Some(Instant::now());
+    }
+
+    /// Collect the result of a finished sync job
+    pub fn poll_sync_job(&mut self) {
+        let Some(result) = self.sync_job.as_ref().and_then(|job| job.try_finish()) else {
+            return;
+        };
+        self.sync_job = None;
+        match result {
+            Ok(_) => {
+                if let Some(ref drift) = self.drift {
+                    drift.request_refresh();
+                }
+            }
+            Err(message) => {
+                self.emit_event(SessionEvent::Error { message: message.clone() });
+                self.sync_error = Some(message);
+            }
+        }
+    }
+
+    /// Variables from ralph.toml's `env` with prd.json's `env` over them (no RALPH_* ones)
+    fn configured_env(&self) -> BTreeMap<&str, &str> {
+        let prd_env = self.prd.as_ref().map(|prd| &prd.env);
+        self.config_env
+            .iter()
+            .chain(prd_env.into_iter().flatten())
+            .filter(|(name, _)| !AUTOMATIC_ENV_VARS.contains(&name.as_str()))
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+
+    /// Environment injected into the agent for the current iteration
+    /// ralph.toml `env` entries, overridden by prd.json's; the automatic RALPH_* variables always win.
+    pub fn iteration_env(&self) -> Vec<(String, String)> {
+        let mut env: Vec<(String, String)> =
+            self.configured_env().into_iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+
+        let task_dir_abs = self.task_dir.canonicalize().unwrap_or_else(|_| self.task_dir.clone());
+        env.push(("RALPH_ITERATION".to_string(), self.current_iteration.to_string()));
+        env.push(("RALPH_TASK_DIR".to_string(), task_dir_abs.to_string_lossy().to_string()));
+        env.push(("RALPH_SESSION_ID".to_string(), self.session_id.clone()));
+        env.push(("RALPH_MAX_ITERATIONS".to_string(), self.max_iterations.to_string()));
+        env
+    }
+
+    /// Names of the variables iteration_env() injects (for display)
+    pub fn injected_env_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.configured_env().into_keys().collect();
+        names.extend(AUTOMATIC_ENV_VARS);
+        names
+    }
+
+    /// Show a message in the footer for a few seconds
+    pub fn show_toast(&mut self, message: impl Into<String>) {
+        self.toast = Some((message.into(), Instant::now()));
+    }
+
+    /// Start the u prefix: the next key restores a backup, undoes the last edit or shows the edit log
+    pub fn open_undo_prefix(&mut self) {
+        self.undo_prefix = true;
+        self.pending_restore = latest_backup(&self.task_dir);
+    }
+
+    /// Leave the u prefix without doing anything
+    pub fn close_undo_prefix(&mut self) {
+        self.undo_prefix = false;
+        self.pending_restore = None;
+    }
+
+    /// Restore the backup awaiting confirmation (journaled, so it can be undone with u z)
+    pub fn confirm_prd_restore(&mut self) {
+        self.undo_prefix = false;
+        if self.pending_restore.take().is_none() {
+            self.show_toast("No prd.json backups to restore");
+            return;
+        }
+        // A prd.json that doesn't parse can't be put back, so that restore isn't journaled
+        let old = journal::read_prd_value(&self.prd_path).ok();
+        match restore_latest(&self.task_dir, &self.prd_path) {
+            Ok(backup) => {
+                self.reload_prd();
+                let name = backup.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                if let (Some(old), Ok(new)) = (old, journal::read_prd_value(&self.prd_path)) {
+                    self.prd_journal.push(PrdEdit::new(format!("restore {}", name), "", old, new));
+                }
+                let committed = self.commit_tui_edit(&format!("restore prd.json from {}", name));
+                self.show_toast(format!("Restored prd.json from {}{}", name, committed));
+            }
+            Err(e) => self.show_toast(format!("Restore failed: {}", e)),
+        }
+    }
+
+    /// Commit prd.json after a TUI edit when the PRD sets autoCommitTuiEdits
+    /// Returns the suffix for the edit's toast (the commit hash, or why it was skipped).
+    fn commit_tui_edit(&self, description: &str) -> String {
+        if !self.prd.as_ref().is_some_and(|p| p.auto_commit_tui_edits) {
+            return String::new();
+        }
+        let message = format!("ralph-tui: {} (session {})", description, self.session_id);
+        let repo = match self.prd_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        match commit_file(repo, &self.prd_path, &message) {
+            Ok(CommitOutcome::Committed(hash)) => format!(" · committed {}", hash),
+            Ok(CommitOutcome::OtherChangesStaged) => " · not committed: other changes are staged".to_string(),
+            Ok(CommitOutcome::Unchanged) => String::new(),
+            Err(_) if repo_root(repo).is_none() => " · not committed: not a git repo".to_string(),
+            Err(e) => format!(" · commit failed: {}", e),
+        }
+    }
+
+    /// Replace one value in prd.json from the TUI: journaled (u z undoes it), reloaded and
+    /// committed when the PRD asks. Returns the toast suffix, or the error message.
+    fn edit_prd_value(&mut self, label: String, pointer: String, value: serde_json::Value) -> Result<String, String> {
+        let old = journal::set_prd_value(&self.task_dir, &self.prd_path, &pointer, value.clone())
+            .map_err(|e| format!("Edit failed: {}", e))?;
+        self.reload_prd();
+        let committed = self.commit_tui_edit(&label);
+        self.prd_journal.push(PrdEdit::new(label, pointer, old, value));
+        Ok(committed)
+    }
+
+    /// Revert the latest prd.json edit made from the TUI this session
+    pub fn undo_prd_edit(&mut self) {
+        self.close_undo_prefix();
+        match journal::undo_last(&mut self.prd_journal, &self.task_dir, &self.prd_path) {
+            Ok(index) => {
+                self.reload_prd();
+                let label = self.prd_journal[index].label.clone();
+                let committed = self.commit_tui_edit(&format!("undo {}", label));
+                self.show_toast(format!("Undid: {}{}", label, committed));
+            }
+            Err(e) => self.show_toast(format!("Undo failed: {}", e)),
+        }
+    }
+
+    /// Tag the tree as the iteration starts when the PRD sets snapshotEachIteration
+    /// The marker is recorded in the iteration log; failures only show a toast.
+    pub fn mark_iteration_start(&mut self) {
+        if !self.prd.as_ref().is_some_and(|p| p.snapshot_each_iteration) {
+            return;
+        }
+        let Some(repo) = self.repo_root.clone() else {
+            return; // The Iterations view explains why there are no markers
+        };
+        let result = create_iteration_marker(&repo, self.current_iteration);
+        match result {
+            Ok(marker) => {
+                append_log_line(
+                    &self.task_dir,
+                    &format!(
+                        "snapshot iteration={} tag={} head={} stash={}",
+                        marker.iteration,
+                        marker.tag,
+                        marker.head,
+                        marker.stash.as_deref().unwrap_or("none")
+                    ),
+                );
+                self.iteration_markers.push(marker);
+                self.selected_marker = self.iteration_markers.len() - 1;
+            }
+            Err(e) => self.show_toast(format!("Iteration snapshot failed: {}", e)),
+        }
+    }
+
+    /// Record the tree as the iteration starts, for the changes view, and drop the last iteration's changes
+    pub fn record_iteration_base(&mut self) {
+        self.iteration_base = self.repo_root.as_deref().and_then(|repo| tree_snapshot(repo).ok());
+        self.iteration_changes = None;
+        self.change_diffs.clear();
+        self.selected_change = 0;
+    }
+
+    /// Open the changes view, listing the files changed this iteration (git runs once per iteration)
+    pub fn open_changes(&mut self) {
+        self.ralph_view_mode = RalphViewMode::Changes;
+        self.ralph_scroll_offset = 0;
+        if self.iteration_changes.is_some() {
+            return;
+        }
+        if let (Some(repo), Some(base)) = (self.repo_root.as_deref(), self.iteration_base.as_deref()) {
+            self.iteration_changes = Some(Arc::new(IterationChanges::load(repo, base, self.current_iteration)));
+        }
+    }
+
+    /// Pick the file above (up) or below the current one in the changes view, wrapping around
+    pub fn move_change_selection(&mut self, up: bool) {
+        let count = self.changed_files().len();
+        if count == 0 {
+            return;
+        }
+        self.selected_change = if up {
+            (self.selected_change.min(count - 1) + count - 1) % count
+        } else {
+            (self.selected_change + 1) % count
+        };
+    }
+
+    /// Show the picked file's diff against the iteration start (in the Ralph terminal)
+    pub fn open_change_diff(&mut self) {
+        let Some(path) = self.changed_files().get(self.selected_change).map(|file| file.path.clone()) else {
+            self.show_toast("No changed file selected");
+            return;
+        };
+        let (Some(repo), Some(base)) = (self.repo_root.as_deref(), self.iteration_base.as_deref()) else {
+            return;
+        };
+        let diff = self
+            .change_diffs
+            .entry(path.clone())
+            .or_insert_with(|| Arc::new(FileDiff::load(repo, base, &path)));
+        self.change_diff = Some(Arc::clone(diff));
+        self.changes_scroll_offset = self.ralph_scroll_offset;
+        self.ralph_view_mode = RalphViewMode::ChangeDiff;
+        self.ralph_scroll_offset = 0;
+    }
+
+    /// Back from a diff to the changes list, where it was scrolled to
+    pub fn close_change_diff(&mut self) {
+        self.change_diff = None;
+        self.ralph_view_mode = RalphViewMode::Changes;
+        self.ralph_scroll_offset = self.changes_scroll_offset;
+    }
+
+    /// Files in the changes view (empty until it has been opened this iteration)
+    fn changed_files(&self) -> &[crate::changes::ChangedFile] {
+        match self.iteration_changes.as_deref() {
+            Some(IterationChanges { files: Ok(files), .. }) => files,
+            _ => &[],
+        }
+    }
+
+    /// Move the marker cursor in the iterations view (list is newest first; wraps)
+    pub fn move_marker_selection(&mut self, older: bool) {
+        let count = self.iteration_markers.len();
+        if count == 0 {
+            return;
+        }
+        self.selected_marker = if older {
+            (self.selected_marker + count - 1) % count
+        } else {
+            (self.selected_marker + 1) % count
+        };
+    }
+
+    /// Pick the activity above (up) or below the current one in the activity view, wrapping around
+    pub fn move_activity_selection(&mut self, up: bool) {
+        let count = lock_pty(&self.pty_state).get_activities().len();
+        if count == 0 {
+            return;
+        }
+        self.selected_activity = if up {
+            (self.selected_activity.min(count - 1) + count - 1) % count
+        } else {
+            (self.selected_activity + 1) % count
+        };
+    }
+
+    /// Preview the file named by the picked activity (read-only, in the Ralph terminal)
+    pub fn open_activity_preview(&mut self) {
+        let Some((path, _)) = self.selected_file_reference() else {
+            self.show_toast("No file path in the selected entry");
+            return;
+        };
+        self.file_preview = Some(Arc::new(FilePreview::load(&path)));
+        self.activity_scroll_offset = self.ralph_scroll_offset;
+        self.ralph_view_mode = RalphViewMode::FilePreview;
+        self.ralph_scroll_offset = 0;
+    }
+
+    /// Back from the preview to the activity list, where it was scrolled to
+    pub fn close_file_preview(&mut self) {
+        self.file_preview = None;
+        self.ralph_view_mode = RalphViewMode::Activity;
+        self.ralph_scroll_offset = self.activity_scroll_offset;
+    }
+
+    /// Move the pick in the links view (wraps at either end)
+    pub fn move_link_selection(&mut self, up: bool) {
+        let count = lock_pty(&self.pty_state).links.len();
+        if count == 0 {
+            return;
+        }
+        self.selected_link = if up {
+            (self.selected_link.min(count - 1) + count - 1) % count
+        } else {
+            (self.selected_link + 1) % count
+        };
+    }
+
+    /// The link picked in the links view
+    pub fn selected_link(&self) -> Option<String> {
+        let state = lock_pty(&self.pty_state);
+        state.links.get(self.selected_link.min(state.links.len().saturating_sub(1))).cloned()
+    }
+
+    /// Open the picked link in the browser (xdg-open / open)
+    pub fn open_selected_link(&mut self) {
+        let Some(link) = self.selected_link() else {
+            self.show_toast("No links yet");
+            return;
+        };
+        match desktop::open_url(&link) {
+            Ok(()) => self.show_toast(format!("Opening {}", link)),
+            Err(e) => self.show_toast(format!("Couldn't open link: {}", e)),
+        }
+    }
+
+    /// Ask for confirmation before rolling back to the selected marker
+    pub fn arm_rollback(&mut self) {
+        if !self.git_available {
+            self.show_toast("Rollback unavailable — not a git repo");
+            return;
+        }
+        self.rollback_armed = !self.iteration_markers.is_empty();
+    }
+
+    /// Roll the tree back to the selected iteration start (git reset --hard)
+    pub fn confirm_rollback(&mut self) {
+        self.rollback_armed = false;
+        let Some(marker) = self.iteration_markers.get(self.selected_marker).cloned() else {
+            return;
+        };
+        let Some(repo) = self.repo_root.clone() else {
+            return;
+        };
+        let result = rollback_to_marker(&repo, &marker);
+        match result {
+            Ok(()) => {
+                append_log_line(&self.task_dir, &format!("rollback iteration={} head={}", marker.iteration, marker.head));
+                self.reload_prd();
+                self.show_toast(format!("Rolled back to the start of iteration {}", marker.iteration));
+            }
+            Err(e) => self.show_toast(format!("Rollback failed: {}", e)),
+        }
+    }
+
+    /// Delete this task's iteration marker tags (the session completed, so they aren't needed)
+    pub fn clear_iteration_markers(&mut self) {
+        if self.iteration_markers.is_empty() {
+            return;
+        }
+        if let Some(ref repo) = self.repo_root {
+            let _ = remove_iteration_markers(repo);
+        }
+        self.iteration_markers.clear();
+    }
+
+    /// Footer prompt while a rollback awaits confirmation
+    fn rollback_prompt(&self) -> String {
+        match self.iteration_markers.get(self.selected_marker) {
+            Some(marker) => format!(
+                "Roll back to the start of iteration {} ({})? git reset --hard DISCARDS everything since · Y confirm, any other key cancels",
+                marker.iteration,
+                &marker.head[..marker.head.len().min(7)]
+            ),
+            None => String::new(),
+        }
+    }
+
+    /// Whether a guarded chord (Ctrl+C/D/Z) should be sent to the agent now
+    /// The first press only arms the guard; the same chord again within KEY_GUARD_WINDOW sends it.
+    pub fn confirm_guarded_chord(&mut self, chord: char) -> bool {
+        if !self.key_guard {
+            return true;
+        }
+        match self.pending_chord.take() {
+            Some((pending, pressed)) if pending == chord && pressed.elapsed() < KEY_GUARD_WINDOW => true,
+            _ => {
+                self.pending_chord = Some((chord, Instant::now()));
+                false
+            }
+        }
+    }
+
+    /// Footer hint while a guarded chord awaits its second press
+    fn pending_chord_hint(&self) -> Option<String> {
+        let (chord, pressed) = self.pending_chord?;
+        if pressed.elapsed() >= KEY_GUARD_WINDOW {
+            return None;
+        }
+        let effect = match chord {
+            'c' => "interrupt agent",
+            'd' => "send EOF to agent",
+            _ => "suspend agent",
+        };
+        Some(format!("Press Ctrl+{} again to {}", chord.to_ascii_uppercase(), effect))
+    }
+
+    /// Show or hide the progress.txt tail strip (the PTY is resized to fit)
+    pub fn toggle_progress_strip(&mut self) {
+        self.progress_strip = !self.progress_strip;
+    }
+
+    /// Open the selected story's progress.txt entries scrolled to the latest ones (or close them)
+    pub fn toggle_progress(&mut self) {
+        if self.ralph_view_mode == RalphViewMode::Progress {
+            self.ralph_view_mode = RalphViewMode::Normal;
+            self.ralph_scroll_offset = 0;
+            return;
+        }
+        self.reload_progress_if_needed();
+        self.ralph_view_mode = RalphViewMode::Progress;
+        let entries = match (self.selected_story(), self.progress.as_deref()) {
+            (Some(story), Some(content)) => story_progress_lines(content, &story.id).map_or(0, |lines| lines.len()),
+            _ => 0,
+        };
+        // The view adds the scroll hint, a blank line and the "Progress for" header above them
+        self.ralph_scroll_offset = (entries + 3).saturating_sub(self.ralph_size.lines() as usize);
+    }
+
+    /// Note progress.txt's size as an iteration starts, for the "+N this iter" count
+    pub fn mark_progress_baseline(&mut self) {
+        self.reload_progress_if_needed();
+        self.progress_lines_at_iteration_start = Some(self.progress_lines);
+    }
+
+    /// Hide or show the Ralph status panel and remember it for the task (the PTY is resized to fit)
+    pub fn toggle_sidebar(&mut self) {
+        self.sidebar_hidden = !self.sidebar_hidden;
+        self.session_state.sidebar_hidden = self.sidebar_hidden;
+        self.session_state.save(&self.task_dir);
+        if self.sidebar_hidden {
+            self.show_toast(format!("Sidebar hidden - press {} to show it", self.keymap.label(KeyAction::ToggleSidebar)));
+        }
+    }
+
+    /// Toast for story list keys pressed while the sidebar is hidden
+    pub fn sidebar_hidden_hint(&self) -> String {
+        format!("Story list hidden - press {} to show the sidebar", self.keymap.label(KeyAction::ToggleSidebar))
+    }
+
+    /// Last lines of progress.txt for the tail strip (trailing blank lines skipped)
+    fn progress_tail(&self) -> Vec<String> {
+        let Some(progress) = self.progress.as_deref() else {
+            return Vec::new();
+        };
+        let lines: Vec<&str> = progress.trim_end().lines().collect();
+        let start = lines.len().saturating_sub(PROGRESS_STRIP_LINES);
+        lines[start..].iter().map(|line| line.to_string()).collect()
+    }
+
+    /// Current footer toast, if it hasn't expired
+    pub fn active_toast(&self) -> Option<&str> {
+        const TOAST_DURATION: Duration = Duration::from_secs(4);
+        match self.toast {
+            Some((ref message, shown)) if shown.elapsed() < TOAST_DURATION => Some(message),
+            _ => None,
+        }
+    }
+
+    /// File mentioned in the entry selected in the current Ralph view
+    /// Progress view: first path at or below the scroll position.
+    /// Normal view: the most recent activity that names a file.
+    pub fn selected_file_reference(&self) -> Option<(String, Option<u32>)> {
+        match self.ralph_view_mode {
+            RalphViewMode::Progress => {
+                let prd = self.prd.as_ref()?;
+                let story = prd.stories_by_priority().get(self.selected_story_index).copied()?;
+                let content = std::fs::read_to_string(self.task_dir.join("progress.txt")).ok()?;
+                let entries = story_progress_lines(&content, &story.id)?;
+                // Rendered line 0 is the "Progress for" header, so entry i is at scroll i + 1
+                let first_visible = self.ralph_scroll_offset.saturating_sub(1);
+                entries.iter().skip(first_visible).find_map(|line| extract_file_path(line))
+            }
+            RalphViewMode::Normal => {
+                let activities = lock_pty(&self.pty_state).get_activities();
+                activities.iter().find_map(|activity| extract_file_path(&activity.target))
+            }
+            RalphViewMode::Activity => {
+                let activities = lock_pty(&self.pty_state).get_activities();
+                extract_file_path(&activities.get(self.selected_activity)?.target)
+            }
+            RalphViewMode::FilePreview => Some((self.file_preview.as_ref()?.path.clone(), None)),
+            RalphViewMode::Changes | RalphViewMode::ChangeDiff => {
+                let path = match self.change_diff {
+                    Some(ref diff) if self.ralph_view_mode == RalphViewMode::ChangeDiff => diff.path.clone(),
+                    _ => self.changed_files().get(self.selected_change)?.path.clone(),
+                };
+                Some((self.repo_root.as_ref()?.join(path).display().to_string(), None))
+            }
+            _ => None,
+        }
+    }
+
+    /// The selected story (None while the completed group row is selected)
+    pub fn selected_story(&self) -> Option<&UserStory> {
+        if self.completed_group_selected {
+            return None;
+        }
+        self.story_index.story(self.prd.as_ref()?, self.selected_story_index)
+    }
+
+    /// Open the quick actions menu on the selected story
+    pub fn open_story_menu(&mut self) {
+        if self.selected_story().is_some() {
+            self.story_menu = Some(0);
+        }
+    }
+
+    /// Move the highlighted quick action (wraps at either end)
+    pub fn move_story_menu(&mut self, down: bool) {
+        let count = StoryAction::ALL.len();
+        self.story_menu = self.story_menu.map(|i| if down { (i + 1) % count } else { (i + count - 1) % count });
+    }
+
+    /// Flip the selected story's `passes` in prd.json
+    pub fn toggle_selected_story_passed(&mut self) {
+        let Some(story) = self.selected_story() else {
+            return;
+        };
+        let (id, passes) = (story.id.clone(), !story.passes);
+        let Some(position) = self.prd.as_ref().and_then(|prd| prd.user_stories.iter().position(|s| s.id == id)) else {
+            return;
+        };
+        let pointer = format!("/userStories/{}/passes", position);
+        match self.edit_prd_value(format!("{} passes", id), pointer, serde_json::Value::Bool(passes)) {
+            Ok(committed) => {
+                let state = if passes { "passed" } else { "not passed" };
+                self.show_toast(format!("Marked {} {}{}", id, state, committed));
+            }
+            Err(message) => self.show_toast(message),
+        }
+    }
+
+    /// Replace a story's notes in prd.json (the quick actions menu edits them in $EDITOR)
+    pub fn set_story_notes(&mut self, story_id: &str, notes: String) {
+        let Some(prd) = self.prd.as_ref() else {
+            return;
+        };
+        let Some(position) = prd.user_stories.iter().position(|s| s.id == story_id) else {
+            return;
+        };
+        if prd.user_stories[position].notes == notes {
+            self.show_toast("Notes unchanged");
+            return;
+        }
+        let pointer = format!("/userStories/{}/notes", position);
+        match self.edit_prd_value(format!("{} notes", story_id), pointer, serde_json::Value::String(notes)) {
+            Ok(committed) => self.show_toast(format!("Updated notes on {}{}", story_id, committed)),
+            Err(message) => self.show_toast(message),
+        }
+    }
+
+    /// Open the details view on the selected story's first open criterion and focus the
+    /// next iterations on it (h/l and F there pick another)
+    pub fn focus_selected_story_criterion(&mut self) {
+        let Some(story) = self.selected_story() else {
+            return;
+        };
+        let Some(criterion) = story.acceptance_criteria.iter().position(|c| !c.passes) else {
+            self.show_toast(format!("All criteria of {} pass", story.id));
+            return;
+        };
+        let focus = CriterionFocus { story_id: story.id.clone(), criterion };
+        self.ralph_view_mode = RalphViewMode::StoryDetails;
+        self.ralph_scroll_offset = 0;
+        self.selected_criterion = criterion;
+        if self.session_state.focused_criterion.as_ref() != Some(&focus) {
+            self.toggle_criterion_focus();
+        }
+    }
+
+    /// Skip the selected story for the rest of the session, or stop skipping it
+    /// Skipped stories are passed over when picking the active story and named in the prompt.
+    pub fn toggle_selected_story_skipped(&mut self) {
+        let Some(id) = self.selected_story().map(|s| s.id.clone()) else {
+            return;
+        };
+        let message = if self.skipped_stories.remove(&id) {
+            format!("{} is no longer skipped", id)
+        } else {
+            self.skipped_stories.insert(id.clone());
+            format!("Skipping {} for this session", id)
+        };
+        if let Some(ref mut prd) = self.prd {
+            prd.skipped_stories = self.skipped_stories.clone();
+        }
+        self.refresh_story_index();
+        self.show_toast(message);
+    }
+
+    /// Toggle inline acceptance criteria on the selected story card
+    pub fn toggle_selected_story_expanded(&mut self) {
+        if self.completed_group_selected {
+            return;
+        }
+        let Some(ref prd) = self.prd else {
+            return;
+        };
+        let Some(story) = prd.stories_by_priority().get(self.selected_story_index).copied() else {
+            return;
+        };
+        if !self.expanded_stories.remove(&story.id) {
+            self.expanded_stories.insert(story.id.clone());
+        }
+    }
+
+    /// Add or remove the selected story from the stories this run is limited to
+    pub fn toggle_selected_story_target(&mut self) {
+        if self.completed_group_selected {
+            return;
+        }
+        let Some(ref mut prd) = self.prd else {
+            return;
+        };
+        let Some(story_id) = prd.stories_by_priority().get(self.selected_story_index).map(|s| s.id.clone()) else {
+            return;
+        };
+        let targeted = &mut self.session_state.targeted_stories;
+        if !targeted.remove(&story_id) {
+            targeted.insert(story_id);
+        }
+        prd.targeted_stories = targeted.clone();
+        self.session_state.save(&self.task_dir);
+        self.refresh_story_index();
+    }
+
+    /// Move the criterion cursor in the story details view (wraps at either end)
+    pub fn move_criterion_selection(&mut self, down: bool) {
+        let count = self
+            .prd
+            .as_ref()
+            .and_then(|prd| prd.stories_by_priority().get(self.selected_story_index).map(|s| s.acceptance_criteria.len()))
+            .unwrap_or(0);
+        if count == 0 {
+            return;
+        }
+        self.selected_criterion = if down {
+            (self.selected_criterion + 1) % count
+        } else {
+            (self.selected_criterion + count - 1) % count
+        };
+    }
+
+    /// Focus the next iterations on the selected criterion, or clear the focus if it already is
+    pub fn toggle_criterion_focus(&mut self) {
+        let Some(story) = self.prd.as_ref().and_then(|prd| prd.stories_by_priority().get(self.selected_story_index).copied())
+        else {
+            return;
+        };
+        let Some(criterion) = story.acceptance_criteria.get(self.selected_criterion) else {
+            return;
+        };
+        let focus = CriterionFocus { story_id: story.id.clone(), criterion: self.selected_criterion };
+        let message = if self.session_state.focused_criterion.as_ref() == Some(&focus) {
+            self.session_state.focused_criterion = None;
+            "Criterion focus cleared".to_string()
+        } else if criterion.passes {
+            "Criterion already passes".to_string()
+        } else {
+            let message = format!("Next iteration focuses on {} criterion {}", story.id, criterion.label(focus.criterion));
+            self.session_state.focused_criterion = Some(focus);
+            message
+        };
+        self.session_state.save(&self.task_dir);
+        self.show_toast(message);
+    }
+
+    /// Go back to working on all stories
+    pub fn clear_story_targets(&mut self) {
+        if self.session_state.targeted_stories.is_empty() {
+            return;
+        }
+        self.session_state.targeted_stories.clear();
+        if let Some(ref mut prd) = self.prd {
+            prd.targeted_stories.clear();
+        }
+        self.session_state.save(&self.task_dir);
+        self.refresh_story_index();
+        self.show_toast("Working on all stories");
+    }
+
+    /// Remember which story is active as an iteration starts (for stuck detection)
+    pub fn begin_story_attempt(&mut self) {
+        self.iteration_story = self.prd.as_ref().and_then(|prd| prd.current_story()).map(|story| {
+            let passed = story.acceptance_criteria.iter().filter(|c| c.passes).count();
+            (story.id.clone(), passed)
+        });
+    }
+
+    /// Whether the story active when the iteration started now passes in prd.json (or has
+    /// every criterion passed) and the agent has printed nothing for prd_idle_after
+    /// A fallback for a stop hook signal that never arrived; output resets the quiet time,
+    /// so a streaming agent is never cut off.
+    pub fn story_done_while_quiet(&self) -> bool {
+        let (Some(after), Some((story_id, _))) = (self.prd_idle_after, self.iteration_story.as_ref()) else {
+            return false;
+        };
+        let story_done = self
+            .prd
+            .as_ref()
+            .and_then(|prd| prd.user_stories.iter().find(|s| &s.id == story_id))
+            .is_some_and(|story| {
+                story.passes
+                    || (!story.acceptance_criteria.is_empty() && story.acceptance_criteria.iter().all(|c| c.passes))
+            });
+        story_done && lock_pty(&self.pty_state).last_output_at.is_some_and(|at| at.elapsed() >= after)
+    }
+
+    /// Test-run summary seen in the running iteration's output
+    /// Also remembered as the session's latest result for the completion summary.
+    pub fn iteration_output(&mut self) -> OutputSummary {
+        let output = lock_pty(&self.pty_state).output_summary();
+        if output.tests.is_some() {
+            self.last_test_result = output.tests;
+        }
+        output
+    }
+
+    /// How this task's run went, for its entry in a batch queue
+    pub fn queue_outcome(&self, result: &io::Result<()>) -> (QueueStatus, String) {
+        let (completed, total) = self.prd.as_ref().map_or((0, 0), |p| (p.completed_count(), p.story_total()));
+        let progress = format!("{}/{} stories, {} iterations", completed, total, self.current_iteration);
+        if let Err(e) = result {
+            (QueueStatus::Failed, e.to_string())
+        } else if self.prd.as_ref().is_some_and(|p| p.all_stories_pass()) {
+            (QueueStatus::Done, progress)
+        } else if self.aborted() {
+            (QueueStatus::Skipped, progress)
+        } else {
+            (QueueStatus::Failed, progress)
+        }
+    }
+
+    /// Whether the session was stopped (Ctrl+Q or a signal) rather than ending on its own
+    pub fn aborted(&self) -> bool {
+        self.iteration_state == IterationState::Aborted
+    }
+
+    /// End the session after a termination signal, logging the interrupted iteration
+    pub fn stop_for_signal(&mut self) {
+        if self.iteration_state == IterationState::Running {
+            let output = self.iteration_output();
+            append_iteration_log(
+                &self.task_dir,
+                self.current_iteration,
+                IterationEndSource::Signal,
+                IterationState::Aborted,
+                self.iteration_start.elapsed(),
+                output,
+                self.model.as_deref(),
+                self.agent_version.as_ref(),
+            );
+        }
+        self.iteration_state = IterationState::Aborted;
+        self.iteration_ending = None;
+        self.session_state.save(&self.task_dir);
+    }
+
+    /// Update the active story's attempt and iteration counters at an iteration boundary
+    /// The attempt counter resets when any criterion for the story passed during the iteration.
+    pub fn record_story_attempt(&mut self) {
+        let Some((story_id, passed_at_start)) = self.iteration_story.take() else {
+            return;
+        };
+        let Some(story) = self
+            .prd
+            .as_ref()
+            .and_then(|prd| prd.user_stories.iter().find(|s| s.id == story_id))
+        else {
+            return;
+        };
+
+        let passed_now = story.acceptance_criteria.iter().filter(|c| c.passes).count();
+        let improved = story.passes || passed_now > passed_at_start;
+        *self.session_state.story_iterations.entry(story_id.clone()).or_insert(0) += 1;
+        *self.session_state.story_seconds.entry(story_id.clone()).or_insert(0) += self.iteration_start.elapsed().as_secs();
+        let attempts = self.session_state.story_attempts.entry(story_id).or_insert(0);
+        if improved {
+            *attempts = 0;
+        } else {
+            *attempts += 1;
+        }
+        self.session_state.save(&self.task_dir);
+    }
+
+    /// Record that a story flipped to passing in the current iteration (persisted with session state)
+    fn record_story_completion(&mut self, story_id: &str) {
+        let completed_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        self.session_state.story_completions.insert(
+            story_id.to_string(),
+            StoryCompletion { iteration: self.current_iteration, completed_at },
+        );
+        self.session_state.save(&self.task_dir);
+    }
+
+    /// Record an event in the task's timeline and publish it on the events socket (if any)
+    pub fn emit_event(&mut self, event: SessionEvent) {
+        self.emit_event_at(now_millis(), event);
+    }
+
+    /// Like `emit_event`, for an event detected earlier at `timestamp` (Unix ms)
+    pub fn emit_event_at(&mut self, timestamp: u64, event: SessionEvent) {
+        if let Some(ref mut events) = self.events {
+            events.emit_at(timestamp, &event);
+        }
+        if let Some(ref metrics) = self.metrics {
+            metrics.update(|metrics| metrics.observe(&event));
+        }
+        // Low on disk space: stop recording rather than add to the problem
+        if self.disk_pressure == DiskPressure::Ok {
+            let entry = TimelineEntry { timestamp, iteration: self.current_iteration, event };
+            let _ = append_timeline(&self.task_dir, &entry);
+        }
+    }
+
+    /// Build the story list rows for the current PRD and layout
+    pub fn story_rows(&self) -> Vec<StoryListRow> {
+        match self.prd {
+            Some(ref prd) => build_story_rows(
+                &self.story_index.stories(prd),
+                self.story_index.current,
+                self.story_list_layout,
+                self.completed_group_expanded,
+            ),
+            None => Vec::new(),
+        }
+    }
+
+    /// Position of the selected row within `rows`
+    /// A selected story hidden inside the collapsed group maps to the group row.
+    pub fn selected_row_position(&self, rows: &[StoryListRow]) -> usize {
+        selected_row_position(rows, self.selected_story_index, self.completed_group_selected)
+    }
+
+    /// Move the story selection by one row (wrapping at either end)
+    /// The "Needs attention" section (when shown) sits above the first row.
+    pub fn move_story_selection(&mut self, down: bool) {
+        let rows = self.story_rows();
+        if rows.is_empty() {
+            return;
+        }
+        if self.follow == FollowMode::Following {
+            self.follow = FollowMode::Suspended;
+        }
+        let current = self.selected_row_position(&rows);
+        let has_attention = !self.blockers.is_empty();
+        let next = if self.attention_selected {
+            self.attention_selected = false;
+            if down { 0 } else { rows.len() - 1 }
+        } else if has_attention && (if down { current + 1 == rows.len() } else { current == 0 }) {
+            self.attention_selected = true;
+            return;
+        } else if down {
+            (current + 1) % rows.len()
+        } else if current > 0 {
+            current - 1
+        } else {
+            rows.len() - 1
+        };
+        match rows[next] {
+            StoryListRow::Story(idx) => {
+                self.selected_story_index = idx;
+                self.completed_group_selected = false;
+            }
+            StoryListRow::CompletedGroup(_) => {
+                self.completed_group_selected = true;
+            }
+        }
+        // Reset scroll and the criterion cursor when changing story
+        self.ralph_scroll_offset = 0;
+        self.selected_criterion = 0;
+    }
+
+    /// Cycle the Ralph terminal height and remember it for the task (the PTY is resized to fit)
+    pub fn cycle_ralph_size(&mut self, larger: bool) {
+        self.ralph_size = self.ralph_size.cycled(larger);
+        self.session_state.ralph_terminal_size = self.ralph_size;
+        self.session_state.save(&self.task_dir);
+    }
+
+    /// Select the active story (the list scrolls to it on the next frame)
+    /// Returns false when every story is done.
+    fn select_current_story(&mut self) -> bool {
+        let Some(idx) = self.story_index.current else {
+            return false;
+        };
+        if idx != self.selected_story_index || self.completed_group_selected || self.attention_selected {
+            self.selected_story_index = idx;
+            self.completed_group_selected = false;
+            self.attention_selected = false;
+            self.ralph_scroll_offset = 0;
+            self.selected_criterion = 0;
+        }
+        true
+    }
+
+    /// Jump the selection to the active story (c)
+    pub fn jump_to_current_story(&mut self) {
+        if !self.select_current_story() {
+            self.show_toast("No active story — all stories are done");
+        }
+    }
+
+    /// Turn follow mode on (also resuming it after manual navigation) or off (F)
+    pub fn toggle_follow(&mut self) {
+        self.follow = match self.follow {
+            FollowMode::Following => FollowMode::Off,
+            FollowMode::Off | FollowMode::Suspended => FollowMode::Following,
+        };
+        if self.follow == FollowMode::Following {
+            self.jump_to_current_story();
+        }
+    }
+
+    /// Make the running iteration the last one, or undo that and restore the budget
+    /// The loop then ends at the next iteration boundary instead of killing the agent.
+    /// Both changes are recorded in the iteration log.
+    pub fn toggle_wind_down(&mut self) {
+        if let Some(max_iterations) = self.iterations_reduced_from.take() {
+            self.max_iterations = max_iterations;
+            append_log_line(
+                &self.task_dir,
+                &format!("iterations_restored iteration={} max={}", self.current_iteration, max_iterations),
+            );
+            self.show_toast(format!("Iteration budget back to {}", max_iterations));
+        } else if self.current_iteration < self.max_iterations {
+            append_log_line(
+                &self.task_dir,
+                &format!(
+                    "iterations_reduced iteration={} from={} to={}",
+                    self.current_iteration, self.max_iterations, self.current_iteration
+                ),
+            );
+            self.iterations_reduced_from = Some(self.max_iterations);
+            self.max_iterations = self.current_iteration;
+            self.show_toast(format!("Stopping after iteration {}", self.current_iteration));
+        } else {
+            self.show_toast("This is already the last iteration");
+        }
+    }
+
+    /// Terminal title showing the task and its progress, e.g. "ralph: my-feature 4/9 · iter 3/10"
+    pub fn terminal_title_text(&self) -> String {
+        let task = match self.task_dir.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => self.prd.as_ref().map(|p| p.project.clone()).unwrap_or_default(),
+        };
+        let stories = self
+            .prd
+            .as_ref()
+            .map(|p| format!(" {}/{}", p.completed_count(), p.story_total()))
+            .unwrap_or_default();
+        format!("ralph: {}{} · iter {}/{}", task, stories, self.current_iteration, self.max_iterations)
+    }
+
+    /// Toggle between the flat and grouped story list layouts
+    pub fn toggle_story_list_layout(&mut self) {
+        self.story_list_layout = match self.story_list_layout {
+            StoryListLayout::Flat => StoryListLayout::Grouped,
+            StoryListLayout::Grouped => StoryListLayout::Flat,
+        };
+        self.completed_group_selected = false;
+        self.story_scroll_offset = 0;
+        self.session_state.story_list_layout = self.story_list_layout;
+        self.session_state.save(&self.task_dir);
+    }
+
+    /// Expand or collapse the completed stories group (remembered for the task)
+    pub fn toggle_completed_group(&mut self) {
+        self.completed_group_expanded = !self.completed_group_expanded;
+        self.session_state.completed_group_expanded = self.completed_group_expanded;
+        self.session_state.save(&self.task_dir);
+    }
+
+    /// Log a panic in the agent output reader and raise the banner
+    /// The reader keeps going, so the iteration carries on with possibly incomplete output.
+    pub fn report_reader_error(&mut self, message: String) {
+        append_log_line(
+            &self.task_dir,
+            &format!("reader_error iteration={} message={:?}", self.current_iteration, message),
+        );
+        self.reader_error = Some(message);
+    }
+
+    /// Re-classify Claude as starting, working or idle, and nudge it after prolonged idle
+    pub fn update_agent_activity(&mut self) {
+        let (activity, quiet_for) = {
+            let state = lock_pty(&self.pty_state);
+            let quiet_for = state.last_output_at.map(|t| t.elapsed());
+            let last_row = state.last_visible_row();
+            (detect_agent_activity(quiet_for, &last_row, self.idle_after, &self.prompt_pattern), quiet_for)
+        };
+        self.agent_activity = activity;
+        if activity != AgentActivity::Idle {
+            self.nudge_sent = false;
+            return;
+        }
+        if let (Some(after), Some(quiet)) = (self.nudge_after, quiet_for)
+            && !self.nudge_sent
+            && quiet >= after
+        {
+            let message = format!("{}\r", self.nudge_message);
+            self.write_to_pty(message.as_bytes());
+            self.nudge_sent = true;
+            self.show_toast("Nudged idle agent");
+        }
+    }
+
+    /// Advance the spinner and pulse animations once per OUTPUT_FRAME. They hold still
+    /// while the terminal is unfocused, and when no story is active and no agent runs.
+    pub fn tick_animation(&mut self) {
+        if !self.redraw.is_focused() || !self.is_animated() {
+            return;
+        }
+        if self.last_animation_update.elapsed() >= OUTPUT_FRAME {
+            self.animation_tick = self.animation_tick.wrapping_add(1);
+            self.last_animation_update = Instant::now();
+        }
+    }
+
+    /// Whether anything on screen animates: an active story or a running agent
+    fn is_animated(&self) -> bool {
+        let active_story = self.prd.as_ref().is_some_and(|prd| prd.current_story().is_some());
+        let agent_running = if self.lanes.is_empty() {
+            self.master_pty.is_some() && !lock_pty(&self.pty_state).child_exited
+        } else {
+            self.lanes.iter().any(|lane| lane.state == IterationState::Running)
+        };
+        active_story || agent_running
+    }
+
+    /// Whether the session has used up --max-runtime
+    pub fn runtime_exhausted(&self) -> bool {
+        self.max_runtime.is_some_and(|limit| self.session_start.elapsed() >= limit)
+    }
+
+    /// Enforce --max-runtime mid-iteration: ask the agent to wrap up once the budget is
+    /// spent, then report true when RUNTIME_WRAP_UP_GRACE has passed and it must be stopped
+    pub fn check_runtime_limit(&mut self) -> bool {
+        if !self.runtime_exhausted() {
+            return false;
+        }
+        match self.wrap_up_requested_at {
+            Some(requested) => requested.elapsed() >= RUNTIME_WRAP_UP_GRACE,
+            None => {
+                let message = format!("{}\r", RUNTIME_WRAP_UP_MESSAGE);
+                if self.lanes.is_empty() {
+                    self.write_to_pty(message.as_bytes());
+                } else {
+                    for lane in self.lanes.iter().filter(|lane| lane.state == IterationState::Running) {
+                        lane.write(message.as_bytes());
+                    }
+                }
+                self.wrap_up_requested_at = Some(Instant::now());
+                self.show_toast("Runtime limit reached - asking the agent to wrap up");
+                false
+            }
+        }
+    }
+
+    /// Enforce --end-on-compaction: once the agent has compacted its context, ask it
+    /// (once per iteration) to wrap up so the next iteration starts with a fresh one
+    pub fn check_compaction(&mut self) {
+        if !self.end_on_compaction || self.compaction_wrap_up_iteration == Some(self.current_iteration) {
+            return;
+        }
+        if lock_pty(&self.pty_state).compactions == 0 {
+            return;
+        }
+        let message = format!("{}\r", COMPACTION_WRAP_UP_MESSAGE);
+        self.write_to_pty(message.as_bytes());
+        self.compaction_wrap_up_iteration = Some(self.current_iteration);
+        self.show_toast("Context compacted - asking the agent to wrap up");
+    }
+
+    /// Queue bytes for the PTY stdin (written on the PTY writer thread)
+    /// Input is dropped rather than blocking the UI when the agent stops reading.
+    /// In --parallel mode it goes to the focused lane's agent.
+    pub fn write_to_pty(&mut self, data: &[u8]) {
+        if let Some(lane) = self.lanes.get(self.focused_lane) {
+            lane.write(data);
+        } else if let Some(ref writer) = self.pty_writer {
+            writer.send(data);
+        }
+    }
+
+    /// Type the pending prompt into the agent once it's ready for a paste
+    /// Err (ending the iteration loop) when it couldn't be typed in.
+    pub fn deliver_pending_prompt(&mut self) -> io::Result<()> {
+        let (Some(pending), Some(writer)) = (self.pending_prompt.as_ref(), self.pty_writer.as_ref()) else {
+            return Ok(());
+        };
+        if pending.try_deliver(&self.pty_state, writer, Instant::now())? {
+            self.pending_prompt = None;
+        }
+        Ok(())
+    }
+
+    /// PTY state of the agent that receives keyboard input (the focused lane in --parallel mode)
+    pub fn input_pty_state(&self) -> &Arc<Mutex<PtyState>> {
+        self.lanes.get(self.focused_lane).map_or(&self.pty_state, |lane| &lane.pty_state)
+    }
+
+    /// Move keyboard focus to the next --parallel lane
+    pub fn focus_next_lane(&mut self) {
+        if !self.lanes.is_empty() {
+            self.focused_lane = (self.focused_lane + 1) % self.lanes.len();
+        }
+    }
+
+    /// Forward pasted text to the PTY as a single write
+    pub fn paste_to_pty(&mut self, text: &str) {
+        // Only bracket the paste if the child enabled bracketed paste mode (DECSET 2004)
+        let bracketed = lock_pty(self.input_pty_state()).parser.screen().bracketed_paste();
+        let payload = build_paste_payload(text, bracketed);
+        self.write_to_pty(&payload);
+    }
+
+    /// Resize the PTY to match the given dimensions
+    pub fn resize_pty(&self, cols: u16, rows: u16) {
+        if let Some(ref master) = self.master_pty {
+            let _ = master.resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            });
+        }
+        // Also resize the VT100 parser's screen
+        lock_pty(&self.pty_state).parser.screen_mut().set_size(rows, cols);
+    }
+
+    /// Resize the PTY even when it already has this size, so an agent that missed a
+    /// resize redraws; the kernel only signals SIGWINCH on a change, so the size is
+    /// nudged by a row first
+    pub fn resync_pty(&self, cols: u16, rows: u16) {
+        if let Some(ref master) = self.master_pty {
+            let _ = master.resize(PtySize { rows: rows.saturating_add(1), cols, pixel_width: 0, pixel_height: 0 });
+        }
+        self.resize_pty(cols, rows);
+    }
+
+    /// The (cols, rows) the agent's PTY has
+    pub fn pty_size(&self) -> Option<(u16, u16)> {
+        let size = self.master_pty.as_ref()?.get_size().ok()?;
+        Some((size.cols, size.rows))
+    }
+
+    /// Whether error lines appeared since the last call (the count restarts each iteration)
+    pub fn take_new_errors(&mut self) -> bool {
+        let count = lock_pty(&self.pty_state).errors.len();
+        let is_new = count > self.errors_seen;
+        self.errors_seen = count;
+        is_new
+    }
+
+    /// Footer prompt listing what the key after u does
+    fn undo_prefix_prompt(&self) -> String {
+        let mut choices = Vec::new();
+        if let Some(ref backup) = self.pending_restore {
+            choices.push(format!(
+                "y: Restore prd.json from {}",
+                backup.file_name().map(|n| n.to_string_lossy()).unwrap_or_default()
+            ));
+        }
+        if let Some(edit) = self.prd_journal.iter().rev().find(|edit| !edit.undone) {
+            choices.push(format!("z: Undo {}", edit.label));
+        }
+        choices.push("l: Edit log".to_string());
+        choices.push("any other key: Cancel".to_string());
+        choices.join(" | ")
+    }
+
+    /// Remaining story estimates against the session's time (None when no story has one)
+    pub fn burndown(&self) -> Option<Burndown> {
+        let prd = self.prd.as_ref()?;
+        // What was left when the session started, counted with the current targets
+        let remaining_at_start = match self.prd_at_start {
+            Some(ref start) => {
+                start.user_stories.iter().filter(|s| prd.is_counted(s) && !s.passes).filter_map(|s| s.estimate).sum()
+            }
+            None => prd.remaining_estimate(),
+        };
+        Burndown::new(prd, remaining_at_start, self.session_start.elapsed(), now_millis() / 1000)
+    }
+
+    /// Capture everything the renderer needs for one frame
+    /// Also refreshes the parsed activities and keeps the story selection in range.
+    pub fn snapshot(&mut self) -> AppSnapshot {
+        // Ensure selected_story_index is valid
+        let story_count = self.prd.as_ref().map(|p| p.user_stories.len()).unwrap_or(0);
+        if self.selected_story_index >= story_count && story_count > 0 {
+            self.selected_story_index = story_count - 1;
+        }
+
+        // Activities and the Claude screen (scrolled into history when requested)
+        let (activities, links, claude_lines, test_result, compactions, error_count, error_flash, errors, output_events, reader_error) = {
+            let mut state = lock_pty(&self.pty_state);
+            state.update_activities();
+            let scrollback = if self.iteration_state == IterationState::WaitingDelay {
+                0
+            } else {
+                self.claude_scroll_offset
+            };
+            state.parser.screen_mut().set_scrollback(scrollback);
+            let lines = render_vt100_screen(state.parser.screen());
+            // Reset scrollback to 0 so stop hook detection sees current content
+            state.parser.screen_mut().set_scrollback(0);
+            let test_result = state.last_test_result.or(self.last_test_result);
+            let error_flash = state.last_error_at.is_some_and(|at| at.elapsed() < ERROR_FLASH);
+            // The matched lines are only copied while the errors view is open
+            let errors = match self.ralph_view_mode {
+                RalphViewMode::Errors => state.errors.clone(),
+                _ => Vec::new(),
+            };
+            (
+                state.get_activities(),
+                state.links.clone(),
+                Some(lines),
+                test_result,
+                state.compactions,
+                state.errors.len(),
+                error_flash,
+                errors,
+                std::mem::take(&mut state.output_events),
+                state.reader_error.take(),
+            )
+        };
+        // The Claude panel stays as it was while the tripwire modal is up
+        let claude_lines = self.tripwire_frozen.clone().or(claude_lines);
+        let footer_tip = self.footer_tips.then(|| {
+            tips::footer_tip(&TipContext {
+                mode: self.mode,
+                view: self.ralph_view_mode,
+                keymap: &self.keymap,
+                used: &self.used_keys,
+                has_stories: self.prd.as_ref().is_some_and(|prd| !prd.user_stories.is_empty()),
+                has_links: !links.is_empty(),
+                has_errors: error_count > 0,
+                session_elapsed: self.session_start.elapsed(),
+            })
+        });
+        for (timestamp, event) in output_events {
+            self.emit_event_at(timestamp, event);
+        }
+        if let Some(message) = reader_error {
+            self.report_reader_error(message);
+        }
+
+        // Files backing the detail views (only while the view is open; progress.txt is cached)
+        let progress = match self.ralph_view_mode {
+            RalphViewMode::StoryDetails | RalphViewMode::Progress | RalphViewMode::Blockers => self.progress.clone(),
+            _ => None,
+        };
+        let requirements = match self.ralph_view_mode {
+            RalphViewMode::Requirements => std::fs::read_to_string(self.task_dir.join("prd.md")).ok(),
+            _ => None,
+        };
+
+        let delay_remaining_secs = self
+            .delay_start
+            .map(|start| ITERATION_DELAY_SECS.saturating_sub(start.elapsed().as_secs()))
+            .unwrap_or(0);
+
+        // Criteria history of the active story, for the sparkline next to its gauge
+        let no_history = VecDeque::new();
+        let active_history = self
+            .prd
+            .as_ref()
+            .and_then(|p| p.current_story())
+            .and_then(|story| self.criteria_history.get(&story.id))
+            .unwrap_or(&no_history);
+
+        AppSnapshot {
+            mode: self.mode,
+            prd: self.prd.clone(),
+            story_index: Arc::clone(&self.story_index),
+            current_iteration: self.current_iteration,
+            max_iterations: self.max_iterations,
+            iterations_reduced: self.iterations_reduced_from.is_some(),
+            iteration_state: self.iteration_state,
+            session_elapsed: self.session_start.elapsed(),
+            burndown: self.burndown(),
+            iteration_elapsed: self.iteration_start.elapsed(),
+            max_runtime: self.max_runtime,
+            delay_remaining_secs,
+            animation_tick: self.animation_tick,
+            session_id: self.session_id.clone(),
+            story_scroll_offset: self.story_scroll_offset,
+            selected_story_index: self.selected_story_index,
+            selected_criterion: self.selected_criterion,
+            ralph_view_mode: self.ralph_view_mode,
+            ralph_size: self.ralph_size,
+            ralph_scroll_offset: self.ralph_scroll_offset,
+            claude_scroll_offset: self.claude_scroll_offset,
+            story_list_layout: self.story_list_layout,
+            completed_group_expanded: self.completed_group_expanded,
+            completed_group_selected: self.completed_group_selected,
+            follow: self.follow,
+            keymap: Arc::clone(&self.keymap),
+            blockers: self.blockers.clone(),
+            attention_selected: self.attention_selected,
+            progress_strip: self.progress_strip,
+            sidebar_hidden: self.sidebar_hidden,
+            progress_tail: if self.progress_strip { self.progress_tail() } else { Vec::new() },
+            progress_lines: self.progress.as_ref().map(|_| self.progress_lines),
+            progress_iteration_lines: self.progress_lines_at_iteration_start.map(|start| self.progress_lines.saturating_sub(start)),
+            rotate_threshold: self.rotate_threshold,
+            progress_highlighted: match self.progress_changed_at {
+                Some(changed) if changed.elapsed() < PROGRESS_HIGHLIGHT => self.progress_new_lines,
+                _ => 0,
+            },
+            session_state: self.session_state.clone(),
+            stuck_threshold: self.stuck_threshold,
+            criteria_history: active_history.iter().map(|&(_, passed)| passed).collect(),
+            criteria_flat: active_history.back().is_some_and(|&(_, passed)| {
+                let since = active_history.iter().rev().take_while(|&&(_, p)| p == passed).last();
+                since.is_some_and(|&(at, _)| at.elapsed() >= self.flat_after)
+            }),
+            expanded_stories: self.expanded_stories.clone(),
+            merge_target: self.drift.as_ref().map(|d| d.target.clone()),
+            drift: self.drift.as_ref().and_then(|d| d.latest()),
+            not_git_repo: !self.git_available,
+            behind_warn: self.behind_warn,
+            sync_requested: self.sync_requested,
+            sync_running: self.sync_job.as_ref().map(|job| (job.target.clone(), job.strategy)),
+            sync_error: self.sync_error.clone(),
+            disk_warning: self.disk_warning(),
+            prd_warning: self.prd_warning(),
+            disk_error: self.disk_error.clone(),
+            scope_warning: self.scope_warning(),
+            scope_hold: self.scope_hold.clone(),
+            tripwire_alert: self.tripwire_alerts.front().cloned(),
+            tripwire_queued: self.tripwire_alerts.len().saturating_sub(1),
+            tripwire_hold: self.tripwire_hold.clone(),
+            prd_churn: self.prd_churn.iter().rev().take(PRD_CHURN_SHOWN).cloned().collect(),
+            churn_hold: self.churn_hold.clone(),
+            queue: self.queue.clone(),
+            // The u prefix keeps its prompt up until the next key
+            toast: if self.batch_quit_prompt {
+                Some(BATCH_QUIT_PROMPT.to_string())
+            } else if self.undo_prefix {
+                Some(self.undo_prefix_prompt())
+            } else if self.rollback_armed {
+                Some(self.rollback_prompt())
+            } else {
+                self.pending_chord_hint()
+                    .or_else(|| self.active_toast().map(str::to_string))
+                    .or_else(|| self.iteration_ending.as_ref().map(|_| ITERATION_ENDING_NOTE.to_string()))
+            },
+            footer_tip: footer_tip.flatten(),
+            agent_activity: self.agent_activity,
+            input_stalled: self.pty_writer.as_ref().is_some_and(|w| w.is_stalled()),
+            reader_error: self.reader_error.is_some(),
+            lanes: self
+                .lanes
+                .iter()
+                .enumerate()
+                .map(|(index, lane)| lane.snapshot(index, index == self.focused_lane))
+                .collect(),
+            env_names: self.injected_env_names().into_iter().map(str::to_string).collect(),
+            prompt_source: self.prompt_source.clone(),
+            activities,
+            claude_lines,
+            test_result,
+            compactions,
+            error_count,
+            model: self.model.clone(),
+            agent_version: self.agent_version.clone(),
+            pty_size: self.show_pty_size.then(|| self.pty_size()).flatten(),
+            unverified: self.unverified.clone(),
+            error_flash,
+            errors,
+            story_menu: self.story_menu,
+            iteration_markers: match self.ralph_view_mode {
+                RalphViewMode::Iterations => self.iteration_markers.clone(),
+                _ => Vec::new(),
+            },
+            iteration_ends: match self.ralph_view_mode {
+                RalphViewMode::Iterations => self.iteration_ends.clone(),
+                _ => BTreeMap::new(),
+            },
+            last_iteration_end: self.iteration_ends.last_key_value().map(|(&iteration, &source)| (iteration, source)),
+            selected_marker: self.selected_marker,
+            selected_activity: self.selected_activity,
+            links,
+            selected_link: self.selected_link,
+            file_preview: match self.ralph_view_mode {
+                RalphViewMode::FilePreview => self.file_preview.clone(),
+                _ => None,
+            },
+            iteration_changes: match self.ralph_view_mode {
+                RalphViewMode::Changes => self.iteration_changes.clone(),
+                _ => None,
+            },
+            selected_change: self.selected_change,
+            change_diff: match self.ralph_view_mode {
+                RalphViewMode::ChangeDiff => self.change_diff.clone(),
+                _ => None,
+            },
+            prd_journal: match self.ralph_view_mode {
+                RalphViewMode::Journal => self.prd_journal.clone(),
+                _ => Vec::new(),
+            },
+            prd_diff: match self.ralph_view_mode {
+                RalphViewMode::Diff => self.session_diff(),
+                _ => Vec::new(),
+            },
+            health: match self.ralph_view_mode {
+                RalphViewMode::Health => self.health.report(self.pty_writer.as_ref().map_or(0, PtyWriter::queued)),
+                _ => Vec::new(),
+            },
+            progress,
+            requirements,
+        }
+    }
+}
+
+/// Seconds to wait between iterations
+pub const ITERATION_DELAY_SECS: u64 = 2;
+
+/// Milliseconds an ended iteration stays on screen before the loop moves on (--linger)
+pub const DEFAULT_LINGER_MS: u64 = 1500;
+
+/// Seconds without output, once the iteration's story passes, before the iteration is
+/// ended without a stop hook signal (--prd-idle)
+pub const DEFAULT_PRD_IDLE_SECS: u64 = 120;
+
+/// Everything the renderer needs for one frame, captured from App
+/// Render functions are pure functions of a snapshot and the frame area.
+#[derive(Debug, Clone, Default)]
+pub struct AppSnapshot {
+    pub mode: Mode,
+    pub prd: Option<Prd>,
+    // App's index of prd (snapshots built without one index prd on demand)
+    pub story_index: Arc<StoryIndex>,
+    pub current_iteration: u32,
+    pub max_iterations: u32,
+    // The budget was cut to the running iteration ("3/3 (reduced)")
+    pub iterations_reduced: bool,
+    pub iteration_state: IterationState,
+    pub session_elapsed: Duration,
+    // Remaining story estimates and the projected finish (None when no story has an estimate)
+    pub burndown: Option<Burndown>,
+    pub iteration_elapsed: Duration,
+    // --max-runtime budget (total limit), shown with the time left
+    pub max_runtime: Option<Duration>,
+    // Seconds left before the next iteration starts (delay screen)
+    pub delay_remaining_secs: u64,
+    pub animation_tick: u64,
+    pub session_id: String,
+    pub story_scroll_offset: usize,
+    pub selected_story_index: usize,
+    pub selected_criterion: usize,
+    pub ralph_view_mode: RalphViewMode,
+    pub ralph_size: RalphTerminalSize,
+    pub ralph_scroll_offset: usize,
+    pub claude_scroll_offset: usize,
+    pub story_list_layout: StoryListLayout,
+    pub completed_group_expanded: bool,
+    pub completed_group_selected: bool,
+    pub follow: FollowMode,
+    // Key bindings, for hints and the footer
+    pub keymap: Arc<KeyMap>,
+    // Open blockers from progress.txt, and whether their section is selected
+    pub blockers: Vec<Blocker>,
+    pub attention_selected: bool,
+    // progress.txt tail strip: shown, its last lines, and how many of them are new
+    pub progress_strip: bool,
+    pub progress_tail: Vec<String>,
+    pub progress_highlighted: usize,
+    // progress.txt's line count (None without the file), lines added this iteration, and
+    // the rotation threshold they're shown against
+    pub progress_lines: Option<usize>,
+    pub progress_iteration_lines: Option<usize>,
+    pub rotate_threshold: u32,
+    // Ralph status panel hidden (essentials go in the footer)
+    pub sidebar_hidden: bool,
+    pub session_state: SessionState,
+    pub stuck_threshold: u32,
+    // Active story's criteria passed at recent PRD loads (oldest first), and whether
+    // the count has stayed the same for --flat-after
+    pub criteria_history: Vec<usize>,
+    pub criteria_flat: bool,
+    pub expanded_stories: HashSet<String>,
+    // Merge target being tracked, and the latest ahead/behind measurement
+    pub merge_target: Option<String>,
+    pub drift: Option<BranchDrift>,
+    // Running outside a git repository (branch, drift and rollback show a hint instead)
+    pub not_git_repo: bool,
+    pub behind_warn: u32,
+    pub sync_requested: bool,
+    // Merge/rebase in progress: (target, strategy)
+    pub sync_running: Option<(String, SyncStrategy)>,
+    pub sync_error: Option<String>,
+    // Low disk space banner, and the pause screen below the hard floor
+    pub disk_warning: Option<String>,
+    pub disk_error: Option<String>,
+    // Red header banner while prd.json is missing or doesn't parse (the stories shown are stale)
+    pub prd_warning: Option<String>,
+    // Red header banner once the agent wrote outside the repository and allowedPaths
+    pub scope_warning: Option<String>,
+    // Pause screen at an iteration boundary after such a write (pauseOnOutOfScope)
+    pub scope_hold: Option<String>,
+    // Dangerous command waiting for an answer in the tripwire modal, and how many more are queued
+    pub tripwire_alert: Option<TripwireAlert>,
+    pub tripwire_queued: usize,
+    // Pause screen at an iteration boundary after "pause loop" in the tripwire modal
+    pub tripwire_hold: Option<String>,
+    // Latest stories the agent added, removed or rewrote in prd.json, newest first
+    pub prd_churn: Vec<PrdChurn>,
+    // Pause screen at an iteration boundary after suspicious churn (pause_on_prd_churn)
+    pub churn_hold: Option<String>,
+    // Batch run: every task and its status (empty for a single task)
+    pub queue: Vec<QueueEntry>,
+    // Active footer toast
+    pub toast: Option<String>,
+    // Tip for an unused feature, in the footer's free space
+    pub footer_tip: Option<String>,
+    pub agent_activity: AgentActivity,
+    // Keystrokes are being dropped because the agent isn't reading its input
+    pub input_stalled: bool,
+    // The output reader panicked this iteration: the screen may be missing output
+    pub reader_error: bool,
+    // --parallel lanes drawn as stacked Claude panes (empty in single-agent mode)
+    pub lanes: Vec<LaneSnapshot>,
+    // Names (never values) of the variables injected into the agent
+    pub env_names: Vec<String>,
+    // prompt.md the agent's prompt came from (None = the embedded default)
+    pub prompt_source: Option<String>,
+    // Recent activities, newest first
+    pub activities: Vec<Activity>,
+    // Latest test-run summary (this iteration's, else the last finished iteration's)
+    pub test_result: Option<TestResult>,
+    // Context compactions seen in the agent's output this iteration
+    pub compactions: u32,
+    // Error lines matched this iteration, whether one just arrived (border flash),
+    // and the matches themselves (errors view only)
+    pub error_count: usize,
+    pub error_flash: bool,
+    pub errors: Vec<ErrorMatch>,
+    // Claude model the agent runs with (None = the claude CLI's default)
+    pub model: Option<String>,
+    // What `claude --version` reported at startup (None for other agents)
+    pub agent_version: Option<AgentVersion>,
+    // The agent's PTY (cols, rows), when show_pty_size is on
+    pub pty_size: Option<(u16, u16)>,
+    // Passed criteria without a matching `Verified:` note, by story (check_verified_notes)
+    pub unverified: HashMap<String, BTreeSet<usize>>,
+    // prd.json edits made from the TUI this session (edit log view only)
+    pub prd_journal: Vec<PrdEdit>,
+    // Each story at session start vs now (diff view only)
+    pub prd_diff: Vec<StoryDiff>,
+    // Health view lines (only while it is open)
+    pub health: Vec<String>,
+    // Highlighted entry of the quick actions menu while it is open
+    pub story_menu: Option<usize>,
+    // Iteration start markers and the one picked (iterations view only)
+    pub iteration_markers: Vec<IterationMarker>,
+    pub selected_marker: usize,
+    // How each iteration ended (iterations view only), and the latest one (delay screen)
+    pub iteration_ends: BTreeMap<u32, IterationEndSource>,
+    pub last_iteration_end: Option<(u32, IterationEndSource)>,
+    // Activity picked in the activity view, and the file previewed from it (preview view only)
+    pub selected_activity: usize,
+    pub file_preview: Option<Arc<FilePreview>>,
+    // Links seen in the agent's output, newest first (links view, and the hyperlinks drawn
+    // over the frame), and the one picked in the view
+    pub links: Vec<String>,
+    pub selected_link: usize,
+    // Changes view: files changed this iteration (None until loaded or outside a repository),
+    // the picked one, and the diff opened from it
+    pub iteration_changes: Option<Arc<IterationChanges>>,
+    pub selected_change: usize,
+    pub change_diff: Option<Arc<FileDiff>>,
+    // Rendered Claude terminal (None if the PTY state is unavailable)
+    pub claude_lines: Option<Vec<Line<'static>>>,
+    // progress.txt contents (story details and progress views only)
+    pub progress: Option<String>,
+    // prd.md contents (requirements view only)
+    pub requirements: Option<String>,
+}
+
+impl AppSnapshot {
+    /// Index of the PRD's stories (App's, unless it was built for a different PRD)
+    pub fn story_index(&self) -> Arc<StoryIndex> {
+        match self.prd {
+            Some(ref prd) if !self.story_index.covers(prd) => Arc::new(StoryIndex::new(prd)),
+            _ => Arc::clone(&self.story_index),
+        }
+    }
+
+    /// Stalled iterations for a story, counting the running iteration while the agent sits idle
+    pub fn stalled_attempts(&self, story_id: &str) -> u32 {
+        let index = self.story_index();
+        let is_active = self
+            .prd
+            .as_ref()
+            .and_then(|prd| index.story(prd, index.current?))
+            .is_some_and(|s| s.id == story_id);
+        let attempts = self.session_state.stalled_attempts(story_id);
+        if is_active && self.agent_activity == AgentActivity::Idle {
+            attempts + 1
+        } else {
+            attempts
+        }
+    }
+
+    /// Rows of the story list for the current layout
+    pub fn story_rows(&self) -> Vec<StoryListRow> {
+        match self.prd {
+            Some(ref prd) => {
+                let index = self.story_index();
+                build_story_rows(
+                    &index.stories(prd),
+                    index.current,
+                    self.story_list_layout,
+                    self.completed_group_expanded,
+                )
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Position of the selected row within `rows` (see App::selected_row_position)
+    pub fn selected_row_position(&self, rows: &[StoryListRow]) -> usize {
+        selected_row_position(rows, self.selected_story_index, self.completed_group_selected)
+    }
+}
+
+/// Story list layout in the Ralph status panel (g toggles it, remembered per task)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoryListLayout {
+    #[default]
+    Flat,    // All stories in priority order
+    Grouped, // Active story pinned at top, pending below, completed collapsed into a summary row
+}
+
+/// A single row in the story list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoryListRow {
+    Story(usize),          // Index into the priority-sorted story list
+    CompletedGroup(usize), // Collapsible summary row for N completed stories
+}
+
+/// Build the rows of the story list for the given layout
+/// `stories` must be sorted by priority; row indices (and `current`, the active
+/// story's position) refer into it
+pub fn build_story_rows(
+    stories: &[&UserStory],
+    current: Option<usize>,
+    layout: StoryListLayout,
+    completed_expanded: bool,
+) -> Vec<StoryListRow> {
+    match layout {
+        StoryListLayout::Flat => (0..stories.len()).map(StoryListRow::Story).collect(),
+        StoryListLayout::Grouped => {
+            let mut rows = Vec::with_capacity(stories.len() + 1);
+            let active = current;
+
+            // Active story pinned at top
+            if let Some(idx) = active {
+                rows.push(StoryListRow::Story(idx));
+            }
+
+            // Pending stories below
+            for (idx, story) in stories.iter().enumerate() {
+                if !story.passes && Some(idx) != active {
+                    rows.push(StoryListRow::Story(idx));
+                }
+            }
+
+            // Completed stories collapsed into a single summary row (at the bottom so
+            // expanding them doesn't push pending stories off screen)
+            let completed: Vec<usize> = (0..stories.len()).filter(|&idx| stories[idx].passes).collect();
+            if !completed.is_empty() {
+                rows.push(StoryListRow::CompletedGroup(completed.len()));
+                if completed_expanded {
+                    rows.extend(completed.into_iter().map(StoryListRow::Story));
+                }
+            }
+
+            rows
+        }
+    }
+}
+
+/// Position of the selected row (a story or the completed-group summary) within `rows`
+pub fn selected_row_position(rows: &[StoryListRow], selected_story_index: usize, group_selected: bool) -> usize {
+    let group_position = rows.iter().position(|r| matches!(r, StoryListRow::CompletedGroup(_)));
+    if group_selected
+        && let Some(pos) = group_position
+    {
+        return pos;
+    }
+    rows.iter()
+        .position(|r| *r == StoryListRow::Story(selected_story_index))
+        .or(group_position)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::git::SyncStrategy;
+    use crate::testutil::TempDir;
+
+    /// An App and its temp task directory, removed when the test is done with both
+    pub(crate) struct TestApp {
+        app: App,
+        _task_dir: TempDir,
+    }
+
+    impl std::ops::Deref for TestApp {
+        type Target = App;
+
+        fn deref(&self) -> &App {
+            &self.app
+        }
+    }
+
+    impl std::ops::DerefMut for TestApp {
+        fn deref_mut(&mut self) -> &mut App {
+            &mut self.app
+        }
+    }
+
+    /// App for a two-story PRD in a fresh temp task directory
+    pub(crate) fn test_app(name: &str) -> TestApp {
+        let task_dir = TempDir::new(name);
+        std::fs::write(
+            task_dir.join("prd.json"),
+            r#"{"project": "demo", "taskDir": "tasks/demo", "type": "feature", "description": "Demo",
+                "userStories": [
+                    {"id": "US-001", "title": "First", "description": "", "acceptanceCriteria": ["a"], "priority": 1, "passes": false, "notes": ""},
+                    {"id": "US-002", "title": "Second", "description": "", "acceptanceCriteria": ["b"], "priority": 2, "passes": false, "notes": ""}
+                ]}"#,
+        )
+        .unwrap();
+        TestApp { app: app_in(&task_dir), _task_dir: task_dir }
+    }
+
+    /// App for the task in `task_dir`, as a new ralph-tui run would start it
+    pub(crate) fn app_in(task_dir: &Path) -> App {
+        App::new(24, 80, CliConfig {
+            task_dir: task_dir.to_path_buf(),
+            prd_path: task_dir.join("prd.json"),
+            max_iterations: 10,
+            max_iterations_source: crate::ValueSource::Default,
+            rotate_threshold: 0,
+            rotate_threshold_source: crate::ValueSource::Default,
+            skip_prompts: true,
+            events_socket: None,
+            metrics_addr: None,
+            once: false,
+            stuck_threshold: 3,
+            flat_after: DEFAULT_FLAT_AFTER,
+            min_free_mb: crate::disk::DEFAULT_MIN_FREE_MB,
+            behind_warn: 20,
+            sync_strategy: SyncStrategy::Merge,
+            idle_after: 5,
+            prompt_pattern: Regex::new(crate::pty::DEFAULT_PROMPT_PATTERN).unwrap(),
+            nudge_after: 0,
+            prd_idle_after: 0,
+            linger_ms: 0,
+            nudge_message: DEFAULT_NUDGE_MESSAGE.to_string(),
+            end_on_compaction: false,
+            squash_on_complete: false,
+            agent_args: Vec::new(),
+            model: None,
+            agent: AgentProfile::claude(),
+            agent_version: None,
+            keymap: KeyMap::default(),
+            targeted_stories: None,
+            kitty_keys: false,
+            key_guard: true,
+            terminal_title: true,
+            progress_strip: false,
+            no_sidebar: false,
+            parallel: None,
+            max_runtime: None,
+            error_patterns: crate::pty::DEFAULT_ERROR_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            error_bell: false,
+            dangerous_commands: Vec::new(),
+            pause_on_prd_churn: false,
+            show_pty_size: false,
+            verify_criteria: false,
+            verify_timeout_secs: crate::verify::DEFAULT_VERIFY_TIMEOUT_SECS,
+            verified_match: None,
+            footer_tips: false,
+            prompt_via_stdin: false,
+            env: BTreeMap::new(),
+            on_iteration_end: None,
+            on_complete: None,
+        })
+    }
+
+    #[test]
+    fn iterations_are_counted_per_active_story() {
+        let mut app = test_app("story-iterations");
+        for _ in 0..2 {
+            app.iteration_story = Some(("US-001".to_string(), 0));
+            app.iteration_start = Instant::now() - Duration::from_secs(90);
+            app.record_story_attempt();
+        }
+        assert_eq!(app.session_state.story_iterations.get("US-001"), Some(&2));
+        assert_eq!(app.session_state.story_seconds.get("US-001"), Some(&180));
+        assert_eq!(app.session_state.stalled_attempts("US-001"), 2);
+        // Persisted so a resumed session keeps counting
+        assert_eq!(SessionState::load(&app.task_dir).story_iterations.get("US-001"), Some(&2));
+    }
+
+    #[test]
+    fn idle_agent_counts_running_iteration_as_stalled() {
+        let mut app = test_app("idle");
+        app.session_state.story_attempts.insert("US-001".to_string(), 1);
+        assert_eq!(app.snapshot().stalled_attempts("US-001"), 1);
+
+        app.agent_activity = AgentActivity::Idle;
+        let snapshot = app.snapshot();
+        assert_eq!(snapshot.stalled_attempts("US-001"), 2);
+        // Only the active story is affected
+        assert_eq!(snapshot.stalled_attempts("US-002"), 0);
+    }
+
+    #[test]
+    fn passed_story_ends_the_iteration_only_once_the_agent_is_quiet() {
+        let mut app = test_app("prd-idle");
+        app.prd_idle_after = Some(Duration::from_secs(60));
+        app.begin_story_attempt();
+        let quiet_since = |app: &App, secs: u64| {
+            lock_pty(&app.pty_state).last_output_at = Instant::now().checked_sub(Duration::from_secs(secs));
+        };
+        quiet_since(&app, 300);
+        // The story hasn't passed yet
+        assert!(!app.story_done_while_quiet());
+
+        let mut prd = app.prd.clone().unwrap();
+        prd.user_stories[0].acceptance_criteria[0].passes = true;
+        app.set_prd(prd);
+        assert!(app.story_done_while_quiet());
+
+        // Output within the quiet period (or none at all yet) keeps the iteration going
+        quiet_since(&app, 10);
+        assert!(!app.story_done_while_quiet());
+        lock_pty(&app.pty_state).last_output_at = None;
+        assert!(!app.story_done_while_quiet());
+
+        // Off without --prd-idle
+        quiet_since(&app, 300);
+        app.prd_idle_after = None;
+        assert!(!app.story_done_while_quiet());
+        assert_eq!(IterationEndSource::PrdProgress.label(), "prd-progress+idle");
+    }
+
+    #[test]
+    fn criteria_history_tracks_prd_loads_and_flags_flat_progress() {
+        let mut app = test_app("criteria-history");
+        // US-001 gets a second criterion so its count can move
+        let mut prd = app.prd.clone().unwrap();
+        let mut second = prd.user_stories[0].acceptance_criteria[0].clone();
+        second.description = "b".to_string();
+        prd.user_stories[0].acceptance_criteria.push(second);
+        app.set_prd(prd.clone());
+        prd.user_stories[0].acceptance_criteria[0].passes = true;
+        app.set_prd(prd.clone());
+
+        let snapshot = app.snapshot();
+        assert_eq!(snapshot.criteria_history, [0, 0, 1]);
+        assert!(!snapshot.criteria_flat);
+        app.flat_after = Duration::ZERO;
+        assert!(app.snapshot().criteria_flat);
+
+        for _ in 0..CRITERIA_HISTORY_LEN {
+            app.set_prd(prd.clone());
+        }
+        assert_eq!(app.criteria_history["US-001"].len(), CRITERIA_HISTORY_LEN);
+        // Completed stories are pruned
+        prd.user_stories[0].passes = true;
+        app.set_prd(prd);
+        assert!(!app.criteria_history.contains_key("US-001"));
+        assert_eq!(app.criteria_history["US-002"].len(), CRITERIA_HISTORY_LEN);
+    }
+
+    #[test]
+    fn records_iteration_when_story_flips_to_passing() {
+        let mut app = test_app("completion");
+        app.current_iteration = 4;
+        let mut prd = app.prd.clone().unwrap();
+        prd.user_stories[1].passes = true;
+        app.set_prd(prd);
+
+        let completion = app.session_state.completion("US-002").unwrap();
+        assert_eq!(completion.iteration, 4);
+        assert!(completion.completed_at > 0);
+        assert_eq!(app.session_state.completion("US-001"), None);
+        // Persisted so a resumed session keeps it
+        assert_eq!(SessionState::load(&app.task_dir).completion("US-002"), Some(completion));
+    }
+
+    #[test]
+    fn targeted_stories_limit_current_story_and_completion() {
+        let mut app = test_app("targets");
+        app.selected_story_index = 1;
+        app.toggle_selected_story_target();
+        let prd = app.prd.as_ref().unwrap();
+        assert_eq!(prd.current_story().map(|s| s.id.as_str()), Some("US-002"));
+        assert_eq!((prd.completed_count(), prd.story_total()), (0, 1));
+
+        // The run is complete once the targeted subset passes, and reloads keep the selection
+        let mut reloaded = Prd::load(&app.prd_path).unwrap();
+        reloaded.user_stories[1].passes = true;
+        app.set_prd(reloaded);
+        assert!(app.prd.as_ref().unwrap().all_stories_pass());
+        assert_eq!(SessionState::load(&app.task_dir).targeted_stories, BTreeSet::from(["US-002".to_string()]));
+
+        app.clear_story_targets();
+        let prd = app.prd.as_ref().unwrap();
+        assert!(!prd.all_stories_pass());
+        assert_eq!(prd.current_story().map(|s| s.id.as_str()), Some("US-001"));
+        assert!(SessionState::load(&app.task_dir).targeted_stories.is_empty());
+    }
+
+    #[test]
+    fn story_list_layout_and_completed_group_survive_a_restart() {
+        let mut app = test_app("layout");
+        app.toggle_story_list_layout();
+        app.toggle_completed_group();
+        let state = SessionState::load(&app.task_dir);
+        assert_eq!((state.story_list_layout, state.completed_group_expanded), (StoryListLayout::Grouped, true));
+
+        let mut restarted = app_in(&app.task_dir);
+        assert_eq!(restarted.story_list_layout, StoryListLayout::Grouped);
+        assert!(restarted.completed_group_expanded);
+        restarted.toggle_story_list_layout();
+        assert_eq!(SessionState::load(&app.task_dir).story_list_layout, StoryListLayout::Flat);
+        // Session files written before the layout was remembered still load
+        std::fs::write(app.task_dir.join(SESSION_STATE_FILE), r#"{"sidebarHidden": true}"#).unwrap();
+        let state = SessionState::load(&app.task_dir);
+        assert!(state.sidebar_hidden && !state.completed_group_expanded);
+        assert_eq!(state.story_list_layout, StoryListLayout::Flat);
+    }
+
+    #[test]
+    fn open_blockers_add_a_selectable_attention_section() {
+        let mut app = test_app("blockers");
+        std::fs::write(app.task_dir.join("progress.txt"), "## Iteration 1\nBLOCKER: need API key\nQUESTION: which port?\n").unwrap();
+        app.reload_progress_if_needed();
+        assert_eq!(app.blockers.len(), 2);
+
+        // Moving up from the first story selects the section; moving down leaves it
+        app.move_story_selection(false);
+        assert!(app.attention_selected);
+        assert!(app.snapshot().attention_selected);
+        app.move_story_selection(true);
+        assert!(!app.attention_selected);
+        assert_eq!(app.selected_story_index, 0);
+    }
+
+    #[test]
+    fn progress_strip_tails_and_highlights_appended_lines() {
+        let mut app = test_app("strip");
+        let progress_path = app.task_dir.join("progress.txt");
+        std::fs::write(&progress_path, "one\ntwo\nthree\nfour\nfive\n\n").unwrap();
+        app.reload_progress_if_needed();
+        app.toggle_progress_strip();
+        let snapshot = app.snapshot();
+        assert_eq!(snapshot.progress_tail, vec!["two", "three", "four", "five"]);
+        // Content present at startup isn't new
+        assert_eq!(snapshot.progress_highlighted, 0);
+
+        std::fs::write(&progress_path, "one\ntwo\nthree\nfour\nfive\nsix\n").unwrap();
+        *app.progress_needs_reload.lock().unwrap() = true;
+        app.reload_progress_if_needed();
+        let snapshot = app.snapshot();
+        assert_eq!(snapshot.progress_tail.last().map(String::as_str), Some("six"));
+        assert_eq!(snapshot.progress_highlighted, 1);
+
+        app.toggle_progress_strip();
+        assert!(app.snapshot().progress_tail.is_empty());
+    }
+
+    #[test]
+    fn progress_counts_lines_per_iteration_and_opens_at_the_latest_entries() {
+        let mut app = test_app("progress-file");
+        let progress_path = app.task_dir.join("progress.txt");
+        let entries: String = (1..=29).map(|n| format!("- entry {}\n", n)).collect();
+        std::fs::write(&progress_path, format!("## US-001\n{}", entries)).unwrap();
+        app.mark_progress_baseline();
+        assert_eq!(app.snapshot().progress_lines, Some(30));
+        assert_eq!(app.snapshot().progress_iteration_lines, Some(0));
+
+        // Trailing blank lines don't count, as in the startup rotation check
+        std::fs::write(&progress_path, format!("## US-001\n{}- entry 30\n- entry 31\n\n", entries)).unwrap();
+        *app.progress_needs_reload.lock().unwrap() = true;
+        app.reload_progress_if_needed();
+        assert_eq!(app.snapshot().progress_iteration_lines, Some(2));
+
+        // Scrolled so the story's last entry sits at the bottom of the Ralph terminal
+        app.toggle_progress();
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Progress);
+        assert_eq!(app.ralph_scroll_offset, 31 + 3 - app.ralph_size.lines() as usize);
+        assert!(app.snapshot().progress.is_some_and(|p| p.contains("entry 31")));
+        app.toggle_progress();
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Normal);
+        assert_eq!(app.ralph_scroll_offset, 0);
+    }
+
+    #[test]
+    fn prd_env_overrides_ralph_toml_env_and_ralph_vars_override_both() {
+        let mut app = test_app("env");
+        app.config_env = BTreeMap::from([
+            ("API_URL".to_string(), "https://prod.example.com".to_string()),
+            ("HTTP_PROXY".to_string(), "http://proxy:3128".to_string()),
+            ("RALPH_ITERATION".to_string(), "99".to_string()),
+        ]);
+        app.prd.as_mut().unwrap().env.insert("API_URL".to_string(), "https://staging.example.com".to_string());
+        let env = app.iteration_env();
+        let value = |name: &str| env.iter().filter(|(n, _)| n == name).map(|(_, v)| v.as_str()).collect::<Vec<_>>();
+        assert_eq!(value("API_URL"), ["https://staging.example.com"]);
+        assert_eq!(value("HTTP_PROXY"), ["http://proxy:3128"]);
+        assert_eq!(value("RALPH_ITERATION"), ["1"]);
+        assert_eq!(app.injected_env_names()[..3], ["API_URL", "HTTP_PROXY", "RALPH_ITERATION"]);
+    }
+
+    #[test]
+    fn passed_criteria_are_cross_checked_against_verified_notes() {
+        let mut app = test_app("crosscheck");
+        let mut prd = app.prd.clone().unwrap();
+        prd.user_stories[0].acceptance_criteria =
+            serde_json::from_str(r#"[{"description": "Login form renders", "passes": true}]"#).unwrap();
+        app.set_prd(prd.clone());
+        // Off unless check_verified_notes is set
+        assert!(app.snapshot().unverified.is_empty());
+
+        app.verified_match = Some(0.5);
+        app.set_prd(prd);
+        assert_eq!(app.snapshot().unverified.get("US-001"), Some(&BTreeSet::from([0])));
+
+        std::fs::write(app.task_dir.join("progress.txt"), "## 09:12 - US-001\n- Verified: login form renders (screenshot)\n").unwrap();
+        *app.progress_needs_reload.lock().unwrap() = true;
+        app.reload_progress_if_needed();
+        assert!(app.snapshot().unverified.is_empty());
+    }
+
+    #[test]
+    fn criterion_focus_persists_until_the_criterion_passes() {
+        let mut app = test_app("focus");
+        let mut prd = app.prd.clone().unwrap();
+        prd.user_stories[1].acceptance_criteria = serde_json::from_str(r#"["b", "c"]"#).unwrap();
+        app.set_prd(prd.clone());
+        app.selected_story_index = 1;
+        app.move_criterion_selection(true);
+        app.toggle_criterion_focus();
+        let focus = CriterionFocus { story_id: "US-002".to_string(), criterion: 1 };
+        assert_eq!(app.session_state.focused_criterion.as_ref(), Some(&focus));
+        assert_eq!(SessionState::load(&app.task_dir).focused_criterion, Some(focus));
+
+        // Unrelated reloads keep it; the criterion passing clears it
+        app.set_prd(prd.clone());
+        assert!(app.session_state.focused_criterion.is_some());
+        prd.user_stories[1].acceptance_criteria[1].passes = true;
+        app.set_prd(prd);
+        assert!(app.session_state.focused_criterion.is_none());
+        assert!(SessionState::load(&app.task_dir).focused_criterion.is_none());
+    }
+
+    #[test]
+    fn compaction_asks_for_wrap_up_once_per_iteration() {
+        let mut app = test_app("compaction");
+        lock_pty(&app.pty_state).compactions = 2;
+        app.check_compaction();
+        assert!(app.compaction_wrap_up_iteration.is_none());
+        assert_eq!(app.snapshot().compactions, 2);
+
+        app.end_on_compaction = true;
+        app.check_compaction();
+        assert_eq!(app.compaction_wrap_up_iteration, Some(app.current_iteration));
+        assert!(app.snapshot().toast.is_some_and(|t| t.contains("wrap up")));
+        app.toast = None;
+        app.check_compaction();
+        assert!(app.toast.is_none());
+
+        // The count goes to the iteration log next to the test results
+        let output = app.iteration_output();
+        append_iteration_log(&app.task_dir, 1, IterationEndSource::ControlFile, IterationState::NeedsRestart, Duration::ZERO, output, None, None);
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.trim_end().ends_with("duration_secs=0 compactions=2"));
+        let version = AgentVersion::new("2.0.14 (Claude Code)");
+        append_iteration_log(&app.task_dir, 2, IterationEndSource::ControlFile, IterationState::Completed, Duration::ZERO, output, Some("opus"), Some(&version));
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.trim_end().ends_with("compactions=2 model=opus agent_version=2.0.14"));
+    }
+
+    #[test]
+    fn runtime_limit_asks_for_wrap_up_then_stops() {
+        let mut app = test_app("runtime");
+        assert!(!app.check_runtime_limit());
+
+        app.max_runtime = Some(Duration::from_secs(3600));
+        app.session_start = Instant::now() - Duration::from_secs(3599);
+        assert!(!app.runtime_exhausted());
+        assert!(!app.check_runtime_limit());
+        assert!(app.wrap_up_requested_at.is_none());
+
+        app.session_start = Instant::now() - Duration::from_secs(3600);
+        // First the agent is asked to wrap up...
+        assert!(!app.check_runtime_limit());
+        assert!(app.wrap_up_requested_at.is_some());
+        assert!(app.snapshot().toast.is_some_and(|t| t.contains("wrap up")));
+        // ...and stopped once the grace period is over
+        app.wrap_up_requested_at = Some(Instant::now() - RUNTIME_WRAP_UP_GRACE);
+        assert!(app.check_runtime_limit());
+    }
+
+    #[test]
+    fn tui_edits_are_committed_when_the_prd_asks() {
+        let mut app = test_app("autocommit");
+        let task_dir = app.task_dir.clone();
+        let git = |args: &[&str]| {
+            let output = std::process::Command::new("git").args(args).current_dir(&task_dir).output().unwrap();
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        };
+        git(&["init", "--quiet"]);
+        git(&["config", "user.email", "ralph@example.com"]);
+        git(&["config", "user.name", "ralph"]);
+        let prd = std::fs::read_to_string(&app.prd_path).unwrap().replacen('{', "{\"autoCommitTuiEdits\": true, ", 1);
+        std::fs::write(&app.prd_path, &prd).unwrap();
+        crate::backup::backup_prd(&app.task_dir, &app.prd_path).unwrap();
+        std::fs::write(&app.prd_path, prd.replace("\"First\"", "\"Renamed\"")).unwrap();
+
+        app.open_undo_prefix();
+        app.confirm_prd_restore();
+        let hash = git(&["rev-parse", "--short", "HEAD"]);
+        assert!(app.active_toast().unwrap().ends_with(&format!(" · committed {}", hash)));
+        let backup = app.prd_journal[0].label.trim_start_matches("restore ");
+        assert_eq!(
+            git(&["log", "-1", "--format=%s"]),
+            format!("ralph-tui: restore prd.json from {} (session {})", backup, app.session_id)
+        );
+        // Only prd.json is committed
+        assert_eq!(git(&["show", "--name-only", "--format=", "HEAD"]), "prd.json");
+
+        // Staged work that isn't ralph-tui's holds the commit back
+        std::fs::write(app.task_dir.join("notes.md"), "wip").unwrap();
+        git(&["add", "notes.md"]);
+        app.undo_prd_edit();
+        assert!(app.active_toast().unwrap().ends_with("not committed: other changes are staged"));
+        assert_eq!(git(&["rev-parse", "--short", "HEAD"]), hash);
+    }
+
+    #[test]
+    fn quick_actions_skip_and_edit_the_selected_story() {
+        let mut app = test_app("quick-actions");
+        // Skipping the active story moves the loop on to the next one
+        app.toggle_selected_story_skipped();
+        assert_eq!(app.prd.as_ref().unwrap().current_story().unwrap().id, "US-002");
+        assert_eq!(app.snapshot().story_index.current, Some(1));
+        app.toggle_selected_story_skipped();
+        assert_eq!(app.prd.as_ref().unwrap().current_story().unwrap().id, "US-001");
+
+        app.selected_story_index = 1;
+        app.toggle_selected_story_passed();
+        assert!(app.prd.as_ref().unwrap().user_stories[1].passes);
+        assert_eq!(journal::read_prd_value(&app.prd_path).unwrap()["userStories"][1]["passes"], true);
+        app.set_story_notes("US-002", "check the cookie flags".to_string());
+        assert_eq!(app.prd.as_ref().unwrap().user_stories[1].notes, "check the cookie flags");
+        let labels: Vec<&str> = app.prd_journal.iter().map(|edit| edit.label.as_str()).collect();
+        assert_eq!(labels, ["US-002 passes", "US-002 notes"]);
+
+        // Both go through the journal, so u z undoes them one at a time
+        app.undo_prd_edit();
+        assert_eq!(app.prd.as_ref().unwrap().user_stories[1].notes, "");
+        app.undo_prd_edit();
+        assert!(!app.prd.as_ref().unwrap().user_stories[1].passes);
+    }
+
+    #[test]
+    fn terminal_title_shows_task_and_progress() {
+        let mut app = test_app("title");
+        let task = app.task_dir.file_name().unwrap().to_string_lossy().into_owned();
+        assert_eq!(app.terminal_title_text(), format!("ralph: {} 0/2 · iter 1/10", task));
+        let mut prd = app.prd.clone().unwrap();
+        prd.user_stories[0].passes = true;
+        app.set_prd(prd);
+        app.current_iteration = 3;
+        assert_eq!(app.terminal_title_text(), format!("ralph: {} 1/2 · iter 3/10", task));
+    }
+
+    #[test]
+    fn queue_outcome_reflects_how_the_task_ended() {
+        let mut app = test_app("queue-outcome");
+        app.current_iteration = 3;
+        let failed = io::Error::other("prd.json vanished");
+        assert_eq!(app.queue_outcome(&Err(failed)), (QueueStatus::Failed, "prd.json vanished".to_string()));
+        // Running out of iterations with stories left counts as failed
+        assert_eq!(app.queue_outcome(&Ok(())), (QueueStatus::Failed, "0/2 stories, 3 iterations".to_string()));
+
+        app.iteration_state = IterationState::Aborted;
+        assert_eq!(app.queue_outcome(&Ok(())).0, QueueStatus::Skipped);
+
+        let prd = app.prd.as_mut().unwrap();
+        for story in &mut prd.user_stories {
+            story.passes = true;
+        }
+        assert_eq!(app.queue_outcome(&Ok(())), (QueueStatus::Done, "2/2 stories, 3 iterations".to_string()));
+    }
+
+    #[test]
+    fn a_lingering_iteration_is_noted_in_the_footer_until_a_signal_drops_it() {
+        let mut app = test_app("linger");
+        assert_eq!(app.snapshot().toast, None);
+        app.iteration_ending = Some(IterationEnding {
+            source: IterationEndSource::ControlFile,
+            complete: false,
+            read_failure: None,
+            at: Instant::now(),
+        });
+        assert_eq!(app.snapshot().toast.as_deref(), Some(ITERATION_ENDING_NOTE));
+        // A toast of its own takes the footer first
+        app.show_toast("Copied US-001");
+        assert_eq!(app.snapshot().toast.as_deref(), Some("Copied US-001"));
+
+        app.stop_for_signal();
+        assert_eq!(app.iteration_ending, None);
+    }
+
+    #[test]
+    fn a_stop_between_iterations_aborts_and_resumes_with_the_next() {
+        let mut app = test_app("abort-delay");
+        app.current_iteration = 3;
+        app.iteration_state = IterationState::WaitingDelay;
+        app.delay_start = Some(Instant::now());
+        // Crashing mid-delay resumes after the finished iteration
+        assert_eq!(app.recovery_snapshot().current_iteration, 4);
+
+        app.stop_for_signal();
+        assert!(app.aborted());
+        assert_eq!(app.iteration_state, IterationState::Aborted);
+        assert_eq!(app.recovery_snapshot().current_iteration, 4);
+        assert_eq!(app.queue_outcome(&Ok(())).0, QueueStatus::Skipped);
+
+        // Stopped during an iteration: it is logged as aborted and redone on resume
+        let mut app = test_app("abort-running");
+        app.current_iteration = 3;
+        app.stop_for_signal();
+        assert!(app.aborted());
+        assert_eq!(app.recovery_snapshot().current_iteration, 3);
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.contains("Aborted"), "{}", log);
+    }
+
+    #[test]
+    fn missing_or_broken_prd_keeps_the_last_good_one_until_it_returns() {
+        let mut app = test_app("prd-missing");
+        let content = std::fs::read_to_string(&app.prd_path).unwrap();
+        let moved = app.task_dir.join("prd.json.bak");
+        std::fs::rename(&app.prd_path, &moved).unwrap();
+
+        assert!(!app.reload_prd());
+        assert_eq!(app.prd_problem, Some(PrdProblem::Missing));
+        let snapshot = app.snapshot();
+        assert_eq!(snapshot.prd_warning.as_deref(), Some("prd.json was deleted — waiting for it to reappear"));
+        assert_eq!(snapshot.prd.unwrap().user_stories.len(), 2);
+
+        std::fs::write(&app.prd_path, "{ not json").unwrap();
+        assert!(!app.reload_prd());
+        assert!(app.snapshot().prd_warning.unwrap().starts_with("prd.json doesn't parse: key must be a string"));
+
+        // The file coming back is picked up without a watcher event
+        std::fs::rename(&moved, &app.prd_path).unwrap();
+        app.prd_checked_at = Some(Instant::now() - PRD_RECHECK);
+        app.reload_prd_if_needed();
+        assert!(app.prd_problem.is_none());
+        assert_eq!(app.snapshot().toast.as_deref(), Some("prd.json is back - reloaded"));
+        assert_eq!(std::fs::read_to_string(&app.prd_path).unwrap(), content);
+
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        let events: Vec<&str> = log.lines().filter_map(|line| line.split(' ').nth(1)).collect();
+        assert_eq!(events, ["prd_missing", "prd_invalid", "prd_restored"]);
+    }
+
+    #[test]
+    fn winding_down_ends_the_run_after_the_running_iteration() {
+        let mut app = test_app("wind-down");
+        let _ = std::fs::remove_file(app.task_dir.join(ITERATION_LOG_FILE));
+        app.current_iteration = 3;
+
+        app.toggle_wind_down();
+        assert_eq!(app.max_iterations, 3);
+        let snapshot = app.snapshot();
+        assert!(snapshot.iterations_reduced);
+        assert_eq!(snapshot.toast.as_deref(), Some("Stopping after iteration 3"));
+
+        // Pressed again: the full budget is back
+        app.toggle_wind_down();
+        assert_eq!(app.max_iterations, 10);
+        assert!(!app.snapshot().iterations_reduced);
+
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        let entries: Vec<String> = log.lines().map(|line| line.split(' ').skip(1).collect::<Vec<_>>().join(" ")).collect();
+        assert_eq!(
+            entries,
+            ["iterations_reduced iteration=3 from=10 to=3", "iterations_restored iteration=3 max=10"]
+        );
+
+        // Already on the last iteration: nothing to cut
+        app.current_iteration = 10;
+        app.toggle_wind_down();
+        assert!(app.iterations_reduced_from.is_none());
+    }
+
+    #[test]
+    fn recovery_snapshot_round_trips_into_a_new_session() {
+        let mut crashed = test_app("recovery-crashed");
+        crashed.current_iteration = 12;
+        crashed.skipped_stories.insert("US-002".to_string());
+        crashed.session_start = Instant::now() - Duration::from_secs(600);
+        let snapshot = crashed.recovery_snapshot();
+        assert_eq!(snapshot.session_elapsed_secs, 600);
+
+        // Through the session file, as a restart would see it
+        let state = SessionState { recovery: Some(snapshot), ..SessionState::default() };
+        state.save(&crashed.task_dir);
+        let recovery = SessionState::load(&crashed.task_dir).recovery.unwrap();
+
+        let mut resumed = test_app("recovery-resumed");
+        resumed.restore_recovery(recovery);
+        assert_eq!(resumed.current_iteration, 12);
+        // The budget of 10 was already passed: it grows to cover the resumed iteration
+        assert_eq!(resumed.max_iterations, 12);
+        assert!(resumed.skipped_stories.contains("US-002"));
+        assert!(resumed.session_start.elapsed() >= Duration::from_secs(600));
+        assert_eq!(resumed.criteria_history["US-001"].len(), crashed.criteria_history["US-001"].len());
+        let log = std::fs::read_to_string(resumed.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.contains("recovered iteration=12"));
+    }
+
+    #[test]
+    fn hooks_log_their_status_and_toast_failures() {
+        let mut app = test_app("hooks");
+        app.prd.as_mut().unwrap().on_iteration_end =
+            Some("test \"$RALPH_EVENT:{task}:{iteration}\" = \"iteration_end:$RALPH_TASK:4\" && exit 2".to_string());
+        app.run_hook(HookEvent::IterationEnd, 4);
+        // No onComplete configured: nothing runs
+        app.run_hook(HookEvent::Complete, 4);
+        assert_eq!(app.hook_jobs.len(), 1);
+        app.finish_hook_jobs();
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.contains("hook event=iteration_end iteration=4 status=2 duration_secs=0"));
+        assert_eq!(app.toast.as_ref().unwrap().0, "onIterationEnd hook failed (exit 2)");
+
+        // ralph.toml's hook runs when prd.json has none, and prd.json's wins over it
+        app.on_complete = Some("exit 3".to_string());
+        app.run_hook(HookEvent::Complete, 5);
+        app.finish_hook_jobs();
+        app.prd.as_mut().unwrap().on_complete = Some("exit 4".to_string());
+        app.run_hook(HookEvent::Complete, 6);
+        app.finish_hook_jobs();
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.contains("hook event=complete iteration=5 status=3"), "{}", log);
+        assert!(log.contains("hook event=complete iteration=6 status=4"), "{}", log);
+    }
+
+    #[test]
+    fn iteration_ends_are_kept_and_read_failures_logged() {
+        let mut app = test_app("iteration-ends");
+        app.record_iteration_end(1, IterationEndSource::ControlFile, None);
+        app.record_iteration_end(2, IterationEndSource::ReadError, Some("Bad file descriptor (os error 9)".to_string()));
+        assert_eq!(app.snapshot().last_iteration_end, Some((2, IterationEndSource::ReadError)));
+        // The per-iteration list is only copied for the iterations view
+        assert!(app.snapshot().iteration_ends.is_empty());
+        app.ralph_view_mode = RalphViewMode::Iterations;
+        assert_eq!(app.snapshot().iteration_ends.len(), 2);
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.ends_with(" read_error iteration=2 message=\"Bad file descriptor (os error 9)\"\n"));
+    }
+
+    #[test]
+    fn conversations_continue_with_the_hooks_session_id() {
+        let mut app = test_app("conversation");
+        std::fs::write(
+            app.task_dir.join(SIGNAL_FILE),
+            r#"{"signal": "iteration-complete", "timestamp": 1700000000, "session_id": "0b6e"}"#,
+        )
+        .unwrap();
+        let message = take_control_signal(&app.task_dir, SIGNAL_FILE).unwrap();
+        assert_eq!(message, ControlMessage { signal: ControlSignal::IterationComplete, session_id: Some("0b6e".to_string()) });
+        std::fs::write(app.task_dir.join(SIGNAL_FILE), "complete").unwrap();
+        assert_eq!(take_control_signal(&app.task_dir, SIGNAL_FILE).unwrap().session_id, None);
+
+        // Off unless prd.json turns it on
+        assert!(app.next_conversation_args().is_empty());
+        app.toggle_fresh_session();
+        assert!(!app.conversation.fresh_requested);
+
+        app.prd.as_mut().unwrap().continue_conversation = true;
+        app.conversation.session_id = message.session_id;
+        app.current_iteration = 2;
+        assert_eq!(app.next_conversation_args(), ["--resume", "0b6e"]);
+        app.iteration_start = Instant::now();
+        app.record_iteration_end(2, IterationEndSource::ChildExited, None);
+        app.toggle_fresh_session();
+        assert!(app.conversation.fresh_requested);
+        app.current_iteration = 3;
+        assert!(app.next_conversation_args().is_empty());
+
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        let lines: Vec<&str> = log.lines().map(|line| line.split_once(' ').unwrap().1).collect();
+        assert_eq!(
+            lines,
+            ["conversation iteration=2 start=continued session=0b6e", "resume_failed iteration=2", "conversation iteration=3 start=fresh"]
+        );
+    }
+
+    #[test]
+    fn animations_hold_while_unfocused_or_idle() {
+        let mut app = test_app("animation");
+        app.last_animation_update = Instant::now() - OUTPUT_FRAME;
+        app.tick_animation();
+        assert_eq!(app.animation_tick, 1);
+
+        app.last_animation_update = Instant::now() - OUTPUT_FRAME;
+        app.redraw.set_focused(false);
+        app.tick_animation();
+        assert_eq!(app.animation_tick, 1);
+
+        // Focused again, but no active story and no agent running
+        app.redraw.set_focused(true);
+        app.prd = None;
+        app.tick_animation();
+        assert_eq!(app.animation_tick, 1);
+    }
+
+    #[test]
+    fn verify_commands_pass_criteria_but_never_unpass_them() {
+        let mut app = test_app("verify");
+        std::fs::write(
+            &app.prd_path,
+            r#"{"project": "demo", "taskDir": "tasks/demo", "type": "feature", "description": "Demo",
+                "userStories": [
+                    {"id": "US-001", "title": "First", "description": "", "priority": 1, "passes": false, "notes": "", "acceptanceCriteria": [
+                        {"id": "AC-1", "description": "lint", "passes": true, "verify": "exit 1"},
+                        {"id": "AC-2", "description": "tests", "passes": false, "verify": "test -f prd.json && echo \"ok $RALPH_MAX_ITERATIONS\""},
+                        {"id": "AC-3", "description": "by hand", "passes": false}]}
+                ]}"#,
+        )
+        .unwrap();
+        app.reload_prd();
+        app.repo_root = Some(app.task_dir.clone());
+        app.verify_selected_story();
+        let started = Instant::now();
+        while app.verify_job.is_some() && started.elapsed() < Duration::from_secs(10) {
+            app.poll_verify_job();
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(app.toast.as_ref().unwrap().0, "Verify: 1 passed, 1 failed (US-001 AC-1 exit 1)");
+
+        let criteria = &app.prd.as_ref().unwrap().user_stories[0].acceptance_criteria;
+        assert!(criteria[0].passes, "a failing command leaves a passed criterion alone");
+        assert_eq!(criteria[0].evidence, None);
+        assert!(criteria[1].passes);
+        assert_eq!(criteria[1].evidence.as_deref(), Some("$ test -f prd.json && echo \"ok $RALPH_MAX_ITERATIONS\"\nok 10"));
+        assert!(criteria[1].verified_at.as_deref().unwrap().ends_with(" UTC"));
+        assert!(!criteria[2].passes);
+        assert_eq!(app.prd_journal.len(), 1);
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.contains("verify story=US-001 criterion=AC-1 status=1 duration_secs=0"));
+        assert!(log.contains("verify story=US-001 criterion=AC-2 status=0 duration_secs=0"));
+
+        // Only unpassed criteria run at the boundary, and only when ralph.toml asks
+        app.verify_at_boundary();
+        assert!(app.verify_job.is_none());
+        app.verify_criteria = true;
+        app.verify_at_boundary();
+        assert!(app.verify_job.is_none(), "AC-3 has no command and the others pass");
+    }
+
+    #[test]
+    fn low_disk_space_pauses_recording_then_the_loop() {
+        let mut app = test_app("disk-space");
+        let megabytes = |mb: u64| Some(DiskSpace { available_bytes: mb * 1024 * 1024, available_inodes: None });
+        let timeline = app.task_dir.join(crate::events::TIMELINE_FILE);
+        let _ = std::fs::remove_file(&timeline);
+
+        assert_eq!(app.apply_disk_space(megabytes(300)), DiskPressure::Low);
+        assert_eq!(app.snapshot().disk_warning.as_deref(), Some("Low disk space: 300 MB free — timeline recording paused"));
+        assert!(app.disk_error.is_none());
+        app.emit_event(SessionEvent::IterationStart { iteration: 1 });
+        assert!(!timeline.exists());
+
+        assert_eq!(app.apply_disk_space(megabytes(20)), DiskPressure::Critical);
+        let snapshot = app.snapshot();
+        assert_eq!(snapshot.disk_warning.as_deref(), Some("Disk almost full: 20 MB free — loop paused"));
+        assert!(snapshot.disk_error.unwrap().starts_with("Only 20 MB free on the task directory's filesystem"));
+
+        // Freed space clears both, and the timeline records again
+        assert_eq!(app.apply_disk_space(megabytes(2048)), DiskPressure::Ok);
+        assert!(app.snapshot().disk_warning.is_none());
+        assert!(app.disk_error.is_none());
+        app.emit_event(SessionEvent::IterationStart { iteration: 1 });
+        assert!(timeline.exists());
+    }
+
+    #[test]
+    fn rewritten_stories_are_listed_and_can_pause_the_loop() {
+        let mut app = test_app("churn");
+        app.pause_on_churn = true;
+        std::fs::write(
+            &app.prd_path,
+            r#"{"project": "demo", "taskDir": "tasks/demo", "type": "feature", "description": "Demo",
+                "userStories": [
+                    {"id": "US-001", "title": "First", "description": "", "acceptanceCriteria": ["a, roughly"], "priority": 1, "passes": false, "notes": ""},
+                    {"id": "US-003", "title": "Third", "description": "", "acceptanceCriteria": ["c"], "priority": 3, "passes": false, "notes": ""}
+                ]}"#,
+        )
+        .unwrap();
+        assert!(app.reload_prd());
+        let messages: Vec<String> = app.snapshot().prd_churn.iter().map(PrdChurn::message).collect();
+        assert_eq!(
+            messages,
+            ["PRD modified: US-002 removed", "PRD modified: US-003 added", "PRD modified: US-001 criteria reworded"]
+        );
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.contains("prd_churn iteration=1 story=US-001 change=criteria_reworded suspicious=true"), "{}", log);
+
+        app.check_churn_pause();
+        let hold = app.churn_hold.clone().unwrap();
+        assert!(hold.contains("US-001 criteria reworded") && hold.contains("US-002 removed"), "{}", hold);
+        assert!(!hold.contains("US-003"));
+        app.dismiss_churn_hold();
+        app.check_churn_pause();
+        assert!(app.churn_hold.is_none());
+
+        // Without pause_on_prd_churn the changes are only listed
+        app.pause_on_churn = false;
+        let churn = app.prd_churn[0].clone();
+        app.prd_churn.push(churn);
+        app.check_churn_pause();
+        assert!(app.churn_hold.is_none());
+    }
+
+    #[test]
+    fn writes_outside_the_repository_are_flagged_and_pause_the_loop() {
+        let mut app = test_app("scope");
+        app.repo_root = Some(app.task_dir.clone());
+        let outside = TempDir::new("scope-outside");
+        lock_pty(&app.pty_state).activities = vec![
+            crate::pty::Activity::new("Write", &format!("{}/src/lib.rs", app.task_dir.display())),
+            crate::pty::Activity::new("Edit", &format!("{}/notes.md", app.task_dir.display())),
+            crate::pty::Activity::new("Write", &format!("{}/notes.md", outside.display())),
+            crate::pty::Activity::new("Read", &format!("{}/other.md", outside.display())),
+        ];
+        app.check_activity_scope();
+        let flagged: Vec<bool> = lock_pty(&app.pty_state).activities.iter().map(|a| a.out_of_scope).collect();
+        assert_eq!(flagged, vec![false, false, true, false]);
+        assert_eq!(app.scope_violations, vec![crate::scope::resolve(&outside.join("notes.md"), None)]);
+        assert!(app.snapshot().scope_warning.unwrap().starts_with("Agent wrote outside the repository: "));
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert_eq!(log.matches("scope_violation action=Write path=").count(), 1);
+
+        // Without pauseOnOutOfScope the loop goes on; with it, it holds until dismissed
+        app.check_scope_pause();
+        assert!(app.scope_hold.is_none());
+        app.prd.as_mut().unwrap().pause_on_out_of_scope = true;
+        app.check_scope_pause();
+        assert!(app.scope_hold.as_ref().unwrap().contains("notes.md"));
+        app.dismiss_scope_hold();
+        app.check_scope_pause();
+        assert!(app.scope_hold.is_none());
+        // The banner stays for the rest of the session
+        assert!(app.snapshot().scope_warning.is_some());
+    }
+
+    #[test]
+    fn dangerous_commands_open_the_tripwire_and_can_pause_the_loop() {
+        let mut app = test_app("tripwire");
+        app.tripwire = Tripwire::new(&["rm -rf".to_string(), "git push --force".to_string()]);
+        lock_pty(&app.pty_state).activities = vec![
+            crate::pty::Activity::new("Bash", "cargo test"),
+            crate::pty::Activity::new("Bash", "rm -rf target"),
+            crate::pty::Activity::new("Read", "rm -rf notes.md"),
+            crate::pty::Activity::new("Bash", "git push origin main --force"),
+        ];
+        app.check_tripwire();
+        let commands: Vec<&str> = app.tripwire_alerts.iter().map(|alert| alert.command.as_str()).collect();
+        assert_eq!(commands, vec!["rm -rf target", "git push origin main --force"]);
+        // Each activity is checked once; the Claude panel is frozen while the modal is up
+        app.check_tripwire();
+        assert_eq!(app.tripwire_alerts.len(), 2);
+        assert!(app.tripwire_frozen.is_some());
+        let snapshot = app.snapshot();
+        assert_eq!(snapshot.tripwire_alert.unwrap().pattern, "rm -rf");
+        assert_eq!(snapshot.tripwire_queued, 1);
+
+        app.answer_tripwire(TripwireAnswer::LetItRun);
+        assert!(app.tripwire_hold.is_none());
+        app.answer_tripwire(TripwireAnswer::PauseLoop);
+        assert!(app.tripwire_alerts.is_empty() && app.tripwire_frozen.is_none());
+        assert!(app.tripwire_hold.as_ref().unwrap().contains("git push origin main --force"));
+        app.dismiss_tripwire_hold();
+        assert!(app.tripwire_hold.is_none());
+
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.contains(r#"tripwire iteration=1 answer=let_it_run pattern="rm -rf" command="rm -rf target""#), "{}", log);
+        assert!(log.contains("answer=pause_loop"), "{}", log);
+    }
+
+    #[test]
+    fn reader_panic_is_logged_and_shown_instead_of_ending_the_iteration() {
+        let mut app = test_app("reader-error");
+        app.iteration_state = IterationState::Running;
+        let state = Arc::clone(&app.pty_state);
+        let _ = std::thread::spawn(move || {
+            let mut guard = state.lock().unwrap();
+            guard.reader_error = Some("index out of bounds".to_string());
+            panic!("index out of bounds");
+        })
+        .join();
+
+        let snapshot = app.snapshot();
+        assert!(snapshot.reader_error);
+        assert!(snapshot.claude_lines.is_some());
+        assert!(!lock_pty(&app.pty_state).child_exited);
+        assert_eq!(app.iteration_state, IterationState::Running);
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.contains("reader_error iteration=1 message=\"index out of bounds\""));
+        // Reported once: the next frame doesn't log it again
+        app.snapshot();
+        let log = std::fs::read_to_string(app.task_dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert_eq!(log.matches("reader_error").count(), 1);
+    }
+}
@@ -0,0 +1,149 @@
+//! prd.json backups taken before ralph-tui writes the file
+//!
+//! prd.json is the agent's source of truth, so every write ralph-tui makes goes
+//! through `write_prd`: the new content must parse as a PRD, the current file is
+//! copied to `<task_dir>/.prd-backups/prd-<unix-millis>.json` (keeping the newest
+//! `MAX_BACKUPS`), and the replacement is written to a temp file and renamed into
+//! place so a crash mid-write never leaves a truncated prd.json behind.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::prd::Prd;
+
+/// Backup directory inside the task directory
+pub const BACKUP_DIR: &str = ".prd-backups";
+
+/// Number of backups kept (older ones are pruned)
+pub const MAX_BACKUPS: usize = 20;
+
+/// Backups in the task directory, oldest first
+pub fn list_backups(task_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(task_dir.join(BACKUP_DIR)) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<(u128, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let stamp = name.strip_prefix("prd-")?.strip_suffix(".json")?.parse().ok()?;
+            Some((stamp, e.path()))
+        })
+        .collect();
+    backups.sort();
+    backups.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Most recent backup, if any
+pub fn latest_backup(task_dir: &Path) -> Option<PathBuf> {
+    list_backups(task_dir).pop()
+}
+
+/// Copy the current prd.json into the backup directory and prune old backups
+/// Returns None when there is no prd.json to back up.
+pub fn backup_prd(task_dir: &Path, prd_path: &Path) -> io::Result<Option<PathBuf>> {
+    if !prd_path.exists() {
+        return Ok(None);
+    }
+    let dir = task_dir.join(BACKUP_DIR);
+    std::fs::create_dir_all(&dir)?;
+
+    // Millisecond stamps; bump past the newest backup if two land in the same millisecond
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+    let newest = list_backups(task_dir)
+        .last()
+        .and_then(|p| p.file_stem()?.to_str()?.strip_prefix("prd-")?.parse::<u128>().ok());
+    let stamp = match newest {
+        Some(newest) if newest >= now => newest + 1,
+        _ => now,
+    };
+    let backup = dir.join(format!("prd-{}.json", stamp));
+    std::fs::copy(prd_path, &backup)?;
+
+    let backups = list_backups(task_dir);
+    for old in backups.iter().take(backups.len().saturating_sub(MAX_BACKUPS)) {
+        let _ = std::fs::remove_file(old);
+    }
+    Ok(Some(backup))
+}
+
+/// Replace prd.json with `content`, backing up the current file first
+/// Content that doesn't parse as a PRD is rejected and prd.json is left untouched.
+pub fn write_prd(task_dir: &Path, prd_path: &Path, content: &str) -> io::Result<()> {
+    serde_json::from_str::<Prd>(content).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("refusing to write invalid prd.json: {}", e))
+    })?;
+    backup_prd(task_dir, prd_path)?;
+
+    let temp = prd_path.with_extension("json.tmp");
+    std::fs::write(&temp, content)?;
+    std::fs::rename(&temp, prd_path)
+}
+
+/// Restore prd.json from the most recent backup (the replaced file is backed up too)
+/// Returns the backup that was restored.
+pub fn restore_latest(task_dir: &Path, prd_path: &Path) -> io::Result<PathBuf> {
+    let backup = latest_backup(task_dir)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no prd.json backups"))?;
+    let content = std::fs::read_to_string(&backup)?;
+    write_prd(task_dir, prd_path, &content)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    const PRD: &str = r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "v1", "userStories": []}"#;
+
+    fn task_dir(name: &str) -> TempDir {
+        TempDir::new(&format!("backup-{}", name))
+    }
+
+    #[test]
+    fn restores_after_a_corrupted_write() {
+        let dir = task_dir("corrupt");
+        let prd_path = dir.join("prd.json");
+        std::fs::write(&prd_path, PRD).unwrap();
+
+        // Invalid content is refused before anything is touched
+        assert!(write_prd(&dir, &prd_path, "{\"project\": ").is_err());
+        assert_eq!(std::fs::read_to_string(&prd_path).unwrap(), PRD);
+        assert!(list_backups(&dir).is_empty());
+
+        // A write path that bypassed validation and truncated the file can be undone
+        let v2 = PRD.replace("v1", "v2");
+        write_prd(&dir, &prd_path, &v2).unwrap();
+        std::fs::write(&prd_path, "{\"proj").unwrap();
+        // Latest backup is v1 (taken before the v2 write); restoring backs up the corrupt file
+        restore_latest(&dir, &prd_path).unwrap();
+        assert_eq!(std::fs::read_to_string(&prd_path).unwrap(), PRD);
+        assert_eq!(list_backups(&dir).len(), 2);
+        assert_eq!(std::fs::read_to_string(latest_backup(&dir).unwrap()).unwrap(), "{\"proj");
+    }
+
+    #[test]
+    fn keeps_only_the_newest_backups() {
+        let dir = task_dir("prune");
+        let prd_path = dir.join("prd.json");
+        std::fs::write(&prd_path, PRD).unwrap();
+        for i in 0..MAX_BACKUPS + 5 {
+            write_prd(&dir, &prd_path, &PRD.replace("v1", &format!("v{}", i + 2))).unwrap();
+        }
+        let backups = list_backups(&dir);
+        assert_eq!(backups.len(), MAX_BACKUPS);
+        // Oldest kept backup holds the content replaced by the 6th write
+        assert!(std::fs::read_to_string(&backups[0]).unwrap().contains("\"v6\""));
+        assert!(std::fs::read_to_string(backups.last().unwrap()).unwrap().contains(&format!("\"v{}\"", MAX_BACKUPS + 5)));
+    }
+
+    #[test]
+    fn restore_without_backups_fails() {
+        let dir = task_dir("none");
+        let prd_path = dir.join("prd.json");
+        std::fs::write(&prd_path, PRD).unwrap();
+        assert_eq!(restore_latest(&dir, &prd_path).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,100 @@
+//! Chained tasks (`nextTask` in prd.json)
+//!
+//! A task can name the task to start once all its stories pass, e.g. a backend
+//! task followed by the frontend task that builds on it. `nextTask` is a task
+//! directory, taken relative to the directory holding this task (so `"frontend"`
+//! next to `tasks/backend` is `tasks/frontend`). The whole chain is followed before
+//! anything starts: a task with no prd.json, or a chain that comes back to a task
+//! already in it, is refused.
+
+use std::path::{Path, PathBuf};
+
+use crate::prd::Prd;
+
+/// Directory named by `next` for the task in `task_dir`
+pub fn resolve_next_task(task_dir: &Path, next: &str) -> PathBuf {
+    let next = Path::new(next);
+    if next.is_absolute() {
+        return next.to_path_buf();
+    }
+    match task_dir.parent() {
+        Some(parent) => parent.join(next),
+        None => next.to_path_buf(),
+    }
+}
+
+/// The tasks that follow the one at `prd_path`, in order, by following `nextTask`
+/// A prd.json that can't be read ends the chain there; it is reported when that task runs.
+pub fn task_chain(task_dir: &Path, prd_path: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut seen = vec![task_identity(task_dir)];
+    let mut names = vec![task_dir.display().to_string()];
+    let mut chain = Vec::new();
+    let (mut dir, mut prd_path) = (task_dir.to_path_buf(), prd_path.to_path_buf());
+    while let Ok(prd) = Prd::load(&prd_path) {
+        let Some(next) = prd.next_task.filter(|next| !next.trim().is_empty()) else {
+            break;
+        };
+        let next_dir = resolve_next_task(&dir, next.trim());
+        names.push(next_dir.display().to_string());
+        if seen.contains(&task_identity(&next_dir)) {
+            return Err(format!("circular nextTask: {}", names.join(" → ")));
+        }
+        prd_path = next_dir.join("prd.json");
+        if !prd_path.exists() {
+            return Err(format!("nextTask of {}: {} has no prd.json", dir.display(), next_dir.display()));
+        }
+        seen.push(task_identity(&next_dir));
+        chain.push(next_dir.clone());
+        dir = next_dir;
+    }
+    Ok(chain)
+}
+
+/// Canonical path of a task directory, so `tasks/a` and `./tasks/a` are the same task
+pub fn task_identity(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn write_task(root: &Path, name: &str, next: Option<&str>) {
+        std::fs::create_dir_all(root.join(name)).unwrap();
+        let next = next.map(|next| format!(", \"nextTask\": \"{}\"", next)).unwrap_or_default();
+        std::fs::write(
+            root.join(name).join("prd.json"),
+            format!(r#"{{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": []{}}}"#, next),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn follows_next_task_and_refuses_cycles() {
+        let root = TempDir::new("chain");
+        write_task(&root, "backend", Some("frontend"));
+        write_task(&root, "frontend", Some("docs"));
+        write_task(&root, "docs", None);
+        let chain = |name: &str| task_chain(&root.join(name), &root.join(name).join("prd.json"));
+        assert_eq!(chain("backend"), Ok(vec![root.join("frontend"), root.join("docs")]));
+        assert_eq!(chain("docs"), Ok(vec![]));
+
+        write_task(&root, "docs", Some("./backend"));
+        let err = chain("backend").unwrap_err();
+        assert!(err.starts_with("circular nextTask: "), "{}", err);
+        assert!(err.ends_with(&format!("docs → {}", root.join("./backend").display())), "{}", err);
+
+        write_task(&root, "docs", Some("docs"));
+        assert!(chain("docs").unwrap_err().starts_with("circular nextTask"));
+
+        write_task(&root, "docs", Some("gone"));
+        assert!(chain("backend").unwrap_err().ends_with("gone has no prd.json"));
+    }
+
+    #[test]
+    fn next_task_is_next_to_the_task() {
+        assert_eq!(resolve_next_task(Path::new("tasks/backend"), "frontend"), PathBuf::from("tasks/frontend"));
+        assert_eq!(resolve_next_task(Path::new("tasks/backend"), "/srv/tasks/ui"), PathBuf::from("/srv/tasks/ui"));
+    }
+}
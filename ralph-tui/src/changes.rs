@@ -0,0 +1,121 @@
+//! What the agent changed in the running iteration (the `v` view)
+//!
+//! As each iteration starts, the tree is recorded with `git::tree_snapshot`
+//! (uncommitted changes included). The changes view lists every file that
+//! differs from it now, committed or not, like `git diff --stat`; Enter on a
+//! file shows its unified diff. Git only runs when the view is opened or a file
+//! is picked, and the results are kept until the next iteration starts. Files
+//! git doesn't track yet aren't listed until they are added.
+
+use std::path::Path;
+
+use crate::git;
+
+/// Lines of a file's diff that are shown (the rest is cut off)
+pub const DIFF_MAX_LINES: usize = 1000;
+
+/// One file in the change list, with its added and removed line counts (None for binary files)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangedFile {
+    /// Relative to the repository root
+    pub path: String,
+    pub added: Option<u32>,
+    pub removed: Option<u32>,
+}
+
+/// Files changed since iteration `iteration` started, or why they can't be listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IterationChanges {
+    pub iteration: u32,
+    pub files: Result<Vec<ChangedFile>, String>,
+}
+
+impl IterationChanges {
+    pub fn load(repo: &Path, base: &str, iteration: u32) -> Self {
+        let files = git::diff_numstat(repo, base).map(|output| parse_numstat(&output)).map_err(|e| e.to_string());
+        IterationChanges { iteration, files }
+    }
+}
+
+/// A file's unified diff against the iteration start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    pub path: String,
+    pub lines: Result<Vec<String>, String>,
+    /// More than DIFF_MAX_LINES lines (only the first ones are kept)
+    pub truncated: bool,
+}
+
+impl FileDiff {
+    pub fn load(repo: &Path, base: &str, path: &str) -> Self {
+        match git::diff_file(repo, base, path) {
+            Ok(output) => {
+                let mut lines: Vec<String> = output.lines().map(|line| line.replace('\t', "    ")).collect();
+                let truncated = lines.len() > DIFF_MAX_LINES;
+                lines.truncate(DIFF_MAX_LINES);
+                FileDiff { path: path.to_string(), lines: Ok(lines), truncated }
+            }
+            Err(e) => FileDiff { path: path.to_string(), lines: Err(e.to_string()), truncated: false },
+        }
+    }
+}
+
+/// Parse `git diff --numstat` output ("added<TAB>removed<TAB>path", `-` counts for binary files)
+fn parse_numstat(output: &str) -> Vec<ChangedFile> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let added = fields.next()?;
+            let removed = fields.next()?;
+            let path = fields.next()?;
+            Some(ChangedFile { path: path.to_string(), added: added.parse().ok(), removed: removed.parse().ok() })
+        })
+        .collect()
+}
+
+/// The `+++--` bar of a `git diff --stat` line, scaled so the largest change is `width` wide
+pub fn stat_bar(file: &ChangedFile, most: u32, width: usize) -> (String, String) {
+    let (Some(added), Some(removed)) = (file.added, file.removed) else {
+        return (String::new(), String::new());
+    };
+    let total = added + removed;
+    if total == 0 || most == 0 {
+        return (String::new(), String::new());
+    }
+    let bar = if most as usize <= width { total as usize } else { (total as usize * width).div_ceil(most as usize) };
+    let bar = bar.max(usize::from(added > 0) + usize::from(removed > 0));
+    // Split the bar in proportion, keeping at least one mark for each side that changed
+    let plus = (added as usize * bar).div_ceil(total as usize).min(bar - usize::from(removed > 0));
+    ("+".repeat(plus), "-".repeat(bar - plus))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numstat_lists_counts_and_binary_files() {
+        assert_eq!(
+            parse_numstat("12\t3\tsrc/app.rs\n-\t-\tlogo.png\n0\t7\told name.txt"),
+            [
+                ChangedFile { path: "src/app.rs".to_string(), added: Some(12), removed: Some(3) },
+                ChangedFile { path: "logo.png".to_string(), added: None, removed: None },
+                ChangedFile { path: "old name.txt".to_string(), added: Some(0), removed: Some(7) },
+            ]
+        );
+        assert!(parse_numstat("").is_empty());
+    }
+
+    #[test]
+    fn stat_bars_scale_to_the_largest_change() {
+        let file = |added, removed| ChangedFile { path: String::new(), added: Some(added), removed: Some(removed) };
+        assert_eq!(stat_bar(&file(3, 2), 5, 20), ("+++".to_string(), "--".to_string()));
+        assert_eq!(stat_bar(&file(30, 10), 40, 20), ("+++++++++++++++".to_string(), "-----".to_string()));
+        // Small changes next to a big one keep a mark for each side
+        assert_eq!(stat_bar(&file(1, 1), 400, 20), ("+".to_string(), "-".to_string()));
+        assert_eq!(stat_bar(&file(0, 0), 5, 20), (String::new(), String::new()));
+        let binary = ChangedFile { path: String::new(), added: None, removed: None };
+        assert_eq!(stat_bar(&binary, 5, 20), (String::new(), String::new()));
+    }
+}
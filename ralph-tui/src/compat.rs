@@ -0,0 +1,131 @@
+//! Claude Code versions ralph-tui's output parsing is known to work with
+//!
+//! Turn-end detection (the Stop hook message) and the activity feed read Claude
+//! Code's output, whose format changes between releases. `claude --version` is run
+//! once at startup; the release it reports is shown on the preflight checklist and
+//! in the Claude panel title, recorded on every iteration log line, and checked
+//! against CLAUDE_MIN_SUPPORTED and CLAUDE_MAX_TESTED. Outside that range the
+//! version is shown in amber, since the parsing heuristics may misbehave.
+
+use std::fmt;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::agents::{AgentProfile, DEFAULT_AGENT};
+use crate::preflight::{agent_version, find_binary};
+
+/// Oldest release whose Stop hook can end a turn (`continue: false`)
+pub const CLAUDE_MIN_SUPPORTED: Release = Release(1, 0, 0);
+
+/// Newest release the parsing was checked against (any patch of this minor version)
+pub const CLAUDE_MAX_TESTED: Release = Release(2, 1, 0);
+
+/// First `major.minor.patch` in a version line
+static RELEASE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+)\.(\d+)\.(\d+)").expect("valid regex"));
+
+/// A `major.minor.patch` release number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Release(pub u32, pub u32, pub u32);
+
+impl Release {
+    /// The release in a line like `2.0.14 (Claude Code)`
+    pub fn parse(text: &str) -> Option<Self> {
+        let caps = RELEASE.captures(text)?;
+        let part = |i: usize| caps[i].parse::<u32>().ok();
+        Some(Release(part(1)?, part(2)?, part(3)?))
+    }
+}
+
+impl fmt::Display for Release {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// How a Claude Code release compares with the range ralph-tui was checked against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compatibility {
+    Supported,
+    TooOld,   // Older than CLAUDE_MIN_SUPPORTED
+    Untested, // Newer than CLAUDE_MAX_TESTED's minor version
+}
+
+impl Compatibility {
+    pub fn of(release: Release) -> Self {
+        if release < CLAUDE_MIN_SUPPORTED {
+            Compatibility::TooOld
+        } else if (release.0, release.1) > (CLAUDE_MAX_TESTED.0, CLAUDE_MAX_TESTED.1) {
+            Compatibility::Untested
+        } else {
+            Compatibility::Supported
+        }
+    }
+}
+
+/// What `claude --version` reported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentVersion {
+    /// Its first line, e.g. `2.0.14 (Claude Code)`
+    pub text: String,
+    pub release: Option<Release>,
+}
+
+impl AgentVersion {
+    pub fn new(text: &str) -> Self {
+        AgentVersion { text: text.trim().to_string(), release: Release::parse(text) }
+    }
+
+    /// The release for titles and the iteration log (the whole line when it has none)
+    pub fn short(&self) -> String {
+        match self.release {
+            Some(release) => release.to_string(),
+            None => self.text.clone(),
+        }
+    }
+
+    /// Why the output parsing may misbehave with this release (None when it's in range
+    /// or couldn't be read)
+    pub fn warning(&self) -> Option<String> {
+        let release = self.release?;
+        let range = format!("{} to {}.{}.x", CLAUDE_MIN_SUPPORTED, CLAUDE_MAX_TESTED.0, CLAUDE_MAX_TESTED.1);
+        match Compatibility::of(release) {
+            Compatibility::Supported => None,
+            Compatibility::TooOld => Some(format!("older than supported ({}); turn ends may go unnoticed", range)),
+            Compatibility::Untested => {
+                Some(format!("newer than tested ({}); stop-hook and activity parsing may misbehave", range))
+            }
+        }
+    }
+}
+
+/// Ask the agent for its version; only Claude is asked, since other agents (and
+/// wrapper scripts) may not treat --version as harmless
+pub fn detect(agent: &AgentProfile) -> Option<AgentVersion> {
+    if agent.name != DEFAULT_AGENT {
+        return None;
+    }
+    let path = find_binary(&agent.binary)?;
+    agent_version(&path).ok().flatten().map(|text| AgentVersion::new(&text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_are_checked_against_the_tested_range() {
+        let version = AgentVersion::new("2.0.14 (Claude Code)\n");
+        assert_eq!((version.release, version.short()), (Some(Release(2, 0, 14)), "2.0.14".to_string()));
+        assert_eq!(version.warning(), None);
+        assert_eq!(AgentVersion::new("2.1.57 (Claude Code)").warning(), None);
+
+        let newer = AgentVersion::new("3.0.1 (Claude Code)").warning().unwrap();
+        assert_eq!(newer, "newer than tested (1.0.0 to 2.1.x); stop-hook and activity parsing may misbehave");
+        assert!(AgentVersion::new("0.2.125 (Claude Code)").warning().unwrap().starts_with("older than supported"));
+
+        // A line without a release number is shown as it is, without a warning
+        let odd = AgentVersion::new("claude nightly");
+        assert_eq!((odd.release, odd.short(), odd.warning()), (None, "claude nightly".to_string(), None));
+    }
+}
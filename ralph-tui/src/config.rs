@@ -0,0 +1,180 @@
+//! Global settings from `~/.config/ralph/ralph.toml`
+//!
+//! Every key is optional; a missing file means the built-in defaults:
+//!
+//! ```toml
+//! # Bash commands that raise the tripwire (replaces the defaults, [] turns it off)
+//! dangerous_commands = ["rm -rf", "git push --force", "DROP TABLE"]
+//! # Hold the loop when the agent drops criteria or rewords unpassed ones
+//! pause_on_prd_churn = true
+//! # Run unpassed criteria's verify commands at each iteration boundary
+//! verify_criteria = true
+//! # How long one verify command may run
+//! verify_timeout_secs = 120
+//! # Show the agent's terminal size in the Claude panel title
+//! show_pty_size = true
+//! # Tag passed criteria without a matching `Verified:` note in progress.txt
+//! check_verified_notes = true
+//! # Share of a criterion's words such a note must contain
+//! verified_match_percent = 50
+//! # Directories holding the tasks (comma-separated; default "tasks")
+//! task_root = "automation/ralph-tasks,tasks"
+//! # No tips for unused features in the footer
+//! hide_tips = true
+//! # Leave the terminal title alone (like --no-title), for multiplexers that manage it
+//! no_title = true
+//! # Forward Ctrl+C/D/Z to the agent on the first press (like --no-key-guard)
+//! key_guard = false
+//! # Ask the agent to wrap up once it compacts its context (like --end-on-compaction)
+//! end_on_compaction = true
+//! # Type every prompt into the agent's terminal, not only those too long for argv
+//! prompt_via_stdin = true
+//! # Defaults for tasks whose prd.json doesn't set defaultIterations / rotateThreshold
+//! default_iterations = 20
+//! rotate_threshold = 500
+//! # Variables for every agent (a task's prd.json `env` overrides them)
+//! env = { HTTP_PROXY = "http://proxy.internal:3128" }
+//! # Hooks for tasks whose prd.json doesn't set onIterationEnd / onComplete (see hooks.rs)
+//! on_iteration_end = "notify-send 'ralph: {task}' 'iteration {iteration} done'"
+//! on_complete = "paplay /usr/share/sounds/freedesktop/stereo/complete.oga"
+//!
+//! # Key bindings (see keymap.rs), replacing each listed action's defaults
+//! [keys]
+//! enter_claude_mode = "a"
+//! story_up = ["up", "ctrl+p"]
+//!
+//! # Agent profiles (see agents.rs), replacing a built-in of the same name
+//! [agents.goose]
+//! binary = "goose"
+//! args = ["session", "{extra_args}"]
+//! prompt_via = "stdin"
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::agents::AgentProfile;
+use crate::keymap::KeyBinding;
+
+/// Settings file, in the global Ralph config directory
+pub const CONFIG_FILE: &str = "ralph.toml";
+
+/// Contents of ralph.toml
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RalphConfig {
+    /// Patterns for the destructive command tripwire (None = tripwire::DEFAULT_DANGEROUS_COMMANDS)
+    pub dangerous_commands: Option<Vec<String>>,
+    /// Pause at the next iteration boundary after suspicious prd.json churn (kept out of
+    /// prd.json so the agent can't turn it off)
+    #[serde(default)]
+    pub pause_on_prd_churn: bool,
+    /// Run the verify commands of unpassed criteria at each iteration boundary (V in the
+    /// story details runs them either way)
+    #[serde(default)]
+    pub verify_criteria: bool,
+    /// Timeout for one verify command (None = verify::DEFAULT_VERIFY_TIMEOUT_SECS)
+    pub verify_timeout_secs: Option<u64>,
+    /// Show the agent's PTY size (cols×rows) in the Claude panel title, to spot an agent
+    /// drawing at a stale size
+    #[serde(default)]
+    pub show_pty_size: bool,
+    /// Cross-check passed criteria against the agent's `Verified:` notes in progress.txt
+    #[serde(default)]
+    pub check_verified_notes: bool,
+    /// Share of a criterion's words a note must contain (None = crosscheck::DEFAULT_MATCH_PERCENT)
+    pub verified_match_percent: Option<u8>,
+    /// Task roots, comma-separated (None = tasks::DEFAULT_TASK_ROOT); --task-root and
+    /// $RALPH_TASK_ROOTS take precedence
+    #[serde(alias = "taskRoot")]
+    pub task_root: Option<String>,
+    /// Leave the footer without tips (tips::footer_tip)
+    #[serde(default)]
+    pub hide_tips: bool,
+    /// Don't show progress in the terminal title (--no-title for every run)
+    #[serde(default)]
+    pub no_title: bool,
+    /// Ask for a second press before Ctrl+C/D/Z reach the agent (None = on); --no-key-guard
+    /// turns it off either way
+    pub key_guard: Option<bool>,
+    /// Ask the agent to wrap up after its first compaction (--end-on-compaction for every run)
+    #[serde(default)]
+    pub end_on_compaction: bool,
+    /// Type the prompt in even when it fits a `{prompt}` argument (agents::AgentProfile::prompt_delivery)
+    #[serde(default)]
+    pub prompt_via_stdin: bool,
+    /// Iteration budget when neither -i nor prd.json's defaultIterations gives one
+    pub default_iterations: Option<u32>,
+    /// progress.txt rotation threshold when neither --rotate-at nor prd.json's rotateThreshold gives one
+    pub rotate_threshold: Option<u32>,
+    /// Variables set for every agent; app::App::iteration_env puts prd.json's `env` over them
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Command run after each iteration when prd.json has no `onIterationEnd`
+    pub on_iteration_end: Option<String>,
+    /// Command run when every story passes when prd.json has no `onComplete`
+    pub on_complete: Option<String>,
+    /// Key bindings by action name (keymap::KeyMap::from_bindings applies them)
+    #[serde(default)]
+    pub keys: BTreeMap<String, KeyBinding>,
+    /// User agent profiles by name (agents::profiles merges them with the built-ins)
+    #[serde(default)]
+    pub agents: BTreeMap<String, AgentProfile>,
+}
+
+/// Parse ralph.toml
+pub fn parse_config(content: &str) -> Result<RalphConfig, String> {
+    toml::from_str(content).map_err(|e| e.message().to_string())
+}
+
+/// Read ralph.toml from the config directory (defaults when there is none)
+pub fn load_config(config_dir: Option<&Path>) -> io::Result<RalphConfig> {
+    let Some(path) = config_dir.map(|dir| dir.join(CONFIG_FILE)).filter(|path| path.exists()) else {
+        return Ok(RalphConfig::default());
+    };
+    let content = std::fs::read_to_string(&path)?;
+    parse_config(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings_and_rejects_unknown_keys() {
+        let config = parse_config("# mine\ndangerous_commands = [\"rm -rf\", \"terraform destroy\"]\n").unwrap();
+        assert_eq!(config.dangerous_commands, Some(vec!["rm -rf".to_string(), "terraform destroy".to_string()]));
+        assert!(!config.pause_on_prd_churn);
+        assert!(parse_config("pause_on_prd_churn = true").unwrap().pause_on_prd_churn);
+        let config = parse_config("verify_criteria = true\nverify_timeout_secs = 600").unwrap();
+        assert!(config.verify_criteria);
+        assert_eq!(config.verify_timeout_secs, Some(600));
+        assert!(parse_config("show_pty_size = true").unwrap().show_pty_size);
+        let config = parse_config("check_verified_notes = true\nverified_match_percent = 70").unwrap();
+        assert!(config.check_verified_notes);
+        assert_eq!(config.verified_match_percent, Some(70));
+        assert!(parse_config("verified_match_percent = 300").is_err());
+        let config = parse_config("taskRoot = \"automation/ralph-tasks,tasks\"").unwrap();
+        assert_eq!(config.task_root.as_deref(), Some("automation/ralph-tasks,tasks"));
+        assert!(parse_config("hide_tips = true").unwrap().hide_tips);
+        assert!(parse_config("no_title = true").unwrap().no_title);
+        assert_eq!(parse_config("key_guard = false").unwrap().key_guard, Some(false));
+        assert!(parse_config("end_on_compaction = true").unwrap().end_on_compaction);
+        assert!(parse_config("prompt_via_stdin = true").unwrap().prompt_via_stdin);
+        let config = parse_config("default_iterations = 20\nrotate_threshold = 500").unwrap();
+        assert_eq!((config.default_iterations, config.rotate_threshold), (Some(20), Some(500)));
+        let config = parse_config("env = { HTTP_PROXY = \"http://proxy:3128\" }").unwrap();
+        assert_eq!(config.env.get("HTTP_PROXY").map(String::as_str), Some("http://proxy:3128"));
+        assert!(parse_config("env = { RETRIES = 3 }").is_err());
+        let config = parse_config("on_iteration_end = \"echo {iteration}\"\non_complete = \"paplay done.oga\"").unwrap();
+        assert_eq!(config.on_iteration_end.as_deref(), Some("echo {iteration}"));
+        assert_eq!(config.on_complete.as_deref(), Some("paplay done.oga"));
+        assert!(parse_config("onComplete = \"paplay done.oga\"").is_err());
+        assert_eq!(parse_config("").unwrap(), RalphConfig::default());
+        assert!(parse_config("dangerous_command = []").unwrap_err().contains("unknown field"));
+        assert!(parse_config("dangerous_commands = \"rm -rf\"").is_err());
+    }
+}
@@ -0,0 +1,109 @@
+//! Picking up the agent's conversation between iterations (`continueConversation`)
+//!
+//! Normally every iteration starts the agent cold. With `"continueConversation":
+//! true` in prd.json, every iteration after the session's first one resumes the
+//! previous conversation instead: `--resume <id>` when the stop hook passed on
+//! Claude's session id in the control file, `--continue` otherwise. An agent that
+//! exits within RESUME_FAILURE_WINDOW of a resume is taken to have failed it, and
+//! the next iteration starts fresh. `N` asks for a fresh start once, for when the
+//! conversation has gone stale. Parallel lanes always start fresh.
+
+use std::time::Duration;
+
+use crate::agents::AgentProfile;
+use crate::app::IterationEndSource;
+
+/// A continued agent that exits sooner than this didn't manage to resume
+pub const RESUME_FAILURE_WINDOW: Duration = Duration::from_secs(10);
+
+/// How an iteration's conversation started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConversationStart {
+    Fresh,
+    Continued,
+}
+
+impl ConversationStart {
+    pub fn label(self) -> &'static str {
+        match self {
+            ConversationStart::Fresh => "fresh",
+            ConversationStart::Continued => "continued",
+        }
+    }
+}
+
+/// The single agent's conversation across the session's iterations
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Conversation {
+    /// Session id from the last control file that carried one
+    pub session_id: Option<String>,
+    /// N: start the next iteration fresh
+    pub fresh_requested: bool,
+    /// The last resume failed, so the next iteration starts fresh
+    pub resume_failed: bool,
+    /// How the running iteration started (None until the first agent is spawned)
+    pub current: Option<ConversationStart>,
+}
+
+impl Conversation {
+    /// Decide how the next iteration starts, returning the arguments for the agent's
+    /// `{resume_args}` (empty for a fresh start)
+    pub fn next_start(&mut self, enabled: bool, profile: &AgentProfile) -> (ConversationStart, Vec<String>) {
+        let fresh_requested = std::mem::take(&mut self.fresh_requested);
+        let resume_failed = std::mem::take(&mut self.resume_failed);
+        let args = if enabled && self.current.is_some() && !fresh_requested && !resume_failed {
+            profile.conversation_args(self.session_id.as_deref())
+        } else {
+            None
+        };
+        let start = if args.is_some() { ConversationStart::Continued } else { ConversationStart::Fresh };
+        if start == ConversationStart::Fresh {
+            // A fresh conversation gets a new id from its stop hook
+            self.session_id = None;
+        }
+        self.current = Some(start);
+        (start, args.unwrap_or_default())
+    }
+
+    /// Note how the running iteration ended; true when it was a resume that failed
+    pub fn iteration_ended(&mut self, source: IterationEndSource, ran: Duration) -> bool {
+        let exited = matches!(source, IterationEndSource::ChildExited | IterationEndSource::ReadError);
+        if self.current == Some(ConversationStart::Continued) && exited && ran < RESUME_FAILURE_WINDOW {
+            self.resume_failed = true;
+            self.session_id = None;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continues_after_the_first_iteration_until_asked_or_failed() {
+        let claude = AgentProfile::claude();
+        let mut conversation = Conversation::default();
+        assert_eq!(conversation.next_start(true, &claude), (ConversationStart::Fresh, vec![]));
+
+        conversation.session_id = Some("0b6e".to_string());
+        assert_eq!(conversation.next_start(true, &claude).1, ["--resume", "0b6e"]);
+        // Without an id from the hook, the latest conversation is continued
+        conversation.session_id = None;
+        assert_eq!(conversation.next_start(true, &claude), (ConversationStart::Continued, vec!["--continue".to_string()]));
+
+        // A resumed agent that exits at once failed to resume: one fresh start
+        assert!(conversation.iteration_ended(IterationEndSource::ChildExited, Duration::from_secs(2)));
+        assert_eq!(conversation.next_start(true, &claude).0, ConversationStart::Fresh);
+        assert!(!conversation.iteration_ended(IterationEndSource::ChildExited, Duration::from_secs(2)));
+        assert_eq!(conversation.next_start(true, &claude).0, ConversationStart::Continued);
+        assert!(!conversation.iteration_ended(IterationEndSource::ControlFile, Duration::from_secs(2)));
+
+        conversation.fresh_requested = true;
+        conversation.session_id = Some("0b6e".to_string());
+        assert_eq!(conversation.next_start(true, &claude).0, ConversationStart::Fresh);
+        assert_eq!(conversation.session_id, None);
+        assert_eq!(conversation.next_start(false, &claude).0, ConversationStart::Fresh);
+    }
+}
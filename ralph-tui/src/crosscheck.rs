@@ -0,0 +1,182 @@
+//! Cross-check of passed criteria against the agent's notes in progress.txt
+//!
+//! The prompt asks the agent to add a `Verified: <criterion> — <how>` line to its
+//! progress entry for each criterion it marks passed. With `check_verified_notes` in
+//! ralph.toml, every passed criterion is matched against the `Verified:` lines filed
+//! under its story: in an entry whose `## ` heading names the story, or naming the story
+//! themselves. A note matches when it names the criterion's id or shares enough of its
+//! words (lowercased, crudely stemmed, without stop words). A passed criterion with no
+//! matching note is tagged "unverified" in the story details view.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::prd::Prd;
+
+/// Share of a criterion's words a note must contain to count as its verification
+pub const DEFAULT_MATCH_PERCENT: u8 = 50;
+
+/// A `Verified: ...` line, also as a list item, in bold or with a note before the colon
+static VERIFIED_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)^[\s\-*]*verified\b([^:]{0,20}):[\s*]*(.+)$").expect("valid regex"));
+
+/// Words that say nothing about what was checked
+const STOP_WORDS: &[&str] = &[
+    "a", "all", "an", "and", "are", "as", "at", "be", "by", "can", "each", "for", "from", "has", "have", "in", "is",
+    "it", "its", "must", "of", "on", "or", "should", "that", "the", "then", "this", "to", "when", "with",
+];
+
+/// The distinct words of `text` that carry meaning, in a form where "passes" and
+/// "passed" meet
+pub fn words(text: &str) -> BTreeSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.len() > 1 && !STOP_WORDS.contains(word))
+        .map(|word| {
+            ["ing", "ed", "es", "s"]
+                .iter()
+                .find_map(|suffix| word.strip_suffix(suffix).filter(|stem| stem.len() >= 3))
+                .unwrap_or(word)
+                .to_string()
+        })
+        .collect()
+}
+
+/// Share of the criterion's words found in the note (1.0 for a criterion without any)
+pub fn overlap(criterion: &str, note: &str) -> f64 {
+    let wanted = words(criterion);
+    if wanted.is_empty() {
+        return 1.0;
+    }
+    let found = words(note);
+    wanted.intersection(&found).count() as f64 / wanted.len() as f64
+}
+
+/// Whether `text` contains `id` as a whole word (`US-3` isn't found in `US-31`)
+fn names(text: &str, id: &str) -> bool {
+    text.match_indices(id).any(|(at, _)| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + id.len()..].chars().next();
+        !before.is_some_and(|c| c.is_alphanumeric() || c == '-') && !after.is_some_and(|c| c.is_alphanumeric() || c == '-')
+    })
+}
+
+/// The `Verified:` notes in progress.txt, by the story they are filed under
+/// A note keeps what came before its colon (`Verified (AC-4): ...` → `(AC-4) ...`).
+pub fn verified_notes(progress: &str, story_ids: &[&str]) -> HashMap<String, Vec<String>> {
+    let mut notes: HashMap<String, Vec<String>> = HashMap::new();
+    let mut entry_story: Option<&str> = None;
+    for line in progress.lines() {
+        if line.starts_with("## ") {
+            entry_story = story_ids.iter().copied().find(|id| names(line, id));
+            continue;
+        }
+        let Some(caps) = VERIFIED_LINE.captures(line) else {
+            continue;
+        };
+        let note = match caps[1].trim() {
+            "" => caps[2].trim().to_string(),
+            label => format!("{} {}", label, caps[2].trim()),
+        };
+        let story = story_ids.iter().copied().find(|id| names(line, id)).or(entry_story);
+        if let Some(story) = story {
+            notes.entry(story.to_string()).or_default().push(note);
+        }
+    }
+    notes
+}
+
+/// Passed criteria (by index) without a matching `Verified:` note, for each story that
+/// has any; `threshold` is the share of a criterion's words a note must contain
+pub fn unverified_criteria(prd: &Prd, progress: &str, threshold: f64) -> HashMap<String, BTreeSet<usize>> {
+    let ids: Vec<&str> = prd.user_stories.iter().map(|story| story.id.as_str()).collect();
+    let notes = verified_notes(progress, &ids);
+    let mut unverified = HashMap::new();
+    for story in &prd.user_stories {
+        let story_notes = notes.get(&story.id).map_or(&[][..], Vec::as_slice);
+        let missing: BTreeSet<usize> = story
+            .acceptance_criteria
+            .iter()
+            .enumerate()
+            .filter(|(_, criterion)| criterion.passes)
+            .filter(|(_, criterion)| {
+                !story_notes.iter().any(|note| {
+                    criterion.id.as_deref().is_some_and(|id| names(note, id))
+                        || overlap(&criterion.description, note) >= threshold
+                })
+            })
+            .map(|(index, _)| index)
+            .collect();
+        if !missing.is_empty() {
+            unverified.insert(story.id.clone(), missing);
+        }
+    }
+    unverified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRESS: &str = "\
+## Codebase Patterns
+- Verified: nothing here belongs to a story
+
+## 2026-10-16 09:12 - US-3
+- Added the login form and its validation
+- Files changed: src/login.tsx, src/api/session.ts
+- Verified: login form rejects an empty password — `npm test login` 12 passed
+- **Verified:** session cookie is set after sign-in (checked in devtools)
+- verified (AC-4): ran the e2e suite
+---
+## 2026-10-16 10:40 - US-31
+- Verified: error toast appears when the API returns 500
+---
+- Verified (US-3): redirects to the dashboard once logged in
+";
+
+    fn prd() -> Prd {
+        serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": [
+                {"id": "US-3", "title": "Login", "description": "", "priority": 1, "passes": true, "notes": "",
+                 "acceptanceCriteria": [
+                    {"id": "AC-1", "description": "Login form rejects empty passwords", "passes": true},
+                    {"id": "AC-2", "description": "A session cookie is set after signing in", "passes": true},
+                    {"id": "AC-3", "description": "Redirect to the dashboard after login", "passes": true},
+                    {"id": "AC-4", "description": "End-to-end tests pass", "passes": true},
+                    {"id": "AC-5", "description": "Password reset email is sent", "passes": true},
+                    {"id": "AC-6", "description": "Remember me checkbox keeps the session", "passes": false}]},
+                {"id": "US-31", "title": "Errors", "description": "", "priority": 2, "passes": false, "notes": "",
+                 "acceptanceCriteria": ["Error toast shows when the API fails", "Retry button resends the request"]}]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn notes_are_filed_under_their_story() {
+        let notes = verified_notes(PROGRESS, &["US-3", "US-31"]);
+        assert_eq!(notes["US-3"].len(), 4);
+        assert_eq!(notes["US-3"][2], "(AC-4) ran the e2e suite");
+        assert_eq!(notes["US-3"][3], "(US-3) redirects to the dashboard once logged in");
+        assert_eq!(notes["US-31"], ["error toast appears when the API returns 500"]);
+    }
+
+    #[test]
+    fn passed_criteria_without_a_matching_note_are_unverified() {
+        assert!(overlap("Login form rejects empty passwords", "login form rejects an empty password") >= 0.99);
+        assert!(overlap("Password reset email is sent", "session cookie is set after sign-in") < 0.5);
+
+        let unverified = unverified_criteria(&prd(), PROGRESS, 0.5);
+        // AC-4 is named by id; AC-5 has no note; AC-6 hasn't passed; US-31 has no passed criteria
+        assert_eq!(unverified.get("US-3"), Some(&BTreeSet::from([4])));
+        assert!(!unverified.contains_key("US-31"));
+
+        // A stricter threshold also flags the loosely worded redirect note
+        let strict = unverified_criteria(&prd(), PROGRESS, 0.9);
+        assert_eq!(strict.get("US-3"), Some(&BTreeSet::from([2, 4])));
+        // Without any notes every passed criterion is unverified
+        assert_eq!(unverified_criteria(&prd(), "", 0.5)["US-3"].len(), 5);
+    }
+}
@@ -0,0 +1,56 @@
+//! Best-effort desktop notifications (notify-send on Linux, osascript on macOS) and
+//! opening links in the default browser (xdg-open / open)
+
+use std::process::{Command, Stdio};
+
+/// Show a desktop notification; silently does nothing if no notifier is available
+pub fn notify(title: &str, body: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut command = Command::new("osascript");
+        command
+            .arg("-e")
+            .arg(format!("display notification {} with title {}", quote(body), quote(title)));
+        command
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command.arg(title).arg(body);
+        command
+    } else {
+        return;
+    };
+
+    let spawned = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    // Reap the notifier in the background so it never blocks the UI
+    if let Ok(mut child) = spawned {
+        std::thread::spawn(move || {
+            let _ = child.wait();
+        });
+    }
+}
+
+/// Open `url` with the desktop's handler (xdg-open on Linux, open on macOS)
+pub fn open_url(url: &str) -> std::io::Result<()> {
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(unix) {
+        "xdg-open"
+    } else {
+        return Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "no opener on this platform"));
+    };
+    let mut child = Command::new(program)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    // Reaped in the background like the notifier: a browser may take a while to return
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
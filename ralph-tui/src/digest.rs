@@ -0,0 +1,437 @@
+//! `ralph-tui digest`: what the agents got done across all tasks in a time window
+//!
+//! Everything comes from files ralph-tui already keeps in each task directory:
+//! completed stories from `.ralph-session.json`, iterations and agent time from
+//! `.ralph-iterations.log`, story titles and criteria evidence from prd.json and
+//! open blockers from progress.txt. A record counts when its timestamp is at or
+//! after the window start.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::app::{SessionState, ITERATION_LOG_FILE};
+use crate::events::TimelineEntry;
+use crate::prd::{open_blockers, Prd, UserStory};
+
+/// Report format for `ralph-tui digest --format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DigestFormat {
+    #[default]
+    Markdown,
+    Json,
+}
+
+impl DigestFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "md" | "markdown" => Some(DigestFormat::Markdown),
+            "json" => Some(DigestFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a length of time like `30m`, `24h`, `7d` or `2w` into seconds
+/// Used for digest windows and --max-runtime.
+pub fn parse_duration(value: &str) -> Option<u64> {
+    let unit = value.chars().last()?;
+    let count: u64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    let unit_secs = match unit {
+        'm' => 60,
+        'h' => 3600,
+        'd' => 86_400,
+        'w' => 7 * 86_400,
+        _ => return None,
+    };
+    count.checked_mul(unit_secs).filter(|secs| *secs > 0)
+}
+
+/// One line of `.ralph-iterations.log`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IterationRecord {
+    /// Unix timestamp in seconds at which the iteration ended
+    pub timestamp: u64,
+    /// Agent run time (absent in lines written before durations were logged)
+    pub duration_secs: Option<u64>,
+}
+
+/// Parse an iteration log line: `<timestamp> iteration=N ... duration_secs=S ...`
+/// Other lines in the log (the TUI's `tui_edit` journal) are skipped.
+pub fn parse_iteration_record(line: &str) -> Option<IterationRecord> {
+    let mut fields = line.split_whitespace();
+    let timestamp = fields.next()?.parse().ok()?;
+    fields.next()?.strip_prefix("iteration=")?;
+    let duration_secs = fields
+        .find_map(|field| field.strip_prefix("duration_secs="))
+        .and_then(|secs| secs.parse().ok());
+    Some(IterationRecord { timestamp, duration_secs })
+}
+
+/// Story that flipped to passing inside the window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletedStory {
+    pub id: String,
+    pub title: String,
+    pub completed_at: u64,
+    /// Evidence recorded on its passed criteria (v2.0 criteria only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub evidence: Vec<CriterionEvidence>,
+}
+
+/// A passed criterion's evidence, as the agent wrote it in prd.json
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CriterionEvidence {
+    pub criterion: String,
+    pub evidence: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified_at: Option<String>,
+}
+
+/// Activity for one task inside the window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskDigest {
+    pub task: String,
+    pub path: PathBuf,
+    pub archived: bool,
+    pub stories_completed: Vec<CompletedStory>,
+    pub iterations: u32,
+    pub agent_secs: u64,
+    /// Open BLOCKER:/QUESTION: lines (active tasks only)
+    pub blockers: Vec<String>,
+}
+
+impl TaskDigest {
+    /// Whether there is anything to report for this task
+    pub fn has_activity(&self) -> bool {
+        !self.stories_completed.is_empty() || self.iterations > 0 || !self.blockers.is_empty()
+    }
+}
+
+/// Digest of every task with activity between `since` and `until` (Unix seconds)
+#[derive(Debug, Clone, Serialize)]
+pub struct Digest {
+    pub since: u64,
+    pub until: u64,
+    pub tasks: Vec<TaskDigest>,
+}
+
+/// Summarize one task directory's activity since `since`
+pub fn task_digest(task_dir: &Path, archived: bool, since: u64) -> TaskDigest {
+    let prd = Prd::load(&task_dir.join("prd.json")).ok();
+    let session = SessionState::load(task_dir);
+
+    let mut stories_completed: Vec<CompletedStory> = session
+        .story_completions
+        .iter()
+        .filter(|(_, completion)| completion.completed_at >= since)
+        .map(|(id, completion)| {
+            let story = prd.as_ref().and_then(|prd| prd.user_stories.iter().find(|s| &s.id == id));
+            CompletedStory {
+                id: id.clone(),
+                title: story.map(|s| s.title.clone()).unwrap_or_default(),
+                completed_at: completion.completed_at,
+                evidence: story.map(criterion_evidence).unwrap_or_default(),
+            }
+        })
+        .collect();
+    stories_completed.sort_by(|a, b| (a.completed_at, &a.id).cmp(&(b.completed_at, &b.id)));
+
+    let records: Vec<IterationRecord> = std::fs::read_to_string(task_dir.join(ITERATION_LOG_FILE))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(parse_iteration_record)
+        .filter(|record| record.timestamp >= since)
+        .collect();
+
+    let blockers = if archived {
+        Vec::new()
+    } else {
+        std::fs::read_to_string(task_dir.join("progress.txt"))
+            .map(|progress| {
+                open_blockers(&progress)
+                    .into_iter()
+                    .map(|b| format!("{}: {}", b.label, b.text))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    TaskDigest {
+        task: task_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| task_dir.display().to_string()),
+        path: task_dir.to_path_buf(),
+        archived,
+        stories_completed,
+        iterations: records.len() as u32,
+        agent_secs: records.iter().filter_map(|r| r.duration_secs).sum(),
+        blockers,
+    }
+}
+
+/// Evidence on a story's passed criteria, in criteria order (also in report.json)
+pub fn criterion_evidence(story: &UserStory) -> Vec<CriterionEvidence> {
+    story
+        .acceptance_criteria
+        .iter()
+        .enumerate()
+        .filter(|(_, criterion)| criterion.passes)
+        .filter_map(|(i, criterion)| {
+            Some(CriterionEvidence {
+                criterion: criterion.label(i),
+                evidence: criterion.evidence.clone()?,
+                verified_at: criterion.verified_at.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Format seconds as e.g. `1h 05m` or `12m`
+fn format_agent_time(secs: u64) -> String {
+    let (hours, mins) = (secs / 3600, secs / 60 % 60);
+    if hours > 0 {
+        format!("{}h {:02}m", hours, mins)
+    } else {
+        format!("{}m", mins)
+    }
+}
+
+/// Format a Unix timestamp as a UTC date and time (YYYY-MM-DD HH:MM)
+pub(crate) fn format_datetime_utc(unix_secs: u64) -> String {
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let secs_of_day = unix_secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60
+    )
+}
+
+/// Render the digest as Markdown, one section per task
+pub fn render_markdown(digest: &Digest) -> String {
+    let mut out = format!(
+        "# Ralph digest: {} to {} UTC\n",
+        format_datetime_utc(digest.since),
+        format_datetime_utc(digest.until)
+    );
+    if digest.tasks.is_empty() {
+        out.push_str("\nNo task activity in this window.\n");
+        return out;
+    }
+    for task in &digest.tasks {
+        let archived = if task.archived { " (archived)" } else { "" };
+        out.push_str(&format!("\n## {}{}\n\n", task.task, archived));
+        out.push_str(&format!("- Stories completed: {}\n", task.stories_completed.len()));
+        for story in &task.stories_completed {
+            out.push_str(&format!("  - {} {}\n", story.id, story.title));
+            for item in &story.evidence {
+                // Multi-line evidence (command output) is folded onto one line
+                let evidence = item.evidence.lines().map(str::trim).collect::<Vec<_>>().join(" ");
+                match item.verified_at {
+                    Some(ref at) => out.push_str(&format!("    - {}: {} (verified {})\n", item.criterion, evidence, at)),
+                    None => out.push_str(&format!("    - {}: {}\n", item.criterion, evidence)),
+                }
+            }
+        }
+        out.push_str(&format!("- Iterations: {}\n", task.iterations));
+        out.push_str(&format!("- Agent time: {}\n", format_agent_time(task.agent_secs)));
+        if !task.blockers.is_empty() {
+            out.push_str(&format!("- Open blockers: {}\n", task.blockers.len()));
+            for blocker in &task.blockers {
+                out.push_str(&format!("  - {}\n", blocker));
+            }
+        }
+    }
+    out
+}
+
+/// Render a task's timeline as Markdown: one table per iteration, in time order
+/// `entries` must already be sorted (see `events::read_timeline`).
+pub fn render_timeline_markdown(task: &str, entries: &[TimelineEntry]) -> String {
+    let mut out = format!("# Ralph timeline: {}\n", task);
+    if entries.is_empty() {
+        out.push_str("\nNo events recorded.\n");
+        return out;
+    }
+    let mut iteration = None;
+    for entry in entries {
+        if iteration != Some(entry.iteration) {
+            iteration = Some(entry.iteration);
+            match entry.iteration {
+                0 => out.push_str("\n## Before the first iteration\n\n"),
+                n => out.push_str(&format!("\n## Iteration {}\n\n", n)),
+            }
+            out.push_str("| Time (UTC) | Event | Details |\n|---|---|---|\n");
+        }
+        let secs = entry.timestamp / 1000;
+        let details = entry.event.details().replace('|', "\\|").replace('\n', " ");
+        out.push_str(&format!(
+            "| {}:{:02} | {} | {} |\n",
+            format_datetime_utc(secs),
+            secs % 60,
+            entry.event.name(),
+            details
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn parses_window_lengths() {
+        assert_eq!(parse_duration("30m"), Some(1800));
+        assert_eq!(parse_duration("24h"), Some(86_400));
+        assert_eq!(parse_duration("7d"), Some(7 * 86_400));
+        assert_eq!(parse_duration("2w"), Some(14 * 86_400));
+        // Unit is required, and empty or overflowing windows are rejected
+        assert_eq!(parse_duration("7"), None);
+        assert_eq!(parse_duration("d"), None);
+        assert_eq!(parse_duration("0d"), None);
+        assert_eq!(parse_duration("-1d"), None);
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("99999999999999999w"), None);
+    }
+
+    #[test]
+    fn parses_iteration_records_with_and_without_duration() {
+        assert_eq!(
+            parse_iteration_record("1700000000 iteration=3 source=hook state=Completed duration_secs=95 tests_passed=4 tests_failed=0"),
+            Some(IterationRecord { timestamp: 1_700_000_000, duration_secs: Some(95) })
+        );
+        assert_eq!(
+            parse_iteration_record("1700000000 iteration=1 source=output state=NeedsRestart"),
+            Some(IterationRecord { timestamp: 1_700_000_000, duration_secs: None })
+        );
+        assert_eq!(parse_iteration_record(""), None);
+        assert_eq!(parse_iteration_record("1700000000 tui_edit field=\"x\" pointer=/ old=1 new=2 undone=false"), None);
+    }
+
+    #[test]
+    fn window_start_is_inclusive() {
+        let dir = TempDir::new("digest");
+        std::fs::write(
+            dir.join("prd.json"),
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [
+                    {"id": "US-001", "title": "Old", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": true, "notes": ""},
+                    {"id": "US-002", "title": "Edge", "description": "", "priority": 2, "passes": true, "notes": "",
+                     "acceptanceCriteria": [
+                        {"id": "AC-1", "description": "renders", "passes": true, "evidence": "screenshot in docs/edge.png"},
+                        {"id": "AC-2", "description": "no evidence", "passes": true}]}
+                ]}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join(".ralph-session.json"),
+            r#"{"storyCompletions": {"US-001": {"iteration": 1, "completedAt": 999}, "US-002": {"iteration": 2, "completedAt": 1000}}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join(ITERATION_LOG_FILE),
+            "999 iteration=1 source=hook state=Completed duration_secs=60\n\
+             1000 iteration=2 source=hook state=Completed duration_secs=120\n\
+             1500 iteration=3 source=output state=NeedsRestart\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("progress.txt"), "BLOCKER: need API key\n").unwrap();
+
+        let digest = task_digest(&dir, false, 1000);
+        assert_eq!(
+            digest.stories_completed,
+            vec![CompletedStory {
+                id: "US-002".to_string(),
+                title: "Edge".to_string(),
+                completed_at: 1000,
+                evidence: vec![CriterionEvidence {
+                    criterion: "AC-1".to_string(),
+                    evidence: "screenshot in docs/edge.png".to_string(),
+                    verified_at: None,
+                }],
+            }]
+        );
+        assert_eq!((digest.iterations, digest.agent_secs), (2, 120));
+        assert_eq!(digest.blockers, vec!["BLOCKER: need API key".to_string()]);
+
+        // Past every record: nothing but the (still open) blocker
+        let digest = task_digest(&dir, false, 1501);
+        assert!(digest.stories_completed.is_empty());
+        assert_eq!(digest.iterations, 0);
+        assert!(digest.has_activity());
+        // Archived tasks don't report blockers
+        assert!(!task_digest(&dir, true, 1501).has_activity());
+    }
+
+    #[test]
+    fn renders_markdown_report() {
+        let digest = Digest {
+            since: 1_699_395_200,
+            until: 1_700_000_000,
+            tasks: vec![TaskDigest {
+                task: "fix-auth".to_string(),
+                path: PathBuf::from("tasks/fix-auth"),
+                archived: false,
+                stories_completed: vec![CompletedStory {
+                    id: "US-001".to_string(),
+                    title: "Login form".to_string(),
+                    completed_at: 1_699_999_000,
+                    evidence: vec![CriterionEvidence {
+                        criterion: "AC-2".to_string(),
+                        evidence: "cargo test login\n4 passed".to_string(),
+                        verified_at: Some("2023-11-14T20:00:00Z".to_string()),
+                    }],
+                }],
+                iterations: 4,
+                agent_secs: 3900,
+                blockers: vec!["QUESTION: which port?".to_string()],
+            }],
+        };
+        let md = render_markdown(&digest);
+        assert!(md.starts_with("# Ralph digest: 2023-11-07 22:13 to 2023-11-14 22:13 UTC\n"));
+        assert!(md.contains("## fix-auth\n"));
+        assert!(md.contains("- Stories completed: 1\n  - US-001 Login form\n    - AC-2: cargo test login 4 passed (verified 2023-11-14T20:00:00Z)\n"));
+        assert!(md.contains("- Iterations: 4\n- Agent time: 1h 05m\n"));
+        assert!(md.contains("  - QUESTION: which port?\n"));
+    }
+
+    #[test]
+    fn renders_timeline_table_per_iteration() {
+        use crate::events::SessionEvent;
+        let entry = |timestamp, iteration, event| TimelineEntry { timestamp, iteration, event };
+        let entries = vec![
+            entry(1_700_000_000_000, 0, SessionEvent::SessionStart { max_iterations: 5 }),
+            entry(1_700_000_001_000, 1, SessionEvent::IterationStart { iteration: 1 }),
+            entry(
+                1_700_000_042_500,
+                1,
+                SessionEvent::ErrorLine { line: "assert a | b failed".to_string() },
+            ),
+        ];
+        let md = render_timeline_markdown("fix-auth", &entries);
+        assert!(md.starts_with("# Ralph timeline: fix-auth\n"));
+        assert!(md.contains("## Before the first iteration\n\n| Time (UTC) | Event | Details |\n|---|---|---|\n| 2023-11-14 22:13:20 | session_start | up to 5 iterations |\n"));
+        assert!(md.contains("## Iteration 1\n"));
+        assert!(md.contains("| 2023-11-14 22:14:02 | error_line | assert a \\| b failed |\n"));
+        assert!(render_timeline_markdown("empty", &[]).contains("No events recorded."));
+    }
+}
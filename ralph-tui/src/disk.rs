@@ -0,0 +1,129 @@
+//! Free space on the task directory's filesystem
+//!
+//! Overnight runs can fill a disk, and an agent whose writes start failing edits
+//! files in confusing ways. Below `--min-free` ralph-tui warns and stops recording
+//! the event timeline; below `HARD_FLOOR_MB` it holds the loop between iterations
+//! until space is freed.
+
+use std::io;
+use std::path::Path;
+
+/// Default free space (MB) below which the low-space banner shows (--min-free)
+pub const DEFAULT_MIN_FREE_MB: u64 = 500;
+
+/// Free space (MB) below which the loop is paused
+pub const HARD_FLOOR_MB: u64 = 50;
+
+/// Free inodes below which the filesystem counts as low / full
+const LOW_INODES: u64 = 10_000;
+const CRITICAL_INODES: u64 = 1_000;
+
+const MB: u64 = 1024 * 1024;
+
+/// Space left for unprivileged writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskSpace {
+    pub available_bytes: u64,
+    /// None on filesystems that don't report inodes
+    pub available_inodes: Option<u64>,
+}
+
+/// How close the filesystem is to full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiskPressure {
+    #[default]
+    Ok,
+    Low,      // Below --min-free: warn and stop recording the timeline
+    Critical, // Below the hard floor: pause the loop
+}
+
+/// Free space on the filesystem holding `path`
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> io::Result<DiskSpace> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(DiskSpace {
+        available_bytes: (stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64),
+        // Filesystems without inode accounting report zero files in total
+        available_inodes: (stat.f_files > 0).then_some(stat.f_favail as u64),
+    })
+}
+
+/// Free space on the filesystem holding `path` (not measured on this platform)
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> io::Result<DiskSpace> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "free space is not measured on this platform"))
+}
+
+/// Classify `space` against the --min-free threshold (in MB)
+/// The hard floor never rises above `min_free_mb`, so a low --min-free still warns first.
+pub fn assess(space: DiskSpace, min_free_mb: u64) -> DiskPressure {
+    let floor = HARD_FLOOR_MB.min(min_free_mb).saturating_mul(MB);
+    let inodes = space.available_inodes.unwrap_or(u64::MAX);
+    if space.available_bytes < floor || inodes < CRITICAL_INODES {
+        DiskPressure::Critical
+    } else if space.available_bytes < min_free_mb.saturating_mul(MB) || inodes < LOW_INODES {
+        DiskPressure::Low
+    } else {
+        DiskPressure::Ok
+    }
+}
+
+/// One-line description of the space left, e.g. "312 MB free" or "2 GB free, 800 inodes"
+pub fn describe(space: DiskSpace) -> String {
+    let megabytes = space.available_bytes / MB;
+    let mut text = if megabytes >= 10 * 1024 {
+        format!("{} GB free", megabytes / 1024)
+    } else {
+        format!("{} MB free", megabytes)
+    };
+    if let Some(inodes) = space.available_inodes.filter(|inodes| *inodes < LOW_INODES) {
+        text.push_str(&format!(", {} inodes", inodes));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space(megabytes: u64, inodes: Option<u64>) -> DiskSpace {
+        DiskSpace { available_bytes: megabytes * MB, available_inodes: inodes }
+    }
+
+    #[test]
+    fn pressure_follows_the_thresholds() {
+        assert_eq!(assess(space(2048, Some(1_000_000)), 500), DiskPressure::Ok);
+        assert_eq!(assess(space(499, Some(1_000_000)), 500), DiskPressure::Low);
+        assert_eq!(assess(space(49, None), 500), DiskPressure::Critical);
+        assert_eq!(assess(space(50, None), 500), DiskPressure::Low);
+        // Running out of inodes counts too
+        assert_eq!(assess(space(2048, Some(5_000)), 500), DiskPressure::Low);
+        assert_eq!(assess(space(2048, Some(10)), 500), DiskPressure::Critical);
+        // A --min-free below the floor lowers the floor with it
+        assert_eq!(assess(space(30, None), 20), DiskPressure::Ok);
+        assert_eq!(assess(space(10, None), 20), DiskPressure::Critical);
+        assert_eq!(assess(space(0, None), 0), DiskPressure::Ok);
+    }
+
+    #[test]
+    fn describes_space_left() {
+        assert_eq!(describe(space(312, Some(1_000_000))), "312 MB free");
+        assert_eq!(describe(space(20 * 1024, Some(800))), "20 GB free, 800 inodes");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn measures_the_temp_directory() {
+        let space = free_space(&std::env::temp_dir()).unwrap();
+        assert!(space.available_bytes > 0);
+        assert!(free_space(Path::new("/nonexistent/ralph")).is_err());
+    }
+}
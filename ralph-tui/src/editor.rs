@@ -0,0 +1,78 @@
+//! Locating file references in activity and progress text, and building
+//! the command line that opens them in $EDITOR
+
+use std::path::Path;
+
+/// Extract the first file path (with optional ":line" suffix) mentioned in `text`
+/// e.g. "Edit: src/api/handler.rs:42" -> ("src/api/handler.rs", Some(42))
+pub fn extract_file_path(text: &str) -> Option<(String, Option<u32>)> {
+    for token in text.split_whitespace() {
+        let token = token
+            .trim_start_matches(|c: char| "\"'`([{<".contains(c))
+            .trim_end_matches(|c: char| "\"'`)]}>,;.".contains(c));
+        if token.contains("://") {
+            continue;
+        }
+        let mut parts = token.split(':');
+        let path = parts.next().unwrap_or("");
+        let line = parts.next().and_then(|n| n.parse::<u32>().ok());
+
+        // A path has a directory separator or a file extension
+        let file_name = path.rsplit(['/', '\\']).next().unwrap_or("");
+        let has_extension = file_name
+            .rsplit_once('.')
+            .is_some_and(|(stem, ext)| !stem.is_empty() && !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()));
+        if !file_name.is_empty() && (has_extension || path.contains('/')) {
+            return Some((path.to_string(), line));
+        }
+    }
+    None
+}
+
+/// Arguments for opening `path` at `line` in `editor`
+/// vi-style editors take "+line path"; others get "path:line".
+pub fn editor_args(editor: &str, path: &str, line: Option<u32>) -> Vec<String> {
+    let Some(line) = line else {
+        return vec![path.to_string()];
+    };
+    let name = Path::new(editor).file_name().and_then(|n| n.to_str()).unwrap_or(editor);
+    match name {
+        "vi" | "vim" | "nvim" | "nano" | "emacs" | "emacsclient" | "micro" | "kak" => {
+            vec![format!("+{}", line), path.to_string()]
+        }
+        "code" | "codium" | "cursor" => vec!["-g".to_string(), format!("{}:{}", path, line)],
+        _ => vec![format!("{}:{}", path, line)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_file_paths_from_entries() {
+        assert_eq!(
+            extract_file_path("Edit: src/api/handler.rs"),
+            Some(("src/api/handler.rs".to_string(), None))
+        );
+        assert_eq!(
+            extract_file_path("- Fixed the bug in `src/main.rs:42`."),
+            Some(("src/main.rs".to_string(), Some(42)))
+        );
+        assert_eq!(
+            extract_file_path("Updated Cargo.toml and README.md"),
+            Some(("Cargo.toml".to_string(), None))
+        );
+        assert_eq!(extract_file_path("See https://example.com/docs for details"), None);
+        assert_eq!(extract_file_path("Ran the full test suite"), None);
+    }
+
+    #[test]
+    fn editor_args_place_line_numbers_per_editor() {
+        assert_eq!(editor_args("vim", "src/main.rs", Some(12)), vec!["+12", "src/main.rs"]);
+        assert_eq!(editor_args("/usr/bin/nvim", "a.rs", Some(3)), vec!["+3", "a.rs"]);
+        assert_eq!(editor_args("code", "a.rs", Some(3)), vec!["-g", "a.rs:3"]);
+        assert_eq!(editor_args("hx", "a.rs", Some(3)), vec!["a.rs:3"]);
+        assert_eq!(editor_args("vim", "a.rs", None), vec!["a.rs"]);
+    }
+}
@@ -0,0 +1,459 @@
+//! Machine-readable event stream for ralph-tui
+//!
+//! When `--events-socket <path>` is given, ralph-tui binds a Unix domain socket
+//! at that path and writes newline-delimited JSON events to every connected
+//! consumer. Writes never block the UI: each consumer has a bounded outgoing
+//! buffer and events are dropped for consumers that fall behind.
+//!
+//! Every event is also appended to the task's timeline file, which
+//! `ralph-tui annotate` reads back as a per-iteration timeline.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum bytes buffered per consumer before new events are dropped
+const MAX_PENDING_BYTES: usize = 64 * 1024;
+
+/// Timeline of the task's session events, one JSON object per line (in the task directory)
+pub const TIMELINE_FILE: &str = ".ralph-timeline.jsonl";
+
+/// Session event published on the events socket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SessionEvent {
+    SessionStart { max_iterations: u32 },
+    IterationStart { iteration: u32 },
+    IterationEnd { iteration: u32, source: String },
+    StoryPassed { story_id: String },
+    CriterionPassed { story_id: String, criterion: usize, description: String },
+    Completion { iteration: u32 },
+    Error { message: String },
+    Activity { action: String, target: String }, // Tool use parsed from the agent's output
+    ErrorLine { line: String },                   // Output line matching an error pattern
+    TestRun { framework: String, passed: u32, failed: u32 },
+    Compaction { count: u32 }, // The agent compacted its context (count so far this iteration)
+}
+
+impl SessionEvent {
+    /// Short name used in the timeline (same as the JSON `event` tag)
+    pub fn name(&self) -> &'static str {
+        match self {
+            SessionEvent::SessionStart { .. } => "session_start",
+            SessionEvent::IterationStart { .. } => "iteration_start",
+            SessionEvent::IterationEnd { .. } => "iteration_end",
+            SessionEvent::StoryPassed { .. } => "story_passed",
+            SessionEvent::CriterionPassed { .. } => "criterion_passed",
+            SessionEvent::Completion { .. } => "completion",
+            SessionEvent::Error { .. } => "error",
+            SessionEvent::Activity { .. } => "activity",
+            SessionEvent::ErrorLine { .. } => "error_line",
+            SessionEvent::TestRun { .. } => "test_run",
+            SessionEvent::Compaction { .. } => "compaction",
+        }
+    }
+
+    /// One-line description for the timeline
+    pub fn details(&self) -> String {
+        match self {
+            SessionEvent::SessionStart { max_iterations } => format!("up to {} iterations", max_iterations),
+            SessionEvent::IterationStart { iteration } => format!("iteration {}", iteration),
+            SessionEvent::IterationEnd { iteration, source } => format!("iteration {} ({})", iteration, source),
+            SessionEvent::StoryPassed { story_id } => story_id.clone(),
+            SessionEvent::CriterionPassed { story_id, criterion, description } => {
+                format!("{} #{}: {}", story_id, criterion, description)
+            }
+            SessionEvent::Completion { iteration } => format!("all stories pass after iteration {}", iteration),
+            SessionEvent::Error { message } => message.clone(),
+            SessionEvent::Activity { action, target } => format!("{} {}", action, target),
+            SessionEvent::ErrorLine { line } => line.clone(),
+            SessionEvent::TestRun { framework, passed, failed } => {
+                format!("{}: {} passed, {} failed", framework, passed, failed)
+            }
+            SessionEvent::Compaction { count } => format!("context compacted ({} this iteration)", count),
+        }
+    }
+}
+
+/// Current time as a Unix timestamp in milliseconds
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// One line of the timeline file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    /// Unix timestamp in milliseconds, taken where the event was detected
+    pub timestamp: u64,
+    /// Iteration running when the event was recorded (0 before the first one)
+    /// Renamed in JSON: several events carry an `iteration` field of their own.
+    #[serde(rename = "at_iteration")]
+    pub iteration: u32,
+    #[serde(flatten)]
+    pub event: SessionEvent,
+}
+
+/// Append an entry to the task's timeline file
+pub fn append_timeline(task_dir: &Path, entry: &TimelineEntry) -> io::Result<()> {
+    let mut line = serde_json::to_string(entry).map_err(io::Error::other)?;
+    line.push('\n');
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(task_dir.join(TIMELINE_FILE))?
+        .write_all(line.as_bytes())
+}
+
+/// Read the task's timeline in chronological order (unreadable lines are skipped)
+/// Events are written as they are collected, so a line can be older than the one
+/// before it. The sort is stable: events with the same timestamp keep file order.
+pub fn read_timeline(task_dir: &Path) -> Vec<TimelineEntry> {
+    let content = std::fs::read_to_string(task_dir.join(TIMELINE_FILE)).unwrap_or_default();
+    let mut entries: Vec<TimelineEntry> = content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    entries.sort_by_key(|entry| entry.timestamp);
+    entries
+}
+
+/// Envelope written to the socket: the event plus timestamp and task metadata
+#[derive(Debug, Serialize)]
+struct EventEnvelope<'a> {
+    /// Unix timestamp in milliseconds
+    timestamp: u64,
+    task_dir: &'a str,
+    session_id: &'a str,
+    #[serde(flatten)]
+    event: &'a SessionEvent,
+}
+
+/// A connected consumer and the bytes not yet written to it
+#[cfg(unix)]
+struct Consumer {
+    stream: UnixStream,
+    pending: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl Consumer {
+    fn new(stream: UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            stream,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Write as much pending data as the socket accepts without blocking
+    /// Returns false if the consumer disconnected.
+    fn flush_pending(&mut self) -> bool {
+        while !self.pending.is_empty() {
+            match self.stream.write(&self.pending) {
+                Ok(0) => return false,
+                Ok(n) => {
+                    self.pending.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+}
+
+/// Publishes session events to consumers connected to a Unix domain socket
+pub struct EventSink {
+    #[cfg(unix)]
+    listener: Option<UnixListener>,
+    #[cfg(unix)]
+    consumers: Vec<Consumer>,
+    socket_path: Option<PathBuf>,
+    task_dir: String,
+    session_id: String,
+    /// Number of events dropped because a consumer was too slow
+    dropped: u64,
+}
+
+impl EventSink {
+    /// Bind the events socket at `path` (replacing a stale socket file)
+    /// Anything else at `path`, including a socket another process is still
+    /// listening on, is left alone and reported as an error.
+    #[cfg(unix)]
+    pub fn bind(path: &Path, task_dir: &Path, session_id: &str) -> io::Result<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a socket", path.display()),
+                ));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another process", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener: Some(listener),
+            consumers: Vec::new(),
+            socket_path: Some(path.to_path_buf()),
+            task_dir: task_dir.display().to_string(),
+            session_id: session_id.to_string(),
+            dropped: 0,
+        })
+    }
+
+    /// Unix domain sockets are not available on this platform
+    #[cfg(not(unix))]
+    pub fn bind(_path: &Path, _task_dir: &Path, _session_id: &str) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "--events-socket requires Unix domain sockets",
+        ))
+    }
+
+    /// Sink without a listener; consumers are attached directly (used by tests)
+    #[cfg(all(unix, test))]
+    fn detached(task_dir: &str, session_id: &str) -> Self {
+        Self {
+            listener: None,
+            consumers: Vec::new(),
+            socket_path: None,
+            task_dir: task_dir.to_string(),
+            session_id: session_id.to_string(),
+            dropped: 0,
+        }
+    }
+
+    /// Attach an already-connected stream as a consumer
+    #[cfg(unix)]
+    fn add_consumer(&mut self, stream: UnixStream) -> io::Result<()> {
+        self.consumers.push(Consumer::new(stream)?);
+        Ok(())
+    }
+
+    /// Accept any consumers waiting on the listener (non-blocking)
+    #[cfg(unix)]
+    fn accept_pending(&mut self) {
+        let mut accepted = Vec::new();
+        if let Some(ref listener) = self.listener {
+            while let Ok((stream, _)) = listener.accept() {
+                accepted.push(stream);
+            }
+        }
+        for stream in accepted {
+            let _ = self.add_consumer(stream);
+        }
+    }
+
+    /// Serialize an event as a single JSON line
+    fn encode(&self, timestamp: u64, event: &SessionEvent) -> Option<Vec<u8>> {
+        let envelope = EventEnvelope {
+            timestamp,
+            task_dir: &self.task_dir,
+            session_id: &self.session_id,
+            event,
+        };
+        let mut line = serde_json::to_vec(&envelope).ok()?;
+        line.push(b'\n');
+        Some(line)
+    }
+
+    /// Publish an event to all consumers without blocking
+    /// Consumers whose buffer is full miss the event; disconnected consumers are removed.
+    #[cfg(unix)]
+    pub fn emit(&mut self, event: &SessionEvent) {
+        self.emit_at(now_millis(), event);
+    }
+
+    /// Publish an event detected at `timestamp` (Unix milliseconds)
+    #[cfg(unix)]
+    pub fn emit_at(&mut self, timestamp: u64, event: &SessionEvent) {
+        self.accept_pending();
+        let Some(line) = self.encode(timestamp, event) else {
+            return;
+        };
+
+        let mut dropped = 0;
+        self.consumers.retain_mut(|consumer| {
+            if consumer.pending.len() + line.len() > MAX_PENDING_BYTES {
+                dropped += 1;
+            } else {
+                consumer.pending.extend_from_slice(&line);
+            }
+            consumer.flush_pending()
+        });
+        self.dropped += dropped;
+    }
+
+    #[cfg(not(unix))]
+    pub fn emit(&mut self, event: &SessionEvent) {
+        self.emit_at(now_millis(), event);
+    }
+
+    #[cfg(not(unix))]
+    pub fn emit_at(&mut self, timestamp: u64, event: &SessionEvent) {
+        let _ = self.encode(timestamp, event);
+    }
+
+    /// Number of events dropped for slow consumers
+    #[allow(dead_code)]
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl Drop for EventSink {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.socket_path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(all(unix, test))]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn writes_newline_delimited_json_with_metadata() {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        let mut sink = EventSink::detached("tasks/demo", "RL-00001");
+        sink.add_consumer(ours).unwrap();
+
+        sink.emit(&SessionEvent::IterationStart { iteration: 3 });
+        sink.emit(&SessionEvent::StoryPassed { story_id: "US-002".to_string() });
+
+        let mut reader = BufReader::new(theirs);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["event"], "iteration_start");
+        assert_eq!(value["iteration"], 3);
+        assert_eq!(value["task_dir"], "tasks/demo");
+        assert_eq!(value["session_id"], "RL-00001");
+        assert!(value["timestamp"].as_u64().unwrap() > 0);
+
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["event"], "story_passed");
+        assert_eq!(value["story_id"], "US-002");
+    }
+
+    #[test]
+    fn drops_events_for_slow_consumer_without_blocking() {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        let mut sink = EventSink::detached("tasks/demo", "RL-00001");
+        sink.add_consumer(ours).unwrap();
+
+        // Never read from `theirs`: the socket buffer and the pending buffer fill up
+        let message = "x".repeat(1024);
+        for _ in 0..2000 {
+            sink.emit(&SessionEvent::Error { message: message.clone() });
+        }
+
+        assert!(sink.dropped_count() > 0);
+        assert!(sink.consumers[0].pending.len() <= MAX_PENDING_BYTES);
+        drop(theirs);
+    }
+
+    #[test]
+    fn removes_disconnected_consumers() {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        let mut sink = EventSink::detached("tasks/demo", "RL-00001");
+        sink.add_consumer(ours).unwrap();
+        drop(theirs);
+
+        sink.emit(&SessionEvent::Completion { iteration: 1 });
+        assert!(sink.consumers.is_empty());
+    }
+
+    #[test]
+    fn accepts_consumers_on_bound_socket() {
+        let dir = TempDir::new("events");
+        let socket_path = dir.join("events.sock");
+
+        let mut sink = EventSink::bind(&socket_path, Path::new("tasks/demo"), "RL-00001").unwrap();
+        let client = UnixStream::connect(&socket_path).unwrap();
+        sink.emit(&SessionEvent::SessionStart { max_iterations: 10 });
+
+        let mut line = String::new();
+        BufReader::new(client).read_line(&mut line).unwrap();
+        assert!(line.contains("\"event\":\"session_start\""));
+
+        drop(sink);
+        assert!(!socket_path.exists());
+    }
+
+    #[test]
+    fn bind_replaces_only_stale_sockets() {
+        let dir = TempDir::new("events-bind");
+
+        // A regular file is never removed
+        let file_path = dir.join("notes.txt");
+        std::fs::write(&file_path, "keep me").unwrap();
+        let err = EventSink::bind(&file_path, Path::new("tasks/demo"), "RL-00001").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "keep me");
+
+        // A socket someone is still listening on is left alone
+        let live_path = dir.join("live.sock");
+        let live = UnixListener::bind(&live_path).unwrap();
+        let err = EventSink::bind(&live_path, Path::new("tasks/demo"), "RL-00001").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        drop(live);
+
+        // Once its listener is gone the socket is stale and gets replaced
+        let sink = EventSink::bind(&live_path, Path::new("tasks/demo"), "RL-00001").unwrap();
+        drop(sink);
+    }
+
+    #[test]
+    fn timeline_is_chronological_and_stable_for_equal_timestamps() {
+        let dir = TempDir::new("timeline");
+        let entry = |timestamp, event| TimelineEntry { timestamp, iteration: 2, event };
+        let activity = |target: &str| SessionEvent::Activity { action: "Edit".to_string(), target: target.to_string() };
+        // Output events are collected after the fact, so the file isn't in time order
+        let written = [
+            entry(5_000, SessionEvent::IterationEnd { iteration: 2, source: "control-file".to_string() }),
+            entry(3_000, activity("a.rs")),
+            entry(3_000, activity("b.rs")),
+            entry(3_400, SessionEvent::ErrorLine { line: "error[E0308]".to_string() }),
+            entry(3_000, activity("c.rs")),
+            entry(1_000, SessionEvent::IterationStart { iteration: 2 }),
+        ];
+        for e in &written {
+            append_timeline(&dir, e).unwrap();
+        }
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join(TIMELINE_FILE))
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+
+        let details: Vec<String> = read_timeline(&dir).iter().map(|e| e.event.details()).collect();
+        assert_eq!(
+            details,
+            ["iteration 2", "Edit a.rs", "Edit b.rs", "Edit c.rs", "error[E0308]", "iteration 2 (control-file)"]
+        );
+        // Reading again gives the same order
+        assert_eq!(read_timeline(&dir), read_timeline(&dir));
+    }
+}
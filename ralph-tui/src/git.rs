@@ -0,0 +1,693 @@
+//! Branch drift tracking against the PRD's `mergeTarget`
+//!
+//! Long-running loops drift away from the branch they will eventually merge
+//! into. `DriftMonitor` periodically counts how far HEAD is ahead of / behind
+//! the merge target on a background thread, and `SyncJob` merges or rebases
+//! onto it, also off the render thread. `commit_file` records ralph-tui's own
+//! prd.json edits when the PRD asks for it (`autoCommitTuiEdits`), and iteration
+//! markers let a bad iteration be rolled back (`snapshotEachIteration`).
+//! Once the task is done, `squash_commits` folds the agent's commits on the branch
+//! into one before it is merged.
+//! All of these need a repository: `repo_root` is checked once at startup and
+//! they are turned off outside one.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the drift monitor re-runs `git rev-list`
+const DRIFT_CHECK_INTERVAL: Duration = Duration::from_secs(180);
+
+/// Commits HEAD has that the merge target doesn't (ahead), and vice versa (behind)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BranchDrift {
+    pub ahead: u32,
+    pub behind: u32,
+}
+
+/// Parse the output of `git rev-list --left-right --count <target>...HEAD`
+/// The left count is commits only on the target (behind), the right count commits only on HEAD (ahead).
+fn parse_rev_list_counts(output: &str) -> Option<BranchDrift> {
+    let mut counts = output.split_whitespace().map(|n| n.parse::<u32>().ok());
+    let behind = counts.next()??;
+    let ahead = counts.next()??;
+    Some(BranchDrift { ahead, behind })
+}
+
+/// Run git in `repo` and return trimmed stdout, or stderr as the error message
+fn git(repo: &Path, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("git").args(args).current_dir(repo).output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()))
+    }
+}
+
+/// Top-level directory of the repository containing `dir` (None outside one, or without git)
+pub fn repo_root(dir: &Path) -> Option<PathBuf> {
+    git(dir, &["rev-parse", "--show-toplevel"])
+        .ok()
+        .filter(|root| !root.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Name of the branch checked out in `repo` ("HEAD" when detached)
+pub fn current_branch(repo: &Path) -> io::Result<String> {
+    git(repo, &["rev-parse", "--abbrev-ref", "HEAD"])
+}
+
+/// Check out `branch`, creating it from HEAD if it doesn't exist yet
+pub fn checkout_branch(repo: &Path, branch: &str) -> io::Result<()> {
+    if git(repo, &["rev-parse", "--verify", "--quiet", &format!("refs/heads/{}", branch)]).is_ok() {
+        git(repo, &["checkout", "--quiet", branch])?;
+    } else {
+        git(repo, &["checkout", "--quiet", "-b", branch])?;
+    }
+    Ok(())
+}
+
+/// Whether `rev` (a branch, remote branch, tag or commit) names a commit in `repo`
+pub fn rev_exists(repo: &Path, rev: &str) -> bool {
+    git(repo, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)]).is_ok()
+}
+
+/// Why git would refuse `name` as a branch name (the `git check-ref-format` rules), or None
+pub fn branch_name_problem(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        Some("it is empty")
+    } else if name.starts_with('-') {
+        Some("it starts with -")
+    } else if name.contains(' ') {
+        Some("it contains a space")
+    } else if name.chars().any(|c| c.is_control()) {
+        Some("it contains a control character")
+    } else if name.contains("..") {
+        Some("it contains ..")
+    } else if name.contains(['~', '^', ':', '?', '*', '[', '\\']) {
+        Some("it contains one of ~ ^ : ? * [ \\")
+    } else if name == "@" || name.contains("@{") {
+        Some("it is @ or contains @{")
+    } else if name.split('/').any(|part| part.is_empty() || part.starts_with('.') || part.ends_with(".lock")) {
+        Some("a /-separated part is empty, starts with . or ends with .lock")
+    } else if name.ends_with('.') {
+        Some("it ends with .")
+    } else {
+        None
+    }
+}
+
+/// `name` made into a valid branch name: "feat branch!" becomes "feat-branch"
+/// Anything but letters, digits and `. _ / -` turns into `-`, repeated separators
+/// collapse into one, and each /-separated part loses leading and trailing `.`/`-`.
+pub fn normalize_branch_name(name: &str) -> String {
+    let mut collapsed = String::new();
+    for c in name.trim().chars() {
+        let c = if c.is_alphanumeric() || matches!(c, '.' | '_' | '/' | '-') { c } else { '-' };
+        if matches!(c, '.' | '/' | '-') && collapsed.ends_with(c) {
+            continue;
+        }
+        collapsed.push(c);
+    }
+    collapsed
+        .split('/')
+        .map(|part| {
+            let part = part.trim_matches(['.', '-']);
+            part.strip_suffix(".lock").unwrap_or(part).trim_end_matches(['.', '-'])
+        })
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Count how far HEAD has diverged from `target`
+fn branch_drift(repo: &Path, target: &str) -> io::Result<BranchDrift> {
+    let range = format!("{}...HEAD", target);
+    let output = git(repo, &["rev-list", "--left-right", "--count", &range])?;
+    parse_rev_list_counts(&output).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected git rev-list output: {}", output),
+        )
+    })
+}
+
+/// What `commit_file` did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitOutcome {
+    Committed(String),  // Short hash of the new commit
+    OtherChangesStaged, // Skipped: other files are staged (probably the agent's in-flight commit)
+    Unchanged,          // Skipped: the file matches HEAD
+}
+
+/// Stage just `file` and commit it with `message`
+/// Nothing is staged or committed when other changes are already staged.
+pub fn commit_file(repo: &Path, file: &Path, message: &str) -> io::Result<CommitOutcome> {
+    let root = PathBuf::from(git(repo, &["rev-parse", "--show-toplevel"])?);
+    let file = std::fs::canonicalize(file)?;
+    let relative = file
+        .strip_prefix(std::fs::canonicalize(&root)?)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("{} is outside the repository", file.display())))?
+        .to_string_lossy()
+        .to_string();
+
+    let staged = git(&root, &["diff", "--cached", "--name-only"])?;
+    if staged.lines().any(|path| path != relative) {
+        return Ok(CommitOutcome::OtherChangesStaged);
+    }
+    git(&root, &["add", "--", &relative])?;
+    // diff --quiet exits 1 (an Err here) when there is something to commit
+    if git(&root, &["diff", "--cached", "--quiet", "--", &relative]).is_ok() {
+        return Ok(CommitOutcome::Unchanged);
+    }
+    git(&root, &["commit", "--quiet", "-m", message, "--", &relative])?;
+    Ok(CommitOutcome::Committed(git(&root, &["rev-parse", "--short", "HEAD"])?))
+}
+
+/// ralph-tui's own files in task directories, as .gitignore patterns
+/// The agent commits with `git add -A`, which would otherwise pick them up.
+pub const STATE_FILE_PATTERNS: [&str; 7] = [
+    ".ralph-signal*",
+    ".ralph-iterations.log",
+    ".ralph-session.json",
+    ".ralph-timeline.jsonl",
+    ".ralph-lock",
+    ".prd-backups/",
+    "report.json",
+];
+
+/// Add the STATE_FILE_PATTERNS missing from the task directory's own .gitignore
+/// The repository's top-level .gitignore is left alone. Returns the patterns added
+/// (none when they were all there already).
+pub fn ignore_state_files(task_dir: &Path) -> io::Result<Vec<&'static str>> {
+    let path = task_dir.join(".gitignore");
+    let existing = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let missing: Vec<&'static str> = STATE_FILE_PATTERNS
+        .into_iter()
+        .filter(|pattern| !existing.lines().any(|line| line.trim() == *pattern))
+        .collect();
+    if missing.is_empty() {
+        return Ok(missing);
+    }
+    let mut addition = String::new();
+    if !existing.is_empty() && !existing.ends_with('\n') {
+        addition.push('\n');
+    }
+    addition.push_str("# ralph-tui state files\n");
+    for pattern in &missing {
+        addition.push_str(pattern);
+        addition.push('\n');
+    }
+    std::fs::write(&path, existing + &addition)?;
+    Ok(missing)
+}
+
+/// Prefix of the tags marking where iterations started
+const MARKER_TAG_PREFIX: &str = "ralph/iter-";
+
+/// Where the tree stood when an iteration started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IterationMarker {
+    pub iteration: u32,
+    /// Tag keeping the marker alive (`ralph/iter-N-start`)
+    pub tag: String,
+    /// HEAD at the start
+    pub head: String,
+    /// `git stash create` commit of the uncommitted changes at the start (None if the tree was clean)
+    pub stash: Option<String>,
+    /// Unix timestamp in seconds
+    pub at: u64,
+}
+
+/// Record the tree's state as iteration `iteration` starts, without touching it
+/// Uncommitted changes are captured with `git stash create`; the tag points at that
+/// commit (or HEAD when the tree is clean) so it survives garbage collection.
+pub fn create_iteration_marker(repo: &Path, iteration: u32) -> io::Result<IterationMarker> {
+    let head = git(repo, &["rev-parse", "HEAD"])?;
+    let stash = git(repo, &["stash", "create", &format!("ralph: iteration {} start", iteration)])?;
+    let stash = (!stash.is_empty()).then_some(stash);
+    let tag = format!("{}{}-start", MARKER_TAG_PREFIX, iteration);
+    git(repo, &["tag", "--force", &tag, stash.as_deref().unwrap_or(&head)])?;
+    let at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok(IterationMarker { iteration, tag, head, stash, at })
+}
+
+/// A commit holding the tree as it is now, uncommitted changes included, without touching it
+/// (the `git stash create` commit, or HEAD when the tree is clean)
+pub fn tree_snapshot(repo: &Path) -> io::Result<String> {
+    let stash = git(repo, &["stash", "create", "ralph: iteration base"])?;
+    if stash.is_empty() { git(repo, &["rev-parse", "HEAD"]) } else { Ok(stash) }
+}
+
+/// `git diff --numstat` of the working tree against `base` (commits and uncommitted changes)
+pub fn diff_numstat(repo: &Path, base: &str) -> io::Result<String> {
+    git(repo, &["diff", "--numstat", "--no-renames", base])
+}
+
+/// Unified diff of `path` (relative to the repository root) in the working tree against `base`
+pub fn diff_file(repo: &Path, base: &str, path: &str) -> io::Result<String> {
+    git(repo, &["diff", "--no-color", "--no-renames", base, "--", path])
+}
+
+/// Put the tree back the way it was when `marker` was taken
+/// Runs `git reset --hard` to the marker's HEAD and re-applies the uncommitted changes it
+/// captured. Refuses when commits by anyone other than the configured git user landed since,
+/// since those would be thrown away too. Untracked files created since are left alone.
+pub fn rollback_to_marker(repo: &Path, marker: &IterationMarker) -> io::Result<()> {
+    let me = git(repo, &["config", "user.email"]).unwrap_or_default();
+    let range = format!("{}..HEAD", marker.head);
+    let authors = git(repo, &["log", "--format=%ae", &range])?;
+    let others: BTreeSet<&str> = authors.lines().filter(|author| *author != me).collect();
+    if !others.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "commits by {} since iteration {} started, not rolled back",
+                others.into_iter().collect::<Vec<_>>().join(", "),
+                marker.iteration
+            ),
+        ));
+    }
+    git(repo, &["reset", "--hard", "--quiet", &marker.head])?;
+    if let Some(ref stash) = marker.stash {
+        git(repo, &["stash", "apply", "--quiet", stash])?;
+    }
+    Ok(())
+}
+
+/// Delete every iteration marker tag; returns how many there were
+pub fn remove_iteration_markers(repo: &Path) -> io::Result<usize> {
+    let tags = git(repo, &["tag", "--list", &format!("{}*-start", MARKER_TAG_PREFIX)])?;
+    let tags: Vec<&str> = tags.lines().collect();
+    if !tags.is_empty() {
+        let mut args = vec!["tag", "--delete"];
+        args.extend(&tags);
+        git(repo, &args)?;
+    }
+    Ok(tags.len())
+}
+
+/// The commits on a task branch since it left the merge target, ready to squash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SquashPlan {
+    pub base: String,         // Where the branch left the target (git merge-base)
+    pub head: String,         // HEAD when planned; the squash refuses if it moved
+    pub commits: Vec<String>, // "<short hash> <subject>", oldest first
+}
+
+/// The commits on `branch` since it left `target`
+/// Refuses when `branch` isn't checked out, when changes are staged (they would end
+/// up in the squashed commit), or when commits by anyone other than the configured git
+/// user are among them, since squashing would pass their work off as the agent's.
+pub fn plan_squash(repo: &Path, branch: &str, target: &str) -> io::Result<SquashPlan> {
+    let refuse = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    let current = current_branch(repo)?;
+    if current != branch {
+        return Err(refuse(format!("{} is checked out, not {}", current, branch)));
+    }
+    if git(repo, &["diff", "--cached", "--quiet"]).is_err() {
+        return Err(refuse("changes are staged".to_string()));
+    }
+    let base = git(repo, &["merge-base", "HEAD", target])?;
+    let range = format!("{}..HEAD", base);
+    let me = git(repo, &["config", "user.email"]).unwrap_or_default();
+    let authors = git(repo, &["log", "--format=%ae", &range])?;
+    let others: BTreeSet<&str> = authors.lines().filter(|author| *author != me).collect();
+    if !others.is_empty() {
+        return Err(refuse(format!(
+            "commits by {} are mixed in",
+            others.into_iter().collect::<Vec<_>>().join(", ")
+        )));
+    }
+    let commits = git(repo, &["log", "--reverse", "--format=%h %s", &range])?;
+    Ok(SquashPlan {
+        base,
+        head: git(repo, &["rev-parse", "HEAD"])?,
+        commits: commits.lines().map(str::to_string).collect(),
+    })
+}
+
+/// Replace the planned commits with one commit with `message` (`git reset --soft` to the
+/// base, then commit); returns "<short hash> <subject>" of the new commit
+/// If the commit fails, the branch is put back where it was.
+pub fn squash_commits(repo: &Path, plan: &SquashPlan, message: &str) -> io::Result<String> {
+    if git(repo, &["rev-parse", "HEAD"])? != plan.head {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "HEAD moved since the squash was planned"));
+    }
+    git(repo, &["reset", "--soft", "--quiet", &plan.base])?;
+    if let Err(e) = git(repo, &["commit", "--quiet", "-m", message]) {
+        let _ = git(repo, &["reset", "--soft", "--quiet", &plan.head]);
+        return Err(e);
+    }
+    git(repo, &["log", "-1", "--format=%h %s"])
+}
+
+/// How to bring the merge target's commits into the task branch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SyncStrategy {
+    #[default]
+    Merge,  // git merge <target>
+    Rebase, // git rebase <target>
+}
+
+impl SyncStrategy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "merge" => Some(SyncStrategy::Merge),
+            "rebase" => Some(SyncStrategy::Rebase),
+            _ => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SyncStrategy::Merge => "merge",
+            SyncStrategy::Rebase => "rebase",
+        }
+    }
+}
+
+/// Merge or rebase onto `target`
+/// On conflict the operation is aborted (leaving the tree as it was) and the
+/// conflicting files are listed in the error.
+fn sync_with_target(repo: &Path, target: &str, strategy: SyncStrategy) -> Result<String, String> {
+    let result = match strategy {
+        SyncStrategy::Merge => git(repo, &["merge", "--no-edit", target]),
+        SyncStrategy::Rebase => git(repo, &["rebase", target]),
+    };
+    match result {
+        Ok(output) => Ok(output),
+        Err(e) => {
+            let conflicts = git(repo, &["diff", "--name-only", "--diff-filter=U"]).unwrap_or_default();
+            let _ = git(repo, &[strategy.label(), "--abort"]);
+            let mut message = format!("git {} {} failed: {}", strategy.label(), target, e);
+            if !conflicts.is_empty() {
+                message.push_str("\n\nConflicting files (aborted, tree left unchanged):");
+                for file in conflicts.lines() {
+                    message.push_str("\n  ");
+                    message.push_str(file);
+                }
+            }
+            Err(message)
+        }
+    }
+}
+
+/// Periodically measures branch drift on a background thread
+pub struct DriftMonitor {
+    pub target: String,
+    latest: Arc<Mutex<Option<BranchDrift>>>,
+    refresh: Arc<AtomicBool>,
+}
+
+impl DriftMonitor {
+    /// Start checking `target` against HEAD in `repo` every DRIFT_CHECK_INTERVAL
+    pub fn spawn(repo: PathBuf, target: String) -> Self {
+        let latest = Arc::new(Mutex::new(None));
+        let refresh = Arc::new(AtomicBool::new(true));
+
+        let thread_latest = Arc::clone(&latest);
+        let thread_refresh = Arc::clone(&refresh);
+        let thread_target = target.clone();
+        thread::spawn(move || {
+            let mut last_check: Option<Instant> = None;
+            loop {
+                let due = last_check.is_none_or(|t| t.elapsed() >= DRIFT_CHECK_INTERVAL);
+                if due || thread_refresh.swap(false, Ordering::Relaxed) {
+                    // A failed check (e.g. unknown target) just leaves no badge
+                    let drift = branch_drift(&repo, &thread_target).ok();
+                    if let Ok(mut guard) = thread_latest.lock() {
+                        *guard = drift;
+                    }
+                    last_check = Some(Instant::now());
+                }
+                thread::sleep(Duration::from_millis(500));
+            }
+        });
+
+        Self { target, latest, refresh }
+    }
+
+    /// Most recent drift measurement (None until the first check succeeds)
+    pub fn latest(&self) -> Option<BranchDrift> {
+        self.latest.lock().ok().and_then(|guard| *guard)
+    }
+
+    /// Re-check on the next tick instead of waiting for the interval
+    pub fn request_refresh(&self) {
+        self.refresh.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A merge/rebase running on a background thread
+pub struct SyncJob {
+    pub target: String,
+    pub strategy: SyncStrategy,
+    receiver: Receiver<Result<String, String>>,
+}
+
+impl SyncJob {
+    pub fn start(repo: PathBuf, target: String, strategy: SyncStrategy) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread_target = target.clone();
+        thread::spawn(move || {
+            let _ = sender.send(sync_with_target(&repo, &thread_target, strategy));
+        });
+        Self { target, strategy, receiver }
+    }
+
+    /// Result of the job once it has finished (None while still running)
+    pub fn try_finish(&self) -> Option<Result<String, String>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("sync thread exited unexpectedly".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn parses_left_right_counts() {
+        assert_eq!(
+            parse_rev_list_counts("12\t3\n"),
+            Some(BranchDrift { ahead: 3, behind: 12 })
+        );
+        assert_eq!(parse_rev_list_counts("0 0"), Some(BranchDrift { ahead: 0, behind: 0 }));
+    }
+
+    #[test]
+    fn branch_names_follow_git_ref_rules() {
+        for name in ["ralph/login", "feat-branch", "fix_2.0", "release/v1"] {
+            assert_eq!(branch_name_problem(name), None, "{}", name);
+        }
+        assert_eq!(branch_name_problem("feat branch!"), Some("it contains a space"));
+        assert_eq!(branch_name_problem("-x"), Some("it starts with -"));
+        assert_eq!(branch_name_problem("a..b"), Some("it contains .."));
+        assert_eq!(branch_name_problem("tab\there"), Some("it contains a control character"));
+        assert!(branch_name_problem("").is_some());
+        assert!(branch_name_problem("wip:login").is_some());
+        assert!(branch_name_problem("ralph//login").is_some());
+        assert!(branch_name_problem("ralph/.hidden").is_some());
+        assert!(branch_name_problem("main.lock").is_some());
+        assert!(branch_name_problem("HEAD@{1}").is_some());
+
+        assert_eq!(normalize_branch_name("feat branch!"), "feat-branch");
+        assert_eq!(normalize_branch_name("  -Fix: the  login..flow/ "), "Fix-the-login.flow");
+        assert_eq!(normalize_branch_name("ralph//.hidden.lock"), "ralph/hidden");
+        for name in ["feat branch!", "  -Fix: the  login..flow/ ", "ralph//.hidden.lock", "a~b^c", "x.lock."] {
+            assert_eq!(branch_name_problem(&normalize_branch_name(name)), None, "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_counts() {
+        assert_eq!(parse_rev_list_counts(""), None);
+        assert_eq!(parse_rev_list_counts("7"), None);
+        assert_eq!(parse_rev_list_counts("fatal: bad revision"), None);
+    }
+
+    #[test]
+    fn commits_only_the_edited_file() {
+        let repo = TempDir::new("git-commit");
+        for args in [
+            &["init", "--quiet"][..],
+            &["config", "user.email", "ralph@example.com"],
+            &["config", "user.name", "ralph"],
+        ] {
+            git(&repo, args).unwrap();
+        }
+        let prd = repo.join("prd.json");
+        std::fs::write(&prd, "{}").unwrap();
+        std::fs::write(repo.join("other.txt"), "x").unwrap();
+
+        let CommitOutcome::Committed(hash) = commit_file(&repo, &prd, "ralph-tui: edit (session RL-00001)").unwrap() else {
+            panic!("expected a commit");
+        };
+        assert_eq!(git(&repo, &["log", "-1", "--format=%h %s"]).unwrap(), format!("{} ralph-tui: edit (session RL-00001)", hash));
+        assert_eq!(git(&repo, &["show", "--name-only", "--format=", "HEAD"]).unwrap(), "prd.json");
+        assert_eq!(commit_file(&repo, &prd, "again").unwrap(), CommitOutcome::Unchanged);
+
+        // Someone else's staged work holds the commit back and stays staged alone
+        std::fs::write(&prd, "{\"a\": 1}").unwrap();
+        git(&repo, &["add", "other.txt"]).unwrap();
+        assert_eq!(commit_file(&repo, &prd, "edit").unwrap(), CommitOutcome::OtherChangesStaged);
+        assert_eq!(git(&repo, &["diff", "--cached", "--name-only"]).unwrap(), "other.txt");
+    }
+
+    #[test]
+    fn finds_the_repo_root_or_none_outside_a_repo() {
+        let repo = TempDir::new("git-root");
+        std::fs::create_dir_all(repo.join("tasks/demo")).unwrap();
+        assert_eq!(repo_root(&repo.join("tasks/demo")), None);
+
+        git(&repo, &["init", "--quiet"]).unwrap();
+        let root = repo_root(&repo.join("tasks/demo")).unwrap();
+        assert_eq!(root.canonicalize().unwrap(), repo.canonicalize().unwrap());
+    }
+
+    #[test]
+    fn checks_out_or_creates_the_branch() {
+        let repo = TempDir::new("git-branch");
+        for args in [
+            &["init", "--quiet", "--initial-branch", "main"][..],
+            &["config", "user.email", "ralph@example.com"],
+            &["config", "user.name", "ralph"],
+            &["commit", "--quiet", "--allow-empty", "-m", "root"],
+        ] {
+            git(&repo, args).unwrap();
+        }
+        assert_eq!(current_branch(&repo).unwrap(), "main");
+        checkout_branch(&repo, "ralph/login").unwrap();
+        assert_eq!(current_branch(&repo).unwrap(), "ralph/login");
+        checkout_branch(&repo, "main").unwrap();
+        assert_eq!(current_branch(&repo).unwrap(), "main");
+    }
+
+    #[test]
+    fn parses_sync_strategy() {
+        assert_eq!(SyncStrategy::parse("merge"), Some(SyncStrategy::Merge));
+        assert_eq!(SyncStrategy::parse("rebase"), Some(SyncStrategy::Rebase));
+        assert_eq!(SyncStrategy::parse("squash"), None);
+    }
+
+    #[test]
+    fn rollback_restores_the_tree_at_the_marker() {
+        let repo = TempDir::new("git-marker");
+        for args in [
+            &["init", "--quiet"][..],
+            &["config", "user.email", "ralph@example.com"],
+            &["config", "user.name", "ralph"],
+        ] {
+            git(&repo, args).unwrap();
+        }
+        let file = repo.join("lib.rs");
+        std::fs::write(&file, "v1").unwrap();
+        git(&repo, &["add", "lib.rs"]).unwrap();
+        git(&repo, &["commit", "--quiet", "-m", "v1"]).unwrap();
+        // Uncommitted work at the start is part of the marker
+        std::fs::write(&file, "v1 + wip").unwrap();
+
+        let marker = create_iteration_marker(&repo, 3).unwrap();
+        assert_eq!(marker.tag, "ralph/iter-3-start");
+        assert!(marker.stash.is_some());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "v1 + wip");
+
+        // The iteration commits something bad
+        std::fs::write(&file, "broken").unwrap();
+        git(&repo, &["commit", "--quiet", "-am", "broken"]).unwrap();
+        rollback_to_marker(&repo, &marker).unwrap();
+        assert_eq!(git(&repo, &["rev-parse", "HEAD"]).unwrap(), marker.head);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "v1 + wip");
+
+        // Someone else's commit since the marker blocks the rollback
+        git(&repo, &["commit", "--quiet", "-am", "theirs", "--author", "Pat <pat@example.com>"]).unwrap();
+        let error = rollback_to_marker(&repo, &marker).unwrap_err();
+        assert!(error.to_string().contains("pat@example.com"));
+
+        assert_eq!(remove_iteration_markers(&repo).unwrap(), 1);
+        assert_eq!(git(&repo, &["tag", "--list"]).unwrap(), "");
+    }
+
+    #[test]
+    fn state_files_are_added_to_the_task_gitignore_once() {
+        let repo = TempDir::new("git-ignore");
+        let task_dir = repo.join("tasks/login");
+        std::fs::create_dir_all(&task_dir).unwrap();
+        git(&repo, &["init", "--quiet"]).unwrap();
+        std::fs::write(repo.join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(task_dir.join(".gitignore"), "notes/\n.ralph-lock").unwrap();
+
+        let added = ignore_state_files(&task_dir).unwrap();
+        assert_eq!(added.len(), STATE_FILE_PATTERNS.len() - 1);
+        assert!(!added.contains(&".ralph-lock"));
+        let content = std::fs::read_to_string(task_dir.join(".gitignore")).unwrap();
+        assert!(content.starts_with("notes/\n.ralph-lock\n# ralph-tui state files\n.ralph-signal*\n"), "{}", content);
+        // A second run leaves the file alone, and the repository's .gitignore is never touched
+        assert!(ignore_state_files(&task_dir).unwrap().is_empty());
+        assert_eq!(std::fs::read_to_string(task_dir.join(".gitignore")).unwrap(), content);
+        assert_eq!(std::fs::read_to_string(repo.join(".gitignore")).unwrap(), "target/\n");
+
+        // git now ignores the task directory's state files
+        for file in [".ralph-signal-lane2", ".ralph-session.json", ".prd-backups/prd-1.json", "report.json"] {
+            assert!(git(&repo, &["check-ignore", "--quiet", &format!("tasks/login/{}", file)]).is_ok(), "{}", file);
+        }
+    }
+
+    #[test]
+    fn squashes_the_agent_commits_since_the_target() {
+        let repo = TempDir::new("git-squash");
+        for args in [
+            &["init", "--quiet", "--initial-branch", "main"][..],
+            &["config", "user.email", "ralph@example.com"],
+            &["config", "user.name", "ralph"],
+            &["commit", "--quiet", "--allow-empty", "-m", "root"],
+            &["checkout", "--quiet", "-b", "ralph/login"],
+        ] {
+            git(&repo, args).unwrap();
+        }
+        let file = repo.join("login.rs");
+        for (content, subject) in [("v1", "wip"), ("v2", "fix typo"), ("v3", "add tests")] {
+            std::fs::write(&file, content).unwrap();
+            git(&repo, &["add", "login.rs"]).unwrap();
+            git(&repo, &["commit", "--quiet", "-m", subject]).unwrap();
+        }
+        // The target moving on doesn't pull its commits in
+        git(&repo, &["checkout", "--quiet", "main"]).unwrap();
+        assert!(plan_squash(&repo, "ralph/login", "main").unwrap_err().to_string().contains("main is checked out"));
+        git(&repo, &["commit", "--quiet", "--allow-empty", "-m", "meanwhile on main"]).unwrap();
+        git(&repo, &["checkout", "--quiet", "ralph/login"]).unwrap();
+
+        let plan = plan_squash(&repo, "ralph/login", "main").unwrap();
+        let subjects: Vec<&str> = plan.commits.iter().map(|c| c.split_once(' ').unwrap().1).collect();
+        assert_eq!(subjects, ["wip", "fix typo", "add tests"]);
+        let squashed = squash_commits(&repo, &plan, "Add login flow").unwrap();
+        assert!(squashed.ends_with(" Add login flow"));
+        assert_eq!(git(&repo, &["log", "--format=%s"]).unwrap(), "Add login flow\nroot");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "v3");
+        // A stale plan is refused
+        assert!(squash_commits(&repo, &plan, "again").is_err());
+
+        // Someone else's commit on the branch blocks it
+        git(&repo, &["commit", "--quiet", "--allow-empty", "-m", "theirs", "--author", "Pat <pat@example.com>"]).unwrap();
+        let error = plan_squash(&repo, "ralph/login", "main").unwrap_err();
+        assert!(error.to_string().contains("pat@example.com"));
+    }
+}
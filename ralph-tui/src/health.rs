@@ -0,0 +1,154 @@
+//! The Health view: ralph-tui's own vital signs, for bug reports
+//!
+//! `H` in Ralph mode lists what the UI has been doing: how many times prd.json was
+//! reloaded (and when last), how many reloads failed, which file watcher backend is
+//! watching it, how much the PTY reader has taken in and how fast, how long frames
+//! take to draw, and how much typed input is still queued for the agent. Counting
+//! is always on; it costs two atomic adds per read and a short ring of frame times.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Frames whose draw time is kept for the average and the worst case
+pub const FRAME_SAMPLES: usize = 120;
+
+/// How often the reads-per-second rate is recomputed
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// PTY reader counters, shared with the reader threads
+#[derive(Debug, Clone, Default)]
+pub struct ReaderStats {
+    bytes: Arc<AtomicU64>,
+    reads: Arc<AtomicU64>,
+}
+
+impl ReaderStats {
+    /// Count one read of `bytes` bytes
+    pub fn record(&self, bytes: usize) {
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// (bytes, reads) so far this session
+    pub fn totals(&self) -> (u64, u64) {
+        (self.bytes.load(Ordering::Relaxed), self.reads.load(Ordering::Relaxed))
+    }
+}
+
+/// Counters behind the Health view
+#[derive(Debug, Default)]
+pub struct Health {
+    pub reader: ReaderStats,
+    // notify's backend for the prd.json / progress.txt watcher (None when it couldn't start)
+    pub watcher_backend: Option<String>,
+    pub prd_reloads: u32,
+    // When prd.json was last reloaded (Unix seconds)
+    pub prd_reloaded_at: Option<u64>,
+    pub prd_reload_failures: u32,
+    // Draw times of the latest FRAME_SAMPLES frames
+    frame_times: VecDeque<Duration>,
+    // Start of the current rate window with the read count then
+    rate_window: Option<(Instant, u64)>,
+    reads_per_sec: f64,
+}
+
+impl Health {
+    /// Record one frame's draw time (and move the reads-per-second window along)
+    pub fn record_frame(&mut self, took: Duration) {
+        if self.frame_times.len() == FRAME_SAMPLES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(took);
+        self.sample_rate(Instant::now());
+    }
+
+    /// Recompute reads per second once RATE_WINDOW has passed since the last time
+    fn sample_rate(&mut self, now: Instant) {
+        let (_, reads) = self.reader.totals();
+        match self.rate_window {
+            Some((start, start_reads)) => {
+                let elapsed = now.saturating_duration_since(start);
+                if elapsed >= RATE_WINDOW {
+                    self.reads_per_sec = (reads - start_reads) as f64 / elapsed.as_secs_f64();
+                    self.rate_window = Some((now, reads));
+                }
+            }
+            None => self.rate_window = Some((now, reads)),
+        }
+    }
+
+    /// The view's lines; `input_queued` is the number of chunks waiting for the agent
+    pub fn report(&self, input_queued: usize) -> Vec<String> {
+        let last_reload = match self.prd_reloaded_at {
+            Some(at) => {
+                let secs = at % 86_400;
+                format!("last at {:02}:{:02}:{:02} UTC", secs / 3600, secs / 60 % 60, secs % 60)
+            }
+            None => "none yet".to_string(),
+        };
+        let (bytes, reads) = self.reader.totals();
+        let frames = match self.frame_times.iter().max() {
+            Some(worst) => {
+                let average = self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32;
+                format!(
+                    "{:.1} ms average, {:.1} ms worst (last {} frames)",
+                    average.as_secs_f64() * 1000.0,
+                    worst.as_secs_f64() * 1000.0,
+                    self.frame_times.len()
+                )
+            }
+            None => "none drawn yet".to_string(),
+        };
+        vec![
+            format!("PRD reloads:     {} ({}), {} failed", self.prd_reloads, last_reload, self.prd_reload_failures),
+            format!("File watcher:    {}", self.watcher_backend.as_deref().unwrap_or("none (rechecked on a timer)")),
+            format!("PTY reader:      {} bytes in {} reads, {:.1} reads/s", bytes, reads, self.reads_per_sec),
+            format!("Frame draw time: {}", frames),
+            format!("Agent input:     {} chunks queued", input_queued),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_counts_reloads_reads_and_frames() {
+        let mut health = Health { watcher_backend: Some("Inotify".to_string()), ..Health::default() };
+        let lines = health.report(0);
+        assert_eq!(lines[0], "PRD reloads:     0 (none yet), 0 failed");
+        assert_eq!(lines[3], "Frame draw time: none drawn yet");
+
+        health.prd_reloads = 3;
+        health.prd_reloaded_at = Some(86_400 * 2 + 3600 * 14 + 60 * 5 + 9);
+        health.prd_reload_failures = 1;
+        let start = Instant::now();
+        health.sample_rate(start);
+        health.reader.record(100);
+        health.reader.record(50);
+        health.sample_rate(start + Duration::from_secs(2));
+        for ms in [2, 4, 9] {
+            health.frame_times.push_back(Duration::from_millis(ms));
+        }
+        let lines = health.report(2);
+        assert_eq!(
+            lines,
+            [
+                "PRD reloads:     3 (last at 14:05:09 UTC), 1 failed",
+                "File watcher:    Inotify",
+                "PTY reader:      150 bytes in 2 reads, 1.0 reads/s",
+                "Frame draw time: 5.0 ms average, 9.0 ms worst (last 3 frames)",
+                "Agent input:     2 chunks queued",
+            ]
+        );
+
+        // Only the latest FRAME_SAMPLES frames count
+        for _ in 0..FRAME_SAMPLES {
+            health.record_frame(Duration::from_millis(1));
+        }
+        assert_eq!(health.report(0)[3], format!("Frame draw time: 1.0 ms average, 1.0 ms worst (last {} frames)", FRAME_SAMPLES));
+    }
+}
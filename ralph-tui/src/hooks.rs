@@ -0,0 +1,177 @@
+//! User commands run when an iteration ends or the whole PRD completes
+//!
+//! prd.json's `onIterationEnd` and `onComplete` (or ralph.toml's `on_iteration_end` and
+//! `on_complete`, for tasks that set neither) are shell command lines. Before
+//! running one, `{task}`, `{iteration}` and `{stories_done}` are replaced with
+//! the event's values, and the RALPH_* variables describing it are set. A hook
+//! runs on its own thread and is killed after its timeout; the loop only learns
+//! how it ended (for the iteration log and, on failure, a toast).
+
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a hook may run when prd.json doesn't set `hookTimeoutSecs`
+pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a running hook is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What a hook runs for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    IterationEnd,
+    Complete,
+}
+
+impl HookEvent {
+    /// prd.json field, also used in the iteration log and toasts
+    pub fn field(self) -> &'static str {
+        match self {
+            HookEvent::IterationEnd => "onIterationEnd",
+            HookEvent::Complete => "onComplete",
+        }
+    }
+
+    /// Value of RALPH_EVENT
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::IterationEnd => "iteration_end",
+            HookEvent::Complete => "complete",
+        }
+    }
+}
+
+/// How a hook ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookStatus {
+    /// Exit code (None when killed by a signal)
+    Exited(Option<i32>),
+    TimedOut,
+    /// The shell couldn't be started
+    Failed(String),
+}
+
+impl HookStatus {
+    /// For the iteration log ("0", "1", "signal", "timeout", "error")
+    pub fn label(&self) -> String {
+        match self {
+            HookStatus::Exited(Some(code)) => code.to_string(),
+            HookStatus::Exited(None) => "signal".to_string(),
+            HookStatus::TimedOut => "timeout".to_string(),
+            HookStatus::Failed(_) => "error".to_string(),
+        }
+    }
+}
+
+/// Values of an event, for the command's placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookContext {
+    pub task: String,
+    pub iteration: u32,
+    pub stories_done: usize,
+}
+
+/// Replace `{task}`, `{iteration}` and `{stories_done}` in `command`
+pub fn expand(command: &str, context: &HookContext) -> String {
+    command
+        .replace("{task}", &context.task)
+        .replace("{iteration}", &context.iteration.to_string())
+        .replace("{stories_done}", &context.stories_done.to_string())
+}
+
+/// A hook running on a background thread
+pub struct HookJob {
+    pub event: HookEvent,
+    pub iteration: u32,
+    started: Instant,
+    receiver: Receiver<HookStatus>,
+}
+
+impl HookJob {
+    /// Run `command` with `sh -c` and the given environment, killing it after `timeout`
+    pub fn start(event: HookEvent, iteration: u32, command: String, env: Vec<(String, String)>, timeout: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(run_with_timeout(&command, env, timeout));
+        });
+        Self { event, iteration, started: Instant::now(), receiver }
+    }
+
+    /// How the hook ended, with how long it ran (None while still running)
+    pub fn try_finish(&self) -> Option<(HookStatus, Duration)> {
+        let status = match self.receiver.try_recv() {
+            Ok(status) => status,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => HookStatus::Failed("hook thread exited unexpectedly".to_string()),
+        };
+        Some((status, self.started.elapsed()))
+    }
+
+    /// Block until the hook ends (it can't outlive its timeout)
+    pub fn wait(self) -> (HookStatus, Duration) {
+        let status = self
+            .receiver
+            .recv()
+            .unwrap_or_else(|_| HookStatus::Failed("hook thread exited unexpectedly".to_string()));
+        (status, self.started.elapsed())
+    }
+}
+
+fn run_with_timeout(command: &str, env: Vec<(String, String)>, timeout: Duration) -> HookStatus {
+    let spawned = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => return HookStatus::Failed(e.to_string()),
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return HookStatus::Exited(status.code()),
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return HookStatus::TimedOut;
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return HookStatus::Failed(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_placeholders() {
+        let context = HookContext { task: "login".to_string(), iteration: 7, stories_done: 3 };
+        assert_eq!(
+            expand("notify '{task}: {stories_done} done after {iteration}' {other}", &context),
+            "notify 'login: 3 done after 7' {other}"
+        );
+    }
+
+    #[test]
+    fn records_exit_status_and_kills_on_timeout() {
+        let env = vec![("RALPH_EVENT".to_string(), "complete".to_string())];
+        let job = HookJob::start(HookEvent::Complete, 1, "test \"$RALPH_EVENT\" = complete".to_string(), env, DEFAULT_HOOK_TIMEOUT);
+        assert_eq!(job.wait().0, HookStatus::Exited(Some(0)));
+
+        let job = HookJob::start(HookEvent::IterationEnd, 1, "exit 3".to_string(), Vec::new(), DEFAULT_HOOK_TIMEOUT);
+        assert_eq!(job.wait().0.label(), "3");
+
+        let job = HookJob::start(HookEvent::IterationEnd, 1, "sleep 5".to_string(), Vec::new(), Duration::from_millis(100));
+        let (status, ran) = job.wait();
+        assert_eq!(status, HookStatus::TimedOut);
+        assert!(ran < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,1092 @@
+//! Key and mouse handling
+//!
+//! Input handlers only change App state. Anything that needs the terminal
+//! (quitting, suspending for $EDITOR) or the PTY is returned as an `Action`
+//! for the main loop to carry out.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind};
+
+use crate::app::{App, IterationState, Mode, RalphViewMode, StoryAction};
+use crate::keymap::{KeyAction, KeyMap};
+use crate::preflight::{Preflight, PreflightAction};
+use crate::pty::{key_to_pty_bytes, lock_pty};
+use crate::tripwire::TripwireAnswer;
+
+/// Max Claude scrollback, matching the parser initialization (1000 lines)
+const MAX_SCROLLBACK: usize = 1000;
+
+/// What the main loop should do after a key was handled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    None,                  // State (if any) already updated
+    Quit,                  // Leave the loop (Ctrl+Q)
+    OpenFile,              // Open the selected file reference in $EDITOR
+    EditNotes(String),     // Edit this story's prd.json notes in $EDITOR
+    Copy(String),          // Put text on the system clipboard (OSC 52)
+    ForwardToPty(Vec<u8>), // Send bytes to Claude's PTY
+}
+
+/// Whether the key is the universal quit binding (Ctrl+Q by default; Ctrl+C goes to the PTY)
+fn is_quit(key: &KeyEvent, app: &App) -> bool {
+    app.keymap.is(KeyAction::Quit, key)
+}
+
+/// Toast for m outside a git repository
+const NO_GIT_SYNC_HINT: &str = "Sync unavailable — not a git repo";
+
+/// Mark the session as quit by the user
+/// In a batch the first Ctrl+Q asks whether to skip this task or quit the whole batch.
+fn quit(app: &mut App) -> Action {
+    if app.queue.len() > 1 && !app.batch_quit_prompt {
+        app.batch_quit_prompt = true;
+        return Action::None;
+    }
+    app.batch_quit_prompt = false;
+    app.iteration_state = IterationState::Aborted;
+    Action::Quit
+}
+
+/// Answer the batch quit prompt: s skips to the next task, q (or Ctrl+Q again) quits the batch
+fn answer_batch_quit(key: &KeyEvent, app: &mut App) -> Action {
+    match key.code {
+        KeyCode::Char('s') => quit(app),
+        KeyCode::Char('q') => {
+            app.quit_batch = true;
+            quit(app)
+        }
+        _ if is_quit(key, app) => {
+            app.quit_batch = true;
+            quit(app)
+        }
+        _ => {
+            app.batch_quit_prompt = false;
+            Action::None
+        }
+    }
+}
+
+/// Answer the tripwire modal: r (or Enter/Esc) lets the command run, c sends Ctrl+C to
+/// the agent, p pauses the loop at the next boundary; other keys are held back from the agent
+fn answer_tripwire(key: &KeyEvent, app: &mut App) -> Action {
+    match key.code {
+        KeyCode::Char('r') | KeyCode::Enter | KeyCode::Esc => app.answer_tripwire(TripwireAnswer::LetItRun),
+        KeyCode::Char('c') => app.answer_tripwire(TripwireAnswer::Interrupt),
+        KeyCode::Char('p') => app.answer_tripwire(TripwireAnswer::PauseLoop),
+        _ => {}
+    }
+    Action::None
+}
+
+/// Switch the Ralph terminal to `view`, or back to Normal if it's already showing
+fn toggle_view(app: &mut App, view: RalphViewMode) {
+    app.ralph_view_mode = if app.ralph_view_mode == view {
+        RalphViewMode::Normal
+    } else {
+        view
+    };
+    app.ralph_scroll_offset = 0; // Reset scroll on view change
+}
+
+/// Carry out a quick action on the selected story
+fn run_story_action(app: &mut App, action: StoryAction) -> Action {
+    match action {
+        StoryAction::ViewDetails => {
+            app.ralph_view_mode = RalphViewMode::StoryDetails;
+            app.ralph_scroll_offset = 0;
+        }
+        StoryAction::AddNote => {
+            if let Some(story) = app.selected_story() {
+                return Action::EditNotes(story.id.clone());
+            }
+        }
+        StoryAction::TogglePassed => app.toggle_selected_story_passed(),
+        StoryAction::FocusCriterion => app.focus_selected_story_criterion(),
+        StoryAction::Skip => app.toggle_selected_story_skipped(),
+        StoryAction::CopyId => {
+            if let Some(id) = app.selected_story().map(|s| s.id.clone()) {
+                app.show_toast(format!("Copied {}", id));
+                return Action::Copy(id);
+            }
+        }
+    }
+    Action::None
+}
+
+/// Handle a key press in `mode` while an iteration is running
+pub fn handle_key(mode: Mode, key: KeyEvent, app: &mut App) -> Action {
+    if app.batch_quit_prompt {
+        return answer_batch_quit(&key, app);
+    }
+    if is_quit(&key, app) {
+        return quit(app);
+    }
+    if !app.tripwire_alerts.is_empty() {
+        return answer_tripwire(&key, app);
+    }
+
+    match mode {
+        Mode::Ralph => {
+            // After u the next key restores a backup (y), undoes the last edit (z) or
+            // toggles the edit log (l); anything else cancels
+            if app.undo_prefix {
+                match key.code {
+                    KeyCode::Char('y') => app.confirm_prd_restore(),
+                    KeyCode::Char('z') => app.undo_prd_edit(),
+                    KeyCode::Char('l') => {
+                        app.close_undo_prefix();
+                        toggle_view(app, RalphViewMode::Journal);
+                    }
+                    _ => app.close_undo_prefix(),
+                }
+                return Action::None;
+            }
+
+            // After R in the iterations view, Y rolls back; anything else cancels
+            if app.rollback_armed {
+                match key.code {
+                    KeyCode::Char('Y') => app.confirm_rollback(),
+                    _ => app.rollback_armed = false,
+                }
+                return Action::None;
+            }
+
+            // Quick actions menu: j/k move, Enter runs the highlighted action, Esc closes
+            if let Some(selected) = app.story_menu {
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => app.move_story_menu(false),
+                    KeyCode::Down | KeyCode::Char('j') => app.move_story_menu(true),
+                    KeyCode::Enter => {
+                        app.story_menu = None;
+                        return run_story_action(app, StoryAction::ALL[selected]);
+                    }
+                    KeyCode::Esc => app.story_menu = None,
+                    _ => {}
+                }
+                return Action::None;
+            }
+
+            // Ctrl+L re-sends the panel size to an agent drawing at a stale width; checked
+            // before the view keys so their plain l doesn't take it
+            if app.keymap.is(KeyAction::ResyncPty, &key) {
+                app.pty_resync = true;
+                return Action::None;
+            }
+
+            // Keys that only mean something in the current view come before the key map
+            match key.code {
+                // Activity view: h/l or ←/→ pick an entry, Enter previews the file it names
+                KeyCode::Left | KeyCode::Char('h') if app.ralph_view_mode == RalphViewMode::Activity => {
+                    app.move_activity_selection(true);
+                    return Action::None;
+                }
+                KeyCode::Right | KeyCode::Char('l') if app.ralph_view_mode == RalphViewMode::Activity => {
+                    app.move_activity_selection(false);
+                    return Action::None;
+                }
+                KeyCode::Enter if app.ralph_view_mode == RalphViewMode::Activity => {
+                    app.open_activity_preview();
+                    return Action::None;
+                }
+                // Links view: h/l or ←/→ pick a link, y copies it, Enter opens it
+                KeyCode::Left | KeyCode::Char('h') if app.ralph_view_mode == RalphViewMode::Links => {
+                    app.move_link_selection(true);
+                    return Action::None;
+                }
+                KeyCode::Right | KeyCode::Char('l') if app.ralph_view_mode == RalphViewMode::Links => {
+                    app.move_link_selection(false);
+                    return Action::None;
+                }
+                KeyCode::Char('y') if app.ralph_view_mode == RalphViewMode::Links => {
+                    let Some(link) = app.selected_link() else {
+                        return Action::None;
+                    };
+                    app.show_toast(format!("Copied {}", link));
+                    return Action::Copy(link);
+                }
+                KeyCode::Enter if app.ralph_view_mode == RalphViewMode::Links => {
+                    app.open_selected_link();
+                    return Action::None;
+                }
+                // Changes view: h/l or ←/→ pick a file, Enter shows its diff
+                KeyCode::Left | KeyCode::Char('h') if app.ralph_view_mode == RalphViewMode::Changes => {
+                    app.move_change_selection(true);
+                    return Action::None;
+                }
+                KeyCode::Right | KeyCode::Char('l') if app.ralph_view_mode == RalphViewMode::Changes => {
+                    app.move_change_selection(false);
+                    return Action::None;
+                }
+                KeyCode::Enter if app.ralph_view_mode == RalphViewMode::Changes => {
+                    app.open_change_diff();
+                    return Action::None;
+                }
+                // Esc: Leave the diff for the changes list
+                KeyCode::Esc if app.ralph_view_mode == RalphViewMode::ChangeDiff => {
+                    app.close_change_diff();
+                    return Action::None;
+                }
+                // Esc: Leave the file preview for the activity list
+                KeyCode::Esc if app.ralph_view_mode == RalphViewMode::FilePreview => {
+                    app.close_file_preview();
+                    return Action::None;
+                }
+                // Tab: with --parallel lanes, move the input focus to the next lane
+                KeyCode::Tab if !app.lanes.is_empty() => {
+                    app.focus_next_lane();
+                    return Action::None;
+                }
+                // Enter acts on the story list, which is hidden with the sidebar
+                KeyCode::Enter if app.sidebar_hidden => {
+                    app.show_toast(app.sidebar_hidden_hint());
+                    return Action::None;
+                }
+                // Enter on the "Needs attention" section: list blockers with context
+                KeyCode::Enter if app.attention_selected => {
+                    toggle_view(app, RalphViewMode::Blockers);
+                    return Action::None;
+                }
+                // Enter: Expand/collapse the completed stories group
+                KeyCode::Enter if app.completed_group_selected => {
+                    app.toggle_completed_group();
+                    return Action::None;
+                }
+                // Enter on a story card: quick actions menu
+                KeyCode::Enter => {
+                    app.open_story_menu();
+                    return Action::None;
+                }
+                // h/l or ←/→: Move between the selected story's criteria in the details and evidence views
+                KeyCode::Left | KeyCode::Char('h')
+                    if matches!(app.ralph_view_mode, RalphViewMode::StoryDetails | RalphViewMode::Evidence) =>
+                {
+                    app.move_criterion_selection(false);
+                    return Action::None;
+                }
+                KeyCode::Right | KeyCode::Char('l')
+                    if matches!(app.ralph_view_mode, RalphViewMode::StoryDetails | RalphViewMode::Evidence) =>
+                {
+                    app.move_criterion_selection(true);
+                    return Action::None;
+                }
+                // e: Full evidence for the selected criterion (again to go back to the details)
+                KeyCode::Char('e') if app.ralph_view_mode == RalphViewMode::StoryDetails => {
+                    app.ralph_view_mode = RalphViewMode::Evidence;
+                    app.ralph_scroll_offset = 0;
+                    return Action::None;
+                }
+                KeyCode::Char('e') if app.ralph_view_mode == RalphViewMode::Evidence => {
+                    app.ralph_view_mode = RalphViewMode::StoryDetails;
+                    app.ralph_scroll_offset = 0;
+                    return Action::None;
+                }
+                // V: Run the selected story's verify commands in the background
+                KeyCode::Char('V') if app.ralph_view_mode == RalphViewMode::StoryDetails => {
+                    app.verify_selected_story();
+                    return Action::None;
+                }
+                // h/l or ←/→: Pick a marker in the iterations view (newest is on top)
+                KeyCode::Left | KeyCode::Char('h') if app.ralph_view_mode == RalphViewMode::Iterations => {
+                    app.move_marker_selection(false);
+                    return Action::None;
+                }
+                KeyCode::Right | KeyCode::Char('l') if app.ralph_view_mode == RalphViewMode::Iterations => {
+                    app.move_marker_selection(true);
+                    return Action::None;
+                }
+                // R: Roll back to the picked marker (needs Y to confirm)
+                KeyCode::Char('R') if app.ralph_view_mode == RalphViewMode::Iterations => {
+                    app.arm_rollback();
+                    return Action::None;
+                }
+                // F: Tell the next iterations to prioritize the selected criterion (again to clear)
+                KeyCode::Char('F') if app.ralph_view_mode == RalphViewMode::StoryDetails => {
+                    app.toggle_criterion_focus();
+                    return Action::None;
+                }
+                _ => {}
+            }
+
+            // In Ralph mode: handle TUI controls (remappable, see keymap.rs)
+            let story_count = app.prd.as_ref().map(|p| p.user_stories.len()).unwrap_or(0);
+
+            let action = app.keymap.action(&key);
+            if let Some(action) = action {
+                app.used_keys.insert(action);
+            }
+            match action {
+                Some(KeyAction::EnterClaudeMode) => {
+                    app.mode = Mode::Claude;
+                }
+                // Story list keys do nothing visible while the sidebar is hidden: say how to bring it back
+                Some(
+                    KeyAction::StoryUp
+                    | KeyAction::StoryDown
+                    | KeyAction::ToggleLayout
+                    | KeyAction::ToggleCriteria
+                    | KeyAction::ToggleTarget
+                    | KeyAction::JumpToCurrent,
+                ) if app.sidebar_hidden => app.show_toast(app.sidebar_hidden_hint()),
+                // Story navigation (wraps at either end; rows follow the current list layout)
+                Some(KeyAction::StoryUp) if story_count > 0 => {
+                    app.move_story_selection(false);
+                }
+                Some(KeyAction::StoryDown) if story_count > 0 => {
+                    app.move_story_selection(true);
+                }
+                // Toggle flat / grouped story list layout
+                Some(KeyAction::ToggleLayout) => {
+                    app.toggle_story_list_layout();
+                }
+                // Sync with the merge target at the next iteration boundary
+                Some(KeyAction::Sync) if app.drift.is_some() => {
+                    app.sync_requested = !app.sync_requested;
+                }
+                Some(KeyAction::Sync) if !app.git_available => app.show_toast(NO_GIT_SYNC_HINT),
+                // Open the file mentioned in the selected activity/progress entry in $EDITOR
+                Some(KeyAction::OpenFile)
+                    if matches!(
+                        app.ralph_view_mode,
+                        RalphViewMode::Normal
+                            | RalphViewMode::Progress
+                            | RalphViewMode::Activity
+                            | RalphViewMode::FilePreview
+                            | RalphViewMode::Changes
+                            | RalphViewMode::ChangeDiff
+                    ) =>
+                {
+                    return Action::OpenFile;
+                }
+                // Target/untarget the selected story (the run only works on targeted stories)
+                Some(KeyAction::ToggleTarget) => {
+                    app.toggle_selected_story_target();
+                }
+                // Jump to the active story
+                Some(KeyAction::JumpToCurrent) => {
+                    app.jump_to_current_story();
+                }
+                // Clear targets and work on all stories again
+                Some(KeyAction::ClearTargets) => {
+                    app.clear_story_targets();
+                }
+                // Prefix for restoring a backup, undoing the last prd.json edit or the edit log
+                Some(KeyAction::UndoMenu) => {
+                    app.open_undo_prefix();
+                }
+                // Expand/collapse acceptance criteria on the selected card
+                Some(KeyAction::ToggleCriteria) => {
+                    app.toggle_selected_story_expanded();
+                }
+                // Scroll the Ralph terminal content
+                Some(KeyAction::ScrollUp) if app.ralph_view_mode != RalphViewMode::Normal && app.ralph_scroll_offset > 0 => {
+                    app.ralph_scroll_offset = app.ralph_scroll_offset.saturating_sub(3);
+                }
+                Some(KeyAction::ScrollDown) if app.ralph_view_mode != RalphViewMode::Normal => {
+                    app.ralph_scroll_offset += 3;
+                }
+                // Follow the active story across PRD reloads (story navigation suspends it)
+                Some(KeyAction::ToggleFollow) => app.toggle_follow(),
+                // Toggle story details, progress and requirements views
+                Some(KeyAction::ToggleStoryDetails) => toggle_view(app, RalphViewMode::StoryDetails),
+                Some(KeyAction::ToggleProgress) => app.toggle_progress(),
+                Some(KeyAction::ToggleRequirements) => toggle_view(app, RalphViewMode::Requirements),
+                // Toggle the list of error lines matched this iteration
+                Some(KeyAction::ToggleErrors) => toggle_view(app, RalphViewMode::Errors),
+                // Toggle the iteration start markers (rollback points)
+                Some(KeyAction::ToggleIterations) => toggle_view(app, RalphViewMode::Iterations),
+                // Toggle the full list of the agent's recent activities
+                Some(KeyAction::ToggleActivity) => toggle_view(app, RalphViewMode::Activity),
+                // Toggle the links seen in the agent's output
+                Some(KeyAction::ToggleLinks) => toggle_view(app, RalphViewMode::Links),
+                // Toggle the comparison of prd.json at session start with now
+                Some(KeyAction::ToggleDiff) => toggle_view(app, RalphViewMode::Diff),
+                // Toggle ralph-tui's own vital signs (reloads, reader, frame times)
+                Some(KeyAction::ToggleHealth) => toggle_view(app, RalphViewMode::Health),
+                // Toggle the files changed this iteration (git diff against the iteration start)
+                Some(KeyAction::ToggleChanges) if matches!(app.ralph_view_mode, RalphViewMode::Changes | RalphViewMode::ChangeDiff) => {
+                    app.ralph_view_mode = RalphViewMode::Normal;
+                    app.ralph_scroll_offset = 0;
+                }
+                Some(KeyAction::ToggleChanges) => app.open_changes(),
+                // Show/hide the progress.txt tail strip
+                Some(KeyAction::ToggleProgressStrip) => app.toggle_progress_strip(),
+                // Hide/show the Ralph status panel (Claude gets the full width)
+                Some(KeyAction::ToggleSidebar) => app.toggle_sidebar(),
+                // Cycle the Ralph terminal between collapsed, normal and large
+                Some(KeyAction::GrowTerminal) => app.cycle_ralph_size(true),
+                Some(KeyAction::ShrinkTerminal) => app.cycle_ralph_size(false),
+                // Stop after the running iteration (again: back to the full budget)
+                Some(KeyAction::WindDown) => app.toggle_wind_down(),
+                // continueConversation: start the next iteration in a fresh conversation
+                Some(KeyAction::FreshSession) => app.toggle_fresh_session(),
+                _ => {}
+            }
+            Action::None
+        }
+        Mode::Claude => {
+            // In Claude mode: Ctrl+O (exit_claude_mode) returns to Ralph mode
+            // All other keys (including ESC) are forwarded to PTY
+            // This allows ESC to work natively in Claude for interrupting
+            if app.keymap.is(KeyAction::ExitClaudeMode, &key) {
+                app.mode = Mode::Ralph;
+                return Action::None;
+            }
+            // Reset scroll offset when user types (auto-scroll to bottom)
+            app.claude_scroll_offset = 0;
+            // Ctrl+C/D/Z can kill a long generation: hold them until pressed twice
+            if let KeyCode::Char(c) = key.code
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+                && matches!(c.to_ascii_lowercase(), 'c' | 'd' | 'z')
+            {
+                if !app.confirm_guarded_chord(c.to_ascii_lowercase()) {
+                    return Action::None;
+                }
+            } else {
+                app.pending_chord = None;
+            }
+            let kitty = app.kitty_keys && lock_pty(app.input_pty_state()).kitty_keyboard;
+            match key_to_pty_bytes(key.code, key.modifiers, kitty) {
+                Some(bytes) => Action::ForwardToPty(bytes),
+                None => Action::None,
+            }
+        }
+    }
+}
+
+/// Handle a mouse event (wheel scrolls Claude's scrollback in Claude mode)
+pub fn handle_mouse(app: &mut App, mouse: MouseEvent) {
+    if app.mode != Mode::Claude {
+        return;
+    }
+    match mouse.kind {
+        MouseEventKind::ScrollUp => {
+            // Scroll up (into history)
+            app.claude_scroll_offset = app.claude_scroll_offset.saturating_add(3).min(MAX_SCROLLBACK);
+        }
+        MouseEventKind::ScrollDown => {
+            // Scroll down (towards current)
+            app.claude_scroll_offset = app.claude_scroll_offset.saturating_sub(3);
+        }
+        _ => {}
+    }
+}
+
+/// Handle a key press during the delay between iterations
+pub fn handle_delay_key(key: KeyEvent, app: &mut App) -> Action {
+    if app.batch_quit_prompt {
+        return answer_batch_quit(&key, app);
+    }
+    if is_quit(&key, app) {
+        return quit(app);
+    }
+    if !app.tripwire_alerts.is_empty() {
+        return answer_tripwire(&key, app);
+    }
+    match key.code {
+        // Sync with the merge target now (we're already between iterations)
+        _ if app.keymap.is(KeyAction::Sync, &key) && !app.git_available => app.show_toast(NO_GIT_SYNC_HINT),
+        _ if app.keymap.is(KeyAction::Sync, &key) && app.sync_error.is_none() => app.start_sync(),
+        // Make the iteration that just ended the last one
+        _ if app.keymap.is(KeyAction::WindDown, &key) => app.toggle_wind_down(),
+        _ if app.keymap.is(KeyAction::FreshSession, &key) => app.toggle_fresh_session(),
+        // Esc: Dismiss the sync error and restart the countdown
+        KeyCode::Esc if app.sync_error.is_some() => {
+            app.sync_error = None;
+            app.delay_start = Some(std::time::Instant::now());
+        }
+        // Esc: Go on after checking the files written outside the repository
+        KeyCode::Esc if app.scope_hold.is_some() => app.dismiss_scope_hold(),
+        // Esc: Go on after checking what the dangerous command did
+        KeyCode::Esc if app.tripwire_hold.is_some() => app.dismiss_tripwire_hold(),
+        // Esc: Go on after reviewing how the agent changed prd.json
+        KeyCode::Esc if app.churn_hold.is_some() => app.dismiss_churn_hold(),
+        _ => {}
+    }
+    Action::None
+}
+
+/// Handle a key on the startup preflight screen (before there is an App)
+/// f applies the offered fixes, Enter or c starts the loop unless a hard check
+/// failed, and q, Esc or the quit key abort.
+pub fn handle_preflight_key(key: KeyEvent, preflight: &Preflight, keymap: &KeyMap) -> PreflightAction {
+    if keymap.is(KeyAction::Quit, &key) {
+        return PreflightAction::Abort;
+    }
+    match key.code {
+        KeyCode::Char('f') if !preflight.fixes().is_empty() => PreflightAction::Fix,
+        KeyCode::Char('p') if preflight.prd_task_dir.is_some() => PreflightAction::UsePrdTaskDir,
+        KeyCode::Enter | KeyCode::Char('c') if !preflight.has_hard_failure() => PreflightAction::Continue,
+        KeyCode::Char('q') | KeyCode::Esc => PreflightAction::Abort,
+        _ => PreflightAction::None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::tests::test_app;
+    use crate::app::{FollowMode, QueueEntry, RalphTerminalSize, SessionState, BATCH_QUIT_PROMPT, KEY_GUARD_WINDOW};
+    use std::collections::BTreeSet;
+    use std::time::Instant;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn ctrl_q_quits_from_either_mode() {
+        let mut app = test_app("quit");
+        assert_eq!(handle_key(Mode::Claude, ctrl('q'), &mut app), Action::Quit);
+        assert!(app.aborted());
+        assert_eq!(app.iteration_state, IterationState::Aborted);
+
+        // From the delay screen too: the session is aborted, not completed
+        let mut app = test_app("quit-delay");
+        app.iteration_state = IterationState::WaitingDelay;
+        assert_eq!(handle_delay_key(ctrl('q'), &mut app), Action::Quit);
+        assert_eq!(app.iteration_state, IterationState::Aborted);
+    }
+
+    #[test]
+    fn remapped_keys_replace_the_defaults() {
+        let mut app = test_app("keymap");
+        let config = "[keys]\nenter_claude_mode = \"a\"\nexit_claude_mode = \"ctrl+g\"\nquit = \"ctrl+x\"";
+        let keys = crate::config::parse_config(config).unwrap().keys;
+        app.keymap = std::sync::Arc::new(crate::keymap::KeyMap::from_bindings(&keys).unwrap());
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('i')), &mut app);
+        assert_eq!(app.mode, Mode::Ralph);
+        handle_key(Mode::Ralph, key(KeyCode::Char('a')), &mut app);
+        assert_eq!(app.mode, Mode::Claude);
+        // The old chord now goes to the agent
+        assert!(matches!(handle_key(Mode::Claude, ctrl('o'), &mut app), Action::ForwardToPty(_)));
+        handle_key(Mode::Claude, ctrl('g'), &mut app);
+        assert_eq!(app.mode, Mode::Ralph);
+
+        assert_eq!(handle_key(Mode::Ralph, ctrl('q'), &mut app), Action::None);
+        assert_eq!(handle_delay_key(ctrl('x'), &mut app), Action::Quit);
+    }
+
+    #[test]
+    fn pressing_a_key_retires_its_footer_tip() {
+        let mut app = test_app("tips");
+        assert_eq!(app.snapshot().footer_tip, None);
+
+        app.footer_tips = true;
+        let tip = app.snapshot().footer_tip.unwrap();
+        assert!(tip.starts_with("Tip: press s to"), "{}", tip);
+        handle_key(Mode::Ralph, key(KeyCode::Char('s')), &mut app);
+        // No tips while a view is open, and the story details tip is gone once it closes
+        assert_eq!(app.snapshot().footer_tip, None);
+        handle_key(Mode::Ralph, key(KeyCode::Char('s')), &mut app);
+        assert!(app.snapshot().footer_tip.unwrap().starts_with("Tip: press i to"));
+    }
+
+    #[test]
+    fn links_view_copies_the_picked_link() {
+        let mut app = test_app("links-view");
+        crate::pty::lock_pty(&app.pty_state).append_output(b"PR: https://github.com/o/r/pull/7\ndocs: https://docs.rs/vt100\n");
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('L')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Links);
+        assert_eq!(app.snapshot().links, ["https://docs.rs/vt100", "https://github.com/o/r/pull/7"]);
+        handle_key(Mode::Ralph, key(KeyCode::Char('l')), &mut app);
+        let copied = handle_key(Mode::Ralph, key(KeyCode::Char('y')), &mut app);
+        assert_eq!(copied, Action::Copy("https://github.com/o/r/pull/7".to_string()));
+        handle_key(Mode::Ralph, key(KeyCode::Char('l')), &mut app);
+        assert_eq!(app.selected_link().as_deref(), Some("https://docs.rs/vt100"));
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('L')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Normal);
+    }
+
+    #[test]
+    fn activity_view_previews_the_picked_file_and_esc_returns() {
+        let mut app = test_app("activity-preview");
+        let file = app.task_dir.join("notes.md");
+        std::fs::write(&file, "# Notes\nline two\n").unwrap();
+        crate::pty::lock_pty(&app.pty_state).activities = vec![
+            crate::pty::Activity::new("Write", &file.display().to_string()),
+            crate::pty::Activity::new("Bash", "cargo test"),
+        ];
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('A')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Activity);
+        // The newest entry (Bash) names no file
+        handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app);
+        assert_eq!(app.snapshot().toast.as_deref(), Some("No file path in the selected entry"));
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('l')), &mut app);
+        app.ralph_scroll_offset = 2;
+        handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::FilePreview);
+        assert_eq!(app.ralph_scroll_offset, 0);
+        let preview = app.snapshot().file_preview.unwrap();
+        assert_eq!(
+            preview.content,
+            crate::preview::PreviewContent::Lines { lines: vec!["# Notes".into(), "line two".into()], truncated: false }
+        );
+
+        handle_key(Mode::Ralph, key(KeyCode::Esc), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Activity);
+        assert_eq!(app.ralph_scroll_offset, 2);
+        assert_eq!(app.selected_activity, 1);
+    }
+
+    #[test]
+    fn preflight_keys_fix_continue_or_abort() {
+        use crate::preflight::{Check, Fix};
+        let keymap = KeyMap::default();
+        let failed = |hard, fix| Check {
+            label: "Git branch",
+            summary: "on main".to_string(),
+            passed: false,
+            hard,
+            detail: None,
+            fix,
+        };
+        let mut preflight = Preflight { checks: vec![failed(false, Some(Fix::RotateProgress))], ..Default::default() };
+        assert_eq!(handle_preflight_key(key(KeyCode::Char('f')), &preflight, &keymap), PreflightAction::Fix);
+        assert_eq!(handle_preflight_key(key(KeyCode::Enter), &preflight, &keymap), PreflightAction::Continue);
+        assert_eq!(handle_preflight_key(ctrl('q'), &preflight, &keymap), PreflightAction::Abort);
+
+        // Nothing to fix, and a hard failure can't be continued past
+        preflight.checks = vec![failed(true, None)];
+        assert_eq!(handle_preflight_key(key(KeyCode::Char('f')), &preflight, &keymap), PreflightAction::None);
+        assert_eq!(handle_preflight_key(key(KeyCode::Enter), &preflight, &keymap), PreflightAction::None);
+        assert_eq!(handle_preflight_key(key(KeyCode::Esc), &preflight, &keymap), PreflightAction::Abort);
+
+        // p only switches when prd.json's taskDir names a task to switch to
+        assert_eq!(handle_preflight_key(key(KeyCode::Char('p')), &preflight, &keymap), PreflightAction::None);
+        preflight.prd_task_dir = Some("tasks/original".into());
+        assert_eq!(handle_preflight_key(key(KeyCode::Char('p')), &preflight, &keymap), PreflightAction::UsePrdTaskDir);
+    }
+
+    #[test]
+    fn ctrl_l_resyncs_the_pty_from_any_view() {
+        let mut app = test_app("resync");
+        app.ralph_view_mode = RalphViewMode::Activity;
+        handle_key(Mode::Ralph, key(KeyCode::Char('l')), &mut app);
+        assert!(!app.pty_resync);
+        assert_eq!(handle_key(Mode::Ralph, ctrl('l'), &mut app), Action::None);
+        assert!(app.pty_resync);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Activity);
+    }
+
+    #[test]
+    fn ctrl_q_in_a_batch_asks_to_skip_or_quit() {
+        let mut app = test_app("batch-quit");
+        app.queue = vec![QueueEntry::new("a".into()), QueueEntry::new("b".into())];
+        assert_eq!(handle_key(Mode::Ralph, ctrl('q'), &mut app), Action::None);
+        assert_eq!(app.snapshot().toast.as_deref(), Some(BATCH_QUIT_PROMPT));
+        // Any other key cancels
+        assert_eq!(handle_key(Mode::Ralph, key(KeyCode::Esc), &mut app), Action::None);
+        assert!(!app.batch_quit_prompt && !app.aborted());
+
+        handle_key(Mode::Ralph, ctrl('q'), &mut app);
+        assert_eq!(handle_key(Mode::Ralph, key(KeyCode::Char('s')), &mut app), Action::Quit);
+        assert!(app.aborted() && !app.quit_batch);
+
+        let mut app = test_app("batch-quit-all");
+        app.queue = vec![QueueEntry::new("a".into()), QueueEntry::new("b".into())];
+        handle_delay_key(ctrl('q'), &mut app);
+        assert_eq!(handle_delay_key(key(KeyCode::Char('q')), &mut app), Action::Quit);
+        assert!(app.quit_batch);
+    }
+
+    #[test]
+    fn ralph_mode_keys_update_state() {
+        let mut app = test_app("ralph");
+        assert_eq!(handle_key(Mode::Ralph, key(KeyCode::Char('j')), &mut app), Action::None);
+        assert_eq!(app.selected_story_index, 1);
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('s')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::StoryDetails);
+        // o only opens files from the Normal and Progress views
+        assert_eq!(handle_key(Mode::Ralph, key(KeyCode::Char('o')), &mut app), Action::None);
+        handle_key(Mode::Ralph, key(KeyCode::Char('s')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Normal);
+        assert_eq!(handle_key(Mode::Ralph, key(KeyCode::Char('o')), &mut app), Action::OpenFile);
+
+        handle_key(Mode::Ralph, key(KeyCode::Tab), &mut app);
+        assert_eq!(app.mode, Mode::Claude);
+    }
+
+    #[test]
+    fn e_opens_the_evidence_view_from_story_details() {
+        let mut app = test_app("evidence");
+        // Only the details view has an evidence sub-view
+        handle_key(Mode::Ralph, key(KeyCode::Char('e')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Normal);
+        handle_key(Mode::Ralph, key(KeyCode::Char('s')), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Char('e')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Evidence);
+        handle_key(Mode::Ralph, key(KeyCode::Char('e')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::StoryDetails);
+        // V runs the story's verify commands; the demo PRD has none
+        handle_key(Mode::Ralph, KeyEvent::new(KeyCode::Char('V'), KeyModifiers::SHIFT), &mut app);
+        assert_eq!(app.snapshot().toast.as_deref(), Some("US-001 has no verify commands"));
+        assert!(app.verify_job.is_none());
+    }
+
+    #[test]
+    fn c_jumps_to_the_active_story_and_f_follows_it() {
+        let mut app = test_app("follow");
+        let passed = |app: &App, count: usize| {
+            let mut prd = app.prd.clone().unwrap();
+            for story in prd.user_stories.iter_mut().take(count) {
+                story.passes = true;
+            }
+            prd
+        };
+        handle_key(Mode::Ralph, key(KeyCode::Char('j')), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Char('c')), &mut app);
+        assert_eq!(app.selected_story_index, 0);
+
+        // Following: the selection moves on with the active story
+        handle_key(Mode::Ralph, key(KeyCode::Char('F')), &mut app);
+        assert_eq!(app.follow, FollowMode::Following);
+        let prd = passed(&app, 1);
+        app.set_prd(prd);
+        assert_eq!(app.selected_story_index, 1);
+
+        // j/k suspend it until F
+        handle_key(Mode::Ralph, key(KeyCode::Char('k')), &mut app);
+        assert_eq!(app.follow, FollowMode::Suspended);
+        let prd = passed(&app, 1);
+        app.set_prd(prd);
+        assert_eq!(app.selected_story_index, 0);
+        handle_key(Mode::Ralph, key(KeyCode::Char('F')), &mut app);
+        assert_eq!((app.follow, app.selected_story_index), (FollowMode::Following, 1));
+        handle_key(Mode::Ralph, key(KeyCode::Char('F')), &mut app);
+        assert_eq!(app.follow, FollowMode::Off);
+
+        // The selection is a position in priority order, not in the file
+        let mut reordered = app.prd.clone().unwrap();
+        reordered.user_stories.reverse();
+        app.set_prd(reordered);
+        handle_key(Mode::Ralph, key(KeyCode::Char('c')), &mut app);
+        assert_eq!(app.selected_story().map(|s| s.id.as_str()), Some("US-002"));
+
+        // Nothing to jump to once every story passes
+        let prd = passed(&app, 2);
+        app.set_prd(prd);
+        handle_key(Mode::Ralph, key(KeyCode::Char('c')), &mut app);
+        assert_eq!(app.active_toast(), Some("No active story — all stories are done"));
+    }
+
+    #[test]
+    fn tab_cycles_parallel_lane_focus() {
+        let mut app = test_app("parallel-focus");
+        app.lanes = (1..=3)
+            .map(|n| crate::lanes::Lane::new(BTreeSet::from([format!("US-{}", n)]), 24, 80))
+            .collect();
+        handle_key(Mode::Ralph, key(KeyCode::Tab), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Tab), &mut app);
+        assert_eq!(app.focused_lane, 2);
+        assert_eq!(app.mode, Mode::Ralph);
+        handle_key(Mode::Ralph, key(KeyCode::Tab), &mut app);
+        assert_eq!(app.focused_lane, 0);
+        handle_key(Mode::Ralph, key(KeyCode::Char('i')), &mut app);
+        assert_eq!(app.mode, Mode::Claude);
+    }
+
+    #[test]
+    fn claude_mode_forwards_keys_except_ctrl_o() {
+        let mut app = test_app("claude");
+        app.mode = Mode::Claude;
+        app.claude_scroll_offset = 12;
+        assert_eq!(
+            handle_key(Mode::Claude, key(KeyCode::Char('s')), &mut app),
+            Action::ForwardToPty(b"s".to_vec())
+        );
+        assert_eq!(app.claude_scroll_offset, 0);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Normal);
+        assert_eq!(
+            handle_key(Mode::Claude, key(KeyCode::Esc), &mut app),
+            Action::ForwardToPty(vec![0x1b])
+        );
+
+        assert_eq!(handle_key(Mode::Claude, ctrl('o'), &mut app), Action::None);
+        assert_eq!(app.mode, Mode::Ralph);
+    }
+
+    #[test]
+    fn enter_on_attention_section_opens_blockers() {
+        let mut app = test_app("attention");
+        std::fs::write(app.task_dir.join("progress.txt"), "BLOCKER: tests need a database\n").unwrap();
+        app.reload_progress_if_needed();
+        handle_key(Mode::Ralph, key(KeyCode::Char('k')), &mut app);
+        assert!(app.attention_selected);
+        handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Blockers);
+    }
+
+    #[test]
+    fn bang_toggles_errors_view_with_matched_lines() {
+        let mut app = test_app("errors");
+        app.pty_state.lock().unwrap().append_output(b"ok\nthread 'main' panicked at src/lib.rs:3\n");
+        handle_key(Mode::Ralph, key(KeyCode::Char('!')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Errors);
+        let snapshot = app.snapshot();
+        assert_eq!(snapshot.error_count, 1);
+        assert_eq!(snapshot.errors[0].before, vec!["ok"]);
+        assert!(app.take_new_errors());
+        assert!(!app.take_new_errors());
+        handle_key(Mode::Ralph, key(KeyCode::Char('!')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Normal);
+        assert!(app.snapshot().errors.is_empty());
+    }
+
+    #[test]
+    fn ctrl_c_is_forwarded_only_on_second_press() {
+        let mut app = test_app("guard");
+        app.mode = Mode::Claude;
+        assert_eq!(handle_key(Mode::Claude, ctrl('c'), &mut app), Action::None);
+        assert_eq!(app.snapshot().toast.as_deref(), Some("Press Ctrl+C again to interrupt agent"));
+        assert_eq!(handle_key(Mode::Claude, ctrl('c'), &mut app), Action::ForwardToPty(vec![0x03]));
+
+        // A different key in between, or the window expiring, starts over
+        handle_key(Mode::Claude, ctrl('d'), &mut app);
+        assert_eq!(handle_key(Mode::Claude, key(KeyCode::Char('x')), &mut app), Action::ForwardToPty(b"x".to_vec()));
+        assert_eq!(handle_key(Mode::Claude, ctrl('d'), &mut app), Action::None);
+        app.pending_chord = Some(('d', Instant::now() - KEY_GUARD_WINDOW));
+        assert_eq!(handle_key(Mode::Claude, ctrl('d'), &mut app), Action::None);
+        assert_eq!(handle_key(Mode::Claude, ctrl('d'), &mut app), Action::ForwardToPty(vec![0x04]));
+
+        app.key_guard = false;
+        assert_eq!(handle_key(Mode::Claude, ctrl('z'), &mut app), Action::ForwardToPty(vec![0x1a]));
+    }
+
+    #[test]
+    fn u_restores_prd_backup_only_after_confirmation() {
+        let mut app = test_app("restore");
+        let original = std::fs::read_to_string(&app.prd_path).unwrap();
+        crate::backup::backup_prd(&app.task_dir, &app.prd_path).unwrap();
+        std::fs::write(&app.prd_path, "{ truncated").unwrap();
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('u')), &mut app);
+        assert!(app.pending_restore.is_some());
+        // Any other key cancels
+        handle_key(Mode::Ralph, key(KeyCode::Char('j')), &mut app);
+        assert!(app.pending_restore.is_none());
+        assert_eq!(app.selected_story_index, 0);
+        assert_eq!(std::fs::read_to_string(&app.prd_path).unwrap(), "{ truncated");
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('u')), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Char('y')), &mut app);
+        assert_eq!(std::fs::read_to_string(&app.prd_path).unwrap(), original);
+        // The truncated file couldn't be put back, so that restore isn't undoable
+        assert!(app.prd_journal.is_empty());
+    }
+
+    #[test]
+    fn u_z_undoes_a_restore_and_u_l_shows_the_edit_log() {
+        let mut app = test_app("undo");
+        crate::backup::backup_prd(&app.task_dir, &app.prd_path).unwrap();
+        let edited = std::fs::read_to_string(&app.prd_path).unwrap().replace("\"passes\": false", "\"passes\": true");
+        std::fs::write(&app.prd_path, &edited).unwrap();
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('u')), &mut app);
+        assert!(app.snapshot().toast.unwrap().contains("l: Edit log"));
+        handle_key(Mode::Ralph, key(KeyCode::Char('y')), &mut app);
+        assert_eq!(app.prd_journal.len(), 1);
+        assert!(!app.prd.as_ref().unwrap().user_stories[1].passes);
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('u')), &mut app);
+        assert!(app.snapshot().toast.unwrap().contains("z: Undo restore prd-"));
+        handle_key(Mode::Ralph, key(KeyCode::Char('z')), &mut app);
+        assert!(app.prd_journal[0].undone);
+        assert!(app.prd.as_ref().unwrap().user_stories[1].passes);
+        assert!(!app.undo_prefix);
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('u')), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Char('l')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Journal);
+        assert_eq!(app.snapshot().prd_journal.len(), 1);
+    }
+
+    #[test]
+    fn esc_dismisses_sync_error_during_delay() {
+        let mut app = test_app("delay");
+        app.sync_error = Some("conflict".to_string());
+        assert_eq!(handle_delay_key(key(KeyCode::Esc), &mut app), Action::None);
+        assert!(app.sync_error.is_none());
+        assert!(app.delay_start.is_some());
+    }
+
+    #[test]
+    fn enter_opens_quick_actions_on_the_selected_story() {
+        let mut app = test_app("story-menu");
+        handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app);
+        assert_eq!(app.story_menu, Some(0));
+        // j/k move inside the menu instead of the story list
+        for _ in 0..4 {
+            handle_key(Mode::Ralph, key(KeyCode::Char('j')), &mut app);
+        }
+        assert_eq!(app.selected_story_index, 0);
+        assert_eq!(StoryAction::ALL[app.story_menu.unwrap()], StoryAction::Skip);
+        assert_eq!(handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app), Action::None);
+        assert_eq!(app.story_menu, None);
+        assert!(app.skipped_stories.contains("US-001"));
+
+        handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Up), &mut app);
+        assert_eq!(handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app), Action::Copy("US-001".to_string()));
+
+        handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Down), &mut app);
+        assert_eq!(handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app), Action::EditNotes("US-001".to_string()));
+
+        handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Esc), &mut app);
+        assert_eq!(app.story_menu, None);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Normal);
+    }
+
+    #[test]
+    fn t_lists_iteration_markers_and_r_asks_before_rolling_back() {
+        let mut app = test_app("rollback-keys");
+        for iteration in 1..=2 {
+            app.iteration_markers.push(crate::git::IterationMarker {
+                iteration,
+                tag: format!("ralph/iter-{}-start", iteration),
+                head: "0123456789abcdef".to_string(),
+                stash: None,
+                at: 0,
+            });
+        }
+        app.selected_marker = 1;
+
+        // R does nothing outside the iterations view
+        handle_key(Mode::Ralph, key(KeyCode::Char('R')), &mut app);
+        assert!(!app.rollback_armed);
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('t')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Iterations);
+        handle_key(Mode::Ralph, key(KeyCode::Char('l')), &mut app);
+        assert_eq!(app.selected_marker, 0);
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('R')), &mut app);
+        assert!(app.rollback_armed);
+        let toast = app.snapshot().toast.unwrap();
+        assert!(toast.contains("iteration 1 (0123456)") && toast.contains("git reset --hard"));
+        // A lowercase y is not enough: it cancels like any other key
+        handle_key(Mode::Ralph, key(KeyCode::Char('y')), &mut app);
+        assert!(!app.rollback_armed);
+        assert_eq!(app.iteration_markers.len(), 2);
+    }
+
+    #[test]
+    fn git_keys_explain_themselves_outside_a_repo() {
+        let mut app = test_app("no-git-keys");
+        app.git_available = false;
+        app.repo_root = None;
+        handle_key(Mode::Ralph, key(KeyCode::Char('m')), &mut app);
+        assert_eq!(app.active_toast(), Some("Sync unavailable — not a git repo"));
+        assert!(!app.sync_requested);
+
+        app.ralph_view_mode = RalphViewMode::Iterations;
+        handle_key(Mode::Ralph, key(KeyCode::Char('R')), &mut app);
+        assert_eq!(app.active_toast(), Some("Rollback unavailable — not a git repo"));
+        assert!(!app.rollback_armed);
+
+        handle_delay_key(key(KeyCode::Char('m')), &mut app);
+        assert!(app.sync_job.is_none());
+    }
+
+    #[test]
+    fn plus_and_minus_cycle_the_ralph_terminal_size() {
+        let mut app = test_app("ralph-size");
+        assert_eq!(app.ralph_size, RalphTerminalSize::Normal);
+        handle_key(Mode::Ralph, key(KeyCode::Char('+')), &mut app);
+        assert_eq!(app.ralph_size, RalphTerminalSize::Large);
+        handle_key(Mode::Ralph, key(KeyCode::Char('=')), &mut app);
+        assert_eq!(app.ralph_size, RalphTerminalSize::Collapsed);
+        handle_key(Mode::Ralph, key(KeyCode::Char('-')), &mut app);
+        assert_eq!(app.ralph_size, RalphTerminalSize::Large);
+        // Remembered for the next session on this task
+        assert_eq!(SessionState::load(&app.task_dir).ralph_terminal_size, RalphTerminalSize::Large);
+
+        // Claude mode forwards the keys instead
+        handle_key(Mode::Claude, key(KeyCode::Char('-')), &mut app);
+        assert_eq!(app.ralph_size, RalphTerminalSize::Large);
+    }
+
+    #[test]
+    fn b_hides_the_sidebar_and_story_keys_explain_themselves() {
+        let mut app = test_app("sidebar");
+        assert!(!app.sidebar_hidden);
+        handle_key(Mode::Ralph, key(KeyCode::Char('b')), &mut app);
+        assert!(app.sidebar_hidden);
+        // Remembered for the next session on this task
+        assert!(SessionState::load(&app.task_dir).sidebar_hidden);
+
+        // Story list keys only show how to bring the sidebar back
+        let selected = app.selected_story_index;
+        for code in [KeyCode::Char('j'), KeyCode::Down, KeyCode::Char('x'), KeyCode::Char(' '), KeyCode::Enter] {
+            app.toast = None;
+            handle_key(Mode::Ralph, key(code), &mut app);
+            assert_eq!(app.active_toast(), Some("Story list hidden - press b to show the sidebar"), "{:?}", code);
+        }
+        assert_eq!(app.selected_story_index, selected);
+        assert!(app.story_menu.is_none());
+        assert!(app.session_state.targeted_stories.is_empty());
+
+        handle_key(Mode::Ralph, key(KeyCode::Char('b')), &mut app);
+        assert!(!app.sidebar_hidden);
+        assert!(!SessionState::load(&app.task_dir).sidebar_hidden);
+        handle_key(Mode::Ralph, key(KeyCode::Char('j')), &mut app);
+        assert_ne!(app.selected_story_index, selected);
+    }
+
+    #[test]
+    fn v_lists_this_iterations_changes_and_enter_shows_a_diff() {
+        let mut app = test_app("changes");
+        let repo = app.task_dir.clone();
+        let run = |args: &[&str]| assert!(std::process::Command::new("git").args(args).current_dir(&repo).status().unwrap().success());
+        run(&["init", "--quiet"]);
+        std::fs::write(repo.join("a.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(repo.join("b.txt"), "keep\n").unwrap();
+        run(&["add", "."]);
+        run(&["-c", "user.email=ralph@example.com", "-c", "user.name=ralph", "commit", "--quiet", "-m", "init"]);
+        // Uncommitted before the iteration: not counted as this iteration's change
+        std::fs::write(repo.join("b.txt"), "keep\nearlier\n").unwrap();
+        app.repo_root = Some(repo.clone());
+        app.current_iteration = 4;
+        app.record_iteration_base();
+
+        std::fs::write(repo.join("a.txt"), "one\n2\nthree\n").unwrap();
+        handle_key(Mode::Ralph, key(KeyCode::Char('v')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Changes);
+        let changes = app.snapshot().iteration_changes.unwrap();
+        assert_eq!(changes.iteration, 4);
+        let files = changes.files.as_ref().unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].path.as_str(), files[0].added, files[0].removed), ("a.txt", Some(2), Some(1)));
+        assert_eq!(app.selected_file_reference(), Some((repo.join("a.txt").display().to_string(), None)));
+
+        handle_key(Mode::Ralph, key(KeyCode::Enter), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::ChangeDiff);
+        let diff = app.snapshot().change_diff.unwrap();
+        let lines = diff.lines.as_ref().unwrap();
+        assert!(lines.contains(&"-two".to_string()) && lines.contains(&"+2".to_string()) && lines.contains(&"+three".to_string()));
+        handle_key(Mode::Ralph, key(KeyCode::Esc), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Changes);
+
+        // Cached for the iteration: git doesn't run again until the next one starts
+        std::fs::write(repo.join("c.txt"), "new\n").unwrap();
+        run(&["add", "c.txt"]);
+        handle_key(Mode::Ralph, key(KeyCode::Char('v')), &mut app);
+        assert_eq!(app.ralph_view_mode, RalphViewMode::Normal);
+        handle_key(Mode::Ralph, key(KeyCode::Char('v')), &mut app);
+        assert_eq!(app.snapshot().iteration_changes.unwrap().files.as_ref().unwrap().len(), 1);
+        app.record_iteration_base();
+        handle_key(Mode::Ralph, key(KeyCode::Char('v')), &mut app);
+        handle_key(Mode::Ralph, key(KeyCode::Char('v')), &mut app);
+        assert!(app.snapshot().iteration_changes.unwrap().files.as_ref().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+//! Journal of the prd.json edits made from the TUI this session
+//!
+//! Each edit records the value it replaced, addressed by a JSON pointer ("" for
+//! the whole file, e.g. a backup restore), so the latest edit can be undone through
+//! `backup::write_prd` like any other write. On exit the journal is appended to the
+//! iteration log, which lets a post-mortem tell human edits from the agent's.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::app::ITERATION_LOG_FILE;
+use crate::backup::write_prd;
+
+/// Longest old/new value written to the iteration log (longer ones are cut)
+const MAX_LOGGED_VALUE: usize = 120;
+
+/// One TUI-initiated change to prd.json
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrdEdit {
+    /// Unix timestamp in seconds
+    pub at: u64,
+    /// What changed, for display ("restore prd-1700000000000.json")
+    pub label: String,
+    /// JSON pointer to the changed value ("" for the whole document)
+    pub pointer: String,
+    pub old: Value,
+    pub new: Value,
+    /// Reverted with undo
+    pub undone: bool,
+}
+
+impl PrdEdit {
+    pub fn new(label: impl Into<String>, pointer: impl Into<String>, old: Value, new: Value) -> Self {
+        let at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self { at, label: label.into(), pointer: pointer.into(), old, new, undone: false }
+    }
+
+    /// Iteration log line: `<timestamp> tui_edit field="..." pointer=... old=... new=... undone=...`
+    pub fn log_line(&self) -> String {
+        format!(
+            "{} tui_edit field={} pointer={} old={} new={} undone={}\n",
+            self.at,
+            Value::String(self.label.clone()),
+            if self.pointer.is_empty() { "/" } else { &self.pointer },
+            logged_value(&self.old),
+            logged_value(&self.new),
+            self.undone
+        )
+    }
+}
+
+/// Compact JSON for the log, cut to MAX_LOGGED_VALUE characters
+fn logged_value(value: &Value) -> String {
+    let json = value.to_string();
+    match json.char_indices().nth(MAX_LOGGED_VALUE) {
+        Some((end, _)) => format!("{}…", &json[..end]),
+        None => json,
+    }
+}
+
+/// Read prd.json as a JSON document
+pub fn read_prd_value(prd_path: &Path) -> io::Result<Value> {
+    let content = std::fs::read_to_string(prd_path)?;
+    serde_json::from_str(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("prd.json is not valid JSON: {}", e)))
+}
+
+/// Replace the value at `pointer` in prd.json (backed up first) and return the old value
+pub fn set_prd_value(task_dir: &Path, prd_path: &Path, pointer: &str, value: Value) -> io::Result<Value> {
+    let mut doc = read_prd_value(prd_path)?;
+    let slot = doc
+        .pointer_mut(pointer)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} not found in prd.json", pointer)))?;
+    let old = std::mem::replace(slot, value);
+    let content = serde_json::to_string_pretty(&doc).map_err(io::Error::other)?;
+    write_prd(task_dir, prd_path, &format!("{}\n", content))?;
+    Ok(old)
+}
+
+/// Put back the old value of the latest edit that hasn't been undone
+/// Refuses when the value has changed since (e.g. the agent rewrote it). Returns the
+/// index of the undone edit.
+pub fn undo_last(journal: &mut [PrdEdit], task_dir: &Path, prd_path: &Path) -> io::Result<usize> {
+    let index = journal
+        .iter()
+        .rposition(|edit| !edit.undone)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "nothing to undo"))?;
+    let edit = &journal[index];
+    let current = read_prd_value(prd_path)?;
+    if current.pointer(&edit.pointer) != Some(&edit.new) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} changed since, not undone", edit.label),
+        ));
+    }
+    set_prd_value(task_dir, prd_path, &edit.pointer, edit.old.clone())?;
+    journal[index].undone = true;
+    Ok(index)
+}
+
+/// Append the session's edits to the iteration log (nothing when there are none)
+pub fn append_to_iteration_log(task_dir: &Path, journal: &[PrdEdit]) {
+    if journal.is_empty() {
+        return;
+    }
+    let lines: String = journal.iter().map(PrdEdit::log_line).collect();
+    if let Ok(mut file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(task_dir.join(ITERATION_LOG_FILE))
+    {
+        let _ = file.write_all(lines.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    const PRD: &str = r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "v1",
+        "userStories": [{"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": false, "notes": ""}]}"#;
+
+    fn task_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("journal-{}", name));
+        std::fs::write(dir.join("prd.json"), PRD).unwrap();
+        dir
+    }
+
+    #[test]
+    fn undo_puts_back_the_old_value_once() {
+        let dir = task_dir("undo");
+        let prd_path = dir.join("prd.json");
+        let old = set_prd_value(&dir, &prd_path, "/userStories/0/passes", Value::Bool(true)).unwrap();
+        let mut journal = vec![PrdEdit::new("US-1 passes", "/userStories/0/passes", old, Value::Bool(true))];
+        assert_eq!(read_prd_value(&prd_path).unwrap()["userStories"][0]["passes"], true);
+        // The write went through the backup path
+        assert_eq!(crate::backup::list_backups(&dir).len(), 1);
+
+        assert_eq!(undo_last(&mut journal, &dir, &prd_path).unwrap(), 0);
+        assert_eq!(read_prd_value(&prd_path).unwrap()["userStories"][0]["passes"], false);
+        assert!(journal[0].undone);
+        assert!(undo_last(&mut journal, &dir, &prd_path).is_err());
+
+        append_to_iteration_log(&dir, &journal);
+        let log = std::fs::read_to_string(dir.join(ITERATION_LOG_FILE)).unwrap();
+        assert!(log.ends_with(" tui_edit field=\"US-1 passes\" pointer=/userStories/0/passes old=false new=true undone=true\n"));
+    }
+
+    #[test]
+    fn undo_refuses_values_changed_since() {
+        let dir = task_dir("changed");
+        let prd_path = dir.join("prd.json");
+        let old = set_prd_value(&dir, &prd_path, "/description", Value::from("v2")).unwrap();
+        let mut journal = vec![PrdEdit::new("description", "/description", old, Value::from("v2"))];
+        set_prd_value(&dir, &prd_path, "/description", Value::from("agent")).unwrap();
+        assert!(undo_last(&mut journal, &dir, &prd_path).is_err());
+        assert!(!journal[0].undone);
+        assert_eq!(read_prd_value(&prd_path).unwrap()["description"], "agent");
+        assert!(set_prd_value(&dir, &prd_path, "/missing", Value::Null).is_err());
+    }
+}
@@ -0,0 +1,452 @@
+//! Remappable key bindings
+//!
+//! The main Ralph-mode keys (and quit / leaving Claude mode) are looked up in a
+//! `KeyMap` instead of being matched directly. The `[keys]` table in
+//! `~/.config/ralph/ralph.toml` maps action names to one chord or a list of
+//! chords, replacing that action's defaults:
+//!
+//! ```toml
+//! [keys]
+//! enter_claude_mode = "a"
+//! story_up = ["up", "ctrl+p"]
+//! story_down = ["down", "ctrl+n"]
+//! ```
+//!
+//! Keys that only mean something inside one view (h/l, e, R, Enter, Esc, the u
+//! and R prefixes) keep their fixed bindings.
+
+use std::collections::BTreeMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+/// Something a remappable key does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyAction {
+    EnterClaudeMode,
+    ExitClaudeMode,
+    Quit,
+    StoryUp,
+    StoryDown,
+    ToggleStoryDetails,
+    ToggleProgress,
+    ToggleRequirements,
+    ToggleErrors,
+    ToggleIterations,
+    ToggleActivity,
+    ToggleLinks,
+    ToggleDiff,
+    ToggleChanges,
+    ToggleHealth,
+    ToggleProgressStrip,
+    ToggleSidebar,
+    ToggleLayout,
+    ToggleCriteria,
+    ToggleTarget,
+    ClearTargets,
+    JumpToCurrent,
+    ToggleFollow,
+    UndoMenu,
+    Sync,
+    OpenFile,
+    ScrollUp,
+    ScrollDown,
+    GrowTerminal,
+    ShrinkTerminal,
+    WindDown,
+    FreshSession,
+    ResyncPty,
+}
+
+impl KeyAction {
+    pub const ALL: [KeyAction; 33] = [
+        KeyAction::EnterClaudeMode,
+        KeyAction::ExitClaudeMode,
+        KeyAction::Quit,
+        KeyAction::StoryUp,
+        KeyAction::StoryDown,
+        KeyAction::ToggleStoryDetails,
+        KeyAction::ToggleProgress,
+        KeyAction::ToggleRequirements,
+        KeyAction::ToggleErrors,
+        KeyAction::ToggleIterations,
+        KeyAction::ToggleActivity,
+        KeyAction::ToggleLinks,
+        KeyAction::ToggleDiff,
+        KeyAction::ToggleChanges,
+        KeyAction::ToggleHealth,
+        KeyAction::ToggleProgressStrip,
+        KeyAction::ToggleSidebar,
+        KeyAction::ToggleLayout,
+        KeyAction::ToggleCriteria,
+        KeyAction::ToggleTarget,
+        KeyAction::ClearTargets,
+        KeyAction::JumpToCurrent,
+        KeyAction::ToggleFollow,
+        KeyAction::UndoMenu,
+        KeyAction::Sync,
+        KeyAction::OpenFile,
+        KeyAction::ScrollUp,
+        KeyAction::ScrollDown,
+        KeyAction::GrowTerminal,
+        KeyAction::ShrinkTerminal,
+        KeyAction::WindDown,
+        KeyAction::FreshSession,
+        KeyAction::ResyncPty,
+    ];
+
+    /// Name used in the [keys] table
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyAction::EnterClaudeMode => "enter_claude_mode",
+            KeyAction::ExitClaudeMode => "exit_claude_mode",
+            KeyAction::Quit => "quit",
+            KeyAction::StoryUp => "story_up",
+            KeyAction::StoryDown => "story_down",
+            KeyAction::ToggleStoryDetails => "toggle_story_details",
+            KeyAction::ToggleProgress => "toggle_progress",
+            KeyAction::ToggleRequirements => "toggle_requirements",
+            KeyAction::ToggleErrors => "toggle_errors",
+            KeyAction::ToggleIterations => "toggle_iterations",
+            KeyAction::ToggleActivity => "toggle_activity",
+            KeyAction::ToggleLinks => "toggle_links",
+            KeyAction::ToggleDiff => "toggle_diff",
+            KeyAction::ToggleChanges => "toggle_changes",
+            KeyAction::ToggleHealth => "toggle_health",
+            KeyAction::ToggleProgressStrip => "toggle_progress_strip",
+            KeyAction::ToggleSidebar => "toggle_sidebar",
+            KeyAction::ToggleLayout => "toggle_layout",
+            KeyAction::ToggleCriteria => "toggle_criteria",
+            KeyAction::ToggleTarget => "toggle_target",
+            KeyAction::ClearTargets => "clear_targets",
+            KeyAction::JumpToCurrent => "jump_to_current",
+            KeyAction::ToggleFollow => "toggle_follow",
+            KeyAction::UndoMenu => "undo_menu",
+            KeyAction::Sync => "sync",
+            KeyAction::OpenFile => "open_file",
+            KeyAction::ScrollUp => "scroll_up",
+            KeyAction::ScrollDown => "scroll_down",
+            KeyAction::GrowTerminal => "grow_terminal",
+            KeyAction::ShrinkTerminal => "shrink_terminal",
+            KeyAction::WindDown => "wind_down",
+            KeyAction::FreshSession => "fresh_session",
+            KeyAction::ResyncPty => "resync_pty",
+        }
+    }
+
+    /// The built-in chords (the documented baseline)
+    pub fn default_chords(self) -> &'static [&'static str] {
+        match self {
+            KeyAction::EnterClaudeMode => &["i", "tab"],
+            KeyAction::ExitClaudeMode => &["ctrl+o"],
+            KeyAction::Quit => &["ctrl+q"],
+            KeyAction::StoryUp => &["up", "k"],
+            KeyAction::StoryDown => &["down", "j"],
+            KeyAction::ToggleStoryDetails => &["s"],
+            KeyAction::ToggleProgress => &["p"],
+            KeyAction::ToggleRequirements => &["r"],
+            KeyAction::ToggleErrors => &["!"],
+            KeyAction::ToggleIterations => &["t"],
+            KeyAction::ToggleActivity => &["A"],
+            KeyAction::ToggleLinks => &["L"],
+            KeyAction::ToggleDiff => &["D"],
+            KeyAction::ToggleChanges => &["v"],
+            KeyAction::ToggleHealth => &["H"],
+            KeyAction::ToggleProgressStrip => &["P"],
+            KeyAction::ToggleSidebar => &["b"],
+            KeyAction::ToggleLayout => &["g"],
+            KeyAction::ToggleCriteria => &["x"],
+            KeyAction::ToggleTarget => &["space"],
+            KeyAction::ClearTargets => &["C"],
+            KeyAction::JumpToCurrent => &["c"],
+            KeyAction::ToggleFollow => &["F"],
+            KeyAction::UndoMenu => &["u"],
+            KeyAction::Sync => &["m"],
+            KeyAction::OpenFile => &["o"],
+            KeyAction::ScrollUp => &["pageup", "K"],
+            KeyAction::ScrollDown => &["pagedown", "J"],
+            KeyAction::GrowTerminal => &["+", "="],
+            KeyAction::ShrinkTerminal => &["-"],
+            KeyAction::WindDown => &["_"],
+            KeyAction::FreshSession => &["N"],
+            KeyAction::ResyncPty => &["ctrl+l"],
+        }
+    }
+
+    fn from_name(name: &str) -> Option<KeyAction> {
+        KeyAction::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// One key with its Ctrl/Alt/Shift modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyChord {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+/// Named keys accepted in chords, with their display labels
+const NAMED_KEYS: [(&str, KeyCode, &str); 12] = [
+    ("up", KeyCode::Up, "↑"),
+    ("down", KeyCode::Down, "↓"),
+    ("left", KeyCode::Left, "←"),
+    ("right", KeyCode::Right, "→"),
+    ("enter", KeyCode::Enter, "⏎"),
+    ("tab", KeyCode::Tab, "Tab"),
+    ("space", KeyCode::Char(' '), "␣"),
+    ("esc", KeyCode::Esc, "Esc"),
+    ("pageup", KeyCode::PageUp, "PgUp"),
+    ("pagedown", KeyCode::PageDown, "PgDn"),
+    ("home", KeyCode::Home, "Home"),
+    ("end", KeyCode::End, "End"),
+];
+
+impl KeyChord {
+    /// Parse "k", "K", "ctrl+o", "alt+enter", "shift+tab", "pageup", "f5"
+    /// Letters are case-sensitive ("K" is Shift+k); modifier and key names are not.
+    pub fn parse(text: &str) -> Result<KeyChord, String> {
+        let invalid = || format!("invalid key \"{}\"", text);
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = text;
+        // A lone "+" is a key, so only split while something follows the separator
+        while let Some((modifier, key)) = rest.split_once('+').filter(|(_, key)| !key.is_empty()) {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(invalid()),
+            };
+            rest = key;
+        }
+        let lower = rest.to_ascii_lowercase();
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if !c.is_control() => KeyCode::Char(c),
+            _ => match NAMED_KEYS.iter().find(|(name, _, _)| *name == lower) {
+                Some((_, code, _)) => *code,
+                None => match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(invalid()),
+                },
+            },
+        };
+        Ok(KeyChord { code, modifiers })
+    }
+
+    /// Whether a key press is this chord
+    /// Shift is part of a letter's case, so it only counts for keys without one.
+    pub fn matches(&self, key: &KeyEvent) -> bool {
+        match (self.code, key.code) {
+            (KeyCode::Char(want), KeyCode::Char(got)) => {
+                let mask = KeyModifiers::CONTROL | KeyModifiers::ALT;
+                let same_key = if self.modifiers.contains(KeyModifiers::CONTROL) {
+                    want.eq_ignore_ascii_case(&got)
+                } else {
+                    want == got
+                };
+                same_key && key.modifiers & mask == self.modifiers & mask
+            }
+            (want, got) => want == got && key.modifiers == self.modifiers,
+        }
+    }
+
+    /// Short label for hints and the footer ("^O", "↑", "␣", "K")
+    pub fn label(&self) -> String {
+        let key = match self.code {
+            KeyCode::Char(c) if self.modifiers.contains(KeyModifiers::CONTROL) => c.to_ascii_uppercase().to_string(),
+            KeyCode::F(n) => format!("F{}", n),
+            code => match NAMED_KEYS.iter().find(|(_, named, _)| *named == code) {
+                Some((_, _, label)) => label.to_string(),
+                None => match code {
+                    KeyCode::Char(c) => c.to_string(),
+                    other => format!("{:?}", other),
+                },
+            },
+        };
+        let mut prefix = String::new();
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            prefix.push('^');
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            prefix.push_str("Alt+");
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            prefix.push_str("Shift+");
+        }
+        format!("{}{}", prefix, key)
+    }
+}
+
+/// One `[keys]` entry: a chord or a list of chords
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum KeyBinding {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The chords bound to each action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: Vec<(KeyAction, Vec<KeyChord>)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let bindings = KeyAction::ALL
+            .into_iter()
+            .map(|action| {
+                let chords = action.default_chords().iter().map(|c| KeyChord::parse(c).expect("valid default chord"));
+                (action, chords.collect())
+            })
+            .collect();
+        Self { bindings }
+    }
+}
+
+impl KeyMap {
+    /// The action bound to a key press, if any
+    pub fn action(&self, key: &KeyEvent) -> Option<KeyAction> {
+        self.bindings
+            .iter()
+            .find(|(_, chords)| chords.iter().any(|chord| chord.matches(key)))
+            .map(|(action, _)| *action)
+    }
+
+    /// Whether a key press is bound to `action`
+    pub fn is(&self, action: KeyAction, key: &KeyEvent) -> bool {
+        self.chords(action).iter().any(|chord| chord.matches(key))
+    }
+
+    pub fn chords(&self, action: KeyAction) -> &[KeyChord] {
+        self.bindings.iter().find(|(a, _)| *a == action).map_or(&[], |(_, chords)| chords.as_slice())
+    }
+
+    /// Label of the action's first chord ("—" when it is unbound)
+    pub fn label(&self, action: KeyAction) -> String {
+        self.chords(action).first().map_or_else(|| "—".to_string(), KeyChord::label)
+    }
+
+    /// Labels for an up/down pair: "↑↓ or j/k" with the defaults
+    pub fn pair_label(&self, up: KeyAction, down: KeyAction) -> String {
+        let pairs: Vec<String> = self
+            .chords(up)
+            .iter()
+            .zip(self.chords(down))
+            .map(|(up, down)| match (up.code, down.code) {
+                (KeyCode::Up, KeyCode::Down) if up.modifiers.is_empty() && down.modifiers.is_empty() => "↑↓".to_string(),
+                _ => format!("{}/{}", down.label(), up.label()),
+            })
+            .collect();
+        if pairs.is_empty() {
+            format!("{}/{}", self.label(down), self.label(up))
+        } else {
+            pairs.join(" or ")
+        }
+    }
+
+    /// Apply the [keys] table: each listed action's chords replace its defaults
+    /// Errors name the offending entry.
+    pub fn from_bindings(keys: &BTreeMap<String, KeyBinding>) -> Result<KeyMap, String> {
+        let mut map = KeyMap::default();
+        let mut remapped = Vec::new();
+        for (name, binding) in keys {
+            let at_entry = |message: String| format!("keys.{}: {}", name, message);
+            let action = KeyAction::from_name(name).ok_or_else(|| at_entry(format!("unknown action \"{}\"", name)))?;
+            let texts: Vec<&str> = match binding {
+                KeyBinding::One(key) => vec![key.as_str()],
+                KeyBinding::Many(keys) => keys.iter().map(String::as_str).collect(),
+            };
+            if texts.is_empty() {
+                return Err(at_entry("needs a key or a list of keys".to_string()));
+            }
+            let chords = texts.into_iter().map(KeyChord::parse).collect::<Result<Vec<_>, _>>().map_err(at_entry)?;
+            if let Some(entry) = map.bindings.iter_mut().find(|(a, _)| *a == action) {
+                entry.1 = chords;
+            }
+            remapped.push(action);
+        }
+        // A chord can only do one thing; report it on the remapped entry
+        for (index, (action, chords)) in map.bindings.iter().enumerate() {
+            for chord in chords {
+                if let Some((other, _)) = map.bindings[index + 1..].iter().find(|(_, c)| c.contains(chord)) {
+                    let culprit = if remapped.contains(other) { *other } else { *action };
+                    return Err(format!(
+                        "keys.{}: {} is bound to both {} and {}",
+                        culprit.name(),
+                        chord.label(),
+                        action.name(),
+                        other.name()
+                    ));
+                }
+            }
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn chords_parse_match_and_label() {
+        let ctrl_o = KeyChord::parse("ctrl+o").unwrap();
+        assert!(ctrl_o.matches(&press(KeyCode::Char('o'), KeyModifiers::CONTROL)));
+        assert!(!ctrl_o.matches(&press(KeyCode::Char('o'), KeyModifiers::NONE)));
+        assert_eq!(ctrl_o.label(), "^O");
+
+        // Letters are case-sensitive and carry Shift in their case
+        let big_k = KeyChord::parse("K").unwrap();
+        assert!(big_k.matches(&press(KeyCode::Char('K'), KeyModifiers::SHIFT)));
+        assert!(!big_k.matches(&press(KeyCode::Char('k'), KeyModifiers::NONE)));
+
+        assert_eq!(KeyChord::parse("+").unwrap().code, KeyCode::Char('+'));
+        assert_eq!(KeyChord::parse("Alt+Enter").unwrap().label(), "Alt+⏎");
+        assert_eq!(KeyChord::parse("f5").unwrap().code, KeyCode::F(5));
+        assert_eq!(KeyChord::parse("space").unwrap().label(), "␣");
+        for bad in ["", "ctrl+", "hyper+k", "pgup", "f13"] {
+            assert!(KeyChord::parse(bad).is_err(), "{:?} should not parse", bad);
+        }
+    }
+
+    #[test]
+    fn default_map_is_the_documented_baseline() {
+        let map = KeyMap::default();
+        assert_eq!(map.action(&press(KeyCode::Char('i'), KeyModifiers::NONE)), Some(KeyAction::EnterClaudeMode));
+        assert_eq!(map.action(&press(KeyCode::Char('q'), KeyModifiers::CONTROL)), Some(KeyAction::Quit));
+        assert_eq!(map.action(&press(KeyCode::Char('J'), KeyModifiers::SHIFT)), Some(KeyAction::ScrollDown));
+        assert_eq!(map.action(&press(KeyCode::Char('q'), KeyModifiers::NONE)), None);
+        assert_eq!(map.pair_label(KeyAction::StoryUp, KeyAction::StoryDown), "↑↓ or j/k");
+        assert_eq!(map.label(KeyAction::ExitClaudeMode), "^O");
+        // Every default chord parses and none is bound twice
+        assert_eq!(KeyMap::from_bindings(&BTreeMap::new()), Ok(map));
+    }
+
+    /// The [keys] table of a ralph.toml
+    fn keys(toml: &str) -> Result<KeyMap, String> {
+        KeyMap::from_bindings(&crate::config::parse_config(toml)?.keys)
+    }
+
+    #[test]
+    fn keys_table_replaces_defaults_and_names_bad_entries() {
+        let map = keys("[keys]\nenter_claude_mode = \"a\"\nstory_up = [\"ctrl+p\", \"k\"]").unwrap();
+        assert_eq!(map.action(&press(KeyCode::Char('a'), KeyModifiers::NONE)), Some(KeyAction::EnterClaudeMode));
+        assert_eq!(map.action(&press(KeyCode::Char('i'), KeyModifiers::NONE)), None);
+        assert_eq!(map.label(KeyAction::EnterClaudeMode), "a");
+        assert_eq!(map.pair_label(KeyAction::StoryUp, KeyAction::StoryDown), "↓/^P or j/k");
+
+        assert_eq!(keys("[keys]\nquit = \"ctrl+\""), Err("keys.quit: invalid key \"ctrl+\"".to_string()));
+        assert_eq!(keys("[keys]\njump = \"z\""), Err("keys.jump: unknown action \"jump\"".to_string()));
+        assert_eq!(
+            keys("[keys]\nquit = \"q\"\ntoggle_layout = \"s\""),
+            Err("keys.toggle_layout: s is bound to both toggle_story_details and toggle_layout".to_string())
+        );
+        assert_eq!(keys("[keys]\nquit = []"), Err("keys.quit: needs a key or a list of keys".to_string()));
+        assert!(keys("[keys]\nquit = 5").is_err());
+    }
+}
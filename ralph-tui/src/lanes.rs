@@ -0,0 +1,196 @@
+//! Experimental parallel mode (--parallel N): one agent per lane of stories
+//!
+//! The incomplete stories are split into lanes (prd.json `lane` field, otherwise
+//! balanced round-robin; see `Prd::partition_lanes`). Each lane runs its own agent
+//! in its own PTY with a prompt limited to the lane's stories, and iterates on its
+//! own schedule. The session completes once every lane's stories pass.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use portable_pty::{Child, MasterPty, PtySize};
+use ratatui::text::Line;
+use regex::Regex;
+
+use crate::app::IterationState;
+use crate::prd::Prd;
+use crate::pty::{detect_agent_activity, lock_pty, AgentActivity, PendingPrompt, PtyState, PtyWriter};
+use crate::render::terminal::render_vt100_screen;
+
+/// Most agents --parallel runs at once
+pub const MAX_LANES: usize = 4;
+
+/// One lane: its stories, its agent and its own iteration state
+pub struct Lane {
+    pub stories: BTreeSet<String>,
+    pub pty_state: Arc<Mutex<PtyState>>,
+    pub master_pty: Option<Box<dyn MasterPty + Send>>,
+    pub pty_writer: Option<PtyWriter>,
+    /// A prompt too long for argv, typed in once the agent is ready for a paste
+    pub pending_prompt: Option<PendingPrompt>,
+    pub child: Option<Box<dyn Child + Send + Sync>>,
+    pub reader_thread: Option<JoinHandle<()>>,
+    pub iteration: u32,
+    pub iteration_start: Instant,
+    /// Running, WaitingDelay (between iterations) or Completed (stories pass or budget spent)
+    pub state: IterationState,
+    pub delay_start: Option<Instant>,
+    pub agent_activity: AgentActivity,
+    /// Whether the agent was asked to wrap up after compacting its context this iteration
+    pub compaction_wrap_up_sent: bool,
+}
+
+impl Lane {
+    pub fn new(stories: BTreeSet<String>, rows: u16, cols: u16) -> Self {
+        Self {
+            stories,
+            pty_state: Arc::new(Mutex::new(PtyState::new(rows, cols))),
+            master_pty: None,
+            pty_writer: None,
+            pending_prompt: None,
+            child: None,
+            reader_thread: None,
+            iteration: 1,
+            iteration_start: Instant::now(),
+            state: IterationState::Running,
+            delay_start: None,
+            agent_activity: AgentActivity::Starting,
+            compaction_wrap_up_sent: false,
+        }
+    }
+
+    /// Control file the lane's stop hook writes (passed to the hook as RALPH_SIGNAL_FILE)
+    pub fn signal_file(index: usize) -> String {
+        format!(".ralph-signal-lane{}", index + 1)
+    }
+
+    /// Whether every story in the lane passes (stories removed from the PRD count as done)
+    pub fn stories_pass(&self, prd: &Prd) -> bool {
+        self.stories
+            .iter()
+            .all(|id| prd.user_stories.iter().find(|s| &s.id == id).is_none_or(|s| s.passes))
+    }
+
+    /// Kill the lane's agent and release its PTY
+    pub fn stop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        drop(self.master_pty.take());
+        drop(self.pty_writer.take());
+        if let Some(reader_thread) = self.reader_thread.take() {
+            let _ = reader_thread.join();
+        }
+    }
+
+    /// Resize the lane's PTY and screen
+    pub fn resize(&self, cols: u16, rows: u16) {
+        if let Some(ref master) = self.master_pty {
+            let _ = master.resize(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 });
+        }
+        lock_pty(&self.pty_state).parser.screen_mut().set_size(rows, cols);
+    }
+
+    /// Resize even when the size is unchanged, nudging it by a row so the agent gets a
+    /// SIGWINCH (see App::resync_pty)
+    pub fn resync(&self, cols: u16, rows: u16) {
+        if let Some(ref master) = self.master_pty {
+            let _ = master.resize(PtySize { rows: rows.saturating_add(1), cols, pixel_width: 0, pixel_height: 0 });
+        }
+        self.resize(cols, rows);
+    }
+
+    /// Queue bytes for the lane agent's stdin
+    pub fn write(&self, data: &[u8]) {
+        if let Some(ref writer) = self.pty_writer {
+            writer.send(data);
+        }
+    }
+
+    /// Type the pending prompt into the lane's agent once it's ready for a paste
+    pub fn deliver_pending_prompt(&mut self) -> io::Result<()> {
+        let (Some(pending), Some(writer)) = (self.pending_prompt.as_ref(), self.pty_writer.as_ref()) else {
+            return Ok(());
+        };
+        if pending.try_deliver(&self.pty_state, writer, Instant::now())? {
+            self.pending_prompt = None;
+        }
+        Ok(())
+    }
+
+    /// Refresh whether the lane's agent is working or idle at its prompt
+    pub fn update_agent_activity(&mut self, idle_after: Duration, prompt_pattern: &Regex) {
+        if self.state != IterationState::Running {
+            return;
+        }
+        let state = lock_pty(&self.pty_state);
+        let quiet_for = state.last_output_at.map(|t| t.elapsed());
+        self.agent_activity = detect_agent_activity(quiet_for, &state.last_visible_row(), idle_after, prompt_pattern);
+    }
+
+    /// Render-ready view of the lane
+    pub fn snapshot(&self, index: usize, focused: bool) -> LaneSnapshot {
+        let lines = render_vt100_screen(lock_pty(&self.pty_state).parser.screen());
+        LaneSnapshot {
+            label: format!("Lane {}", index + 1),
+            stories: self.stories.iter().cloned().collect(),
+            iteration: self.iteration,
+            state: self.state,
+            agent_activity: self.agent_activity,
+            lines,
+            focused,
+        }
+    }
+}
+
+/// A lane as drawn in the stacked Claude panes
+#[derive(Debug, Clone)]
+pub struct LaneSnapshot {
+    pub label: String,
+    pub stories: Vec<String>,
+    pub iteration: u32,
+    pub state: IterationState,
+    pub agent_activity: AgentActivity,
+    pub lines: Vec<Line<'static>>,
+    // Keyboard input goes to this lane in Claude mode
+    pub focused: bool,
+}
+
+/// Prompt section telling a lane's agent about the agents working beside it
+pub fn lane_prompt_section(index: usize, lanes: usize) -> String {
+    format!(
+        "\n\n## Parallel Lanes\n\n\
+         You are lane {lane} of {lanes}. Other agents are working on the other stories \
+         in this same checkout at the same time. Only edit files your stories need, \
+         commit only your own changes, and never revert or reformat work you didn't make. \
+         Re-read prd.json right before updating it, since another lane may have changed it.\n",
+        lane = index + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lane_completes_when_its_stories_pass() {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [
+                    {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": true, "notes": ""},
+                    {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": false, "notes": ""}
+                ]}"#,
+        )
+        .unwrap();
+        let lane = |ids: &[&str]| Lane::new(ids.iter().map(|id| id.to_string()).collect(), 24, 80);
+        assert!(lane(&["US-1"]).stories_pass(&prd));
+        assert!(!lane(&["US-1", "US-2"]).stories_pass(&prd));
+        // A story deleted from the PRD no longer holds the lane open
+        assert!(lane(&["US-1", "US-9"]).stories_pass(&prd));
+        assert_eq!(Lane::signal_file(1), ".ralph-signal-lane2");
+    }
+}
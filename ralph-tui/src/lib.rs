@@ -0,0 +1,147 @@
+//! Ralph TUI: an interactive terminal interface for the Ralph agent loop
+//!
+//! The binary (main.rs) parses the command line and drives the loop; everything it
+//! builds on lives in these modules so benches and tests can use them too.
+
+pub mod agents;
+pub mod app;
+pub mod backup;
+pub mod chain;
+pub mod changes;
+pub mod compat;
+pub mod config;
+pub mod conversation;
+pub mod crosscheck;
+pub mod desktop;
+pub mod digest;
+pub mod disk;
+pub mod editor;
+pub mod events;
+pub mod git;
+pub mod health;
+pub mod hooks;
+pub mod input;
+pub mod journal;
+pub mod keymap;
+pub mod lanes;
+pub mod links;
+pub mod metrics;
+pub mod preflight;
+pub mod prd;
+pub mod prd_diff;
+pub mod preview;
+pub mod pty;
+pub mod recovery;
+pub mod redraw;
+pub mod render;
+pub mod report;
+pub mod scope;
+pub mod shell;
+pub mod signals;
+pub mod status;
+pub mod tasks;
+pub mod testrun;
+pub mod theme;
+pub mod tips;
+pub mod tripwire;
+pub mod verify;
+#[cfg(test)]
+mod testutil;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use agents::AgentProfile;
+use compat::AgentVersion;
+use git::SyncStrategy;
+use keymap::KeyMap;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Where a setting's value came from (shown in the startup banner)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSource {
+    Flag,
+    PrdJson,
+    ConfigFile,
+    Default,
+    Prompt,
+}
+
+impl ValueSource {
+    pub fn label(self) -> &'static str {
+        match self {
+            ValueSource::Flag => "flag",
+            ValueSource::PrdJson => "prd.json",
+            ValueSource::ConfigFile => "ralph.toml",
+            ValueSource::Default => "default",
+            ValueSource::Prompt => "entered",
+        }
+    }
+}
+
+/// Configuration from CLI arguments
+#[derive(Clone)]
+pub struct CliConfig {
+    pub task_dir: PathBuf,
+    pub prd_path: PathBuf,
+    pub max_iterations: u32,
+    pub max_iterations_source: ValueSource,
+    pub rotate_threshold: u32,
+    pub rotate_threshold_source: ValueSource,
+    pub skip_prompts: bool,
+    pub events_socket: Option<PathBuf>,
+    pub metrics_addr: Option<SocketAddr>,
+    pub once: bool,
+    pub stuck_threshold: u32,
+    pub flat_after: u64,
+    pub min_free_mb: u64,
+    pub behind_warn: u32,
+    pub sync_strategy: SyncStrategy,
+    pub idle_after: u64,
+    pub prompt_pattern: Regex,
+    pub nudge_after: u64,
+    /// Seconds without output once the iteration's story passes before the iteration ends (0 = never)
+    pub prd_idle_after: u64,
+    /// How long an ended iteration stays on screen before the loop moves on
+    pub linger_ms: u64,
+    pub nudge_message: String,
+    pub end_on_compaction: bool,
+    /// Fold the agent's commits since mergeTarget into one when the task completes
+    pub squash_on_complete: bool,
+    pub agent_args: Vec<String>,
+    pub model: Option<String>,
+    pub agent: AgentProfile,
+    /// What `claude --version` reported (None for other agents or when it couldn't be read)
+    pub agent_version: Option<AgentVersion>,
+    pub keymap: KeyMap,
+    pub targeted_stories: Option<BTreeSet<String>>,
+    pub kitty_keys: bool,
+    pub key_guard: bool,
+    pub terminal_title: bool,
+    pub progress_strip: bool,
+    pub no_sidebar: bool,
+    pub parallel: Option<usize>,
+    pub max_runtime: Option<u64>,
+    pub error_patterns: Vec<String>,
+    pub error_bell: bool,
+    pub dangerous_commands: Vec<String>,
+    pub pause_on_prd_churn: bool,
+    pub show_pty_size: bool,
+    pub verify_criteria: bool,
+    pub verify_timeout_secs: u64,
+    /// Share of a criterion's words a `Verified:` note must contain (None = no cross-check)
+    pub verified_match: Option<f64>,
+    /// Show tips for unused features in the footer (off with hide_tips in ralph.toml)
+    pub footer_tips: bool,
+    /// Type every prompt into the agent's terminal (prompt_via_stdin in ralph.toml)
+    pub prompt_via_stdin: bool,
+    /// Variables for every agent from ralph.toml's `env` (prd.json's `env` overrides them)
+    pub env: BTreeMap<String, String>,
+    /// Hook commands from ralph.toml, for tasks whose prd.json doesn't set them
+    pub on_iteration_end: Option<String>,
+    pub on_complete: Option<String>,
+}
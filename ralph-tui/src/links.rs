@@ -0,0 +1,127 @@
+//! Links in the agent's output (the `L` view)
+//!
+//! URLs the agent prints, and the targets of the OSC 8 hyperlinks it emits, are
+//! collected into one list for the session: newest first, each link once, at most
+//! MAX_LINKS. The view copies the picked link (OSC 52) or opens it with `open` /
+//! `xdg-open`. On a terminal that understands OSC 8, cells showing a collected link
+//! are also turned into real hyperlinks once a frame is drawn (see
+//! `render::terminal::hyperlink_runs`).
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::pty::strip_ansi_codes;
+
+/// Links kept for the view
+pub const MAX_LINKS: usize = 50;
+
+/// Environment variable forcing OSC 8 hyperlinks on (1) or off (0)
+pub const HYPERLINKS_ENV: &str = "RALPH_HYPERLINKS";
+
+/// OSC 8 hyperlink start (`ESC ] 8 ; params ; URI` ended by BEL or ST); the end has an empty URI
+static OSC8_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\]8;[^;\x07\x1b]*;([^\x07\x1b]+)(?:\x07|\x1b\\)").expect("valid regex"));
+
+/// A plain http(s) URL (characters allowed in a URI; trailing punctuation is trimmed after)
+pub static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"https?://[A-Za-z0-9\-._~:/?#\[\]@!$&'()*+,;=%]+").expect("valid regex"));
+
+/// Ends the OSC 8 hyperlink started by `hyperlink_start`
+pub const HYPERLINK_END: &str = "\x1b]8;;\x1b\\";
+
+/// OSC 8 sequence making the text written after it a hyperlink to `url`
+pub fn hyperlink_start(url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\", url)
+}
+
+/// Drop punctuation that ends the sentence rather than the URL, and closing brackets
+/// that have no opening one inside it (`(see https://x.dev/a)` → `https://x.dev/a`)
+pub fn trim_url(url: &str) -> &str {
+    let mut url = url;
+    loop {
+        let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '*']);
+        let trimmed = match trimmed.chars().last() {
+            Some(')') if trimmed.matches('(').count() < trimmed.matches(')').count() => &trimmed[..trimmed.len() - 1],
+            Some(']') if trimmed.matches('[').count() < trimmed.matches(']').count() => &trimmed[..trimmed.len() - 1],
+            _ => trimmed,
+        };
+        if trimmed == url {
+            return url;
+        }
+        url = trimmed;
+    }
+}
+
+/// Links in a piece of raw output, in order: each line's OSC 8 targets, then the URLs it shows
+pub fn find_links(raw: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    for line in raw.split('\n') {
+        links.extend(OSC8_LINK.captures_iter(line).map(|caps| caps[1].to_string()));
+        let text = strip_ansi_codes(line);
+        let urls = URL.find_iter(&text).map(|url| trim_url(url.as_str())).filter(|url| !url.ends_with("://"));
+        links.extend(urls.map(str::to_string));
+    }
+    links
+}
+
+/// Put newly found links at the front of `links`, keeping each once and at most MAX_LINKS
+pub fn remember_links(links: &mut Vec<String>, found: Vec<String>) {
+    for link in found {
+        links.retain(|known| *known != link);
+        links.insert(0, link);
+    }
+    links.truncate(MAX_LINKS);
+}
+
+/// Whether the terminal ralph-tui runs in shows OSC 8 hyperlinks
+/// Terminals known to support them are detected from the environment (tmux only
+/// passes them on when configured to, so it needs RALPH_HYPERLINKS=1).
+pub fn terminal_supports_hyperlinks() -> bool {
+    let var = |name: &str| std::env::var(name).unwrap_or_default();
+    match var(HYPERLINKS_ENV).as_str() {
+        "0" => return false,
+        "1" => return true,
+        _ => {}
+    }
+    if std::env::var_os("TMUX").is_some() {
+        return false;
+    }
+    matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm" | "vscode" | "ghostty" | "Hyper")
+        || std::env::var_os("KITTY_WINDOW_ID").is_some()
+        || std::env::var_os("WT_SESSION").is_some()
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|version| version >= 5000)
+        || ["kitty", "foot", "alacritty"].iter().any(|name| var("TERM").contains(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_urls_and_hyperlink_targets() {
+        let raw = "See https://docs.rs/ratatui. and (https://en.wikipedia.org/wiki/Rust_(language))\n\
+                   \x1b]8;;https://github.com/o/r/pull/7\x1b\\PR #7\x1b]8;;\x1b\\ at \x1b[4mhttps://x.dev/a?b=1\x1b[0m";
+        assert_eq!(
+            find_links(raw),
+            [
+                "https://docs.rs/ratatui",
+                "https://en.wikipedia.org/wiki/Rust_(language)",
+                "https://github.com/o/r/pull/7",
+                "https://x.dev/a?b=1"
+            ]
+        );
+        assert!(find_links("no links, just http:// and words").is_empty());
+    }
+
+    #[test]
+    fn newest_links_come_first_once() {
+        let mut links = vec!["https://a.dev".to_string(), "https://b.dev".to_string()];
+        remember_links(&mut links, vec!["https://c.dev".to_string(), "https://b.dev".to_string()]);
+        assert_eq!(links, ["https://b.dev", "https://c.dev", "https://a.dev"]);
+
+        remember_links(&mut links, (0..60).map(|i| format!("https://{}.dev", i)).collect());
+        assert_eq!(links.len(), MAX_LINKS);
+        assert_eq!(links[0], "https://59.dev");
+    }
+}
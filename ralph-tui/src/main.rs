@@ -1,1298 +1,938 @@
-mod theme;
 
-use theme::{
-    get_pulse_color, get_spinner_frame, BG_PRIMARY, BG_SECONDARY, BG_TERTIARY, BORDER_SUBTLE, CYAN_DIM, CYAN_PRIMARY,
-    GREEN_ACTIVE, GREEN_SUCCESS, AMBER_WARNING, RED_ERROR, ROUNDED_BORDERS, TEXT_MUTED, TEXT_PRIMARY,
-    TEXT_SECONDARY,
-};
-
-use std::io::{self, stdout, Read, Write};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, stdout, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers, MouseEventKind},
+    event::{
+        self, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste, EnableFocusChange,
+        EnableMouseCapture, Event,
+        KeyEventKind,
+    },
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
-use ratatui::{
-    prelude::*,
-    widgets::{Block, Borders, Gauge, Paragraph},
+use ratatui::prelude::*;
+
+use ralph_tui::{
+    agents, app, chain, compat, config, crosscheck, digest, disk, editor, events, git, hooks, input, journal, keymap, lanes, links, metrics, preflight, prd, prd_diff, pty, recovery, redraw, render, report, shell, signals, status, tasks, tripwire, verify, CliConfig, ValueSource,
+    VERSION,
+};
+
+use agents::{AgentProfile, ArgValues, PromptDelivery};
+use app::{
+    append_iteration_log, take_control_signal, App, SIGNAL_FILE, ControlSignal, CriterionFocus, IterationEndSource,
+    IterationEnding, IterationState, Mode, QueueEntry, QueueStatus, RalphTerminalSize, RecoverySnapshot,
+    SessionState, COMPACTION_WRAP_UP_MESSAGE, DEFAULT_BEHIND_WARN, DEFAULT_FLAT_AFTER, DEFAULT_LINGER_MS, DEFAULT_NUDGE_MESSAGE, DEFAULT_PRD_IDLE_SECS, DEFAULT_STUCK_THRESHOLD, ITERATION_DELAY_SECS,
+    RUNTIME_WRAP_UP_GRACE,
+};
+use compat::AgentVersion;
+use digest::{Digest, DigestFormat};
+use editor::editor_args;
+use events::{EventSink, SessionEvent};
+use git::SyncStrategy;
+use hooks::HookEvent;
+use input::Action;
+use lanes::Lane;
+use links::{hyperlink_start, terminal_supports_hyperlinks, HYPERLINK_END};
+use preflight::{Preflight, PreflightAction};
+use prd::{Prd, progress_line_count};
+use pty::{build_paste_payload, feed_output, is_pty_hangup, lock_pty, panic_message, strip_ansi_codes, PendingPrompt, PtyState, PtyWriter, DEFAULT_ERROR_PATTERNS, DEFAULT_IDLE_SECS, DEFAULT_PROMPT_PATTERN};
+use redraw::RedrawSchedule;
+use render::terminal::{hyperlink_runs, HyperlinkRun};
+use regex::Regex;
+use shell::split_shell_words;
+use tasks::{
+    discover_tasks, find_active_tasks, parse_task_choice, parse_task_roots, plan_archive, plan_unarchive, resolve_in_roots,
+    sort_tasks, TaskChoice, TaskInfo, TaskSort,
 };
-use serde::Deserialize;
 
-/// Acceptance criterion - supports both v1.0 (string) and v2.0 (object) schemas
-#[derive(Debug, Clone, PartialEq)]
-struct AcceptanceCriterion {
-    description: String,
-    passes: bool,
+/// Build the Ralph prompt from task directory and prompt.md
+/// Returns the full prompt string to be piped to Claude Code stdin
+/// Embedded default prompt.md as fallback
+const EMBEDDED_PROMPT: &str = include_str!("../../prompt.md");
+
+/// Global Ralph config directory: ~/.config/ralph (Unix) or %USERPROFILE%\.config\ralph (Windows)
+fn ralph_config_dir() -> Option<PathBuf> {
+    let home_dir = if cfg!(windows) {
+        std::env::var_os("USERPROFILE")
+    } else {
+        std::env::var_os("HOME")
+    };
+    home_dir.map(|home| PathBuf::from(home).join(".config").join("ralph"))
 }
 
-// Custom deserializer to handle both string (v1.0) and object (v2.0) formats
-impl<'de> serde::Deserialize<'de> for AcceptanceCriterion {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        use serde::de::{self, MapAccess, Visitor};
+/// Prompt file name, in the task directory, ./ralph/ or the global config directory
+const PROMPT_FILE: &str = "prompt.md";
 
-        struct AcceptanceCriterionVisitor;
+/// The prompt.md file `find_prompt_content` reads for `task_dir` (None = the embedded default)
+fn find_prompt_path(task_dir: &Path) -> Option<PathBuf> {
+    resolve_prompt_path(task_dir, Path::new("ralph"), ralph_config_dir().as_deref())
+}
 
-        impl<'de> Visitor<'de> for AcceptanceCriterionVisitor {
-            type Value = AcceptanceCriterion;
+/// First prompt.md that exists, in order of priority:
+/// 1. <task_dir>/prompt.md (task-specific instructions)
+/// 2. <local_dir>/prompt.md, i.e. ./ralph/prompt.md (local project customization)
+/// 3. <config_dir>/prompt.md, i.e. ~/.config/ralph/prompt.md (global user config)
+fn resolve_prompt_path(task_dir: &Path, local_dir: &Path, config_dir: Option<&Path>) -> Option<PathBuf> {
+    [Some(task_dir), Some(local_dir), config_dir]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(PROMPT_FILE))
+        .find(|path| path.exists())
+}
 
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a string or an object with description and passes fields")
-            }
+/// Read the prompt.md `find_prompt_path` picks, falling back to the embedded default
+/// Returns the content and the file it came from (None = embedded).
+fn find_prompt_content(task_dir: &Path) -> (String, Option<String>) {
+    if let Some(path) = find_prompt_path(task_dir)
+        && let Ok(content) = std::fs::read_to_string(&path)
+    {
+        return (content, Some(path.display().to_string()));
+    }
 
-            // v1.0 schema: plain string (treated as passes: false)
-            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                Ok(AcceptanceCriterion {
-                    description: value.to_string(),
-                    passes: false,
-                })
-            }
+    // Fall back to embedded prompt
+    eprintln!("Warning: No prompt.md found in the task directory, ./ralph/ or ~/.config/ralph/, using embedded default");
+    (EMBEDDED_PROMPT.to_string(), None)
+}
 
-            // v2.0 schema: object with description and passes
-            fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
-            where
-                M: MapAccess<'de>,
-            {
-                let mut description: Option<String> = None;
-                let mut passes: Option<bool> = None;
+/// Copy the prompt currently in effect for `task_dir` into the task directory for customization
+fn copy_prompt_to_task(task_dir: &Path, source: Option<&Path>) -> io::Result<PathBuf> {
+    let content = match source {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => EMBEDDED_PROMPT.to_string(),
+    };
+    let target = task_dir.join(PROMPT_FILE);
+    std::fs::write(&target, content)?;
+    Ok(target)
+}
 
-                while let Some(key) = map.next_key::<String>()? {
-                    match key.as_str() {
-                        "description" => {
-                            description = Some(map.next_value()?);
-                        }
-                        "passes" => {
-                            passes = Some(map.next_value()?);
-                        }
-                        _ => {
-                            let _: serde::de::IgnoredAny = map.next_value()?;
-                        }
-                    }
-                }
+/// Offer to copy the effective prompt into a task that hasn't started and has no prompt.md yet
+fn offer_task_prompt(task_dir: &Path) -> io::Result<()> {
+    if task_dir.join(PROMPT_FILE).exists() || task_dir.join("progress.txt").exists() {
+        return Ok(());
+    }
+    let source = find_prompt_path(task_dir);
+    let source_label = source.as_ref().map_or_else(|| "embedded default".to_string(), |path| path.display().to_string());
+    print!(
+        "Copy the current prompt ({}) into {} to customize it for this task? [y/N]: ",
+        source_label,
+        task_dir.join(PROMPT_FILE).display()
+    );
+    io::stdout().flush()?;
 
-                Ok(AcceptanceCriterion {
-                    description: description.unwrap_or_default(),
-                    passes: passes.unwrap_or(false),
-                })
-            }
-        }
-
-        deserializer.deserialize_any(AcceptanceCriterionVisitor)
-    }
-}
-
-/// PRD user story
-#[derive(Debug, Clone, Deserialize, PartialEq)]
-#[serde(rename_all = "camelCase")]
-struct UserStory {
-    id: String,
-    title: String,
-    #[allow(dead_code)]
-    description: String,
-    #[allow(dead_code)]
-    acceptance_criteria: Vec<AcceptanceCriterion>,
-    priority: u32,
-    passes: bool,
-    #[allow(dead_code)]
-    notes: String,
-}
-
-/// Default schema version for backwards compatibility
-fn default_schema_version() -> String {
-    "1.0".to_string()
-}
-
-/// PRD document structure
-#[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Prd {
-    /// Schema version for format compatibility (default: "1.0")
-    #[allow(dead_code)]
-    #[serde(default = "default_schema_version")]
-    schema_version: String,
-    #[allow(dead_code)]
-    project: String,
-    #[allow(dead_code)]
-    task_dir: String,
-    /// Branch name for this effort (null = don't create branch, work in existing repos)
-    #[serde(default)]
-    branch_name: Option<String>,
-    /// Target branch to merge into when complete (null = no merge)
-    #[allow(dead_code)]
-    #[serde(default)]
-    merge_target: Option<String>,
-    /// Whether to auto-merge on completion (default: false)
-    #[allow(dead_code)]
-    #[serde(default)]
-    auto_merge: bool,
-    #[allow(dead_code)]
-    #[serde(rename = "type")]
-    prd_type: String,
-    description: String,
-    user_stories: Vec<UserStory>,
-}
-
-impl Prd {
-    /// Load PRD from a JSON file
-    fn load(path: &PathBuf) -> io::Result<Self> {
-        let content = std::fs::read_to_string(path)?;
-        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-    }
-
-    /// Count completed stories
-    fn completed_count(&self) -> usize {
-        self.user_stories.iter().filter(|s| s.passes).count()
-    }
-
-    /// Check if all stories pass (project complete)
-    fn all_stories_pass(&self) -> bool {
-        !self.user_stories.is_empty() && self.user_stories.iter().all(|s| s.passes)
-    }
-
-    /// Get current story (first with passes: false, sorted by priority)
-    fn current_story(&self) -> Option<&UserStory> {
-        self.user_stories
-            .iter()
-            .filter(|s| !s.passes)
-            .min_by_key(|s| s.priority)
-    }
-
-    /// Calculate progress as percentage based on per-criteria completion
-    /// This gives more granular progress than story-level tracking
-    #[allow(dead_code)]
-    fn criteria_progress(&self) -> f64 {
-        let total: usize = self.user_stories.iter()
-            .map(|s| s.acceptance_criteria.len())
-            .sum();
-        if total == 0 {
-            return 0.0;
-        }
-        let passed: usize = self.user_stories.iter()
-            .flat_map(|s| &s.acceptance_criteria)
-            .filter(|c| c.passes)
-            .count();
-        (passed as f64 / total as f64) * 100.0
-    }
-}
-
-/// Mode for modal input system
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Mode {
-    Ralph,  // Default mode - focus on left panel
-    Claude, // Claude mode - focus on right panel, forward input to PTY
-}
-
-/// View mode for Ralph terminal panel
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-enum RalphViewMode {
-    #[default]
-    Normal,       // Default: show minimal ralph output or ASCII logo
-    StoryDetails, // Show selected story details from prd.json
-    Progress,     // Show progress.txt entries for selected story
-    Requirements, // Show requirements from prd.md for selected story
-}
-
-/// Recent activity from Claude Code (tool calls, actions)
-#[derive(Debug, Clone)]
-struct Activity {
-    action_type: String,
-    target: String,
-}
-
-impl Activity {
-    fn new(action_type: &str, target: &str) -> Self {
-        Self {
-            action_type: action_type.to_string(),
-            target: target.to_string(),
-        }
-    }
-
-    /// Format for display (truncate target if too long)
-    fn format(&self, max_width: usize) -> String {
-        let prefix = format!("{}: ", self.action_type);
-        let available = max_width.saturating_sub(prefix.len());
-        let char_count = self.target.chars().count();
-        let target = if char_count > available {
-            // Safely truncate from the end using character boundaries
-            let skip_chars = char_count.saturating_sub(available.saturating_sub(3));
-            let truncated: String = self.target.chars().skip(skip_chars).collect();
-            format!("...{}", truncated)
-        } else {
-            self.target.clone()
-        };
-        format!("{}{}", prefix, target)
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if input.trim().to_lowercase().starts_with('y') {
+        let target = copy_prompt_to_task(task_dir, source.as_deref())?;
+        println!("Wrote {} - edit it before the first iteration starts.", target.display());
     }
+    Ok(())
 }
 
-/// Parse activities from Claude output
-/// Looks for tool call patterns in the output
-fn parse_activities(text: &str) -> Vec<Activity> {
-    let mut activities = Vec::new();
+fn build_ralph_prompt(
+    task_dir: &Path,
+    prd_path: &Path,
+    targeted_stories: &BTreeSet<String>,
+    skipped_stories: &BTreeSet<String>,
+    focus: Option<&CriterionFocus>,
+) -> io::Result<String> {
+    let (prompt_content, _source) = find_prompt_content(task_dir);
 
-    // Patterns to look for (case-insensitive matching in output)
-    // Claude Code typically shows tool usage in various formats
-    let patterns: &[(&str, &[&str])] = &[
-        ("Read", &["reading ", "read file", "read("]),
-        ("Edit", &["editing ", "edit file", "edit("]),
-        ("Write", &["writing ", "write file", "write("]),
-        ("Bash", &["running ", "$ ", "bash(", "executing "]),
-        ("Grep", &["searching ", "grep(", "grep for"]),
-        ("Glob", &["finding files", "glob(", "globbing"]),
-        ("TodoWrite", &["updating todos", "todowrite(", "adding todo"]),
-    ];
+    // Build the full prompt matching ralph.sh format
+    let mut prompt = format!(
+        "# Ralph Agent Instructions\n\n\
+         Task Directory: {task_dir}\n\
+         PRD File: {prd_path}\n\
+         Progress File: {task_dir}/progress.txt\n\n\
+         {prompt_content}",
+        task_dir = task_dir.display(),
+        prd_path = prd_path.display(),
+        prompt_content = prompt_content,
+    );
 
-    for line in text.lines() {
-        let line_lower = line.to_lowercase();
-
-        for (action_type, prefixes) in patterns {
-            for prefix in *prefixes {
-                if let Some(pos) = line_lower.find(prefix) {
-                    // Extract target (rest of line after pattern, cleaned up)
-                    // Use get() to safely handle potential UTF-8 boundary issues
-                    let start_idx = pos + prefix.len();
-                    let after = match line.get(start_idx..) {
-                        Some(s) => s,
-                        None => continue, // Skip if index is invalid
-                    };
-                    let target = after
-                        .trim()
-                        .trim_matches(|c: char| c == '"' || c == '\'' || c == '`')
-                        .split(|c: char| c == '\n' || c == '\r')
-                        .next()
-                        .unwrap_or("")
-                        .chars()
-                        .take(100)  // Limit target length
-                        .collect::<String>();
-
-                    if !target.is_empty() {
-                        let activity = Activity::new(action_type, &target);
-                        // Avoid duplicates
-                        if !activities.iter().any(|a: &Activity|
-                            a.action_type == activity.action_type && a.target == activity.target
-                        ) {
-                            activities.push(activity);
-                        }
-                    }
-                    break;  // Only match first pattern per line
-                }
-            }
+    // Selective runs (--stories / Space): narrow the agent to the targeted stories
+    if !targeted_stories.is_empty() {
+        prompt.push_str(&story_selection_section(targeted_stories));
+    }
+
+    // Stories skipped from the quick actions menu wait for a later session
+    if !skipped_stories.is_empty() {
+        prompt.push_str(&skipped_stories_section(skipped_stories));
+    }
+
+    // Stories assigned to people (assignee other than "agent") are off limits
+    // and a criterion focused with F comes first
+    if let Ok(prd) = Prd::load(&prd_path.to_path_buf()) {
+        prompt.push_str(&human_stories_section(&prd));
+        if let Some(section) = focus.and_then(|focus| criterion_focus_section(&prd, focus)) {
+            prompt.push_str(&section);
         }
     }
 
-    activities
+    Ok(prompt)
 }
 
-/// Maximum number of activities to track
-const MAX_ACTIVITIES: usize = 10;
+/// Prompt section limiting the agent to the targeted stories
+fn story_selection_section(targeted_stories: &BTreeSet<String>) -> String {
+    let ids = targeted_stories.iter().map(String::as_str).collect::<Vec<_>>().join(", ");
+    format!(
+        "\n\n## Story Selection\n\n\
+         Only work on these stories: {ids}\n\n\
+         Ignore every other story in the PRD, even if it has a higher priority. \
+         When all of these stories have `passes: true`, treat the task as complete \
+         and reply with `<promise>COMPLETE</promise>`.\n"
+    )
+}
 
-/// Shared state for PTY with VT100 parser
-struct PtyState {
-    parser: vt100::Parser,
-    child_exited: bool,
-    /// Recent raw output for detecting completion signal
-    recent_output: String,
-    /// Recent activities parsed from output
-    activities: Vec<Activity>,
-    /// Last parsed output position (to avoid re-parsing)
-    last_activity_parse_pos: usize,
+/// Prompt section telling the agent to leave the skipped stories alone this session
+fn skipped_stories_section(skipped_stories: &BTreeSet<String>) -> String {
+    let ids = skipped_stories.iter().map(String::as_str).collect::<Vec<_>>().join(", ");
+    format!(
+        "\n\n## Skipped Stories\n\n\
+         Skip these stories for now: {ids}\n\n\
+         Don't work on them and don't change their `passes`, even if they have a higher \
+         priority; pick the next story instead.\n"
+    )
 }
 
-impl PtyState {
-    fn new(rows: u16, cols: u16) -> Self {
-        Self {
-            parser: vt100::Parser::new(rows, cols, 1000), // 1000 lines of scrollback
-            child_exited: false,
-            recent_output: String::new(),
-            activities: Vec::new(),
-            last_activity_parse_pos: 0,
-        }
-    }
+/// Prompt section pointing the agent at the focused criterion (None once it passes or is gone)
+fn criterion_focus_section(prd: &Prd, focus: &CriterionFocus) -> Option<String> {
+    let story = prd.user_stories.iter().find(|s| s.id == focus.story_id)?;
+    let criterion = story.acceptance_criteria.get(focus.criterion).filter(|c| !c.passes)?;
+    Some(format!(
+        "\n\n## Focus\n\n\
+         FOCUS: In story {}, prioritize criterion {}: {}\n\n\
+         Work on this criterion before anything else in the story.\n",
+        story.id,
+        criterion.label(focus.criterion),
+        criterion.description
+    ))
+}
 
-    /// Append output and trim to last 10KB to prevent memory issues
-    fn append_output(&mut self, data: &[u8]) {
-        if let Ok(s) = std::str::from_utf8(data) {
-            self.recent_output.push_str(s);
-            // Keep only last 10KB to limit memory
-            if self.recent_output.len() > 10 * 1024 {
-                let target_start = self.recent_output.len() - 8 * 1024;
-                // Find a valid UTF-8 character boundary using char_indices
-                // char_indices always returns valid byte boundaries
-                if let Some((start, _)) = self
-                    .recent_output
-                    .char_indices()
-                    .find(|(i, _)| *i >= target_start)
-                {
-                    // Use safe get() to avoid any potential panic
-                    if let Some(trimmed) = self.recent_output.get(start..) {
-                        self.recent_output = trimmed.to_string();
-                    }
-                }
-                // If we can't find a valid boundary, just clear (shouldn't happen)
-            }
-        }
+/// Prompt section telling the agent to skip human-assigned stories (empty when there are none)
+fn human_stories_section(prd: &Prd) -> String {
+    let stories: Vec<String> = prd
+        .user_stories
+        .iter()
+        .filter(|s| s.is_human())
+        .map(|s| format!("- {} ({})", s.id, s.assignee.as_deref().unwrap_or("human")))
+        .collect();
+    if stories.is_empty() {
+        return String::new();
     }
+    let completion = if prd.wait_for_humans {
+        "The task is only complete once these stories pass as well."
+    } else {
+        "Leave them out when deciding whether the task is complete."
+    };
+    format!(
+        "\n\n## Human-Assigned Stories\n\n\
+         These stories are assigned to people. Skip them: don't implement them, \
+         don't edit them in the PRD and never set their `passes`. {completion}\n\n{list}\n",
+        list = stories.join("\n"),
+    )
+}
 
-    /// Check if completion signal is present in recent output
-    fn has_completion_signal(&self) -> bool {
-        self.recent_output.contains("<promise>COMPLETE</promise>")
-    }
+fn print_usage() {
+    eprintln!("Ralph TUI - Interactive terminal interface for Ralph agent");
+    eprintln!();
+    eprintln!("Usage: ralph-tui [task-directory...] [OPTIONS]");
+    eprintln!("       ralph-tui init");
+    eprintln!("       ralph-tui digest [--since <WINDOW>] [--format md|json]");
+    eprintln!("       ralph-tui annotate <task-directory> [--format md|json]");
+    eprintln!("       ralph-tui status <task-directory> [--format <FORMAT>] [--json]");
+    eprintln!("       ralph-tui archive <task-directory> [--dry-run]");
+    eprintln!("       ralph-tui unarchive <archived-task-directory> [--dry-run]");
+    eprintln!();
+    eprintln!("Commands:");
+    eprintln!("  init              Install the stop hook and settings.json to ~/.config/ralph");
+    eprintln!("  digest            Summarize every task's (active and archived) stories completed,");
+    eprintln!("                    iterations, agent time and open blockers over a window");
+    eprintln!("                    (--since 30m/24h/7d/2w, default 7d; --format md or json)");
+    eprintln!("  annotate          Print a task's activities, errors, test runs and criteria");
+    eprintln!("                    passed as a timeline per iteration (--format md or json)");
+    eprintln!("  status            Print a task's progress on one line, e.g. for a tmux status bar");
+    eprintln!("                    (--format with {{{}}}, default \"{}\"; --json for", status::PLACEHOLDERS.join("} {"), status::DEFAULT_STATUS_FORMAT);
+    eprintln!("                    everything). Exits {} while a loop runs on it, {} once complete,", status::EXIT_RUNNING, status::EXIT_COMPLETE);
+    eprintln!("                    {} otherwise", status::EXIT_IDLE);
+    eprintln!("  archive           Move a task to archived/<name>-<YYYYMMDD-HHMM> under its root,");
+    eprintln!("                    following it in other tasks' nextTask (refused while it runs)");
+    eprintln!("  unarchive         Move an archived task back under its root");
+    eprintln!();
+    eprintln!("Arguments:");
+    eprintln!("  [task-directory]  Path to the task directory containing prd.json,");
+    eprintln!("                    or a path to the PRD JSON file itself");
+    eprintln!("                    A relative path is looked up in the task roots first");
+    eprintln!("                    If omitted, prompts for task selection from the task roots");
+    eprintln!("                    Several task directories run one after another (a batch,");
+    eprintln!("                    without prompts; --once, --stories and --events-socket");
+    eprintln!("                    need a single task)");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --task-root <DIRS>     Directories holding the tasks, comma-separated; also applies to");
+    eprintln!("                         subcommands (default: $RALPH_TASK_ROOTS, else ralph.toml");
+    eprintln!("                         task_root, else tasks)");
+    eprintln!("  -i, --iterations <N>   Maximum iterations to run (default: prd.json defaultIterations, else 10)");
+    eprintln!("  --rotate-at <N>        Rotate progress file at N lines (default: prd.json rotateThreshold, else 300)");
+    eprintln!("  --stuck-after <N>      Flag a story as stuck after N iterations without progress (default: 3)");
+    eprintln!("  --min-free <MB>        Warn and stop recording the timeline below MB free on the task");
+    eprintln!("                         directory's filesystem; the loop pauses below {} MB (default: {})", disk::HARD_FLOOR_MB, disk::DEFAULT_MIN_FREE_MB);
+    eprintln!("  --flat-after <TIME>    Tint the active story's criteria sparkline amber after TIME without");
+    eprintln!("                         a criterion passing (e.g. 15m, 1h; default: {}m)", DEFAULT_FLAT_AFTER / 60);
+    eprintln!("  --behind-warn <N>      Warn when N commits behind the PRD mergeTarget (default: 20)");
+    eprintln!("  --sync-strategy <S>    How m syncs with mergeTarget: merge or rebase (default: merge)");
+    eprintln!("  --idle-after <SECS>    Seconds without output at the prompt before Claude counts as idle (default: 5)");
+    eprintln!("  --prompt-pattern <RE>  Regex matching Claude's input prompt on the last screen row");
+    eprintln!("  --nudge-after <SECS>   Send a nudge message after SECS idle at the prompt (default: off)");
+    eprintln!("  --nudge-message <TEXT> Message sent by --nudge-after (default: \"{}\")", DEFAULT_NUDGE_MESSAGE);
+    eprintln!("  --prd-idle <SECS>      End the iteration once its story passes in prd.json and the agent");
+    eprintln!("                         printed nothing for SECS, in case the stop hook signal was lost");
+    eprintln!("                         (default: {}; 0 waits for the signal)", DEFAULT_PRD_IDLE_SECS);
+    eprintln!("  --linger <SECS>        Keep an ended iteration on screen for SECS before moving on, so its");
+    eprintln!("                         last output is drawn (default: {}; 0 moves on at once)", DEFAULT_LINGER_MS as f64 / 1000.0);
+    eprintln!("  --end-on-compaction    Ask the agent to wrap up once it compacts its context, so the");
+    eprintln!("                         next iteration starts fresh (or set end_on_compaction = true in");
+    eprintln!("                         ralph.toml; default: only count compactions)");
+    eprintln!("  --squash-on-complete   Squash the agent's commits on branchName since mergeTarget into one");
+    eprintln!("                         when the task completes, before the complete hook (default: ask)");
+    eprintln!("  --progress-strip       Start with the progress.txt tail strip shown (toggle with P)");
+    eprintln!("  --no-sidebar           Hide the Ralph status panel (toggle with b)");
+    eprintln!("  --no-key-guard         Forward Ctrl+C/D/Z to the agent on the first press (or set");
+    eprintln!("                         key_guard = false in ralph.toml; default: press twice within a second)");
+    eprintln!("  --no-title             Leave the terminal title alone (or set no_title in ralph.toml)");
+    eprintln!("  --kitty-keys           Encode modified keys (e.g. Shift+Enter) with the kitty keyboard");
+    eprintln!("                         protocol once the agent enables it (default: plain xterm bytes)");
+    eprintln!("  --max-runtime <TIME>   Stop the session after TIME wall-clock (e.g. 90m, 6h); the agent");
+    eprintln!("                         is asked to wrap up, then stopped {}s later", RUNTIME_WRAP_UP_GRACE.as_secs());
+    eprintln!("  --error-pattern <TEXT> Count output lines containing TEXT as errors (repeatable; replaces");
+    eprintln!("                         the defaults: {})", DEFAULT_ERROR_PATTERNS.join(", "));
+    eprintln!("  --error-bell           Ring the terminal bell when a new error line appears");
+    eprintln!("  --parallel <N>         Experimental: run N agents (2-{}) on disjoint story lanes", lanes::MAX_LANES);
+    eprintln!("                         (prd.json story \"lane\" field, otherwise round-robin)");
+    eprintln!("  --stories <IDS>        Only work on these stories (comma-separated, e.g. US-7,US-9)");
+    eprintln!("  --agent-args <ARGS>    Extra arguments for the agent, split like a shell command line");
+    eprintln!("                         (default: prd.json agentArgs, e.g. \"--verbose\")");
+    eprintln!("  --model <NAME>         Claude model for the agent (sonnet, opus, haiku or a full name)");
+    eprintln!("                         (default: prd.json model, else the claude CLI's default)");
+    eprintln!("  --agent <NAME>         Agent profile to run: claude, opencode, aider or one defined in");
+    eprintln!("                         ~/.config/ralph/{} (default: prd.json agent, else claude)", config::CONFIG_FILE);
+    eprintln!("  --events-socket <PATH> Publish newline-delimited JSON events on a Unix socket");
+    eprintln!("  --metrics-port <PORT>  Serve /metrics (Prometheus) and /status (JSON) over HTTP on");
+    eprintln!("                         127.0.0.1:PORT (or pass ADDRESS:PORT to listen elsewhere)");
+    eprintln!("  --queue <FILE>         Add the task directories listed in FILE (one per line) to the batch");
+    eprintln!("  -y, --yes              Skip confirmation prompts");
+    eprintln!("  --once                 Run exactly one iteration, print the result and exit");
+    eprintln!("                         (exit 0 = complete, 2 = more work remains, 1 = error)");
+    eprintln!("  -h, --help             Show this help message");
+    eprintln!("  -V, --version          Show version");
+    eprintln!();
+    eprintln!("Examples:");
+    eprintln!("  ralph-tui                          # Interactive task selection");
+    eprintln!("  ralph-tui tasks/my-feature         # Run specific task");
+    eprintln!("  ralph-tui tasks/my-feature -i 5    # Run with 5 iterations");
+    eprintln!("  ralph-tui tasks/my-feature --once  # Run one iteration for scripting");
+    eprintln!("  ralph-tui tasks/a tasks/b -i 20    # Run two tasks one after another");
+}
 
-    /// Check if stop hook fired (iteration complete message in output)
-    /// This is used to detect when Claude's Stop hook runs with continue: false
-    /// Since Claude doesn't exit, we detect the message instead
-    /// We check for multiple possible patterns since ANSI codes may interfere
-    fn has_stop_hook_signal(&self) -> bool {
-        // Check raw output first (with ANSI stripping)
-        let stripped = strip_ansi_codes(&self.recent_output);
-        let stripped_lower = stripped.to_lowercase();
+/// Embedded stop hook scripts and Claude settings (installed by `ralph-tui init`)
+const EMBEDDED_STOP_HOOK_SH: &str = include_str!("../../hooks/stop-iteration.sh");
+const EMBEDDED_STOP_HOOK_PS1: &str = include_str!("../../hooks/stop-iteration.ps1");
+const EMBEDDED_SETTINGS: &str = include_str!("../../hooks/settings.json");
 
-        if stripped_lower.contains("iteration complete")
-            || stripped_lower.contains("ralph-tui will start next iteration")
-            || stripped_lower.contains("ran 1 stop hook")
-            || stripped_lower.contains("stop hook")
+/// Install the stop hook and settings.json into the global Ralph config directory
+/// The hook writes the .ralph-signal control file that ralph-tui watches for
+fn run_init() -> io::Result<()> {
+    let config_dir = ralph_config_dir().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "Could not determine home directory")
+    })?;
+    let hooks_dir = config_dir.join("hooks");
+    std::fs::create_dir_all(&hooks_dir)?;
+
+    let settings = if cfg!(windows) {
+        // Claude hooks on Windows need to invoke PowerShell to run .ps1 scripts
+        let hook_path = hooks_dir.join("stop-iteration.ps1");
+        std::fs::write(&hook_path, EMBEDDED_STOP_HOOK_PS1)?;
+        println!("Installed {}", hook_path.display());
+        let command = format!(
+            "powershell -NoProfile -ExecutionPolicy Bypass -File \"{}\"",
+            hook_path.display()
+        );
+        let settings = serde_json::json!({
+            "hooks": {
+                "Stop": [{ "hooks": [{ "type": "command", "command": command }] }]
+            }
+        });
+        serde_json::to_string_pretty(&settings).map_err(io::Error::other)?
+    } else {
+        let hook_path = hooks_dir.join("stop-iteration.sh");
+        std::fs::write(&hook_path, EMBEDDED_STOP_HOOK_SH)?;
+        #[cfg(unix)]
         {
-            return true;
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755))?;
         }
+        println!("Installed {}", hook_path.display());
+        EMBEDDED_SETTINGS.to_string()
+    };
 
-        // Also check the VT100 screen content (rendered text)
-        let screen = self.parser.screen();
-        let (rows, _cols) = screen.size();
-        for row in 0..rows {
-            let row_text = screen.contents_between(row, 0, row, 200);
-            let row_lower = row_text.to_lowercase();
-            if row_lower.contains("stop hook") || row_lower.contains("iteration complete") {
-                return true;
-            }
-        }
+    // Always overwrite settings.json - it's generated, not user-customized
+    let settings_path = config_dir.join("settings.json");
+    std::fs::write(&settings_path, settings)?;
+    println!("Installed {}", settings_path.display());
 
-        false
-    }
+    Ok(())
+}
 
-    /// Clear recent output (called when starting new iteration)
-    fn clear_recent_output(&mut self) {
-        self.recent_output.clear();
-        self.activities.clear();
-        self.last_activity_parse_pos = 0;
-    }
+/// Default window for `ralph-tui digest`
+const DEFAULT_DIGEST_WINDOW: &str = "7d";
 
-    /// Parse activities from new output since last parse
-    fn update_activities(&mut self) {
-        if self.recent_output.len() <= self.last_activity_parse_pos {
-            return;
-        }
+/// Print a report of what the agents got done across all tasks (active and archived)
+fn run_digest(args: &[String], roots: &[PathBuf]) -> io::Result<()> {
+    let mut window = digest::parse_duration(DEFAULT_DIGEST_WINDOW).expect("default digest window is valid");
+    let mut format = DigestFormat::default();
 
-        // Parse only the new portion of output (safe slice access)
-        let new_output = match self.recent_output.get(self.last_activity_parse_pos..) {
-            Some(s) => s,
-            None => {
-                // Position is invalid (maybe string was trimmed), reset
-                self.last_activity_parse_pos = 0;
-                return;
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == "--since" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --since",
+                ));
             }
-        };
-        let new_activities = parse_activities(new_output);
-
-        // Add new activities, avoiding duplicates
-        for activity in new_activities {
-            if !self.activities.iter().any(|a|
-                a.action_type == activity.action_type && a.target == activity.target
-            ) {
-                self.activities.push(activity);
-            }
-        }
-
-        // Keep only the last MAX_ACTIVITIES
-        if self.activities.len() > MAX_ACTIVITIES {
-            let remove_count = self.activities.len() - MAX_ACTIVITIES;
-            self.activities.drain(0..remove_count);
-        }
-
-        self.last_activity_parse_pos = self.recent_output.len();
-    }
-
-    /// Get recent activities (newest first)
-    fn get_activities(&self) -> Vec<Activity> {
-        self.activities.iter().rev().cloned().collect()
-    }
-}
-
-/// Iteration state for tracking progress across Claude restarts
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum IterationState {
-    Running,       // Claude is currently running
-    Completed,     // All stories complete (<promise>COMPLETE</promise> found)
-    NeedsRestart,  // Iteration finished but more work remains
-    WaitingDelay,  // Waiting before starting next iteration
-}
-
-/// Application state
-struct App {
-    pty_state: Arc<Mutex<PtyState>>,
-    master_pty: Option<Box<dyn portable_pty::MasterPty + Send>>,
-    pty_writer: Option<Box<dyn Write + Send>>,
-    mode: Mode,
-    task_dir: PathBuf,
-    prd_path: PathBuf,
-    prd: Option<Prd>,
-    prd_needs_reload: Arc<Mutex<bool>>,
-    // Iteration loop state
-    current_iteration: u32,
-    max_iterations: u32,
-    iteration_state: IterationState,
-    delay_start: Option<Instant>,
-    // Elapsed time tracking
-    session_start: Instant,
-    iteration_start: Instant,
-    // Progress rotation (reserved for future progress file rotation feature)
-    #[allow(dead_code)]
-    rotate_threshold: u32,
-    #[allow(dead_code)]
-    skip_prompts: bool,
-    // Animation state
-    animation_tick: u64,
-    last_animation_update: Instant,
-    // Session identification
-    session_id: String,
-    // Story list scroll offset (for arrow key navigation)
-    story_scroll_offset: usize,
-    // Currently selected story index (for detail views)
-    selected_story_index: usize,
-    // Ralph terminal view mode (what content to show)
-    ralph_view_mode: RalphViewMode,
-    // Whether Ralph terminal is expanded (true = 5-6 lines, false = 2-3 lines)
-    ralph_expanded: bool,
-    // Scroll offset for Ralph terminal content (when viewing details)
-    ralph_scroll_offset: usize,
-    // Scroll offset for Claude terminal (0 = at bottom, >0 = scrolled up into history)
-    claude_scroll_offset: usize,
-}
-
-impl App {
-    fn new(rows: u16, cols: u16, config: CliConfig) -> Self {
-        let prd_path = config.task_dir.join("prd.json");
-        let prd = Prd::load(&prd_path).ok();
-        let now = Instant::now();
-        // Generate session ID from process ID (format: RL-XXXXX)
-        let session_id = format!("RL-{:05}", std::process::id() % 100000);
-        // Find first incomplete story before moving prd
-        let selected_story_index = Self::find_first_incomplete_story(&prd);
-
-        Self {
-            pty_state: Arc::new(Mutex::new(PtyState::new(rows, cols))),
-            master_pty: None,
-            pty_writer: None,
-            mode: Mode::Ralph, // Default to Ralph mode
-            task_dir: config.task_dir,
-            prd_path,
-            prd,
-            prd_needs_reload: Arc::new(Mutex::new(false)),
-            current_iteration: 1,
-            max_iterations: config.max_iterations,
-            iteration_state: IterationState::Running,
-            delay_start: None,
-            session_start: now,
-            iteration_start: now,
-            rotate_threshold: config.rotate_threshold,
-            skip_prompts: config.skip_prompts,
-            animation_tick: 0,
-            last_animation_update: now,
-            session_id,
-            story_scroll_offset: 0,
-            selected_story_index,
-            ralph_view_mode: RalphViewMode::Normal,
-            ralph_expanded: false,
-            ralph_scroll_offset: 0,
-            claude_scroll_offset: 0,
-        }
-    }
-
-    /// Find the index of the first incomplete story (or 0 if all complete)
-    fn find_first_incomplete_story(prd: &Option<Prd>) -> usize {
-        if let Some(prd) = prd {
-            prd.user_stories
-                .iter()
-                .position(|s| !s.passes)
-                .unwrap_or(0)
+            window = digest::parse_duration(&args[i]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid since value: {} (expected e.g. 30m, 24h, 7d or 2w)", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--format" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --format",
+                ));
+            }
+            format = DigestFormat::parse(&args[i]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid format value: {} (expected md or json)", args[i]),
+                )
+            })?;
+            i += 1;
         } else {
-            0
+            print_usage();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown digest argument: {}", arg),
+            ));
         }
     }
 
-    /// Reload PRD from disk if flagged
-    fn reload_prd_if_needed(&mut self) {
-        let needs_reload = {
-            let Ok(mut flag) = self.prd_needs_reload.lock() else {
-                return;
-            };
-            if *flag {
-                *flag = false;
-                true
-            } else {
-                false
-            }
-        };
-
-        if needs_reload {
-            if let Ok(prd) = Prd::load(&self.prd_path) {
-                self.prd = Some(prd);
-            }
+    let until = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let since = until.saturating_sub(window);
+
+    let archived_roots: Vec<PathBuf> = roots.iter().map(|root| root.join(tasks::ARCHIVE_DIR)).collect();
+    let tasks = find_active_tasks(roots)
+        .into_iter()
+        .map(|dir| (dir, false))
+        .chain(find_active_tasks(&archived_roots).into_iter().map(|dir| (dir, true)))
+        .map(|(dir, archived)| digest::task_digest(&dir, archived, since))
+        .filter(|task| task.has_activity())
+        .collect();
+    let report = Digest { since, until, tasks };
+
+    match format {
+        DigestFormat::Markdown => print!("{}", digest::render_markdown(&report)),
+        DigestFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&report).map_err(io::Error::other)?)
         }
     }
+    Ok(())
+}
+
+/// Print a task's recorded events as a timeline, one section per iteration
+fn run_annotate(args: &[String], roots: &[PathBuf]) -> io::Result<()> {
+    let mut format = DigestFormat::default();
+    let mut task_arg = None;
 
-    /// Write bytes to the PTY stdin
-    fn write_to_pty(&mut self, data: &[u8]) {
-        if let Some(ref mut writer) = self.pty_writer {
-            let _ = writer.write_all(data);
-            let _ = writer.flush();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == "--format" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --format",
+                ));
+            }
+            format = DigestFormat::parse(&args[i]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid format value: {} (expected md or json)", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if !arg.starts_with('-') && task_arg.is_none() {
+            task_arg = Some(PathBuf::from(arg));
+            i += 1;
+        } else {
+            print_usage();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown annotate argument: {}", arg),
+            ));
         }
     }
 
-    /// Resize the PTY to match the given dimensions
-    fn resize_pty(&self, cols: u16, rows: u16) {
-        if let Some(ref master) = self.master_pty {
-            let _ = master.resize(PtySize {
-                rows,
-                cols,
-                pixel_width: 0,
-                pixel_height: 0,
-            });
+    let Some(task_arg) = task_arg else {
+        print_usage();
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Missing task directory"));
+    };
+    let (task_dir, _) = resolve_task_arg(task_arg, roots);
+    let entries = events::read_timeline(&task_dir);
+    match format {
+        DigestFormat::Markdown => {
+            let task = task_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            print!("{}", digest::render_timeline_markdown(&task, &entries))
         }
-        // Also resize the VT100 parser's screen
-        if let Ok(mut state) = self.pty_state.lock() {
-            state.parser.screen_mut().set_size(rows, cols);
+        DigestFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&entries).map_err(io::Error::other)?)
         }
     }
+    Ok(())
 }
 
-/// Simple text wrapping helper
-fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
-    if max_width == 0 {
-        return vec![text.to_string()];
-    }
-
-    let mut lines = Vec::new();
-    let mut current_line = String::new();
+/// Print a task's status line (or JSON); returns the exit code for its state
+fn run_status(args: &[String], roots: &[PathBuf]) -> io::Result<i32> {
+    let mut format = status::DEFAULT_STATUS_FORMAT.to_string();
+    let mut json = false;
+    let mut task_arg = None;
 
-    for word in text.split_whitespace() {
-        if current_line.is_empty() {
-            current_line = word.to_string();
-        } else if current_line.len() + 1 + word.len() <= max_width {
-            current_line.push(' ');
-            current_line.push_str(word);
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == "--format" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --format",
+                ));
+            }
+            format = args[i].clone();
+            i += 1;
+        } else if arg == "--json" {
+            json = true;
+            i += 1;
+        } else if !arg.starts_with('-') && task_arg.is_none() {
+            task_arg = Some(PathBuf::from(arg));
+            i += 1;
         } else {
-            lines.push(current_line);
-            current_line = word.to_string();
-        }
-    }
-
-    if !current_line.is_empty() {
-        lines.push(current_line);
-    }
-
-    if lines.is_empty() {
-        lines.push(String::new());
-    }
-
-    lines
-}
-
-/// Format duration as MM:SS
-fn format_duration(duration: Duration) -> String {
-    let total_secs = duration.as_secs();
-    let mins = total_secs / 60;
-    let secs = total_secs % 60;
-    format!("{:02}:{:02}", mins, secs)
-}
-
-/// Render iteration and completion stat cards in a given area
-/// Returns the widgets to be rendered: (left_card, right_card)
-fn render_stat_cards(
-    area: Rect,
-    current_iteration: u32,
-    max_iterations: u32,
-    completed: usize,
-    total: usize,
-    frame: &mut Frame,
-) {
-    // Split area horizontally for two cards with a small gap
-    let card_layout = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(50),
-            Constraint::Percentage(50),
-        ])
-        .split(area);
-
-    // Left card: Iterations
-    let iter_block = Block::default()
-        .borders(Borders::ALL)
-        .border_set(ROUNDED_BORDERS)
-        .border_style(Style::default().fg(BORDER_SUBTLE))
-        .style(Style::default().bg(BG_SECONDARY));
-
-    let iter_content = vec![
-        Line::from(vec![
-            Span::styled("⏱ ", Style::default().fg(CYAN_PRIMARY)),
-            Span::styled(
-                format!("{}/{}", current_iteration, max_iterations),
-                Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
-            ),
-        ]),
-        Line::from(vec![
-            Span::styled("ITERATIONS", Style::default().fg(TEXT_MUTED)),
-        ]),
-    ];
-
-    let iter_paragraph = Paragraph::new(iter_content)
-        .block(iter_block)
-        .alignment(Alignment::Center);
-
-    frame.render_widget(iter_paragraph, card_layout[0]);
-
-    // Right card: Completed
-    let comp_block = Block::default()
-        .borders(Borders::ALL)
-        .border_set(ROUNDED_BORDERS)
-        .border_style(Style::default().fg(BORDER_SUBTLE))
-        .style(Style::default().bg(BG_SECONDARY));
-
-    let comp_content = vec![
-        Line::from(vec![
-            Span::styled("◎ ", Style::default().fg(CYAN_PRIMARY)),
-            Span::styled(
-                format!("{}/{}", completed, total),
-                Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
-            ),
-        ]),
-        Line::from(vec![
-            Span::styled("COMPLETED", Style::default().fg(TEXT_MUTED)),
-        ]),
-    ];
-
-    let comp_paragraph = Paragraph::new(comp_content)
-        .block(comp_block)
-        .alignment(Alignment::Center);
-
-    frame.render_widget(comp_paragraph, card_layout[1]);
-}
-
-/// Story state for rendering
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum StoryState {
-    Completed,
-    Active,
-    Pending,
-}
-
-/// Render a single user story card
-/// Returns the height of the card:
-/// - Completed/Pending: 3 lines (border + content + border)
-/// - Active: 5 lines (border + title + progress bar + percentage + border)
-fn render_story_card(
-    area: Rect,
-    story_id: &str,
-    story_title: &str,
-    state: StoryState,
-    tick: u64,
-    progress_percent: u16,
-    criteria_passed: usize,
-    criteria_total: usize,
-    selected: bool,
-    frame: &mut Frame,
-) {
-    // Determine colors based on state
-    // For active state, use pulsing indicator color
-    let (indicator, indicator_color, text_color, bg_color) = match state {
-        StoryState::Completed => ("●", GREEN_SUCCESS, CYAN_PRIMARY, BG_SECONDARY),
-        StoryState::Active => {
-            let pulse_color = get_pulse_color(tick, GREEN_ACTIVE, CYAN_DIM);
-            ("●", pulse_color, CYAN_PRIMARY, BG_TERTIARY)
-        }
-        StoryState::Pending => ("○", TEXT_MUTED, TEXT_SECONDARY, BG_SECONDARY),
-    };
+            print_usage();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown status argument: {}", arg),
+            ));
+        }
+    }
 
-    // Use highlight border for selected card, normal for others
-    let border_color = if selected { CYAN_PRIMARY } else { BORDER_SUBTLE };
-
-    // Create card block with rounded borders
-    let card_block = Block::default()
-        .borders(Borders::ALL)
-        .border_set(ROUNDED_BORDERS)
-        .border_style(Style::default().fg(border_color))
-        .style(Style::default().bg(bg_color));
-
-    // Format story ID as #XX (extract numeric part)
-    let story_num = story_id.trim_start_matches(|c: char| !c.is_ascii_digit());
-    let formatted_id = format!("#{}", story_num);
-
-    // Build card content - single line with indicator, ID, and truncated title
-    let inner_width = area.width.saturating_sub(4) as usize; // Account for borders and padding
-    let prefix = format!("{} {} ", indicator, formatted_id);
-    let prefix_len = prefix.chars().count();
-    let available_title_width = inner_width.saturating_sub(prefix_len);
-
-    let title_char_count = story_title.chars().count();
-    let truncated_title = if title_char_count > available_title_width {
-        // Safely truncate using character boundaries
-        let take_chars = available_title_width.saturating_sub(3);
-        let truncated: String = story_title.chars().take(take_chars).collect();
-        format!("{}...", truncated)
-    } else {
-        story_title.to_string()
+    let Some(task_arg) = task_arg else {
+        print_usage();
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Missing task directory"));
     };
-
-    let title_line = Line::from(vec![
-        Span::styled(format!("{} ", indicator), Style::default().fg(indicator_color)),
-        Span::styled(format!("{} ", formatted_id), Style::default().fg(text_color).add_modifier(Modifier::BOLD)),
-        Span::styled(truncated_title, Style::default().fg(text_color)),
-    ]);
-
-    // For active state, show progress bar and percentage
-    if state == StoryState::Active {
-        // Render block first to get inner area
-        let inner_area = card_block.inner(area);
-        frame.render_widget(card_block, area);
-
-        // Split inner area: title (1 line), progress bar (1 line), percentage (1 line)
-        let inner_layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([
-                Constraint::Length(1), // Title line
-                Constraint::Length(1), // Progress bar
-                Constraint::Length(1), // Percentage
-            ])
-            .split(inner_area);
-
-        // Render title
-        let title_paragraph = Paragraph::new(vec![title_line]);
-        frame.render_widget(title_paragraph, inner_layout[0]);
-
-        // Render progress bar (Gauge widget)
-        let gauge = Gauge::default()
-            .gauge_style(Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY))
-            .percent(progress_percent)
-            .label(""); // No label on the gauge itself
-        frame.render_widget(gauge, inner_layout[1]);
-
-        // Render criteria count below the progress bar (e.g., "2/5 criteria")
-        let criteria_text = format!("{}/{} criteria ({}%)", criteria_passed, criteria_total, progress_percent);
-        let percent_line = Line::from(Span::styled(
-            criteria_text,
-            Style::default().fg(TEXT_MUTED),
-        ));
-        let percent_paragraph = Paragraph::new(vec![percent_line]);
-        frame.render_widget(percent_paragraph, inner_layout[2]);
-    } else {
-        // Completed and Pending states - simple single line card
-        let paragraph = Paragraph::new(vec![title_line])
-            .block(card_block);
-        frame.render_widget(paragraph, area);
-    }
-}
-
-/// Render progress stat cards (stories left + completion %) in a given area
-fn render_progress_cards(
-    area: Rect,
-    completed: usize,
-    total: usize,
-    frame: &mut Frame,
-) {
-    // Split area horizontally for two cards
-    let card_layout = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Percentage(50),
-            Constraint::Percentage(50),
-        ])
-        .split(area);
-
-    // Left card: Stories Left
-    let stories_left = total.saturating_sub(completed);
-    let left_block = Block::default()
-        .borders(Borders::ALL)
-        .border_set(ROUNDED_BORDERS)
-        .border_style(Style::default().fg(BORDER_SUBTLE))
-        .style(Style::default().bg(BG_SECONDARY));
-
-    let left_content = vec![
-        Line::from(vec![
-            Span::styled("◇ ", Style::default().fg(CYAN_PRIMARY)),
-            Span::styled(
-                format!("{}", stories_left),
-                Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
-            ),
-        ]),
-        Line::from(vec![
-            Span::styled("STORIES LEFT", Style::default().fg(TEXT_MUTED)),
-        ]),
-    ];
-
-    let left_paragraph = Paragraph::new(left_content)
-        .block(left_block)
-        .alignment(Alignment::Center);
-
-    frame.render_widget(left_paragraph, card_layout[0]);
-
-    // Right card: Progress percentage
-    let progress_pct = if total > 0 {
-        (completed as f32 / total as f32 * 100.0) as u8
+    let (task_dir, prd_path) = resolve_task_arg(task_arg, roots);
+    let task_status = status::TaskStatus::load(&task_dir, &prd_path).map_err(|e| {
+        io::Error::new(e.kind(), format!("Failed to read {}: {}", prd_path.display(), e))
+    })?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&task_status).map_err(io::Error::other)?);
     } else {
-        0
-    };
+        let line = task_status
+            .format(&format)
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+        println!("{}", line);
+    }
+    Ok(task_status.state.exit_code())
+}
 
-    let right_block = Block::default()
-        .borders(Borders::ALL)
-        .border_set(ROUNDED_BORDERS)
-        .border_style(Style::default().fg(BORDER_SUBTLE))
-        .style(Style::default().bg(BG_SECONDARY));
+/// Archive a task (or bring an archived one back) and print what was done
+fn run_archive(args: &[String], unarchive: bool, roots: &[PathBuf]) -> io::Result<()> {
+    let command = if unarchive { "unarchive" } else { "archive" };
+    let mut dry_run = false;
+    let mut task_arg = None;
+
+    for arg in args {
+        if arg == "--dry-run" {
+            dry_run = true;
+        } else if !arg.starts_with('-') && task_arg.is_none() {
+            task_arg = Some(PathBuf::from(arg));
+        } else {
+            print_usage();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown {} argument: {}", command, arg),
+            ));
+        }
+    }
 
-    let progress_color = if progress_pct == 100 {
-        GREEN_SUCCESS
-    } else {
-        CYAN_PRIMARY
+    let Some(task_arg) = task_arg else {
+        print_usage();
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Missing task directory"));
     };
+    let (task_dir, _) = resolve_task_arg(task_arg, roots);
+    let plan = if unarchive { plan_unarchive(&task_dir) } else { plan_archive(&task_dir, std::time::SystemTime::now()) }
+        .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
+    if !dry_run {
+        plan.apply()?;
+    }
+    for line in plan.describe(dry_run) {
+        println!("{}", line);
+    }
+    Ok(())
+}
 
-    let right_content = vec![
-        Line::from(vec![
-            Span::styled("⟠ ", Style::default().fg(progress_color)),
-            Span::styled(
-                format!("{}%", progress_pct),
-                Style::default().fg(progress_color).add_modifier(Modifier::BOLD),
-            ),
-        ]),
-        Line::from(vec![
-            Span::styled("PROGRESS", Style::default().fg(TEXT_MUTED)),
-        ]),
-    ];
+/// Built-in default for --iterations
+const DEFAULT_ITERATIONS: u32 = 10;
 
-    let right_paragraph = Paragraph::new(right_content)
-        .block(right_block)
-        .alignment(Alignment::Center);
+/// Built-in default for --rotate-at
+const DEFAULT_ROTATE_THRESHOLD: u32 = 300;
 
-    frame.render_widget(right_paragraph, card_layout[1]);
+/// Pick a setting by precedence: CLI flag > prd.json > config file > built-in default
+fn resolve_setting<T>(flag: Option<T>, prd: Option<T>, config_file: Option<T>, default: T) -> (T, ValueSource) {
+    if let Some(value) = flag {
+        (value, ValueSource::Flag)
+    } else if let Some(value) = prd {
+        (value, ValueSource::PrdJson)
+    } else if let Some(value) = config_file {
+        (value, ValueSource::ConfigFile)
+    } else {
+        (default, ValueSource::Default)
+    }
 }
 
-/// Build the Ralph prompt from task directory and prompt.md
-/// Returns the full prompt string to be piped to Claude Code stdin
-/// Embedded default prompt.md as fallback
-const EMBEDDED_PROMPT: &str = include_str!("../../prompt.md");
+/// Environment variable listing the task roots to scan (separated like PATH)
+const TASK_ROOTS_ENV: &str = "RALPH_TASK_ROOTS";
 
-/// Find prompt.md in order of priority:
-/// 1. ./ralph/prompt.md (local project customization)
-/// 2. ~/.config/ralph/prompt.md (global user config)
-/// 3. Embedded fallback (with warning)
-fn find_prompt_content() -> (String, Option<String>) {
-    // 1. Check local ./ralph/prompt.md
-    let local_path = PathBuf::from("ralph/prompt.md");
-    if local_path.exists() {
-        if let Ok(content) = std::fs::read_to_string(&local_path) {
-            return (content, Some(local_path.display().to_string()));
-        }
+/// Task roots to scan for active tasks: --task-root, else $RALPH_TASK_ROOTS, else
+/// task_root in ralph.toml, else ./tasks
+fn task_roots(flag: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    if !flag.is_empty() {
+        return Ok(flag.to_vec());
+    }
+    if let Some(roots) = std::env::var_os(TASK_ROOTS_ENV).filter(|roots| !roots.is_empty()) {
+        return Ok(std::env::split_paths(&roots).collect());
     }
+    let configured = config::load_config(ralph_config_dir().as_deref())?.task_root.map(|value| parse_task_roots(&value));
+    match configured {
+        Some(roots) if !roots.is_empty() => Ok(roots),
+        _ => Ok(vec![PathBuf::from(tasks::DEFAULT_TASK_ROOT)]),
+    }
+}
 
-    // 2. Check global ~/.config/ralph/prompt.md (Unix) or %USERPROFILE%\.config\ralph\prompt.md (Windows)
-    let home_dir = if cfg!(windows) {
-        std::env::var_os("USERPROFILE")
-    } else {
-        std::env::var_os("HOME")
-    };
-    if let Some(home) = home_dir {
-        let global_path = PathBuf::from(home).join(".config").join("ralph").join("prompt.md");
-        if global_path.exists() {
-            if let Ok(content) = std::fs::read_to_string(&global_path) {
-                return (content, Some(global_path.display().to_string()));
+/// Take every `--task-root <DIRS>` (or `--task-root=DIRS`) out of the arguments, wherever
+/// it appears, so it applies to the subcommands too
+fn take_task_root_flag(args: &mut Vec<String>) -> io::Result<Vec<PathBuf>> {
+    let mut roots = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        if let Some(value) = args[i].strip_prefix("--task-root=") {
+            roots.extend(parse_task_roots(value));
+            args.remove(i);
+        } else if args[i] == "--task-root" {
+            if i + 1 >= args.len() {
+                print_usage();
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Missing value for --task-root"));
             }
+            roots.extend(parse_task_roots(&args[i + 1]));
+            args.drain(i..i + 2);
+        } else {
+            i += 1;
         }
     }
-
-    // 3. Fall back to embedded prompt
-    eprintln!("Warning: No prompt.md found in ./ralph/ or ~/.config/ralph/, using embedded default");
-    (EMBEDDED_PROMPT.to_string(), None)
+    Ok(roots)
 }
 
-fn build_ralph_prompt(task_dir: &PathBuf) -> io::Result<String> {
-    let (prompt_content, _source) = find_prompt_content();
-
-    // Build the full prompt matching ralph.sh format
-    let prompt = format!(
-        "# Ralph Agent Instructions\n\n\
-         Task Directory: {task_dir}\n\
-         PRD File: {task_dir}/prd.json\n\
-         Progress File: {task_dir}/progress.txt\n\n\
-         {prompt_content}",
-        task_dir = task_dir.display(),
-        prompt_content = prompt_content,
-    );
-
-    Ok(prompt)
+/// Task directories listed in a --queue file: one per line, blank lines and `#` comments skipped
+/// Relative paths are resolved like task directories on the command line.
+fn parse_queue_file(content: &str) -> Vec<PathBuf> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect()
 }
 
-/// Convert vt100::Color to ratatui::Color
-fn vt100_to_ratatui_color(color: vt100::Color) -> Color {
-    match color {
-        vt100::Color::Default => Color::Reset,
-        vt100::Color::Idx(idx) => Color::Indexed(idx),
-        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+/// Resolve the positional argument to (task_dir, prd_path)
+/// Accepts a task directory containing prd.json, or a path to a PRD JSON file directly
+/// (e.g. docs/prds/feature-x/prd.json), in which case the task directory is its parent.
+/// A relative path is looked up in the task roots before the current directory.
+fn resolve_task_arg(arg: PathBuf, roots: &[PathBuf]) -> (PathBuf, PathBuf) {
+    let arg = resolve_in_roots(&arg, roots);
+    if arg.is_file() && arg.extension().is_some_and(|ext| ext == "json") {
+        let task_dir = match arg.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        (task_dir, arg)
+    } else {
+        let prd_path = arg.join("prd.json");
+        (arg, prd_path)
     }
 }
 
-/// Render the VT100 screen to a Vec of ratatui Lines (styled text)
-/// This function renders the visible content of the terminal emulator
-fn render_vt100_screen(screen: &vt100::Screen) -> Vec<Line<'static>> {
-    let (rows, cols) = screen.size();
-    let mut lines = Vec::with_capacity(rows as usize);
+/// Check that the PRD's `taskDir` field agrees with the task directory in use
+/// Returns a warning message on mismatch (None if they agree or taskDir is empty)
+fn task_dir_mismatch(prd_path: &Path, task_dir: &Path) -> Option<String> {
+    let declared = preflight::task_dir_mismatch(prd_path, task_dir)?;
+    Some(format!(
+        "prd.json taskDir \"{}\" does not match task directory \"{}\" (using {})",
+        declared,
+        task_dir.display(),
+        task_dir.display()
+    ))
+}
 
-    // Render each visible row
-    for row in 0..rows {
-        let mut spans = Vec::new();
-        let mut col = 0u16;
+/// Record in the session log which side of a prd.json taskDir mismatch was kept
+/// `choice` is rewrite (prd.json now names the CLI directory), prd or cli.
+fn log_task_dir_choice(task_dir: &Path, cli_dir: &Path, declared: &str, choice: &str) {
+    app::append_log_line(
+        task_dir,
+        &format!("task_dir_mismatch cli={} prd={} choice={}", cli_dir.display(), declared, choice),
+    );
+}
 
-        while col < cols {
-            if let Some(cell) = screen.cell(row, col) {
-                let contents = cell.contents();
+/// Display the task picker and return the selected task
+/// `o` cycles the sort order; a running task needs a trailing `!` to be opened.
+/// `a<N>` archives task N and `u` brings an archived task back into the list.
+fn prompt_task_selection(mut tasks: Vec<TaskInfo>, roots: &[PathBuf]) -> io::Result<PathBuf> {
+    println!();
+    println!("╔═══════════════════════════════════════════════════════════════╗");
+    println!("║  Ralph TUI - Select a Task                                    ║");
+    println!("╚═══════════════════════════════════════════════════════════════╝");
 
-                // Skip wide character continuations
-                if cell.is_wide_continuation() {
-                    col += 1;
-                    continue;
-                }
+    let color = stdout().is_terminal();
+    let mut sort = TaskSort::default();
+    loop {
+        sort_tasks(&mut tasks, sort);
+        println!();
+        println!("Active tasks (by {}):", sort.label());
+        println!();
+        let now = std::time::SystemTime::now();
+        for (i, task) in tasks.iter().enumerate() {
+            println!("{}", task.line(i + 1, now, color));
+            if !task.description.is_empty() {
+                println!("     {}", task.description);
+            }
+        }
 
-                let display_str = if contents.is_empty() {
-                    " ".to_string()
-                } else {
-                    contents.to_string()
-                };
+        println!();
+        print!(
+            "Select task [1-{}, o to sort by {}, a<N> to archive, u to unarchive]: ",
+            tasks.len(),
+            sort.next().label()
+        );
+        io::stdout().flush()?;
 
-                let mut style = Style::default();
-                style = style.fg(vt100_to_ratatui_color(cell.fgcolor()));
-                style = style.bg(vt100_to_ratatui_color(cell.bgcolor()));
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
 
-                if cell.bold() {
-                    style = style.add_modifier(Modifier::BOLD);
-                }
-                if cell.italic() {
-                    style = style.add_modifier(Modifier::ITALIC);
-                }
-                if cell.underline() {
-                    style = style.add_modifier(Modifier::UNDERLINED);
+        let index = match parse_task_choice(&input, tasks.len()) {
+            Ok(TaskChoice::CycleSort) => {
+                sort = sort.next();
+                continue;
+            }
+            Ok(TaskChoice::Archive(index)) => {
+                println!();
+                match plan_archive(&tasks[index].dir, std::time::SystemTime::now()) {
+                    Ok(plan) => match plan.apply() {
+                        Ok(()) => {
+                            for line in plan.describe(false) {
+                                println!("{}", line);
+                            }
+                            tasks.remove(index);
+                        }
+                        Err(e) => println!("Archiving failed: {}", e),
+                    },
+                    Err(message) => println!("{}", message),
                 }
-                if cell.inverse() {
-                    style = style.add_modifier(Modifier::REVERSED);
+                if tasks.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, "No active tasks left"));
                 }
-
-                spans.push(Span::styled(display_str, style));
-
-                // Wide characters take 2 columns
-                if cell.is_wide() {
-                    col += 2;
-                } else {
-                    col += 1;
+                continue;
+            }
+            Ok(TaskChoice::Unarchive) => {
+                if let Some(dir) = prompt_unarchive(roots)? {
+                    tasks.push(TaskInfo::load(&dir).with_root(roots));
                 }
-            } else {
-                spans.push(Span::raw(" "));
-                col += 1;
+                continue;
             }
-        }
-        lines.push(Line::from(spans));
-    }
+            Ok(TaskChoice::Pick(index)) if tasks[index].running.is_some() => {
+                println!();
+                println!(
+                    "{} is running (pid {}) - enter {}! to open it anyway",
+                    tasks[index].dir.display(),
+                    tasks[index].running.unwrap_or_default(),
+                    index + 1
+                );
+                continue;
+            }
+            Ok(TaskChoice::Pick(index) | TaskChoice::Force(index)) => index,
+            Err(message) => return Err(io::Error::new(io::ErrorKind::InvalidInput, message)),
+        };
 
-    lines
+        println!();
+        println!("Selected: {}", tasks[index].dir.display());
+        println!();
+        return Ok(tasks.swap_remove(index).dir);
+    }
 }
 
-/// Strip ANSI escape sequences from a string for reliable text matching
-fn strip_ansi_codes(s: &str) -> String {
-    let mut result = String::with_capacity(s.len());
-    let mut chars = s.chars().peekable();
-
-    while let Some(c) = chars.next() {
-        if c == '\x1b' {
-            // Skip ESC and the following sequence
-            if let Some(&next) = chars.peek() {
-                if next == '[' {
-                    chars.next(); // consume '['
-                    // Skip until we hit a letter (the terminator)
-                    while let Some(&ch) = chars.peek() {
-                        chars.next();
-                        if ch.is_ascii_alphabetic() {
-                            break;
-                        }
-                    }
-                } else if next == ']' {
-                    // OSC sequence - skip until BEL or ST
-                    chars.next();
-                    while let Some(ch) = chars.next() {
-                        if ch == '\x07' || ch == '\\' {
-                            break;
-                        }
-                    }
-                }
-            }
-        } else {
-            result.push(c);
-        }
+/// List the archived tasks and move the one picked back under its root
+/// Returns its new directory (None when nothing was unarchived).
+fn prompt_unarchive(roots: &[PathBuf]) -> io::Result<Option<PathBuf>> {
+    let archive_roots: Vec<PathBuf> = roots.iter().map(|root| root.join(tasks::ARCHIVE_DIR)).collect();
+    let archived = find_active_tasks(&archive_roots);
+    println!();
+    if archived.is_empty() {
+        println!("No archived tasks.");
+        return Ok(None);
     }
-    result
-}
+    println!("Archived tasks:");
+    println!();
+    for (i, dir) in archived.iter().enumerate() {
+        println!("  {}) {}", i + 1, dir.display());
+    }
+    println!();
+    print!("Unarchive task [1-{}, Enter to go back]: ", archived.len());
+    io::stdout().flush()?;
 
-/// Forward a key event to the PTY
-/// Converts crossterm key events to the appropriate byte sequences for the terminal
-fn forward_key_to_pty(app: &mut App, key_code: KeyCode, modifiers: KeyModifiers) {
-    let bytes: Vec<u8> = match key_code {
-        // Printable characters
-        KeyCode::Char(c) => {
-            if modifiers.contains(KeyModifiers::CONTROL) {
-                // Handle Ctrl+key combinations
-                // Ctrl+A = 0x01, Ctrl+B = 0x02, ..., Ctrl+Z = 0x1A
-                // Ctrl+C = 0x03 (interrupt)
-                if c.is_ascii_alphabetic() {
-                    let ctrl_char = (c.to_ascii_lowercase() as u8) - b'a' + 1;
-                    vec![ctrl_char]
-                } else if c == '[' {
-                    vec![0x1b] // Escape
-                } else if c == '\\' {
-                    vec![0x1c] // File separator (Ctrl+\)
-                } else if c == ']' {
-                    vec![0x1d] // Group separator (Ctrl+])
-                } else if c == '^' {
-                    vec![0x1e] // Record separator (Ctrl+^)
-                } else if c == '_' {
-                    vec![0x1f] // Unit separator (Ctrl+_)
-                } else {
-                    // Just send the character for other Ctrl combinations
-                    c.to_string().into_bytes()
-                }
-            } else if modifiers.contains(KeyModifiers::ALT) {
-                // Alt+key sends ESC followed by the character
-                let mut bytes = vec![0x1b]; // ESC
-                bytes.extend(c.to_string().into_bytes());
-                bytes
-            } else {
-                // Regular character
-                c.to_string().into_bytes()
-            }
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if input.trim().is_empty() {
+        return Ok(None);
+    }
+    let index = match parse_task_choice(&input, archived.len()) {
+        Ok(TaskChoice::Pick(index)) => index,
+        Ok(_) => return Ok(None),
+        Err(message) => {
+            println!("{}", message);
+            return Ok(None);
         }
-
-        // Special keys
-        KeyCode::Enter => {
-            if modifiers.contains(KeyModifiers::SHIFT) {
-                // Shift+Enter: send newline for multi-line input
-                // Some terminals use CSI 13;2u for modified Enter
-                vec![0x1b, b'[', b'1', b'3', b';', b'2', b'u']
-            } else {
-                vec![b'\r'] // Regular Enter: carriage return
-            }
-        }
-        KeyCode::Backspace => vec![0x7f],  // DEL character (most terminals)
-        KeyCode::Delete => vec![0x1b, b'[', b'3', b'~'], // ANSI escape sequence
-        KeyCode::Tab => vec![b'\t'],       // Tab character
-
-        // Arrow keys (ANSI escape sequences)
-        KeyCode::Up => vec![0x1b, b'[', b'A'],
-        KeyCode::Down => vec![0x1b, b'[', b'B'],
-        KeyCode::Right => vec![0x1b, b'[', b'C'],
-        KeyCode::Left => vec![0x1b, b'[', b'D'],
-
-        // Home/End keys
-        KeyCode::Home => vec![0x1b, b'[', b'H'],
-        KeyCode::End => vec![0x1b, b'[', b'F'],
-
-        // Page Up/Down
-        KeyCode::PageUp => vec![0x1b, b'[', b'5', b'~'],
-        KeyCode::PageDown => vec![0x1b, b'[', b'6', b'~'],
-
-        // Insert key
-        KeyCode::Insert => vec![0x1b, b'[', b'2', b'~'],
-
-        // Function keys
-        KeyCode::F(1) => vec![0x1b, b'O', b'P'],
-        KeyCode::F(2) => vec![0x1b, b'O', b'Q'],
-        KeyCode::F(3) => vec![0x1b, b'O', b'R'],
-        KeyCode::F(4) => vec![0x1b, b'O', b'S'],
-        KeyCode::F(5) => vec![0x1b, b'[', b'1', b'5', b'~'],
-        KeyCode::F(6) => vec![0x1b, b'[', b'1', b'7', b'~'],
-        KeyCode::F(7) => vec![0x1b, b'[', b'1', b'8', b'~'],
-        KeyCode::F(8) => vec![0x1b, b'[', b'1', b'9', b'~'],
-        KeyCode::F(9) => vec![0x1b, b'[', b'2', b'0', b'~'],
-        KeyCode::F(10) => vec![0x1b, b'[', b'2', b'1', b'~'],
-        KeyCode::F(11) => vec![0x1b, b'[', b'2', b'3', b'~'],
-        KeyCode::F(12) => vec![0x1b, b'[', b'2', b'4', b'~'],
-        KeyCode::F(_) => return, // Unsupported function keys
-
-        // Escape key - send raw ESC byte
-        KeyCode::Esc => vec![0x1b],
-
-        // Other keys we don't handle
-        _ => return,
     };
-
-    app.write_to_pty(&bytes);
+    println!();
+    let plan = match plan_unarchive(&archived[index]) {
+        Ok(plan) => plan,
+        Err(message) => {
+            println!("{}", message);
+            return Ok(None);
+        }
+    };
+    if let Err(e) = plan.apply() {
+        println!("Unarchiving failed: {}", e);
+        return Ok(None);
+    }
+    for line in plan.describe(false) {
+        println!("{}", line);
+    }
+    Ok(Some(plan.to))
 }
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
-
-fn print_usage() {
-    eprintln!("Ralph TUI - Interactive terminal interface for Ralph agent");
-    eprintln!();
-    eprintln!("Usage: ralph-tui [task-directory] [OPTIONS]");
-    eprintln!();
-    eprintln!("Arguments:");
-    eprintln!("  [task-directory]  Path to the task directory containing prd.json");
-    eprintln!("                    If omitted, prompts for task selection");
-    eprintln!();
-    eprintln!("Options:");
-    eprintln!("  -i, --iterations <N>   Maximum iterations to run (default: 10)");
-    eprintln!("  --rotate-at <N>        Rotate progress file at N lines (default: 300)");
-    eprintln!("  -y, --yes              Skip confirmation prompts");
-    eprintln!("  -h, --help             Show this help message");
-    eprintln!("  -V, --version          Show version");
-    eprintln!();
-    eprintln!("Examples:");
-    eprintln!("  ralph-tui                          # Interactive task selection");
-    eprintln!("  ralph-tui tasks/my-feature         # Run specific task");
-    eprintln!("  ralph-tui tasks/my-feature -i 5    # Run with 5 iterations");
-}
+/// Prompt for extra arguments passed to the agent (Enter keeps `default`)
+fn prompt_agent_args(default: &str) -> io::Result<String> {
+    print!("Extra agent arguments [{}]: ", default);
+    io::stdout().flush()?;
 
-/// Configuration from CLI arguments
-struct CliConfig {
-    task_dir: PathBuf,
-    max_iterations: u32,
-    rotate_threshold: u32,
-    skip_prompts: bool,
-}
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
 
-/// Find active tasks (directories with prd.json, excluding archived)
-fn find_active_tasks() -> Vec<PathBuf> {
-    let tasks_dir = PathBuf::from("tasks");
-    if !tasks_dir.exists() {
-        return Vec::new();
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(default.to_string());
     }
+    Ok(input.to_string())
+}
 
-    let mut tasks = Vec::new();
+/// Models offered by the model prompt (any other name can be typed in)
+const MODEL_CHOICES: [&str; 3] = ["sonnet", "opus", "haiku"];
 
-    // Look for prd.json files in tasks/ subdirectories
-    if let Ok(entries) = std::fs::read_dir(&tasks_dir) {
-        for entry in entries.filter_map(|e| e.ok()) {
-            let path = entry.path();
-            // Skip archived directory
-            if path.file_name().map_or(false, |n| n == "archived") {
-                continue;
-            }
-            if path.is_dir() {
-                let prd_path = path.join("prd.json");
-                if prd_path.exists() {
-                    tasks.push(path);
-                }
-            }
-        }
+/// Prompt for the agent's model: a number from MODEL_CHOICES or a custom name
+/// Enter keeps `default`; None leaves the choice to the claude CLI.
+fn prompt_model(default: Option<&str>) -> io::Result<Option<String>> {
+    for (i, name) in MODEL_CHOICES.iter().enumerate() {
+        println!("  {}. {}", i + 1, name);
     }
+    print!("Model (number or name) [{}]: ", default.unwrap_or("claude default"));
+    io::stdout().flush()?;
 
-    tasks.sort();
-    tasks
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(parse_model_choice(input.trim()).or_else(|| default.map(str::to_string)))
 }
 
-/// Get task info for display
-fn get_task_info(task_dir: &PathBuf) -> (String, usize, usize, String) {
-    let prd_path = task_dir.join("prd.json");
-    let content = std::fs::read_to_string(&prd_path).unwrap_or_default();
-
-    // Parse JSON to get info
-    if let Ok(prd) = serde_json::from_str::<serde_json::Value>(&content) {
-        let description = prd.get("description")
-            .and_then(|v| v.as_str())
-            .unwrap_or("No description")
-            .chars()
-            .take(50)
-            .collect::<String>();
-
-        let stories = prd.get("userStories")
-            .and_then(|v| v.as_array())
-            .map(|arr| arr.len())
-            .unwrap_or(0);
-
-        let completed = prd.get("userStories")
-            .and_then(|v| v.as_array())
-            .map(|arr| arr.iter().filter(|s| {
-                s.get("passes").and_then(|v| v.as_bool()).unwrap_or(false)
-            }).count())
-            .unwrap_or(0);
-
-        let prd_type = prd.get("type")
-            .and_then(|v| v.as_str())
-            .unwrap_or("feature")
-            .to_string();
-
-        (description, completed, stories, prd_type)
-    } else {
-        ("Unable to parse prd.json".to_string(), 0, 0, "unknown".to_string())
+/// Model picked at the prompt: "2" is the second entry of MODEL_CHOICES, anything else
+/// is taken as a model name ("" = no choice)
+fn parse_model_choice(input: &str) -> Option<String> {
+    if input.is_empty() {
+        return None;
     }
+    let choice = input
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|n| MODEL_CHOICES.get(n));
+    Some(choice.copied().unwrap_or(input).to_string())
 }
 
-/// Display task selection prompt and return selected task
-fn prompt_task_selection(tasks: &[PathBuf]) -> io::Result<PathBuf> {
-    println!();
-    println!("╔═══════════════════════════════════════════════════════════════╗");
-    println!("║  Ralph TUI - Select a Task                                    ║");
-    println!("╚═══════════════════════════════════════════════════════════════╝");
-    println!();
-    println!("Active tasks:");
-    println!();
-
-    for (i, task) in tasks.iter().enumerate() {
-        let (desc, completed, total, prd_type) = get_task_info(task);
-        let task_name = task.display().to_string();
-        println!(
-            "  {}) {:35} [{}/{}] ({})",
-            i + 1,
-            task_name,
-            completed,
-            total,
-            prd_type
-        );
-        if !desc.is_empty() {
-            println!("     {}", desc);
-        }
+/// Prompt for the agent profile: a number from the list or a profile name
+/// Enter (or an unknown name) keeps `default`.
+fn prompt_agent(profiles: &[AgentProfile], default: &str) -> io::Result<String> {
+    for (i, profile) in profiles.iter().enumerate() {
+        println!("  {}. {} ({})", i + 1, profile.name, profile.binary);
     }
-
-    println!();
-    print!("Select task [1-{}]: ", tasks.len());
+    print!("Agent (number or name) [{}]: ", default);
     io::stdout().flush()?;
 
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
+    Ok(parse_agent_choice(input.trim(), profiles).unwrap_or_else(|| default.to_string()))
+}
 
-    let selection: usize = input.trim().parse().map_err(|_| {
-        io::Error::new(io::ErrorKind::InvalidInput, "Invalid selection")
-    })?;
-
-    if selection < 1 || selection > tasks.len() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Selection out of range",
-        ));
-    }
-
-    println!();
-    println!("Selected: {}", tasks[selection - 1].display());
-    println!();
-
-    Ok(tasks[selection - 1].clone())
+/// Profile picked at the prompt: "2" is the second profile, or a profile's name
+fn parse_agent_choice(input: &str, profiles: &[AgentProfile]) -> Option<String> {
+    let by_number = input
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|n| profiles.get(n));
+    by_number
+        .or_else(|| profiles.iter().find(|p| p.name == input))
+        .map(|p| p.name.clone())
 }
 
-/// Prompt for iterations if not provided
-fn prompt_iterations() -> io::Result<u32> {
-    print!("Max iterations [10]: ");
+/// Prompt for iterations if not provided (Enter keeps the task's `default`)
+fn prompt_iterations(default: u32) -> io::Result<u32> {
+    print!("Max iterations [{}]: ", default);
     io::stdout().flush()?;
 
     let mut input = String::new();
@@ -1300,13 +940,42 @@ fn prompt_iterations() -> io::Result<u32> {
 
     let input = input.trim();
     if input.is_empty() {
-        return Ok(10);
+        return Ok(default);
     }
 
     input.parse().map_err(|_| {
-        eprintln!("Invalid number. Using default of 10.");
+        eprintln!("Invalid number. Using default of {}.", default);
         io::Error::new(io::ErrorKind::InvalidInput, "Invalid number")
-    }).or(Ok(10))
+    }).or(Ok(default))
+}
+
+/// Ask whether to resume a crashed session (Enter resumes)
+fn prompt_resume(task_dir: &Path, recovery: &RecoverySnapshot) -> io::Result<bool> {
+    println!();
+    println!(
+        "The last session in {} ended unexpectedly at iteration {} of {}.",
+        task_dir.display(),
+        recovery.current_iteration,
+        recovery.max_iterations
+    );
+    print!("Resume it? [Y/n]: ");
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(!input.trim().to_lowercase().starts_with('n'))
+}
+
+/// Keep a crashed session's recovery snapshot (App::new resumes from it) or drop it
+/// --yes resumes without asking.
+fn confirm_recovery(config: &CliConfig) -> io::Result<()> {
+    let Some(recovery) = SessionState::load(&config.task_dir).recovery else {
+        return Ok(());
+    };
+    if !config.skip_prompts && !prompt_resume(&config.task_dir, &recovery)? {
+        recovery::clear(&config.task_dir);
+    }
+    Ok(())
 }
 
 /// Prompt for rotation threshold
@@ -1330,13 +999,71 @@ fn prompt_rotation_threshold(current: u32, progress_lines: usize) -> io::Result<
     }).or(Ok(current))
 }
 
-/// Parse CLI arguments and return configuration
-fn parse_args() -> io::Result<CliConfig> {
-    let args: Vec<String> = std::env::args().collect();
-    let mut task_dir: Option<PathBuf> = None;
+/// Parse CLI arguments and return the configuration of each task to run
+/// More than one task (several task directories or --queue) is a batch, run one after another.
+fn parse_args() -> io::Result<Vec<CliConfig>> {
+    let mut args: Vec<String> = std::env::args().collect();
+    let task_root_flag = take_task_root_flag(&mut args)?;
+    let mut task_args: Vec<PathBuf> = Vec::new();
+    let mut queue_file: Option<PathBuf> = None;
     let mut max_iterations: Option<u32> = None;
-    let mut rotate_threshold: u32 = 300;
+    let mut rotate_threshold: Option<u32> = None;
     let mut skip_prompts = false;
+    let mut events_socket: Option<PathBuf> = None;
+    let mut metrics_addr = None;
+    let mut once = false;
+    let mut stuck_threshold = DEFAULT_STUCK_THRESHOLD;
+    let mut flat_after = DEFAULT_FLAT_AFTER;
+    let mut min_free_mb = disk::DEFAULT_MIN_FREE_MB;
+    let mut behind_warn = DEFAULT_BEHIND_WARN;
+    let mut sync_strategy = SyncStrategy::default();
+    let mut idle_after = DEFAULT_IDLE_SECS;
+    let mut prompt_pattern = Regex::new(DEFAULT_PROMPT_PATTERN).expect("default prompt pattern is valid");
+    let mut nudge_after: u64 = 0;
+    let mut prd_idle_after = DEFAULT_PRD_IDLE_SECS;
+    let mut linger_ms = DEFAULT_LINGER_MS;
+    let mut nudge_message = DEFAULT_NUDGE_MESSAGE.to_string();
+    let mut agent_args: Option<String> = None;
+    let mut model: Option<String> = None;
+    let mut agent: Option<String> = None;
+    let mut targeted_stories: Option<BTreeSet<String>> = None;
+    let mut kitty_keys = false;
+    let mut key_guard = true;
+    let mut end_on_compaction = false;
+    let mut squash_on_complete = false;
+    let mut terminal_title = true;
+    let mut progress_strip = false;
+    let mut no_sidebar = false;
+    let mut parallel: Option<usize> = None;
+    let mut max_runtime: Option<u64> = None;
+    let mut error_patterns: Option<Vec<String>> = None;
+    let mut error_bell = false;
+
+    // Subcommands
+    if args.get(1).map(String::as_str) == Some("init") {
+        run_init()?;
+        std::process::exit(0);
+    }
+    if args.get(1).map(String::as_str) == Some("digest") {
+        run_digest(&args[2..], &task_roots(&task_root_flag)?)?;
+        std::process::exit(0);
+    }
+    if args.get(1).map(String::as_str) == Some("annotate") {
+        run_annotate(&args[2..], &task_roots(&task_root_flag)?)?;
+        std::process::exit(0);
+    }
+    if args.get(1).map(String::as_str) == Some("archive") {
+        run_archive(&args[2..], false, &task_roots(&task_root_flag)?)?;
+        std::process::exit(0);
+    }
+    if args.get(1).map(String::as_str) == Some("unarchive") {
+        run_archive(&args[2..], true, &task_roots(&task_root_flag)?)?;
+        std::process::exit(0);
+    }
+    if args.get(1).map(String::as_str) == Some("status") {
+        let code = run_status(&args[2..], &task_roots(&task_root_flag)?)?;
+        std::process::exit(code);
+    }
 
     let mut i = 1;
     while i < args.len() {
@@ -1350,6 +1077,90 @@ fn parse_args() -> io::Result<CliConfig> {
         } else if arg == "-y" || arg == "--yes" {
             skip_prompts = true;
             i += 1;
+        } else if arg == "--error-pattern" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --error-pattern",
+                ));
+            }
+            if args[i].is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Invalid error-pattern value: pattern is empty",
+                ));
+            }
+            // The first --error-pattern replaces the defaults, later ones add to it
+            error_patterns.get_or_insert_with(Vec::new).push(args[i].clone());
+            i += 1;
+        } else if arg == "--error-bell" {
+            error_bell = true;
+            i += 1;
+        } else if arg == "--max-runtime" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --max-runtime",
+                ));
+            }
+            max_runtime = Some(digest::parse_duration(&args[i]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid max-runtime value: {} (use e.g. 90m or 6h)", args[i]),
+                )
+            })?);
+            i += 1;
+        } else if arg == "--parallel" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --parallel",
+                ));
+            }
+            let lanes = args[i]
+                .parse::<usize>()
+                .ok()
+                .filter(|n| (2..=lanes::MAX_LANES).contains(n))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid parallel value: {} (expected 2 to {})", args[i], lanes::MAX_LANES),
+                    )
+                })?;
+            parallel = Some(lanes);
+            i += 1;
+        } else if arg == "--progress-strip" {
+            progress_strip = true;
+            i += 1;
+        } else if arg == "--no-sidebar" {
+            no_sidebar = true;
+            i += 1;
+        } else if arg == "--no-key-guard" {
+            key_guard = false;
+            i += 1;
+        } else if arg == "--end-on-compaction" {
+            end_on_compaction = true;
+            i += 1;
+        } else if arg == "--squash-on-complete" {
+            squash_on_complete = true;
+            i += 1;
+        } else if arg == "--no-title" {
+            terminal_title = false;
+            i += 1;
+        } else if arg == "--kitty-keys" {
+            kitty_keys = true;
+            i += 1;
+        } else if arg == "--once" {
+            // Scripted single iteration: no interactive prompts
+            once = true;
+            skip_prompts = true;
+            i += 1;
         } else if arg == "-i" || arg == "--iterations" {
             i += 1;
             if i >= args.len() {
@@ -1375,113 +1186,599 @@ fn parse_args() -> io::Result<CliConfig> {
                     "Missing value for --rotate-at",
                 ));
             }
-            rotate_threshold = args[i].parse().map_err(|_| {
+            rotate_threshold = Some(args[i].parse().map_err(|_| {
                 io::Error::new(
                     io::ErrorKind::InvalidInput,
                     format!("Invalid rotate-at value: {}", args[i]),
                 )
+            })?);
+            i += 1;
+        } else if arg == "--stuck-after" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --stuck-after",
+                ));
+            }
+            stuck_threshold = args[i].parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid stuck-after value: {}", args[i]),
+                )
             })?;
             i += 1;
-        } else if !arg.starts_with('-') {
-            task_dir = Some(PathBuf::from(arg));
+        } else if arg == "--min-free" {
             i += 1;
-        } else {
-            print_usage();
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                format!("Unknown argument: {}", arg),
-            ));
-        }
-    }
-
-    // If no task directory provided, find and prompt
-    let task_dir = if let Some(dir) = task_dir {
-        dir
-    } else {
-        let tasks = find_active_tasks();
-        if tasks.is_empty() {
-            println!("No active tasks found.");
-            println!();
-            println!("To create a new task:");
-            println!("  1. Use /prd to create a PRD in tasks/{{effort-name}}/");
-            println!("  2. Use /ralph to convert it to prd.json");
-            println!("  3. Run: ralph-tui tasks/{{effort-name}}");
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "No active tasks found",
-            ));
-        } else if tasks.len() == 1 {
-            println!("Found one active task: {}", tasks[0].display());
-            println!();
-            tasks[0].clone()
-        } else {
-            prompt_task_selection(&tasks)?
-        }
-    };
-
-    // Prompt for iterations if not provided and not skipping prompts
-    let max_iterations = if let Some(iters) = max_iterations {
-        iters
-    } else if skip_prompts {
-        10
-    } else {
-        prompt_iterations().unwrap_or(10)
-    };
-
-    // Check progress file for rotation threshold prompt
-    let progress_path = task_dir.join("progress.txt");
-    if progress_path.exists() && !skip_prompts {
-        if let Ok(content) = std::fs::read_to_string(&progress_path) {
-            let lines = content.lines().count();
-            // Prompt if within 50 lines of threshold or has prior rotations
-            let has_prior_rotation = task_dir.join("progress-1.txt").exists();
-            if lines > rotate_threshold.saturating_sub(50) as usize || has_prior_rotation {
-                rotate_threshold = prompt_rotation_threshold(rotate_threshold, lines)
-                    .unwrap_or(rotate_threshold);
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --min-free",
+                ));
             }
-        }
-    }
-
-    Ok(CliConfig {
-        task_dir,
-        max_iterations,
-        rotate_threshold,
-        skip_prompts,
-    })
-}
-
-/// Spawn Claude Code process and return (child, reader_thread)
-/// Returns None if spawning fails
-fn spawn_claude(
-    app: &mut App,
-    pty_rows: u16,
-    pty_cols: u16,
-) -> io::Result<(Box<dyn portable_pty::Child + Send + Sync>, thread::JoinHandle<()>)> {
-    // Build the Ralph prompt
-    let ralph_prompt = build_ralph_prompt(&app.task_dir)?;
-
-    // Write prompt to a temp file for safe handling of special characters
-    let prompt_temp_file = std::env::temp_dir().join(format!(
-        "ralph_prompt_{}_{}.txt",
-        std::process::id(),
-        app.current_iteration
-    ));
-    std::fs::write(&prompt_temp_file, &ralph_prompt)?;
-
-    // Create PTY
-    let pty_system = native_pty_system();
-    let pair = pty_system
-        .openpty(PtySize {
-            rows: pty_rows,
+            min_free_mb = args[i].parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid min-free value: {} (megabytes)", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--flat-after" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --flat-after",
+                ));
+            }
+            flat_after = digest::parse_duration(&args[i]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid flat-after value: {} (use e.g. 15m or 1h)", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--behind-warn" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --behind-warn",
+                ));
+            }
+            behind_warn = args[i].parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid behind-warn value: {}", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--sync-strategy" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --sync-strategy",
+                ));
+            }
+            sync_strategy = SyncStrategy::parse(&args[i]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid sync-strategy value: {} (expected merge or rebase)", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--idle-after" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --idle-after",
+                ));
+            }
+            idle_after = args[i].parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid idle-after value: {}", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--prompt-pattern" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --prompt-pattern",
+                ));
+            }
+            prompt_pattern = Regex::new(&args[i]).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid prompt-pattern regex: {}", e),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--nudge-after" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --nudge-after",
+                ));
+            }
+            nudge_after = args[i].parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid nudge-after value: {}", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--prd-idle" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --prd-idle",
+                ));
+            }
+            prd_idle_after = args[i].parse().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid prd-idle value: {}", args[i]),
+                )
+            })?;
+            i += 1;
+        } else if arg == "--linger" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --linger",
+                ));
+            }
+            let secs = args[i].parse::<f64>().ok().filter(|secs| secs.is_finite() && *secs >= 0.0).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid linger value: {} (seconds, e.g. 1.5; 0 turns it off)", args[i]),
+                )
+            })?;
+            linger_ms = (secs * 1000.0).round() as u64;
+            i += 1;
+        } else if arg == "--nudge-message" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --nudge-message",
+                ));
+            }
+            nudge_message = args[i].clone();
+            i += 1;
+        } else if arg == "--agent-args" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --agent-args",
+                ));
+            }
+            agent_args = Some(args[i].clone());
+            i += 1;
+        } else if arg == "--model" {
+            i += 1;
+            if i >= args.len() || args[i].trim().is_empty() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --model",
+                ));
+            }
+            model = Some(args[i].trim().to_string());
+            i += 1;
+        } else if arg == "--agent" {
+            i += 1;
+            if i >= args.len() || args[i].trim().is_empty() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --agent",
+                ));
+            }
+            agent = Some(args[i].trim().to_string());
+            i += 1;
+        } else if arg == "--stories" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --stories",
+                ));
+            }
+            let ids: BTreeSet<String> = args[i]
+                .split(',')
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+                .collect();
+            if ids.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid stories value: {}", args[i]),
+                ));
+            }
+            targeted_stories = Some(ids);
+            i += 1;
+        } else if arg == "--events-socket" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --events-socket",
+                ));
+            }
+            events_socket = Some(PathBuf::from(&args[i]));
+            i += 1;
+        } else if arg == "--metrics-port" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Missing value for --metrics-port",
+                ));
+            }
+            metrics_addr = Some(
+                metrics::parse_bind_addr(&args[i])
+                    .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?,
+            );
+            i += 1;
+        } else if arg == "--queue" {
+            i += 1;
+            if i >= args.len() {
+                print_usage();
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Missing value for --queue"));
+            }
+            queue_file = Some(PathBuf::from(&args[i]));
+            i += 1;
+        } else if !arg.starts_with('-') {
+            task_args.push(PathBuf::from(arg));
+            i += 1;
+        } else {
+            print_usage();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown argument: {}", arg),
+            ));
+        }
+    }
+
+    if let Some(ref path) = queue_file {
+        let listed = std::fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("Failed to read queue file {}: {}", path.display(), e)))?;
+        task_args.extend(parse_queue_file(&listed));
+    }
+
+    // If no task directory provided, find and prompt
+    let roots = task_roots(&task_root_flag)?;
+    let tasks: Vec<(PathBuf, PathBuf)> = if !task_args.is_empty() {
+        task_args.into_iter().map(|arg| resolve_task_arg(arg, &roots)).collect()
+    } else {
+        let tasks = discover_tasks(&roots);
+        if tasks.is_empty() {
+            let searched: Vec<String> = roots.iter().map(|root| root.display().to_string()).collect();
+            println!("No active tasks found in {}.", searched.join(", "));
+            println!();
+            println!("To create a new task:");
+            println!("  1. Use /prd to create a PRD in {}/{{effort-name}}/", searched[0]);
+            println!("  2. Use /ralph to convert it to prd.json");
+            println!("  3. Run: ralph-tui {{effort-name}}");
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No active tasks found",
+            ));
+        } else if tasks.len() == 1 {
+            println!("Found one active task: {}", tasks[0].dir.display());
+            println!();
+            vec![resolve_task_arg(tasks[0].dir.clone(), &[])]
+        } else {
+            vec![resolve_task_arg(prompt_task_selection(tasks, &roots)?, &[])]
+        }
+    };
+
+    if once && parallel.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--parallel cannot be combined with --once",
+        ));
+    }
+
+    // A batch runs unattended, one task after another, in a single TUI session
+    let batch = tasks.len() > 1;
+    if batch {
+        let single_task_flag = [
+            (once, "--once"),
+            (targeted_stories.is_some(), "--stories"),
+            (events_socket.is_some(), "--events-socket"),
+        ]
+        .into_iter()
+        .find_map(|(set, flag)| set.then_some(flag));
+        if let Some(flag) = single_task_flag {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} applies to a single task and cannot be used with several tasks", flag),
+            ));
+        }
+        skip_prompts = true;
+    }
+
+    let ralph_config = config::load_config(ralph_config_dir().as_deref())?;
+    let keymap = keymap::KeyMap::from_bindings(&ralph_config.keys).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("~/.config/ralph/{}: {}", config::CONFIG_FILE, e))
+    })?;
+    let profiles = agents::profiles(&ralph_config.agents)?;
+    let dangerous_commands = ralph_config.dangerous_commands.unwrap_or_else(|| {
+        tripwire::DEFAULT_DANGEROUS_COMMANDS.iter().map(|pattern| pattern.to_string()).collect()
+    });
+    let mut configs = Vec::with_capacity(tasks.len());
+    // `claude --version` is asked once per binary, however many tasks use it
+    let mut agent_versions: HashMap<String, Option<AgentVersion>> = HashMap::new();
+    for (task_dir, prd_path) in tasks {
+        // Task-specific defaults from prd.json (ralph.toml's apply to every task)
+        let task_prd = Prd::load(&prd_path).ok();
+        if let (Some(prd), Some(ids)) = (&task_prd, &targeted_stories) {
+            let unknown: Vec<&str> = ids
+                .iter()
+                .filter(|id| !prd.user_stories.iter().any(|s| &s.id == *id))
+                .map(String::as_str)
+                .collect();
+            if !unknown.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown story ids in --stories: {}", unknown.join(", ")),
+                ));
+            }
+        }
+        let (prd_iterations, prd_rotate_threshold, prd_agent_args, prd_model, prd_agent) = match task_prd {
+            Some(prd) => (prd.default_iterations, prd.rotate_threshold, prd.agent_args, prd.model, prd.agent),
+            None => (None, None, None, None, None),
+        };
+
+        // Prompt for iterations if not provided and not skipping prompts
+        // (--once always runs exactly one iteration)
+        let (mut max_iterations, mut max_iterations_source) =
+            resolve_setting(max_iterations, prd_iterations, ralph_config.default_iterations, DEFAULT_ITERATIONS);
+        if once {
+            (max_iterations, max_iterations_source) = (1, ValueSource::Flag);
+        } else if max_iterations_source != ValueSource::Flag && !skip_prompts {
+            let entered = prompt_iterations(max_iterations).unwrap_or(max_iterations);
+            if entered != max_iterations {
+                (max_iterations, max_iterations_source) = (entered, ValueSource::Prompt);
+            }
+        }
+
+        // Agent profile: --agent, else the task's prd.json agent (the prompt offers it as the default)
+        let default_agent = prd_agent.unwrap_or_else(|| agents::DEFAULT_AGENT.to_string());
+        let agent_name = match agent.clone() {
+            Some(agent) => agent,
+            None if skip_prompts => default_agent,
+            None => prompt_agent(&profiles, &default_agent).unwrap_or(default_agent),
+        };
+        let agent = profiles.iter().find(|p| p.name == agent_name).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown agent: {} (define it as [agents.{}] in ~/.config/ralph/{})", agent_name, agent_name, config::CONFIG_FILE),
+            )
+        })?;
+
+        // Extra agent arguments: --agent-args, else the task's prd.json agentArgs
+        // (offered as the default at the interactive prompt, Enter keeps it)
+        let default_agent_args = agent_args.clone().or(prd_agent_args).unwrap_or_default();
+        let agent_args_text = if skip_prompts {
+            default_agent_args
+        } else {
+            prompt_agent_args(&default_agent_args).unwrap_or(default_agent_args)
+        };
+        let agent_args = split_shell_words(&agent_args_text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid agent arguments ({}): {}", e, agent_args_text),
+            )
+        })?;
+
+        // Model: --model, else the task's prd.json model (the prompt offers it as the default)
+        let model = match model.clone() {
+            Some(model) => Some(model),
+            None if skip_prompts => prd_model,
+            None => prompt_model(prd_model.as_deref()).unwrap_or(prd_model),
+        };
+
+        // A task that hasn't started can get its own copy of the prompt to customize
+        if !skip_prompts
+            && let Err(e) = offer_task_prompt(&task_dir)
+        {
+            eprintln!("Warning: could not copy the prompt into {}: {}", task_dir.display(), e);
+        }
+
+        // Check progress file for rotation threshold prompt
+        let (mut rotate_threshold, mut rotate_threshold_source) =
+            resolve_setting(rotate_threshold, prd_rotate_threshold, ralph_config.rotate_threshold, DEFAULT_ROTATE_THRESHOLD);
+        let progress_path = task_dir.join("progress.txt");
+        if progress_path.exists()
+            && !skip_prompts
+            && let Ok(content) = std::fs::read_to_string(&progress_path)
+        {
+            let lines = progress_line_count(&content);
+            // Prompt if within 50 lines of threshold or has prior rotations
+            let has_prior_rotation = task_dir.join("progress-1.txt").exists();
+            if lines > rotate_threshold.saturating_sub(50) as usize || has_prior_rotation {
+                let entered = prompt_rotation_threshold(rotate_threshold, lines)
+                    .unwrap_or(rotate_threshold);
+                if entered != rotate_threshold {
+                    (rotate_threshold, rotate_threshold_source) = (entered, ValueSource::Prompt);
+                }
+            }
+        }
+
+        let agent_version = agent_versions.entry(agent.binary.clone()).or_insert_with(|| compat::detect(&agent)).clone();
+
+        configs.push(CliConfig {
+            task_dir,
+            prd_path,
+            max_iterations,
+            max_iterations_source,
+            rotate_threshold,
+            rotate_threshold_source,
+            skip_prompts,
+            events_socket: events_socket.clone(),
+            metrics_addr,
+            once,
+            stuck_threshold,
+            flat_after,
+            min_free_mb,
+            behind_warn,
+            sync_strategy,
+            idle_after,
+            prompt_pattern: prompt_pattern.clone(),
+            nudge_after,
+            prd_idle_after,
+            linger_ms,
+            nudge_message: nudge_message.clone(),
+            end_on_compaction: end_on_compaction || ralph_config.end_on_compaction,
+            squash_on_complete,
+            agent_args,
+            model,
+            agent,
+            agent_version,
+            keymap: keymap.clone(),
+            targeted_stories: targeted_stories.clone(),
+            kitty_keys,
+            key_guard: key_guard && ralph_config.key_guard.unwrap_or(true),
+            terminal_title: terminal_title && !ralph_config.no_title,
+            progress_strip,
+            no_sidebar,
+            parallel,
+            max_runtime,
+            error_patterns: error_patterns
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ERROR_PATTERNS.iter().map(|p| p.to_string()).collect()),
+            error_bell,
+            dangerous_commands: dangerous_commands.clone(),
+            pause_on_prd_churn: ralph_config.pause_on_prd_churn,
+            show_pty_size: ralph_config.show_pty_size,
+            verify_criteria: ralph_config.verify_criteria,
+            verify_timeout_secs: ralph_config.verify_timeout_secs.unwrap_or(verify::DEFAULT_VERIFY_TIMEOUT_SECS),
+            footer_tips: !ralph_config.hide_tips,
+            prompt_via_stdin: ralph_config.prompt_via_stdin,
+            env: ralph_config.env.clone(),
+            on_iteration_end: ralph_config.on_iteration_end.clone(),
+            on_complete: ralph_config.on_complete.clone(),
+            verified_match: ralph_config.check_verified_notes.then(|| {
+                f64::from(ralph_config.verified_match_percent.unwrap_or(crosscheck::DEFAULT_MATCH_PERCENT).min(100)) / 100.0
+            }),
+        });
+    }
+    Ok(configs)
+}
+
+/// Spawn Claude Code process and return (child, reader_thread)
+/// Returns None if spawning fails
+fn spawn_claude(
+    app: &mut App,
+    pty_rows: u16,
+    pty_cols: u16,
+) -> io::Result<(Box<dyn portable_pty::Child + Send + Sync>, thread::JoinHandle<()>)> {
+    // Build the Ralph prompt (the task's prompt.md first; the Ralph panel shows which one)
+    app.prompt_source = find_prompt_path(&app.task_dir).map(|path| path.display().to_string());
+    let ralph_prompt = build_ralph_prompt(
+        &app.task_dir,
+        &app.prd_path,
+        &app.session_state.targeted_stories,
+        &app.skipped_stories,
+        app.session_state.focused_criterion.as_ref(),
+    )?;
+
+    // Record the tree before the agent touches it (snapshotEachIteration, and the changes view)
+    app.mark_iteration_start();
+    app.record_iteration_base();
+    app.mark_progress_baseline();
+
+    // continueConversation: resume the previous iteration's conversation
+    let resume_args = app.next_conversation_args();
+    let agent = spawn_agent(
+        app,
+        &ralph_prompt,
+        app.iteration_env(),
+        SIGNAL_FILE,
+        &resume_args,
+        &app.pty_state,
+        pty_rows,
+        pty_cols,
+    )?;
+
+    // Update app state
+    app.master_pty = Some(agent.master);
+    app.pty_writer = Some(agent.writer);
+    app.pending_prompt = agent.pending_prompt;
+
+    app.iteration_state = IterationState::Running;
+    app.begin_story_attempt();
+    app.reader_error = None;
+
+    Ok((agent.child, agent.reader_thread))
+}
+
+/// A spawned agent: its process, PTY master, input writer and output reader thread, and
+/// the prompt still to be typed in when it was too long for argv
+struct AgentProcess {
+    child: Box<dyn portable_pty::Child + Send + Sync>,
+    reader_thread: thread::JoinHandle<()>,
+    master: Box<dyn portable_pty::MasterPty + Send>,
+    writer: PtyWriter,
+    pending_prompt: Option<PendingPrompt>,
+}
+
+/// Spawn the agent with `prompt` in a new PTY whose output feeds `pty_state`
+/// `signal_file` is the control file name its stop hook writes in the task directory, and
+/// `resume_args` pick up an earlier conversation (empty for a fresh one).
+#[allow(clippy::too_many_arguments)]
+fn spawn_agent(
+    app: &App,
+    prompt: &str,
+    env: Vec<(String, String)>,
+    signal_file: &str,
+    resume_args: &[String],
+    pty_state: &Arc<Mutex<PtyState>>,
+    pty_rows: u16,
+    pty_cols: u16,
+) -> io::Result<AgentProcess> {
+    // Create PTY
+    let pty_system = native_pty_system();
+    let pair = pty_system
+        .openpty(PtySize {
+            rows: pty_rows,
             cols: pty_cols,
             pixel_width: 0,
             pixel_height: 0,
         })
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        .map_err(|e| io::Error::other(e.to_string()))?;
 
-    // Spawn Claude Code interactively with the prompt as a positional argument
-    // This runs Claude in full interactive mode with the Ralph prompt
-    let mut cmd = CommandBuilder::new("claude");
+    // Spawn the agent from its profile (Claude Code by default, in full interactive
+    // mode with the Ralph prompt as its last argument)
+    let profile = &app.agent;
+    let mut cmd = CommandBuilder::new(&profile.binary);
 
     // Set working directory to current directory (where ralph-tui was invoked)
     if let Ok(cwd) = std::env::current_dir() {
@@ -1495,133 +1792,954 @@ fn spawn_claude(
     cmd.env("COLORTERM", "truecolor");
     // Explicitly remove NO_COLOR if it's set in the parent environment
     cmd.env_remove("NO_COLOR");
-
-    cmd.arg("--dangerously-skip-permissions");
+    for (name, value) in &profile.env {
+        cmd.env(name, value);
+    }
+    // Per-task variables from prd.json, then the loop variables (RALPH_TASK_DIR also
+    // tells the stop hook where to write the .ralph-signal control file)
+    for (name, value) in env {
+        cmd.env(name, value);
+    }
 
     // Use ralph settings file for stop hook (enables iteration detection)
-    // Settings are installed to ~/.config/ralph/settings.json by install.sh (Unix)
-    // or %USERPROFILE%\.config\ralph\settings.json by install.ps1 (Windows)
-    let home_dir = if cfg!(windows) {
-        std::env::var_os("USERPROFILE")
+    // Settings are installed to ~/.config/ralph/settings.json by install.sh (Unix),
+    // %USERPROFILE%\.config\ralph\settings.json by install.ps1 (Windows), or `ralph-tui init`
+    let settings = ralph_config_dir()
+        .map(|config_dir| config_dir.join("settings.json"))
+        .filter(|path| path.exists())
+        .map(|path| path.to_string_lossy().to_string());
+
+    // Agents that read the prompt from a file get one per agent (lanes have their own)
+    let prompt_file = if profile.uses_prompt_file() {
+        let path = prompt_file_path(signal_file);
+        std::fs::write(&path, prompt)?;
+        Some(path.to_string_lossy().to_string())
     } else {
-        std::env::var_os("HOME")
+        None
+    };
+
+    // A prompt too long for the command line (or any prompt, with prompt_via_stdin) is
+    // typed in after the spawn instead of passed as {prompt}, as a paste once the agent
+    // is ready for one
+    let delivery = profile.prompt_delivery(prompt, app.prompt_via_stdin);
+    let pending_prompt = match (delivery, profile.prompt_via) {
+        (PromptDelivery::Stdin, PromptDelivery::Argv) => Some(PendingPrompt::new(prompt)?),
+        _ => None,
+    };
+
+    // Model from --model / prd.json model, extra arguments from --agent-args / prd.json agentArgs
+    let args = profile.expand_args(&ArgValues {
+        prompt: (delivery == PromptDelivery::Argv).then_some(prompt),
+        prompt_file: prompt_file.as_deref(),
+        model: app.model.as_deref(),
+        settings: settings.as_deref(),
+        extra_args: &app.agent_args,
+        resume_args,
+    });
+    for arg in args {
+        cmd.arg(arg);
+    }
+
+    // Discard any stale control signal left over from a previous iteration
+    let _ = std::fs::remove_file(app.task_dir.join(signal_file));
+
+    let mut child = pair
+        .slave
+        .spawn_command(cmd)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    // Drop slave after spawning (important for proper cleanup)
+    drop(pair.slave);
+
+    // Clone reader for background thread (must be done before take_writer)
+    let mut reader = pair
+        .master
+        .try_clone_reader()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    // Get writer for sending input to PTY
+    let pty_writer = pair
+        .master
+        .take_writer()
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    // Reset PTY state for new iteration
+    {
+        let mut state = lock_pty(pty_state);
+        state.child_exited = false;
+        state.read_failure = None;
+        state.clear_recent_output();
+        // Re-initialize parser to clear screen
+        state.parser = vt100::Parser::new(pty_rows, pty_cols, 1000);
+        state.completion_sentinel = profile.completion_sentinel.clone();
+        state.stop_sentinels = profile.stop_sentinels.clone();
+        state.compaction_sentinels = profile.compaction_sentinels.clone();
+    }
+
+    // Spawn thread to read PTY output and feed to VT100 parser (flagging a redraw)
+    let pty_state = Arc::clone(pty_state);
+    let redraw = app.redraw.flag();
+    let reader_stats = app.health.reader.clone();
+    let reader_thread = thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        // A panic while processing a chunk is reported and reading resumes, so the
+        // agent never blocks on a full PTY and isn't mistaken for having exited
+        loop {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => {
+                        // EOF - child process has exited
+                        lock_pty(&pty_state).child_exited = true;
+                        break;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        // Nothing more can be read: treated as an exit, but one that is
+                        // logged as a read error unless it's the PTY hanging up
+                        let mut state = lock_pty(&pty_state);
+                        state.child_exited = true;
+                        if !is_pty_hangup(&e) {
+                            state.read_failure = Some(e.to_string());
+                        }
+                        break;
+                    }
+                    Ok(n) => {
+                        // Feed raw bytes to VT100 parser and track for completion detection
+                        feed_output(&pty_state, &buf[..n]);
+                        reader_stats.record(n);
+                        redraw.mark();
+                    }
+                }
+            }));
+            match result {
+                Ok(()) => break,
+                Err(payload) => lock_pty(&pty_state).reader_error = Some(panic_message(payload.as_ref())),
+            }
+        }
+    });
+
+    // Agents that take the prompt on stdin get it typed in, then Enter
+    let writer = PtyWriter::spawn(pty_writer);
+    if profile.prompt_via == PromptDelivery::Stdin {
+        let mut payload = build_paste_payload(prompt, false);
+        payload.push(b'\r');
+        if !writer.send(&payload) {
+            let _ = child.kill();
+            return Err(io::Error::other(format!(
+                "The prompt ({} bytes) didn't fit the agent's input queue",
+                prompt.len()
+            )));
+        }
+    }
+
+    Ok(AgentProcess {
+        child,
+        reader_thread,
+        master: pair.master,
+        writer,
+        pending_prompt,
+    })
+}
+
+/// Temp file holding the prompt for a `{prompt_file}` agent; named after the agent's
+/// control file so each lane has its own, and removed once the agent is gone
+fn prompt_file_path(signal_file: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("ralph_prompt_{}{}.txt", std::process::id(), signal_file.replace('.', "_")))
+}
+
+/// Format a --max-runtime budget for display ("6h", "90m")
+fn format_runtime(secs: u64) -> String {
+    if secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else {
+        format!("{}m", secs.div_ceil(60))
+    }
+}
+
+/// What to do with the task at startup, decided before the agent is spawned
+#[derive(Debug, PartialEq, Eq)]
+enum StartupCheck {
+    /// Stories are left for the agent: start the loop
+    Ready,
+    /// The PRD (or the targeted subset) has no stories at all
+    NoStories,
+    /// Every story already passes: report completion without an iteration
+    AllPass,
+}
+
+fn startup_check(prd: &Prd) -> StartupCheck {
+    if prd.story_total() == 0 {
+        StartupCheck::NoStories
+    } else if prd.all_stories_pass() {
+        StartupCheck::AllPass
+    } else {
+        StartupCheck::Ready
+    }
+}
+
+/// Completion summary for a task that was already done at startup
+/// --once also prints its machine-parsable result line.
+fn print_already_complete(prd: &Prd, once: bool) {
+    let (completed, total) = (prd.completed_count(), prd.story_total());
+    if once {
+        println!("result=complete stories={}/{} duration=0s", completed, total);
+        return;
+    }
+    println!();
+    println!("All stories already pass ({}/{} complete) - nothing to do.", completed, total);
+    let (human_completed, human_total) = prd.human_progress();
+    if human_completed < human_total {
+        println!(
+            "{} human-assigned {} still open.",
+            human_total - human_completed,
+            if human_total - human_completed == 1 { "story is" } else { "stories are" }
+        );
+    }
+    println!();
+}
+
+/// Restore the host terminal (raw mode, mouse capture, bracketed paste, focus reports, alternate screen, title)
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = stdout().execute(DisableFocusChange);
+    let _ = stdout().execute(DisableBracketedPaste);
+    let _ = stdout().execute(DisableMouseCapture);
+    let _ = stdout().execute(LeaveAlternateScreen);
+    if TITLE_PUSHED.swap(false, Ordering::SeqCst) {
+        let _ = stdout().write_all(POP_TITLE.as_bytes());
+        let _ = stdout().flush();
+    }
+}
+
+fn main() -> io::Result<()> {
+    // Set up panic hook to restore terminal state before panicking
+    let default_panic = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Save what the session got to, so the next start can resume it
+        recovery::write_latest();
+        // Restore terminal state
+        restore_terminal();
+        // Call the default panic handler
+        default_panic(info);
+    }));
+
+    // Clean up the agent and the terminal on SIGTERM/SIGHUP/SIGINT
+    signals::install(restore_terminal)?;
+
+    // Parse CLI arguments (includes interactive prompts if needed)
+    let mut configs = parse_args()?;
+    recovery::spawn_writer();
+
+    // Several tasks: run them one after another, each followed by its nextTask chain
+    if configs.len() > 1 {
+        let tasks = expand_chains(configs)?;
+        for (config, _) in &tasks {
+            // Validate task directory exists
+            if !config.task_dir.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Task directory not found: {}", config.task_dir.display()),
+                ));
+            }
+
+            // Validate prd.json exists
+            if !config.prd_path.exists() {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("prd.json not found: {}", config.prd_path.display()),
+                ));
+            }
+
+            // Warn if the PRD's taskDir points somewhere else
+            if let Some(warning) = task_dir_mismatch(&config.prd_path, &config.task_dir) {
+                eprintln!("Warning: {}", warning);
+            }
+            confirm_recovery(config)?;
+        }
+        return run_batch(tasks, Vec::new());
+    }
+    let mut config = configs.remove(0);
+    // Tasks to offer once this one is done (prd.json nextTask); a circular chain refuses to start
+    let chained: Vec<CliConfig> = expand_chains(vec![config.clone()])?.into_iter().skip(1).map(|(c, _)| c).collect();
+
+    // Nothing for the agent to do: don't spawn it just to find that out
+    let targeted_stories = config
+        .targeted_stories
+        .clone()
+        .unwrap_or_else(|| SessionState::load(&config.task_dir).targeted_stories);
+    if let Ok(mut prd) = Prd::load(&config.prd_path) {
+        prd.targeted_stories = targeted_stories.clone();
+        match startup_check(&prd) {
+            StartupCheck::Ready => {}
+            StartupCheck::NoStories => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} has no user stories to work on. Create the task's stories with the /prd \
+                         and /ralph skills (or add them to userStories), then start ralph-tui again.",
+                        config.prd_path.display()
+                    ),
+                ));
+            }
+            StartupCheck::AllPass => {
+                print_already_complete(&prd, config.once);
+                let mut finished = QueueEntry::new(config.task_dir.clone());
+                (finished.status, finished.detail) = (QueueStatus::Done, "already complete".to_string());
+                return continue_chain(&config, chained, finished);
+            }
+        }
+    }
+
+    // The last session for this task crashed: offer to pick it back up
+    confirm_recovery(&config)?;
+
+    // Don't start an agent on a disk that is about to fill up
+    if let Ok(space) = disk::free_space(&config.task_dir)
+        && disk::assess(space, config.min_free_mb) == disk::DiskPressure::Critical
+    {
+        return Err(io::Error::other(format!(
+            "Only {} on the task directory's filesystem (ralph-tui needs at least {} MB); free some space and retry",
+            disk::describe(space),
+            disk::HARD_FLOOR_MB.min(config.min_free_mb)
+        )));
+    }
+
+    // Preflight checklist: --yes skips the screen (warning about soft failures on stderr)
+    // but still refuses to start past a hard failure
+    let mut prompt_source = find_prompt_path(&config.task_dir).map(|path| path.display().to_string());
+    let mut preflight = Preflight {
+        checks: preflight::run_checks(&config, prompt_source.as_deref()),
+        settings: run_settings(&config, &targeted_stories),
+        message: None,
+        prd_task_dir: preflight::prd_task_dir(&config.prd_path, &config.task_dir),
+    };
+    if config.skip_prompts {
+        if preflight.has_hard_failure() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Preflight checks failed:\n  {}", preflight.failures().join("\n  ")),
+            ));
+        }
+        for failure in preflight.failures() {
+            eprintln!("Warning: {}", failure);
+        }
+        if let Some(declared) = preflight::task_dir_mismatch(&config.prd_path, &config.task_dir) {
+            log_task_dir_choice(&config.task_dir, &config.task_dir, &declared, "cli");
+        }
+    }
+
+    let mut terminal = enter_tui()?;
+    if !config.skip_prompts {
+        let start = match run_preflight(&mut terminal, &mut preflight, &mut config, &mut prompt_source) {
+            Ok(start) => start,
+            Err(e) => {
+                restore_terminal();
+                return Err(e);
+            }
+        };
+        if !start {
+            restore_terminal();
+            if let Some(signal) = signals::received() {
+                std::process::exit(128 + signal);
+            }
+            println!("Aborted at the preflight checklist - nothing was started.");
+            return Ok(());
+        }
+    }
+    let mut queue = Vec::new();
+    if !chained.is_empty() {
+        queue.push(QueueEntry { status: QueueStatus::Running, ..QueueEntry::new(config.task_dir.clone()) });
+        queue.extend(chained.iter().map(|c| QueueEntry::chained(c.task_dir.clone())));
+    }
+    let first = config.clone();
+    let (mut app, result) = match run_session(&mut terminal, config, queue) {
+        Ok(session) => session,
+        Err(e) => {
+            restore_terminal();
+            return Err(e);
+        }
+    };
+
+    // Always restore terminal, regardless of any errors
+    restore_terminal();
+    signals::set_loop_active(false);
+
+    // --once: print a machine-parsable result line and exit with a status code
+    if app.once {
+        if let Err(ref e) = result {
+            eprintln!("Error: {}", e);
+        }
+        let (outcome, mut exit_code) = once_outcome(&app, &result);
+        if let Some(signal) = signals::received() {
+            exit_code = 128 + signal;
+        }
+        if let Ok(mut prd) = Prd::load(&app.prd_path) {
+            prd.targeted_stories = app.session_state.targeted_stories.clone();
+            app.prd = Some(prd);
+        }
+        let (completed, total) = app
+            .prd
+            .as_ref()
+            .map(|p| (p.completed_count(), p.story_total()))
+            .unwrap_or((0, 0));
+        let tests = app
+            .last_test_result
+            .map(|t| format!(" tests={}/{}", t.passed, t.failed))
+            .unwrap_or_default();
+        println!(
+            "result={} stories={}/{} duration={}s{}",
+            outcome,
+            completed,
+            total,
+            app.session_start.elapsed().as_secs(),
+            tests
+        );
+        std::process::exit(exit_code);
+    }
+
+    // Completion summary for a session cut short by --max-runtime
+    if app.runtime_limit_hit {
+        let (completed, total) = app
+            .prd
+            .as_ref()
+            .map(|p| (p.completed_count(), p.story_total()))
+            .unwrap_or((0, 0));
+        println!(
+            "Stopped: runtime limit of {} reached after {} iterations ({}/{} stories complete)",
+            format_runtime(app.max_runtime.map_or(0, |limit| limit.as_secs())),
+            app.current_iteration,
+            completed,
+            total
+        );
+    }
+
+    // Aborted summary for a session stopped with Ctrl+Q (or a signal)
+    if app.aborted() {
+        let (completed, total) = app
+            .prd
+            .as_ref()
+            .map(|p| (p.completed_count(), p.story_total()))
+            .unwrap_or((0, 0));
+        println!(
+            "Aborted: stopped during iteration {} of {} ({}/{} stories complete)",
+            app.current_iteration, app.max_iterations, completed, total
+        );
+    }
+
+    print_session_diff(&app);
+    print_story_iterations(&app);
+
+    // --squash-on-complete already squashed (or said why not); otherwise offer it
+    let completed = result.is_ok() && !app.aborted() && app.prd.as_ref().is_some_and(|p| p.all_stories_pass());
+    if let Some(ref outcome) = app.squash_outcome {
+        println!("{}", outcome);
+    } else if completed && !first.skip_prompts {
+        offer_squash(&app)?;
+    }
+
+    // Terminated by a signal: exit with the conventional 128 + signal status
+    if let Some(signal) = signals::received() {
+        eprintln!("Terminated by signal {}", signal);
+        std::process::exit(128 + signal);
+    }
+
+    // Done: move on to the nextTask chain (the rest of the chain runs unattended); a
+    // quit ends the session here, even when the last story passed before it
+    let mut finished = QueueEntry::new(first.task_dir.clone());
+    (finished.status, finished.detail) = app.queue_outcome(&result);
+    if result.is_ok() && finished.status == QueueStatus::Done && !app.aborted() {
+        return continue_chain(&first, chained, finished);
+    }
+    result
+}
+
+/// The agent's commits on the PRD's branchName since its mergeTarget (None when the PRD
+/// lacks either or the task isn't in a repository)
+fn agent_squash_plan(app: &App) -> Option<(&str, &str, io::Result<git::SquashPlan>)> {
+    let prd = app.prd.as_ref()?;
+    let (branch, target) = (prd.branch_name.as_deref()?, prd.merge_target.as_deref()?);
+    Some((branch, target, git::plan_squash(app.repo_root.as_ref()?, branch, target)))
+}
+
+/// Message for the squashed commit: the PRD's description (first line), else its project
+fn squash_message(prd: &Prd) -> String {
+    match prd.description.lines().map(str::trim).find(|line| !line.is_empty()) {
+        Some(line) => line.to_string(),
+        None => prd.project.clone(),
+    }
+}
+
+/// --squash-on-complete: squash with the default message, logging what happened
+fn squash_at_completion(app: &mut App) {
+    let Some(prd) = app.prd.as_ref() else {
+        return;
+    };
+    let (outcome, log) = match (agent_squash_plan(app), app.repo_root.as_ref()) {
+        (_, None) => (
+            "Not squashed: not in a git repository".to_string(),
+            "squash_refused reason=\"not a git repo\"".to_string(),
+        ),
+        (None, _) => (
+            "Not squashed: the PRD needs both branchName and mergeTarget".to_string(),
+            "squash_refused reason=\"no branchName or mergeTarget\"".to_string(),
+        ),
+        (Some((_, _, Err(e))), _) => (format!("Not squashed: {}", e), format!("squash_refused reason={:?}", e.to_string())),
+        (Some((branch, target, Ok(plan))), _) if plan.commits.len() < 2 => (
+            format!("Not squashed: {} has {} commit(s) since {}", branch, plan.commits.len(), target),
+            format!("squash_skipped commits={}", plan.commits.len()),
+        ),
+        (Some((_, _, Ok(plan))), Some(repo)) => match git::squash_commits(repo, &plan, &squash_message(prd)) {
+            Ok(commit) => (
+                format!("Squashed {} agent commits into {}", plan.commits.len(), commit),
+                format!("squash commits={} head={}", plan.commits.len(), commit.split(' ').next().unwrap_or_default()),
+            ),
+            Err(e) => (format!("Squash failed: {}", e), format!("squash_failed message={:?}", e.to_string())),
+        },
+    };
+    app::append_log_line(&app.task_dir, &format!("{} iteration={}", log, app.current_iteration));
+    app.squash_outcome = Some(outcome);
+}
+
+/// Offer to squash the agent's commits on the task branch into one, asking for the
+/// message (Enter takes the PRD's description); anything but y keeps the commits
+fn offer_squash(app: &App) -> io::Result<()> {
+    let (Some(prd), Some(repo)) = (app.prd.as_ref(), app.repo_root.as_ref()) else {
+        return Ok(());
+    };
+    let Some((branch, target, plan)) = agent_squash_plan(app) else {
+        return Ok(());
+    };
+    let plan = match plan {
+        Ok(plan) if plan.commits.len() >= 2 => plan,
+        Ok(_) => return Ok(()),
+        Err(e) => {
+            println!("Can't squash the agent's commits on {}: {}", branch, e);
+            return Ok(());
+        }
+    };
+    const LISTED: usize = 10;
+    println!();
+    println!("{} has {} commits since {}:", branch, plan.commits.len(), target);
+    for commit in plan.commits.iter().take(LISTED) {
+        println!("  {}", commit);
+    }
+    if plan.commits.len() > LISTED {
+        println!("  … and {} more", plan.commits.len() - LISTED);
+    }
+    print!("Squash them into one commit? [y/N]: ");
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if !input.trim().to_lowercase().starts_with('y') {
+        return Ok(());
+    }
+
+    let default = squash_message(prd);
+    print!("Commit message [{}]: ", default);
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let message = match input.trim() {
+        "" => default,
+        message => message.to_string(),
+    };
+    match git::squash_commits(repo, &plan, &message) {
+        Ok(commit) => println!("Squashed into {}", commit),
+        Err(e) => println!("Squash failed: {}", e),
+    }
+    Ok(())
+}
+
+/// Settings for a task started by nextTask: `config`'s options, with the iteration
+/// budget from the task's own prd.json (or ralph.toml) unless it was given with --iterations
+fn chained_config(config: &CliConfig, task_dir: PathBuf) -> CliConfig {
+    let prd_path = task_dir.join("prd.json");
+    let mut chained = config.clone();
+    if config.max_iterations_source != ValueSource::Flag {
+        let prd_iterations = Prd::load(&prd_path).ok().and_then(|prd| prd.default_iterations);
+        // Already read successfully by parse_args
+        let config_iterations = config::load_config(ralph_config_dir().as_deref()).unwrap_or_default().default_iterations;
+        (chained.max_iterations, chained.max_iterations_source) =
+            resolve_setting(None, prd_iterations, config_iterations, DEFAULT_ITERATIONS);
+    }
+    // --stories names the first task's stories
+    chained.targeted_stories = None;
+    (chained.task_dir, chained.prd_path) = (task_dir, prd_path);
+    chained
+}
+
+/// Follow each task with its nextTask chain, flagging the chained tasks
+/// A chained task that is listed anyway (or already chained) isn't added twice; a
+/// circular chain or a nextTask without a prd.json refuses the whole run.
+fn expand_chains(configs: Vec<CliConfig>) -> io::Result<Vec<(CliConfig, bool)>> {
+    let mut listed: Vec<PathBuf> = configs.iter().map(|c| chain::task_identity(&c.task_dir)).collect();
+    let mut tasks = Vec::with_capacity(configs.len());
+    for config in configs {
+        // --once runs one iteration of one task
+        let next = if config.once {
+            Vec::new()
+        } else {
+            chain::task_chain(&config.task_dir, &config.prd_path)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?
+        };
+        let mut chained = Vec::new();
+        for task_dir in next {
+            let identity = chain::task_identity(&task_dir);
+            if listed.contains(&identity) {
+                break;
+            }
+            listed.push(identity);
+            chained.push(chained_config(&config, task_dir));
+        }
+        tasks.push((config, false));
+        tasks.extend(chained.into_iter().map(|c| (c, true)));
+    }
+    Ok(tasks)
+}
+
+/// Offer to start the tasks chained after a completed single task (--yes starts them)
+fn continue_chain(first: &CliConfig, chained: Vec<CliConfig>, finished: QueueEntry) -> io::Result<()> {
+    let Some(next) = chained.first() else {
+        return Ok(());
+    };
+    if !first.skip_prompts && !prompt_next_task(&first.task_dir, &next.task_dir, chained.len())? {
+        return Ok(());
+    }
+    for config in &chained {
+        confirm_recovery(config)?;
+    }
+    run_batch(chained.into_iter().map(|c| (c, true)).collect(), vec![finished])
+}
+
+/// Ask whether to start the task chained after `done` (Enter starts it)
+fn prompt_next_task(done: &Path, next: &Path, chained: usize) -> io::Result<bool> {
+    println!();
+    println!("{} is complete; its nextTask is {}.", done.display(), next.display());
+    if chained > 1 {
+        println!("The {} tasks chained after it run one after another without asking again.", chained);
+    }
+    print!("Start {}? [Y/n]: ", next.display());
+    io::stdout().flush()?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(!input.trim().to_lowercase().starts_with('n'))
+}
+
+/// Completion summary: the stories this session changed in prd.json (as in the `D` view)
+fn print_session_diff(app: &App) {
+    let (Some(start), Some(now)) = (&app.prd_at_start, Prd::load(&app.prd_path).ok().or_else(|| app.prd.clone())) else {
+        return;
+    };
+    let diffs = prd_diff::diff(start, &now);
+    let changed = prd_diff::changed(&diffs);
+    if changed.is_empty() {
+        return;
+    }
+    println!();
+    println!("This session changed {} of {} stories:", changed.len(), diffs.len());
+    for diff in changed {
+        println!("  {}", diff.summary());
+    }
+    println!();
+}
+
+/// Completion summary: iterations per story as a bar chart (red: never completed) and,
+/// with estimates, each story's estimate against its agent time; both also written to
+/// the task's report.json, with the stories the session changed and criteria evidence
+fn print_story_iterations(app: &App) {
+    let Some(prd) = Prd::load(&app.prd_path).ok().or_else(|| app.prd.clone()) else {
+        return;
     };
-    if let Some(home) = home_dir {
-        let settings_path = PathBuf::from(home).join(".config").join("ralph").join("settings.json");
-        if settings_path.exists() {
-            cmd.arg("--settings");
-            cmd.arg(settings_path.to_string_lossy().to_string());
+    let stories = report::story_iterations(&prd, &app.session_state);
+    let estimates = report::story_estimates(&prd, &app.session_state);
+    let session_report = report::SessionReport {
+        session_id: app.session_id.clone(),
+        iterations: app.current_iteration,
+        stories_completed: prd.completed_count(),
+        stories_total: prd.story_total(),
+        story_iterations: stories.clone(),
+        estimate_unit: prd.has_estimates().then_some(prd.estimate_unit),
+        story_estimates: estimates.clone(),
+        changed_stories: app.prd_at_start.as_ref().map(|start| report::changed_stories(start, &prd)).unwrap_or_default(),
+        story_evidence: report::story_evidence(&prd),
+    };
+    if let Err(e) = report::write_report(&app.task_dir, &session_report) {
+        eprintln!("Warning: could not write {}: {}", report::REPORT_FILE, e);
+    }
+    let width = crossterm::terminal::size().map_or(80, |(cols, _)| cols as usize);
+    let color = stdout().is_terminal();
+    let print_unfinished = |completed: bool, line: &str| {
+        if color && !completed {
+            println!("{}{}{}", ANSI_RED, line, ANSI_RESET);
+        } else {
+            println!("{}", line);
+        }
+    };
+    if !stories.is_empty() {
+        println!("Iterations per story:");
+        for (story, line) in stories.iter().zip(report::chart_lines(&stories, width)) {
+            print_unfinished(story.completed, &line);
+        }
+        println!();
+    }
+    if !estimates.is_empty() {
+        println!("Estimate vs actual agent time:");
+        for (story, line) in estimates.iter().zip(report::estimate_lines(&estimates, prd.estimate_unit)) {
+            print_unfinished(story.completed, &line);
         }
+        println!();
+    }
+}
+
+/// Colors for the completion summary's unfinished stories
+const ANSI_RED: &str = "\x1b[31m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// (label, value) rows describing the run, shown under the preflight checklist
+fn run_settings(config: &CliConfig, targeted_stories: &BTreeSet<String>) -> Vec<(&'static str, String)> {
+    let mut settings = vec![
+        ("Max iters", format!("{} ({})", config.max_iterations, config.max_iterations_source.label())),
+        ("Rotate at", format!("{} lines ({})", config.rotate_threshold, config.rotate_threshold_source.label())),
+    ];
+    if let Some(secs) = config.max_runtime {
+        settings.push(("Runtime", format!("{} max", format_runtime(secs))));
+    }
+    if let Some(lanes) = config.parallel {
+        settings.push(("Parallel", format!("{} lanes (experimental)", lanes)));
+    }
+    if let Some(ref model) = config.model {
+        settings.push(("Model", model.clone()));
+    }
+    if !config.agent_args.is_empty() {
+        settings.push(("Agent args", config.agent_args.join(" ")));
+    }
+    if !targeted_stories.is_empty() {
+        let ids: Vec<&str> = targeted_stories.iter().map(String::as_str).collect();
+        settings.push(("Stories", ids.join(", ")));
     }
+    settings
+}
 
-    // Prompt is passed as the last positional argument
-    let prompt_content = std::fs::read_to_string(&prompt_temp_file)?;
-    cmd.arg(&prompt_content);
+/// Show the preflight checklist until the user continues (true) or aborts (false)
+/// f applies the offered fixes and runs the checks again; p switches `config` to the
+/// task directory prd.json's taskDir names.
+fn run_preflight(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    preflight: &mut Preflight,
+    config: &mut CliConfig,
+    prompt_source: &mut Option<String>,
+) -> io::Result<bool> {
+    loop {
+        if signals::received().is_some() {
+            return Ok(false);
+        }
+        terminal.draw(|frame| render::draw_preflight(frame, preflight))?;
 
-    // Clean up temp file
-    let _ = std::fs::remove_file(&prompt_temp_file);
+        if event::poll(Duration::from_millis(100))?
+            && let Event::Key(key) = event::read()?
+            // Only handle key press events (Windows sends both Press and Release)
+            && key.kind == KeyEventKind::Press
+        {
+            match input::handle_preflight_key(key, preflight, &config.keymap) {
+                PreflightAction::Continue => {
+                    if let Some(declared) = preflight::task_dir_mismatch(&config.prd_path, &config.task_dir) {
+                        log_task_dir_choice(&config.task_dir, &config.task_dir, &declared, "cli");
+                    }
+                    return Ok(true);
+                }
+                PreflightAction::Abort => return Ok(false),
+                PreflightAction::Fix => {
+                    let declared = preflight::task_dir_mismatch(&config.prd_path, &config.task_dir);
+                    let outcomes: Vec<String> = preflight
+                        .fixes()
+                        .iter()
+                        .map(|fix| match preflight::apply_fix(fix, &config.task_dir, &config.prd_path) {
+                            Ok(done) => {
+                                if let (preflight::Fix::RewriteTaskDir(_), Some(declared)) = (fix, &declared) {
+                                    log_task_dir_choice(&config.task_dir, &config.task_dir, declared, "rewrite");
+                                }
+                                done
+                            }
+                            Err(e) => format!("couldn't {}: {}", fix.label(), e),
+                        })
+                        .collect();
+                    preflight.checks = preflight::run_checks(config, prompt_source.as_deref());
+                    preflight.prd_task_dir = preflight::prd_task_dir(&config.prd_path, &config.task_dir);
+                    preflight.message = Some(outcomes.join("; "));
+                }
+                PreflightAction::UsePrdTaskDir => {
+                    let Some(task_dir) = preflight.prd_task_dir.take() else { continue };
+                    let declared = task_dir.display().to_string();
+                    log_task_dir_choice(&task_dir, &config.task_dir, &declared, "prd");
+                    config.prd_path = task_dir.join("prd.json");
+                    config.task_dir = task_dir;
+                    *prompt_source = find_prompt_path(&config.task_dir).map(|path| path.display().to_string());
+                    preflight.checks = preflight::run_checks(config, prompt_source.as_deref());
+                    preflight.prd_task_dir = preflight::prd_task_dir(&config.prd_path, &config.task_dir);
+                    preflight.message = Some(format!("switched to {}", declared));
+                }
+                PreflightAction::None => {}
+            }
+        }
+    }
+}
 
-    let child = pair
-        .slave
-        .spawn_command(cmd)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+/// Take over the terminal for the TUI (raw mode, alternate screen, mouse, bracketed paste, focus reports)
+fn enter_tui() -> io::Result<Terminal<CrosstermBackend<io::Stdout>>> {
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    stdout().execute(EnableMouseCapture)?;
+    stdout().execute(EnableBracketedPaste)?;
+    stdout().execute(EnableFocusChange)?;
+    let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    signals::set_loop_active(true);
+    Ok(terminal)
+}
 
-    // Drop slave after spawning (important for proper cleanup)
-    drop(pair.slave);
+/// Run one task's loop in the TUI until it completes, its budget is spent or the user quits
+/// Returns the App and the loop's result; failing to bind the events socket or to start
+/// the first agent is the outer error. `queue` is the batch the task belongs to (if any).
+fn run_session(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    config: CliConfig,
+    queue: Vec<QueueEntry>,
+) -> io::Result<(App, io::Result<()>)> {
+    // Get initial terminal size for PTY
+    let initial_size = terminal.size()?;
+    let session_state = SessionState::load(&config.task_dir);
+    let sidebar_hidden = config.no_sidebar || session_state.sidebar_hidden;
+    let (pty_cols, pty_rows) = pty_size_for(
+        initial_size.width,
+        initial_size.height,
+        config.progress_strip,
+        sidebar_hidden,
+        session_state.ralph_terminal_size,
+    );
 
-    // Clone reader for background thread (must be done before take_writer)
-    let mut reader = pair
-        .master
-        .try_clone_reader()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    // Create app state with VT100 parser sized to PTY dimensions
+    let events_socket = config.events_socket.clone();
+    let metrics_addr = config.metrics_addr;
+    let parallel = config.parallel;
+    let mut app = App::new(pty_rows, pty_cols, config);
+    app.queue = queue;
+    // `ralph-tui status` reports the task as running while this is held
+    let _lock = status::SessionLock::acquire(&app.task_dir);
+    // Keep ralph-tui's state files out of the agent's `git add -A` (task-local .gitignore)
+    if app.repo_root.is_some() {
+        match git::ignore_state_files(&app.task_dir) {
+            Ok(added) if !added.is_empty() => {
+                app::append_log_line(&app.task_dir, &format!("gitignore added={}", added.join(",")));
+            }
+            Ok(_) => {}
+            Err(e) => app::append_log_line(&app.task_dir, &format!("gitignore_failed reason={:?}", e.to_string())),
+        }
+    }
 
-    // Get writer for sending input to PTY
-    let pty_writer = pair
-        .master
-        .take_writer()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    // Bind the events socket if requested
+    if let Some(ref socket_path) = events_socket {
+        match EventSink::bind(socket_path, &app.task_dir, &app.session_id) {
+            Ok(sink) => app.events = Some(sink),
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("Failed to bind events socket {}: {}", socket_path.display(), e),
+                ));
+            }
+        }
+    }
+    // Serve metrics if requested (bound per task, so a batch reuses the port)
+    if let Some(addr) = metrics_addr {
+        match metrics::MetricsServer::bind(addr) {
+            Ok(server) => app.metrics = Some(server),
+            Err(e) => {
+                return Err(io::Error::new(e.kind(), format!("Failed to serve metrics on {}: {}", addr, e)));
+            }
+        }
+    }
+    app.emit_event(SessionEvent::SessionStart { max_iterations: app.max_iterations });
 
-    // Update app state
-    app.master_pty = Some(pair.master);
-    app.pty_writer = Some(pty_writer);
+    // Set up file watcher for prd.json and progress.txt
+    // Kept for the whole session: dropping it stops the watch
+    let watcher = setup_file_watcher(vec![
+        (app.prd_path.clone(), Arc::clone(&app.prd_needs_reload)),
+        (app.task_dir.join("progress.txt"), Arc::clone(&app.progress_needs_reload)),
+    ]);
+    app.health.watcher_backend = watcher.as_ref().map(|_| format!("{:?}", RecommendedWatcher::kind()));
 
-    // Reset PTY state for new iteration
-    {
-        let mut state = app.pty_state.lock().map_err(|_| {
-            io::Error::new(io::ErrorKind::Other, "Failed to lock PTY state")
-        })?;
-        state.child_exited = false;
-        state.clear_recent_output();
-        // Re-initialize parser to clear screen
-        state.parser = vt100::Parser::new(pty_rows, pty_cols, 1000);
-    }
+    // Track last known size for resize detection
+    let mut last_cols = pty_cols;
+    let mut last_rows = pty_rows;
 
-    // Spawn thread to read PTY output and feed to VT100 parser
-    let pty_state = Arc::clone(&app.pty_state);
-    let reader_thread = thread::spawn(move || {
-        let mut buf = [0u8; 4096];
-        loop {
-            match reader.read(&mut buf) {
-                Ok(0) => {
-                    // EOF - child process has exited
-                    if let Ok(mut state) = pty_state.lock() {
-                        state.child_exited = true;
-                    }
-                    break;
+    // Run the main loop (--parallel: one agent per lane, each iterating on its own)
+    let result = match parallel {
+        Some(lanes) => run_parallel(terminal, &mut app, lanes, &mut last_cols, &mut last_rows),
+        None => {
+            // Spawn initial Claude process
+            let (child, reader_thread) = match spawn_claude(&mut app, pty_rows, pty_cols) {
+                Ok((child, reader_thread)) => {
+                    signals::set_child(child.process_id());
+                    (child, reader_thread)
                 }
-                Ok(n) => {
-                    // Feed raw bytes to VT100 parser and track for completion detection
-                    if let Ok(mut state) = pty_state.lock() {
-                        state.parser.process(&buf[..n]);
-                        state.append_output(&buf[..n]);
-                    }
+                Err(e) => {
+                    app.emit_event(SessionEvent::Error { message: e.to_string() });
+                    return Err(e);
                 }
-                Err(_) => {
-                    if let Ok(mut state) = pty_state.lock() {
-                        state.child_exited = true;
-                    }
-                    break;
+            };
+            app.emit_event(SessionEvent::IterationStart { iteration: app.current_iteration });
+            run_iterations(terminal, &mut app, child, reader_thread, &mut last_cols, &mut last_rows)
+        }
+    };
+
+    // Publish the session outcome
+    match result {
+        // A quit or signal is not a completion, even with every story passing
+        Ok(()) => {
+            if !app.aborted() && app.prd.as_ref().is_some_and(|p| p.all_stories_pass()) {
+                app.emit_event(SessionEvent::Completion { iteration: app.current_iteration });
+                app.clear_iteration_markers();
+                // One commit for the task before the complete hook, which may merge it
+                if app.squash_on_complete {
+                    squash_at_completion(&mut app);
                 }
+                app.run_hook(HookEvent::Complete, app.current_iteration);
             }
         }
-    });
-
-    app.iteration_state = IterationState::Running;
-
-    Ok((child, reader_thread))
-}
+        Err(ref e) => app.emit_event(SessionEvent::Error { message: e.to_string() }),
+    }
+    // Hooks still running get until their timeout, so their status makes the log
+    app.finish_hook_jobs();
 
-fn main() -> io::Result<()> {
-    // Set up panic hook to restore terminal state before panicking
-    let default_panic = std::panic::take_hook();
-    std::panic::set_hook(Box::new(move |info| {
-        // Restore terminal state
-        let _ = disable_raw_mode();
-        let _ = stdout().execute(DisableMouseCapture);
-        let _ = stdout().execute(LeaveAlternateScreen);
-        // Call the default panic handler
-        default_panic(info);
-    }));
+    // The agents are gone, and with them the need for their {prompt_file} copies
+    let _ = std::fs::remove_file(prompt_file_path(SIGNAL_FILE));
+    for index in 0..app.lanes.len() {
+        let _ = std::fs::remove_file(prompt_file_path(&Lane::signal_file(index)));
+    }
 
-    // Parse CLI arguments (includes interactive prompts if needed)
-    let config = parse_args()?;
+    // Record the session's prd.json edits next to the agent's iterations
+    journal::append_to_iteration_log(&app.task_dir, &app.prd_journal);
+    prd_diff::append_to_iteration_log(&app.task_dir, &app.session_diff());
 
-    // Validate task directory exists
-    if !config.task_dir.exists() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("Task directory not found: {}", config.task_dir.display()),
-        ));
+    // A session stopped by a signal can be resumed like a crashed one; any other end is clean
+    if signals::received().is_some() {
+        app.save_recovery();
+    } else {
+        recovery::clear(&app.task_dir);
     }
+    Ok((app, result))
+}
 
-    // Validate prd.json exists
-    let prd_path = config.task_dir.join("prd.json");
-    if !prd_path.exists() {
-        return Err(io::Error::new(
-            io::ErrorKind::NotFound,
-            format!("prd.json not found in: {}", config.task_dir.display()),
-        ));
-    }
+/// Run several tasks one after another in one TUI session (several task directories, --queue
+/// or a nextTask chain). A task that can't start is marked failed and the batch moves on to
+/// the next one; a chained task (flagged true) only starts when the task before it is done.
+/// `finished` are tasks already run, shown at the top of the queue.
+fn run_batch(tasks: Vec<(CliConfig, bool)>, finished: Vec<QueueEntry>) -> io::Result<()> {
+    let offset = finished.len();
+    let mut queue = finished;
+    queue.extend(tasks.iter().map(|(c, chained)| {
+        if *chained { QueueEntry::chained(c.task_dir.clone()) } else { QueueEntry::new(c.task_dir.clone()) }
+    }));
+    let configs: Vec<CliConfig> = tasks.into_iter().map(|(c, _)| c).collect();
 
     // Show startup banner
     println!();
@@ -1629,56 +2747,131 @@ fn main() -> io::Result<()> {
     println!("║  Ralph TUI - Autonomous Agent Loop                            ║");
     println!("╚═══════════════════════════════════════════════════════════════╝");
     println!();
-    println!("  Task:       {}", config.task_dir.display());
-    println!("  Max iters:  {}", config.max_iterations);
+    println!("  Batch:      {} tasks, one after another", configs.len());
+    for (config, entry) in configs.iter().zip(&queue[offset..]) {
+        println!(
+            "    {}{} (max iters {}, {})",
+            if entry.chained { "↳ " } else { "" },
+            config.task_dir.display(),
+            config.max_iterations,
+            config.max_iterations_source.label()
+        );
+    }
     println!();
     println!("Starting TUI...");
     println!();
 
-    // Setup terminal
-    enable_raw_mode()?;
-    stdout().execute(EnterAlternateScreen)?;
-    stdout().execute(EnableMouseCapture)?;
-    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
-
-    // Get initial terminal size for PTY
-    let initial_size = terminal.size()?;
-    // Calculate right panel size (70% of width, minus borders)
-    // Ensure minimum sizes to prevent issues
-    let pty_cols = ((initial_size.width as f32 * 0.70) as u16).saturating_sub(2).max(40);
-    let pty_rows = initial_size.height.saturating_sub(3).max(10);
-
-    // Create app state with VT100 parser sized to PTY dimensions
-    let mut app = App::new(pty_rows, pty_cols, config);
+    let mut terminal = enter_tui()?;
+    for (index, config) in configs.into_iter().enumerate() {
+        let index = index + offset;
+        if queue[index].chained && index > 0 && queue[index - 1].status != QueueStatus::Done {
+            let detail = format!("{} is not done", queue[index - 1].name());
+            (queue[index].status, queue[index].detail) = (QueueStatus::Skipped, detail);
+            continue;
+        }
+        if let Some((status, detail)) = batch_start_check(&config) {
+            (queue[index].status, queue[index].detail) = (status, detail);
+            continue;
+        }
+        queue[index].status = QueueStatus::Running;
+        match run_session(&mut terminal, config, queue.clone()) {
+            Ok((app, result)) => {
+                (queue[index].status, queue[index].detail) = app.queue_outcome(&result);
+                if app.quit_batch {
+                    break;
+                }
+            }
+            Err(e) => (queue[index].status, queue[index].detail) = (QueueStatus::Failed, e.to_string()),
+        }
+        if signals::received().is_some() {
+            break;
+        }
+    }
+    restore_terminal();
+    signals::set_loop_active(false);
 
-    // Set up file watcher for prd.json
-    let prd_needs_reload = Arc::clone(&app.prd_needs_reload);
-    let prd_path_for_watcher = app.prd_path.clone();
-    let _watcher = setup_prd_watcher(prd_path_for_watcher, prd_needs_reload);
+    print_batch_summary(&queue);
+    if let Some(signal) = signals::received() {
+        eprintln!("Terminated by signal {}", signal);
+        std::process::exit(128 + signal);
+    }
+    Ok(())
+}
 
-    // Track last known size for resize detection
-    let mut last_cols = pty_cols;
-    let mut last_rows = pty_rows;
+/// Why a batch task won't start (already complete, no stories, unreadable PRD, disk full), if so
+fn batch_start_check(config: &CliConfig) -> Option<(QueueStatus, String)> {
+    let mut prd = match Prd::load(&config.prd_path) {
+        Ok(prd) => prd,
+        Err(e) => return Some((QueueStatus::Failed, format!("prd.json: {}", e))),
+    };
+    prd.targeted_stories = SessionState::load(&config.task_dir).targeted_stories;
+    match startup_check(&prd) {
+        StartupCheck::Ready => {}
+        StartupCheck::NoStories => return Some((QueueStatus::Failed, "no user stories".to_string())),
+        StartupCheck::AllPass => return Some((QueueStatus::Done, "already complete".to_string())),
+    }
+    match disk::free_space(&config.task_dir) {
+        Ok(space) if disk::assess(space, config.min_free_mb) == disk::DiskPressure::Critical => {
+            Some((QueueStatus::Failed, format!("disk almost full ({})", disk::describe(space))))
+        }
+        _ => None,
+    }
+}
 
-    // Spawn initial Claude process
-    let (mut child, mut reader_thread) = spawn_claude(&mut app, pty_rows, pty_cols)?;
+/// Print how each task of a batch went
+fn print_batch_summary(queue: &[QueueEntry]) {
+    let done = queue.iter().filter(|entry| entry.status == QueueStatus::Done).count();
+    println!();
+    println!("Batch: {}/{} tasks done", done, queue.len());
+    let width = queue.iter().map(|entry| entry.name().chars().count()).max().unwrap_or(0);
+    for entry in queue {
+        let detail = match entry.status {
+            QueueStatus::Pending => "not run",
+            _ => entry.detail.as_str(),
+        };
+        println!(
+            "  {} {:<width$}  {:<7}  {}",
+            entry.status.symbol(),
+            entry.name(),
+            entry.status.label(),
+            detail,
+            width = width
+        );
+    }
+}
 
-    // Run the main loop
-    let result = loop {
+/// Run iterations of the single agent until the task completes, the budget is spent or the user quits
+fn run_iterations(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+    mut child: Box<dyn portable_pty::Child + Send + Sync>,
+    mut reader_thread: thread::JoinHandle<()>,
+    last_cols: &mut u16,
+    last_rows: &mut u16,
+) -> io::Result<()> {
+    loop {
         // Run the UI loop for current iteration
-        let run_result = run(&mut terminal, &mut app, &mut last_cols, &mut last_rows);
+        let run_result = run(terminal, app, last_cols, last_rows);
 
         // Clean up current iteration - kill the child process first to avoid blocking
         let _ = child.kill();
         let _ = child.wait();
+        signals::set_child(None);
         drop(app.master_pty.take());
         drop(app.pty_writer.take());
         let _ = reader_thread.join();
+        let _ = std::fs::remove_file(prompt_file_path(SIGNAL_FILE));
+
+        // Update the stuck-story counter for iterations that ran to completion
+        if !app.aborted() && run_result.is_ok() {
+            app.reload_prd();
+            app.record_story_attempt();
+        }
 
         // Check iteration state
         match app.iteration_state {
-            IterationState::Completed => {
-                // All done!
+            IterationState::Completed | IterationState::Aborted => {
+                // All done, or stopped by the user
                 break run_result;
             }
             IterationState::NeedsRestart => {
@@ -1691,14 +2884,26 @@ fn main() -> io::Result<()> {
                 app.iteration_state = IterationState::WaitingDelay;
                 app.delay_start = Some(std::time::Instant::now());
 
+                // Safe boundary: no Claude process running, so a requested sync can run now
+                if app.sync_requested {
+                    app.start_sync();
+                }
+
                 // Wait for 2 seconds (with UI updates)
-                let delay_result = run_delay(&mut terminal, &mut app, &mut last_cols, &mut last_rows);
+                let delay_result = run_delay(terminal, app, last_cols, last_rows);
                 if let Err(e) = delay_result {
                     break Err(e);
                 }
 
                 // Check if user quit during delay
-                if matches!(app.iteration_state, IterationState::Completed) {
+                if app.aborted() {
+                    break Ok(());
+                }
+
+                // --max-runtime ran out during the delay
+                if app.runtime_exhausted() {
+                    app.runtime_limit_hit = true;
+                    app.iteration_state = IterationState::Completed;
                     break Ok(());
                 }
 
@@ -1706,23 +2911,25 @@ fn main() -> io::Result<()> {
                 app.current_iteration += 1;
                 app.iteration_start = Instant::now();
                 app.delay_start = None;
+                app.save_recovery();
 
                 // Reload PRD to get latest state
-                if let Ok(prd) = Prd::load(&app.prd_path) {
-                    // Check if all stories pass - project is complete!
-                    if prd.all_stories_pass() {
-                        app.prd = Some(prd);
+                if app.reload_prd() {
+                    // Check if all (targeted) stories pass - project is complete!
+                    let all_pass = app.prd.as_ref().is_some_and(|p| p.all_stories_pass());
+                    if all_pass {
                         app.iteration_state = IterationState::Completed;
                         break Ok(());
                     }
-                    app.prd = Some(prd);
                 }
 
                 // Spawn new Claude process
-                match spawn_claude(&mut app, last_rows, last_cols) {
+                match spawn_claude(app, *last_rows, *last_cols) {
                     Ok((new_child, new_thread)) => {
+                        signals::set_child(new_child.process_id());
                         child = new_child;
                         reader_thread = new_thread;
+                        app.emit_event(SessionEvent::IterationStart { iteration: app.current_iteration });
                     }
                     Err(e) => {
                         break Err(e);
@@ -1734,908 +2941,577 @@ fn main() -> io::Result<()> {
                 break run_result;
             }
         }
-    };
-
-    // Always restore terminal, regardless of any errors
-    let _ = disable_raw_mode();
-    let _ = stdout().execute(DisableMouseCapture);
-    let _ = stdout().execute(LeaveAlternateScreen);
-
-    result
+    }
 }
 
-/// Set up a file watcher for prd.json changes
-fn setup_prd_watcher(
-    prd_path: PathBuf,
-    needs_reload: Arc<Mutex<bool>>,
-) -> Option<RecommendedWatcher> {
-    // Use a shorter poll interval for more responsive updates
-    let config = Config::default().with_poll_interval(Duration::from_millis(500));
-
-    // Canonicalize the path for reliable comparison
-    let canonical_prd = prd_path.canonicalize().unwrap_or_else(|_| prd_path.clone());
-    let prd_filename = prd_path.file_name().map(|s| s.to_os_string());
+/// Spawn the agent for lane `index` with a prompt limited to the lane's stories
+fn spawn_lane(app: &mut App, index: usize, pty_rows: u16, pty_cols: u16) -> io::Result<()> {
+    let lane_count = app.lanes.len();
+    let lane = &app.lanes[index];
+    // The criterion focus only goes to the lane working on its story
+    let focus = app.session_state.focused_criterion.as_ref().filter(|focus| lane.stories.contains(&focus.story_id));
+    let prompt = build_ralph_prompt(&app.task_dir, &app.prd_path, &lane.stories, &app.skipped_stories, focus)?
+        + &lanes::lane_prompt_section(index, lane_count);
+
+    let signal_file = Lane::signal_file(index);
+    let mut env = app.iteration_env();
+    for (name, value) in env.iter_mut() {
+        if name == "RALPH_ITERATION" {
+            *value = lane.iteration.to_string();
+        }
+    }
+    env.push(("RALPH_LANE".to_string(), (index + 1).to_string()));
+    env.push(("RALPH_SIGNAL_FILE".to_string(), signal_file.clone()));
+
+    let agent = spawn_agent(app, &prompt, env, &signal_file, &[], &lane.pty_state, pty_rows, pty_cols)?;
+    signals::set_lane_child(index, agent.child.process_id());
+
+    let iteration = {
+        let lane = &mut app.lanes[index];
+        lane.child = Some(agent.child);
+        lane.reader_thread = Some(agent.reader_thread);
+        lane.master_pty = Some(agent.master);
+        lane.pty_writer = Some(agent.writer);
+        lane.pending_prompt = agent.pending_prompt;
+        lane.state = IterationState::Running;
+        lane.iteration_start = Instant::now();
+        lane.delay_start = None;
+        lane.compaction_wrap_up_sent = false;
+        lane.iteration
+    };
+    app.emit_event(SessionEvent::IterationStart { iteration });
+    Ok(())
+}
 
-    let watcher_result = RecommendedWatcher::new(
-        move |res: Result<notify::Event, notify::Error>| {
-            if let Ok(event) = res {
-                // Check if any event path matches our prd.json file
-                // Compare by filename since paths may differ in representation
-                let matches = event.paths.iter().any(|p| {
-                    // Try canonical path comparison first
-                    if let Ok(canonical) = p.canonicalize() {
-                        if canonical == canonical_prd {
-                            return true;
-                        }
-                    }
-                    // Fall back to filename comparison
-                    if let Some(ref expected_name) = prd_filename {
-                        if let Some(event_name) = p.file_name() {
-                            return event_name == expected_name;
-                        }
-                    }
-                    false
-                });
+/// Run --parallel: one agent per lane, each iterating on its own until its stories pass
+/// or it spends the iteration budget. Ends when every lane is done or the user quits.
+fn run_parallel(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+    lane_count: usize,
+    last_cols: &mut u16,
+    last_rows: &mut u16,
+) -> io::Result<()> {
+    let partition = app
+        .prd
+        .as_ref()
+        .map(|prd| prd.partition_lanes(lane_count))
+        .unwrap_or_default();
+    let size = terminal.size()?;
+    let active_lanes = partition.iter().filter(|stories| !stories.is_empty()).count().max(1);
+    (*last_cols, *last_rows) = lane_pty_size_for(size.width, size.height, app.progress_strip, app.sidebar_hidden, app.ralph_size, active_lanes);
+    app.lanes = partition
+        .into_iter()
+        .filter(|stories| !stories.is_empty())
+        .map(|stories| Lane::new(stories, *last_rows, *last_cols))
+        .collect();
+    if app.lanes.is_empty() {
+        app.iteration_state = IterationState::Completed;
+        return Ok(());
+    }
+    app.iteration_state = IterationState::Running;
+    for index in 0..app.lanes.len() {
+        spawn_lane(app, index, *last_rows, *last_cols)?;
+    }
 
-                if matches {
-                    if let Ok(mut flag) = needs_reload.lock() {
-                        *flag = true;
-                    }
-                }
-            }
-        },
-        config,
-    );
+    let result = parallel_loop(terminal, app, last_cols, last_rows);
 
-    match watcher_result {
-        Ok(mut watcher) => {
-            // Watch the parent directory since some editors replace files
-            if let Some(parent) = prd_path.parent() {
-                let _ = watcher.watch(parent, RecursiveMode::NonRecursive);
-            }
-            Some(watcher)
-        }
-        Err(_) => None,
+    for (index, lane) in app.lanes.iter_mut().enumerate() {
+        lane.stop();
+        signals::set_lane_child(index, None);
     }
+    result
 }
 
-fn run(
+/// UI loop for --parallel: drives every lane's iteration state machine
+fn parallel_loop(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     app: &mut App,
     last_cols: &mut u16,
     last_rows: &mut u16,
 ) -> io::Result<()> {
     loop {
-        // Check if PRD needs reloading (file changed on disk)
+        // SIGTERM/SIGHUP: the handler already stopped the agents, record the interrupted iterations
+        if signals::received().is_some() {
+            for lane in app.lanes.iter().filter(|lane| lane.state == IterationState::Running) {
+                let output = lock_pty(&lane.pty_state).output_summary();
+                append_iteration_log(
+                    &app.task_dir,
+                    lane.iteration,
+                    IterationEndSource::Signal,
+                    IterationState::Aborted,
+                    lane.iteration_start.elapsed(),
+                    output,
+                    app.model.as_deref(),
+                    app.agent_version.as_ref(),
+                );
+            }
+            app.iteration_state = IterationState::Aborted;
+            app.session_state.save(&app.task_dir);
+            return Ok(());
+        }
+
         app.reload_prd_if_needed();
+        app.reload_progress_if_needed();
+        app.publish_recovery();
+        app.poll_hook_jobs();
+        app.poll_verify_job();
+        for lane in app.lanes.iter_mut() {
+            lane.deliver_pending_prompt()?;
+        }
 
-        // Update animation tick every 100ms
-        if app.last_animation_update.elapsed() >= Duration::from_millis(100) {
-            app.animation_tick = app.animation_tick.wrapping_add(1);
-            app.last_animation_update = Instant::now();
-        }
-
-        terminal.draw(|frame| {
-            let area = frame.area();
-
-            // Check for terminal resize
-            let new_pty_cols = ((area.width as f32 * 0.70) as u16).saturating_sub(2).max(40);
-            let new_pty_rows = area.height.saturating_sub(3).max(10);
-
-            if new_pty_cols != *last_cols || new_pty_rows != *last_rows {
-                *last_cols = new_pty_cols;
-                *last_rows = new_pty_rows;
-                app.resize_pty(new_pty_cols, new_pty_rows);
-            }
-
-            // Create main layout: content area + bottom bar
-            let main_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Min(3),    // Main content area
-                    Constraint::Length(1), // Bottom bar (single line)
-                ])
-                .split(area);
-
-            let content_area = main_layout[0];
-            let bottom_bar_area = main_layout[1];
-
-            // Create horizontal split: 30% left panel, 70% right panel
-            let panels = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([
-                    Constraint::Percentage(30), // Ralph Status panel
-                    Constraint::Percentage(70), // Claude Code panel
-                ])
-                .split(content_area);
-
-            let left_panel_area = panels[0];
-            let right_panel_area = panels[1];
-
-            // Determine border styles based on current mode
-            let (left_border_style, right_border_style) = match app.mode {
-                Mode::Ralph => (
-                    Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
-                    Style::default().fg(BORDER_SUBTLE),
-                ),
-                Mode::Claude => (
-                    Style::default().fg(BORDER_SUBTLE),
-                    Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
-                ),
-            };
+        app.tick_animation();
 
-            // Left panel: Ralph Status
-            let left_title = match app.mode {
-                Mode::Ralph => Line::from(vec![
-                    Span::raw(" Ralph Status "),
-                    Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)),
-                    Span::raw(" "),
-                ]),
-                Mode::Claude => Line::from(" Ralph Status "),
-            };
-            let left_block = Block::default()
-                .title(left_title)
-                .borders(Borders::ALL)
-                .border_style(left_border_style)
-                .style(Style::default().bg(BG_PRIMARY));
-
-            // Render the outer block first to get the inner area
-            let left_inner = left_block.inner(left_panel_area);
-            frame.render_widget(left_block, left_panel_area);
-
-            // Get PRD data for stats
-            let (completed, total) = if let Some(ref prd) = app.prd {
-                (prd.completed_count(), prd.user_stories.len())
-            } else {
-                (0, 0)
-            };
+        // Resize every lane to its share of the Claude panel once the size settles (Ctrl+L: now)
+        let size = terminal.size()?;
+        let wanted = lane_pty_size_for(size.width, size.height, app.progress_strip, app.sidebar_hidden, app.ralph_size, app.lanes.len());
+        if std::mem::take(&mut app.pty_resync) {
+            (*last_cols, *last_rows) = wanted;
+            for lane in &app.lanes {
+                lane.resync(wanted.0, wanted.1);
+            }
+            app.show_toast(format!("Re-synced the agents' terminals to {}×{}", wanted.0, wanted.1));
+            app.redraw.request();
+        } else if let Some((new_cols, new_rows)) = app.pty_resize.settle((*last_cols, *last_rows), wanted) {
+            *last_cols = new_cols;
+            *last_rows = new_rows;
+            for lane in &app.lanes {
+                lane.resize(new_cols, new_rows);
+            }
+            app.redraw.request();
+        }
 
-            // Get PTY state for display (use default values if mutex is poisoned)
-            let mut pty_state_guard = app.pty_state.lock().ok();
-
-            // Split inner area: header (3 lines), stat cards (8 lines for 2 rows), rest
-            let inner_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(3), // Header
-                    Constraint::Length(8), // Two stat card rows (4 lines each)
-                    Constraint::Min(0),    // Rest of content
-                ])
-                .split(left_inner);
-
-            let header_area = inner_layout[0];
-            let cards_area = inner_layout[1];
-            let content_area_inner = inner_layout[2];
-
-            // Header: Ralph branding
-            let header_lines = vec![
-                Line::from(vec![
-                    Span::styled("● ", Style::default().fg(GREEN_ACTIVE)),
-                    Span::styled("RALPH LOOP", Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)),
-                ]),
-                Line::from(vec![
-                    Span::styled(format!("Terminal v{}", VERSION), Style::default().fg(CYAN_PRIMARY)),
-                ]),
-                Line::from(""), // Gap after header
-            ];
-            let header = Paragraph::new(header_lines);
-            frame.render_widget(header, header_area);
-
-            // Split cards area into two rows
-            let cards_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(4), // First row: iteration/completed
-                    Constraint::Length(4), // Second row: stories left/progress
-                ])
-                .split(cards_area);
-
-            // Render iteration/completion stat cards (first row)
-            render_stat_cards(
-                cards_layout[0],
-                app.current_iteration,
-                app.max_iterations,
-                completed,
-                total,
-                frame,
-            );
+        let (idle_after, prompt_pattern) = (app.idle_after, app.prompt_pattern.clone());
+        for lane in app.lanes.iter_mut() {
+            lane.update_agent_activity(idle_after, &prompt_pattern);
+        }
+        app.current_iteration = app.lanes.iter().map(|lane| lane.iteration).max().unwrap_or(1);
+        app.check_activity_scope();
+        app.check_tripwire();
+        update_terminal_title(app);
+        if app.redraw.due() {
+            let started = Instant::now();
+            let snapshot = app.snapshot();
+            let mut story_scroll_offset = snapshot.story_scroll_offset;
+            let drawn = terminal.draw(|frame| {
+                story_scroll_offset = render::draw_running(frame, &snapshot);
+            })?;
+            let runs = if *HYPERLINKS { hyperlink_runs(drawn.buffer, &snapshot.links) } else { Vec::new() };
+            draw_hyperlinks(terminal, runs)?;
+            app.story_scroll_offset = story_scroll_offset;
+            app.health.record_frame(started.elapsed());
+        }
 
-            // Render progress stat cards (second row)
-            render_progress_cards(
-                cards_layout[1],
-                completed,
-                total,
-                frame,
-            );
+        let runtime_stop = app.check_runtime_limit();
+        for index in 0..app.lanes.len() {
+            advance_lane(app, index, runtime_stop, *last_rows, *last_cols)?;
+        }
+        if app.lanes.iter().all(|lane| lane.state == IterationState::Completed) {
+            app.iteration_state = IterationState::Completed;
+            return Ok(());
+        }
+
+        if poll_input(&app.redraw, Duration::from_millis(50))? {
+            app.redraw.request();
+            match event::read()? {
+                Event::Mouse(mouse) => input::handle_mouse(app, mouse),
+                Event::Key(key) if key.kind == KeyEventKind::Press => match input::handle_key(app.mode, key, app) {
+                    Action::Quit => return Ok(()),
+                    Action::OpenFile => open_selected_file(terminal, app)?,
+                    Action::EditNotes(story_id) => edit_story_notes(terminal, app, &story_id)?,
+                    Action::Copy(text) => copy_to_clipboard(&text),
+                    Action::ForwardToPty(bytes) => app.write_to_pty(&bytes),
+                    Action::None => {}
+                },
+                Event::Paste(text) if app.mode == Mode::Claude => app.paste_to_pty(&text),
+                Event::FocusGained => app.redraw.set_focused(true),
+                Event::FocusLost => app.redraw.set_focused(false),
+                _ => {}
+            }
+        }
+    }
+}
 
-            // Build remaining status content
-            let mut status_lines: Vec<Line> = Vec::new();
-            status_lines.push(Line::from("")); // Gap after cards
-
-            // Active Phase section
-            let session_elapsed = app.session_start.elapsed();
-            status_lines.push(Line::from(vec![
-                Span::styled("✦ ACTIVE PHASE", Style::default().fg(TEXT_MUTED)),
-            ]));
-            // Determine current phase name based on iteration state
-            let phase_name = match app.iteration_state {
-                IterationState::Running => "Execute Iteration Cycle",
-                IterationState::Completed => "All Stories Complete",
-                IterationState::NeedsRestart => "Preparing Next Iteration",
-                IterationState::WaitingDelay => "Waiting for Delay",
+/// Step lane `index`: end its iteration when the agent stops, restart it after the delay
+/// `runtime_stop` ends a running iteration because --max-runtime's wrap-up grace is over.
+fn advance_lane(app: &mut App, index: usize, runtime_stop: bool, pty_rows: u16, pty_cols: u16) -> io::Result<()> {
+    match app.lanes[index].state {
+        IterationState::Running => {
+            let lane = &app.lanes[index];
+            let (child_exited, read_failure, is_complete, stop_hook_fired, output, output_events, reader_error) = {
+                let mut state = lock_pty(&lane.pty_state);
+                state.update_activities();
+                (
+                    state.child_exited,
+                    state.read_failure.clone(),
+                    state.has_completion_signal(),
+                    state.has_stop_hook_signal(),
+                    state.output_summary(),
+                    std::mem::take(&mut state.output_events),
+                    state.reader_error.take(),
+                )
             };
-            status_lines.push(Line::from(vec![
-                Span::styled(
-                    phase_name,
-                    Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD),
-                ),
-            ]));
-            status_lines.push(Line::from(vec![
-                Span::styled(
-                    format!("⏱ Uptime: {}", format_duration(session_elapsed)),
-                    Style::default().fg(TEXT_MUTED),
-                ),
-            ]));
-            status_lines.push(Line::from("")); // Gap after active phase
-
-            // Elapsed time (iteration-specific)
-            let iteration_elapsed = app.iteration_start.elapsed();
-            status_lines.push(Line::from(vec![
-                Span::styled("Session: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                Span::styled(
-                    format_duration(session_elapsed),
-                    Style::default().fg(TEXT_PRIMARY),
-                ),
-                Span::raw("  "),
-                Span::styled("Iter: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                Span::styled(
-                    format_duration(iteration_elapsed),
-                    Style::default().fg(TEXT_PRIMARY),
-                ),
-            ]));
-            status_lines.push(Line::from(""));
-
-            // Update activities from PTY output
-            let activities = if let Some(ref mut guard) = pty_state_guard {
-                guard.update_activities();
-                guard.get_activities()
+            for (timestamp, event) in output_events {
+                app.emit_event_at(timestamp, event);
+            }
+            if let Some(message) = reader_error {
+                app.report_reader_error(message);
+            }
+            if app.end_on_compaction && output.compactions > 0 && !app.lanes[index].compaction_wrap_up_sent {
+                let message = format!("{}\r", COMPACTION_WRAP_UP_MESSAGE);
+                app.lanes[index].write(message.as_bytes());
+                app.lanes[index].compaction_wrap_up_sent = true;
+                app.show_toast(format!("Lane {}: context compacted - asking the agent to wrap up", index + 1));
+            }
+            let control_signal = take_control_signal(&app.task_dir, &Lane::signal_file(index)).map(|message| message.signal);
+            let mut source = if control_signal.is_some() {
+                IterationEndSource::ControlFile
+            } else if stop_hook_fired {
+                IterationEndSource::OutputScrape
+            } else if child_exited && read_failure.is_some() {
+                IterationEndSource::ReadError
+            } else if child_exited {
+                IterationEndSource::ChildExited
+            } else if runtime_stop {
+                IterationEndSource::RuntimeLimit
             } else {
-                Vec::new()
+                return Ok(());
             };
 
-            // Recent activities section
-            if !activities.is_empty() {
-                status_lines.push(Line::from(vec![
-                    Span::styled("Recent Activity:", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                ]));
-                let max_activity_width = left_panel_area.width.saturating_sub(6) as usize;
-                for activity in activities.iter().take(5) {
-                    status_lines.push(Line::from(vec![
-                        Span::styled("  • ", Style::default().fg(TEXT_MUTED)),
-                        Span::styled(
-                            activity.format(max_activity_width),
-                            Style::default().fg(TEXT_PRIMARY),
-                        ),
-                    ]));
-                }
-                status_lines.push(Line::from(""));
-            }
-
-            // PRD information
-            if let Some(ref prd) = app.prd {
-                // Description
-                status_lines.push(Line::from(vec![
-                    Span::styled("Task: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                ]));
-                // Wrap description to fit panel
-                for line in wrap_text(&prd.description, left_panel_area.width.saturating_sub(4) as usize) {
-                    status_lines.push(Line::from(Span::raw(format!("  {}", line))));
-                }
-                status_lines.push(Line::from(""));
-
-                // Branch (or working directory note if no branch)
-                let branch_display = prd.branch_name.as_deref().unwrap_or("(working in existing repos)");
-                status_lines.push(Line::from(vec![
-                    Span::styled("Branch: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::raw(branch_display),
-                ]));
-                status_lines.push(Line::from(""));
-
-                // Progress (text display - cards show the numbers)
-                let progress_pct = if total > 0 {
-                    (completed as f32 / total as f32 * 100.0) as u8
-                } else {
-                    0
-                };
-                status_lines.push(Line::from(vec![
-                    Span::styled("Progress: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::styled(
-                        format!("{}%", progress_pct),
-                        if completed == total {
-                            Style::default().fg(GREEN_SUCCESS).add_modifier(Modifier::BOLD)
-                        } else {
-                            Style::default().fg(CYAN_PRIMARY)
-                        },
-                    ),
-                ]));
-
-                // Overall progress bar (text-based)
-                let bar_width = left_panel_area.width.saturating_sub(6) as usize; // Leave room for borders
-                let filled = (bar_width as f32 * progress_pct as f32 / 100.0) as usize;
-                let empty = bar_width.saturating_sub(filled);
-                let bar_filled: String = "█".repeat(filled);
-                let bar_empty: String = "░".repeat(empty);
-                let progress_color = if completed == total { GREEN_SUCCESS } else { CYAN_PRIMARY };
-                status_lines.push(Line::from(vec![
-                    Span::styled(bar_filled, Style::default().fg(progress_color)),
-                    Span::styled(bar_empty, Style::default().fg(BORDER_SUBTLE)),
-                ]));
-                status_lines.push(Line::from(""));
-
-                // User Stories section header
-                status_lines.push(Line::from(vec![
-                    Span::styled("↳ USER STORIES / PHASES", Style::default().fg(TEXT_MUTED)),
-                ]));
+            app.lanes[index].stop();
+            signals::set_lane_child(index, None);
+            app.reload_prd();
+            if output.tests.is_some() {
+                app.last_test_result = output.tests;
+            }
+            let lane = &app.lanes[index];
+            let finished = is_complete
+                || control_signal == Some(ControlSignal::Complete)
+                || app.prd.as_ref().is_some_and(|prd| lane.stories_pass(prd));
+            if !finished && app.runtime_exhausted() {
+                source = IterationEndSource::RuntimeLimit;
+                app.runtime_limit_hit = true;
+            }
+            let done = finished || app.runtime_exhausted() || lane.iteration >= app.max_iterations;
+            let state = if done { IterationState::Completed } else { IterationState::NeedsRestart };
+            append_iteration_log(
+                &app.task_dir,
+                lane.iteration,
+                source,
+                state,
+                lane.iteration_start.elapsed(),
+                output,
+                app.model.as_deref(),
+                app.agent_version.as_ref(),
+            );
+            app.observe_iteration(lane.iteration_start.elapsed());
+            let iteration = lane.iteration;
+            app.record_iteration_end(iteration, source, read_failure);
+            app.emit_event(SessionEvent::IterationEnd { iteration, source: source.label().to_string() });
+            app.run_hook(HookEvent::IterationEnd, iteration);
+            app.verify_at_boundary();
+
+            let lane = &mut app.lanes[index];
+            if done {
+                lane.state = IterationState::Completed;
             } else {
-                status_lines.push(Line::from(vec![
-                    Span::styled("Error: ", Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD)),
-                    Span::raw("Failed to load prd.json"),
-                ]));
-            }
-
-            // Calculate lines for status content
-            let status_line_count = status_lines.len() as u16;
-
-            // Split content area: status text at top, story cards in middle, hints at bottom
-            let content_split = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(status_line_count),
-                    Constraint::Min(0), // Story cards area
-                    Constraint::Length(4), // Hints area
-                ])
-                .split(content_area_inner);
-
-            let status_area = content_split[0];
-            let stories_area = content_split[1];
-            let hints_area = content_split[2];
-
-            let left_content = Paragraph::new(status_lines)
-                .style(Style::default().fg(TEXT_PRIMARY));
-
-            frame.render_widget(left_content, status_area);
-
-            // Render keybinding hints at the bottom of left panel
-            let hints_lines = vec![
-                Line::from(Span::styled("─── Navigation ───", Style::default().fg(BORDER_SUBTLE))),
-                Line::from(vec![
-                    Span::styled("↑↓", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::styled(" or ", Style::default().fg(TEXT_MUTED)),
-                    Span::styled("j/k", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::styled(" Select story", Style::default().fg(TEXT_MUTED)),
-                ]),
-                Line::from(vec![
-                    Span::styled("s", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::styled(" Story  ", Style::default().fg(TEXT_MUTED)),
-                    Span::styled("p", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::styled(" Progress  ", Style::default().fg(TEXT_MUTED)),
-                    Span::styled("r", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::styled(" Reqs", Style::default().fg(TEXT_MUTED)),
-                ]),
-            ];
-            let hints = Paragraph::new(hints_lines);
-            frame.render_widget(hints, hints_area);
-
-            // Render story cards if we have a PRD
-            if let Some(ref prd) = app.prd {
-                // Calculate progress percent for active story based on per-criteria completion
-                let progress_percent = if let Some(story) = prd.current_story() {
-                    let total = story.acceptance_criteria.len();
-                    if total > 0 {
-                        let passed = story.acceptance_criteria.iter().filter(|c| c.passes).count();
-                        ((passed as f32 / total as f32) * 100.0) as u16
-                    } else {
-                        0
-                    }
+                lane.state = IterationState::WaitingDelay;
+                lane.delay_start = Some(Instant::now());
+                app.check_disk_space();
+            }
+        }
+        IterationState::WaitingDelay => {
+            // A nearly full disk holds every lane until space is freed
+            app.poll_disk_space();
+            let lane = &app.lanes[index];
+            if lane.delay_start.is_some_and(|start| start.elapsed().as_secs() >= ITERATION_DELAY_SECS)
+                && app.disk_error.is_none()
+            {
+                if app.runtime_exhausted() {
+                    app.runtime_limit_hit = true;
+                    app.lanes[index].state = IterationState::Completed;
+                } else if app.prd.as_ref().is_some_and(|prd| lane.stories_pass(prd)) {
+                    app.lanes[index].state = IterationState::Completed;
                 } else {
-                    100 // All stories complete
-                };
-
-                // Get stories sorted by priority
-                let mut stories: Vec<_> = prd.user_stories.iter().collect();
-                stories.sort_by_key(|s| s.priority);
-
-                // Find current story for state comparison
-                let current_story = prd.current_story();
-
-                // Card heights: active = 5 lines, others = 3 lines
-                let active_card_height = 5u16;
-                let normal_card_height = 3u16;
-
-                // Ensure selected_story_index is valid
-                if app.selected_story_index >= stories.len() && !stories.is_empty() {
-                    app.selected_story_index = stories.len() - 1;
-                }
-
-                // Make scroll follow selection: adjust scroll_offset so selected story is visible
-                // If selected < scroll_offset, scroll up
-                if app.selected_story_index < app.story_scroll_offset {
-                    app.story_scroll_offset = app.selected_story_index;
-                }
-                // Estimate visible stories (assuming average card height of 3 lines)
-                // Subtract 2 lines for potential scroll indicators (above/below)
-                let effective_height = stories_area.height.saturating_sub(2);
-                let estimated_visible = (effective_height / normal_card_height).max(1) as usize;
-                if app.selected_story_index >= app.story_scroll_offset + estimated_visible {
-                    // Selected story is below visible area, scroll down
-                    // Put selected story at the bottom of visible area
-                    app.story_scroll_offset = app.selected_story_index.saturating_sub(estimated_visible.saturating_sub(1));
-                }
-                let max_scroll = stories.len().saturating_sub(1);
-                if app.story_scroll_offset > max_scroll {
-                    app.story_scroll_offset = max_scroll;
+                    app.lanes[index].iteration += 1;
+                    spawn_lane(app, index, pty_rows, pty_cols)?;
                 }
+            }
+        }
+        IterationState::NeedsRestart | IterationState::Completed | IterationState::Aborted => {}
+    }
+    Ok(())
+}
 
-                // Calculate total height needed and visible stories
-                let mut y_offset = 0u16;
-                let mut rendered_count = 0usize;
-
-                // Show scroll indicator if content extends above
-                if app.story_scroll_offset > 0 {
-                    let indicator = Line::from(vec![
-                        Span::styled("  ▲ ", Style::default().fg(TEXT_MUTED)),
-                        Span::styled(
-                            format!("{} more above", app.story_scroll_offset),
-                            Style::default().fg(TEXT_MUTED),
-                        ),
-                    ]);
-                    let indicator_para = Paragraph::new(indicator);
-                    let indicator_area = Rect {
-                        x: stories_area.x,
-                        y: stories_area.y,
-                        width: stories_area.width,
-                        height: 1,
-                    };
-                    frame.render_widget(indicator_para, indicator_area);
-                    y_offset = 1;
-                }
+/// Result of a `--once` run: (result label, exit code)
+/// 0 = completion signal seen, 2 = more work remains (or --max-runtime ran out),
+/// 1 = error or interrupted
+fn once_outcome(app: &App, result: &io::Result<()>) -> (&'static str, i32) {
+    if result.is_err() {
+        return ("error", 1);
+    }
+    if app.aborted() {
+        return ("interrupted", 1);
+    }
+    if app.runtime_limit_hit {
+        return ("runtime_limit", 2);
+    }
+    match app.iteration_state {
+        IterationState::Completed => ("complete", 0),
+        IterationState::NeedsRestart => ("needs_more", 2),
+        _ => ("error", 1),
+    }
+}
 
-                for (idx, story) in stories.iter().enumerate() {
-                    // Skip stories before scroll offset
-                    if idx < app.story_scroll_offset {
-                        continue;
-                    }
+/// Set up a file watcher that raises a reload flag when one of the watched files changes
+/// Used for prd.json and progress.txt, each paired with its own flag.
+fn setup_file_watcher(files: Vec<(PathBuf, Arc<Mutex<bool>>)>) -> Option<RecommendedWatcher> {
+    // Use a shorter poll interval for more responsive updates
+    let config = Config::default().with_poll_interval(Duration::from_millis(500));
+
+    // Canonicalize the paths for reliable comparison
+    let targets: Vec<_> = files
+        .iter()
+        .map(|(path, flag)| {
+            let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+            (canonical, path.file_name().map(|s| s.to_os_string()), Arc::clone(flag))
+        })
+        .collect();
 
-                    // Determine story state
-                    let state = if story.passes {
-                        StoryState::Completed
-                    } else if Some(*story) == current_story {
-                        StoryState::Active
-                    } else {
-                        StoryState::Pending
-                    };
-
-                    let card_height = if state == StoryState::Active {
-                        active_card_height
-                    } else {
-                        normal_card_height
-                    };
-
-                    // Check if card fits in available space (reserve 1 line for bottom indicator)
-                    let remaining_stories = stories.len() - idx - 1;
-                    let reserve_for_indicator = if remaining_stories > 0 { 1 } else { 0 };
-                    if y_offset + card_height + reserve_for_indicator > stories_area.height {
-                        // Show scroll indicator for remaining stories
-                        let remaining = stories.len() - idx;
-                        if remaining > 0 && y_offset < stories_area.height {
-                            let indicator = Line::from(vec![
-                                Span::styled("  ▼ ", Style::default().fg(TEXT_MUTED)),
-                                Span::styled(
-                                    format!("{} more below", remaining),
-                                    Style::default().fg(TEXT_MUTED),
-                                ),
-                            ]);
-                            let indicator_para = Paragraph::new(indicator);
-                            let indicator_area = Rect {
-                                x: stories_area.x,
-                                y: stories_area.y + y_offset,
-                                width: stories_area.width,
-                                height: 1,
-                            };
-                            frame.render_widget(indicator_para, indicator_area);
+    let watcher_result = RecommendedWatcher::new(
+        move |res: Result<notify::Event, notify::Error>| {
+            if let Ok(event) = res {
+                for (canonical_path, filename, needs_reload) in &targets {
+                    // Check if any event path matches this file
+                    // Compare by filename since paths may differ in representation
+                    let matches = event.paths.iter().any(|p| {
+                        // Try canonical path comparison first
+                        if let Ok(canonical) = p.canonicalize()
+                            && &canonical == canonical_path
+                        {
+                            return true;
                         }
-                        break;
-                    }
+                        // Fall back to filename comparison
+                        if let Some(expected_name) = filename
+                            && let Some(event_name) = p.file_name()
+                        {
+                            return event_name == expected_name;
+                        }
+                        false
+                    });
 
-                    let card_area = Rect {
-                        x: stories_area.x,
-                        y: stories_area.y + y_offset,
-                        width: stories_area.width,
-                        height: card_height,
-                    };
-
-                    // Check if this story is selected
-                    let is_selected = idx == app.selected_story_index;
-
-                    // Calculate criteria progress for this story
-                    let criteria_total = story.acceptance_criteria.len();
-                    let criteria_passed = story.acceptance_criteria.iter().filter(|c| c.passes).count();
-
-                    render_story_card(
-                        card_area,
-                        &story.id,
-                        &story.title,
-                        state,
-                        app.animation_tick,
-                        progress_percent,
-                        criteria_passed,
-                        criteria_total,
-                        is_selected,
-                        frame,
-                    );
-
-                    y_offset += card_height;
-                    rendered_count += 1;
+                    if matches
+                        && let Ok(mut flag) = needs_reload.lock()
+                    {
+                        *flag = true;
+                    }
                 }
-
-                // If we rendered all remaining stories, no need for bottom indicator
-                let _ = rendered_count;
             }
+        },
+        config,
+    );
 
-            // Right panel: Two separate terminals (Ralph on top, Claude on bottom)
-            // Each terminal is its own bordered section
-
-            // Determine Ralph terminal height based on expanded state
-            let ralph_is_expanded = app.ralph_expanded || app.ralph_view_mode != RalphViewMode::Normal;
-            let ralph_terminal_height = if ralph_is_expanded {
-                9  // Expanded: 2 border + 5 content + 2 padding
-            } else {
-                6  // Normal: 2 border + 2 content + 2 padding
-            };
-
-            // Split right panel directly into Ralph terminal (top) and Claude terminal (bottom)
-            let terminal_split = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(ralph_terminal_height),  // Ralph terminal (top)
-                    Constraint::Min(0),  // Claude terminal (takes remaining space, bottom)
-                ])
-                .split(right_panel_area);
-
-            let ralph_terminal_area = terminal_split[0];
-            let claude_terminal_area = terminal_split[1];
-
-            // === CLAUDE TERMINAL ===
-            // Create bordered block for Claude terminal
-            let claude_title = match app.mode {
-                Mode::Claude => Line::from(vec![
-                    Span::raw(" >_ claude-code - ralph-loop "),
-                    Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)),
-                    Span::raw(" "),
-                ]),
-                Mode::Ralph => Line::from(" >_ claude-code - ralph-loop "),
-            };
-            let claude_block = Block::default()
-                .title(claude_title)
-                .borders(Borders::ALL)
-                .border_style(right_border_style)
-                .style(Style::default().bg(BG_PRIMARY));
-
-            let claude_content_area = claude_block.inner(claude_terminal_area);
-            frame.render_widget(claude_block, claude_terminal_area);
-
-            // Claude terminal content (VT100 rendered) - uses full inner area
-            // Set scrollback offset for user-controlled scrolling (mouse wheel)
-            let lines = if let Some(ref mut pty_state) = pty_state_guard {
-                // Set scrollback position for viewing history
-                pty_state.parser.screen_mut().set_scrollback(app.claude_scroll_offset);
-                let screen = pty_state.parser.screen();
-                let rendered = render_vt100_screen(screen);
-                // Reset scrollback to 0 so stop hook detection sees current content
-                pty_state.parser.screen_mut().set_scrollback(0);
-                rendered
-            } else {
-                vec![Line::from(Span::styled(
-                    "Error: Failed to access PTY state",
-                    Style::default().fg(RED_ERROR),
-                ))]
-            };
+    match watcher_result {
+        Ok(mut watcher) => {
+            // Watch the parent directories since some editors replace files
+            let mut parents: Vec<&Path> = files.iter().filter_map(|(path, _)| path.parent()).collect();
+            parents.dedup();
+            for parent in parents {
+                let _ = watcher.watch(parent, RecursiveMode::NonRecursive);
+            }
+            Some(watcher)
+        }
+        Err(_) => None,
+    }
+}
 
-            // Scroll to show the bottom of the terminal output (most recent content)
-            // When claude_scroll_offset is 0, we're at the bottom (current view)
-            // When claude_scroll_offset > 0, we're viewing history
-            let content_height = claude_content_area.height as usize;
-            let scroll_offset = if app.claude_scroll_offset == 0 && lines.len() > content_height {
-                (lines.len() - content_height) as u16
-            } else {
-                0
-            };
+/// Columns inside the Claude panel's borders (at least 40)
+fn panel_cols(width: u16, sidebar_hidden: bool) -> u16 {
+    let panel = if sidebar_hidden { width } else { (width as f32 * 0.70) as u16 };
+    panel.saturating_sub(2).max(40)
+}
 
-            let claude_content = Paragraph::new(lines)
-                .scroll((scroll_offset, 0));
-            frame.render_widget(claude_content, claude_content_area);
-
-            // === RALPH TERMINAL ===
-            // Create bordered block for Ralph terminal
-            let ralph_title = match app.mode {
-                Mode::Ralph => Line::from(vec![
-                    Span::raw(" >_ ralph output "),
-                    Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)),
-                    Span::raw(" "),
-                ]),
-                Mode::Claude => Line::from(" >_ ralph output "),
-            };
-            let ralph_border_style = match app.mode {
-                Mode::Ralph => Style::default().fg(CYAN_PRIMARY),
-                Mode::Claude => Style::default().fg(BORDER_SUBTLE),
-            };
-            let ralph_block = Block::default()
-                .title(ralph_title)
-                .borders(Borders::ALL)
-                .border_style(ralph_border_style)
-                .style(Style::default().bg(BG_PRIMARY));
-
-            let ralph_content_area = ralph_block.inner(ralph_terminal_area);
-            frame.render_widget(ralph_block, ralph_terminal_area);
-
-            // Ralph terminal content (based on view mode)
-            let ralph_content_lines: Vec<Line> = match app.ralph_view_mode {
-                RalphViewMode::Normal => {
-                    // Show ASCII logo and status
-                    vec![
-                        Line::from(vec![
-                            Span::styled("  ▶▶ ", Style::default().fg(GREEN_ACTIVE)),
-                            Span::styled("RALPH LOOP", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                            Span::styled(" ◀◀", Style::default().fg(GREEN_ACTIVE)),
-                        ]),
-                        Line::from(Span::styled(
-                            format!("     Iteration {}/{}", app.current_iteration, app.max_iterations),
-                            Style::default().fg(TEXT_MUTED),
-                        )),
-                    ]
-                }
-                RalphViewMode::StoryDetails => {
-                    // Show selected story details from prd.json
-                    if let Some(ref prd) = app.prd {
-                        let mut stories: Vec<_> = prd.user_stories.iter().collect();
-                        stories.sort_by_key(|s| s.priority);
-                        if let Some(story) = stories.get(app.selected_story_index) {
-                            let status_text = if story.passes { "✓ PASSED" } else { "○ PENDING" };
-                            let status_color = if story.passes { GREEN_SUCCESS } else { AMBER_WARNING };
-                            let mut lines = vec![
-                                Line::from(vec![
-                                    Span::styled(format!("  {} ", story.id), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                                    Span::styled(status_text, Style::default().fg(status_color)),
-                                ]),
-                                Line::from(Span::styled(format!("  {}", story.title), Style::default().fg(TEXT_PRIMARY))),
-                            ];
-                            // Add all acceptance criteria (scrollable)
-                            lines.push(Line::from(Span::styled("  ─── Acceptance Criteria ───", Style::default().fg(BORDER_SUBTLE))));
-                            for (i, criterion) in story.acceptance_criteria.iter().enumerate() {
-                                let check = if criterion.passes { "✓" } else { "○" };
-                                let check_color = if criterion.passes { GREEN_SUCCESS } else { TEXT_MUTED };
-                                lines.push(Line::from(vec![
-                                    Span::styled(format!("  {} ", check), Style::default().fg(check_color)),
-                                    Span::styled(format!("{}. {}", i + 1, criterion.description), Style::default().fg(TEXT_SECONDARY)),
-                                ]));
-                            }
-                            // Add description if present
-                            if !story.description.is_empty() {
-                                lines.push(Line::from(""));
-                                lines.push(Line::from(Span::styled("  ─── Description ───", Style::default().fg(BORDER_SUBTLE))));
-                                lines.push(Line::from(Span::styled(format!("  {}", story.description), Style::default().fg(TEXT_MUTED))));
-                            }
-                            // Add notes if present
-                            if !story.notes.is_empty() {
-                                lines.push(Line::from(""));
-                                lines.push(Line::from(Span::styled("  ─── Notes ───", Style::default().fg(BORDER_SUBTLE))));
-                                lines.push(Line::from(Span::styled(format!("  {}", story.notes), Style::default().fg(TEXT_MUTED))));
-                            }
-                            lines
-                        } else {
-                            vec![Line::from(Span::styled("  No story selected", Style::default().fg(TEXT_MUTED)))]
-                        }
-                    } else {
-                        vec![Line::from(Span::styled("  No PRD loaded", Style::default().fg(TEXT_MUTED)))]
-                    }
-                }
-                RalphViewMode::Progress => {
-                    // Show progress.txt entries for selected story
-                    if let Some(ref prd) = app.prd {
-                        let mut stories: Vec<_> = prd.user_stories.iter().collect();
-                        stories.sort_by_key(|s| s.priority);
-                        if let Some(story) = stories.get(app.selected_story_index) {
-                            let progress_path = app.task_dir.join("progress.txt");
-                            if let Ok(content) = std::fs::read_to_string(&progress_path) {
-                                // Find entries containing the story ID
-                                let story_id = &story.id;
-                                let mut matching_lines: Vec<Line> = vec![
-                                    Line::from(vec![
-                                        Span::styled("  Progress for ", Style::default().fg(TEXT_MUTED)),
-                                        Span::styled(story_id.clone(), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                                    ]),
-                                ];
-
-                                // Find section that mentions this story ID
-                                let mut in_matching_section = false;
-                                let mut found_any = false;
-                                for line in content.lines() {
-                                    if line.contains(story_id) && line.starts_with("##") {
-                                        in_matching_section = true;
-                                        found_any = true;
-                                        continue; // Skip the header line itself
-                                    } else if line.starts_with("##") || line.starts_with("---") {
-                                        in_matching_section = false;
-                                    }
-
-                                    if in_matching_section && !line.is_empty() {
-                                        // Show full line (scrollable)
-                                        matching_lines.push(Line::from(Span::styled(
-                                            format!("  {}", line),
-                                            Style::default().fg(TEXT_SECONDARY),
-                                        )));
-                                    }
-                                }
+/// PTY size (cols, rows) for the Claude panel in a terminal of `width` x `height`
+/// The Claude panel is 70% of the width (all of it with the sidebar hidden), minus
+/// borders, the footer, the Ralph terminal above it and the progress.txt strip when
+/// shown. Minimum sizes prevent issues in tiny terminals.
+fn pty_size_for(
+    width: u16,
+    height: u16,
+    progress_strip: bool,
+    sidebar_hidden: bool,
+    ralph_size: RalphTerminalSize,
+) -> (u16, u16) {
+    let strip = if progress_strip { render::PROGRESS_STRIP_HEIGHT } else { 0 };
+    let cols = panel_cols(width, sidebar_hidden);
+    let rows = height.saturating_sub(3 + strip + ralph_size.height()).max(10);
+    (cols, rows)
+}
 
-                                if !found_any {
-                                    matching_lines.push(Line::from(Span::styled(
-                                        "  No progress entries found",
-                                        Style::default().fg(TEXT_MUTED),
-                                    )));
-                                }
-                                matching_lines
-                            } else {
-                                vec![Line::from(Span::styled("  progress.txt not found", Style::default().fg(TEXT_MUTED)))]
-                            }
-                        } else {
-                            vec![Line::from(Span::styled("  No story selected", Style::default().fg(TEXT_MUTED)))]
-                        }
-                    } else {
-                        vec![Line::from(Span::styled("  No PRD loaded", Style::default().fg(TEXT_MUTED)))]
-                    }
-                }
-                RalphViewMode::Requirements => {
-                    // Show requirements from prd.md for selected story
-                    if let Some(ref prd) = app.prd {
-                        let mut stories: Vec<_> = prd.user_stories.iter().collect();
-                        stories.sort_by_key(|s| s.priority);
-                        if let Some(story) = stories.get(app.selected_story_index) {
-                            let prd_md_path = app.task_dir.join("prd.md");
-                            if let Ok(content) = std::fs::read_to_string(&prd_md_path) {
-                                let story_id = &story.id;
-                                let story_title = &story.title;
-                                let mut matching_lines: Vec<Line> = vec![
-                                    Line::from(vec![
-                                        Span::styled("  Requirements for ", Style::default().fg(TEXT_MUTED)),
-                                        Span::styled(story_id.clone(), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                                    ]),
-                                ];
-
-                                // Find section that mentions this story ID or title
-                                let mut in_matching_section = false;
-                                let mut found_any = false;
-                                for line in content.lines() {
-                                    // Look for headers containing story ID or title
-                                    if (line.contains(story_id) || line.contains(story_title.as_str()))
-                                        && (line.starts_with("#") || line.starts_with("##"))
-                                    {
-                                        in_matching_section = true;
-                                        found_any = true;
-                                        continue;
-                                    } else if line.starts_with("#") {
-                                        in_matching_section = false;
-                                    }
-
-                                    if in_matching_section && !line.is_empty() {
-                                        // Show full line (scrollable)
-                                        matching_lines.push(Line::from(Span::styled(
-                                            format!("  {}", line),
-                                            Style::default().fg(TEXT_SECONDARY),
-                                        )));
-                                    }
-                                }
+/// PTY size (cols, rows) for each of `lanes` stacked panes in --parallel mode
+/// The panes share the Claude panel below the ralph output terminal.
+fn lane_pty_size_for(
+    width: u16,
+    height: u16,
+    progress_strip: bool,
+    sidebar_hidden: bool,
+    ralph_size: RalphTerminalSize,
+    lanes: usize,
+) -> (u16, u16) {
+    let strip = if progress_strip { render::PROGRESS_STRIP_HEIGHT } else { 0 };
+    let cols = panel_cols(width, sidebar_hidden);
+    let panel = height.saturating_sub(1 + strip + ralph_size.height());
+    let rows = (panel / lanes.max(1) as u16).saturating_sub(2).max(5);
+    (cols, rows)
+}
 
-                                if !found_any {
-                                    matching_lines.push(Line::from(Span::styled(
-                                        "  No requirements section found in prd.md",
-                                        Style::default().fg(TEXT_MUTED),
-                                    )));
-                                }
-                                matching_lines
-                            } else {
-                                vec![Line::from(Span::styled("  prd.md not found", Style::default().fg(TEXT_MUTED)))]
-                            }
-                        } else {
-                            vec![Line::from(Span::styled("  No story selected", Style::default().fg(TEXT_MUTED)))]
-                        }
-                    } else {
-                        vec![Line::from(Span::styled("  No PRD loaded", Style::default().fg(TEXT_MUTED)))]
-                    }
-                }
-            };
+/// Wait for terminal input for the schedule's poll interval (`focused`, longer while
+/// unfocused). Agent output cuts a long wait short so it is still shown at once.
+fn poll_input(redraw: &RedrawSchedule, focused: Duration) -> io::Result<bool> {
+    let deadline = Instant::now() + redraw.poll_interval(focused);
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if event::poll(left.min(focused))? {
+            return Ok(true);
+        }
+        if left <= focused || redraw.output_pending() {
+            return Ok(false);
+        }
+    }
+}
 
-            // Add scroll hint and apply scroll offset for Ralph terminal content (only when not in Normal mode)
-            let mut ralph_content_lines = ralph_content_lines;
-            let ralph_scroll = if app.ralph_view_mode != RalphViewMode::Normal {
-                // Add scroll hint at the top
-                ralph_content_lines.insert(0, Line::from(vec![
-                    Span::styled("  PgUp/PgDn", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::styled(" to scroll │ Press key again to close", Style::default().fg(TEXT_MUTED)),
-                ]));
-                ralph_content_lines.insert(1, Line::from(""));
-                // Cap scroll offset to content length
-                let max_scroll = ralph_content_lines.len().saturating_sub(ralph_content_area.height as usize);
-                app.ralph_scroll_offset.min(max_scroll) as u16
-            } else {
-                0
-            };
+/// Record the iteration that ended (log, timeline, event, hooks) and decide what comes
+/// next; `is_complete` is whether the completion sentinel is in the output by now
+fn finish_iteration(app: &mut App, is_complete: bool) {
+    let Some(ending) = app.iteration_ending.take() else {
+        return;
+    };
+    let mut source = ending.source;
+    // Set iteration state based on signal and output
+    // (out of runtime, this is the last iteration however it ended)
+    if is_complete || ending.complete {
+        app.iteration_state = IterationState::Completed;
+    } else if app.runtime_exhausted() {
+        source = IterationEndSource::RuntimeLimit;
+        app.runtime_limit_hit = true;
+        app.iteration_state = IterationState::Completed;
+    } else {
+        app.iteration_state = IterationState::NeedsRestart;
+    }
+    let output = app.iteration_output();
+    append_iteration_log(
+        &app.task_dir,
+        app.current_iteration,
+        source,
+        app.iteration_state,
+        app.iteration_start.elapsed(),
+        output,
+        app.model.as_deref(),
+        app.agent_version.as_ref(),
+    );
+    app.observe_iteration(app.iteration_start.elapsed());
+    app.record_iteration_end(app.current_iteration, source, ending.read_failure);
+    app.emit_event(SessionEvent::IterationEnd {
+        iteration: app.current_iteration,
+        source: source.label().to_string(),
+    });
+    app.run_hook(HookEvent::IterationEnd, app.current_iteration);
+    app.verify_at_boundary();
+}
 
-            let ralph_content = Paragraph::new(ralph_content_lines)
-                .style(Style::default().bg(BG_SECONDARY))
-                .scroll((ralph_scroll, 0));
-            frame.render_widget(ralph_content, ralph_content_area);
+fn run(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+    last_cols: &mut u16,
+    last_rows: &mut u16,
+) -> io::Result<()> {
+    loop {
+        // SIGTERM/SIGHUP: the handler already stopped Claude, record the interrupted iteration
+        if signals::received().is_some() {
+            app.stop_for_signal();
+            break;
+        }
 
-            // Bottom footer bar with session ID, mode indicator, and keybinding hints
-            let (mode_text, keybindings_text) = match app.mode {
-                Mode::Ralph => ("Ralph Mode", "i: Claude Mode | ^Q: Quit"),
-                Mode::Claude => ("Claude Mode", "^O: Ralph Mode | ^Q: Quit"),
-            };
+        // Check if PRD needs reloading (file changed on disk)
+        app.reload_prd_if_needed();
+        app.reload_progress_if_needed();
+        app.publish_recovery();
+        app.poll_hook_jobs();
+        app.poll_verify_job();
+        app.deliver_pending_prompt()?;
+
+        app.tick_animation();
+
+        // Resize the PTY to match the Claude panel before drawing (also after P toggles the
+        // strip), once the size held for two frames; Ctrl+L re-sends it straight away
+        let size = terminal.size()?;
+        let wanted = pty_size_for(size.width, size.height, app.progress_strip, app.sidebar_hidden, app.ralph_size);
+        if std::mem::take(&mut app.pty_resync) {
+            (*last_cols, *last_rows) = wanted;
+            app.resync_pty(wanted.0, wanted.1);
+            app.show_toast(format!("Re-synced the agent's terminal to {}×{}", wanted.0, wanted.1));
+            app.redraw.request();
+        } else if let Some((new_pty_cols, new_pty_rows)) = app.pty_resize.settle((*last_cols, *last_rows), wanted) {
+            *last_cols = new_pty_cols;
+            *last_rows = new_pty_rows;
+            app.resize_pty(new_pty_cols, new_pty_rows);
+            app.redraw.request();
+        }
 
-            // Create footer line with session ID on left, mode in middle, keybindings on right
-            // Calculate total fixed width: " Session ID " (12) + session_id + " │ " (3) + mode_text + remaining + keybindings + " " (1)
-            let fixed_width = 12 + app.session_id.len() as u16 + 3 + mode_text.len() as u16 + keybindings_text.len() as u16 + 2;
-            let fill_width = bottom_bar_area.width.saturating_sub(fixed_width) as usize;
-
-            let footer_line = Line::from(vec![
-                Span::styled(" Session ID ", Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)),
-                Span::styled(&app.session_id, Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY)),
-                Span::styled(" │ ", Style::default().fg(BORDER_SUBTLE).bg(BG_SECONDARY)),
-                Span::styled(mode_text, Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY)),
-                // Fill remaining space with background color
-                Span::styled(
-                    " ".repeat(fill_width),
-                    Style::default().bg(BG_SECONDARY),
-                ),
-                Span::styled(keybindings_text, Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)),
-                Span::styled(" ", Style::default().bg(BG_SECONDARY)),
-            ]);
-
-            let footer = Paragraph::new(footer_line)
-                .style(Style::default().bg(BG_SECONDARY));
-
-            frame.render_widget(footer, bottom_bar_area);
-        })?;
+        app.update_agent_activity();
+        app.check_activity_scope();
+        app.check_tripwire();
+        update_terminal_title(app);
+        if app.take_new_errors() && app.error_bell {
+            let _ = stdout().write_all(b"\x07");
+            let _ = stdout().flush();
+        }
+        // Only when output arrived, after input, or on the heartbeat (a flood is one frame per pass)
+        if app.redraw.due() {
+            let started = Instant::now();
+            let snapshot = app.snapshot();
+            let mut story_scroll_offset = snapshot.story_scroll_offset;
+            let drawn = terminal.draw(|frame| {
+                story_scroll_offset = render::draw_running(frame, &snapshot);
+            })?;
+            let runs = if *HYPERLINKS { hyperlink_runs(drawn.buffer, &snapshot.links) } else { Vec::new() };
+            draw_hyperlinks(terminal, runs)?;
+            app.story_scroll_offset = story_scroll_offset;
+            app.health.record_frame(started.elapsed());
+        }
 
         // Check if child exited or stop hook fired
         {
-            let state_result = app.pty_state.lock();
-            let (child_exited, is_complete, stop_hook_fired, debug_info) = match state_result {
-                Ok(mut state) => {
-                    // Update activities one final time before checking exit
-                    state.update_activities();
-                    let stop_signal = state.has_stop_hook_signal();
-                    // Debug: capture last 500 chars of recent_output for logging
-                    let debug = if stop_signal {
-                        format!("STOP HOOK DETECTED! Buffer len: {}", state.recent_output.len())
-                    } else {
-                        let stripped = strip_ansi_codes(&state.recent_output);
-                        let lower = stripped.to_lowercase();
-                        format!(
-                            "No stop hook. Buffer len: {}. Contains 'stop hook': {}, 'iteration complete': {}",
-                            state.recent_output.len(),
-                            lower.contains("stop hook"),
-                            lower.contains("iteration complete")
-                        )
-                    };
-                    (state.child_exited, state.has_completion_signal(), stop_signal, debug)
-                }
-                Err(_) => (true, false, false, "Mutex poisoned".to_string()),
+            let (child_exited, read_failure, is_complete, stop_hook_fired, debug_info) = {
+                let mut state = lock_pty(&app.pty_state);
+                // Update activities one final time before checking exit
+                state.update_activities();
+                let stop_signal = state.has_stop_hook_signal();
+                // Debug: capture last 500 chars of recent_output for logging
+                let debug = if stop_signal {
+                    format!("STOP HOOK DETECTED! Buffer len: {}", state.recent_output.len())
+                } else {
+                    let stripped = strip_ansi_codes(&state.recent_output);
+                    let lower = stripped.to_lowercase();
+                    format!(
+                        "No stop hook. Buffer len: {}. Contains 'stop hook': {}, 'iteration complete': {}",
+                        state.recent_output.len(),
+                        lower.contains("stop hook"),
+                        lower.contains("iteration complete")
+                    )
+                };
+                (state.child_exited, state.read_failure.clone(), state.has_completion_signal(), stop_signal, debug)
             };
+            // The signal handler killed the agent after this pass checked for a signal
+            if child_exited && signals::received().is_some() {
+                app.stop_for_signal();
+                break;
+            }
 
             // Write debug info periodically (every ~5 seconds based on loop timing)
             static DEBUG_COUNTER: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
             let count = DEBUG_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-            if count % 100 == 0 || stop_hook_fired {
+            if count.is_multiple_of(100) || stop_hook_fired {
                 let debug_log_path = std::env::temp_dir().join("ralph-tui-debug.log");
                 let _ = std::fs::write(debug_log_path, format!(
                     "Count: {}\nchild_exited: {}\nstop_hook_fired: {}\nis_complete: {}\nDebug: {}\n",
@@ -2644,146 +3520,246 @@ fn run(
             }
 
             // Stop hook fires when Claude's response completes - triggers new iteration
-            // Claude doesn't actually exit, so we detect the hook message in output
-            if child_exited || stop_hook_fired {
-                // Wait a moment before proceeding so user can see final output
-                std::thread::sleep(std::time::Duration::from_millis(500));
+            // The control file written by the hook is authoritative; since Claude doesn't
+            // actually exit, scraping the hook message from output remains as a fallback
+            let control_message = take_control_signal(&app.task_dir, SIGNAL_FILE);
+            // continueConversation resumes the conversation the hook named
+            if let Some(session_id) = control_message.as_ref().and_then(|message| message.session_id.clone()) {
+                app.conversation.session_id = Some(session_id);
+            }
+            let control_signal = control_message.map(|message| message.signal);
+            // --max-runtime: asks the agent to wrap up, true once its grace period is over
+            let runtime_stop = app.check_runtime_limit();
+            app.check_compaction();
+            let end_source = if control_signal.is_some() {
+                Some(IterationEndSource::ControlFile)
+            } else if stop_hook_fired {
+                Some(IterationEndSource::OutputScrape)
+            } else if child_exited && read_failure.is_some() {
+                Some(IterationEndSource::ReadError)
+            } else if child_exited {
+                Some(IterationEndSource::ChildExited)
+            } else if runtime_stop {
+                Some(IterationEndSource::RuntimeLimit)
+            } else if app.story_done_while_quiet() {
+                Some(IterationEndSource::PrdProgress)
+            } else {
+                None
+            };
 
-                // Set iteration state based on output
-                if is_complete {
-                    app.iteration_state = IterationState::Completed;
-                } else {
-                    app.iteration_state = IterationState::NeedsRestart;
-                }
+            // Keep drawing (and handling input) for the linger period so the final output
+            // is seen, then wrap the iteration up
+            if let Some(source) = end_source
+                && app.iteration_ending.is_none()
+            {
+                app.iteration_ending = Some(IterationEnding {
+                    source,
+                    complete: is_complete || control_signal == Some(ControlSignal::Complete),
+                    read_failure,
+                    at: Instant::now(),
+                });
+                app.redraw.request();
+            }
+            if app.iteration_ending.as_ref().is_some_and(|ending| ending.at.elapsed() >= app.linger) {
+                finish_iteration(app, is_complete);
                 break;
             }
         }
 
         // Handle input based on current mode
-        if event::poll(std::time::Duration::from_millis(50))? {
+        if poll_input(&app.redraw, Duration::from_millis(50))? {
+            app.redraw.request();
             match event::read()? {
-            Event::Mouse(mouse) => {
                 // Handle mouse scroll in Claude mode for terminal scrollback
-                if app.mode == Mode::Claude {
-                    // Max scrollback matches the parser initialization (1000 lines)
-                    const MAX_SCROLLBACK: usize = 1000;
-                    match mouse.kind {
-                        MouseEventKind::ScrollUp => {
-                            // Scroll up (into history)
-                            app.claude_scroll_offset = app.claude_scroll_offset.saturating_add(3);
-                            app.claude_scroll_offset = app.claude_scroll_offset.min(MAX_SCROLLBACK);
-                        }
-                        MouseEventKind::ScrollDown => {
-                            // Scroll down (towards current)
-                            app.claude_scroll_offset = app.claude_scroll_offset.saturating_sub(3);
+                Event::Mouse(mouse) => input::handle_mouse(app, mouse),
+                Event::Key(key) if key.kind == KeyEventKind::Press => match input::handle_key(app.mode, key, app) {
+                    // An iteration that already ended is recorded before quitting
+                    Action::Quit if app.iteration_ending.is_some() => {
+                        let is_complete = lock_pty(&app.pty_state).has_completion_signal();
+                        finish_iteration(app, is_complete);
+                        if app.iteration_state != IterationState::Completed {
+                            app.iteration_state = IterationState::Aborted;
                         }
-                        _ => {}
+                        break;
                     }
+                    Action::Quit => break,
+                    Action::OpenFile => open_selected_file(terminal, app)?,
+                    Action::EditNotes(story_id) => edit_story_notes(terminal, app, &story_id)?,
+                    Action::Copy(text) => copy_to_clipboard(&text),
+                    Action::ForwardToPty(bytes) => app.write_to_pty(&bytes),
+                    Action::None => {}
+                },
+                // Forward pastes to the PTY in one write instead of one keystroke per character
+                Event::Paste(text) if app.mode == Mode::Claude => {
+                    app.paste_to_pty(&text);
+                    app.claude_scroll_offset = 0;
                 }
+                Event::FocusGained => app.redraw.set_focused(true),
+                Event::FocusLost => app.redraw.set_focused(false),
+                _ => {} // Ignore other events (resize, etc.)
             }
-            Event::Key(key) if key.kind == KeyEventKind::Press => {
-                // Universal quit: Ctrl+Q only (Ctrl+C should go to PTY for interrupt)
-                if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('q') {
-                    app.iteration_state = IterationState::Completed;
-                    break;
-                }
+        }
+    }
 
-                match app.mode {
-                    Mode::Ralph => {
-                        // In Ralph mode: handle TUI controls
-                        let story_count = app.prd.as_ref().map(|p| p.user_stories.len()).unwrap_or(0);
+    Ok(())
+}
 
-                        match key.code {
-                            KeyCode::Char('i') | KeyCode::Tab => {
-                                app.mode = Mode::Claude;
-                            }
-                            // j/k and arrow keys for story navigation
-                            KeyCode::Up | KeyCode::Char('k') => {
-                                if story_count > 0 {
-                                    if app.selected_story_index > 0 {
-                                        app.selected_story_index -= 1;
-                                    } else {
-                                        // Wrap to bottom
-                                        app.selected_story_index = story_count - 1;
-                                    }
-                                    // Reset scroll when changing story
-                                    app.ralph_scroll_offset = 0;
-                                }
-                            }
-                            KeyCode::Down | KeyCode::Char('j') => {
-                                if story_count > 0 {
-                                    if app.selected_story_index < story_count - 1 {
-                                        app.selected_story_index += 1;
-                                    } else {
-                                        // Wrap to top
-                                        app.selected_story_index = 0;
-                                    }
-                                    // Reset scroll when changing story
-                                    app.ralph_scroll_offset = 0;
-                                }
-                            }
-                            // PageUp/PageDown for scrolling Ralph terminal content
-                            KeyCode::PageUp | KeyCode::Char('K') => {
-                                if app.ralph_view_mode != RalphViewMode::Normal && app.ralph_scroll_offset > 0 {
-                                    app.ralph_scroll_offset = app.ralph_scroll_offset.saturating_sub(3);
-                                }
-                            }
-                            KeyCode::PageDown | KeyCode::Char('J') => {
-                                if app.ralph_view_mode != RalphViewMode::Normal {
-                                    app.ralph_scroll_offset += 3;
-                                }
-                            }
-                            // s: Toggle story details view
-                            KeyCode::Char('s') => {
-                                app.ralph_view_mode = if app.ralph_view_mode == RalphViewMode::StoryDetails {
-                                    RalphViewMode::Normal
-                                } else {
-                                    RalphViewMode::StoryDetails
-                                };
-                                app.ralph_scroll_offset = 0; // Reset scroll on view change
-                            }
-                            // p: Toggle progress view
-                            KeyCode::Char('p') => {
-                                app.ralph_view_mode = if app.ralph_view_mode == RalphViewMode::Progress {
-                                    RalphViewMode::Normal
-                                } else {
-                                    RalphViewMode::Progress
-                                };
-                                app.ralph_scroll_offset = 0; // Reset scroll on view change
-                            }
-                            // r: Toggle requirements view
-                            KeyCode::Char('r') => {
-                                app.ralph_view_mode = if app.ralph_view_mode == RalphViewMode::Requirements {
-                                    RalphViewMode::Normal
-                                } else {
-                                    RalphViewMode::Requirements
-                                };
-                                app.ralph_scroll_offset = 0; // Reset scroll on view change
-                            }
-                            _ => {}
-                        }
-                    }
-                    Mode::Claude => {
-                        // In Claude mode: Ctrl+O returns to Ralph mode
-                        // All other keys (including ESC) are forwarded to PTY
-                        // This allows ESC to work natively in Claude for interrupting
-                        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('o') {
-                            app.mode = Mode::Ralph;
-                        } else {
-                            // Forward key to PTY (including ESC)
-                            forward_key_to_pty(app, key.code, key.modifiers);
-                            // Reset scroll offset when user types (auto-scroll to bottom)
-                            app.claude_scroll_offset = 0;
-                        }
-                    }
-                }
-            }
-            _ => {} // Ignore other events (resize, focus, etc.)
+/// Open the file referenced by the selected entry in $EDITOR
+fn open_selected_file(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App) -> io::Result<()> {
+    let Some((path, line)) = app.selected_file_reference() else {
+        app.show_toast("No file path in the selected entry");
+        return Ok(());
+    };
+    if !Path::new(&path).exists() {
+        app.show_toast(format!("File not found: {}", path));
+        return Ok(());
+    }
+    run_editor(terminal, app, &path, line)?;
+    Ok(())
+}
+
+/// Edit a story's notes in $EDITOR and write them back to prd.json (journaled like any TUI edit)
+fn edit_story_notes(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App, story_id: &str) -> io::Result<()> {
+    let Some(notes) = app
+        .prd
+        .as_ref()
+        .and_then(|prd| prd.user_stories.iter().find(|s| s.id == story_id))
+        .map(|s| s.notes.clone())
+    else {
+        return Ok(());
+    };
+    let notes_file = notes_file_path(story_id);
+    std::fs::write(&notes_file, &notes)?;
+    let edited = run_editor(terminal, app, &notes_file.to_string_lossy(), None)?;
+    let content = std::fs::read_to_string(&notes_file);
+    let _ = std::fs::remove_file(&notes_file);
+    if edited {
+        app.set_story_notes(story_id, content?.trim_end().to_string());
+    }
+    Ok(())
+}
+
+/// Put `text` on the system clipboard with an OSC 52 escape (works over SSH and in tmux
+/// with set-clipboard on)
+fn copy_to_clipboard(text: &str) {
+    let mut out = stdout();
+    let _ = out.write_all(osc52_sequence(text).as_bytes());
+    let _ = out.flush();
+}
+
+/// Whether drawn links are made clickable with OSC 8 (see links.rs)
+static HYPERLINKS: LazyLock<bool> = LazyLock::new(terminal_supports_hyperlinks);
+
+/// Write the cells showing a link again, wrapped in OSC 8, so the terminal makes them
+/// clickable (ratatui's cells can't carry the escape themselves)
+fn draw_hyperlinks(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, runs: Vec<HyperlinkRun>) -> io::Result<()> {
+    if runs.is_empty() {
+        return Ok(());
+    }
+    let backend = terminal.backend_mut();
+    for run in runs {
+        backend.write_all(hyperlink_start(&run.url).as_bytes())?;
+        backend.draw(run.cells.iter().map(|(x, y, cell)| (*x, *y, cell)))?;
+        backend.write_all(HYPERLINK_END.as_bytes())?;
+    }
+    Backend::flush(backend)
+}
+
+/// Save / restore the window title on the terminal's title stack (xterm; ignored elsewhere)
+const PUSH_TITLE: &str = "\x1b[22;2t";
+const POP_TITLE: &str = "\x1b[23;2t";
+
+/// Whether the host's title was pushed and still needs popping on exit
+static TITLE_PUSHED: AtomicBool = AtomicBool::new(false);
+
+/// Show the task's progress in the terminal title when it changed (prd.json reloads,
+/// iteration boundaries); the host's title is saved the first time
+fn update_terminal_title(app: &mut App) {
+    if !app.terminal_title {
+        return;
+    }
+    let title = app.terminal_title_text();
+    if app.title_shown.as_ref() == Some(&title) {
+        return;
+    }
+    let mut out = stdout();
+    if !TITLE_PUSHED.swap(true, Ordering::SeqCst) {
+        let _ = out.write_all(PUSH_TITLE.as_bytes());
+    }
+    let _ = out.write_all(title_sequence(&title).as_bytes());
+    let _ = out.flush();
+    app.title_shown = Some(title);
+}
+
+/// Temp file the notes of `story_id` are edited in; the id comes from prd.json, so
+/// anything but `[A-Za-z0-9_-]` becomes `_` to keep the file inside the temp directory
+fn notes_file_path(story_id: &str) -> PathBuf {
+    let id: String = story_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    std::env::temp_dir().join(format!("ralph_notes_{}_{}.txt", std::process::id(), id))
+}
+
+/// OSC 2 "set window title" sequence (control characters dropped)
+fn title_sequence(title: &str) -> String {
+    let title: String = title.chars().filter(|c| !c.is_control()).collect();
+    format!("\x1b]2;{}\x07", title)
+}
+
+/// OSC 52 "set clipboard" sequence carrying `text` base64-encoded
+fn osc52_sequence(text: &str) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in text.as_bytes().chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
             }
         }
     }
+    format!("\x1b]52;c;{}\x07", encoded)
+}
 
-    Ok(())
+/// Run $EDITOR on `path`, suspending the TUI while it runs
+/// The PTY reader thread keeps draining Claude's output in the meantime, so Claude
+/// never blocks on a full pipe. Returns whether the editor exited successfully
+/// (failures are shown as a toast).
+fn run_editor(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    app: &mut App,
+    path: &str,
+    line: Option<u32>,
+) -> io::Result<bool> {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let mut editor_parts = editor.split_whitespace();
+    let program = editor_parts.next().unwrap_or("vi");
+
+    // Suspend the TUI and hand the terminal to the editor
+    restore_terminal();
+    let status = std::process::Command::new(program)
+        .args(editor_parts)
+        .args(editor_args(program, path, line))
+        .status();
+
+    // Restore the TUI and continue where we left off (the title was restored too)
+    app.title_shown = None;
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    stdout().execute(EnableMouseCapture)?;
+    stdout().execute(EnableBracketedPaste)?;
+    stdout().execute(EnableFocusChange)?;
+    terminal.clear()?;
+
+    match status {
+        Ok(status) if !status.success() => app.show_toast(format!("{} exited with {}", program, status)),
+        Err(e) => app.show_toast(format!("Failed to launch {}: {}", program, e)),
+        Ok(_) => return Ok(true),
+    }
+    Ok(false)
 }
 
 /// Run the delay loop between iterations (2 seconds)
@@ -2794,423 +3770,322 @@ fn run_delay(
     last_cols: &mut u16,
     last_rows: &mut u16,
 ) -> io::Result<()> {
-    const DELAY_SECS: u64 = 2;
-
-    loop {
-        // Check if delay is complete
-        if let Some(start) = app.delay_start {
-            if start.elapsed() >= Duration::from_secs(DELAY_SECS) {
-                break;
-            }
-        } else {
+    // Loop until the delay is complete (or cleared)
+    // A running sync extends the delay, and a sync error holds it until dismissed;
+    // so does a nearly full disk, until space is freed, and a write outside the repository
+    // (pauseOnOutOfScope) or "pause loop" in the tripwire modal until dismissed
+    app.check_disk_space();
+    app.check_scope_pause();
+    app.check_churn_pause();
+    while let Some(start) = app.delay_start {
+        if signals::received().is_some() {
+            app.stop_for_signal();
+            break;
+        }
+        app.poll_sync_job();
+        app.poll_disk_space();
+        let sync_pending = app.sync_job.is_some()
+            || app.sync_error.is_some()
+            || app.disk_error.is_some()
+            || app.scope_hold.is_some()
+            || app.tripwire_hold.is_some()
+            || app.churn_hold.is_some();
+        if start.elapsed() >= Duration::from_secs(ITERATION_DELAY_SECS) && !sync_pending {
             break;
         }
 
         // Reload PRD if needed
         app.reload_prd_if_needed();
+        app.reload_progress_if_needed();
+        app.publish_recovery();
+        app.poll_hook_jobs();
+        app.poll_verify_job();
 
-        // Update animation tick every 100ms (for spinner animation)
-        if app.last_animation_update.elapsed() >= Duration::from_millis(100) {
-            app.animation_tick = app.animation_tick.wrapping_add(1);
-            app.last_animation_update = Instant::now();
-        }
-
-        terminal.draw(|frame| {
-            let area = frame.area();
-
-            // Check for terminal resize
-            let new_pty_cols = ((area.width as f32 * 0.70) as u16).saturating_sub(2).max(40);
-            let new_pty_rows = area.height.saturating_sub(3).max(10);
-
-            if new_pty_cols != *last_cols || new_pty_rows != *last_rows {
-                *last_cols = new_pty_cols;
-                *last_rows = new_pty_rows;
-            }
-
-            // Create main layout: content area + bottom bar
-            let main_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Min(3),
-                    Constraint::Length(1),
-                ])
-                .split(area);
-
-            let content_area = main_layout[0];
-            let bottom_bar_area = main_layout[1];
-
-            // Create horizontal split
-            let panels = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints([
-                    Constraint::Percentage(30),
-                    Constraint::Percentage(70),
-                ])
-                .split(content_area);
-
-            let left_panel_area = panels[0];
-            let right_panel_area = panels[1];
-
-            // Left panel with delay message
-            let left_title = Line::from(vec![
-                Span::raw(" Ralph Status "),
-                Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)),
-                Span::raw(" "),
-            ]);
-            let left_block = Block::default()
-                .title(left_title)
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD))
-                .style(Style::default().bg(BG_PRIMARY));
-
-            // Render the outer block first to get the inner area
-            let left_inner = left_block.inner(left_panel_area);
-            frame.render_widget(left_block, left_panel_area);
-
-            // Get PRD data for stats
-            let (completed, total) = if let Some(ref prd) = app.prd {
-                (prd.completed_count(), prd.user_stories.len())
-            } else {
-                (0, 0)
-            };
-
-            // Split inner area: header (3 lines), stat cards (8 lines for 2 rows), rest
-            let inner_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(3), // Header
-                    Constraint::Length(8), // Two stat card rows (4 lines each)
-                    Constraint::Min(0),    // Rest of content
-                ])
-                .split(left_inner);
-
-            let header_area = inner_layout[0];
-            let cards_area = inner_layout[1];
-            let content_area_inner = inner_layout[2];
-
-            // Header: Ralph branding
-            let header_lines = vec![
-                Line::from(vec![
-                    Span::styled("● ", Style::default().fg(GREEN_ACTIVE)),
-                    Span::styled("RALPH LOOP", Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)),
-                ]),
-                Line::from(vec![
-                    Span::styled(format!("Terminal v{}", VERSION), Style::default().fg(CYAN_PRIMARY)),
-                ]),
-                Line::from(""), // Gap after header
-            ];
-            let header = Paragraph::new(header_lines);
-            frame.render_widget(header, header_area);
-
-            // Split cards area into two rows
-            let cards_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(4), // First row: iteration/completed
-                    Constraint::Length(4), // Second row: stories left/progress
-                ])
-                .split(cards_area);
-
-            // Render iteration/completion stat cards (first row)
-            render_stat_cards(
-                cards_layout[0],
-                app.current_iteration,
-                app.max_iterations,
-                completed,
-                total,
-                frame,
-            );
-
-            // Render progress stat cards (second row)
-            render_progress_cards(
-                cards_layout[1],
-                completed,
-                total,
-                frame,
-            );
-
-            // Build remaining content
-            let mut status_lines: Vec<Line> = Vec::new();
-            status_lines.push(Line::from("")); // Gap after cards
-
-            // Active Phase section
-            let session_elapsed = app.session_start.elapsed();
-            status_lines.push(Line::from(vec![
-                Span::styled("✦ ACTIVE PHASE", Style::default().fg(TEXT_MUTED)),
-            ]));
-            // During delay, we're waiting for the next iteration
-            let phase_name = "Preparing Next Iteration";
-            status_lines.push(Line::from(vec![
-                Span::styled(
-                    phase_name,
-                    Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD),
-                ),
-            ]));
-            status_lines.push(Line::from(vec![
-                Span::styled(
-                    format!("⏱ Uptime: {}", format_duration(session_elapsed)),
-                    Style::default().fg(TEXT_MUTED),
-                ),
-            ]));
-            status_lines.push(Line::from("")); // Gap after active phase
-
-            // Elapsed time (iteration-specific)
-            let iteration_elapsed = app.iteration_start.elapsed();
-            status_lines.push(Line::from(vec![
-                Span::styled("Session: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                Span::styled(
-                    format_duration(session_elapsed),
-                    Style::default().fg(TEXT_PRIMARY),
-                ),
-                Span::raw("  "),
-                Span::styled("Iter: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                Span::styled(
-                    format_duration(iteration_elapsed),
-                    Style::default().fg(TEXT_PRIMARY),
-                ),
-            ]));
-            status_lines.push(Line::from(""));
-
-            // Delay countdown - prominently displayed with spinner
-            let remaining = if let Some(start) = app.delay_start {
-                DELAY_SECS.saturating_sub(start.elapsed().as_secs())
-            } else {
-                0
-            };
-            let spinner = get_spinner_frame(app.animation_tick);
-            // Add visual separator for prominence
-            status_lines.push(Line::from(vec![
-                Span::styled("━━━━━━━━━━━━━━━━━━━━━━━━━", Style::default().fg(AMBER_WARNING)),
-            ]));
-            status_lines.push(Line::from(vec![
-                Span::styled(
-                    format!("{} ", spinner),
-                    Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD),
-                ),
-                Span::styled(
-                    format!("Starting next iteration in {}s...", remaining),
-                    Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD),
-                ),
-            ]));
-            status_lines.push(Line::from(vec![
-                Span::styled("━━━━━━━━━━━━━━━━━━━━━━━━━", Style::default().fg(AMBER_WARNING)),
-            ]));
-            status_lines.push(Line::from(""));
-
-            // PRD info
-            if let Some(ref prd) = app.prd {
-                status_lines.push(Line::from(vec![
-                    Span::styled("Task: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                ]));
-                for line in wrap_text(&prd.description, left_panel_area.width.saturating_sub(4) as usize) {
-                    status_lines.push(Line::from(Span::raw(format!("  {}", line))));
-                }
-                status_lines.push(Line::from(""));
+        app.tick_animation();
 
-                let progress_pct = if total > 0 {
-                    (completed as f32 / total as f32 * 100.0) as u8
-                } else {
-                    0
-                };
-                status_lines.push(Line::from(vec![
-                    Span::styled("Progress: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
-                    Span::styled(
-                        format!("{}%", progress_pct),
-                        if completed == total {
-                            Style::default().fg(GREEN_SUCCESS).add_modifier(Modifier::BOLD)
-                        } else {
-                            Style::default().fg(CYAN_PRIMARY)
-                        },
-                    ),
-                ]));
-
-                // Overall progress bar (text-based)
-                let bar_width = left_panel_area.width.saturating_sub(6) as usize;
-                let filled = (bar_width as f32 * progress_pct as f32 / 100.0) as usize;
-                let empty = bar_width.saturating_sub(filled);
-                let bar_filled: String = "█".repeat(filled);
-                let bar_empty: String = "░".repeat(empty);
-                let progress_color = if completed == total { GREEN_SUCCESS } else { CYAN_PRIMARY };
-                status_lines.push(Line::from(vec![
-                    Span::styled(bar_filled, Style::default().fg(progress_color)),
-                    Span::styled(bar_empty, Style::default().fg(BORDER_SUBTLE)),
-                ]));
-            }
-
-            let left_content = Paragraph::new(status_lines)
-                .style(Style::default().fg(TEXT_PRIMARY));
-
-            frame.render_widget(left_content, content_area_inner);
-
-            // Right panel - dual terminals (same layout as run())
-            let right_block = Block::default()
-                .title(" Terminals ")
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(BORDER_SUBTLE))
-                .style(Style::default().bg(BG_PRIMARY));
-
-            // Render the outer block first to get the inner area
-            let right_inner = right_block.inner(right_panel_area);
-            frame.render_widget(right_block, right_panel_area);
-
-            // Determine Ralph terminal height (always normal during delay)
-            let ralph_terminal_height = 4u16;  // Normal: 1 chrome + 2 content + 1 separator
-
-            // Split right inner into Claude terminal (top) and Ralph terminal (bottom)
-            let terminal_split = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Min(0),  // Claude terminal
-                    Constraint::Length(ralph_terminal_height),  // Ralph terminal
-                ])
-                .split(right_inner);
-
-            let claude_terminal_area = terminal_split[0];
-            let ralph_terminal_area = terminal_split[1];
-
-            // === CLAUDE TERMINAL ===
-            let claude_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(1), // Window chrome
-                    Constraint::Min(0),    // Content
-                    Constraint::Length(1), // Input bar
-                ])
-                .split(claude_terminal_area);
-
-            let claude_chrome_area = claude_layout[0];
-            let claude_content_area = claude_layout[1];
-            let claude_input_area = claude_layout[2];
-
-            // Claude window chrome with centered title (no traffic lights)
-            let claude_title = ">_ claude-code - ralph-loop";
-            let title_width = claude_title.len() as u16;
-            let available_width = claude_chrome_area.width;
-            let center_offset = (available_width.saturating_sub(title_width)) / 2;
-            let right_pad = available_width.saturating_sub(center_offset + title_width);
-
-            let claude_chrome_line = Line::from(vec![
-                Span::styled(" ".repeat(center_offset as usize), Style::default().bg(BG_TERTIARY)),
-                Span::styled(claude_title, Style::default().fg(TEXT_SECONDARY).bg(BG_TERTIARY)),
-                Span::styled(" ".repeat(right_pad as usize), Style::default().bg(BG_TERTIARY)),
-            ]);
-
-            let claude_chrome = Paragraph::new(claude_chrome_line)
-                .style(Style::default().bg(BG_TERTIARY));
-            frame.render_widget(claude_chrome, claude_chrome_area);
-
-            // Render VT100 screen content
-            let lines = if let Ok(pty_state) = app.pty_state.lock() {
-                let screen = pty_state.parser.screen();
-                render_vt100_screen(screen)
-            } else {
-                vec![Line::from(Span::styled(
-                    "Error: Failed to access PTY state",
-                    Style::default().fg(RED_ERROR),
-                ))]
-            };
+        // Track the panel size so the next Claude spawns at the right size
+        let size = terminal.size()?;
+        (*last_cols, *last_rows) = pty_size_for(size.width, size.height, app.progress_strip, app.sidebar_hidden, app.ralph_size);
 
-            let claude_content = Paragraph::new(lines);
-            frame.render_widget(claude_content, claude_content_area);
-
-            // Claude input bar (placeholder style during delay)
-            let remaining_width = claude_input_area.width.saturating_sub(32);
-            let claude_input_content = Line::from(vec![
-                Span::styled("│ ", Style::default().fg(BORDER_SUBTLE).bg(BG_SECONDARY)),
-                Span::styled("> ", Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY)),
-                Span::styled("ralph@loop:~$ ", Style::default().fg(TEXT_SECONDARY).bg(BG_SECONDARY)),
-                Span::styled("Enter command...", Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)),
-                Span::styled(" ".repeat(remaining_width as usize), Style::default().bg(BG_SECONDARY)),
-            ]);
-
-            let claude_input = Paragraph::new(claude_input_content)
-                .style(Style::default().bg(BG_SECONDARY));
-            frame.render_widget(claude_input, claude_input_area);
-
-            // === RALPH TERMINAL ===
-            let ralph_layout = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(1), // Window chrome
-                    Constraint::Min(0),    // Content
-                ])
-                .split(ralph_terminal_area);
-
-            let ralph_chrome_area = ralph_layout[0];
-            let ralph_content_area = ralph_layout[1];
-
-            // Ralph window chrome
-            let ralph_title = ">_ ralph output";
-            let ralph_title_width = ralph_title.len() as u16;
-            let ralph_available_width = ralph_chrome_area.width;
-            let ralph_center_offset = (ralph_available_width.saturating_sub(ralph_title_width)) / 2;
-            let ralph_right_pad = ralph_available_width.saturating_sub(ralph_center_offset + ralph_title_width);
-
-            let ralph_chrome_line = Line::from(vec![
-                Span::styled(" ".repeat(ralph_center_offset as usize), Style::default().bg(BG_TERTIARY)),
-                Span::styled(ralph_title, Style::default().fg(TEXT_SECONDARY).bg(BG_TERTIARY)),
-                Span::styled(" ".repeat(ralph_right_pad as usize), Style::default().bg(BG_TERTIARY)),
-            ]);
-
-            let ralph_chrome = Paragraph::new(ralph_chrome_line)
-                .style(Style::default().bg(BG_TERTIARY));
-            frame.render_widget(ralph_chrome, ralph_chrome_area);
-
-            // Ralph content: show waiting message during delay
-            let ralph_content_lines = vec![
-                Line::from(Span::styled(
-                    format!("  Waiting {} seconds before next iteration...", remaining),
-                    Style::default().fg(AMBER_WARNING),
-                )),
-            ];
-
-            let ralph_content = Paragraph::new(ralph_content_lines)
-                .style(Style::default().bg(BG_SECONDARY));
-            frame.render_widget(ralph_content, ralph_content_area);
-
-            // Bottom footer bar with session ID, mode indicator, and keybinding hints
-            let mode_text = "Ralph Mode";
-            let keybindings_text = "^Q: Quit | Waiting for next iteration...";
-
-            // Create footer line with session ID on left, mode in middle, keybindings on right
-            let fixed_width = 12 + app.session_id.len() as u16 + 3 + mode_text.len() as u16 + keybindings_text.len() as u16 + 2;
-            let fill_width = bottom_bar_area.width.saturating_sub(fixed_width) as usize;
-
-            let footer_line = Line::from(vec![
-                Span::styled(" Session ID ", Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)),
-                Span::styled(&app.session_id, Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY)),
-                Span::styled(" │ ", Style::default().fg(BORDER_SUBTLE).bg(BG_SECONDARY)),
-                Span::styled(mode_text, Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY)),
-                // Fill remaining space with background color
-                Span::styled(
-                    " ".repeat(fill_width),
-                    Style::default().bg(BG_SECONDARY),
-                ),
-                Span::styled(keybindings_text, Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)),
-                Span::styled(" ", Style::default().bg(BG_SECONDARY)),
-            ]);
-
-            let footer = Paragraph::new(footer_line)
-                .style(Style::default().bg(BG_SECONDARY));
-
-            frame.render_widget(footer, bottom_bar_area);
-        })?;
+        update_terminal_title(app);
+        let snapshot = app.snapshot();
+        let drawn = terminal.draw(|frame| render::draw_delay(frame, &snapshot))?;
+        let runs = if *HYPERLINKS { hyperlink_runs(drawn.buffer, &snapshot.links) } else { Vec::new() };
+        draw_hyperlinks(terminal, runs)?;
 
         // Handle input - allow quit during delay
-        if event::poll(std::time::Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+        if poll_input(&app.redraw, Duration::from_millis(100))? {
+            match event::read()? {
                 // Only handle key press events (Windows sends both Press and Release)
-                if key.kind != KeyEventKind::Press {
-                    continue;
-                }
-                // Ctrl+Q to quit
-                if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('q') {
-                    app.iteration_state = IterationState::Completed;
+                Event::Key(key) if key.kind == KeyEventKind::Press && input::handle_delay_key(key, app) == Action::Quit => {
                     break;
                 }
+                Event::FocusGained => app.redraw.set_focused(true),
+                Event::FocusLost => app.redraw.set_focused(false),
+                _ => {}
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod testutil;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn queue_file_skips_blank_lines_and_comments() {
+        let content = "tasks/login\n\n# later\n  tasks/search  \n";
+        assert_eq!(parse_queue_file(content), vec![PathBuf::from("tasks/login"), PathBuf::from("tasks/search")]);
+    }
+
+    #[test]
+    fn task_root_flag_is_taken_from_anywhere_in_the_arguments() {
+        let mut args: Vec<String> =
+            ["ralph-tui", "status", "--task-root", "automation/ralph-tasks,tasks", "login", "--task-root=extra"]
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect();
+        let roots = take_task_root_flag(&mut args).unwrap();
+        assert_eq!(roots, [PathBuf::from("automation/ralph-tasks"), PathBuf::from("tasks"), PathBuf::from("extra")]);
+        assert_eq!(args, ["ralph-tui", "status", "login"]);
+
+        let mut args = vec!["ralph-tui".to_string(), "--task-root".to_string()];
+        assert!(take_task_root_flag(&mut args).is_err());
+    }
+
+    #[test]
+    fn pty_rows_leave_room_for_progress_strip() {
+        assert_eq!(pty_size_for(100, 40, false, false, RalphTerminalSize::Normal), (68, 30));
+        assert_eq!(
+            pty_size_for(100, 40, true, false, RalphTerminalSize::Normal),
+            (68, 30 - render::PROGRESS_STRIP_HEIGHT)
+        );
+    }
+
+    #[test]
+    fn pty_rows_follow_the_ralph_terminal_size() {
+        assert_eq!(pty_size_for(100, 40, false, false, RalphTerminalSize::Collapsed), (68, 33));
+        assert_eq!(pty_size_for(100, 40, false, false, RalphTerminalSize::Large), (68, 23));
+        // Tiny terminals keep the minimum
+        assert_eq!(pty_size_for(100, 20, false, false, RalphTerminalSize::Large), (68, 10));
+    }
+
+    #[test]
+    fn hidden_sidebar_gives_the_claude_panel_the_full_width() {
+        assert_eq!(pty_size_for(100, 40, false, true, RalphTerminalSize::Normal), (98, 30));
+        assert_eq!(lane_pty_size_for(100, 40, false, true, RalphTerminalSize::Normal, 2), (98, 14));
+        // Narrow terminals keep the minimum
+        assert_eq!(pty_size_for(30, 40, false, true, RalphTerminalSize::Normal), (40, 30));
+    }
+
+    #[test]
+    fn lanes_split_the_claude_panel_rows() {
+        assert_eq!(lane_pty_size_for(100, 40, false, false, RalphTerminalSize::Normal, 2), (68, 14));
+        assert_eq!(lane_pty_size_for(100, 40, false, false, RalphTerminalSize::Normal, 4), (68, 6));
+        // Tiny terminals still get a usable pane
+        assert_eq!(lane_pty_size_for(100, 12, true, false, RalphTerminalSize::Normal, 4), (68, 5));
+    }
+
+    #[test]
+    fn prompt_resolves_task_then_local_then_global() {
+        let root = TempDir::new("prompt");
+        let (task_dir, local_dir, config_dir) = (root.join("tasks/fix"), root.join("ralph"), root.join("config"));
+        for dir in [&task_dir, &local_dir, &config_dir] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let resolve = || resolve_prompt_path(&task_dir, &local_dir, Some(&config_dir));
+        assert_eq!(resolve(), None);
+        assert_eq!(resolve_prompt_path(&task_dir, &local_dir, None), None);
+
+        std::fs::write(config_dir.join(PROMPT_FILE), "global").unwrap();
+        assert_eq!(resolve(), Some(config_dir.join(PROMPT_FILE)));
+        std::fs::write(local_dir.join(PROMPT_FILE), "local").unwrap();
+        assert_eq!(resolve(), Some(local_dir.join(PROMPT_FILE)));
+        std::fs::write(task_dir.join(PROMPT_FILE), "task").unwrap();
+        assert_eq!(resolve(), Some(task_dir.join(PROMPT_FILE)));
+    }
+
+    #[test]
+    fn effective_prompt_is_copied_into_the_task() {
+        let root = TempDir::new("prompt-copy");
+        let task_dir = root.join("tasks/new");
+        std::fs::create_dir_all(&task_dir).unwrap();
+        std::fs::write(root.join(PROMPT_FILE), "Fix bugs only").unwrap();
+
+        let target = copy_prompt_to_task(&task_dir, Some(&root.join(PROMPT_FILE))).unwrap();
+        assert_eq!(target, task_dir.join(PROMPT_FILE));
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "Fix bugs only");
+        // Without a prompt.md anywhere, the embedded default is copied
+        copy_prompt_to_task(&task_dir, None).unwrap();
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), EMBEDDED_PROMPT);
+    }
+
+    #[test]
+    fn settings_resolve_cli_then_prd_then_config_then_default() {
+        assert_eq!(resolve_setting(Some(5), Some(20), Some(30), 10), (5, ValueSource::Flag));
+        assert_eq!(resolve_setting(None, Some(20), Some(30), 10), (20, ValueSource::PrdJson));
+        assert_eq!(resolve_setting(None, None, Some(30), 10), (30, ValueSource::ConfigFile));
+        assert_eq!(resolve_setting(None, None, None, 10), (10, ValueSource::Default));
+    }
+
+    #[test]
+    fn prd_json_carries_task_defaults() {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [], "defaultIterations": 25, "rotateThreshold": 800}"#,
+        )
+        .unwrap();
+        assert_eq!(prd.default_iterations, Some(25));
+        assert_eq!(prd.rotate_threshold, Some(800));
+        assert_eq!(
+            resolve_setting(None, prd.rotate_threshold, None, DEFAULT_ROTATE_THRESHOLD),
+            (800, ValueSource::PrdJson)
+        );
+    }
+
+    #[test]
+    fn ralph_toml_defaults_rank_below_prd_json() {
+        let ralph_config = config::parse_config("default_iterations = 40\nrotate_threshold = 500").unwrap();
+        assert_eq!(
+            resolve_setting(None, None, ralph_config.default_iterations, DEFAULT_ITERATIONS),
+            (40, ValueSource::ConfigFile)
+        );
+        assert_eq!(
+            resolve_setting(None, Some(800), ralph_config.rotate_threshold, DEFAULT_ROTATE_THRESHOLD),
+            (800, ValueSource::PrdJson)
+        );
+        assert_eq!(
+            resolve_setting(Some(3), Some(25), ralph_config.default_iterations, DEFAULT_ITERATIONS),
+            (3, ValueSource::Flag)
+        );
+        assert_eq!(
+            resolve_setting(None, None, config::RalphConfig::default().rotate_threshold, DEFAULT_ROTATE_THRESHOLD),
+            (DEFAULT_ROTATE_THRESHOLD, ValueSource::Default)
+        );
+    }
+
+    #[test]
+    fn model_prompt_takes_a_number_or_a_name() {
+        assert_eq!(parse_model_choice("2").as_deref(), Some("opus"));
+        assert_eq!(parse_model_choice("claude-sonnet-4-5").as_deref(), Some("claude-sonnet-4-5"));
+        // Out-of-range numbers are not a menu entry, so they are taken literally
+        assert_eq!(parse_model_choice("9").as_deref(), Some("9"));
+        assert_eq!(parse_model_choice(""), None);
+    }
+
+    #[test]
+    fn agent_prompt_takes_a_number_or_a_profile_name() {
+        let profiles = agents::builtin_profiles();
+        assert_eq!(parse_agent_choice("3", &profiles).as_deref(), Some("aider"));
+        assert_eq!(parse_agent_choice("opencode", &profiles).as_deref(), Some("opencode"));
+        // Unknown names and numbers keep the default
+        assert_eq!(parse_agent_choice("4", &profiles), None);
+        assert_eq!(parse_agent_choice("codex", &profiles), None);
+        assert_eq!(parse_agent_choice("", &profiles), None);
+    }
+
+    #[test]
+    fn osc52_sequence_base64_encodes_the_text() {
+        assert_eq!(osc52_sequence("US-002"), "\x1b]52;c;VVMtMDAy\x07");
+        assert_eq!(osc52_sequence("US-7"), "\x1b]52;c;VVMtNw==\x07");
+        assert_eq!(osc52_sequence("US-12"), "\x1b]52;c;VVMtMTI=\x07");
+    }
+
+    #[test]
+    fn title_sequence_drops_control_characters() {
+        assert_eq!(title_sequence("ralph: demo 1/2 · iter 3/10"), "\x1b]2;ralph: demo 1/2 · iter 3/10\x07");
+        assert_eq!(title_sequence("ralph: \x1b]0;x\x07demo"), "\x1b]2;ralph: ]0;xdemo\x07");
+    }
+
+    #[test]
+    fn notes_file_stays_in_the_temp_dir_whatever_the_story_id() {
+        let pid = std::process::id();
+        assert_eq!(notes_file_path("US-7_a"), std::env::temp_dir().join(format!("ralph_notes_{}_US-7_a.txt", pid)));
+        let path = notes_file_path("../../etc/x y");
+        assert_eq!(path.parent(), Some(std::env::temp_dir().as_path()));
+        assert_eq!(path.file_name().unwrap(), format!("ralph_notes_{}_______etc_x_y.txt", pid).as_str());
+    }
+
+    #[test]
+    fn story_selection_section_lists_targeted_ids() {
+        let ids = BTreeSet::from(["US-9".to_string(), "US-7".to_string()]);
+        let section = story_selection_section(&ids);
+        assert!(section.contains("Only work on these stories: US-7, US-9"));
+        assert!(section.contains("<promise>COMPLETE</promise>"));
+    }
+
+    #[test]
+    fn runtime_budget_formats_as_given() {
+        assert_eq!(format_runtime(digest::parse_duration("6h").unwrap()), "6h");
+        assert_eq!(format_runtime(digest::parse_duration("90m").unwrap()), "90m");
+    }
+
+    #[test]
+    fn startup_check_skips_spawning_without_work() {
+        let mut prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [
+                    {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": true, "notes": ""},
+                    {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": false, "notes": ""}
+                ]}"#,
+        )
+        .unwrap();
+        assert_eq!(startup_check(&prd), StartupCheck::Ready);
+
+        // Only the targeted subset counts
+        prd.targeted_stories = BTreeSet::from(["US-1".to_string()]);
+        assert_eq!(startup_check(&prd), StartupCheck::AllPass);
+        prd.targeted_stories.clear();
+
+        prd.user_stories[1].passes = true;
+        assert_eq!(startup_check(&prd), StartupCheck::AllPass);
+
+        prd.user_stories.clear();
+        assert_eq!(startup_check(&prd), StartupCheck::NoStories);
+    }
+
+    #[test]
+    fn criterion_focus_section_names_the_open_criterion() {
+        let mut prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [
+                    {"id": "US-4", "title": "", "description": "", "priority": 1, "passes": false, "notes": "",
+                     "acceptanceCriteria": ["renders", "handles errors", "logs"]}
+                ]}"#,
+        )
+        .unwrap();
+        let focus = CriterionFocus { story_id: "US-4".to_string(), criterion: 2 };
+        let section = criterion_focus_section(&prd, &focus).unwrap();
+        assert!(section.contains("FOCUS: In story US-4, prioritize criterion 3: logs"));
+
+        prd.user_stories[0].acceptance_criteria[2].passes = true;
+        assert_eq!(criterion_focus_section(&prd, &focus), None);
+        let gone = CriterionFocus { story_id: "US-9".to_string(), criterion: 0 };
+        assert_eq!(criterion_focus_section(&prd, &gone), None);
+    }
+
+    #[test]
+    fn human_stories_section_lists_assigned_stories() {
+        let mut prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [
+                    {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": false, "notes": "", "assignee": "dana"},
+                    {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": false, "notes": "", "assignee": "agent"}
+                ]}"#,
+        )
+        .unwrap();
+        let section = human_stories_section(&prd);
+        assert!(section.contains("- US-1 (dana)"));
+        assert!(!section.contains("US-2"));
+        assert!(section.contains("Leave them out"));
+
+        prd.user_stories[0].assignee = None;
+        assert_eq!(human_stories_section(&prd), "");
+    }
+}
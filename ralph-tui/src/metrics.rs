@@ -0,0 +1,279 @@
+//! Session metrics over HTTP for dashboards (`--metrics-port`)
+//!
+//! A background thread answers `GET /metrics` in the Prometheus text format and
+//! `GET /status` with the same JSON as `ralph-tui status --json`. The App only
+//! updates the shared counters under a short lock, so a slow or stuck scraper
+//! never holds up the UI. The server binds 127.0.0.1 unless given an address,
+//! and stops when dropped.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::events::SessionEvent;
+use crate::status::TaskStatus;
+
+/// Upper bounds (seconds) of the iteration duration histogram buckets
+pub const ITERATION_BUCKETS: [f64; 8] = [30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0];
+
+/// How long the server sleeps between checks for connections and shutdown
+const ACCEPT_POLL: Duration = Duration::from_millis(50);
+
+/// Time a client gets to send its request and read the response
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counters for the session so far, and the latest task status
+#[derive(Debug, Clone, Default)]
+pub struct SessionMetrics {
+    pub iterations_total: u64,
+    pub stories_completed: u64,
+    pub criteria_completed: u64,
+    /// Error lines in the agent's output, plus session errors such as a failed start
+    pub agent_errors_total: u64,
+    /// Iterations per ITERATION_BUCKETS bound (not cumulative); the last slot is +Inf
+    bucket_counts: [u64; ITERATION_BUCKETS.len() + 1],
+    duration_sum_secs: f64,
+    pub status: Option<TaskStatus>,
+}
+
+impl SessionMetrics {
+    /// Count an event published by the App
+    pub fn observe(&mut self, event: &SessionEvent) {
+        match event {
+            SessionEvent::StoryPassed { .. } => self.stories_completed += 1,
+            SessionEvent::CriterionPassed { .. } => self.criteria_completed += 1,
+            SessionEvent::Error { .. } | SessionEvent::ErrorLine { .. } => self.agent_errors_total += 1,
+            _ => {}
+        }
+    }
+
+    /// Count a finished iteration that ran for `duration`
+    pub fn observe_iteration(&mut self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let bucket = ITERATION_BUCKETS.iter().position(|&bound| secs <= bound).unwrap_or(ITERATION_BUCKETS.len());
+        self.bucket_counts[bucket] += 1;
+        self.duration_sum_secs += secs;
+        self.iterations_total += 1;
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut counter = |name: &str, help: &str, value: u64| {
+            out.push_str(&format!("# HELP {0} {1}\n# TYPE {0} counter\n{0} {2}\n", name, help, value));
+        };
+        counter("ralph_iterations_total", "Iterations finished this session.", self.iterations_total);
+        counter("ralph_stories_completed", "Stories that started passing this session.", self.stories_completed);
+        counter("ralph_criteria_completed", "Acceptance criteria that started passing this session.", self.criteria_completed);
+        counter("ralph_agent_errors_total", "Error lines in the agent's output and session errors.", self.agent_errors_total);
+
+        let name = "ralph_iteration_duration_seconds";
+        out.push_str(&format!("# HELP {0} Wall-clock time of each iteration.\n# TYPE {0} histogram\n", name));
+        let mut cumulative = 0;
+        for (bound, count) in ITERATION_BUCKETS.iter().zip(self.bucket_counts) {
+            cumulative += count;
+            out.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n", name, bound, cumulative));
+        }
+        out.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n", name, self.iterations_total));
+        out.push_str(&format!("{}_sum {}\n", name, self.duration_sum_secs));
+        out.push_str(&format!("{}_count {}\n", name, self.iterations_total));
+        out
+    }
+}
+
+/// Address for a `--metrics-port` value: a port (on 127.0.0.1) or a full `address:port`
+pub fn parse_bind_addr(value: &str) -> Result<SocketAddr, String> {
+    if let Ok(port) = value.parse::<u16>() {
+        return Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port));
+    }
+    value
+        .parse()
+        .map_err(|_| format!("Invalid --metrics-port: {} (expected a port, e.g. 9184, or address:port)", value))
+}
+
+/// Status line, content type and body answering an HTTP request line
+pub fn respond_to(request_line: &str, metrics: &SessionMetrics) -> (&'static str, &'static str, String) {
+    const TEXT: &str = "text/plain; charset=utf-8";
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return ("400 Bad Request", TEXT, "Bad request\n".to_string());
+    };
+    if method != "GET" {
+        return ("405 Method Not Allowed", TEXT, "Only GET is supported\n".to_string());
+    }
+    match target.split('?').next().unwrap_or_default() {
+        "/metrics" => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", metrics.render()),
+        "/status" => match metrics.status.as_ref().and_then(|status| serde_json::to_string_pretty(status).ok()) {
+            Some(json) => ("200 OK", "application/json", json + "\n"),
+            None => ("503 Service Unavailable", TEXT, "No prd.json loaded yet\n".to_string()),
+        },
+        _ => ("404 Not Found", TEXT, "Try /metrics or /status\n".to_string()),
+    }
+}
+
+/// Background HTTP server for the session's metrics; stops when dropped
+pub struct MetricsServer {
+    metrics: Arc<Mutex<SessionMetrics>>,
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Bind `addr` and start serving
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let metrics = Arc::new(Mutex::new(SessionMetrics::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let metrics = Arc::clone(&metrics);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || serve(listener, &metrics, &stop))
+        };
+        Ok(Self {
+            metrics,
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server listens on (with the actual port when bound to port 0)
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Change the metrics under the lock (a poisoned lock is taken over)
+    pub fn update(&self, change: impl FnOnce(&mut SessionMetrics)) {
+        change(&mut self.metrics.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Answer connections one at a time until `stop` is set
+fn serve(listener: TcpListener, metrics: &Mutex<SessionMetrics>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = handle(stream, metrics);
+            }
+            Err(_) => thread::sleep(ACCEPT_POLL),
+        }
+    }
+}
+
+/// Read one request from `stream` and write the response
+fn handle(mut stream: TcpStream, metrics: &Mutex<SessionMetrics>) -> io::Result<()> {
+    // Accepted sockets can inherit the listener's non-blocking mode
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers; no route needs them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let (status, content_type, body) = {
+        let metrics = metrics.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        respond_to(&request_line, &metrics)
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn renders_counters_and_a_cumulative_histogram() {
+        let mut metrics = SessionMetrics::default();
+        metrics.observe(&SessionEvent::StoryPassed { story_id: "US-001".to_string() });
+        metrics.observe(&SessionEvent::CriterionPassed { story_id: "US-001".to_string(), criterion: 1, description: String::new() });
+        metrics.observe(&SessionEvent::ErrorLine { line: "error[E0308]".to_string() });
+        metrics.observe(&SessionEvent::IterationStart { iteration: 1 });
+        metrics.observe_iteration(Duration::from_secs(45));
+        metrics.observe_iteration(Duration::from_secs(90));
+        metrics.observe_iteration(Duration::from_secs(5000));
+
+        let text = metrics.render();
+        for line in [
+            "ralph_iterations_total 3",
+            "ralph_stories_completed 1",
+            "ralph_criteria_completed 1",
+            "ralph_agent_errors_total 1",
+            "# TYPE ralph_iteration_duration_seconds histogram",
+            "ralph_iteration_duration_seconds_bucket{le=\"30\"} 0",
+            "ralph_iteration_duration_seconds_bucket{le=\"60\"} 1",
+            "ralph_iteration_duration_seconds_bucket{le=\"120\"} 2",
+            "ralph_iteration_duration_seconds_bucket{le=\"3600\"} 2",
+            "ralph_iteration_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "ralph_iteration_duration_seconds_sum 5135",
+            "ralph_iteration_duration_seconds_count 3",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {:?} in:\n{}", line, text);
+        }
+    }
+
+    #[test]
+    fn routes_requests() {
+        let metrics = SessionMetrics::default();
+        assert_eq!(respond_to("GET /metrics HTTP/1.1", &metrics).0, "200 OK");
+        assert_eq!(respond_to("GET /metrics?x=1 HTTP/1.1", &metrics).0, "200 OK");
+        assert_eq!(respond_to("GET /status HTTP/1.1", &metrics).0, "503 Service Unavailable");
+        assert_eq!(respond_to("GET / HTTP/1.1", &metrics).0, "404 Not Found");
+        assert_eq!(respond_to("POST /metrics HTTP/1.1", &metrics).0, "405 Method Not Allowed");
+        assert_eq!(respond_to("", &metrics).0, "400 Bad Request");
+    }
+
+    #[test]
+    fn bind_address_defaults_to_localhost() {
+        assert_eq!(parse_bind_addr("9184"), Ok("127.0.0.1:9184".parse().unwrap()));
+        assert_eq!(parse_bind_addr("0.0.0.0:9184"), Ok("0.0.0.0:9184".parse().unwrap()));
+        assert!(parse_bind_addr("localhost").is_err());
+    }
+
+    #[test]
+    fn serves_updates_and_stops_when_dropped() {
+        let server = MetricsServer::bind(parse_bind_addr("0").unwrap()).unwrap();
+        let addr = server.local_addr();
+        assert!(addr.ip().is_loopback());
+        server.update(|metrics| metrics.observe_iteration(Duration::from_secs(1)));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("\nralph_iterations_total 1\n"), "{}", response);
+
+        drop(server);
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
@@ -0,0 +1,791 @@
+//! PRD (prd.json) model and progress.txt helpers
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Acceptance criterion - supports both v1.0 (string) and v2.0 (object) schemas
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptanceCriterion {
+    /// Optional criterion id (v2.0 only, e.g. "AC-3")
+    pub id: Option<String>,
+    pub description: String,
+    pub passes: bool,
+    /// What the agent recorded when marking it passed (v2.0 only, e.g. a path or command output)
+    pub evidence: Option<String>,
+    /// When the agent verified it (v2.0 only, as written by the agent)
+    pub verified_at: Option<String>,
+    /// Shell command ralph-tui can run to check it (v2.0 only, see verify.rs)
+    pub verify: Option<String>,
+}
+
+// Custom deserializer to handle both string (v1.0) and object (v2.0) formats
+impl<'de> serde::Deserialize<'de> for AcceptanceCriterion {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::{self, MapAccess, Visitor};
+
+        struct AcceptanceCriterionVisitor;
+
+        impl<'de> Visitor<'de> for AcceptanceCriterionVisitor {
+            type Value = AcceptanceCriterion;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a string or an object with description, passes, and optional id, evidence, verifiedAt and verify fields")
+            }
+
+            // v1.0 schema: plain string (treated as passes: false)
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(AcceptanceCriterion {
+                    id: None,
+                    description: value.to_string(),
+                    passes: false,
+                    evidence: None,
+                    verified_at: None,
+                    verify: None,
+                })
+            }
+
+            // v2.0 schema: object with description, passes, and optional id, evidence, verifiedAt and verify
+            fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+            where
+                M: MapAccess<'de>,
+            {
+                let mut id: Option<String> = None;
+                let mut description: Option<String> = None;
+                let mut passes: Option<bool> = None;
+                let mut evidence: Option<String> = None;
+                let mut verified_at: Option<String> = None;
+                let mut verify: Option<String> = None;
+
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "id" => {
+                            id = map.next_value()?;
+                        }
+                        "description" => {
+                            description = Some(map.next_value()?);
+                        }
+                        "passes" => {
+                            passes = Some(map.next_value()?);
+                        }
+                        "evidence" => {
+                            evidence = map.next_value()?;
+                        }
+                        "verifiedAt" => {
+                            verified_at = map.next_value()?;
+                        }
+                        "verify" => {
+                            verify = map.next_value()?;
+                        }
+                        _ => {
+                            let _: serde::de::IgnoredAny = map.next_value()?;
+                        }
+                    }
+                }
+
+                Ok(AcceptanceCriterion {
+                    id,
+                    description: description.unwrap_or_default(),
+                    passes: passes.unwrap_or(false),
+                    // Agents sometimes write "" rather than leaving the field out
+                    evidence: evidence.filter(|e| !e.trim().is_empty()),
+                    verified_at: verified_at.filter(|v| !v.trim().is_empty()),
+                    verify: verify.filter(|v| !v.trim().is_empty()),
+                })
+            }
+        }
+
+        deserializer.deserialize_any(AcceptanceCriterionVisitor)
+    }
+}
+
+impl AcceptanceCriterion {
+    /// How to refer to the criterion at `index`: its v2.0 id, else its 1-based number
+    pub fn label(&self, index: usize) -> String {
+        self.id.clone().unwrap_or_else(|| (index + 1).to_string())
+    }
+}
+
+/// PRD user story
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserStory {
+    pub id: String,
+    pub title: String,
+    #[allow(dead_code)]
+    pub description: String,
+    #[allow(dead_code)]
+    pub acceptance_criteria: Vec<AcceptanceCriterion>,
+    pub priority: u32,
+    pub passes: bool,
+    #[allow(dead_code)]
+    pub notes: String,
+    /// Lane for --parallel mode (stories sharing a lane go to the same agent)
+    #[serde(default)]
+    pub lane: Option<String>,
+    /// Who works on the story: "agent" (the default), "human" or a person's name
+    #[serde(default)]
+    pub assignee: Option<String>,
+    /// Size of the story, in the PRD's estimateUnit (story points unless it says minutes)
+    #[serde(default)]
+    pub estimate: Option<f64>,
+}
+
+impl UserStory {
+    /// Whether a person, not the agent, is assigned the story
+    pub fn is_human(&self) -> bool {
+        self.assignee.as_deref().is_some_and(|a| !a.eq_ignore_ascii_case("agent"))
+    }
+}
+
+/// What the stories' `estimate` counts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EstimateUnit {
+    #[default]
+    Points,
+    Minutes,
+}
+
+impl EstimateUnit {
+    pub fn label(self) -> &'static str {
+        match self {
+            EstimateUnit::Points => "pt",
+            EstimateUnit::Minutes => "min",
+        }
+    }
+
+    /// "3 pt", "2.5 pt", "90 min"
+    pub fn format(self, value: f64) -> String {
+        if value.fract() == 0.0 {
+            format!("{} {}", value, self.label())
+        } else {
+            format!("{:.1} {}", value, self.label())
+        }
+    }
+}
+
+/// Remaining estimate against the session's time, for the status panel's burndown line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Burndown {
+    pub remaining: f64,
+    pub total: f64,
+    pub unit: EstimateUnit,
+    /// Estimate completed per hour this session (0 until a story with an estimate passes)
+    pub rate_per_hour: f64,
+    /// Unix time (seconds) the remaining estimate is done at that rate
+    pub projected_finish: Option<u64>,
+}
+
+impl Burndown {
+    /// Burndown of `prd`, from what was left when the session started and how long it has run
+    /// None when no story has an estimate.
+    pub fn new(prd: &Prd, remaining_at_start: f64, elapsed: Duration, now: u64) -> Option<Self> {
+        if !prd.has_estimates() {
+            return None;
+        }
+        let remaining = prd.remaining_estimate();
+        let burned = (remaining_at_start - remaining).max(0.0);
+        let hours = elapsed.as_secs_f64() / 3600.0;
+        let rate_per_hour = if hours > 0.0 { burned / hours } else { 0.0 };
+        let projected_finish = (rate_per_hour > 0.0 && remaining > 0.0)
+            .then(|| now + (remaining / rate_per_hour * 3600.0).round() as u64);
+        Some(Burndown { remaining, total: prd.total_estimate(), unit: prd.estimate_unit, rate_per_hour, projected_finish })
+    }
+}
+
+/// Default schema version for backwards compatibility
+fn default_schema_version() -> String {
+    "1.0".to_string()
+}
+
+/// Newest schema version this ralph-tui understands (major, minor)
+/// The /ralph skill writes 2.1 (phases and decision gates on top of 2.0).
+pub const SUPPORTED_SCHEMA: (u32, u32) = (2, 1);
+
+/// How a PRD's schemaVersion relates to what ralph-tui understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaCompat {
+    Supported,
+    Newer,        // A later schema: fields it adds are ignored
+    Unrecognized, // Not a "major.minor" version number
+}
+
+/// Check a schemaVersion string ("2", "2.0" and "2.0.1" all parse)
+pub fn schema_compatibility(version: &str) -> SchemaCompat {
+    let mut parts = version.trim().split('.');
+    let major = parts.next().and_then(|p| p.parse::<u32>().ok());
+    let minor = match parts.next() {
+        None => Some(0),
+        Some(p) => p.parse::<u32>().ok(),
+    };
+    let patch_ok = parts.all(|p| p.parse::<u32>().is_ok());
+    match (major, minor) {
+        (Some(major), Some(minor)) if patch_ok => {
+            if (major, minor) > SUPPORTED_SCHEMA {
+                SchemaCompat::Newer
+            } else {
+                SchemaCompat::Supported
+            }
+        }
+        _ => SchemaCompat::Unrecognized,
+    }
+}
+
+/// PRD document structure
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Prd {
+    /// Schema version for format compatibility (default: "1.0")
+    #[serde(default = "default_schema_version")]
+    pub schema_version: String,
+    pub project: String,
+    #[allow(dead_code)]
+    pub task_dir: String,
+    /// Branch name for this effort (null = don't create branch, work in existing repos)
+    #[serde(default)]
+    pub branch_name: Option<String>,
+    /// Target branch to merge into when complete (null = no merge)
+    #[serde(default)]
+    pub merge_target: Option<String>,
+    /// Whether to auto-merge on completion (default: false)
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub auto_merge: bool,
+    #[serde(rename = "type")]
+    pub prd_type: String,
+    pub description: String,
+    pub user_stories: Vec<UserStory>,
+    /// Extra environment variables for the agent, applied on every iteration
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Extra arguments for the agent command line (shell-split, e.g. "--permission-mode plan")
+    #[serde(default)]
+    pub agent_args: Option<String>,
+    /// Claude model for the agent (passed as --model, e.g. "sonnet")
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Agent profile to launch (e.g. "claude", "aider")
+    #[serde(default)]
+    pub agent: Option<String>,
+    /// Task-specific default for --iterations
+    #[serde(default)]
+    pub default_iterations: Option<u32>,
+    /// Task-specific default for --rotate-at
+    #[serde(default)]
+    pub rotate_threshold: Option<u32>,
+    /// Whether the loop also waits for human-assigned stories before completing
+    #[serde(default)]
+    pub wait_for_humans: bool,
+    /// Commit prd.json right after each edit made from the TUI (restore, undo)
+    #[serde(default)]
+    pub auto_commit_tui_edits: bool,
+    /// Tag the tree's state as each iteration starts so a bad iteration can be rolled back
+    #[serde(default)]
+    pub snapshot_each_iteration: bool,
+    /// Shell command run after each iteration (see hooks.rs for placeholders and variables)
+    #[serde(default)]
+    pub on_iteration_end: Option<String>,
+    /// Shell command run when every story passes
+    #[serde(default)]
+    pub on_complete: Option<String>,
+    /// Seconds a hook may run before it is killed (default 30)
+    #[serde(default)]
+    pub hook_timeout_secs: Option<u64>,
+    /// Directories outside the repository the agent may write to (relative to the repository root)
+    #[serde(default)]
+    pub allowed_paths: Vec<String>,
+    /// Hold the loop at the next iteration boundary after a write outside the repository
+    #[serde(default)]
+    pub pause_on_out_of_scope: bool,
+    /// Start each iteration in the previous iteration's agent conversation instead of a fresh one
+    #[serde(default)]
+    pub continue_conversation: bool,
+    /// Task directory to start once every story passes (next to this one, e.g. "frontend")
+    #[serde(default)]
+    pub next_task: Option<String>,
+    /// What story estimates count: "points" (the default) or "minutes"
+    #[serde(default)]
+    pub estimate_unit: EstimateUnit,
+    /// Stories this run is limited to (--stories / Space in the TUI; empty = all)
+    /// Kept in ralph-tui's session state, never read from or written to prd.json
+    #[serde(skip)]
+    pub targeted_stories: BTreeSet<String>,
+    /// Stories skipped for this session from the TUI's quick actions menu (never saved)
+    #[serde(skip)]
+    pub skipped_stories: BTreeSet<String>,
+}
+
+impl Prd {
+    /// Warning shown when the schema version is newer than (or not) one ralph-tui knows
+    pub fn schema_warning(&self) -> Option<String> {
+        match schema_compatibility(&self.schema_version) {
+            SchemaCompat::Supported => None,
+            SchemaCompat::Newer => Some(format!(
+                "PRD schema {} is newer than this ralph-tui — some fields may be ignored",
+                self.schema_version
+            )),
+            SchemaCompat::Unrecognized => Some(format!(
+                "PRD schema \"{}\" is not a version this ralph-tui knows — some fields may be ignored",
+                self.schema_version
+            )),
+        }
+    }
+
+    /// Load PRD from a JSON file
+    pub fn load(path: &PathBuf) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Whether the story counts for this run (it is targeted, or nothing is)
+    pub fn is_counted(&self, story: &UserStory) -> bool {
+        self.targeted_stories.is_empty() || self.targeted_stories.contains(&story.id)
+    }
+
+    /// Stories that count for this run
+    fn counted_stories(&self) -> impl Iterator<Item = &UserStory> {
+        self.user_stories.iter().filter(|s| self.is_counted(s))
+    }
+
+    /// Count stories that count for this run
+    pub fn story_total(&self) -> usize {
+        self.counted_stories().count()
+    }
+
+    /// Count completed stories
+    pub fn completed_count(&self) -> usize {
+        self.counted_stories().filter(|s| s.passes).count()
+    }
+
+    /// Check if all stories pass (project complete, or the targeted subset is)
+    /// Human-assigned stories only hold the loop open with `waitForHumans`.
+    pub fn all_stories_pass(&self) -> bool {
+        self.story_total() > 0
+            && self
+                .counted_stories()
+                .filter(|s| self.wait_for_humans || !s.is_human())
+                .all(|s| s.passes)
+    }
+
+    /// (completed, total) counted stories assigned to the agent
+    pub fn agent_progress(&self) -> (usize, usize) {
+        Self::progress(self.counted_stories().filter(|s| !s.is_human()))
+    }
+
+    /// (completed, total) counted stories assigned to people
+    pub fn human_progress(&self) -> (usize, usize) {
+        Self::progress(self.counted_stories().filter(|s| s.is_human()))
+    }
+
+    fn progress<'a>(stories: impl Iterator<Item = &'a UserStory>) -> (usize, usize) {
+        stories.fold((0, 0), |(done, total), s| (done + s.passes as usize, total + 1))
+    }
+
+    /// Get stories sorted by priority (the order used by the story list and detail views)
+    pub fn stories_by_priority(&self) -> Vec<&UserStory> {
+        let mut stories: Vec<_> = self.user_stories.iter().collect();
+        stories.sort_by_key(|s| s.priority);
+        stories
+    }
+
+    /// Whether the story was skipped for this session
+    pub fn is_skipped(&self, story: &UserStory) -> bool {
+        self.skipped_stories.contains(&story.id)
+    }
+
+    /// Get current story (first counted agent story with passes: false, sorted by priority)
+    /// Stories skipped for this session are passed over.
+    pub fn current_story(&self) -> Option<&UserStory> {
+        self.counted_stories()
+            .filter(|s| !s.passes && !s.is_human() && !self.is_skipped(s))
+            .min_by_key(|s| s.priority)
+    }
+
+    /// Split the incomplete counted stories into `lanes` disjoint sets (--parallel mode)
+    /// Stories sharing a `lane` field stay together, with named lanes dealt out in the
+    /// priority order of their first story; the rest go one at a time to the lane with
+    /// the fewest stories. Lanes come back empty when there isn't enough work.
+    pub fn partition_lanes(&self, lanes: usize) -> Vec<BTreeSet<String>> {
+        let mut partition = vec![BTreeSet::new(); lanes];
+        if lanes == 0 {
+            return partition;
+        }
+        let mut pending: Vec<&UserStory> = self
+            .counted_stories()
+            .filter(|s| !s.passes && !s.is_human() && !self.is_skipped(s))
+            .collect();
+        pending.sort_by_key(|s| s.priority);
+
+        let mut named: Vec<&str> = Vec::new();
+        for story in &pending {
+            let Some(lane) = story.lane.as_deref() else {
+                continue;
+            };
+            let index = named.iter().position(|n| *n == lane).unwrap_or_else(|| {
+                named.push(lane);
+                named.len() - 1
+            });
+            partition[index % lanes].insert(story.id.clone());
+        }
+        for story in pending.iter().filter(|s| s.lane.is_none()) {
+            let smallest = (0..lanes).min_by_key(|&i| partition[i].len()).unwrap_or(0);
+            partition[smallest].insert(story.id.clone());
+        }
+        partition
+    }
+
+    /// Whether any counted story has an estimate (without one the burndown stays hidden)
+    pub fn has_estimates(&self) -> bool {
+        self.counted_stories().any(|s| s.estimate.is_some())
+    }
+
+    /// Estimate of the counted stories, in total
+    pub fn total_estimate(&self) -> f64 {
+        self.counted_stories().filter_map(|s| s.estimate).sum()
+    }
+
+    /// Estimate of the counted stories that don't pass yet
+    pub fn remaining_estimate(&self) -> f64 {
+        self.counted_stories().filter(|s| !s.passes).filter_map(|s| s.estimate).sum()
+    }
+
+    /// Calculate progress as percentage based on per-criteria completion
+    /// This gives more granular progress than story-level tracking
+    #[allow(dead_code)]
+    pub fn criteria_progress(&self) -> f64 {
+        let total: usize = self.user_stories.iter()
+            .map(|s| s.acceptance_criteria.len())
+            .sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let passed: usize = self.user_stories.iter()
+            .flat_map(|s| &s.acceptance_criteria)
+            .filter(|c| c.passes)
+            .count();
+        (passed as f64 / total as f64) * 100.0
+    }
+}
+
+/// A PRD's priority order, current story and criteria counts, computed once per
+/// load rather than on every frame (the story list redraws many times a second)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StoryIndex {
+    /// Indices into `user_stories` in priority order (as `stories_by_priority`)
+    pub order: Vec<usize>,
+    /// Position in `order` of the current story
+    pub current: Option<usize>,
+    /// (passed, total) acceptance criteria of each story, in priority order
+    pub criteria: Vec<(usize, usize)>,
+}
+
+impl StoryIndex {
+    pub fn new(prd: &Prd) -> Self {
+        let mut order: Vec<usize> = (0..prd.user_stories.len()).collect();
+        order.sort_by_key(|&i| prd.user_stories[i].priority);
+        let current = prd
+            .current_story()
+            .and_then(|current| order.iter().position(|&i| std::ptr::eq(&prd.user_stories[i], current)));
+        let criteria = order
+            .iter()
+            .map(|&i| {
+                let criteria = &prd.user_stories[i].acceptance_criteria;
+                (criteria.iter().filter(|c| c.passes).count(), criteria.len())
+            })
+            .collect();
+        Self { order, current, criteria }
+    }
+
+    /// Whether the index was built for a PRD with `prd`'s stories
+    pub fn covers(&self, prd: &Prd) -> bool {
+        self.order.len() == prd.user_stories.len()
+    }
+
+    /// Stories in priority order
+    pub fn stories<'a>(&self, prd: &'a Prd) -> Vec<&'a UserStory> {
+        self.order.iter().map(|&i| &prd.user_stories[i]).collect()
+    }
+
+    /// Story at `position` in priority order
+    pub fn story<'a>(&self, prd: &'a Prd, position: usize) -> Option<&'a UserStory> {
+        self.order.get(position).map(|&i| &prd.user_stories[i])
+    }
+}
+
+/// Lines of the progress.txt sections whose "##" header mentions `story_id`
+/// Returns None when no section mentions the story.
+pub fn story_progress_lines<'a>(content: &'a str, story_id: &str) -> Option<Vec<&'a str>> {
+    let mut lines = Vec::new();
+    let mut in_matching_section = false;
+    let mut found_any = false;
+    for line in content.lines() {
+        if line.contains(story_id) && line.starts_with("##") {
+            in_matching_section = true;
+            found_any = true;
+            continue; // Skip the header line itself
+        } else if line.starts_with("##") || line.starts_with("---") {
+            in_matching_section = false;
+        }
+
+        if in_matching_section && !line.is_empty() {
+            lines.push(line);
+        }
+    }
+    found_any.then_some(lines)
+}
+
+/// progress.txt's length in lines, as shown in the status panel and checked against the
+/// rotation threshold (trailing blank lines don't count)
+pub fn progress_line_count(content: &str) -> usize {
+    content.trim_end().lines().count()
+}
+
+/// An unresolved `BLOCKER:` or `QUESTION:` line in progress.txt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blocker {
+    pub label: &'static str, // "BLOCKER" or "QUESTION"
+    pub text: String,
+    /// Line index in progress.txt (0-based)
+    pub line: usize,
+}
+
+/// Open blockers and questions the agent wrote to progress.txt
+/// A line starting with `BLOCKER:` / `QUESTION:` (optionally as a `- ` list item) opens one;
+/// a later line containing `RESOLVED:<same text>` (with or without the label) closes it.
+pub fn open_blockers(progress: &str) -> Vec<Blocker> {
+    let lines: Vec<&str> = progress.lines().collect();
+    let mut blockers = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start().trim_start_matches(['-', '*']).trim_start();
+        let Some((label, text)) = ["BLOCKER", "QUESTION"]
+            .into_iter()
+            .find_map(|label| Some((label, trimmed.strip_prefix(label)?.strip_prefix(':')?.trim())))
+        else {
+            continue;
+        };
+        if text.is_empty() {
+            continue;
+        }
+        let full = format!("{}: {}", label, text);
+        let resolved = lines[index + 1..].iter().any(|later| {
+            later.match_indices("RESOLVED:").any(|(pos, marker)| {
+                let rest = later[pos + marker.len()..].trim();
+                rest == text || rest == full
+            })
+        });
+        if !resolved {
+            blockers.push(Blocker { label, text: text.to_string(), line: index });
+        }
+    }
+    blockers
+}
+
+/// Find the criterion the agent is currently "on" from progress.txt
+/// Looks for the most recent "working on <id>" line naming one of the given criteria ids.
+pub fn find_working_criterion(progress: &str, criteria: &[AcceptanceCriterion]) -> Option<String> {
+    const MARKER: &str = "working on ";
+
+    for line in progress.lines().rev() {
+        let line_lower = line.to_lowercase();
+        let Some(pos) = line_lower.find(MARKER) else {
+            continue;
+        };
+        // Use get() to safely handle potential UTF-8 boundary issues
+        let Some(after) = line.get(pos + MARKER.len()..) else {
+            continue;
+        };
+        let token = after
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .trim_matches(|c: char| !c.is_alphanumeric());
+        if let Some(id) = criteria
+            .iter()
+            .filter_map(|c| c.id.as_deref())
+            .find(|id| id.eq_ignore_ascii_case(token))
+        {
+            return Some(id.to_string());
+        }
+    }
+
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_versions_are_checked_against_the_supported_one() {
+        assert_eq!(schema_compatibility("1.0"), SchemaCompat::Supported);
+        assert_eq!(schema_compatibility("2.0"), SchemaCompat::Supported);
+        assert_eq!(schema_compatibility("2.1"), SchemaCompat::Supported);
+        assert_eq!(schema_compatibility(" 2 "), SchemaCompat::Supported);
+        assert_eq!(schema_compatibility("2.5"), SchemaCompat::Newer);
+        assert_eq!(schema_compatibility("10.0"), SchemaCompat::Newer);
+        assert_eq!(schema_compatibility("2.1.3"), SchemaCompat::Supported);
+        for garbage in ["", "latest", "v2.0", "2.x", "2..0", "-1.0", "2.0-beta"] {
+            assert_eq!(schema_compatibility(garbage), SchemaCompat::Unrecognized, "{:?}", garbage);
+        }
+    }
+
+    #[test]
+    fn burndown_projects_the_finish_from_this_sessions_rate() {
+        let mut prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": [
+                {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": true, "notes": "", "estimate": 3},
+                {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": false, "notes": "", "estimate": 5.5},
+                {"id": "US-3", "title": "", "description": "", "acceptanceCriteria": [], "priority": 3, "passes": false, "notes": ""}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!((prd.total_estimate(), prd.remaining_estimate()), (8.5, 5.5));
+        assert_eq!(prd.estimate_unit.format(prd.remaining_estimate()), "5.5 pt");
+
+        // 3 points in the first half hour: 6 per hour, so 5.5 more take 55 minutes
+        let burndown = Burndown::new(&prd, 8.5, Duration::from_secs(1800), 1_000_000).unwrap();
+        assert_eq!(burndown.rate_per_hour, 6.0);
+        assert_eq!(burndown.projected_finish, Some(1_000_000 + 55 * 60));
+        // Nothing burned yet: no projection
+        assert_eq!(Burndown::new(&prd, 5.5, Duration::from_secs(1800), 0).unwrap().projected_finish, None);
+
+        prd.targeted_stories.insert("US-3".to_string());
+        assert!(Burndown::new(&prd, 0.0, Duration::ZERO, 0).is_none());
+    }
+
+    #[test]
+    fn open_blockers_drop_resolved_ones() {
+        let progress = "## US-001\n\
+            BLOCKER: need staging API key\n\
+            - QUESTION: keep the legacy endpoint?\n\
+            notes mention BLOCKER: mid-line, ignored\n\
+            BLOCKER: flaky CI runner\n\
+            RESOLVED: need staging API key\n\
+            RESOLVED: BLOCKER: flaky CI runner\n";
+        assert_eq!(
+            open_blockers(progress),
+            vec![Blocker { label: "QUESTION", text: "keep the legacy endpoint?".to_string(), line: 2 }]
+        );
+        // Resolutions only count after the blocker
+        assert_eq!(open_blockers("RESOLVED: x\nBLOCKER: x\n").len(), 1);
+    }
+
+    #[test]
+    fn criteria_keep_evidence_and_ignore_unknown_fields() {
+        let criteria: Vec<AcceptanceCriterion> = serde_json::from_str(
+            r#"["plain v1.0 criterion",
+                {"id": "AC-1", "description": "Login works", "passes": true,
+                 "evidence": "cargo test login: 4 passed", "verifiedAt": "2026-10-16T09:30:00Z", "reviewer": "bot"},
+                {"id": "AC-2", "description": "Logout works", "passes": false, "evidence": " ", "confidence": 0.4}]"#,
+        )
+        .unwrap();
+        assert_eq!(criteria[0].evidence, None);
+        assert_eq!(criteria[1].evidence.as_deref(), Some("cargo test login: 4 passed"));
+        assert_eq!(criteria[1].verified_at.as_deref(), Some("2026-10-16T09:30:00Z"));
+        // Blank evidence counts as none
+        assert_eq!((criteria[2].evidence.clone(), criteria[2].verified_at.clone()), (None, None));
+        assert!(!criteria[2].passes);
+    }
+
+    #[test]
+    fn partitions_incomplete_stories_into_lanes() {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [
+                    {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": false, "notes": "", "lane": "backend"},
+                    {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": false, "notes": "", "lane": "frontend"},
+                    {"id": "US-3", "title": "", "description": "", "acceptanceCriteria": [], "priority": 3, "passes": false, "notes": "", "lane": "backend"},
+                    {"id": "US-4", "title": "", "description": "", "acceptanceCriteria": [], "priority": 4, "passes": false, "notes": ""},
+                    {"id": "US-5", "title": "", "description": "", "acceptanceCriteria": [], "priority": 5, "passes": true, "notes": ""},
+                    {"id": "US-6", "title": "", "description": "", "acceptanceCriteria": [], "priority": 6, "passes": false, "notes": ""}
+                ]}"#,
+        )
+        .unwrap();
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<BTreeSet<_>>();
+
+        // Named lanes stay together; unlaned stories balance the lanes; passing stories are skipped
+        assert_eq!(prd.partition_lanes(2), vec![ids(&["US-1", "US-3", "US-6"]), ids(&["US-2", "US-4"])]);
+        // More named lanes than agents wrap around
+        assert_eq!(prd.partition_lanes(1), vec![ids(&["US-1", "US-2", "US-3", "US-4", "US-6"])]);
+
+        let mut targeted = prd.clone();
+        targeted.targeted_stories = ids(&["US-1", "US-3"]);
+        assert_eq!(targeted.partition_lanes(2), vec![ids(&["US-1", "US-3"]), BTreeSet::new()]);
+    }
+
+    #[test]
+    fn empty_and_finished_prds() {
+        let mut prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": []}"#,
+        )
+        .unwrap();
+        // An empty PRD is never complete, and has no current story
+        assert!(!prd.all_stories_pass());
+        assert!(prd.current_story().is_none());
+        assert_eq!(prd.story_total(), 0);
+
+        prd.user_stories = serde_json::from_str(
+            r#"[{"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": true, "notes": ""}]"#,
+        )
+        .unwrap();
+        assert!(prd.all_stories_pass());
+        assert!(prd.current_story().is_none());
+    }
+
+    #[test]
+    fn human_stories_are_skipped_by_the_loop() {
+        let mut prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [
+                    {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": false, "notes": "", "assignee": "alice"},
+                    {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": true, "notes": "", "assignee": "agent"},
+                    {"id": "US-3", "title": "", "description": "", "acceptanceCriteria": [], "priority": 3, "passes": false, "notes": ""},
+                    {"id": "US-4", "title": "", "description": "", "acceptanceCriteria": [], "priority": 4, "passes": true, "notes": "", "assignee": "human"}
+                ]}"#,
+        )
+        .unwrap();
+        assert_eq!(prd.current_story().map(|s| s.id.as_str()), Some("US-3"));
+        assert_eq!(prd.agent_progress(), (1, 2));
+        assert_eq!(prd.human_progress(), (1, 2));
+        assert_eq!(prd.partition_lanes(1), vec![BTreeSet::from(["US-3".to_string()])]);
+
+        prd.user_stories[2].passes = true;
+        assert!(prd.current_story().is_none());
+        assert!(prd.all_stories_pass());
+        // waitForHumans keeps the loop open until the people finish too
+        prd.wait_for_humans = true;
+        assert!(!prd.all_stories_pass());
+    }
+
+    #[test]
+    fn story_index_matches_priority_order() {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "",
+                "userStories": [
+                    {"id": "US-3", "title": "", "description": "", "acceptanceCriteria": [], "priority": 3, "passes": false, "notes": ""},
+                    {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [{"description": "a", "passes": true}, "b"], "priority": 1, "passes": true, "notes": ""},
+                    {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": ["c"], "priority": 2, "passes": false, "notes": ""}
+                ]}"#,
+        )
+        .unwrap();
+        let index = StoryIndex::new(&prd);
+        let ids = |stories: Vec<&UserStory>| stories.iter().map(|s| s.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(index.stories(&prd)), ids(prd.stories_by_priority()));
+        assert_eq!(index.current, Some(1));
+        assert_eq!(index.story(&prd, 1).map(|s| s.id.as_str()), Some("US-2"));
+        assert_eq!(index.criteria, vec![(1, 2), (0, 1), (0, 0)]);
+        assert!(index.covers(&prd));
+        assert!(!StoryIndex::default().covers(&prd));
+    }
+}
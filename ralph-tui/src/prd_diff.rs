@@ -0,0 +1,410 @@
+//! What this session changed in prd.json
+//!
+//! The App keeps a copy of the PRD as it was when the session started. Diffing
+//! it against the current PRD pairs stories by id (so reordering them is not a
+//! change) and criteria by their v2.0 id or, failing that, their position. The
+//! result feeds the `D` view, the summary printed on exit, report.json and the
+//! iteration log.
+//!
+//! Between two consecutive loads, `churn` looks for the agent rewriting the
+//! stories themselves: stories added or removed, criteria dropped, or the text
+//! of a story's unpassed criteria changed to make them easier to pass.
+
+use std::io::Write;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::app::ITERATION_LOG_FILE;
+use crate::prd::{AcceptanceCriterion, Prd, UserStory};
+
+/// One criterion's mark at session start and now (None: not in that PRD)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CriterionDiff {
+    pub label: String,
+    pub before: Option<bool>,
+    pub after: Option<bool>,
+}
+
+impl CriterionDiff {
+    pub fn changed(&self) -> bool {
+        self.before != self.after
+    }
+
+    /// Was passing and no longer is
+    pub fn regressed(&self) -> bool {
+        self.before == Some(true) && self.after != Some(true)
+    }
+}
+
+/// How a story moved between session start and now
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoryChange {
+    Added,
+    Removed,
+    /// Started passing
+    Completed,
+    /// Passed at start and no longer does, or lost a passing criterion
+    Regressed,
+    /// Criteria changed without the story's status flipping
+    Progressed,
+    Unchanged,
+}
+
+/// One story at session start and now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoryDiff {
+    pub id: String,
+    pub title: String,
+    /// Whether the story passed (None: not in that PRD)
+    pub before: Option<bool>,
+    pub after: Option<bool>,
+    /// Position in userStories at start and now
+    pub position: (Option<usize>, Option<usize>),
+    pub criteria: Vec<CriterionDiff>,
+}
+
+impl StoryDiff {
+    pub fn change(&self) -> StoryChange {
+        match (self.before, self.after) {
+            (None, _) => StoryChange::Added,
+            (_, None) => StoryChange::Removed,
+            (Some(false), Some(true)) => StoryChange::Completed,
+            (Some(true), Some(false)) => StoryChange::Regressed,
+            _ if self.criteria.iter().any(CriterionDiff::regressed) => StoryChange::Regressed,
+            _ if self.criteria.iter().any(CriterionDiff::changed) => StoryChange::Progressed,
+            _ => StoryChange::Unchanged,
+        }
+    }
+
+    /// Moved within userStories (only meaningful for stories in both PRDs)
+    pub fn moved(&self) -> bool {
+        matches!(self.position, (Some(before), Some(after)) if before != after)
+    }
+
+    /// (passed, total) criteria at start and now
+    pub fn criteria_counts(&self) -> ((usize, usize), (usize, usize)) {
+        let count = |mark: fn(&CriterionDiff) -> Option<bool>| {
+            let marks: Vec<bool> = self.criteria.iter().filter_map(mark).collect();
+            (marks.iter().filter(|&&passes| passes).count(), marks.len())
+        };
+        (count(|c| c.before), count(|c| c.after))
+    }
+
+    /// One line for the exit summary and the iteration log ("US-4 Title: open → passing (criteria 1/3 → 3/3)")
+    pub fn summary(&self) -> String {
+        let status = |passes: Option<bool>| match passes {
+            None => "absent",
+            Some(true) => "passing",
+            Some(false) => "open",
+        };
+        let ((before_passed, before_total), (after_passed, after_total)) = self.criteria_counts();
+        format!(
+            "{} {}: {} → {} (criteria {}/{} → {}/{})",
+            self.id,
+            self.title,
+            status(self.before),
+            status(self.after),
+            before_passed,
+            before_total,
+            after_passed,
+            after_total
+        )
+    }
+}
+
+/// Stories of `now` in order, then the ones removed since `start`
+pub fn diff(start: &Prd, now: &Prd) -> Vec<StoryDiff> {
+    let find = |prd: &Prd, id: &str| prd.user_stories.iter().position(|story| story.id == id);
+    let mut diffs: Vec<StoryDiff> = now
+        .user_stories
+        .iter()
+        .enumerate()
+        .map(|(position, story)| {
+            let before = find(start, &story.id).map(|index| (index, &start.user_stories[index]));
+            story_diff(before, Some((position, story)))
+        })
+        .collect();
+    diffs.extend(
+        start
+            .user_stories
+            .iter()
+            .enumerate()
+            .filter(|(_, story)| find(now, &story.id).is_none())
+            .map(|(position, story)| story_diff(Some((position, story)), None)),
+    );
+    diffs
+}
+
+/// Only the stories that changed (reorders alone don't count)
+pub fn changed(diffs: &[StoryDiff]) -> Vec<&StoryDiff> {
+    diffs.iter().filter(|diff| diff.change() != StoryChange::Unchanged).collect()
+}
+
+/// Append the changed stories to the iteration log ("<ts> story_diff US-4 …"), next to the iterations
+pub fn append_to_iteration_log(task_dir: &Path, diffs: &[StoryDiff]) {
+    let changed = changed(diffs);
+    if changed.is_empty() {
+        return;
+    }
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let lines: String = changed.iter().map(|diff| format!("{} story_diff {}\n", timestamp, diff.summary())).collect();
+    if let Ok(mut file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(task_dir.join(ITERATION_LOG_FILE))
+    {
+        let _ = file.write_all(lines.as_bytes());
+    }
+}
+
+/// What a reload found the agent did to a story's definition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChurnKind {
+    StoryAdded,
+    StoryRemoved,
+    /// Criteria text changed on a story that doesn't pass
+    CriteriaReworded,
+    /// Fewer criteria than before
+    CriteriaReduced,
+}
+
+impl ChurnKind {
+    /// Name used in the iteration log
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChurnKind::StoryAdded => "story_added",
+            ChurnKind::StoryRemoved => "story_removed",
+            ChurnKind::CriteriaReworded => "criteria_reworded",
+            ChurnKind::CriteriaReduced => "criteria_reduced",
+        }
+    }
+}
+
+/// One change to the PRD's stories between two loads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrdChurn {
+    pub story_id: String,
+    pub kind: ChurnKind,
+    /// Could make the story easier to pass: fewer criteria, reworded unpassed ones, or an unpassed story removed
+    pub suspicious: bool,
+}
+
+impl PrdChurn {
+    /// Feed entry ("PRD modified: US-3 criteria reworded")
+    pub fn message(&self) -> String {
+        let what = match self.kind {
+            ChurnKind::StoryAdded => "added",
+            ChurnKind::StoryRemoved => "removed",
+            ChurnKind::CriteriaReworded => "criteria reworded",
+            ChurnKind::CriteriaReduced => "criteria reduced",
+        };
+        format!("PRD modified: {} {}", self.story_id, what)
+    }
+}
+
+/// Classify what changed in the stories' definitions from one PRD load to the next
+/// Status and criteria marks are ignored: passing things is progress, not churn.
+pub fn churn(before: &Prd, after: &Prd) -> Vec<PrdChurn> {
+    let exists = |prd: &Prd, id: &str| prd.user_stories.iter().any(|story| story.id == id);
+    let entry = |story: &UserStory, kind: ChurnKind, suspicious: bool| PrdChurn { story_id: story.id.clone(), kind, suspicious };
+    let mut churn = Vec::new();
+    for story in &after.user_stories {
+        let Some(old) = before.user_stories.iter().find(|old| old.id == story.id) else {
+            churn.push(entry(story, ChurnKind::StoryAdded, false));
+            continue;
+        };
+        if story.acceptance_criteria.len() < old.acceptance_criteria.len() {
+            churn.push(entry(story, ChurnKind::CriteriaReduced, true));
+        }
+        if !story.passes {
+            // Paired by label, like the diff; returns whether an unpassed criterion was reworded
+            let reworded: Vec<bool> = story
+                .acceptance_criteria
+                .iter()
+                .enumerate()
+                .filter_map(|(index, criterion)| {
+                    let label = criterion.label(index);
+                    let (_, previous) =
+                        old.acceptance_criteria.iter().enumerate().find(|(i, c)| c.label(*i) == label)?;
+                    (previous.description.trim() != criterion.description.trim()).then_some(!previous.passes)
+                })
+                .collect();
+            if !reworded.is_empty() {
+                churn.push(entry(story, ChurnKind::CriteriaReworded, reworded.contains(&true)));
+            }
+        }
+    }
+    churn.extend(
+        before
+            .user_stories
+            .iter()
+            .filter(|story| !exists(after, &story.id))
+            .map(|story| entry(story, ChurnKind::StoryRemoved, !story.passes)),
+    );
+    churn
+}
+
+fn story_diff(before: Option<(usize, &UserStory)>, after: Option<(usize, &UserStory)>) -> StoryDiff {
+    let story = after.or(before).map(|(_, story)| story).expect("a story on at least one side");
+    let no_criteria: &[AcceptanceCriterion] = &[];
+    StoryDiff {
+        id: story.id.clone(),
+        title: story.title.clone(),
+        before: before.map(|(_, story)| story.passes),
+        after: after.map(|(_, story)| story.passes),
+        position: (before.map(|(index, _)| index), after.map(|(index, _)| index)),
+        criteria: criteria_diff(
+            before.map_or(no_criteria, |(_, story)| &story.acceptance_criteria),
+            after.map_or(no_criteria, |(_, story)| &story.acceptance_criteria),
+        ),
+    }
+}
+
+/// Pair criteria by label (v2.0 id, else 1-based number): the current ones in order, then removed ones
+fn criteria_diff(before: &[AcceptanceCriterion], after: &[AcceptanceCriterion]) -> Vec<CriterionDiff> {
+    let labelled = |criteria: &[AcceptanceCriterion]| -> Vec<(String, bool)> {
+        criteria.iter().enumerate().map(|(index, c)| (c.label(index), c.passes)).collect()
+    };
+    let (before, after) = (labelled(before), labelled(after));
+    let mark = |criteria: &[(String, bool)], label: &str| {
+        criteria.iter().find(|(other, _)| other == label).map(|&(_, passes)| passes)
+    };
+    let mut diffs: Vec<CriterionDiff> = after
+        .iter()
+        .map(|(label, passes)| CriterionDiff { label: label.clone(), before: mark(&before, label), after: Some(*passes) })
+        .collect();
+    diffs.extend(before.iter().filter(|(label, _)| mark(&after, label).is_none()).map(|(label, passes)| {
+        CriterionDiff { label: label.clone(), before: Some(*passes), after: None }
+    }));
+    diffs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prd(stories: &str) -> Prd {
+        serde_json::from_str(&format!(
+            r#"{{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": [{}]}}"#,
+            stories
+        ))
+        .unwrap()
+    }
+
+    fn story(id: &str, passes: bool, criteria: &str) -> String {
+        format!(
+            r#"{{"id": "{}", "title": "Story {}", "description": "", "acceptanceCriteria": [{}], "priority": 1, "passes": {}, "notes": ""}}"#,
+            id, id, criteria, passes
+        )
+    }
+
+    #[test]
+    fn pairs_stories_by_id_across_additions_removals_and_reorders() {
+        let start = prd(&[
+            story("US-1", false, r#"{"description": "a", "passes": false}, {"description": "b", "passes": true}"#),
+            story("US-2", true, r#"{"description": "c", "passes": true}"#),
+            story("US-3", false, r#""d""#),
+        ]
+        .join(","));
+        let now = prd(&[
+            story("US-2", true, r#"{"description": "c", "passes": true}"#),
+            story("US-1", true, r#"{"description": "a", "passes": true}, {"description": "b", "passes": true}"#),
+            story("US-4", false, r#""e""#),
+        ]
+        .join(","));
+
+        let diffs = diff(&start, &now);
+        let ids: Vec<&str> = diffs.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["US-2", "US-1", "US-4", "US-3"]);
+        let changes: Vec<StoryChange> = diffs.iter().map(StoryDiff::change).collect();
+        assert_eq!(
+            changes,
+            [StoryChange::Unchanged, StoryChange::Completed, StoryChange::Added, StoryChange::Removed]
+        );
+
+        // Reordered without changing: moved, but not listed as a change
+        assert!(diffs[0].moved());
+        assert_eq!(changed(&diffs).len(), 3);
+        assert_eq!(diffs[1].criteria_counts(), ((1, 2), (2, 2)));
+        assert_eq!(diffs[1].summary(), "US-1 Story US-1: open → passing (criteria 1/2 → 2/2)");
+        assert_eq!(diffs[3].summary(), "US-3 Story US-3: open → absent (criteria 0/1 → 0/0)");
+    }
+
+    #[test]
+    fn churn_flags_reductions_and_reworded_open_criteria() {
+        let before = prd(&[
+            story("US-1", false, r#"{"description": "Login works", "passes": false}, {"description": "Logout works", "passes": true}"#),
+            story("US-2", false, r#""Form validates", "Errors show""#),
+            story("US-3", false, r#""Old story""#),
+            story("US-4", true, r#""Done story""#),
+            story("US-5", false, r#"{"description": "Has tests", "passes": true}, {"description": "Docs", "passes": false}"#),
+        ]
+        .join(","));
+        let after = prd(&[
+            // US-1: an open criterion made easier; US-2: one criterion dropped
+            story("US-1", false, r#"{"description": "Login page renders", "passes": false}, {"description": "Logout works", "passes": true}"#),
+            story("US-2", false, r#""Form validates""#),
+            story("US-6", false, r#""New story""#),
+            // US-5: only a passed criterion's wording changed, and a mark flipped
+            story("US-5", false, r#"{"description": "Has unit tests", "passes": true}, {"description": "Docs", "passes": true}"#),
+        ]
+        .join(","));
+
+        let changes = churn(&before, &after);
+        let kinds: Vec<(&str, ChurnKind, bool)> =
+            changes.iter().map(|c| (c.story_id.as_str(), c.kind, c.suspicious)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("US-1", ChurnKind::CriteriaReworded, true),
+                ("US-2", ChurnKind::CriteriaReduced, true),
+                ("US-6", ChurnKind::StoryAdded, false),
+                ("US-5", ChurnKind::CriteriaReworded, false),
+                ("US-3", ChurnKind::StoryRemoved, true),
+                ("US-4", ChurnKind::StoryRemoved, false),
+            ]
+        );
+        assert_eq!(
+            PrdChurn { story_id: "US-3".to_string(), kind: ChurnKind::CriteriaReworded, suspicious: true }.message(),
+            "PRD modified: US-3 criteria reworded"
+        );
+        assert!(churn(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn lost_criteria_are_regressions() {
+        let start = prd(&story(
+            "US-1",
+            false,
+            r#"{"id": "AC-1", "description": "a", "passes": true}, {"id": "AC-2", "description": "b", "passes": false}"#,
+        ));
+        // AC-1 flipped back, AC-2 was removed and AC-3 added (paired by id, not position)
+        let now = prd(&story(
+            "US-1",
+            false,
+            r#"{"id": "AC-3", "description": "c", "passes": true}, {"id": "AC-1", "description": "a", "passes": false}"#,
+        ));
+        let diffs = diff(&start, &now);
+        assert_eq!(diffs[0].change(), StoryChange::Regressed);
+        let criteria: Vec<(&str, Option<bool>, Option<bool>)> =
+            diffs[0].criteria.iter().map(|c| (c.label.as_str(), c.before, c.after)).collect();
+        assert_eq!(
+            criteria,
+            [("AC-3", None, Some(true)), ("AC-1", Some(true), Some(false)), ("AC-2", Some(false), None)]
+        );
+
+        // Only a criterion newly passing: progress without a status flip
+        let now = prd(&story(
+            "US-1",
+            false,
+            r#"{"id": "AC-1", "description": "a", "passes": true}, {"id": "AC-2", "description": "b", "passes": true}"#,
+        ));
+        assert_eq!(diff(&start, &now)[0].change(), StoryChange::Progressed);
+        assert_eq!(diff(&start, &start)[0].change(), StoryChange::Unchanged);
+    }
+}
@@ -0,0 +1,514 @@
+//! Startup preflight checklist
+//!
+//! Before the first agent is spawned, ralph-tui checks what the loop depends on:
+//! the task directory (and that prd.json's `taskDir` agrees with it), prd.json,
+//! the agent binary, the git branch (and the merge target, when the PRD sets
+//! one), the prompt and the size of progress.txt. The results are shown as a
+//! ✓/✗ checklist; a failed check explains itself and may offer a fix. Hard
+//! failures (the loop can't run at all) can only be aborted, soft ones can be
+//! continued past.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::agents::DEFAULT_AGENT;
+use crate::compat::AgentVersion;
+use crate::digest::format_datetime_utc;
+use crate::git;
+use crate::journal::set_prd_value;
+use crate::prd::{Prd, progress_line_count};
+use crate::CliConfig;
+
+/// How long `--version` may take before the version is reported as unknown
+const VERSION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Something ralph-tui can put right itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    RotateProgress,         // Move progress.txt to progress-N.txt and start a summary
+    CheckoutBranch(String), // Check out (or create) the PRD's branchName
+    RewriteTaskDir(String), // Set prd.json's taskDir to the task directory in use
+}
+
+impl Fix {
+    /// What pressing f will do
+    pub fn label(&self) -> String {
+        match self {
+            Fix::RotateProgress => "rotate progress.txt now".to_string(),
+            Fix::CheckoutBranch(branch) => format!("check out {}", branch),
+            Fix::RewriteTaskDir(task_dir) => format!("set prd.json taskDir to {}", task_dir),
+        }
+    }
+}
+
+/// One line of the checklist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub label: &'static str,
+    /// What was found (shown next to the label)
+    pub summary: String,
+    pub passed: bool,
+    /// A failed hard check can't be continued past
+    pub hard: bool,
+    /// Why the check failed (shown under a ✗)
+    pub detail: Option<String>,
+    pub fix: Option<Fix>,
+}
+
+impl Check {
+    fn pass(label: &'static str, summary: impl Into<String>) -> Self {
+        Check { label, summary: summary.into(), passed: true, hard: false, detail: None, fix: None }
+    }
+
+    fn fail(label: &'static str, summary: impl Into<String>, detail: impl Into<String>) -> Self {
+        Check { label, summary: summary.into(), passed: false, hard: false, detail: Some(detail.into()), fix: None }
+    }
+
+    fn hard(mut self) -> Self {
+        self.hard = true;
+        self
+    }
+
+    fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+}
+
+/// What the user chose on the preflight screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightAction {
+    None,
+    Fix,           // Apply every offered fix and re-run the checks
+    Continue,      // Start the loop
+    Abort,         // Leave without starting
+    UsePrdTaskDir, // Switch to the task directory prd.json's taskDir names
+}
+
+/// The checklist and the run settings it is shown with
+#[derive(Debug, Clone, Default)]
+pub struct Preflight {
+    pub checks: Vec<Check>,
+    /// (label, value) rows describing the run (iterations, rotation, agent, ...)
+    pub settings: Vec<(&'static str, String)>,
+    /// Outcome of the last fix attempt
+    pub message: Option<String>,
+    /// prd.json's taskDir when it disagrees with the task directory and holds a prd.json itself
+    pub prd_task_dir: Option<PathBuf>,
+}
+
+impl Preflight {
+    pub fn all_passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+
+    pub fn has_hard_failure(&self) -> bool {
+        self.checks.iter().any(|c| !c.passed && c.hard)
+    }
+
+    /// Fixes offered by the failed checks
+    pub fn fixes(&self) -> Vec<Fix> {
+        self.checks.iter().filter(|c| !c.passed).filter_map(|c| c.fix.clone()).collect()
+    }
+
+    /// One line per failed check, for printing outside the TUI
+    pub fn failures(&self) -> Vec<String> {
+        self.checks
+            .iter()
+            .filter(|c| !c.passed)
+            .map(|c| match c.detail {
+                Some(ref detail) => format!("{}: {} ({})", c.label, c.summary, detail),
+                None => format!("{}: {}", c.label, c.summary),
+            })
+            .collect()
+    }
+}
+
+/// Run every check for `config`; `prompt_source` is the prompt.md in use (None = embedded)
+pub fn run_checks(config: &CliConfig, prompt_source: Option<&str>) -> Vec<Check> {
+    let prd = Prd::load(&config.prd_path);
+    let mut checks = vec![task_dir_check(&config.task_dir)];
+    checks.extend(task_dir_field_check(&config.prd_path, &config.task_dir));
+    checks.extend([
+        prd_check(&config.prd_path, &prd),
+        agent_check(
+            &config.agent.name,
+            &config.agent.binary,
+            config.agent.name == DEFAULT_AGENT,
+            config.agent_version.as_ref(),
+        ),
+        git_check(&config.task_dir, prd.as_ref().ok().and_then(|p| p.branch_name.as_deref())),
+    ]);
+    checks.extend(merge_target_check(&config.task_dir, prd.as_ref().ok().and_then(|p| p.merge_target.as_deref())));
+    checks.push(prompt_check(prompt_source));
+    checks.push(progress_check(&config.task_dir, config.rotate_threshold));
+    checks
+}
+
+fn task_dir_check(task_dir: &Path) -> Check {
+    if task_dir.is_dir() {
+        Check::pass("Task directory", task_dir.display().to_string())
+    } else {
+        Check::fail("Task directory", task_dir.display().to_string(), "not found").hard()
+    }
+}
+
+/// prd.json's `taskDir` when it names another directory than `task_dir` (None if they
+/// agree, the field is empty or prd.json can't be read)
+pub fn task_dir_mismatch(prd_path: &Path, task_dir: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(prd_path).ok()?;
+    let prd = serde_json::from_str::<serde_json::Value>(&content).ok()?;
+    let declared = prd.get("taskDir").and_then(|v| v.as_str())?.trim();
+    if declared.is_empty() {
+        return None;
+    }
+    let declared_path = PathBuf::from(declared);
+    let same = match (declared_path.canonicalize(), task_dir.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => declared_path == task_dir,
+    };
+    (!same).then(|| declared.to_string())
+}
+
+/// The directory prd.json's mismatched `taskDir` names, when it holds a prd.json to run instead
+pub fn prd_task_dir(prd_path: &Path, task_dir: &Path) -> Option<PathBuf> {
+    let declared = PathBuf::from(task_dir_mismatch(prd_path, task_dir)?);
+    declared.join("prd.json").is_file().then_some(declared)
+}
+
+/// A copied task folder keeps the old taskDir, which can point the agent at another
+/// task's progress.txt (only shown when they disagree)
+fn task_dir_field_check(prd_path: &Path, task_dir: &Path) -> Option<Check> {
+    let declared = task_dir_mismatch(prd_path, task_dir)?;
+    Some(
+        Check::fail(
+            "taskDir",
+            format!("prd.json says {}", declared),
+            format!("not {}; the agent may use the other task's progress.txt", task_dir.display()),
+        )
+        .with_fix(Fix::RewriteTaskDir(task_dir.display().to_string())),
+    )
+}
+
+fn prd_check(prd_path: &Path, prd: &io::Result<Prd>) -> Check {
+    match prd {
+        Ok(prd) => Check::pass(
+            "prd.json",
+            format!("{} stories, {} complete", prd.story_total(), prd.completed_count()),
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Check::fail("prd.json", prd_path.display().to_string(), "not found").hard()
+        }
+        Err(e) => Check::fail("prd.json", "doesn't parse", e.to_string()).hard(),
+    }
+}
+
+/// The agent binary must be on PATH; only Claude is asked for its version (at
+/// startup, see compat.rs), which fails softly outside the range ralph-tui was tested with
+fn agent_check(name: &str, binary: &str, ask_version: bool, version: Option<&AgentVersion>) -> Check {
+    let Some(path) = find_binary(binary) else {
+        return Check::fail("Agent", name.to_string(), format!("{} not found on PATH", binary)).hard();
+    };
+    if !ask_version {
+        return Check::pass("Agent", format!("{} ({})", name, path.display()));
+    }
+    let Some(version) = version else {
+        return Check::pass("Agent", format!("{} (version unknown)", name));
+    };
+    let summary = format!("{} {}", name, version.text);
+    match version.warning() {
+        Some(warning) => Check::fail("Agent", summary, warning),
+        None => Check::pass("Agent", summary),
+    }
+}
+
+/// Where `binary` would be run from: itself when it is a path, else the first match on PATH
+pub fn find_binary(binary: &str) -> Option<PathBuf> {
+    let is_file = |path: &Path| path.is_file() || (cfg!(windows) && path.with_extension("exe").is_file());
+    let path = Path::new(binary);
+    if path.components().count() > 1 {
+        return is_file(path).then(|| path.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(binary))
+        .find(|candidate| is_file(candidate))
+}
+
+/// First line of `binary --version` (None when it prints nothing, exits non-zero or
+/// takes longer than VERSION_TIMEOUT). Err means the binary couldn't be run at all.
+pub fn agent_version(binary: &Path) -> io::Result<Option<String>> {
+    let mut child = Command::new(binary)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let started = Instant::now();
+    while child.try_wait()?.is_none() {
+        if started.elapsed() >= VERSION_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let output = child.wait_with_output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(output
+        .status
+        .success()
+        .then(|| stdout.lines().next().unwrap_or("").trim().to_string())
+        .filter(|line| !line.is_empty()))
+}
+
+fn git_check(task_dir: &Path, branch_name: Option<&str>) -> Check {
+    let Some(repo) = git::repo_root(task_dir) else {
+        return Check::fail(
+            "Git branch",
+            "not a git repository",
+            "branch tracking, drift and rollback are off",
+        );
+    };
+    let current = git::current_branch(&repo).unwrap_or_else(|_| "HEAD".to_string());
+    // An invalid branchName would only fail later, in the agent's git commands
+    if let Some(wanted) = branch_name
+        && let Some(problem) = git::branch_name_problem(wanted)
+    {
+        return Check::fail(
+            "Git branch",
+            format!("branchName {:?} is invalid", wanted),
+            format!("{}; try {:?} in prd.json", problem, git::normalize_branch_name(wanted)),
+        )
+        .hard();
+    }
+    match branch_name {
+        Some(wanted) if wanted != current => Check::fail(
+            "Git branch",
+            format!("on {}", current),
+            format!("prd.json branchName is {}", wanted),
+        )
+        .with_fix(Fix::CheckoutBranch(wanted.to_string())),
+        _ => Check::pass("Git branch", current),
+    }
+}
+
+/// The PRD's mergeTarget must name something git can resolve (None when there is none,
+/// or no repository to look in)
+fn merge_target_check(task_dir: &Path, merge_target: Option<&str>) -> Option<Check> {
+    let target = merge_target?;
+    let repo = git::repo_root(task_dir)?;
+    Some(if git::rev_exists(&repo, target) {
+        Check::pass("Merge target", target.to_string())
+    } else {
+        Check::fail("Merge target", format!("{} not found", target), "drift tracking and sync with it won't work")
+    })
+}
+
+fn prompt_check(prompt_source: Option<&str>) -> Check {
+    match prompt_source {
+        Some(path) => Check::pass("Prompt", path.to_string()),
+        None => Check::pass("Prompt", "embedded default (no prompt.md in the task, ralph/ or ~/.config/ralph)"),
+    }
+}
+
+fn progress_check(task_dir: &Path, rotate_threshold: u32) -> Check {
+    let Ok(content) = std::fs::read_to_string(task_dir.join("progress.txt")) else {
+        return Check::pass("progress.txt", "not started yet");
+    };
+    let lines = progress_line_count(&content);
+    let summary = format!("{} lines (rotates at {})", lines, rotate_threshold);
+    if lines > rotate_threshold as usize {
+        Check::fail("progress.txt", summary, "over the rotation threshold; the agent reads all of it")
+            .with_fix(Fix::RotateProgress)
+    } else {
+        Check::pass("progress.txt", summary)
+    }
+}
+
+/// Apply `fix`; returns a short description of what was done
+pub fn apply_fix(fix: &Fix, task_dir: &Path, prd_path: &Path) -> io::Result<String> {
+    match fix {
+        Fix::RotateProgress => {
+            let rotated = rotate_progress(task_dir)?;
+            Ok(format!("progress.txt rotated to {}", rotated.display()))
+        }
+        Fix::CheckoutBranch(branch) => {
+            let repo = git::repo_root(task_dir)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a git repository"))?;
+            git::checkout_branch(&repo, branch)?;
+            Ok(format!("checked out {}", branch))
+        }
+        Fix::RewriteTaskDir(value) => {
+            set_prd_value(task_dir, prd_path, "/taskDir", serde_json::Value::String(value.clone()))?;
+            Ok(format!("prd.json taskDir set to {}", value))
+        }
+    }
+}
+
+/// Move progress.txt to the next free progress-N.txt and start a new one
+/// The new file keeps the header and the Codebase Patterns section and points at the
+/// rotated file, the same layout ralph.sh writes. Returns the rotated file's path.
+pub fn rotate_progress(task_dir: &Path) -> io::Result<PathBuf> {
+    let progress_path = task_dir.join("progress.txt");
+    let content = std::fs::read_to_string(&progress_path)?;
+    let n = (1..).find(|n| !task_dir.join(format!("progress-{}.txt", n)).exists()).unwrap_or(1);
+    let rotated = task_dir.join(format!("progress-{}.txt", n));
+    std::fs::rename(&progress_path, &rotated)?;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    std::fs::write(&progress_path, rotated_summary(&content, n, now))?;
+    Ok(rotated)
+}
+
+/// The new progress.txt after rotating `content` to progress-`n`.txt at `now`
+fn rotated_summary(content: &str, n: u32, now: u64) -> String {
+    let header = |prefix: &str| content.lines().find(|l| l.starts_with(prefix)).unwrap_or("").to_string();
+    let mut patterns = Vec::new();
+    let mut in_patterns = false;
+    for line in content.lines() {
+        if line.starts_with("## ") {
+            in_patterns = line.starts_with("## Codebase Patterns");
+        }
+        if in_patterns {
+            patterns.push(line);
+        }
+    }
+    let iterations = content
+        .lines()
+        .filter(|l| l.starts_with("## ") && l.contains(" - S") && !l.starts_with("## Codebase Patterns"))
+        .count();
+    let prior = if n > 1 { format!(" (continues from progress-{}.txt)", n - 1) } else { String::new() };
+    format!(
+        "# Ralph Progress Log\n{}\n{}\n{}\nRotation: {} (rotated at {} UTC)\n\n{}\n\n## Prior Progress\n\
+         Completed {} iterations in progress-{}.txt{}.\n_See progress-{}.txt for detailed iteration logs._\n\n---\n",
+        header("Effort:"),
+        header("Type:"),
+        header("Started:"),
+        n,
+        format_datetime_utc(now),
+        patterns.join("\n").trim_end(),
+        iterations,
+        n,
+        prior,
+        n
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn rotation_keeps_the_header_and_codebase_patterns() {
+        let content = "# Ralph Progress Log\nEffort: login\nType: feature\nStarted: Mon\n\n\
+                       ## Codebase Patterns\n- Use sqlx\n\n## 2026-01-01 - S1\ndid things\n## 2026-01-02 - S2\nmore\n";
+        let summary = rotated_summary(content, 2, 0);
+        assert!(summary.contains("Effort: login\nType: feature\nStarted: Mon\nRotation: 2 (rotated at 1970-01-01 00:00 UTC)"));
+        assert!(summary.contains("## Codebase Patterns\n- Use sqlx\n\n## Prior Progress"));
+        assert!(summary.contains("Completed 2 iterations in progress-2.txt (continues from progress-1.txt)."));
+        assert!(!summary.contains("did things"));
+    }
+
+    #[test]
+    fn progress_over_the_threshold_offers_a_rotation() {
+        let dir = TempDir::new("preflight-progress");
+        assert!(progress_check(&dir, 3).passed);
+
+        std::fs::write(dir.join("progress.txt"), "Effort: x\n1\n2\n3\n").unwrap();
+        let check = progress_check(&dir, 3);
+        assert!(!check.passed && !check.hard);
+        assert_eq!(check.summary, "4 lines (rotates at 3)");
+        assert_eq!(check.fix, Some(Fix::RotateProgress));
+
+        apply_fix(&Fix::RotateProgress, &dir, &dir.join("prd.json")).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.join("progress-1.txt")).unwrap(), "Effort: x\n1\n2\n3\n");
+        assert!(std::fs::read_to_string(dir.join("progress.txt")).unwrap().contains("_See progress-1.txt"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn claude_outside_the_tested_range_fails_softly() {
+        let check = agent_check("claude", "sh", true, Some(&AgentVersion::new("2.0.14 (Claude Code)")));
+        assert!(check.passed);
+        assert_eq!(check.summary, "claude 2.0.14 (Claude Code)");
+        let check = agent_check("claude", "sh", true, Some(&AgentVersion::new("3.0.1 (Claude Code)")));
+        assert!(!check.passed && !check.hard);
+        assert!(check.detail.unwrap().starts_with("newer than tested"));
+    }
+
+    #[test]
+    fn missing_pieces_are_hard_failures() {
+        let root = TempDir::new("preflight-missing");
+        let dir = root.join("task");
+        assert!(task_dir_check(&dir).hard);
+        assert!(agent_check("claude", "ralph-no-such-agent-binary", true, None).hard);
+
+        std::fs::create_dir_all(&dir).unwrap();
+        let prd_path = dir.join("prd.json");
+        std::fs::write(&prd_path, "{ not json").unwrap();
+        let check = prd_check(&prd_path, &Prd::load(&prd_path));
+        assert!(!check.passed && check.hard);
+        assert_eq!(check.summary, "doesn't parse");
+
+        let preflight = Preflight { checks: vec![check, task_dir_check(&dir)], ..Default::default() };
+        assert!(preflight.has_hard_failure());
+        assert!(preflight.fixes().is_empty());
+        assert_eq!(preflight.failures().len(), 1);
+    }
+
+    #[test]
+    fn task_dir_field_mismatch_offers_a_rewrite() {
+        let root = TempDir::new("preflight-taskdir");
+        let (dir, other) = (root.join("copy"), root.join("original"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+        let prd_path = dir.join("prd.json");
+        let prd = |task_dir: &Path| format!(r#"{{"project": "p", "taskDir": "{}", "type": "feature", "description": "", "userStories": []}}"#, task_dir.display());
+        std::fs::write(&prd_path, prd(&dir)).unwrap();
+        assert_eq!(task_dir_field_check(&prd_path, &dir), None);
+
+        std::fs::write(&prd_path, prd(&other)).unwrap();
+        let check = task_dir_field_check(&prd_path, &dir).unwrap();
+        assert!(!check.passed && !check.hard);
+        assert_eq!(check.summary, format!("prd.json says {}", other.display()));
+        assert_eq!(check.fix, Some(Fix::RewriteTaskDir(dir.display().to_string())));
+        // The other directory has no prd.json to switch to yet
+        assert_eq!(prd_task_dir(&prd_path, &dir), None);
+        std::fs::write(other.join("prd.json"), prd(&other)).unwrap();
+        assert_eq!(prd_task_dir(&prd_path, &dir), Some(other.clone()));
+
+        apply_fix(check.fix.as_ref().unwrap(), &dir, &prd_path).unwrap();
+        assert_eq!(task_dir_mismatch(&prd_path, &dir), None);
+        assert_eq!(crate::backup::list_backups(&dir).len(), 1);
+    }
+
+    #[test]
+    fn invalid_branch_names_and_missing_merge_targets_fail() {
+        let dir = TempDir::new("preflight-git");
+        for args in [
+            &["init", "--quiet", "--initial-branch", "main"][..],
+            &["-c", "user.email=ralph@example.com", "-c", "user.name=ralph", "commit", "--quiet", "--allow-empty", "-m", "init"],
+        ] {
+            assert!(Command::new("git").args(args).current_dir(&dir).status().unwrap().success());
+        }
+
+        let check = git_check(&dir, Some("feat branch!"));
+        assert!(!check.passed && check.hard);
+        assert_eq!(check.summary, "branchName \"feat branch!\" is invalid");
+        assert_eq!(check.detail.as_deref(), Some("it contains a space; try \"feat-branch\" in prd.json"));
+        assert_eq!(check.fix, None);
+        assert_eq!(git_check(&dir, Some("ralph/login")).fix, Some(Fix::CheckoutBranch("ralph/login".to_string())));
+
+        assert_eq!(merge_target_check(&dir, None), None);
+        assert!(merge_target_check(&dir, Some("main")).unwrap().passed);
+        let check = merge_target_check(&dir, Some("origin/develop")).unwrap();
+        assert!(!check.passed && !check.hard);
+        assert_eq!(check.summary, "origin/develop not found");
+    }
+}
@@ -0,0 +1,128 @@
+//! Read-only previews of the files the agent works on
+//!
+//! Enter on an entry in the activity view loads the file it names into a
+//! `FilePreview`, shown in the Ralph terminal area with line numbers. Only the
+//! first PREVIEW_MAX_LINES lines are read; missing, unreadable and binary
+//! files get a message instead of content.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Lines loaded into a preview (the rest of the file is cut off)
+pub const PREVIEW_MAX_LINES: usize = 500;
+
+/// Bytes sniffed for a NUL to tell binary files apart
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// What a preview shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreviewContent {
+    Lines { lines: Vec<String>, truncated: bool },
+    Missing,
+    Binary,
+    Unreadable(String),
+}
+
+/// A file loaded for the preview view
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePreview {
+    /// The path as the activity named it
+    pub path: String,
+    pub content: PreviewContent,
+}
+
+impl FilePreview {
+    pub fn load(path: &str) -> Self {
+        let content = match read_preview(Path::new(path)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => PreviewContent::Missing,
+            Err(e) => PreviewContent::Unreadable(e.to_string()),
+        };
+        FilePreview { path: path.to_string(), content }
+    }
+}
+
+/// Read up to PREVIEW_MAX_LINES lines of `path` (tabs expanded), or note that it is binary
+fn read_preview(path: &Path) -> io::Result<PreviewContent> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.is_dir() {
+        return Err(io::Error::other("is a directory"));
+    }
+    let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    (&mut file).take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head)?;
+    if head.contains(&0) {
+        return Ok(PreviewContent::Binary);
+    }
+
+    let mut reader = BufReader::new(head.as_slice().chain(file));
+    let mut lines = Vec::new();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(PreviewContent::Lines { lines, truncated: false });
+        }
+        if lines.len() == PREVIEW_MAX_LINES {
+            return Ok(PreviewContent::Lines { lines, truncated: true });
+        }
+        let Ok(line) = std::str::from_utf8(&buf) else {
+            return Ok(PreviewContent::Binary);
+        };
+        lines.push(line.trim_end_matches(['\n', '\r']).replace('\t', "    "));
+    }
+}
+
+/// Whether a source line is a comment in most languages (dimmed in the preview)
+pub fn is_comment_line(line: &str) -> bool {
+    let line = line.trim_start();
+    ["//", "#", "--", "/*", "* ", "*/", "<!--"].iter().any(|prefix| line.starts_with(prefix)) || line == "*"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn temp_file(dir: &TempDir, name: &str, content: &[u8]) -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn loads_lines_up_to_the_limit() {
+        let dir = TempDir::new("preview-lines");
+        let path = temp_file(&dir, "short", b"fn main() {\n\tprintln!();\r\n}");
+        assert_eq!(
+            FilePreview::load(&path).content,
+            PreviewContent::Lines {
+                lines: vec!["fn main() {".to_string(), "    println!();".to_string(), "}".to_string()],
+                truncated: false,
+            }
+        );
+
+        let long: String = (0..PREVIEW_MAX_LINES + 20).map(|n| format!("line {}\n", n)).collect();
+        let path_long = temp_file(&dir, "long", long.as_bytes());
+        let PreviewContent::Lines { lines, truncated } = FilePreview::load(&path_long).content else {
+            panic!("expected lines");
+        };
+        assert_eq!(lines.len(), PREVIEW_MAX_LINES);
+        assert!(truncated);
+
+        // Exactly at the limit is not truncated
+        let exact: String = (0..PREVIEW_MAX_LINES).map(|n| format!("line {}\n", n)).collect();
+        let path_exact = temp_file(&dir, "exact", exact.as_bytes());
+        assert!(matches!(FilePreview::load(&path_exact).content, PreviewContent::Lines { truncated: false, .. }));
+    }
+
+    #[test]
+    fn missing_and_binary_files_get_a_message() {
+        assert_eq!(FilePreview::load("ralph-no-such-file.rs").content, PreviewContent::Missing);
+        let dir = TempDir::new("preview-binary");
+        let path = temp_file(&dir, "binary", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+        assert_eq!(FilePreview::load(&path).content, PreviewContent::Binary);
+        let latin1 = temp_file(&dir, "latin1", b"caf\xe9\n");
+        assert_eq!(FilePreview::load(&latin1).content, PreviewContent::Binary);
+    }
+}
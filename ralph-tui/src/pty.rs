@@ -0,0 +1,1290 @@
+//! PTY output tracking: VT100 screen state, activity parsing, and key encoding
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyModifiers};
+use regex::Regex;
+
+use crate::events::{SessionEvent, now_millis};
+use crate::links::{find_links, remember_links};
+use crate::render::widgets::{display_width, truncate_start_to_width};
+use crate::testrun::{last_test_summary, TestResult};
+
+/// Kitty keyboard protocol mode changes: push (`CSI > flags u`), pop (`CSI < n u`)
+/// and set (`CSI = flags ; mode u`)
+static KITTY_KEYBOARD_MODE: LazyLock<regex::bytes::Regex> = LazyLock::new(|| {
+    regex::bytes::Regex::new(r"\x1b\[([<>=])(\d*)(?:;\d+)?u").expect("valid regex")
+});
+
+/// Recent activity from Claude Code (tool calls, actions)
+#[derive(Debug, Clone)]
+pub struct Activity {
+    pub action_type: String,
+    pub target: String,
+    /// A write outside the repository and allowedPaths (set by the App, see scope.rs)
+    pub out_of_scope: bool,
+    /// Already matched against the dangerous command patterns (see tripwire.rs)
+    pub tripwire_checked: bool,
+}
+
+impl Activity {
+    pub fn new(action_type: &str, target: &str) -> Self {
+        Self {
+            action_type: action_type.to_string(),
+            target: target.to_string(),
+            out_of_scope: false,
+            tripwire_checked: false,
+        }
+    }
+
+    /// Format for display (truncate target if too long)
+    /// The target keeps its end, where a path's file name is, and the
+    /// result never takes more than `max_width` columns.
+    pub fn format(&self, max_width: usize) -> String {
+        let prefix = format!("{}: ", self.action_type);
+        let available = max_width.saturating_sub(display_width(&prefix));
+        format!("{}{}", prefix, truncate_start_to_width(&self.target, available))
+    }
+}
+
+/// Parse activities from Claude output
+/// Looks for tool call patterns in the output
+pub fn parse_activities(text: &str) -> Vec<Activity> {
+    let mut activities = Vec::new();
+
+    // Patterns to look for (case-insensitive matching in output)
+    // Claude Code typically shows tool usage in various formats
+    let patterns: &[(&str, &[&str])] = &[
+        ("Read", &["reading ", "read file", "read("]),
+        ("Edit", &["editing ", "edit file", "edit("]),
+        ("Write", &["writing ", "write file", "write("]),
+        ("Bash", &["running ", "$ ", "bash(", "executing "]),
+        ("Grep", &["searching ", "grep(", "grep for"]),
+        ("Glob", &["finding files", "glob(", "globbing"]),
+        ("TodoWrite", &["updating todos", "todowrite(", "adding todo"]),
+    ];
+
+    for line in text.lines() {
+        // Minified dumps and the like hold no tool calls, only a long lowercase copy
+        if line.len() > MAX_ACTIVITY_LINE {
+            continue;
+        }
+        let line_lower = line.to_lowercase();
+
+        for (action_type, prefixes) in patterns {
+            for prefix in *prefixes {
+                if let Some(pos) = line_lower.find(prefix) {
+                    // Extract target (rest of line after pattern, cleaned up)
+                    // Use get() to safely handle potential UTF-8 boundary issues
+                    let start_idx = pos + prefix.len();
+                    let after = match line.get(start_idx..) {
+                        Some(s) => s,
+                        None => continue, // Skip if index is invalid
+                    };
+                    let target = after
+                        .trim()
+                        .trim_matches(|c: char| c == '"' || c == '\'' || c == '`')
+                        .split(['\n', '\r'])
+                        .next()
+                        .unwrap_or("")
+                        .chars()
+                        .take(100)  // Limit target length
+                        .collect::<String>();
+
+                    if !target.is_empty() {
+                        let activity = Activity::new(action_type, &target);
+                        // Avoid duplicates
+                        if !activities.iter().any(|a: &Activity|
+                            a.action_type == activity.action_type && a.target == activity.target
+                        ) {
+                            activities.push(activity);
+                        }
+                    }
+                    break;  // Only match first pattern per line
+                }
+            }
+        }
+    }
+
+    activities
+}
+
+/// Maximum number of activities to track
+pub const MAX_ACTIVITIES: usize = 10;
+
+/// Lines longer than this are skipped when parsing activities
+pub const MAX_ACTIVITY_LINE: usize = 4 * 1024;
+
+/// Raw output kept for sentinel and activity scans: past RECENT_OUTPUT_MAX it is
+/// trimmed back to the last RECENT_OUTPUT_KEEP bytes
+pub const RECENT_OUTPUT_MAX: usize = 10 * 1024;
+pub const RECENT_OUTPUT_KEEP: usize = 8 * 1024;
+
+/// Most output processed under one hold of the PTY state lock (see `feed_output`)
+pub const PTY_FEED_CHUNK: usize = 1024;
+
+/// Seconds without output before the agent can be considered idle
+pub const DEFAULT_IDLE_SECS: u64 = 5;
+
+/// Default pattern for Claude's input prompt on the last visible screen row
+pub const DEFAULT_PROMPT_PATTERN: &str = r"^\s*(│\s*)?>(\s|$)|\? for shortcuts";
+
+/// Output substrings counted as errors unless --error-pattern is given
+pub const DEFAULT_ERROR_PATTERNS: &[&str] = &["error[E", "panicked at", "Traceback (most recent call last)", "FAILED"];
+
+/// Lines of context kept before and after each matched error line
+pub const ERROR_CONTEXT_LINES: usize = 2;
+
+/// Output marking that every story is done (the agent prompt asks for it)
+pub const COMPLETION_SENTINEL: &str = "<promise>COMPLETE</promise>";
+
+/// Claude output meaning its Stop hook ended the turn (lowercase)
+pub const CLAUDE_STOP_SENTINELS: &[&str] =
+    &["iteration complete", "ralph-tui will start next iteration", "ran 1 stop hook", "stop hook"];
+
+/// Claude output meaning the conversation is being compacted or nearly out of context (lowercase)
+pub const CLAUDE_COMPACTION_SENTINELS: &[&str] = &["compacting conversation", "conversation compacted", "context low"];
+
+/// Compaction text seen again within this long belongs to the same compaction (the TUI redraws it)
+pub const COMPACTION_QUIET: Duration = Duration::from_secs(30);
+
+/// What an iteration's output showed, for the iteration log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputSummary {
+    pub tests: Option<TestResult>,
+    pub compactions: u32,
+}
+
+/// An output line matching an error pattern, with the lines around it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorMatch {
+    pub line: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/// Whether the agent is generating output or sitting at its input prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AgentActivity {
+    #[default]
+    Starting, // No output yet this iteration
+    Working,  // Producing output (or not at its prompt)
+    Idle,     // Quiet for a while with the input prompt showing
+}
+
+/// Classify the agent from the time since its last output and the last visible screen row
+/// Idle needs both: no output for `idle_after` and a row matching the prompt pattern.
+pub fn detect_agent_activity(
+    since_output: Option<Duration>,
+    last_row: &str,
+    idle_after: Duration,
+    prompt: &Regex,
+) -> AgentActivity {
+    match since_output {
+        None => AgentActivity::Starting,
+        Some(quiet) if quiet >= idle_after && prompt.is_match(last_row) => AgentActivity::Idle,
+        Some(_) => AgentActivity::Working,
+    }
+}
+
+/// Shared state for PTY with VT100 parser
+pub struct PtyState {
+    pub parser: vt100::Parser,
+    pub child_exited: bool,
+    /// Recent raw output for detecting completion signal
+    pub recent_output: String,
+    /// Recent activities parsed from output
+    pub activities: Vec<Activity>,
+    /// Last parsed output position (to avoid re-parsing)
+    pub last_activity_parse_pos: usize,
+    /// When the child last produced output (None until the first output)
+    pub last_output_at: Option<Instant>,
+    /// Most recent test-run summary seen this iteration
+    pub last_test_result: Option<TestResult>,
+    /// Output position up to which test summaries have been parsed (complete lines only)
+    pub last_test_parse_pos: usize,
+    /// Whether the child has enabled the kitty keyboard protocol (CSI-u key encoding)
+    pub kitty_keyboard: bool,
+    /// Substrings that mark an output line as an error
+    pub error_patterns: Vec<String>,
+    /// Error lines seen this iteration, oldest first
+    pub errors: Vec<ErrorMatch>,
+    /// When the last new error line was seen
+    pub last_error_at: Option<Instant>,
+    /// Output position up to which error lines have been scanned (complete lines only)
+    pub last_error_parse_pos: usize,
+    /// Last few scanned lines, kept as context for the next error
+    recent_lines: Vec<String>,
+    /// Activities, error lines and test runs not yet collected for the timeline (Unix ms)
+    pub output_events: Vec<(u64, SessionEvent)>,
+    /// Panic message from the output reader thread, until the App reports it
+    pub reader_error: Option<String>,
+    /// Read error that closed the agent's output this iteration (None after a clean exit)
+    pub read_failure: Option<String>,
+    /// Output marking that every story is done (from the agent profile)
+    pub completion_sentinel: String,
+    /// Output meaning the agent ended its turn, matched case-insensitively (from the agent profile)
+    pub stop_sentinels: Vec<String>,
+    /// Output meaning the agent compacted its context, matched case-insensitively (from the agent profile)
+    pub compaction_sentinels: Vec<String>,
+    /// Context compactions seen this iteration
+    pub compactions: u32,
+    /// When compaction text was last seen
+    pub last_compaction_at: Option<Instant>,
+    /// Output position up to which compaction text has been scanned (complete lines only)
+    pub last_compaction_parse_pos: usize,
+    /// Links seen this session, newest first (see links.rs)
+    pub links: Vec<String>,
+    /// Output position up to which links have been scanned (complete lines only)
+    pub last_link_parse_pos: usize,
+}
+
+/// Lock the shared PTY state, recovering it if a thread panicked while holding the lock
+/// The state is plain data, so at worst one chunk of output is half-processed; the
+/// panic itself is reported through `reader_error` rather than treated as an exit.
+pub fn lock_pty(state: &Mutex<PtyState>) -> MutexGuard<'_, PtyState> {
+    state.lock().unwrap_or_else(|poisoned| {
+        state.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+/// Feed agent output to the shared state PTY_FEED_CHUNK bytes per lock, so one huge
+/// read (a minified JSON dump on a single line) can't keep the UI thread waiting
+/// (the VT100 parser carries escape sequences split between chunks over)
+pub fn feed_output(state: &Mutex<PtyState>, data: &[u8]) {
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (chunk, tail) = rest.split_at(utf8_chunk_end(rest, PTY_FEED_CHUNK));
+        let mut state = lock_pty(state);
+        state.parser.process(chunk);
+        state.append_output(chunk);
+        rest = tail;
+    }
+}
+
+/// Length of the first chunk of `data` at most `max` bytes long, not splitting a UTF-8 character
+fn utf8_chunk_end(data: &[u8], max: usize) -> usize {
+    if data.len() <= max {
+        return data.len();
+    }
+    // Continuation bytes look like 0b10xxxxxx; a character has at most three
+    (max.saturating_sub(3).max(1)..=max).rev().find(|&end| data[end] & 0xC0 != 0x80).unwrap_or(max)
+}
+
+/// The last `max` bytes of `data` or fewer, starting on a UTF-8 character
+fn utf8_tail(data: &[u8], max: usize) -> &[u8] {
+    let mut start = data.len().saturating_sub(max);
+    while start < data.len() && data[start] & 0xC0 == 0x80 {
+        start += 1;
+    }
+    &data[start..]
+}
+
+/// Whether a read error on the PTY master only means the agent exited
+/// (Linux reports EIO rather than EOF once the agent's side of the PTY is closed)
+pub fn is_pty_hangup(error: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        error.raw_os_error() == Some(libc::EIO)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+/// Text of a caught panic payload
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+impl PtyState {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        Self {
+            parser: vt100::Parser::new(rows, cols, 1000), // 1000 lines of scrollback
+            child_exited: false,
+            recent_output: String::new(),
+            activities: Vec::new(),
+            last_activity_parse_pos: 0,
+            last_output_at: None,
+            last_test_result: None,
+            last_test_parse_pos: 0,
+            kitty_keyboard: false,
+            error_patterns: DEFAULT_ERROR_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            errors: Vec::new(),
+            last_error_at: None,
+            last_error_parse_pos: 0,
+            recent_lines: Vec::new(),
+            output_events: Vec::new(),
+            reader_error: None,
+            read_failure: None,
+            completion_sentinel: COMPLETION_SENTINEL.to_string(),
+            stop_sentinels: CLAUDE_STOP_SENTINELS.iter().map(|s| s.to_string()).collect(),
+            compaction_sentinels: CLAUDE_COMPACTION_SENTINELS.iter().map(|s| s.to_string()).collect(),
+            compactions: 0,
+            last_compaction_at: None,
+            last_compaction_parse_pos: 0,
+            links: Vec::new(),
+            last_link_parse_pos: 0,
+        }
+    }
+
+    /// PtyState matching the given error patterns instead of the defaults
+    pub fn with_error_patterns(rows: u16, cols: u16, error_patterns: Vec<String>) -> Self {
+        Self { error_patterns, ..Self::new(rows, cols) }
+    }
+
+    /// Append output and trim to the last RECENT_OUTPUT_KEEP bytes to limit memory
+    pub fn append_output(&mut self, data: &[u8]) {
+        self.last_output_at = Some(Instant::now());
+        self.track_keyboard_mode(data);
+        // Sentinels span a few hundred bytes at most, so only a huge read's tail is kept
+        if let Ok(s) = std::str::from_utf8(utf8_tail(data, RECENT_OUTPUT_KEEP)) {
+            self.recent_output.push_str(s);
+            if self.recent_output.len() > RECENT_OUTPUT_MAX {
+                let mut start = self.recent_output.len() - RECENT_OUTPUT_KEEP;
+                while !self.recent_output.is_char_boundary(start) {
+                    start += 1;
+                }
+                self.recent_output.drain(..start);
+                self.last_activity_parse_pos = self.last_activity_parse_pos.saturating_sub(start);
+                self.last_test_parse_pos = self.last_test_parse_pos.saturating_sub(start);
+                self.last_error_parse_pos = self.last_error_parse_pos.saturating_sub(start);
+                self.last_compaction_parse_pos = self.last_compaction_parse_pos.saturating_sub(start);
+                self.last_link_parse_pos = self.last_link_parse_pos.saturating_sub(start);
+            }
+        }
+    }
+
+    /// Follow the child enabling/disabling the kitty keyboard protocol (last change wins)
+    fn track_keyboard_mode(&mut self, data: &[u8]) {
+        if let Some(caps) = KITTY_KEYBOARD_MODE.captures_iter(data).last() {
+            let flags = std::str::from_utf8(&caps[2]).ok().and_then(|f| f.parse::<u32>().ok());
+            self.kitty_keyboard = match &caps[1] {
+                b">" | b"=" => flags.unwrap_or(0) > 0,
+                _ => false, // Pop back to the default (legacy) encoding
+            };
+        }
+    }
+
+    /// Check if completion signal is present in recent output
+    pub fn has_completion_signal(&self) -> bool {
+        self.recent_output.contains(self.completion_sentinel.as_str())
+    }
+
+    /// Check if stop hook fired (iteration complete message in output)
+    /// This is used to detect when Claude's Stop hook runs with continue: false
+    /// Since Claude doesn't exit, we detect the message instead
+    /// We check for multiple possible patterns since ANSI codes may interfere
+    pub fn has_stop_hook_signal(&self) -> bool {
+        if self.stop_sentinels.is_empty() {
+            return false;
+        }
+        let sentinels: Vec<String> = self.stop_sentinels.iter().map(|s| s.to_lowercase()).collect();
+        let matches = |text: &str| {
+            let lower = text.to_lowercase();
+            sentinels.iter().any(|s| lower.contains(s.as_str()))
+        };
+
+        // Check raw output first (with ANSI stripping)
+        if matches(&strip_ansi_codes(&self.recent_output)) {
+            return true;
+        }
+
+        // Also check the VT100 screen content (rendered text)
+        let screen = self.parser.screen();
+        let (rows, _cols) = screen.size();
+        (0..rows).any(|row| matches(&screen.contents_between(row, 0, row, 200)))
+    }
+
+    /// Clear recent output (called when starting new iteration)
+    pub fn clear_recent_output(&mut self) {
+        self.recent_output.clear();
+        self.activities.clear();
+        self.last_activity_parse_pos = 0;
+        self.last_output_at = None;
+        self.last_test_result = None;
+        self.last_test_parse_pos = 0;
+        self.errors.clear();
+        self.last_error_at = None;
+        self.last_error_parse_pos = 0;
+        self.recent_lines.clear();
+        self.compactions = 0;
+        self.last_compaction_at = None;
+        self.last_compaction_parse_pos = 0;
+        self.last_link_parse_pos = 0; // The links themselves are kept for the session
+        self.kitty_keyboard = false; // A new agent process starts in legacy key mode
+    }
+
+    /// Last non-blank row of the visible screen
+    pub fn last_visible_row(&self) -> String {
+        self.parser
+            .screen()
+            .contents()
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("")
+            .to_string()
+    }
+
+    /// Parse activities (and test summaries) from new output since last parse
+    pub fn update_activities(&mut self) {
+        self.update_test_result();
+        self.update_errors();
+        self.update_compactions();
+        self.update_links();
+        if self.recent_output.len() <= self.last_activity_parse_pos {
+            return;
+        }
+
+        // Parse only the new portion of output (safe slice access)
+        let new_output = match self.recent_output.get(self.last_activity_parse_pos..) {
+            Some(s) => s,
+            None => {
+                // Position is invalid (maybe string was trimmed), reset
+                self.last_activity_parse_pos = 0;
+                return;
+            }
+        };
+        let new_activities = parse_activities(new_output);
+
+        // Add new activities, avoiding duplicates
+        for activity in new_activities {
+            if !self.activities.iter().any(|a|
+                a.action_type == activity.action_type && a.target == activity.target
+            ) {
+                self.output_events.push((
+                    now_millis(),
+                    SessionEvent::Activity { action: activity.action_type.clone(), target: activity.target.clone() },
+                ));
+                self.activities.push(activity);
+            }
+        }
+
+        // Keep only the last MAX_ACTIVITIES
+        if self.activities.len() > MAX_ACTIVITIES {
+            let remove_count = self.activities.len() - MAX_ACTIVITIES;
+            self.activities.drain(0..remove_count);
+        }
+
+        self.last_activity_parse_pos = self.recent_output.len();
+    }
+
+    /// Pick up test summaries from complete lines written since the last parse
+    fn update_test_result(&mut self) {
+        let Some(new_output) = self.recent_output.get(self.last_test_parse_pos..) else {
+            self.last_test_parse_pos = 0;
+            return;
+        };
+        let Some(end) = new_output.rfind('\n') else {
+            return;
+        };
+        if let Some(result) = last_test_summary(&strip_ansi_codes(&new_output[..end])) {
+            self.output_events.push((
+                now_millis(),
+                SessionEvent::TestRun {
+                    framework: result.framework.to_string(),
+                    passed: result.passed,
+                    failed: result.failed,
+                },
+            ));
+            self.last_test_result = Some(result);
+        }
+        self.last_test_parse_pos += end + 1;
+    }
+
+    /// Collect error-pattern matches from complete lines written since the last scan
+    fn update_errors(&mut self) {
+        let Some(new_output) = self.recent_output.get(self.last_error_parse_pos..) else {
+            self.last_error_parse_pos = 0;
+            return;
+        };
+        let Some(end) = new_output.rfind('\n') else {
+            return;
+        };
+        let text = strip_ansi_codes(&new_output[..end]);
+        self.last_error_parse_pos += end + 1;
+        for line in text.split('\n') {
+            let line = line.trim_end_matches('\r').trim_end();
+            if line.trim().is_empty() {
+                continue;
+            }
+            // Fill in the trailing context of earlier errors
+            for error in self.errors.iter_mut().rev().take_while(|e| e.after.len() < ERROR_CONTEXT_LINES) {
+                error.after.push(line.to_string());
+            }
+            // The agent's TUI redraws its screen, so only count a line the first time it shows up
+            let is_error = self.error_patterns.iter().any(|p| line.contains(p.as_str()));
+            if is_error && !self.errors.iter().any(|e| e.line == line) {
+                self.output_events.push((now_millis(), SessionEvent::ErrorLine { line: line.to_string() }));
+                self.errors.push(ErrorMatch {
+                    line: line.to_string(),
+                    before: self.recent_lines.clone(),
+                    after: Vec::new(),
+                });
+                self.last_error_at = Some(Instant::now());
+            }
+            self.recent_lines.push(line.to_string());
+            if self.recent_lines.len() > ERROR_CONTEXT_LINES {
+                self.recent_lines.remove(0);
+            }
+        }
+    }
+
+    /// Count context compactions in complete lines written since the last scan
+    /// Compaction text keeps being redrawn while it is on screen, so a match only
+    /// counts as a new compaction after COMPACTION_QUIET without one.
+    fn update_compactions(&mut self) {
+        let Some(new_output) = self.recent_output.get(self.last_compaction_parse_pos..) else {
+            self.last_compaction_parse_pos = 0;
+            return;
+        };
+        let Some(end) = new_output.rfind('\n') else {
+            return;
+        };
+        let text = strip_ansi_codes(&new_output[..end]).to_lowercase();
+        self.last_compaction_parse_pos += end + 1;
+        if !self.compaction_sentinels.iter().any(|s| text.contains(&s.to_lowercase())) {
+            return;
+        }
+        if self.last_compaction_at.is_none_or(|at| at.elapsed() >= COMPACTION_QUIET) {
+            self.compactions += 1;
+            self.output_events.push((now_millis(), SessionEvent::Compaction { count: self.compactions }));
+        }
+        self.last_compaction_at = Some(Instant::now());
+    }
+
+    /// Collect links from complete lines written since the last scan
+    fn update_links(&mut self) {
+        let Some(new_output) = self.recent_output.get(self.last_link_parse_pos..) else {
+            self.last_link_parse_pos = 0;
+            return;
+        };
+        let Some(end) = new_output.rfind('\n') else {
+            return;
+        };
+        let found = find_links(&new_output[..end]);
+        self.last_link_parse_pos += end + 1;
+        remember_links(&mut self.links, found);
+    }
+
+    /// Test results and compactions seen this iteration
+    pub fn output_summary(&self) -> OutputSummary {
+        OutputSummary { tests: self.last_test_result, compactions: self.compactions }
+    }
+
+    /// Get recent activities (newest first)
+    pub fn get_activities(&self) -> Vec<Activity> {
+        self.activities.iter().rev().cloned().collect()
+    }
+}
+
+/// Strip ANSI escape sequences from a string for reliable text matching
+pub fn strip_ansi_codes(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip ESC and the following sequence
+            if let Some(&next) = chars.peek() {
+                if next == '[' {
+                    chars.next(); // consume '['
+                    // Skip until we hit a letter (the terminator)
+                    while let Some(&ch) = chars.peek() {
+                        chars.next();
+                        if ch.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                } else if next == ']' {
+                    // OSC sequence - skip until BEL or ST
+                    chars.next();
+                    for ch in chars.by_ref() {
+                        if ch == '\x07' || ch == '\\' {
+                            break;
+                        }
+                    }
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Holds back PTY resizes until the panel size settles
+/// A new size reaches the agent once it held for two consecutive frames, so dragging a
+/// window edge (or a burst of tmux resizes) doesn't send it a resize every frame.
+#[derive(Debug, Default)]
+pub struct ResizeDebounce {
+    // Panel size seen on the previous frame, when it differs from the PTY's
+    pending: Option<(u16, u16)>,
+}
+
+impl ResizeDebounce {
+    /// The (cols, rows) to resize the PTY to this frame, given its size and the panel's
+    pub fn settle(&mut self, current: (u16, u16), wanted: (u16, u16)) -> Option<(u16, u16)> {
+        if wanted == current {
+            self.pending = None;
+            return None;
+        }
+        if self.pending == Some(wanted) {
+            self.pending = None;
+            return Some(wanted);
+        }
+        self.pending = Some(wanted);
+        None
+    }
+}
+
+/// Maximum bytes per PTY write when forwarding large input (pastes)
+pub const PTY_WRITE_CHUNK: usize = 4096;
+
+/// Chunks queued for the PTY writer thread before further input is dropped
+pub const PTY_WRITE_QUEUE: usize = 64;
+
+/// Writes input to the PTY on a dedicated thread
+/// The UI thread only ever queues chunks; if the child stops reading, the queue
+/// fills up and further input is dropped instead of blocking the UI.
+pub struct PtyWriter {
+    sender: SyncSender<Vec<u8>>,
+    // Set when input was dropped; cleared once the writer thread makes progress again
+    stalled: Arc<AtomicBool>,
+    // Chunks sent but not yet written (the Health view's input queue depth)
+    queued: Arc<AtomicUsize>,
+}
+
+impl PtyWriter {
+    pub fn spawn(mut writer: Box<dyn Write + Send>) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(PTY_WRITE_QUEUE);
+        let stalled = Arc::new(AtomicBool::new(false));
+        let thread_stalled = Arc::clone(&stalled);
+        let queued = Arc::new(AtomicUsize::new(0));
+        let thread_queued = Arc::clone(&queued);
+        // Detached: nothing ever joins this thread, so a wedged write can't hold up quitting
+        thread::spawn(move || {
+            while let Ok(chunk) = receiver.recv() {
+                if writer.write_all(&chunk).is_err() || writer.flush().is_err() {
+                    break;
+                }
+                thread_queued.fetch_sub(1, Ordering::Relaxed);
+                thread_stalled.store(false, Ordering::Relaxed);
+            }
+        });
+        Self { sender, stalled, queued }
+    }
+
+    /// Queue `data` for the PTY without blocking
+    /// All or nothing: returns false, queuing none of it, if the queue hasn't room
+    /// for every chunk or the writer thread has exited.
+    pub fn send(&self, data: &[u8]) -> bool {
+        let chunks = data.len().div_ceil(PTY_WRITE_CHUNK);
+        // Reserved up front so a paste is never cut off partway; counted before sending so
+        // the writer thread never finishes a chunk that isn't counted yet
+        let reserved = self.queued.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+            (queued + chunks <= PTY_WRITE_QUEUE).then_some(queued + chunks)
+        });
+        if reserved.is_err() {
+            self.stalled.store(true, Ordering::Relaxed);
+            return false;
+        }
+        for (sent, chunk) in data.chunks(PTY_WRITE_CHUNK).enumerate() {
+            if self.sender.try_send(chunk.to_vec()).is_err() {
+                self.queued.fetch_sub(chunks - sent, Ordering::Relaxed);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether input is being dropped because the agent isn't reading it
+    pub fn is_stalled(&self) -> bool {
+        self.stalled.load(Ordering::Relaxed)
+    }
+
+    /// Chunks queued for the agent that the writer thread hasn't written yet
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+}
+
+/// How long a prompt typed in after the spawn waits for the agent to turn on bracketed paste
+pub const PROMPT_PASTE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A prompt to be typed into a freshly spawned agent once it is ready for a paste
+/// Until the agent puts its terminal in raw mode the line discipline is still canonical:
+/// it cuts lines off at 4095 bytes and would hand each newline to the agent as Enter.
+/// Agents turn bracketed paste on (ESC[?2004h) once their input editor is up, so the
+/// prompt waits for that and goes in as one bracketed paste followed by Enter.
+#[derive(Debug)]
+pub struct PendingPrompt {
+    payload: Vec<u8>,
+    since: Instant,
+}
+
+impl PendingPrompt {
+    /// Err when the prompt is too big for the PTY writer's queue
+    pub fn new(prompt: &str) -> io::Result<Self> {
+        let mut payload = build_paste_payload(prompt, true);
+        payload.push(b'\r');
+        if payload.len() > PTY_WRITE_QUEUE * PTY_WRITE_CHUNK {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "The prompt ({} bytes) is too long to type into the agent (at most {})",
+                    prompt.len(),
+                    PTY_WRITE_QUEUE * PTY_WRITE_CHUNK
+                ),
+            ));
+        }
+        Ok(Self { payload, since: Instant::now() })
+    }
+
+    /// Send the prompt if the agent has turned bracketed paste on
+    /// Ok(true) once it is queued, Ok(false) while the agent isn't ready yet; Err when
+    /// the writer refused it or the agent wasn't ready within PROMPT_PASTE_TIMEOUT.
+    pub fn try_deliver(&self, state: &Mutex<PtyState>, writer: &PtyWriter, now: Instant) -> io::Result<bool> {
+        if !lock_pty(state).parser.screen().bracketed_paste() {
+            if now.saturating_duration_since(self.since) >= PROMPT_PASTE_TIMEOUT {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "The agent didn't turn on bracketed paste within {}s, so its prompt couldn't be typed in",
+                        PROMPT_PASTE_TIMEOUT.as_secs()
+                    ),
+                ));
+            }
+            return Ok(false);
+        }
+        if !writer.send(&self.payload) {
+            return Err(io::Error::other("The agent's input queue refused its prompt"));
+        }
+        Ok(true)
+    }
+}
+
+/// Bracketed paste start/end markers
+pub const PASTE_START: &str = "\x1b[200~";
+pub const PASTE_END: &str = "\x1b[201~";
+
+/// Build the bytes to send to the PTY for a paste
+/// With bracketed paste the text is wrapped in ESC[200~ / ESC[201~ (with any embedded end
+/// marker removed so the paste can't terminate early). Without it, ESC characters are
+/// stripped so pasted text can't inject control sequences.
+pub fn build_paste_payload(text: &str, bracketed: bool) -> Vec<u8> {
+    if bracketed {
+        let sanitized = text.replace(PASTE_END, "");
+        let mut payload = Vec::with_capacity(sanitized.len() + PASTE_START.len() + PASTE_END.len());
+        payload.extend_from_slice(PASTE_START.as_bytes());
+        payload.extend_from_slice(sanitized.as_bytes());
+        payload.extend_from_slice(PASTE_END.as_bytes());
+        payload
+    } else {
+        text.chars().filter(|&c| c != '\x1b').collect::<String>().into_bytes()
+    }
+}
+
+/// Byte sequence to send to the PTY for a key event
+/// Converts crossterm key events to the appropriate byte sequences for the terminal
+/// (None for keys we don't forward)
+/// `kitty` selects CSI-u encodings for keys legacy terminals can't express (Shift+Enter);
+/// only pass true when the child has enabled the kitty keyboard protocol.
+pub fn key_to_pty_bytes(key_code: KeyCode, modifiers: KeyModifiers, kitty: bool) -> Option<Vec<u8>> {
+    // xterm modifier parameter: 1 + Shift(1) + Alt(2) + Ctrl(4)
+    let modifier_param = 1
+        + u8::from(modifiers.contains(KeyModifiers::SHIFT))
+        + 2 * u8::from(modifiers.contains(KeyModifiers::ALT))
+        + 4 * u8::from(modifiers.contains(KeyModifiers::CONTROL));
+    // Cursor keys: CSI <final> unmodified, CSI 1;<modifier> <final> with modifiers (e.g. Ctrl+Left)
+    let cursor_key = |final_byte: u8| {
+        if modifier_param > 1 {
+            format!("\x1b[1;{}{}", modifier_param, final_byte as char).into_bytes()
+        } else {
+            vec![0x1b, b'[', final_byte]
+        }
+    };
+
+    let bytes: Vec<u8> = match key_code {
+        // Printable characters
+        KeyCode::Char(c) => {
+            if modifiers.contains(KeyModifiers::CONTROL) {
+                // Handle Ctrl+key combinations
+                // Ctrl+A = 0x01, Ctrl+B = 0x02, ..., Ctrl+Z = 0x1A
+                // Ctrl+C = 0x03 (interrupt)
+                if c.is_ascii_alphabetic() {
+                    let ctrl_char = (c.to_ascii_lowercase() as u8) - b'a' + 1;
+                    vec![ctrl_char]
+                } else if c == '[' {
+                    vec![0x1b] // Escape
+                } else if c == '\\' {
+                    vec![0x1c] // File separator (Ctrl+\)
+                } else if c == ']' {
+                    vec![0x1d] // Group separator (Ctrl+])
+                } else if c == '^' {
+                    vec![0x1e] // Record separator (Ctrl+^)
+                } else if c == '_' {
+                    vec![0x1f] // Unit separator (Ctrl+_)
+                } else {
+                    // Just send the character for other Ctrl combinations
+                    c.to_string().into_bytes()
+                }
+            } else if modifiers.contains(KeyModifiers::ALT) {
+                // Alt+key sends ESC followed by the character
+                let mut bytes = vec![0x1b]; // ESC
+                bytes.extend(c.to_string().into_bytes());
+                bytes
+            } else {
+                // Regular character
+                c.to_string().into_bytes()
+            }
+        }
+
+        // Special keys
+        KeyCode::Enter => {
+            if modifiers.contains(KeyModifiers::SHIFT) {
+                // Shift+Enter: CSI 13;2u under the kitty protocol, otherwise a plain
+                // newline (which Claude treats as a line break in multi-line input)
+                if kitty {
+                    vec![0x1b, b'[', b'1', b'3', b';', b'2', b'u']
+                } else {
+                    vec![b'\n']
+                }
+            } else {
+                vec![b'\r'] // Regular Enter: carriage return
+            }
+        }
+        KeyCode::Backspace if modifiers.contains(KeyModifiers::ALT) => vec![0x1b, 0x7f], // Alt+Backspace: delete word
+        KeyCode::Backspace => vec![0x7f],  // DEL character (most terminals)
+        KeyCode::Delete => vec![0x1b, b'[', b'3', b'~'], // ANSI escape sequence
+        KeyCode::Tab => vec![b'\t'],       // Tab character
+
+        // Arrow keys (ANSI escape sequences)
+        KeyCode::Up => cursor_key(b'A'),
+        KeyCode::Down => cursor_key(b'B'),
+        KeyCode::Right => cursor_key(b'C'),
+        KeyCode::Left => cursor_key(b'D'),
+
+        // Home/End keys
+        KeyCode::Home => cursor_key(b'H'),
+        KeyCode::End => cursor_key(b'F'),
+
+        // Page Up/Down
+        KeyCode::PageUp => vec![0x1b, b'[', b'5', b'~'],
+        KeyCode::PageDown => vec![0x1b, b'[', b'6', b'~'],
+
+        // Insert key
+        KeyCode::Insert => vec![0x1b, b'[', b'2', b'~'],
+
+        // Function keys
+        KeyCode::F(1) => vec![0x1b, b'O', b'P'],
+        KeyCode::F(2) => vec![0x1b, b'O', b'Q'],
+        KeyCode::F(3) => vec![0x1b, b'O', b'R'],
+        KeyCode::F(4) => vec![0x1b, b'O', b'S'],
+        KeyCode::F(5) => vec![0x1b, b'[', b'1', b'5', b'~'],
+        KeyCode::F(6) => vec![0x1b, b'[', b'1', b'7', b'~'],
+        KeyCode::F(7) => vec![0x1b, b'[', b'1', b'8', b'~'],
+        KeyCode::F(8) => vec![0x1b, b'[', b'1', b'9', b'~'],
+        KeyCode::F(9) => vec![0x1b, b'[', b'2', b'0', b'~'],
+        KeyCode::F(10) => vec![0x1b, b'[', b'2', b'1', b'~'],
+        KeyCode::F(11) => vec![0x1b, b'[', b'2', b'3', b'~'],
+        KeyCode::F(12) => vec![0x1b, b'[', b'2', b'4', b'~'],
+        KeyCode::F(_) => return None, // Unsupported function keys
+
+        // Escape key - send raw ESC byte
+        KeyCode::Esc => vec![0x1b],
+
+        // Other keys we don't handle
+        _ => return None,
+    };
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::mpsc::Receiver;
+    use std::time::{Duration, Instant};
+
+    /// PTY consumer that never reads: every write blocks until the gate is dropped
+    struct StalledConsumer {
+        gate: Receiver<()>,
+    }
+
+    impl Write for StalledConsumer {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            let _ = self.gate.recv();
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// PTY consumer that records everything written to it
+    struct RecordingConsumer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for RecordingConsumer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn detects_idle_only_when_quiet_at_prompt() {
+        let prompt = Regex::new(DEFAULT_PROMPT_PATTERN).unwrap();
+        let idle_after = Duration::from_secs(DEFAULT_IDLE_SECS);
+        let quiet = Some(Duration::from_secs(30));
+
+        assert_eq!(detect_agent_activity(None, "", idle_after, &prompt), AgentActivity::Starting);
+        assert_eq!(detect_agent_activity(quiet, "│ >                 │", idle_after, &prompt), AgentActivity::Idle);
+        assert_eq!(detect_agent_activity(quiet, "  ? for shortcuts", idle_after, &prompt), AgentActivity::Idle);
+        // Recent output, or a quiet screen that isn't the prompt, is still working
+        assert_eq!(
+            detect_agent_activity(Some(Duration::from_secs(1)), "│ > ", idle_after, &prompt),
+            AgentActivity::Working
+        );
+        assert_eq!(
+            detect_agent_activity(quiet, "✻ Running tests… (esc to interrupt)", idle_after, &prompt),
+            AgentActivity::Working
+        );
+    }
+
+    #[test]
+    fn test_summary_is_read_from_complete_lines_only() {
+        let mut state = PtyState::new(24, 80);
+        state.append_output(b"running 43 tests\r\ntest result: \x1b[31mFAILED\x1b[0m. 42 passed; 1");
+        state.update_activities();
+        assert_eq!(state.last_test_result, None);
+
+        state.append_output(b" failed; 0 ignored\r\n");
+        state.update_activities();
+        assert_eq!(
+            state.last_test_result,
+            Some(TestResult { framework: "cargo", passed: 42, failed: 1 })
+        );
+
+        // A new iteration starts without a result
+        state.clear_recent_output();
+        assert_eq!(state.last_test_result, None);
+    }
+
+    #[test]
+    fn turn_end_and_completion_use_the_profile_sentinels() {
+        let mut state = PtyState::new(24, 80);
+        state.append_output(b"\x1b[2mRan 1 Stop hook\x1b[0m\r\n");
+        assert!(state.has_stop_hook_signal());
+        assert!(!state.has_completion_signal());
+
+        // An agent without a stop hook only ends its turn by exiting
+        state.stop_sentinels.clear();
+        assert!(!state.has_stop_hook_signal());
+        state.stop_sentinels = vec!["Turn Complete".to_string()];
+        state.append_output(b"turn complete\r\n");
+        assert!(state.has_stop_hook_signal());
+
+        state.completion_sentinel = "ALL DONE".to_string();
+        state.append_output(b"ALL DONE\r\n");
+        assert!(state.has_completion_signal());
+    }
+
+    #[test]
+    fn pty_hangup_is_not_a_read_failure() {
+        #[cfg(unix)]
+        assert!(is_pty_hangup(&std::io::Error::from_raw_os_error(libc::EIO)));
+        assert!(!is_pty_hangup(&std::io::Error::other("broken")));
+    }
+
+    #[test]
+    fn poisoned_state_is_recovered() {
+        let state = Arc::new(Mutex::new(PtyState::new(24, 80)));
+        let thread_state = Arc::clone(&state);
+        let result = thread::spawn(move || {
+            let mut guard = thread_state.lock().unwrap();
+            guard.append_output(b"half a chunk");
+            panic!("vt100 blew up");
+        })
+        .join();
+        assert_eq!(panic_message(result.unwrap_err().as_ref()), "vt100 blew up");
+        assert!(state.is_poisoned());
+
+        // The data written before the panic survives and the poison is cleared
+        let mut guard = lock_pty(&state);
+        assert_eq!(guard.recent_output, "half a chunk");
+        assert!(!guard.child_exited);
+        guard.append_output(b" and more");
+        drop(guard);
+        assert!(!state.is_poisoned());
+        assert_eq!(state.lock().unwrap().recent_output, "half a chunk and more");
+    }
+
+    #[test]
+    fn a_huge_single_line_never_stalls_the_ui() {
+        // A 5MB minified JSON dump, read the way the reader thread reads it
+        let dump: Vec<u8> = b"{\"reading \":\"caf\xc3\xa9\",\"n\":[1,2,3]},".repeat(5 * 1024 * 1024 / 36);
+        assert!(parse_activities(std::str::from_utf8(&dump[..8 * 1024]).unwrap()).is_empty());
+        let state = Arc::new(Mutex::new(PtyState::new(24, 80)));
+        let reader_state = Arc::clone(&state);
+        let reader = thread::spawn(move || {
+            for read in dump.chunks(4096) {
+                feed_output(&reader_state, read);
+            }
+            feed_output(&reader_state, b"\r\n<promise>COMPLETE</promise>\r\n");
+        });
+
+        // What the UI does each frame: lock, parse new output, check for the sentinels
+        let mut slowest = Duration::ZERO;
+        loop {
+            let started = Instant::now();
+            let mut guard = lock_pty(&state);
+            guard.update_activities();
+            guard.has_stop_hook_signal();
+            let done = guard.has_completion_signal();
+            assert!(guard.recent_output.len() <= RECENT_OUTPUT_MAX);
+            drop(guard);
+            slowest = slowest.max(started.elapsed());
+            if done {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        reader.join().unwrap();
+        assert!(slowest < Duration::from_millis(250), "a frame waited {:?}", slowest);
+    }
+
+    #[test]
+    fn feeding_keeps_characters_whole() {
+        let state = Mutex::new(PtyState::new(24, 80));
+        // "a" puts a character across every chunk boundary
+        let text = format!("a{}ok", "é".repeat(PTY_FEED_CHUNK));
+        feed_output(&state, text.as_bytes());
+        assert_eq!(lock_pty(&state).recent_output, text);
+        assert_eq!(utf8_tail(text.as_bytes(), 3), "ok".as_bytes());
+    }
+
+    #[test]
+    fn resizes_wait_for_the_size_to_settle() {
+        let mut debounce = ResizeDebounce::default();
+        // A drag: every frame sees a new size, none reaches the agent
+        assert_eq!(debounce.settle((80, 24), (90, 24)), None);
+        assert_eq!(debounce.settle((80, 24), (100, 24)), None);
+        assert_eq!(debounce.settle((80, 24), (100, 24)), Some((100, 24)));
+        // Dragged back to the PTY's size before settling: nothing to do
+        assert_eq!(debounce.settle((100, 24), (110, 30)), None);
+        assert_eq!(debounce.settle((100, 24), (100, 24)), None);
+        assert_eq!(debounce.settle((100, 24), (110, 30)), None);
+    }
+
+    #[test]
+    fn compactions_are_counted_once_per_redraw_burst() {
+        let mut state = PtyState::new(24, 80);
+        state.append_output(b"\x1b[2m* Compacting conversation... (3s)\x1b[0m\r\n");
+        state.update_activities();
+        state.append_output(b"* Compacting conversation... (9s)\r\nConversation compacted\r\n");
+        state.update_activities();
+        assert_eq!(state.compactions, 1);
+
+        // The same text after a quiet spell is a new compaction
+        state.last_compaction_at = Some(Instant::now() - COMPACTION_QUIET);
+        state.append_output(b"Context low (4% remaining)\n");
+        state.update_activities();
+        assert_eq!(state.output_summary(), OutputSummary { tests: None, compactions: 2 });
+        let events: Vec<SessionEvent> = std::mem::take(&mut state.output_events).into_iter().map(|(_, e)| e).collect();
+        assert_eq!(events, vec![SessionEvent::Compaction { count: 1 }, SessionEvent::Compaction { count: 2 }]);
+
+        // Agents without compaction sentinels never count one, and each iteration starts at 0
+        state.clear_recent_output();
+        assert_eq!(state.compactions, 0);
+        state.compaction_sentinels.clear();
+        state.append_output(b"Compacting conversation\n");
+        state.update_activities();
+        assert_eq!(state.compactions, 0);
+    }
+
+    #[test]
+    fn error_lines_are_collected_with_context() {
+        let mut state = PtyState::new(24, 80);
+        state.append_output(b"Compiling app\r\nchecking\r\n\x1b[31merror[E0308]\x1b[0m: mismatched types\r\n --> src/main.rs:3\r\n");
+        state.update_activities();
+        assert_eq!(state.errors.len(), 1);
+        assert!(state.last_error_at.is_some());
+        assert_eq!(state.errors[0].line, "error[E0308]: mismatched types");
+        assert_eq!(state.errors[0].before, vec!["Compiling app", "checking"]);
+        assert_eq!(state.errors[0].after, vec![" --> src/main.rs:3"]);
+
+        // Trailing context fills in as lines arrive; a redrawn error line isn't counted again
+        state.append_output(b"  |\r\nerror[E0308]: mismatched types\r\nthread 'main' panicked at src/lib.rs:9");
+        state.update_activities();
+        assert_eq!(state.errors.len(), 1);
+        assert_eq!(state.errors[0].after, vec![" --> src/main.rs:3", "  |"]);
+        state.append_output(b"\n");
+        state.update_activities();
+        assert_eq!(state.errors.len(), 2);
+        // Each new error line is queued once for the timeline
+        let lines: Vec<SessionEvent> = std::mem::take(&mut state.output_events).into_iter().map(|(_, e)| e).collect();
+        assert_eq!(
+            lines,
+            vec![
+                SessionEvent::ErrorLine { line: "error[E0308]: mismatched types".to_string() },
+                SessionEvent::ErrorLine { line: "thread 'main' panicked at src/lib.rs:9".to_string() },
+            ]
+        );
+
+        // Custom patterns replace the defaults
+        state.clear_recent_output();
+        assert!(state.errors.is_empty());
+        state.error_patterns = vec!["BOOM".to_string()];
+        state.append_output(b"panicked at x\nBOOM\n");
+        state.update_activities();
+        assert_eq!(state.errors.len(), 1);
+        assert_eq!(state.errors[0].line, "BOOM");
+    }
+
+    #[test]
+    fn activity_keeps_the_end_of_wide_targets_within_width() {
+        let activity = Activity::new("Edit", "src/画面/ログイン_🚀.rs");
+        assert_eq!(activity.format(40), "Edit: src/画面/ログイン_🚀.rs");
+        assert_eq!(activity.format(21), "Edit: …ログイン_🚀.rs");
+        assert_eq!(activity.format(20), "Edit: …グイン_🚀.rs");
+        for target in ["docs/👨‍👩‍👧‍👦/家族.md", "notes/cafe\u{301}.txt", "日本語のファイル名.txt"] {
+            let activity = Activity::new("Write", target);
+            for width in 7..40 {
+                let line = activity.format(width);
+                assert!(display_width(&line) <= width, "{:?} at width {} gave {:?}", target, width, line);
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_modified_keys() {
+        let none = KeyModifiers::NONE;
+        assert_eq!(key_to_pty_bytes(KeyCode::Enter, KeyModifiers::SHIFT, false), Some(b"\n".to_vec()));
+        assert_eq!(key_to_pty_bytes(KeyCode::Enter, KeyModifiers::SHIFT, true), Some(b"\x1b[13;2u".to_vec()));
+        assert_eq!(key_to_pty_bytes(KeyCode::Enter, none, true), Some(b"\r".to_vec()));
+        assert_eq!(key_to_pty_bytes(KeyCode::Left, none, false), Some(b"\x1b[D".to_vec()));
+        assert_eq!(key_to_pty_bytes(KeyCode::Left, KeyModifiers::CONTROL, false), Some(b"\x1b[1;5D".to_vec()));
+        assert_eq!(key_to_pty_bytes(KeyCode::Right, KeyModifiers::ALT, false), Some(b"\x1b[1;3C".to_vec()));
+        assert_eq!(key_to_pty_bytes(KeyCode::Backspace, KeyModifiers::ALT, false), Some(b"\x1b\x7f".to_vec()));
+        assert_eq!(key_to_pty_bytes(KeyCode::Backspace, none, false), Some(b"\x7f".to_vec()));
+    }
+
+    #[test]
+    fn tracks_kitty_keyboard_protocol() {
+        let mut state = PtyState::new(24, 80);
+        assert!(!state.kitty_keyboard);
+        state.append_output(b"\x1b[?u\x1b[>1u> ");
+        assert!(state.kitty_keyboard);
+        state.append_output(b"bye\x1b[<u");
+        assert!(!state.kitty_keyboard);
+        state.append_output(b"\x1b[=1;1u");
+        assert!(state.kitty_keyboard);
+    }
+
+    #[test]
+    fn last_visible_row_skips_blank_rows() {
+        let mut state = PtyState::new(5, 20);
+        state.parser.process(b"first\r\n> \r\n\r\n");
+        assert_eq!(state.last_visible_row().trim_end(), ">");
+    }
+
+    #[test]
+    fn stalled_consumer_drops_input_without_blocking() {
+        let (gate, gate_rx) = mpsc::channel();
+        let writer = PtyWriter::spawn(Box::new(StalledConsumer { gate: gate_rx }));
+
+        let start = Instant::now();
+        let accepted = (0..PTY_WRITE_QUEUE + 2).filter(|_| writer.send(b"x")).count();
+        assert_eq!(accepted, PTY_WRITE_QUEUE);
+        assert!(writer.is_stalled());
+        // Still returns immediately once the queue is full
+        assert!(!writer.send(&[b'y'; PTY_WRITE_CHUNK * 3]));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // Release the blocked write so the thread exits
+        drop(gate);
+    }
+
+    #[test]
+    fn queues_all_of_a_paste_or_none_of_it() {
+        let (gate, gate_rx) = mpsc::channel();
+        let writer = PtyWriter::spawn(Box::new(StalledConsumer { gate: gate_rx }));
+        for _ in 0..PTY_WRITE_QUEUE - 2 {
+            assert!(writer.send(b"x"));
+        }
+
+        // Three chunks don't fit in the two free slots, so none of them is queued
+        assert!(!writer.send(&[b'y'; PTY_WRITE_CHUNK * 3]));
+        assert_eq!(writer.queued(), PTY_WRITE_QUEUE - 2);
+        assert!(writer.send(&[b'z'; PTY_WRITE_CHUNK * 2]));
+        assert_eq!(writer.queued(), PTY_WRITE_QUEUE);
+
+        drop(gate);
+    }
+
+    #[test]
+    fn pending_prompt_waits_for_bracketed_paste() {
+        let state = Mutex::new(PtyState::new(24, 80));
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let writer = PtyWriter::spawn(Box::new(RecordingConsumer(Arc::clone(&written))));
+        let prompt = "line one\nline two\n".repeat(2000);
+        let pending = PendingPrompt::new(&prompt).unwrap();
+        let start = Instant::now();
+
+        assert!(!pending.try_deliver(&state, &writer, start).unwrap());
+        lock_pty(&state).parser.process(b"\x1b[?2004h> ");
+        assert!(pending.try_deliver(&state, &writer, start).unwrap());
+        let expected = [PASTE_START.as_bytes(), prompt.as_bytes(), PASTE_END.as_bytes(), b"\r"].concat();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while written.lock().unwrap().len() < expected.len() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*written.lock().unwrap(), expected);
+
+        // An agent that never turns bracketed paste on is an error, as is a prompt over the queue
+        let quiet = Mutex::new(PtyState::new(24, 80));
+        let err = pending.try_deliver(&quiet, &writer, start + PROMPT_PASTE_TIMEOUT).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(PendingPrompt::new(&"x".repeat(PTY_WRITE_QUEUE * PTY_WRITE_CHUNK)).is_err());
+    }
+
+    #[test]
+    fn delivers_input_in_order() {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let writer = PtyWriter::spawn(Box::new(RecordingConsumer(Arc::clone(&written))));
+        let paste = vec![b'p'; PTY_WRITE_CHUNK + 10];
+        assert!(writer.send(b"ab"));
+        assert!(writer.send(&paste));
+
+        let mut expected = b"ab".to_vec();
+        expected.extend_from_slice(&paste);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while written.lock().unwrap().len() < expected.len() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*written.lock().unwrap(), expected);
+        assert!(!writer.is_stalled());
+    }
+}
@@ -0,0 +1,93 @@
+//! Crash-recovery snapshots of the running session
+//!
+//! While a loop runs, the App publishes its session state (with the `recovery`
+//! section filled in) here about once a second. A background thread writes the
+//! latest one to `.ralph-session.json` every RECOVERY_INTERVAL, the loop writes
+//! it at iteration boundaries, and the panic hook writes it one last time before
+//! restoring the terminal. A clean exit clears the section, so finding one at
+//! startup means the last session ended without cleaning up.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::app::SessionState;
+
+/// How often the background thread writes the latest snapshot
+pub const RECOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Latest published (task directory, session state), and whether it was written yet
+static LATEST: Mutex<Option<(PathBuf, SessionState, bool)>> = Mutex::new(None);
+
+/// Make `state` the snapshot the next write saves for `task_dir`
+pub fn publish(task_dir: PathBuf, state: SessionState) {
+    if let Ok(mut latest) = LATEST.lock() {
+        *latest = Some((task_dir, state, false));
+    }
+}
+
+/// Write the latest snapshot if it changed since the last write
+/// Also used by the panic hook, so a poisoned lock is taken over rather than skipped.
+pub fn write_latest() {
+    let mut latest = LATEST.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some((ref task_dir, ref state, ref mut written)) = *latest
+        && !*written
+    {
+        state.save(task_dir);
+        *written = true;
+    }
+}
+
+/// Stop snapshotting and drop the recovery section from the task's session file
+/// Called when a session ends cleanly.
+pub fn clear(task_dir: &std::path::Path) {
+    let mut latest = LATEST.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    *latest = None;
+    let mut state = SessionState::load(task_dir);
+    if state.recovery.take().is_some() {
+        state.save(task_dir);
+    }
+}
+
+/// Start the thread writing the latest snapshot every RECOVERY_INTERVAL
+pub fn spawn_writer() {
+    std::thread::spawn(|| {
+        loop {
+            std::thread::sleep(RECOVERY_INTERVAL);
+            write_latest();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+    use crate::app::RecoverySnapshot;
+
+    #[test]
+    fn latest_snapshot_is_written_once_and_cleared_on_exit() {
+        let task_dir = TempDir::new("recovery");
+        let state = SessionState {
+            recovery: Some(RecoverySnapshot { current_iteration: 4, ..RecoverySnapshot::default() }),
+            ..SessionState::default()
+        };
+
+        publish(task_dir.to_path_buf(), state);
+        write_latest();
+        assert_eq!(SessionState::load(&task_dir).recovery.unwrap().current_iteration, 4);
+
+        // Nothing new published: the file is left alone
+        std::fs::write(task_dir.join(crate::app::SESSION_STATE_FILE), "{}").unwrap();
+        write_latest();
+        assert!(SessionState::load(&task_dir).recovery.is_none());
+
+        std::fs::remove_file(task_dir.join(crate::app::SESSION_STATE_FILE)).unwrap();
+        let state = SessionState { recovery: Some(RecoverySnapshot::default()), ..SessionState::default() };
+        publish(task_dir.to_path_buf(), state);
+        write_latest();
+        clear(&task_dir);
+        write_latest();
+        assert!(SessionState::load(&task_dir).recovery.is_none());
+    }
+}
@@ -0,0 +1,159 @@
+//! When the running screen is redrawn
+//!
+//! Agent output used to redraw the whole UI on every pass of the main loop,
+//! which pins a core while the agent dumps thousands of lines. Now the output
+//! reader threads only set a `RedrawFlag`; the loop draws when the flag was set
+//! (however many times since the last frame, and at most once per OUTPUT_FRAME),
+//! right after input or a resize, and on a HEARTBEAT so clocks, toasts and
+//! animations keep moving. While the terminal reports it has lost focus, the
+//! heartbeat and the input poll slow to UNFOCUSED_INTERVAL; new output and
+//! regaining focus still draw at once.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Longest the screen goes without a redraw
+pub const HEARTBEAT: Duration = Duration::from_millis(250);
+
+/// Shortest time between frames drawn for new output (the animation tick's period)
+pub const OUTPUT_FRAME: Duration = Duration::from_millis(100);
+
+/// Heartbeat and input poll interval while the terminal is unfocused
+pub const UNFOCUSED_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Set by output reader threads when the screen has something new to show
+#[derive(Debug, Clone, Default)]
+pub struct RedrawFlag(Arc<AtomicBool>);
+
+impl RedrawFlag {
+    pub fn mark(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Whether it was set since the last take, clearing it
+    pub fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+
+    /// Whether it is set, leaving it for the next take
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// The UI loop's side: what asked for a frame since the last one
+#[derive(Debug, Default)]
+pub struct RedrawSchedule {
+    output: RedrawFlag,
+    requested: bool,
+    last_draw: Option<Instant>,
+    // Set by FocusLost, cleared by FocusGained (terminals without focus reporting stay focused)
+    unfocused: bool,
+}
+
+impl RedrawSchedule {
+    /// The flag to hand to an output reader thread
+    pub fn flag(&self) -> RedrawFlag {
+        self.output.clone()
+    }
+
+    /// Draw the next frame regardless of output (input, resize, state change)
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Track the terminal's focus; regaining it draws the next frame straight away
+    pub fn set_focused(&mut self, focused: bool) {
+        if focused && self.unfocused {
+            self.requested = true;
+        }
+        self.unfocused = !focused;
+    }
+
+    pub fn is_focused(&self) -> bool {
+        !self.unfocused
+    }
+
+    /// Output arrived that the screen doesn't show yet
+    pub fn output_pending(&self) -> bool {
+        self.output.is_set()
+    }
+
+    /// How long to wait for input: `focused` normally, UNFOCUSED_INTERVAL while unfocused
+    pub fn poll_interval(&self, focused: Duration) -> Duration {
+        if self.unfocused { UNFOCUSED_INTERVAL } else { focused }
+    }
+
+    /// Whether to draw now; consumes the pending output and requests
+    /// Output that arrives too soon after a frame stays pending for the next one.
+    pub fn due(&mut self) -> bool {
+        let since_draw = self.last_draw.map(|at| at.elapsed());
+        let heartbeat_every = if self.unfocused { UNFOCUSED_INTERVAL } else { HEARTBEAT };
+        let heartbeat = since_draw.is_none_or(|elapsed| elapsed >= heartbeat_every);
+        let output = since_draw.is_none_or(|elapsed| elapsed >= OUTPUT_FRAME) && self.output.take();
+        let due = self.requested || heartbeat || output;
+        if due {
+            // The frame shows that output too
+            self.output.take();
+            self.requested = false;
+            self.last_draw = Some(Instant::now());
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_marks_coalesce_into_one_frame() {
+        let mut schedule = RedrawSchedule::default();
+        // The first frame is always drawn
+        assert!(schedule.due());
+        assert!(!schedule.due());
+
+        // A flood right after a frame waits for OUTPUT_FRAME, then draws once
+        let flag = schedule.flag();
+        for _ in 0..1000 {
+            flag.mark();
+        }
+        assert!(!schedule.due());
+        std::thread::sleep(OUTPUT_FRAME);
+        assert!(schedule.due());
+        assert!(!schedule.due());
+
+        schedule.request();
+        assert!(schedule.due());
+        assert!(!schedule.due());
+
+        std::thread::sleep(HEARTBEAT);
+        assert!(schedule.due());
+    }
+
+    #[test]
+    fn unfocused_screens_slow_down_but_still_show_output() {
+        let mut schedule = RedrawSchedule::default();
+        assert!(schedule.is_focused());
+        assert_eq!(schedule.poll_interval(OUTPUT_FRAME), OUTPUT_FRAME);
+        assert!(schedule.due());
+
+        schedule.set_focused(false);
+        assert_eq!(schedule.poll_interval(OUTPUT_FRAME), UNFOCUSED_INTERVAL);
+        std::thread::sleep(HEARTBEAT);
+        assert!(!schedule.due());
+
+        schedule.flag().mark();
+        assert!(schedule.output_pending());
+        assert!(schedule.due());
+        assert!(!schedule.output_pending());
+
+        // Losing focus twice doesn't ask for a frame, regaining it does
+        schedule.set_focused(false);
+        assert!(!schedule.due());
+        schedule.set_focused(true);
+        assert!(schedule.due());
+        assert!(schedule.is_focused());
+    }
+}
@@ -0,0 +1,3078 @@
+//! Frame rendering for the running and between-iteration screens
+//!
+//! Everything here is a pure function of an `AppSnapshot` (or, before the loop
+//! starts, the `Preflight` checklist) and the frame area, so frames can be
+//! rendered against a TestBackend without a PTY or files.
+
+pub mod terminal;
+pub mod widgets;
+
+use ratatui::{
+    Frame,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph},
+};
+
+use crate::app::{AppSnapshot, FollowMode, IterationEndSource, IterationState, Mode, RalphViewMode, StoryAction, StoryListRow, PROGRESS_STRIP_LINES};
+use crate::changes::{ChangedFile, FileDiff, IterationChanges, DIFF_MAX_LINES, stat_bar};
+use crate::keymap::KeyAction;
+use crate::preflight::Preflight;
+use crate::prd_diff::{self, StoryChange, StoryDiff};
+use crate::preview::{FilePreview, PreviewContent, PREVIEW_MAX_LINES, is_comment_line};
+use crate::prd::{AcceptanceCriterion, find_working_criterion, story_progress_lines};
+use crate::theme::*;
+use crate::VERSION;
+use widgets::*;
+
+/// Height of the progress.txt tail strip (title rule + tail lines)
+pub const PROGRESS_STRIP_HEIGHT: u16 = PROGRESS_STRIP_LINES as u16 + 1;
+
+/// Smallest terminal the layout supports; anything smaller gets a resize message instead
+pub const MIN_WIDTH: u16 = 80;
+pub const MIN_HEIGHT: u16 = 20;
+
+/// Draw "Terminal too small" centered in the frame when it is under MIN_WIDTH x MIN_HEIGHT
+/// Returns whether it did, in which case the normal layout must be skipped.
+fn draw_too_small(frame: &mut Frame) -> bool {
+    let area = frame.area();
+    if area.width >= MIN_WIDTH && area.height >= MIN_HEIGHT {
+        return false;
+    }
+    let message = format!(
+        "Terminal too small: need {}x{}, have {}x{}",
+        MIN_WIDTH, MIN_HEIGHT, area.width, area.height
+    );
+    let lines: Vec<Line> = wrap_text(&message, area.width as usize)
+        .into_iter()
+        .map(|line| Line::from(Span::styled(line, Style::default().fg(AMBER_WARNING))))
+        .collect();
+    let height = (lines.len() as u16).min(area.height);
+    let message_area = Rect {
+        y: area.y + (area.height - height) / 2,
+        height,
+        ..area
+    };
+    frame.render_widget(Block::default().style(Style::default().bg(BG_PRIMARY)), area);
+    frame.render_widget(Paragraph::new(lines).alignment(Alignment::Center), message_area);
+    true
+}
+
+/// Split off the bottom footer and, when shown, the progress.txt strip above it
+/// Returns (content, strip, footer) areas; the strip is empty when hidden.
+fn split_footer(area: Rect, progress_strip: bool) -> (Rect, Rect, Rect) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(3), // Main content area
+            Constraint::Length(if progress_strip { PROGRESS_STRIP_HEIGHT } else { 0 }), // progress.txt tail
+            Constraint::Length(1), // Bottom bar (single line)
+        ])
+        .split(area);
+    (layout[0], layout[1], layout[2])
+}
+
+/// Story counts for the COMPLETED card: (completed, total) overall, or split into
+/// agent and human counts when people are assigned some of the stories
+fn assignee_counts(snapshot: &AppSnapshot, completed: usize, total: usize) -> ((usize, usize), Option<(usize, usize)>) {
+    match snapshot.prd.as_ref().map(|prd| (prd.agent_progress(), prd.human_progress())) {
+        Some((agent, human)) if human.1 > 0 => (agent, Some(human)),
+        _ => ((completed, total), None),
+    }
+}
+
+/// Split the content area into the Ralph Status panel (30%) and the right side (70%)
+/// With the sidebar hidden the right side gets the full width.
+fn split_panels(content_area: Rect, sidebar_hidden: bool) -> (Rect, Rect) {
+    if sidebar_hidden {
+        return (Rect { width: 0, ..content_area }, content_area);
+    }
+    let panels = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30), // Ralph Status panel
+            Constraint::Percentage(70), // Claude Code panel
+        ])
+        .split(content_area);
+    (panels[0], panels[1])
+}
+
+/// Footer keybindings led by the essentials of the hidden sidebar: "It 3/10 · 2/5 done · US-003"
+fn with_sidebar_summary(snapshot: &AppSnapshot, keybindings_text: String) -> String {
+    if !snapshot.sidebar_hidden {
+        return keybindings_text;
+    }
+    let mut parts = vec![format!("It {}/{}", snapshot.current_iteration, snapshot.max_iterations)];
+    if let Some(ref prd) = snapshot.prd {
+        parts.push(format!("{}/{} done", prd.completed_count(), prd.story_total()));
+        if let Some(story) = prd.current_story() {
+            parts.push(story.id.clone());
+        }
+    }
+    format!("{} | {}", parts.join(" · "), keybindings_text)
+}
+
+/// One row of the navigation hints: "s Story  p Progress  r Reqs"
+fn key_hints(hints: &[(String, &'static str)]) -> Line<'static> {
+    let mut spans = Vec::new();
+    for (index, (key, label)) in hints.iter().enumerate() {
+        let gap = if index + 1 < hints.len() { "  " } else { "" };
+        spans.push(Span::styled(key.clone(), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)));
+        spans.push(Span::styled(format!(" {}{}", label, gap), Style::default().fg(TEXT_MUTED)));
+    }
+    Line::from(spans)
+}
+
+/// Banner lines for a header warning on a `color` background, wrapped to `width`
+fn warning_banner(warning: &str, color: Color, width: u16) -> Vec<Line<'static>> {
+    wrap_text(&format!("⚠ {}", warning), width as usize)
+        .into_iter()
+        .map(|line| {
+            let line = format!("{:<width$}", line, width = width as usize);
+            Line::from(Span::styled(line, Style::default().fg(BG_PRIMARY).bg(color)))
+        })
+        .collect()
+}
+
+/// Left panel header: Ralph branding, the PRD's project · type · schema, and an amber
+/// warning when the schema is newer than (or not) one this ralph-tui understands, another
+/// while disk space is low, and red ones while prd.json is missing or doesn't parse and once
+/// the agent has written outside the repository
+fn header_lines(snapshot: &AppSnapshot, width: u16) -> Vec<Line<'static>> {
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled("● ", Style::default().fg(GREEN_ACTIVE)),
+            Span::styled("RALPH LOOP", Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)),
+        ]),
+        Line::from(vec![
+            Span::styled(format!("Terminal v{}", VERSION), Style::default().fg(CYAN_PRIMARY)),
+        ]),
+    ];
+    if let Some(ref prd) = snapshot.prd {
+        let mut parts: Vec<&str> = [prd.project.as_str(), prd.prd_type.as_str()]
+            .into_iter()
+            .filter(|part| !part.trim().is_empty())
+            .collect();
+        let schema = format!("schema v{}", prd.schema_version);
+        parts.push(&schema);
+        lines.push(Line::from(Span::styled(
+            truncate_to_width(&parts.join(" · "), width as usize),
+            Style::default().fg(TEXT_SECONDARY),
+        )));
+        if let Some(warning) = prd.schema_warning() {
+            lines.extend(warning_banner(&warning, AMBER_WARNING, width));
+        }
+    }
+    if let Some(ref warning) = snapshot.disk_warning {
+        lines.extend(warning_banner(warning, AMBER_WARNING, width));
+    }
+    if let Some(ref warning) = snapshot.prd_warning {
+        lines.extend(warning_banner(warning, RED_ERROR, width));
+    }
+    if let Some(ref warning) = snapshot.scope_warning {
+        lines.extend(warning_banner(warning, RED_ERROR, width));
+    }
+    lines.push(Line::from("")); // Gap after header
+    lines
+}
+
+/// Draw the main screen while an iteration runs
+/// Returns the story list scroll offset adjusted to keep the selection visible.
+pub fn draw_running(frame: &mut Frame, snapshot: &AppSnapshot) -> usize {
+    if draw_too_small(frame) {
+        return snapshot.story_scroll_offset;
+    }
+    let area = frame.area();
+
+    // Create main layout: content area + progress strip (P) + bottom bar
+    let (content_area, strip_area, bottom_bar_area) = split_footer(area, snapshot.progress_strip);
+    if snapshot.progress_strip {
+        render_progress_strip(strip_area, &snapshot.progress_tail, snapshot.progress_highlighted, frame);
+    }
+
+    // Create horizontal split: 30% left panel, 70% right panel (all of it without the sidebar)
+    let (left_panel_area, right_panel_area) = split_panels(content_area, snapshot.sidebar_hidden);
+
+    // Determine border styles based on current mode
+    let (left_border_style, right_border_style) = match snapshot.mode {
+        Mode::Ralph => (
+            Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+            Style::default().fg(BORDER_SUBTLE),
+        ),
+        Mode::Claude => (
+            Style::default().fg(BORDER_SUBTLE),
+            Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+        ),
+    };
+
+    // Left panel: Ralph Status (hidden with --no-sidebar / the sidebar toggle)
+    let story_scroll_offset = if snapshot.sidebar_hidden {
+        snapshot.story_scroll_offset
+    } else {
+        draw_status_panel(frame, snapshot, left_panel_area, left_border_style)
+    };
+
+    // Right panel: Two separate terminals (Ralph on top, Claude on bottom)
+    // Each terminal is its own bordered section
+
+    // Ralph terminal height follows the chosen size (+/-), detail views included
+    let ralph_terminal_height = snapshot.ralph_size.height();
+
+    // Split right panel directly into Ralph terminal (top) and Claude terminal (bottom)
+    let terminal_split = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(ralph_terminal_height),  // Ralph terminal (top)
+            Constraint::Min(0),  // Claude terminal (takes remaining space, bottom)
+        ])
+        .split(right_panel_area);
+
+    let ralph_terminal_area = terminal_split[0];
+    let claude_terminal_area = terminal_split[1];
+
+    // === CLAUDE TERMINAL ===
+    // --parallel: one stacked pane per lane instead of the single Claude terminal
+    if !snapshot.lanes.is_empty() {
+        let lane_areas = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Ratio(1, snapshot.lanes.len() as u32); snapshot.lanes.len()])
+            .split(claude_terminal_area);
+        for (lane, lane_area) in snapshot.lanes.iter().zip(lane_areas.iter()) {
+            render_lane_pane(*lane_area, lane, snapshot.mode, snapshot.animation_tick, frame);
+        }
+    } else {
+        // Create bordered block for Claude terminal
+        let activity_chip = agent_activity_chip(snapshot.agent_activity, snapshot.animation_tick);
+        let mut claude_title = Line::from(" >_ claude-code - ralph-loop ");
+        if let Some(ref model) = snapshot.model {
+            claude_title.push_span(Span::styled(format!("({}) ", model), Style::default().fg(TEXT_SECONDARY)));
+        }
+        // A release outside the tested range is amber: the output parsing may misbehave
+        if let Some(ref version) = snapshot.agent_version {
+            let (text, color) = match version.warning() {
+                Some(_) => (format!("v{} untested ", version.short()), AMBER_WARNING),
+                None => (format!("v{} ", version.short()), TEXT_MUTED),
+            };
+            claude_title.push_span(Span::styled(text, Style::default().fg(color)));
+        }
+        // show_pty_size: the size the agent was given, to compare with the panel after resizes
+        if let Some((cols, rows)) = snapshot.pty_size {
+            claude_title.push_span(Span::styled(format!("{}×{} ", cols, rows), Style::default().fg(TEXT_MUTED)));
+        }
+        if snapshot.mode == Mode::Claude {
+            claude_title.push_span(Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)));
+            claude_title.push_span(Span::raw(" "));
+        }
+        claude_title.push_span(activity_chip);
+        if snapshot.error_count > 0 {
+            claude_title.push_span(Span::raw(" "));
+            claude_title.push_span(Span::styled(
+                format!(" errors: {} ", snapshot.error_count),
+                Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD),
+            ));
+        }
+        // A new error line flashes the border red for a moment
+        let claude_border_style = if snapshot.error_flash {
+            Style::default().fg(RED_ERROR)
+        } else {
+            right_border_style
+        };
+        let claude_block = Block::default()
+            .title(claude_title)
+            .borders(Borders::ALL)
+            .border_style(claude_border_style)
+            .style(Style::default().bg(BG_PRIMARY));
+
+        let claude_content_area = claude_block.inner(claude_terminal_area);
+        frame.render_widget(claude_block, claude_terminal_area);
+
+        // Claude terminal content (VT100 rendered, scrolled into history by the snapshot) - uses full inner area
+        let lines = if let Some(ref lines) = snapshot.claude_lines {
+            lines.clone()
+        } else {
+            vec![Line::from(Span::styled(
+                "Error: Failed to access PTY state",
+                Style::default().fg(RED_ERROR),
+            ))]
+        };
+
+        // Scroll to show the bottom of the terminal output (most recent content)
+        // When claude_scroll_offset is 0, we're at the bottom (current view)
+        // When claude_scroll_offset > 0, we're viewing history
+        let content_height = claude_content_area.height as usize;
+        let scroll_offset = if snapshot.claude_scroll_offset == 0 && lines.len() > content_height {
+            (lines.len() - content_height) as u16
+        } else {
+            0
+        };
+
+        let claude_content = Paragraph::new(lines)
+            .scroll((scroll_offset, 0));
+        frame.render_widget(claude_content, claude_content_area);
+
+        if snapshot.input_stalled {
+            render_input_stalled_banner(Rect { height: claude_content_area.height.min(1), ..claude_content_area }, frame);
+        }
+        if snapshot.reader_error && claude_content_area.height > 0 {
+            let last_row = claude_content_area.bottom() - 1;
+            render_reader_error_banner(Rect { y: last_row, height: 1, ..claude_content_area }, frame);
+        }
+    }
+
+    // === RALPH TERMINAL ===
+    // Create bordered block for Ralph terminal
+    let ralph_title = match snapshot.mode {
+        Mode::Ralph => Line::from(vec![
+            Span::raw(" >_ ralph output "),
+            Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)),
+            Span::raw(" "),
+        ]),
+        Mode::Claude => Line::from(" >_ ralph output "),
+    };
+    let ralph_border_style = match snapshot.mode {
+        Mode::Ralph => Style::default().fg(CYAN_PRIMARY),
+        Mode::Claude => Style::default().fg(BORDER_SUBTLE),
+    };
+    let ralph_block = Block::default()
+        .title(ralph_title)
+        .borders(Borders::ALL)
+        .border_style(ralph_border_style)
+        .style(Style::default().bg(BG_PRIMARY));
+
+    let ralph_content_area = ralph_block.inner(ralph_terminal_area);
+    frame.render_widget(ralph_block, ralph_terminal_area);
+
+    // Ralph terminal content (based on view mode)
+    let ralph_content_lines: Vec<Line> = match snapshot.ralph_view_mode {
+        RalphViewMode::Normal => {
+            // Show ASCII logo and status
+            vec![
+                Line::from(vec![
+                    Span::styled("  ▶▶ ", Style::default().fg(GREEN_ACTIVE)),
+                    Span::styled("RALPH LOOP", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+                    Span::styled(" ◀◀", Style::default().fg(GREEN_ACTIVE)),
+                ]),
+                Line::from(Span::styled(
+                    format!("     Iteration {}/{}", snapshot.current_iteration, snapshot.max_iterations),
+                    Style::default().fg(TEXT_MUTED),
+                )),
+                // Injected variable names only - values may be secrets
+                Line::from(vec![
+                    Span::styled("     Env: ", Style::default().fg(TEXT_MUTED)),
+                    Span::styled(
+                        snapshot.env_names.join(", "),
+                        Style::default().fg(TEXT_SECONDARY),
+                    ),
+                ]),
+                Line::from(vec![
+                    Span::styled("     Prompt: ", Style::default().fg(TEXT_MUTED)),
+                    Span::styled(
+                        snapshot.prompt_source.clone().unwrap_or_else(|| "embedded default".to_string()),
+                        Style::default().fg(TEXT_SECONDARY),
+                    ),
+                ]),
+            ]
+        }
+        RalphViewMode::StoryDetails => {
+            // Show selected story details from prd.json
+            if let Some(ref prd) = snapshot.prd {
+                if let Some(story) = snapshot.story_index().story(prd, snapshot.selected_story_index) {
+                    let status_text = if story.passes { "✓ PASSED" } else { "○ PENDING" };
+                    let status_color = if story.passes { GREEN_SUCCESS } else { AMBER_WARNING };
+                    let mut lines = vec![
+                        Line::from(vec![
+                            Span::styled(format!("  {} ", story.id), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+                            Span::styled(status_text, Style::default().fg(status_color)),
+                        ]),
+                        Line::from(Span::styled(format!("  {}", story.title), Style::default().fg(TEXT_PRIMARY))),
+                    ];
+                    if story.passes {
+                        let completed_text = match snapshot.session_state.completion(&story.id) {
+                            Some(completion) => format!(
+                                "  Completed: iteration {} at {} UTC",
+                                completion.iteration,
+                                format_clock_utc(completion.completed_at)
+                            ),
+                            None => "  Completed: before this session".to_string(),
+                        };
+                        lines.push(Line::from(Span::styled(completed_text, Style::default().fg(TEXT_MUTED))));
+                    }
+                    // Criterion the agent reports working on in progress.txt (v2.0 ids only)
+                    let working_on = snapshot
+                        .progress
+                        .as_deref()
+                        .and_then(|content| find_working_criterion(content, &story.acceptance_criteria));
+                    // Add all acceptance criteria (scrollable)
+                    lines.push(Line::from(Span::styled("  ─── Acceptance Criteria ───", Style::default().fg(BORDER_SUBTLE))));
+                    // The criterion cursor (h/l) is marked with ›, the focused criterion (F) with ◎
+                    let focused = snapshot
+                        .session_state
+                        .focused_criterion
+                        .as_ref()
+                        .filter(|focus| focus.story_id == story.id)
+                        .map(|focus| focus.criterion);
+                    let mut has_evidence = false;
+                    let has_verify = story.acceptance_criteria.iter().any(|c| c.verify.is_some());
+                    for (i, criterion) in story.acceptance_criteria.iter().enumerate() {
+                        let check = if criterion.passes { "✓" } else { "○" };
+                        let check_color = if criterion.passes { GREEN_SUCCESS } else { TEXT_MUTED };
+                        let is_working = working_on.is_some() && criterion.id == working_on;
+                        let text_color = if is_working {
+                            get_pulse_color(snapshot.animation_tick, GREEN_ACTIVE, CYAN_DIM)
+                        } else {
+                            TEXT_SECONDARY
+                        };
+                        let cursor = if i == snapshot.selected_criterion { "› " } else { "  " };
+                        let mut spans = vec![Span::styled(cursor, Style::default().fg(CYAN_PRIMARY))];
+                        if let Some(ref id) = criterion.id {
+                            // v2.0: "AC-3 ✓ description"
+                            spans.push(Span::styled(format!("{} ", id), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)));
+                        }
+                        spans.push(Span::styled(format!("{} ", check), Style::default().fg(check_color)));
+                        if focused == Some(i) {
+                            spans.push(Span::styled("◎ ", Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD)));
+                        }
+                        let description = match criterion.id {
+                            Some(_) => criterion.description.clone(),
+                            None => format!("{}. {}", i + 1, criterion.description),
+                        };
+                        spans.push(Span::styled(description, Style::default().fg(text_color)));
+                        // Passed without a `Verified:` note in progress.txt to back it (check_verified_notes)
+                        if snapshot.unverified.get(&story.id).is_some_and(|missing| missing.contains(&i)) {
+                            spans.push(Span::styled(" unverified", Style::default().fg(AMBER_WARNING)));
+                        }
+                        lines.push(Line::from(spans));
+                        // Evidence the agent recorded for a passed criterion, dimmed (e shows it in full)
+                        if criterion.passes
+                            && let Some(ref evidence) = criterion.evidence
+                        {
+                            has_evidence = true;
+                            let text = match criterion.verified_at {
+                                Some(ref at) => format!("    ↳ verified {}: {}", at, evidence.lines().next().unwrap_or_default()),
+                                None => format!("    ↳ {}", evidence.lines().next().unwrap_or_default()),
+                            };
+                            lines.push(Line::from(Span::styled(
+                                truncate_to_width(&text, ralph_content_area.width as usize),
+                                Style::default().fg(TEXT_MUTED).add_modifier(Modifier::DIM),
+                            )));
+                        } else if !criterion.passes
+                            && let Some(ref command) = criterion.verify
+                        {
+                            // The command V (or verify_criteria) runs to check it
+                            lines.push(Line::from(Span::styled(
+                                truncate_to_width(&format!("    $ {}", command), ralph_content_area.width as usize),
+                                Style::default().fg(TEXT_MUTED).add_modifier(Modifier::DIM),
+                            )));
+                        }
+                    }
+                    let criteria_hint = match (has_evidence, has_verify) {
+                        (true, true) => Some("  h/l pick a criterion · e full evidence · V run verify commands"),
+                        (true, false) => Some("  h/l pick a criterion · e full evidence"),
+                        (false, true) => Some("  V run verify commands"),
+                        (false, false) => None,
+                    };
+                    if let Some(hint) = criteria_hint {
+                        lines.push(Line::from(Span::styled(hint, Style::default().fg(TEXT_MUTED))));
+                    }
+                    // Add description if present
+                    if !story.description.is_empty() {
+                        lines.push(Line::from(""));
+                        lines.push(Line::from(Span::styled("  ─── Description ───", Style::default().fg(BORDER_SUBTLE))));
+                        lines.push(Line::from(Span::styled(format!("  {}", story.description), Style::default().fg(TEXT_MUTED))));
+                    }
+                    // Add notes if present
+                    if !story.notes.is_empty() {
+                        lines.push(Line::from(""));
+                        lines.push(Line::from(Span::styled("  ─── Notes ───", Style::default().fg(BORDER_SUBTLE))));
+                        lines.push(Line::from(Span::styled(format!("  {}", story.notes), Style::default().fg(TEXT_MUTED))));
+                    }
+                    lines
+                } else {
+                    vec![Line::from(Span::styled("  No story selected", Style::default().fg(TEXT_MUTED)))]
+                }
+            } else {
+                vec![Line::from(Span::styled("  No PRD loaded", Style::default().fg(TEXT_MUTED)))]
+            }
+        }
+        RalphViewMode::Progress => {
+            // Show progress.txt entries for selected story
+            if let Some(ref prd) = snapshot.prd {
+                if let Some(story) = snapshot.story_index().story(prd, snapshot.selected_story_index) {
+                    if let Some(ref content) = snapshot.progress {
+                        // Find entries containing the story ID
+                        let story_id = &story.id;
+                        let mut matching_lines: Vec<Line> = vec![
+                            Line::from(vec![
+                                Span::styled("  Progress for ", Style::default().fg(TEXT_MUTED)),
+                                Span::styled(story_id.clone(), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+                            ]),
+                        ];
+
+                        let entries = story_progress_lines(content, story_id);
+                        let found_any = entries.is_some();
+                        for line in entries.unwrap_or_default() {
+                            // Show full line (scrollable)
+                            matching_lines.push(Line::from(Span::styled(
+                                format!("  {}", line),
+                                Style::default().fg(TEXT_SECONDARY),
+                            )));
+                        }
+
+                        if !found_any {
+                            matching_lines.push(Line::from(Span::styled(
+                                "  No progress entries found",
+                                Style::default().fg(TEXT_MUTED),
+                            )));
+                        }
+                        matching_lines
+                    } else {
+                        vec![Line::from(Span::styled("  progress.txt not found", Style::default().fg(TEXT_MUTED)))]
+                    }
+                } else {
+                    vec![Line::from(Span::styled("  No story selected", Style::default().fg(TEXT_MUTED)))]
+                }
+            } else {
+                vec![Line::from(Span::styled("  No PRD loaded", Style::default().fg(TEXT_MUTED)))]
+            }
+        }
+        RalphViewMode::Requirements => {
+            // Show requirements from prd.md for selected story
+            if let Some(ref prd) = snapshot.prd {
+                if let Some(story) = snapshot.story_index().story(prd, snapshot.selected_story_index) {
+                    if let Some(ref content) = snapshot.requirements {
+                        let story_id = &story.id;
+                        let story_title = &story.title;
+                        let mut matching_lines: Vec<Line> = vec![
+                            Line::from(vec![
+                                Span::styled("  Requirements for ", Style::default().fg(TEXT_MUTED)),
+                                Span::styled(story_id.clone(), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+                            ]),
+                        ];
+
+                        // Find section that mentions this story ID or title
+                        let mut in_matching_section = false;
+                        let mut found_any = false;
+                        for line in content.lines() {
+                            // Look for headers containing story ID or title
+                            if (line.contains(story_id) || line.contains(story_title.as_str()))
+                                && (line.starts_with("#") || line.starts_with("##"))
+                            {
+                                in_matching_section = true;
+                                found_any = true;
+                                continue;
+                            } else if line.starts_with("#") {
+                                in_matching_section = false;
+                            }
+
+                            if in_matching_section && !line.is_empty() {
+                                // Show full line (scrollable)
+                                matching_lines.push(Line::from(Span::styled(
+                                    format!("  {}", line),
+                                    Style::default().fg(TEXT_SECONDARY),
+                                )));
+                            }
+                        }
+
+                        if !found_any {
+                            matching_lines.push(Line::from(Span::styled(
+                                "  No requirements section found in prd.md",
+                                Style::default().fg(TEXT_MUTED),
+                            )));
+                        }
+                        matching_lines
+                    } else {
+                        vec![Line::from(Span::styled("  prd.md not found", Style::default().fg(TEXT_MUTED)))]
+                    }
+                } else {
+                    vec![Line::from(Span::styled("  No story selected", Style::default().fg(TEXT_MUTED)))]
+                }
+            } else {
+                vec![Line::from(Span::styled("  No PRD loaded", Style::default().fg(TEXT_MUTED)))]
+            }
+        }
+        RalphViewMode::Evidence => {
+            // Full evidence for the criterion under the details view's cursor
+            let story = snapshot.prd.as_ref().and_then(|prd| snapshot.story_index().story(prd, snapshot.selected_story_index));
+            match story.and_then(|story| story.acceptance_criteria.get(snapshot.selected_criterion).map(|c| (story, c))) {
+                Some((story, criterion)) => {
+                    let mut lines = vec![
+                        Line::from(vec![
+                            Span::styled(format!("  {} ", story.id), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+                            Span::styled(
+                                format!("criterion {}", criterion.label(snapshot.selected_criterion)),
+                                Style::default().fg(TEXT_MUTED),
+                            ),
+                        ]),
+                        Line::from(Span::styled(format!("  {}", criterion.description), Style::default().fg(TEXT_PRIMARY))),
+                    ];
+                    if let Some(ref at) = criterion.verified_at {
+                        lines.push(Line::from(Span::styled(format!("  Verified: {}", at), Style::default().fg(TEXT_MUTED))));
+                    }
+                    lines.push(Line::from(Span::styled("  ─── Evidence ───", Style::default().fg(BORDER_SUBTLE))));
+                    match criterion.evidence {
+                        Some(ref evidence) => {
+                            let width = (ralph_content_area.width as usize).saturating_sub(2).max(1);
+                            for line in evidence.lines().flat_map(|line| wrap_text(line, width)) {
+                                lines.push(Line::from(Span::styled(format!("  {}", line), Style::default().fg(TEXT_SECONDARY))));
+                            }
+                        }
+                        None => lines.push(Line::from(Span::styled(
+                            "  No evidence recorded for this criterion",
+                            Style::default().fg(TEXT_MUTED),
+                        ))),
+                    }
+                    lines
+                }
+                None => vec![Line::from(Span::styled("  No criterion selected", Style::default().fg(TEXT_MUTED)))],
+            }
+        }
+        RalphViewMode::Blockers => {
+            // Show each open blocker with the progress.txt lines around it
+            const CONTEXT_LINES: usize = 2;
+            let progress_lines: Vec<&str> = snapshot.progress.as_deref().map(|p| p.lines().collect()).unwrap_or_default();
+            let mut lines = vec![Line::from(Span::styled(
+                format!("  Needs attention ({})", snapshot.blockers.len()),
+                Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD),
+            ))];
+            for blocker in &snapshot.blockers {
+                lines.push(Line::from(""));
+                lines.push(Line::from(Span::styled(
+                    format!("  {} (progress.txt line {})", blocker.label, blocker.line + 1),
+                    Style::default().fg(TEXT_MUTED),
+                )));
+                let first = blocker.line.saturating_sub(CONTEXT_LINES);
+                let last = (blocker.line + CONTEXT_LINES).min(progress_lines.len().saturating_sub(1));
+                for (index, line) in progress_lines.iter().enumerate().take(last + 1).skip(first) {
+                    let style = if index == blocker.line {
+                        Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(TEXT_SECONDARY)
+                    };
+                    lines.push(Line::from(Span::styled(format!("  {}", line), style)));
+                }
+            }
+            if snapshot.blockers.is_empty() {
+                lines.push(Line::from(Span::styled("  No open blockers", Style::default().fg(TEXT_MUTED))));
+            }
+            lines
+        }
+        RalphViewMode::Errors => {
+            // Show each error line matched this iteration with the output around it
+            let mut lines = vec![Line::from(Span::styled(
+                format!("  Errors this iteration ({})", snapshot.errors.len()),
+                Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD),
+            ))];
+            for error in &snapshot.errors {
+                lines.push(Line::from(""));
+                for line in &error.before {
+                    lines.push(Line::from(Span::styled(format!("  {}", line), Style::default().fg(TEXT_SECONDARY))));
+                }
+                lines.push(Line::from(Span::styled(
+                    format!("  {}", error.line),
+                    Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD),
+                )));
+                for line in &error.after {
+                    lines.push(Line::from(Span::styled(format!("  {}", line), Style::default().fg(TEXT_SECONDARY))));
+                }
+            }
+            if snapshot.errors.is_empty() {
+                lines.push(Line::from(Span::styled("  No errors this iteration", Style::default().fg(TEXT_MUTED))));
+            }
+            lines
+        }
+        RalphViewMode::Journal => {
+            // Show the TUI's prd.json edits, newest first, with what they replaced
+            let mut lines = vec![Line::from(Span::styled(
+                format!("  prd.json edits this session ({})", snapshot.prd_journal.len()),
+                Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+            ))];
+            lines.push(Line::from(""));
+            for edit in snapshot.prd_journal.iter().rev() {
+                let label_style = if edit.undone {
+                    Style::default().fg(TEXT_MUTED).add_modifier(Modifier::CROSSED_OUT)
+                } else {
+                    Style::default().fg(TEXT_PRIMARY)
+                };
+                let mut spans = vec![
+                    Span::styled(format!("  {} UTC  ", format_clock_utc(edit.at)), Style::default().fg(TEXT_MUTED)),
+                    Span::styled(edit.label.clone(), label_style),
+                ];
+                // Whole-document edits (restores) are too big to show inline
+                if !edit.pointer.is_empty() {
+                    spans.push(Span::styled(
+                        format!("  {} → {}", edit.old, edit.new),
+                        Style::default().fg(TEXT_SECONDARY),
+                    ));
+                }
+                if edit.undone {
+                    spans.push(Span::styled("  (undone)", Style::default().fg(TEXT_MUTED)));
+                }
+                lines.push(Line::from(spans));
+            }
+            if snapshot.prd_journal.is_empty() {
+                lines.push(Line::from(Span::styled("  No edits yet", Style::default().fg(TEXT_MUTED))));
+            }
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled("  u z: undo the latest edit", Style::default().fg(TEXT_MUTED))));
+            lines
+        }
+        RalphViewMode::Iterations => {
+            // Show the iteration start markers, newest first, with the picked one highlighted
+            let mut lines = vec![Line::from(Span::styled(
+                format!("  Iteration start markers ({})", snapshot.iteration_markers.len()),
+                Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+            ))];
+            lines.push(Line::from(""));
+            for (index, marker) in snapshot.iteration_markers.iter().enumerate().rev() {
+                let selected = index == snapshot.selected_marker;
+                let mut spans = vec![
+                    Span::styled(if selected { "  ▸ " } else { "    " }, Style::default().fg(CYAN_PRIMARY)),
+                    Span::styled(
+                        format!("iteration {}", marker.iteration),
+                        if selected {
+                            Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)
+                        } else {
+                            Style::default().fg(TEXT_PRIMARY)
+                        },
+                    ),
+                    Span::styled(format!("  {} UTC  ", format_clock_utc(marker.at)), Style::default().fg(TEXT_MUTED)),
+                    Span::styled(
+                        marker.head[..marker.head.len().min(7)].to_string(),
+                        Style::default().fg(TEXT_SECONDARY),
+                    ),
+                ];
+                if marker.stash.is_some() {
+                    spans.push(Span::styled("  + uncommitted changes", Style::default().fg(TEXT_MUTED)));
+                }
+                if let Some(&source) = snapshot.iteration_ends.get(&marker.iteration) {
+                    spans.push(Span::styled(
+                        format!("  ended: {}", source.description()),
+                        Style::default().fg(end_source_color(source)),
+                    ));
+                }
+                lines.push(Line::from(spans));
+            }
+            if snapshot.iteration_markers.is_empty() {
+                let hint = if snapshot.not_git_repo {
+                    format!("  No markers {}", NOT_GIT_REPO)
+                } else {
+                    "  No markers yet (set snapshotEachIteration in prd.json)".to_string()
+                };
+                lines.push(Line::from(Span::styled(hint, Style::default().fg(TEXT_MUTED))));
+            }
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                "  h/l: pick a marker │ R: roll back to it (git reset --hard, asks first)",
+                Style::default().fg(TEXT_MUTED),
+            )));
+            lines
+        }
+        RalphViewMode::Activity => {
+            // Show the recent activities, newest first, with the picked one highlighted
+            let mut lines = vec![Line::from(Span::styled(
+                format!("  Recent activity ({})", snapshot.activities.len()),
+                Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+            ))];
+            lines.push(Line::from(""));
+            let width = (ralph_content_area.width as usize).saturating_sub(4).max(1);
+            let selected_activity = snapshot.selected_activity.min(snapshot.activities.len().saturating_sub(1));
+            for (index, activity) in snapshot.activities.iter().enumerate() {
+                let selected = index == selected_activity;
+                lines.push(Line::from(vec![
+                    Span::styled(if selected { "  ▸ " } else { "    " }, Style::default().fg(CYAN_PRIMARY)),
+                    Span::styled(
+                        activity.format(width),
+                        match (selected, activity.out_of_scope) {
+                            (true, true) => Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD),
+                            (true, false) => Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD),
+                            (false, true) => Style::default().fg(RED_ERROR),
+                            (false, false) => Style::default().fg(TEXT_SECONDARY),
+                        },
+                    ),
+                ]));
+            }
+            if snapshot.activities.is_empty() {
+                lines.push(Line::from(Span::styled("  No activity yet", Style::default().fg(TEXT_MUTED))));
+            }
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                format!(
+                    "  h/l: pick an entry │ Enter: preview its file │ {}: open in $EDITOR",
+                    snapshot.keymap.label(KeyAction::OpenFile)
+                ),
+                Style::default().fg(TEXT_MUTED),
+            )));
+            lines
+        }
+        RalphViewMode::Links => {
+            // Show the links seen in the agent's output, newest first, with the picked one highlighted
+            let mut lines = vec![Line::from(Span::styled(
+                format!("  Links ({})", snapshot.links.len()),
+                Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+            ))];
+            lines.push(Line::from(""));
+            let width = (ralph_content_area.width as usize).saturating_sub(4).max(1);
+            let selected_link = snapshot.selected_link.min(snapshot.links.len().saturating_sub(1));
+            for (index, link) in snapshot.links.iter().enumerate() {
+                let selected = index == selected_link;
+                lines.push(Line::from(vec![
+                    Span::styled(if selected { "  ▸ " } else { "    " }, Style::default().fg(CYAN_PRIMARY)),
+                    Span::styled(
+                        truncate_to_width(link, width),
+                        if selected {
+                            Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)
+                        } else {
+                            Style::default().fg(TEXT_SECONDARY)
+                        },
+                    ),
+                ]));
+            }
+            if snapshot.links.is_empty() {
+                lines.push(Line::from(Span::styled("  No links in the agent's output yet", Style::default().fg(TEXT_MUTED))));
+            }
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                "  h/l: pick a link │ y: copy it │ Enter: open it in the browser",
+                Style::default().fg(TEXT_MUTED),
+            )));
+            lines
+        }
+        RalphViewMode::FilePreview => match snapshot.file_preview {
+            Some(ref preview) => file_preview_lines(preview),
+            None => vec![Line::from(Span::styled("  No file open", Style::default().fg(TEXT_MUTED)))],
+        },
+        RalphViewMode::Diff => prd_diff_lines(&snapshot.prd_diff, ralph_content_area.width as usize),
+        RalphViewMode::Health => {
+            // ralph-tui's own counters, worded to paste into a bug report
+            let mut lines = vec![
+                Line::from(Span::styled("  Health", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD))),
+                Line::from(""),
+            ];
+            for line in &snapshot.health {
+                lines.push(Line::from(Span::styled(format!("  {}", line), Style::default().fg(TEXT_SECONDARY))));
+            }
+            lines
+        }
+        RalphViewMode::Changes => changes_lines(snapshot, ralph_content_area.width as usize),
+        RalphViewMode::ChangeDiff => match snapshot.change_diff {
+            Some(ref diff) => change_diff_lines(diff, &snapshot.keymap.label(KeyAction::OpenFile)),
+            None => vec![Line::from(Span::styled("  No diff open", Style::default().fg(TEXT_MUTED)))],
+        },
+    };
+
+    // Add scroll hint and apply scroll offset for Ralph terminal content (only when not in Normal mode)
+    let mut ralph_content_lines = ralph_content_lines;
+    let ralph_scroll = if snapshot.ralph_view_mode != RalphViewMode::Normal {
+        // Add scroll hint at the top
+        ralph_content_lines.insert(0, Line::from(vec![
+            Span::styled("  PgUp/PgDn", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+            Span::styled(" to scroll │ Press key again to close", Style::default().fg(TEXT_MUTED)),
+        ]));
+        ralph_content_lines.insert(1, Line::from(""));
+        // Cap scroll offset to content length
+        let max_scroll = ralph_content_lines.len().saturating_sub(ralph_content_area.height as usize);
+        snapshot.ralph_scroll_offset.min(max_scroll) as u16
+    } else {
+        0
+    };
+
+    let ralph_content = Paragraph::new(ralph_content_lines)
+        .style(Style::default().bg(BG_SECONDARY))
+        .scroll((ralph_scroll, 0));
+    frame.render_widget(ralph_content, ralph_content_area);
+
+    // Bottom footer bar with session ID, mode indicator, and keybinding hints
+    let keys = &snapshot.keymap;
+    let quit = keys.label(KeyAction::Quit);
+    let (mode_text, keybindings_text) = match snapshot.mode {
+        Mode::Ralph => ("Ralph Mode", format!("{}: Claude Mode | {}: Quit", keys.label(KeyAction::EnterClaudeMode), quit)),
+        Mode::Claude => ("Claude Mode", format!("{}: Ralph Mode | {}: Quit", keys.label(KeyAction::ExitClaudeMode), quit)),
+    };
+    let mode_text = snapshot.toast.as_deref().unwrap_or(mode_text);
+    // Follow mode indicator ahead of the keybindings
+    let keybindings_text = match snapshot.follow {
+        FollowMode::Off => keybindings_text,
+        FollowMode::Following => format!("◉ Follow | {}", keybindings_text),
+        FollowMode::Suspended => format!("○ Follow paused ({}) | {}", keys.label(KeyAction::ToggleFollow), keybindings_text),
+    };
+    let keybindings_text = with_sidebar_summary(snapshot, keybindings_text);
+
+    render_footer(bottom_bar_area, &snapshot.session_id, mode_text, &keybindings_text, snapshot.footer_tip.as_deref(), &quit, frame);
+
+    // Quick actions menu (Enter on a story card) over everything else
+    if let (Some(selected), Some(prd)) = (snapshot.story_menu, snapshot.prd.as_ref())
+        && let Some(story) = snapshot.story_index().story(prd, snapshot.selected_story_index)
+    {
+        let actions: Vec<&str> = StoryAction::ALL.iter().map(|action| action.label()).collect();
+        render_story_menu(area, &story.id, &actions, selected, frame);
+    }
+    if let Some(ref alert) = snapshot.tripwire_alert {
+        render_tripwire_modal(area, alert, snapshot.tripwire_queued, frame);
+    }
+
+    story_scroll_offset
+}
+
+/// Left panel: header, stat cards, activity and the story list; returns the story list's scroll offset
+fn draw_status_panel(frame: &mut Frame, snapshot: &AppSnapshot, left_panel_area: Rect, left_border_style: Style) -> usize {
+    // Left panel: Ralph Status
+    let left_title = match snapshot.mode {
+        Mode::Ralph => Line::from(vec![
+            Span::raw(" Ralph Status "),
+            Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)),
+            Span::raw(" "),
+        ]),
+        Mode::Claude => Line::from(" Ralph Status "),
+    };
+    let left_block = Block::default()
+        .title(left_title)
+        .borders(Borders::ALL)
+        .border_style(left_border_style)
+        .style(Style::default().bg(BG_PRIMARY));
+
+    // Render the outer block first to get the inner area
+    let left_inner = left_block.inner(left_panel_area);
+    frame.render_widget(left_block, left_panel_area);
+
+    // Get PRD data for stats
+    let (completed, total) = if let Some(ref prd) = snapshot.prd {
+        (prd.completed_count(), prd.story_total())
+    } else {
+        (0, 0)
+    };
+
+    // Split inner area: open blockers (only when there are any), header (3+ lines),
+    // stat cards (8 lines for 2 rows), rest
+    let header_lines = header_lines(snapshot, left_inner.width);
+    let inner_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(attention_section_height(snapshot.blockers.len())), // Needs attention
+            Constraint::Length(header_lines.len() as u16), // Header
+            Constraint::Length(8), // Two stat card rows (4 lines each)
+            Constraint::Min(0),    // Rest of content
+        ])
+        .split(left_inner);
+
+    let attention_area = inner_layout[0];
+    let header_area = inner_layout[1];
+    let cards_area = inner_layout[2];
+    let content_area_inner = inner_layout[3];
+
+    if !snapshot.blockers.is_empty() {
+        render_attention_section(attention_area, &snapshot.blockers, snapshot.attention_selected, frame);
+    }
+
+    let header = Paragraph::new(header_lines);
+    frame.render_widget(header, header_area);
+
+    // Split cards area into two rows
+    let cards_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(4), // First row: iteration/completed
+            Constraint::Length(4), // Second row: stories left/progress
+        ])
+        .split(cards_area);
+
+    // Render iteration/completion stat cards (first row)
+    let (agent_counts, human_counts) = assignee_counts(snapshot, completed, total);
+    render_stat_cards(
+        cards_layout[0],
+        snapshot.current_iteration,
+        snapshot.max_iterations,
+        snapshot.iterations_reduced,
+        agent_counts,
+        human_counts,
+        frame,
+    );
+
+    // Render progress stat cards (second row)
+    render_progress_cards(
+        cards_layout[1],
+        completed,
+        total,
+        frame,
+    );
+
+    // Build remaining status content
+    let mut status_lines: Vec<Line> = Vec::new();
+    status_lines.push(Line::from("")); // Gap after cards
+
+    // Active Phase section
+    let session_elapsed = snapshot.session_elapsed;
+    status_lines.push(Line::from(vec![
+        Span::styled("✦ ACTIVE PHASE", Style::default().fg(TEXT_MUTED)),
+    ]));
+    // Determine current phase name based on iteration state
+    let phase_name = match snapshot.iteration_state {
+        IterationState::Running => "Execute Iteration Cycle",
+        IterationState::Completed => "All Stories Complete",
+        IterationState::NeedsRestart => "Preparing Next Iteration",
+        IterationState::WaitingDelay => "Waiting for Delay",
+        IterationState::Aborted => "Stopped by User",
+    };
+    status_lines.push(Line::from(vec![
+        Span::styled(
+            phase_name,
+            Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD),
+        ),
+    ]));
+    status_lines.push(Line::from(vec![
+        Span::styled(
+            format!("⏱ Uptime: {}", format_duration(session_elapsed)),
+            Style::default().fg(TEXT_MUTED),
+        ),
+    ]));
+    status_lines.push(Line::from("")); // Gap after active phase
+
+    // Elapsed time (iteration-specific)
+    let iteration_elapsed = snapshot.iteration_elapsed;
+    status_lines.push(Line::from(vec![
+        Span::styled("Session: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(
+            format_duration(session_elapsed),
+            Style::default().fg(TEXT_PRIMARY),
+        ),
+        Span::raw("  "),
+        Span::styled("Iter: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(
+            format_duration(iteration_elapsed),
+            Style::default().fg(TEXT_PRIMARY),
+        ),
+    ]));
+    if let Some(budget) = runtime_budget_line(snapshot) {
+        status_lines.push(budget);
+    }
+    if let Some(badge) = drift_badge_line(snapshot) {
+        status_lines.push(badge);
+    }
+    status_lines.extend(queue_lines(snapshot));
+    status_lines.push(Line::from(""));
+
+    let activities = &snapshot.activities;
+
+    // Recent activities section, led by the stories the agent added, removed or rewrote in
+    // prd.json: changes that make a story easier to pass are red, the rest amber
+    if !activities.is_empty() || !snapshot.prd_churn.is_empty() {
+        status_lines.push(Line::from(vec![
+            Span::styled("Recent Activity:", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        ]));
+        let max_activity_width = left_panel_area.width.saturating_sub(6) as usize;
+        for change in &snapshot.prd_churn {
+            let (bullet, color) = if change.suspicious { ("  ! ", RED_ERROR) } else { ("  • ", AMBER_WARNING) };
+            status_lines.push(Line::from(vec![
+                Span::styled(bullet, Style::default().fg(color)),
+                Span::styled(truncate_to_width(&change.message(), max_activity_width), Style::default().fg(color)),
+            ]));
+        }
+        for activity in activities.iter().take(5) {
+            // Writes outside the repository stand out in red
+            let (bullet, color) = if activity.out_of_scope { ("  ! ", RED_ERROR) } else { ("  • ", TEXT_PRIMARY) };
+            status_lines.push(Line::from(vec![
+                Span::styled(bullet, Style::default().fg(if activity.out_of_scope { RED_ERROR } else { TEXT_MUTED })),
+                Span::styled(activity.format(max_activity_width), Style::default().fg(color)),
+            ]));
+        }
+        status_lines.push(Line::from(""));
+    }
+
+    // PRD information
+    if let Some(ref prd) = snapshot.prd {
+        // Description
+        status_lines.push(Line::from(vec![
+            Span::styled("Task: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        ]));
+        // Wrap description to fit panel
+        for line in wrap_text(&prd.description, left_panel_area.width.saturating_sub(4) as usize) {
+            status_lines.push(Line::from(Span::raw(format!("  {}", line))));
+        }
+        status_lines.push(Line::from(""));
+
+        // Branch (or working directory note if no branch)
+        let branch_display = if snapshot.not_git_repo {
+            NOT_GIT_REPO
+        } else {
+            prd.branch_name.as_deref().unwrap_or("(working in existing repos)")
+        };
+        status_lines.push(Line::from(vec![
+            Span::styled("Branch: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+            Span::raw(branch_display),
+        ]));
+        status_lines.push(Line::from(""));
+
+        // Progress (text display - cards show the numbers)
+        let progress_pct = if total > 0 {
+            (completed as f32 / total as f32 * 100.0) as u8
+        } else {
+            0
+        };
+        status_lines.push(Line::from(vec![
+            Span::styled("Progress: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+            Span::styled(
+                format!("{}%", progress_pct),
+                if completed == total {
+                    Style::default().fg(GREEN_SUCCESS).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(CYAN_PRIMARY)
+                },
+            ),
+        ]));
+
+        // Overall progress bar (text-based)
+        let bar_width = left_panel_area.width.saturating_sub(6) as usize; // Leave room for borders
+        let filled = (bar_width as f32 * progress_pct as f32 / 100.0) as usize;
+        let empty = bar_width.saturating_sub(filled);
+        let bar_filled: String = "█".repeat(filled);
+        let bar_empty: String = "░".repeat(empty);
+        let progress_color = if completed == total { GREEN_SUCCESS } else { CYAN_PRIMARY };
+        status_lines.push(Line::from(vec![
+            Span::styled(bar_filled, Style::default().fg(progress_color)),
+            Span::styled(bar_empty, Style::default().fg(BORDER_SUBTLE)),
+        ]));
+        if let Some(burndown) = snapshot.burndown {
+            status_lines.extend(burndown_lines(burndown, snapshot.session_elapsed));
+        }
+        status_lines.push(Line::from(""));
+
+        // Last test run seen in the agent's output
+        if let Some(result) = snapshot.test_result {
+            status_lines.push(test_result_line(result));
+            status_lines.push(Line::from(""));
+        }
+        if snapshot.compactions > 0 {
+            status_lines.push(compaction_line(snapshot.compactions));
+            status_lines.push(Line::from(""));
+        }
+        if let Some(lines) = snapshot.progress_lines {
+            status_lines.extend(progress_file_lines(lines, snapshot.rotate_threshold, snapshot.progress_iteration_lines));
+            status_lines.push(Line::from(""));
+        }
+
+        // Stuck story hint (active story stalled past the threshold)
+        let index = snapshot.story_index();
+        if let Some(story) = index.current.and_then(|position| index.story(prd, position)) {
+            let stalled = snapshot.stalled_attempts(&story.id);
+            if let Some(color) = stuck_story_color(stalled, snapshot.stuck_threshold) {
+                let hint = format!(
+                    "⚠ {} stalled for {} iterations - add notes to the story or press {} to intervene",
+                    story.id,
+                    stalled,
+                    snapshot.keymap.label(KeyAction::EnterClaudeMode)
+                );
+                for line in wrap_text(&hint, left_panel_area.width.saturating_sub(4) as usize) {
+                    status_lines.push(Line::from(Span::styled(line, Style::default().fg(color))));
+                }
+                status_lines.push(Line::from(""));
+            }
+        }
+
+        // User Stories section header
+        let mut heading = vec![Span::styled("↳ USER STORIES / PHASES", Style::default().fg(TEXT_MUTED))];
+        if snapshot.prd_warning.is_some() {
+            heading.push(Span::styled(" (stale)", Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD)));
+        }
+        status_lines.push(Line::from(heading));
+    } else {
+        status_lines.push(Line::from(vec![
+            Span::styled("Error: ", Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD)),
+            Span::raw("Failed to load prd.json"),
+        ]));
+    }
+
+    // Calculate lines for status content
+    let status_line_count = status_lines.len() as u16;
+
+    // Split content area: status text at top, story cards in middle, hints at bottom
+    let content_split = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(status_line_count),
+            Constraint::Min(0), // Story cards area
+            Constraint::Length(6), // Hints area
+        ])
+        .split(content_area_inner);
+
+    let status_area = content_split[0];
+    let stories_area = content_split[1];
+    let hints_area = content_split[2];
+
+    let left_content = Paragraph::new(status_lines)
+        .style(Style::default().fg(TEXT_PRIMARY));
+
+    frame.render_widget(left_content, status_area);
+
+    // Render keybinding hints at the bottom of left panel
+    let keys = &snapshot.keymap;
+    let hints_lines = vec![
+        Line::from(Span::styled("─── Navigation ───", Style::default().fg(BORDER_SUBTLE))),
+        key_hints(&[(keys.pair_label(KeyAction::StoryUp, KeyAction::StoryDown), "Select story")]),
+        key_hints(&[
+            (keys.label(KeyAction::ToggleStoryDetails), "Story"),
+            (keys.label(KeyAction::ToggleProgress), "Progress"),
+            (keys.label(KeyAction::ToggleRequirements), "Reqs"),
+        ]),
+        key_hints(&[
+            (keys.label(KeyAction::ToggleLayout), "Group"),
+            (keys.label(KeyAction::ToggleCriteria), "Criteria"),
+            ("⏎".to_string(), "Actions"),
+        ]),
+        key_hints(&[
+            (keys.label(KeyAction::ToggleTarget), "Target"),
+            (keys.label(KeyAction::ClearTargets), "Clear"),
+            (keys.label(KeyAction::UndoMenu), "Restore PRD"),
+        ]),
+        key_hints(&[
+            (keys.label(KeyAction::JumpToCurrent), "Current story"),
+            (keys.label(KeyAction::ToggleFollow), "Follow"),
+            (keys.label(KeyAction::WindDown), "Last"),
+        ]),
+    ];
+    let hints = Paragraph::new(hints_lines);
+    frame.render_widget(hints, hints_area);
+
+    // Render story cards if we have a PRD
+    let mut story_scroll_offset = snapshot.story_scroll_offset;
+    if let Some(ref prd) = snapshot.prd {
+        // Priority order, current story and criteria counts, kept by App between PRD reloads
+        let index = snapshot.story_index();
+
+        // Calculate progress percent for active story based on per-criteria completion
+        let progress_percent = match index.current.map(|position| index.criteria[position]) {
+            Some((passed, total)) if total > 0 => ((passed as f32 / total as f32) * 100.0) as u16,
+            Some(_) => 0,
+            None => 100, // All stories complete
+        };
+
+        // Completed group summary row is 1 line; story card heights come from story_card_height()
+        let group_row_height = 1u16;
+
+        // Rows to display (flat list, or grouped with completed stories collapsible)
+        let rows = snapshot.story_rows();
+        let selected_row = snapshot.selected_row_position(&rows);
+
+        // Determine each row's state and real height (expanded cards are taller)
+        // This only reads flags and counts; widgets are built below for visible rows alone.
+        let row_layout: Vec<(StoryState, u16)> = rows
+            .iter()
+            .map(|row| match *row {
+                StoryListRow::Story(idx) => {
+                    let story = &prd.user_stories[index.order[idx]];
+                    let state = if story.passes {
+                        StoryState::Completed
+                    } else if Some(idx) == index.current {
+                        StoryState::Active
+                    } else {
+                        StoryState::Pending
+                    };
+                    let shown_criteria = if !snapshot.expanded_stories.is_empty() && snapshot.expanded_stories.contains(&story.id) {
+                        index.criteria[idx].1
+                    } else {
+                        0
+                    };
+                    (state, story_card_height(state, shown_criteria))
+                }
+                StoryListRow::CompletedGroup(_) => (StoryState::Completed, group_row_height),
+            })
+            .collect();
+        let row_heights: Vec<u16> = row_layout.iter().map(|(_, height)| *height).collect();
+
+        // Make scroll follow selection using the real card heights
+        story_scroll_offset = scroll_to_selected(
+            &row_heights,
+            selected_row,
+            snapshot.story_scroll_offset,
+            stories_area.height,
+        );
+
+        // Calculate total height needed and visible rows
+        let mut y_offset = 0u16;
+
+        // Show scroll indicator if content extends above
+        if story_scroll_offset > 0 {
+            let indicator = Line::from(vec![
+                Span::styled("  ▲ ", Style::default().fg(TEXT_MUTED)),
+                Span::styled(
+                    format!("{} more above", story_scroll_offset),
+                    Style::default().fg(TEXT_MUTED),
+                ),
+            ]);
+            let indicator_para = Paragraph::new(indicator);
+            let indicator_area = Rect {
+                x: stories_area.x,
+                y: stories_area.y,
+                width: stories_area.width,
+                height: 1,
+            };
+            frame.render_widget(indicator_para, indicator_area);
+            y_offset = 1;
+        }
+
+        for (row_idx, row) in rows.iter().enumerate() {
+            // Skip rows before scroll offset
+            if row_idx < story_scroll_offset {
+                continue;
+            }
+
+            let (state, card_height) = row_layout[row_idx];
+
+            // Check if row fits in available space (reserve 1 line for bottom indicator)
+            let remaining_rows = rows.len() - row_idx - 1;
+            let reserve_for_indicator = if remaining_rows > 0 { 1 } else { 0 };
+            if y_offset + card_height + reserve_for_indicator > stories_area.height {
+                // Show scroll indicator for remaining rows
+                let remaining = rows.len() - row_idx;
+                if remaining > 0 && y_offset < stories_area.height {
+                    let indicator = Line::from(vec![
+                        Span::styled("  ▼ ", Style::default().fg(TEXT_MUTED)),
+                        Span::styled(
+                            format!("{} more below", remaining),
+                            Style::default().fg(TEXT_MUTED),
+                        ),
+                    ]);
+                    let indicator_para = Paragraph::new(indicator);
+                    let indicator_area = Rect {
+                        x: stories_area.x,
+                        y: stories_area.y + y_offset,
+                        width: stories_area.width,
+                        height: 1,
+                    };
+                    frame.render_widget(indicator_para, indicator_area);
+                }
+                break;
+            }
+
+            let card_area = Rect {
+                x: stories_area.x,
+                y: stories_area.y + y_offset,
+                width: stories_area.width,
+                height: card_height,
+            };
+
+            // Check if this row is selected (not while the attention section has the selection)
+            let is_selected = row_idx == selected_row && !snapshot.attention_selected;
+
+            match *row {
+                StoryListRow::Story(idx) => {
+                    let story = &prd.user_stories[index.order[idx]];
+                    let (criteria_passed, criteria_total) = index.criteria[idx];
+
+                    let shown_criteria: &[AcceptanceCriterion] = if snapshot.expanded_stories.contains(&story.id) {
+                        &story.acceptance_criteria
+                    } else {
+                        &[]
+                    };
+
+                    render_story_card(
+                        card_area,
+                        &story.id,
+                        &story.title,
+                        shown_criteria,
+                        state,
+                        snapshot.animation_tick,
+                        progress_percent,
+                        criteria_passed,
+                        criteria_total,
+                        snapshot.stalled_attempts(&story.id),
+                        snapshot.stuck_threshold,
+                        if state == StoryState::Active { &snapshot.criteria_history } else { &[] },
+                        snapshot.criteria_flat,
+                        snapshot.session_state.completion(&story.id).map(|c| c.iteration),
+                        snapshot.session_state.targeted_stories.contains(&story.id),
+                        story.assignee.as_deref().filter(|_| story.is_human()),
+                        prd.is_skipped(story),
+                        is_selected,
+                        frame,
+                    );
+                }
+                StoryListRow::CompletedGroup(count) => {
+                    render_completed_group_row(
+                        card_area,
+                        count,
+                        snapshot.completed_group_expanded,
+                        is_selected,
+                        frame,
+                    );
+                }
+            }
+
+            y_offset += card_height;
+        }
+    }
+
+    story_scroll_offset
+}
+
+/// Draw the screen shown between iterations (countdown, sync status or sync error)
+pub fn draw_delay(frame: &mut Frame, snapshot: &AppSnapshot) {
+    if draw_too_small(frame) {
+        return;
+    }
+    let area = frame.area();
+
+    // Create main layout: content area + progress strip (P) + bottom bar
+    let (content_area, strip_area, bottom_bar_area) = split_footer(area, snapshot.progress_strip);
+    if snapshot.progress_strip {
+        render_progress_strip(strip_area, &snapshot.progress_tail, snapshot.progress_highlighted, frame);
+    }
+
+    // Create horizontal split
+    let (left_panel_area, right_panel_area) = split_panels(content_area, snapshot.sidebar_hidden);
+
+    // Left panel with delay message (hidden with --no-sidebar / the sidebar toggle)
+    if !snapshot.sidebar_hidden {
+        draw_delay_status_panel(frame, snapshot, left_panel_area);
+    }
+
+    // Right panel - dual terminals (same layout as run())
+    let right_block = Block::default()
+        .title(" Terminals ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(BORDER_SUBTLE))
+        .style(Style::default().bg(BG_PRIMARY));
+
+    // Render the outer block first to get the inner area
+    let right_inner = right_block.inner(right_panel_area);
+    frame.render_widget(right_block, right_panel_area);
+
+    // Determine Ralph terminal height (always normal during delay)
+    let ralph_terminal_height = 4u16;  // Normal: 1 chrome + 2 content + 1 separator
+
+    // Split right inner into Claude terminal (top) and Ralph terminal (bottom)
+    let terminal_split = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),  // Claude terminal
+            Constraint::Length(ralph_terminal_height),  // Ralph terminal
+        ])
+        .split(right_inner);
+
+    let claude_terminal_area = terminal_split[0];
+    let ralph_terminal_area = terminal_split[1];
+
+    // === CLAUDE TERMINAL ===
+    let claude_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1), // Window chrome
+            Constraint::Min(0),    // Content
+            Constraint::Length(1), // Input bar
+        ])
+        .split(claude_terminal_area);
+
+    let claude_chrome_area = claude_layout[0];
+    let claude_content_area = claude_layout[1];
+    let claude_input_area = claude_layout[2];
+
+    // Claude window chrome with centered title (no traffic lights)
+    let claude_title = ">_ claude-code - ralph-loop";
+    let title_width = claude_title.len() as u16;
+    let available_width = claude_chrome_area.width;
+    let center_offset = (available_width.saturating_sub(title_width)) / 2;
+    let right_pad = available_width.saturating_sub(center_offset + title_width);
+
+    let claude_chrome_line = Line::from(vec![
+        Span::styled(" ".repeat(center_offset as usize), Style::default().bg(BG_TERTIARY)),
+        Span::styled(claude_title, Style::default().fg(TEXT_SECONDARY).bg(BG_TERTIARY)),
+        Span::styled(" ".repeat(right_pad as usize), Style::default().bg(BG_TERTIARY)),
+    ]);
+
+    let claude_chrome = Paragraph::new(claude_chrome_line)
+        .style(Style::default().bg(BG_TERTIARY));
+    frame.render_widget(claude_chrome, claude_chrome_area);
+
+    // Render VT100 screen content
+    let lines = if let Some(ref lines) = snapshot.claude_lines {
+        lines.clone()
+    } else {
+        vec![Line::from(Span::styled(
+            "Error: Failed to access PTY state",
+            Style::default().fg(RED_ERROR),
+        ))]
+    };
+
+    let claude_content = Paragraph::new(lines);
+    frame.render_widget(claude_content, claude_content_area);
+
+    // Claude input bar (placeholder style during delay)
+    let remaining_width = claude_input_area.width.saturating_sub(32);
+    let claude_input_content = Line::from(vec![
+        Span::styled("│ ", Style::default().fg(BORDER_SUBTLE).bg(BG_SECONDARY)),
+        Span::styled("> ", Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY)),
+        Span::styled("ralph@loop:~$ ", Style::default().fg(TEXT_SECONDARY).bg(BG_SECONDARY)),
+        Span::styled("Enter command...", Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)),
+        Span::styled(" ".repeat(remaining_width as usize), Style::default().bg(BG_SECONDARY)),
+    ]);
+
+    let claude_input = Paragraph::new(claude_input_content)
+        .style(Style::default().bg(BG_SECONDARY));
+    frame.render_widget(claude_input, claude_input_area);
+
+    // === RALPH TERMINAL ===
+    let ralph_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1), // Window chrome
+            Constraint::Min(0),    // Content
+        ])
+        .split(ralph_terminal_area);
+
+    let ralph_chrome_area = ralph_layout[0];
+    let ralph_content_area = ralph_layout[1];
+
+    // Ralph window chrome
+    let ralph_title = ">_ ralph output";
+    let ralph_title_width = ralph_title.len() as u16;
+    let ralph_available_width = ralph_chrome_area.width;
+    let ralph_center_offset = (ralph_available_width.saturating_sub(ralph_title_width)) / 2;
+    let ralph_right_pad = ralph_available_width.saturating_sub(ralph_center_offset + ralph_title_width);
+
+    let ralph_chrome_line = Line::from(vec![
+        Span::styled(" ".repeat(ralph_center_offset as usize), Style::default().bg(BG_TERTIARY)),
+        Span::styled(ralph_title, Style::default().fg(TEXT_SECONDARY).bg(BG_TERTIARY)),
+        Span::styled(" ".repeat(ralph_right_pad as usize), Style::default().bg(BG_TERTIARY)),
+    ]);
+
+    let ralph_chrome = Paragraph::new(ralph_chrome_line)
+        .style(Style::default().bg(BG_TERTIARY));
+    frame.render_widget(ralph_chrome, ralph_chrome_area);
+
+    // Ralph content: show waiting message during delay (or sync status)
+    let remaining = snapshot.delay_remaining_secs;
+    let waiting_message = if let Some((ref target, strategy)) = snapshot.sync_running {
+        format!("  Running git {} {}...", strategy.label(), target)
+    } else if snapshot.sync_error.is_some() {
+        "  Sync failed - loop paused".to_string()
+    } else if snapshot.disk_error.is_some() {
+        "  Disk almost full - loop paused".to_string()
+    } else if snapshot.scope_hold.is_some() {
+        "  Write outside the repository - loop paused".to_string()
+    } else if snapshot.tripwire_hold.is_some() {
+        "  Dangerous command - loop paused".to_string()
+    } else if snapshot.churn_hold.is_some() {
+        "  prd.json stories rewritten - loop paused".to_string()
+    } else {
+        format!("  Waiting {} seconds before next iteration...", remaining)
+    };
+    let ralph_content_lines = vec![
+        Line::from(Span::styled(waiting_message, Style::default().fg(AMBER_WARNING))),
+    ];
+
+    let ralph_content = Paragraph::new(ralph_content_lines)
+        .style(Style::default().bg(BG_SECONDARY));
+    frame.render_widget(ralph_content, ralph_content_area);
+
+    // Bottom footer bar with session ID, mode indicator, and keybinding hints
+    let mode_text = "Ralph Mode";
+    let quit = snapshot.keymap.label(KeyAction::Quit);
+    let keybindings_text = with_sidebar_summary(snapshot, format!("{}: Quit | Waiting for next iteration...", quit));
+
+    render_footer(bottom_bar_area, &snapshot.session_id, mode_text, &keybindings_text, snapshot.footer_tip.as_deref(), &quit, frame);
+
+    // Sync error screen over the Claude terminal (or the disk space one)
+    if let Some(ref message) = snapshot.sync_error {
+        render_pause_screen(claude_terminal_area, " Sync failed ", message, Some("Dismiss and continue the loop"), frame);
+    } else if let Some(ref message) = snapshot.disk_error {
+        render_pause_screen(claude_terminal_area, " Disk almost full ", message, None, frame);
+    } else if let Some(ref message) = snapshot.scope_hold {
+        render_pause_screen(claude_terminal_area, " Write outside the repository ", message, Some("Continue the loop"), frame);
+    } else if let Some(ref message) = snapshot.tripwire_hold {
+        render_pause_screen(claude_terminal_area, " Dangerous command ", message, Some("Continue the loop"), frame);
+    } else if let Some(ref message) = snapshot.churn_hold {
+        render_pause_screen(claude_terminal_area, " Stories rewritten ", message, Some("Continue the loop"), frame);
+    }
+    if let Some(ref alert) = snapshot.tripwire_alert {
+        render_tripwire_modal(area, alert, snapshot.tripwire_queued, frame);
+    }
+}
+
+/// Left panel between iterations: header, stat cards and the waiting phase
+/// Iterations ended by the stop hook are routine; an agent exiting or its output
+/// failing to read can mean a premature restart
+fn end_source_color(source: IterationEndSource) -> Color {
+    match source {
+        IterationEndSource::ChildExited | IterationEndSource::ReadError => AMBER_WARNING,
+        _ => TEXT_SECONDARY,
+    }
+}
+
+fn draw_delay_status_panel(frame: &mut Frame, snapshot: &AppSnapshot, left_panel_area: Rect) {
+    // Left panel with delay message
+    let left_title = Line::from(vec![
+        Span::raw(" Ralph Status "),
+        Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)),
+        Span::raw(" "),
+    ]);
+    let left_block = Block::default()
+        .title(left_title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD))
+        .style(Style::default().bg(BG_PRIMARY));
+
+    // Render the outer block first to get the inner area
+    let left_inner = left_block.inner(left_panel_area);
+    frame.render_widget(left_block, left_panel_area);
+
+    // Get PRD data for stats
+    let (completed, total) = if let Some(ref prd) = snapshot.prd {
+        (prd.completed_count(), prd.story_total())
+    } else {
+        (0, 0)
+    };
+
+    // Split inner area: header (3+ lines), stat cards (8 lines for 2 rows), rest
+    let header_lines = header_lines(snapshot, left_inner.width);
+    let inner_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(header_lines.len() as u16), // Header
+            Constraint::Length(8), // Two stat card rows (4 lines each)
+            Constraint::Min(0),    // Rest of content
+        ])
+        .split(left_inner);
+
+    let header_area = inner_layout[0];
+    let cards_area = inner_layout[1];
+    let content_area_inner = inner_layout[2];
+
+    let header = Paragraph::new(header_lines);
+    frame.render_widget(header, header_area);
+
+    // Split cards area into two rows
+    let cards_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(4), // First row: iteration/completed
+            Constraint::Length(4), // Second row: stories left/progress
+        ])
+        .split(cards_area);
+
+    // Render iteration/completion stat cards (first row)
+    let (agent_counts, human_counts) = assignee_counts(snapshot, completed, total);
+    render_stat_cards(
+        cards_layout[0],
+        snapshot.current_iteration,
+        snapshot.max_iterations,
+        snapshot.iterations_reduced,
+        agent_counts,
+        human_counts,
+        frame,
+    );
+
+    // Render progress stat cards (second row)
+    render_progress_cards(
+        cards_layout[1],
+        completed,
+        total,
+        frame,
+    );
+
+    // Build remaining content
+    let mut status_lines: Vec<Line> = Vec::new();
+    status_lines.push(Line::from("")); // Gap after cards
+
+    // Active Phase section
+    let session_elapsed = snapshot.session_elapsed;
+    status_lines.push(Line::from(vec![
+        Span::styled("✦ ACTIVE PHASE", Style::default().fg(TEXT_MUTED)),
+    ]));
+    // During delay, we're waiting for the next iteration
+    let phase_name = "Preparing Next Iteration";
+    status_lines.push(Line::from(vec![
+        Span::styled(
+            phase_name,
+            Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD),
+        ),
+    ]));
+    status_lines.push(Line::from(vec![
+        Span::styled(
+            format!("⏱ Uptime: {}", format_duration(session_elapsed)),
+            Style::default().fg(TEXT_MUTED),
+        ),
+    ]));
+    status_lines.push(Line::from("")); // Gap after active phase
+
+    // Elapsed time (iteration-specific)
+    let iteration_elapsed = snapshot.iteration_elapsed;
+    status_lines.push(Line::from(vec![
+        Span::styled("Session: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(
+            format_duration(session_elapsed),
+            Style::default().fg(TEXT_PRIMARY),
+        ),
+        Span::raw("  "),
+        Span::styled("Iter: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(
+            format_duration(iteration_elapsed),
+            Style::default().fg(TEXT_PRIMARY),
+        ),
+    ]));
+    if let Some(budget) = runtime_budget_line(snapshot) {
+        status_lines.push(budget);
+    }
+    // Why the last iteration ended (an exit without a stop hook is worth a look)
+    if let Some((iteration, source)) = snapshot.last_iteration_end {
+        status_lines.push(Line::from(vec![
+            Span::styled("Ended: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+            Span::styled(
+                format!("iteration {} · {}", iteration, source.description()),
+                Style::default().fg(end_source_color(source)),
+            ),
+        ]));
+    }
+    if let Some(badge) = drift_badge_line(snapshot) {
+        status_lines.push(badge);
+    }
+    status_lines.extend(queue_lines(snapshot));
+    status_lines.push(Line::from(""));
+
+    // Delay countdown - prominently displayed with spinner
+    let remaining = snapshot.delay_remaining_secs;
+    let spinner = get_spinner_frame(snapshot.animation_tick);
+    // Add visual separator for prominence
+    status_lines.push(Line::from(vec![
+        Span::styled("━━━━━━━━━━━━━━━━━━━━━━━━━", Style::default().fg(AMBER_WARNING)),
+    ]));
+    status_lines.push(Line::from(vec![
+        Span::styled(
+            format!("{} ", spinner),
+            Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            format!("Starting next iteration in {}s...", remaining),
+            Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD),
+        ),
+    ]));
+    status_lines.push(Line::from(vec![
+        Span::styled("━━━━━━━━━━━━━━━━━━━━━━━━━", Style::default().fg(AMBER_WARNING)),
+    ]));
+    status_lines.push(Line::from(""));
+
+    // PRD info
+    if let Some(ref prd) = snapshot.prd {
+        status_lines.push(Line::from(vec![
+            Span::styled("Task: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        ]));
+        for line in wrap_text(&prd.description, left_panel_area.width.saturating_sub(4) as usize) {
+            status_lines.push(Line::from(Span::raw(format!("  {}", line))));
+        }
+        status_lines.push(Line::from(""));
+
+        let progress_pct = if total > 0 {
+            (completed as f32 / total as f32 * 100.0) as u8
+        } else {
+            0
+        };
+        status_lines.push(Line::from(vec![
+            Span::styled("Progress: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+            Span::styled(
+                format!("{}%", progress_pct),
+                if completed == total {
+                    Style::default().fg(GREEN_SUCCESS).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(CYAN_PRIMARY)
+                },
+            ),
+        ]));
+
+        // Overall progress bar (text-based)
+        let bar_width = left_panel_area.width.saturating_sub(6) as usize;
+        let filled = (bar_width as f32 * progress_pct as f32 / 100.0) as usize;
+        let empty = bar_width.saturating_sub(filled);
+        let bar_filled: String = "█".repeat(filled);
+        let bar_empty: String = "░".repeat(empty);
+        let progress_color = if completed == total { GREEN_SUCCESS } else { CYAN_PRIMARY };
+        status_lines.push(Line::from(vec![
+            Span::styled(bar_filled, Style::default().fg(progress_color)),
+            Span::styled(bar_empty, Style::default().fg(BORDER_SUBTLE)),
+        ]));
+        if let Some(burndown) = snapshot.burndown {
+            status_lines.extend(burndown_lines(burndown, snapshot.session_elapsed));
+        }
+    }
+
+    let left_content = Paragraph::new(status_lines)
+        .style(Style::default().fg(TEXT_PRIMARY));
+
+    frame.render_widget(left_content, content_area_inner);
+}
+
+/// Diff view: each story at session start (left) next to now (right), criteria side by side;
+/// what the session gained is green, what it lost red
+fn prd_diff_lines(diffs: &[StoryDiff], width: usize) -> Vec<Line<'static>> {
+    let column = (width.saturating_sub(4) / 2).max(16);
+    let mut lines = vec![
+        Line::from(Span::styled(
+            format!(
+                "  prd.json since session start ({} of {} stories changed)",
+                prd_diff::changed(diffs).len(),
+                diffs.len()
+            ),
+            Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled(format!("  {:<column$}", "Session start"), Style::default().fg(TEXT_MUTED)),
+            Span::styled("Now", Style::default().fg(TEXT_MUTED)),
+        ]),
+    ];
+    let mark = |passes: Option<bool>| match passes {
+        None => "—",
+        Some(true) => "✓",
+        Some(false) => "○",
+    };
+    // The "now" side: green for gains, red for losses
+    let now_style = |before: Option<bool>, after: Option<bool>| match (before, after) {
+        _ if before == after => Style::default().fg(TEXT_SECONDARY),
+        (_, Some(true)) => Style::default().fg(GREEN_SUCCESS).add_modifier(Modifier::BOLD),
+        (Some(true), _) | (_, None) => Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD),
+        _ => Style::default().fg(CYAN_PRIMARY),
+    };
+    for diff in diffs {
+        let change = diff.change();
+        let title_color = match change {
+            StoryChange::Completed | StoryChange::Progressed => GREEN_SUCCESS,
+            StoryChange::Regressed | StoryChange::Removed => RED_ERROR,
+            StoryChange::Added => CYAN_PRIMARY,
+            StoryChange::Unchanged => TEXT_PRIMARY,
+        };
+        let mut title = vec![Span::styled(
+            format!("  {} {}", diff.id, diff.title),
+            Style::default().fg(title_color).add_modifier(Modifier::BOLD),
+        )];
+        let note = match (change, diff.position.0) {
+            (StoryChange::Added, _) => Some("  (added)".to_string()),
+            (StoryChange::Removed, _) => Some("  (removed)".to_string()),
+            (_, Some(from)) if diff.moved() => Some(format!("  (moved from #{})", from + 1)),
+            _ => None,
+        };
+        if let Some(note) = note {
+            title.push(Span::styled(note, Style::default().fg(TEXT_MUTED)));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(title));
+
+        let ((before_passed, before_total), (after_passed, after_total)) = diff.criteria_counts();
+        let status = |passes: Option<bool>, passed: usize, total: usize| match passes {
+            None => "—".to_string(),
+            Some(true) => format!("✓ passing ({}/{})", passed, total),
+            Some(false) => format!("○ open ({}/{})", passed, total),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("    {:<width$}", status(diff.before, before_passed, before_total), width = column - 2),
+                Style::default().fg(TEXT_SECONDARY),
+            ),
+            Span::styled(status(diff.after, after_passed, after_total), now_style(diff.before, diff.after)),
+        ]));
+        for criterion in &diff.criteria {
+            lines.push(Line::from(vec![
+                Span::styled(
+                    format!("      {} {:<width$}", mark(criterion.before), criterion.label, width = column - 6),
+                    Style::default().fg(TEXT_MUTED),
+                ),
+                Span::styled(
+                    format!("{} {}", mark(criterion.after), criterion.label),
+                    now_style(criterion.before, criterion.after),
+                ),
+            ]));
+        }
+    }
+    if diffs.is_empty() {
+        lines.push(Line::from(Span::styled("  No prd.json to compare", Style::default().fg(TEXT_MUTED))));
+    }
+    lines
+}
+
+/// Preview view: the file's lines behind a line-number gutter (comments dimmed), or why
+/// there is nothing to show, with a note when only the first lines were loaded
+fn file_preview_lines(preview: &FilePreview) -> Vec<Line<'static>> {
+    let mut header = vec![Span::styled(
+        format!("  {}", preview.path),
+        Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+    )];
+    if matches!(preview.content, PreviewContent::Lines { truncated: true, .. }) {
+        header.push(Span::styled(format!("  first {} lines", PREVIEW_MAX_LINES), Style::default().fg(AMBER_WARNING)));
+    }
+    header.push(Span::styled("  read-only │ Esc: back to the activity list", Style::default().fg(TEXT_MUTED)));
+    let mut lines = vec![Line::from(header), Line::from("")];
+    let message = match preview.content {
+        PreviewContent::Lines { lines: ref content, truncated } => {
+            let gutter = content.len().to_string().len();
+            for (number, line) in content.iter().enumerate() {
+                let style = if is_comment_line(line) {
+                    Style::default().fg(TEXT_MUTED)
+                } else {
+                    Style::default().fg(TEXT_PRIMARY)
+                };
+                lines.push(Line::from(vec![
+                    Span::styled(format!("  {:>gutter$} │ ", number + 1, gutter = gutter), Style::default().fg(BORDER_SUBTLE)),
+                    Span::styled(line.clone(), style),
+                ]));
+            }
+            if content.is_empty() {
+                Some("Empty file".to_string())
+            } else if truncated {
+                Some(format!("… truncated: only the first {} lines are shown", PREVIEW_MAX_LINES))
+            } else {
+                None
+            }
+        }
+        PreviewContent::Missing => Some("File not found (it may have been moved or deleted)".to_string()),
+        PreviewContent::Binary => Some("Binary file — no preview".to_string()),
+        PreviewContent::Unreadable(ref e) => Some(format!("Can't read the file: {}", e)),
+    };
+    if let Some(message) = message {
+        lines.push(Line::from(Span::styled(format!("  {}", message), Style::default().fg(AMBER_WARNING))));
+    }
+    lines
+}
+
+/// Changes view: a `git diff --stat` line per file changed since the iteration started,
+/// with the picked one highlighted, or why there is nothing to list
+fn changes_lines(snapshot: &AppSnapshot, width: usize) -> Vec<Line<'static>> {
+    let files: &[ChangedFile] = match snapshot.iteration_changes.as_deref() {
+        Some(IterationChanges { files: Ok(files), .. }) => files,
+        _ => &[],
+    };
+    let title = match snapshot.iteration_changes.as_deref() {
+        Some(changes) => format!("  Changed since iteration {} started ({} files)", changes.iteration, files.len()),
+        None => "  Changed this iteration".to_string(),
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(title, Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD))),
+        Line::from(""),
+    ];
+    let most = files.iter().filter_map(|f| Some(f.added? + f.removed?)).max().unwrap_or(0);
+    let count_width = most.to_string().len().max(3);
+    // "  ▸ <path> | <count> <bar>": the path gets up to half the width, the bar what is left
+    let path_width = files.iter().map(|f| display_width(&f.path)).max().unwrap_or(0).min(width / 2).max(1);
+    let bar_width = width.saturating_sub(4 + path_width + 3 + count_width + 1).max(1);
+    let selected_change = snapshot.selected_change.min(files.len().saturating_sub(1));
+    for (index, file) in files.iter().enumerate() {
+        let selected = index == selected_change;
+        let count = match (file.added, file.removed) {
+            (Some(added), Some(removed)) => (added + removed).to_string(),
+            _ => "Bin".to_string(),
+        };
+        let (plus, minus) = stat_bar(file, most, bar_width);
+        lines.push(Line::from(vec![
+            Span::styled(if selected { "  ▸ " } else { "    " }, Style::default().fg(CYAN_PRIMARY)),
+            Span::styled(
+                pad_to_width(&truncate_to_width(&file.path, path_width), path_width),
+                if selected {
+                    Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(TEXT_SECONDARY)
+                },
+            ),
+            Span::styled(format!(" | {:>count_width$} ", count, count_width = count_width), Style::default().fg(TEXT_MUTED)),
+            Span::styled(plus, Style::default().fg(GREEN_SUCCESS)),
+            Span::styled(minus, Style::default().fg(RED_ERROR)),
+        ]));
+    }
+    let message = match snapshot.iteration_changes.as_deref() {
+        _ if snapshot.not_git_repo => Some((format!("No changes to show {}", NOT_GIT_REPO), TEXT_MUTED)),
+        None => Some(("Nothing to compare with yet (no iteration start recorded)".to_string(), TEXT_MUTED)),
+        Some(IterationChanges { files: Err(e), .. }) => Some((format!("git diff failed: {}", e), AMBER_WARNING)),
+        Some(_) if files.is_empty() => Some(("No changes yet".to_string(), TEXT_MUTED)),
+        Some(_) => None,
+    };
+    if let Some((message, color)) = message {
+        lines.push(Line::from(Span::styled(format!("  {}", message), Style::default().fg(color))));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        format!(
+            "  h/l: pick a file │ Enter: show its diff │ {}: open in $EDITOR",
+            snapshot.keymap.label(KeyAction::OpenFile)
+        ),
+        Style::default().fg(TEXT_MUTED),
+    )));
+    lines
+}
+
+/// Diff view: the file's unified diff with added lines green, removed ones red and hunk
+/// headers cyan, and a note pointing to $EDITOR when it was cut off
+fn change_diff_lines(diff: &FileDiff, open_key: &str) -> Vec<Line<'static>> {
+    let mut header = vec![Span::styled(format!("  {}", diff.path), Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD))];
+    if diff.truncated {
+        header.push(Span::styled(format!("  first {} lines", DIFF_MAX_LINES), Style::default().fg(AMBER_WARNING)));
+    }
+    header.push(Span::styled("  Esc: back to the changes list", Style::default().fg(TEXT_MUTED)));
+    let mut lines = vec![Line::from(header), Line::from("")];
+    let message = match diff.lines {
+        Ok(ref content) => {
+            for line in content {
+                let style = if line.starts_with("+++") || line.starts_with("---") {
+                    Style::default().fg(TEXT_SECONDARY).add_modifier(Modifier::BOLD)
+                } else if line.starts_with('+') {
+                    Style::default().fg(GREEN_SUCCESS)
+                } else if line.starts_with('-') {
+                    Style::default().fg(RED_ERROR)
+                } else if line.starts_with("@@") {
+                    Style::default().fg(CYAN_PRIMARY)
+                } else if line.starts_with(' ') {
+                    Style::default().fg(TEXT_PRIMARY)
+                } else {
+                    // diff --git, index, new file mode, Binary files ...
+                    Style::default().fg(TEXT_MUTED)
+                };
+                lines.push(Line::from(Span::styled(format!("  {}", line), style)));
+            }
+            if content.is_empty() {
+                Some("No differences any more (the change was undone)".to_string())
+            } else if diff.truncated {
+                Some(format!("… diff cut off at {} lines: press {} to open the file in $EDITOR", DIFF_MAX_LINES, open_key))
+            } else {
+                None
+            }
+        }
+        Err(ref e) => Some(format!("git diff failed: {}", e)),
+    };
+    if let Some(message) = message {
+        lines.push(Line::from(Span::styled(format!("  {}", message), Style::default().fg(AMBER_WARNING))));
+    }
+    lines
+}
+
+/// Draw the startup checklist: a ✓/✗ line per check (failures explain themselves
+/// and name their fix), the run settings, and the keys to fix, continue or abort
+pub fn draw_preflight(frame: &mut Frame, preflight: &Preflight) {
+    if draw_too_small(frame) {
+        return;
+    }
+    let area = frame.area();
+    let block = Block::default()
+        .title(Line::from(vec![
+            Span::raw(" Ralph Preflight "),
+            Span::styled(format!("v{} ", VERSION), Style::default().fg(TEXT_MUTED)),
+        ]))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD))
+        .style(Style::default().bg(BG_PRIMARY));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let label_width = preflight.checks.iter().map(|c| c.label.len()).max().unwrap_or(0);
+    let mut lines = vec![Line::from("")];
+    for check in &preflight.checks {
+        let (glyph, color) = match (check.passed, check.hard) {
+            (true, _) => ("✓", GREEN_SUCCESS),
+            (false, true) => ("✗", RED_ERROR),
+            (false, false) => ("✗", AMBER_WARNING),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {} ", glyph), Style::default().fg(color).add_modifier(Modifier::BOLD)),
+            Span::styled(format!("{:<width$}  ", check.label, width = label_width), Style::default().fg(TEXT_PRIMARY)),
+            Span::styled(check.summary.clone(), Style::default().fg(TEXT_SECONDARY)),
+        ]));
+        if check.passed {
+            continue;
+        }
+        if let Some(ref detail) = check.detail {
+            lines.push(Line::from(Span::styled(format!("      {}", detail), Style::default().fg(color))));
+        }
+        if let Some(ref fix) = check.fix {
+            lines.push(Line::from(vec![
+                Span::styled("      f", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+                Span::styled(format!(" {}", fix.label()), Style::default().fg(TEXT_MUTED)),
+            ]));
+        }
+    }
+
+    if !preflight.settings.is_empty() {
+        lines.push(Line::from(""));
+        let setting_width = preflight.settings.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        for (label, value) in &preflight.settings {
+            lines.push(Line::from(vec![
+                Span::styled(format!("    {:<width$}  ", label, width = setting_width), Style::default().fg(TEXT_MUTED)),
+                Span::styled(value.clone(), Style::default().fg(TEXT_SECONDARY)),
+            ]));
+        }
+    }
+
+    lines.push(Line::from(""));
+    if let Some(ref message) = preflight.message {
+        lines.push(Line::from(Span::styled(format!("  {}", message), Style::default().fg(CYAN_PRIMARY))));
+    }
+    let mut hints = Vec::new();
+    if !preflight.fixes().is_empty() {
+        hints.push(("f".to_string(), "Fix"));
+    }
+    if preflight.prd_task_dir.is_some() {
+        hints.push(("p".to_string(), "Use prd.json taskDir"));
+    }
+    if preflight.has_hard_failure() {
+        lines.push(Line::from(Span::styled(
+            "  Can't start until the ✗ in red is resolved",
+            Style::default().fg(RED_ERROR),
+        )));
+    } else {
+        hints.push(("Enter".to_string(), "Continue"));
+    }
+    hints.push(("q".to_string(), "Abort"));
+    let mut hint_line = key_hints(&hints);
+    hint_line.spans.insert(0, Span::raw("  "));
+    lines.push(hint_line);
+
+    frame.render_widget(Paragraph::new(lines), inner);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testrun::TestResult;
+    use crate::prd::{Blocker, Prd};
+    use crate::lanes::LaneSnapshot;
+    use crate::app::{QueueEntry, QueueStatus, RalphTerminalSize};
+    use crate::journal::PrdEdit;
+    use crate::pty::{AgentActivity, ErrorMatch};
+    use crate::tripwire::TripwireAlert;
+    use crate::prd_diff::{ChurnKind, PrdChurn};
+    use crate::compat::AgentVersion;
+    use ratatui::{Terminal, backend::TestBackend};
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+    use std::time::Duration;
+
+    fn demo_snapshot() -> AppSnapshot {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "demo", "taskDir": "tasks/demo", "type": "feature", "description": "Add login",
+                "userStories": [
+                    {"id": "US-001", "title": "Login form", "description": "", "acceptanceCriteria": [{"description": "renders", "passes": true}], "priority": 1, "passes": true, "notes": ""},
+                    {"id": "US-002", "title": "Session cookie", "description": "", "acceptanceCriteria": [{"description": "sets cookie", "passes": false}], "priority": 2, "passes": false, "notes": ""}
+                ]}"#,
+        )
+        .unwrap();
+        let mut session_state = crate::app::SessionState::default();
+        session_state.story_completions.insert(
+            "US-001".to_string(),
+            crate::app::StoryCompletion { iteration: 2, completed_at: 1_700_000_000 },
+        );
+        AppSnapshot {
+            prd: Some(prd),
+            session_state,
+            current_iteration: 3,
+            max_iterations: 10,
+            session_elapsed: Duration::from_secs(125),
+            iteration_elapsed: Duration::from_secs(42),
+            session_id: "RL-00042".to_string(),
+            selected_story_index: 1,
+            stuck_threshold: 3,
+            env_names: vec!["RALPH_ITERATION".to_string()],
+            claude_lines: Some(vec![Line::from("claude says hi")]),
+            ..AppSnapshot::default()
+        }
+    }
+
+    /// Render with `draw` and return the frame as one string per row
+    fn render_rows(width: u16, height: u16, draw: impl FnOnce(&mut Frame)) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(draw).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| (0..width).map(|x| buffer[(x, y)].symbol()).collect())
+            .collect()
+    }
+
+    fn contains(rows: &[String], text: &str) -> bool {
+        rows.iter().any(|row| row.contains(text))
+    }
+
+    #[test]
+    fn running_frame_shows_status_stories_and_claude_output() {
+        let snapshot = demo_snapshot();
+        let mut scroll = usize::MAX;
+        let rows = render_rows(120, 48, |frame| scroll = draw_running(frame, &snapshot));
+
+        assert_eq!(scroll, 0);
+        assert!(rows[0].starts_with("┌ Ralph Status [ACTIVE] "));
+        assert!(contains(&rows, "Execute Iteration Cycle"));
+        assert!(contains(&rows, "Session: 02:05  Iter: 00:42"));
+        assert!(contains(&rows, "● #001 Login form ✓ iter 2"));
+        assert!(contains(&rows, "#002 Session cookie"));
+        assert!(contains(&rows, "Iteration 3/10"));
+        assert!(contains(&rows, "Env: RALPH_ITERATION"));
+        assert!(contains(&rows, "claude says hi"));
+        assert!(rows[47].starts_with(" Session ID RL-00042 │ Ralph Mode"));
+        assert!(rows[47].ends_with("i: Claude Mode | ^Q: Quit "));
+        assert!(contains(&rows, "c Current story  F Follow"));
+
+        let following = AppSnapshot { follow: FollowMode::Suspended, ..snapshot.clone() };
+        let rows = render_rows(120, 48, |frame| { draw_running(frame, &following); });
+        assert!(rows[47].ends_with("○ Follow paused (F) | i: Claude Mode | ^Q: Quit "));
+    }
+
+    #[test]
+    fn story_details_show_completion_iteration() {
+        let mut snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::StoryDetails,
+            selected_story_index: 0,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "Completed: iteration 2 at 22:13 UTC"));
+
+        snapshot.session_state.story_completions.clear();
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "Completed: before this session"));
+        assert!(!contains(&rows, "✓ iter"));
+    }
+
+    #[test]
+    fn passed_criteria_show_evidence_with_full_text_on_e() {
+        let mut snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::StoryDetails,
+            ralph_size: RalphTerminalSize::Large,
+            selected_story_index: 0,
+            ..demo_snapshot()
+        };
+        let prd = snapshot.prd.as_mut().unwrap();
+        let criterion = &mut prd.user_stories[0].acceptance_criteria[0];
+        criterion.evidence = Some(format!("cargo test login_form: {} ok\nsecond line", "x".repeat(150)));
+        criterion.verified_at = Some("2026-10-16T09:30Z".to_string());
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        let evidence_row = rows.iter().find(|row| row.contains("↳ verified 2026-10-16T09:30Z: cargo test login_form: xx")).unwrap();
+        assert!(evidence_row.contains("x…"));
+        assert!(!contains(&rows, "second line"));
+        assert!(contains(&rows, "h/l pick a criterion · e full evidence"));
+
+        snapshot.ralph_view_mode = RalphViewMode::Evidence;
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "US-001 criterion 1"));
+        assert!(contains(&rows, "Verified: 2026-10-16T09:30Z"));
+        assert!(contains(&rows, " ok"));
+        assert!(contains(&rows, "second line"));
+
+        // Nothing recorded: the details view has no evidence lines or hint
+        snapshot.ralph_view_mode = RalphViewMode::StoryDetails;
+        snapshot.selected_story_index = 1;
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(!contains(&rows, "↳ verified"));
+        assert!(!contains(&rows, "e full evidence"));
+
+        // An unpassed criterion shows the command V runs to check it
+        let prd = snapshot.prd.as_mut().unwrap();
+        prd.user_stories[1].acceptance_criteria[0].verify = Some("cargo test signup".to_string());
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "    $ cargo test signup"));
+        assert!(contains(&rows, "  V run verify commands"));
+    }
+
+    #[test]
+    fn passed_criteria_without_a_verified_note_are_tagged() {
+        let mut snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::StoryDetails,
+            ralph_size: RalphTerminalSize::Large,
+            selected_story_index: 0,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(!contains(&rows, "unverified"));
+
+        snapshot.unverified = HashMap::from([("US-001".to_string(), BTreeSet::from([0]))]);
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "1. renders unverified"));
+    }
+
+    #[test]
+    fn claude_title_shows_agent_activity_chip() {
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(contains(&rows, ">_ claude-code - ralph-loop  STARTING "));
+
+        let snapshot = AppSnapshot { agent_activity: AgentActivity::Idle, ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, ">_ claude-code - ralph-loop  IDLE — awaiting input "));
+
+        let snapshot = AppSnapshot { model: Some("opus".to_string()), ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, ">_ claude-code - ralph-loop (opus)  STARTING "));
+
+        let snapshot = AppSnapshot { agent_version: Some(AgentVersion::new("3.0.1 (Claude Code)")), ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, ">_ claude-code - ralph-loop v3.0.1 untested  STARTING "));
+
+        let snapshot = AppSnapshot { pty_size: Some((118, 30)), ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, ">_ claude-code - ralph-loop 118×30  STARTING "));
+    }
+
+    #[test]
+    fn iterations_view_lists_markers_newest_first() {
+        let marker = |iteration, stash: Option<&str>| crate::git::IterationMarker {
+            iteration,
+            tag: format!("ralph/iter-{}-start", iteration),
+            head: format!("{}abcdef0123", iteration),
+            stash: stash.map(str::to_string),
+            at: 1_700_000_000 + u64::from(iteration) * 60,
+        };
+        let snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::Iterations,
+            iteration_markers: vec![marker(1, None), marker(2, Some("feed"))],
+            iteration_ends: BTreeMap::from([(1, IterationEndSource::ReadError)]),
+            selected_marker: 0,
+            ralph_size: RalphTerminalSize::Large,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        let row_of = |text: &str| rows.iter().position(|row| row.contains(text)).unwrap();
+        assert!(contains(&rows, "Iteration start markers (2)"));
+        assert!(row_of("iteration 2") < row_of("iteration 1"));
+        assert!(contains(&rows, "▸ iteration 1  22:14 UTC  1abcdef"));
+        assert!(contains(&rows, "2abcdef  + uncommitted changes"));
+        assert!(contains(&rows, "1abcdef  ended: agent output unreadable"));
+        assert!(!contains(&rows, "feed  ended"));
+
+        let snapshot = AppSnapshot { ralph_view_mode: RalphViewMode::Iterations, ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "No markers yet (set snapshotEachIteration in prd.json)"));
+    }
+
+    #[test]
+    fn health_view_lists_the_counters() {
+        let snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::Health,
+            health: vec!["PRD reloads:     3 (last at 14:05:09 UTC), 1 failed".to_string()],
+            ralph_size: RalphTerminalSize::Large,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "  Health"));
+        assert!(contains(&rows, "PRD reloads:     3 (last at 14:05:09 UTC), 1 failed"));
+    }
+
+    #[test]
+    fn claude_panel_shows_error_count_and_errors_view() {
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(!contains(&rows, "errors:"));
+
+        let snapshot = AppSnapshot {
+            error_count: 3,
+            ralph_view_mode: RalphViewMode::Errors,
+            errors: vec![ErrorMatch {
+                line: "error[E0308]: mismatched types".to_string(),
+                before: vec!["Compiling app".to_string()],
+                after: vec![" --> src/main.rs:3".to_string()],
+            }],
+            ralph_size: RalphTerminalSize::Large,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, " errors: 3 "));
+        assert!(contains(&rows, "Errors this iteration (1)"));
+        let at = |text: &str| rows.iter().position(|row| row.contains(text)).unwrap();
+        assert_eq!(at("error[E0308]: mismatched types"), at("Compiling app") + 1);
+        assert_eq!(at(" --> src/main.rs:3"), at("error[E0308]: mismatched types") + 1);
+    }
+
+    #[test]
+    fn edit_log_view_lists_edits_newest_first() {
+        let mut undone = PrdEdit::new("US-002 passes", "/userStories/1/passes", false.into(), true.into());
+        undone.undone = true;
+        let snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::Journal,
+            prd_journal: vec![undone, PrdEdit::new("restore prd-1.json", "", Default::default(), Default::default())],
+            ralph_size: RalphTerminalSize::Large,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "prd.json edits this session (2)"));
+        let at = |text: &str| rows.iter().position(|row| row.contains(text)).unwrap();
+        assert!(at("restore prd-1.json") < at("US-002 passes"));
+        assert!(contains(&rows, "US-002 passes  false → true  (undone)"));
+    }
+
+    #[test]
+    fn status_panel_shows_last_test_result() {
+        let snapshot = AppSnapshot {
+            test_result: Some(TestResult { framework: "cargo", passed: 42, failed: 1 }),
+            ..demo_snapshot()
+        };
+        let mut terminal = Terminal::new(TestBackend::new(120, 48)).unwrap();
+        terminal.draw(|frame| {
+            draw_running(frame, &snapshot);
+        }).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..48).map(|y| (0..120).map(|x| buffer[(x, y)].symbol()).collect()).collect();
+        let y = rows.iter().position(|row| row.contains("Tests: 42 ✓ / 1 ✗ (cargo)")).unwrap();
+        let x = rows[y][..rows[y].find("Tests:").unwrap()].chars().count() as u16;
+        // Failures turn the counts red
+        assert_eq!(buffer[(x + 7, y as u16)].fg, RED_ERROR);
+    }
+
+    #[test]
+    fn missing_prd_shows_a_red_banner_and_marks_stories_stale() {
+        let snapshot = AppSnapshot {
+            prd_warning: Some("prd.json was deleted — waiting for it to reappear".to_string()),
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "⚠ prd.json was deleted"));
+        assert!(contains(&rows, "USER STORIES / PHASES (stale)"));
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(!contains(&rows, "(stale)"));
+    }
+
+    #[test]
+    fn hints_and_footer_show_remapped_keys() {
+        let config = crate::config::parse_config("[keys]\nenter_claude_mode = \"a\"\nquit = \"ctrl+x\"\ntoggle_follow = \"f\"");
+        let keymap = crate::keymap::KeyMap::from_bindings(&config.unwrap().keys);
+        let snapshot = AppSnapshot { keymap: std::sync::Arc::new(keymap.unwrap()), ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(rows[47].ends_with("a: Claude Mode | ^X: Quit "));
+        assert!(contains(&rows, "c Current story  f Follow"));
+        assert!(contains(&rows, "↑↓ or j/k Select story"));
+    }
+
+    #[test]
+    fn hidden_sidebar_moves_the_essentials_to_the_footer() {
+        let snapshot = AppSnapshot { sidebar_hidden: true, ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(!contains(&rows, "Ralph Status"));
+        assert!(!contains(&rows, "USER STORIES"));
+        // The right side starts at the left edge
+        assert!(rows[0].starts_with('┌'));
+        assert!(rows[47].contains("It 3/10 · 1/2 done · US-002 | i: Claude Mode"));
+        let rows = render_rows(120, 48, |frame| {
+            draw_delay(frame, &snapshot);
+        });
+        assert!(!contains(&rows, "Ralph Status"));
+        assert!(rows[47].contains("It 3/10 · 1/2 done · US-002 | ^Q: Quit"));
+
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(contains(&rows, "Ralph Status"));
+        assert!(!contains(&rows, "It 3/10"));
+    }
+
+    #[test]
+    fn status_panel_warns_about_compactions() {
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &AppSnapshot { compactions: 2, ..demo_snapshot() });
+        });
+        assert!(contains(&rows, "Compactions: 2"));
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(!contains(&rows, "Compactions"));
+    }
+
+    #[test]
+    fn active_card_shows_criteria_sparkline_amber_when_flat() {
+        let mut snapshot = AppSnapshot { criteria_history: vec![0, 0, 1], ..demo_snapshot() };
+        for (flat, color) in [(false, GREEN_ACTIVE), (true, AMBER_WARNING)] {
+            snapshot.criteria_flat = flat;
+            let mut terminal = Terminal::new(TestBackend::new(120, 48)).unwrap();
+            terminal.draw(|frame| {
+                draw_running(frame, &snapshot);
+            }).unwrap();
+            let buffer = terminal.backend().buffer();
+            let rows: Vec<String> = (0..48).map(|y| (0..120).map(|x| buffer[(x, y)].symbol()).collect()).collect();
+            let y = rows.iter().position(|row| row.contains(" ▁▁█")).unwrap();
+            let x = rows[y][..rows[y].find("▁▁█").unwrap()].chars().count() as u16;
+            assert_eq!(buffer[(x, y as u16)].fg, color);
+        }
+    }
+
+    #[test]
+    fn status_panel_shows_progress_size_amber_near_rotation() {
+        for (lines, color) in [(250, TEXT_PRIMARY), (284, AMBER_WARNING)] {
+            let snapshot = AppSnapshot {
+                progress_lines: Some(lines),
+                progress_iteration_lines: Some(37),
+                rotate_threshold: 300,
+                ..demo_snapshot()
+            };
+            let mut terminal = Terminal::new(TestBackend::new(120, 48)).unwrap();
+            terminal.draw(|frame| {
+                draw_running(frame, &snapshot);
+            }).unwrap();
+            let buffer = terminal.backend().buffer();
+            let rows: Vec<String> = (0..48).map(|y| (0..120).map(|x| buffer[(x, y)].symbol()).collect()).collect();
+            let text = format!("progress.txt: {}/300 lines", lines);
+            let y = rows.iter().position(|row| row.contains(&text)).unwrap();
+            let x = rows[y][..rows[y].find(&text).unwrap()].chars().count() as u16 + 14;
+            assert_eq!(buffer[(x, y as u16)].fg, color);
+            assert!(rows[y + 1].contains("  +37 this iter"));
+        }
+    }
+
+    #[test]
+    fn open_blockers_show_attention_section() {
+        let blockers = vec![
+            Blocker { label: "BLOCKER", text: "need API key".to_string(), line: 3 },
+            Blocker { label: "QUESTION", text: "which port?".to_string(), line: 5 },
+        ];
+        let snapshot = AppSnapshot { blockers, ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "Needs attention (2)"));
+        assert!(contains(&rows, "BLOCKER: need API key"));
+        assert!(contains(&rows, "QUESTION: which port?"));
+
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(!contains(&rows, "Needs attention"));
+    }
+
+    #[test]
+    fn progress_strip_sits_above_footer() {
+        let snapshot = AppSnapshot {
+            progress_strip: true,
+            progress_tail: vec!["## Iteration 3".to_string(), "- Added session cookie".to_string()],
+            progress_highlighted: 1,
+            ..demo_snapshot()
+        };
+        let mut terminal = Terminal::new(TestBackend::new(120, 48)).unwrap();
+        terminal.draw(|frame| {
+            draw_running(frame, &snapshot);
+        }).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..48).map(|y| (0..120).map(|x| buffer[(x, y)].symbol()).collect()).collect();
+        assert!(rows[48 - 1 - PROGRESS_STRIP_HEIGHT as usize].contains(" progress.txt "));
+        let y = rows.iter().position(|row| row.starts_with("- Added session cookie")).unwrap();
+        assert_eq!(buffer[(0, y as u16)].fg, GREEN_ACTIVE);
+        let y = rows.iter().position(|row| row.starts_with("## Iteration 3")).unwrap();
+        assert_eq!(buffer[(0, y as u16)].fg, TEXT_SECONDARY);
+    }
+
+    #[test]
+    fn parallel_lanes_render_as_stacked_panes() {
+        let lane = |label: &str, stories: &[&str], state, focused| LaneSnapshot {
+            label: label.to_string(),
+            stories: stories.iter().map(|id| id.to_string()).collect(),
+            iteration: 2,
+            state,
+            agent_activity: AgentActivity::Working,
+            lines: vec![Line::from(format!("{} output", label))],
+            focused,
+        };
+        let snapshot = AppSnapshot {
+            mode: Mode::Claude,
+            lanes: vec![
+                lane("Lane 1", &["US-001", "US-003"], IterationState::Running, false),
+                lane("Lane 2", &["US-002"], IterationState::Completed, true),
+            ],
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, ">_ Lane 1 · US-001, US-003 · iter 2"));
+        assert!(contains(&rows, ">_ Lane 2 · US-002 · iter 2 [ACTIVE] ✓ DONE"));
+        assert!(contains(&rows, "Lane 2 output"));
+        // The lanes replace the single Claude terminal
+        assert!(!contains(&rows, "claude says hi"));
+    }
+
+    #[test]
+    fn human_assigned_stories_split_completed_card() {
+        let mut snapshot = demo_snapshot();
+        if let Some(prd) = snapshot.prd.as_mut() {
+            prd.user_stories[1].assignee = Some("dana".to_string());
+        }
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "1/1 agent"));
+        assert!(contains(&rows, "0/1 human"));
+        // The glyph is two cells wide
+        assert!(contains(&rows, "#002 👤  dana Session cookie"));
+        assert!(!contains(&rows, "COMPLETED"));
+    }
+
+    #[test]
+    fn runtime_budget_turns_amber_in_last_tenth() {
+        let budget = |elapsed| {
+            let snapshot = AppSnapshot {
+                max_runtime: Some(Duration::from_secs(6000)),
+                session_elapsed: Duration::from_secs(elapsed),
+                ..demo_snapshot()
+            };
+            runtime_budget_line(&snapshot).unwrap()
+        };
+        let line = budget(1200);
+        assert_eq!(line.spans[1].content, "80:00 left of 100:00");
+        assert_eq!(line.spans[1].style.fg, Some(TEXT_PRIMARY));
+        assert_eq!(budget(5400).spans[1].style.fg, Some(AMBER_WARNING));
+        assert_eq!(budget(7000).spans[1].content, "limit reached - wrapping up");
+        assert!(runtime_budget_line(&demo_snapshot()).is_none());
+
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &AppSnapshot { max_runtime: Some(Duration::from_secs(6000)), ..demo_snapshot() });
+        });
+        assert!(contains(&rows, "Budget: 97:55 left of 100:00"));
+    }
+
+    #[test]
+    fn story_details_mark_criterion_cursor_and_focus() {
+        let mut snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::StoryDetails,
+            ralph_size: RalphTerminalSize::Large,
+            ..demo_snapshot()
+        };
+        snapshot.session_state.focused_criterion =
+            Some(crate::app::CriterionFocus { story_id: "US-002".to_string(), criterion: 0 });
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "› ○ ◎ 1. sets cookie"));
+    }
+
+    #[test]
+    fn stalled_input_shows_banner() {
+        let snapshot = AppSnapshot { input_stalled: true, ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "⚠ agent not reading input"));
+    }
+
+    #[test]
+    fn header_shows_prd_identity_and_warns_on_newer_schema() {
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(contains(&rows, "demo · feature · schema v1.0"));
+        assert!(!contains(&rows, "⚠ PRD schema"));
+
+        let mut snapshot = demo_snapshot();
+        snapshot.prd.as_mut().unwrap().schema_version = "3.0".to_string();
+        for draw in [draw_running_frame, draw_delay] {
+            let rows = render_rows(120, 48, |frame| draw(frame, &snapshot));
+            assert!(contains(&rows, "demo · feature · schema v3.0"));
+            assert!(contains(&rows, "⚠ PRD schema 3.0 is newer than"));
+            assert!(contains(&rows, "this ralph-tui — some fields may"));
+        }
+    }
+
+    fn draw_running_frame(frame: &mut Frame, snapshot: &AppSnapshot) {
+        draw_running(frame, snapshot);
+    }
+
+    #[test]
+    fn reader_error_shows_banner() {
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(!contains(&rows, "internal reader error"));
+
+        let snapshot = AppSnapshot { reader_error: true, ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "⚠ internal reader error — output may be incomplete"));
+    }
+
+    #[test]
+    fn ralph_terminal_height_follows_the_chosen_size_in_every_view() {
+        for view in [RalphViewMode::Normal, RalphViewMode::StoryDetails] {
+            for size in [RalphTerminalSize::Collapsed, RalphTerminalSize::Normal, RalphTerminalSize::Large] {
+                let snapshot = AppSnapshot { ralph_view_mode: view, ralph_size: size, ..demo_snapshot() };
+                let rows = render_rows(120, 48, |frame| {
+                    draw_running(frame, &snapshot);
+                });
+                let ralph = rows.iter().position(|row| row.contains(">_ ralph output")).unwrap();
+                let claude = rows.iter().position(|row| row.contains(">_ claude-code")).unwrap();
+                assert_eq!(claude - ralph, size.height() as usize, "{:?} {:?}", view, size);
+            }
+        }
+    }
+
+    #[test]
+    fn outside_a_git_repo_git_details_show_a_hint() {
+        let mut snapshot = AppSnapshot { not_git_repo: true, ..demo_snapshot() };
+        if let Some(ref mut prd) = snapshot.prd {
+            prd.merge_target = Some("main".to_string());
+        }
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "Branch: — (not a git repo)"));
+        assert!(contains(&rows, "⎇ main  — (not a git repo)"));
+
+        snapshot.ralph_view_mode = RalphViewMode::Iterations;
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "No markers — (not a git repo)"));
+    }
+
+    #[test]
+    fn delay_frame_shows_countdown_or_sync_error() {
+        let mut snapshot = AppSnapshot {
+            iteration_state: IterationState::WaitingDelay,
+            delay_remaining_secs: 2,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 40, |frame| draw_delay(frame, &snapshot));
+        assert!(contains(&rows, "Starting next iteration in 2s..."));
+        assert!(contains(&rows, "Waiting 2 seconds before next iteration..."));
+        assert!(rows[39].ends_with("^Q: Quit | Waiting for next iteration... "));
+        assert!(!contains(&rows, "Ended:"));
+        snapshot.last_iteration_end = Some((3, IterationEndSource::ChildExited));
+        let rows = render_rows(120, 40, |frame| draw_delay(frame, &snapshot));
+        assert!(contains(&rows, "Ended: iteration 3 · agent exited"));
+
+        snapshot.sync_error = Some("git merge main failed: conflict".to_string());
+        let rows = render_rows(120, 40, |frame| draw_delay(frame, &snapshot));
+        assert!(contains(&rows, "Sync failed - loop paused"));
+        assert!(contains(&rows, "git merge main failed: conflict"));
+        assert!(contains(&rows, "Esc Dismiss and continue the loop"));
+
+        // Disk pause: banner in the header, pause screen without Esc
+        snapshot.sync_error = None;
+        snapshot.disk_warning = Some("Disk almost full: 20 MB free — loop paused".to_string());
+        snapshot.disk_error = Some("Only 20 MB free on the task directory's filesystem".to_string());
+        let rows = render_rows(120, 40, |frame| draw_delay(frame, &snapshot));
+        assert!(contains(&rows, "⚠ Disk almost full: 20 MB free"));
+        assert!(contains(&rows, "Disk almost full - loop paused"));
+        assert!(contains(&rows, "Only 20 MB free on the task directory's filesystem"));
+        assert!(!contains(&rows, "Esc Dismiss"));
+
+        // Write outside the repository: red banner, pause screen with Esc
+        snapshot.disk_warning = None;
+        snapshot.disk_error = None;
+        snapshot.scope_warning = Some("Agent wrote outside the repository: /home/me/.bashrc".to_string());
+        snapshot.scope_hold = Some("The agent wrote outside the repository and allowedPaths:".to_string());
+        let rows = render_rows(120, 40, |frame| draw_delay(frame, &snapshot));
+        assert!(contains(&rows, "⚠ Agent wrote outside"));
+        assert!(contains(&rows, "Write outside the repository - loop paused"));
+        assert!(contains(&rows, "Esc Continue the loop"));
+
+        // Suspicious prd.json churn (pause_on_prd_churn)
+        snapshot.scope_warning = None;
+        snapshot.scope_hold = None;
+        snapshot.churn_hold = Some("The agent changed the stories in prd.json:\n  PRD modified: US-3 criteria reduced".to_string());
+        let rows = render_rows(120, 40, |frame| draw_delay(frame, &snapshot));
+        assert!(contains(&rows, "prd.json stories rewritten - loop paused"));
+        assert!(contains(&rows, "PRD modified: US-3 criteria reduced"));
+        assert!(contains(&rows, "Esc Continue the loop"));
+    }
+
+    #[test]
+    fn prd_churn_leads_the_activity_feed() {
+        let churn = |story_id: &str, kind, suspicious| PrdChurn { story_id: story_id.to_string(), kind, suspicious };
+        let snapshot = AppSnapshot {
+            prd_churn: vec![churn("US-3", ChurnKind::CriteriaReworded, true), churn("US-4", ChurnKind::StoryAdded, false)],
+            ..demo_snapshot()
+        };
+        let mut terminal = Terminal::new(TestBackend::new(160, 48)).unwrap();
+        terminal.draw(|frame| {
+            draw_running(frame, &snapshot);
+        }).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..48).map(|y| (0..160).map(|x| buffer[(x, y)].symbol()).collect()).collect();
+        let color_of = |text: &str| {
+            let y = rows.iter().position(|row| row.contains(text)).unwrap();
+            let x = rows[y][..rows[y].find(text).unwrap()].chars().count() as u16;
+            buffer[(x, y as u16)].fg
+        };
+        // Suspicious changes are red, the rest amber
+        assert_eq!(color_of("PRD modified: US-3 criteria reworded"), RED_ERROR);
+        assert_eq!(color_of("PRD modified: US-4 added"), AMBER_WARNING);
+    }
+
+    #[test]
+    fn batch_queue_lists_each_task_with_its_status() {
+        let entry = |name: &str, status| QueueEntry { status, ..QueueEntry::new(name.into()) };
+        let mut snapshot = AppSnapshot {
+            queue: vec![
+                entry("tasks/login", QueueStatus::Done),
+                entry("tasks/search", QueueStatus::Running),
+                QueueEntry::chained("tasks/search-ui".into()),
+            ],
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| { draw_running(frame, &snapshot); });
+        assert!(contains(&rows, "Queue"));
+        assert!(contains(&rows, "✓ login  done"));
+        assert!(contains(&rows, "▶ search  running"));
+        assert!(contains(&rows, "○ ↳ search-ui  pending"));
+
+        // A single task has no queue to show
+        snapshot.queue.truncate(1);
+        let rows = render_rows(120, 48, |frame| { draw_running(frame, &snapshot); });
+        assert!(!contains(&rows, "Queue"));
+    }
+
+    #[test]
+    fn tiny_terminals_show_a_resize_message() {
+        let expanded = AppSnapshot {
+            progress_strip: true,
+            expanded_stories: ["US-002".to_string()].into_iter().collect(),
+            ralph_view_mode: RalphViewMode::StoryDetails,
+            ..demo_snapshot()
+        };
+        for (width, height) in [(0, 0), (1, 1), (12, 3), (40, 10), (70, 18), (79, 40), (200, 19), (80, 20), (81, 21)] {
+            for snapshot in [demo_snapshot(), expanded.clone(), AppSnapshot::default()] {
+                let running = render_rows(width, height, |frame| {
+                    draw_running(frame, &snapshot);
+                });
+                let delay = render_rows(width, height, |frame| draw_delay(frame, &snapshot));
+                let too_small = width < MIN_WIDTH || height < MIN_HEIGHT;
+                assert_eq!(contains(&running, "Ralph Status"), !too_small, "{}x{}", width, height);
+                assert_eq!(contains(&delay, "Ralph Status"), !too_small, "{}x{}", width, height);
+            }
+        }
+
+        let rows = render_rows(70, 18, |frame| {
+            draw_running(frame, &demo_snapshot());
+        });
+        assert!(contains(&rows, "Terminal too small: need 80x20, have 70x18"));
+        assert_eq!(rows.iter().position(|row| row.contains("Terminal too small")), Some(8));
+    }
+
+    #[test]
+    fn tripwire_modal_shows_the_command_and_answers() {
+        let snapshot = AppSnapshot {
+            tripwire_alert: Some(TripwireAlert {
+                command: "git push origin main --force".to_string(),
+                pattern: "git push --force".to_string(),
+                lane: None,
+            }),
+            tripwire_queued: 1,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "⚠ Dangerous command"));
+        assert!(contains(&rows, "git push origin main --force"));
+        assert!(contains(&rows, "may already be running"));
+        assert!(contains(&rows, "c Send Ctrl+C to agent"));
+        assert!(contains(&rows, "1 more waiting"));
+    }
+
+    #[test]
+    fn story_menu_pops_up_over_the_screen() {
+        let snapshot = AppSnapshot { story_menu: Some(2), ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, " US-002 "));
+        assert!(contains(&rows, "▶ Toggle passed"));
+        assert!(contains(&rows, "   Skip for this session"));
+
+        // A skipped story is no longer active and gets a muted tag
+        let mut snapshot = demo_snapshot();
+        snapshot.prd.as_mut().unwrap().skipped_stories.insert("US-002".to_string());
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "Session cookie skipped"));
+        assert!(!contains(&rows, "View details"));
+    }
+
+    #[test]
+    fn preflight_lists_checks_with_details_and_fixes() {
+        use crate::preflight::{Check, Fix};
+        let check = |label, summary: &str, passed, hard, detail: Option<&str>, fix| Check {
+            label,
+            summary: summary.to_string(),
+            passed,
+            hard,
+            detail: detail.map(str::to_string),
+            fix,
+        };
+        let mut preflight = Preflight {
+            checks: vec![
+                check("prd.json", "4 stories, 1 complete", true, false, None, None),
+                check("Git branch", "on main", false, false, Some("prd.json branchName is ralph/login"), Some(Fix::CheckoutBranch("ralph/login".to_string()))),
+            ],
+            settings: vec![("Max iters", "10 (default)".to_string())],
+            message: None,
+            prd_task_dir: None,
+        };
+        let rows = render_rows(100, 24, |frame| draw_preflight(frame, &preflight));
+        assert!(rows[0].starts_with("┌ Ralph Preflight "));
+        assert!(contains(&rows, "✓ prd.json    4 stories, 1 complete"));
+        assert!(contains(&rows, "✗ Git branch  on main"));
+        assert!(contains(&rows, "prd.json branchName is ralph/login"));
+        assert!(contains(&rows, "f check out ralph/login"));
+        assert!(contains(&rows, "Max iters  10 (default)"));
+        assert!(contains(&rows, "f Fix  Enter Continue  q Abort"));
+        preflight.prd_task_dir = Some("tasks/original".into());
+        let rows = render_rows(100, 24, |frame| draw_preflight(frame, &preflight));
+        assert!(contains(&rows, "f Fix  p Use prd.json taskDir  Enter Continue  q Abort"));
+        preflight.prd_task_dir = None;
+
+        // A hard failure takes Continue away
+        preflight.checks.push(check("Agent", "claude", false, true, Some("claude not found on PATH"), None));
+        let rows = render_rows(100, 24, |frame| draw_preflight(frame, &preflight));
+        assert!(contains(&rows, "Can't start until"));
+        assert!(contains(&rows, "f Fix  q Abort"));
+    }
+
+    #[test]
+    fn reduced_iteration_budget_is_marked_on_the_stat_card() {
+        let snapshot = AppSnapshot { max_iterations: 3, iterations_reduced: true, ..demo_snapshot() };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "3/3 (reduced)"));
+        assert!(contains(&rows, "_ Last"));
+    }
+
+    #[test]
+    fn file_preview_numbers_lines_and_flags_truncation() {
+        use crate::preview::{FilePreview, PreviewContent};
+        let preview = FilePreview {
+            path: "src/lib.rs".to_string(),
+            content: PreviewContent::Lines { lines: vec!["// entry point".to_string(), "pub mod app;".to_string()], truncated: true },
+        };
+        let mut snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::FilePreview,
+            file_preview: Some(std::sync::Arc::new(preview)),
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "src/lib.rs  first 500 lines  read-only │ Esc: back to the activity list"));
+        assert!(contains(&rows, "1 │ // entry point"));
+        // The note at the end is reached by scrolling
+        snapshot.ralph_scroll_offset = 4;
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "2 │ pub mod app;"));
+        assert!(contains(&rows, "… truncated: only the first 500 lines are shown"));
+
+        let snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::FilePreview,
+            file_preview: Some(std::sync::Arc::new(FilePreview { path: "logo.png".to_string(), content: PreviewContent::Binary })),
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "Binary file — no preview"));
+    }
+
+    #[test]
+    fn changes_view_lists_stat_lines_and_colors_the_diff() {
+        use crate::app::RalphTerminalSize;
+        use crate::changes::{ChangedFile, FileDiff, IterationChanges};
+        let changes = IterationChanges {
+            iteration: 3,
+            files: Ok(vec![
+                ChangedFile { path: "src/app.rs".to_string(), added: Some(12), removed: Some(3) },
+                ChangedFile { path: "logo.png".to_string(), added: None, removed: None },
+            ]),
+        };
+        let snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::Changes,
+            ralph_size: RalphTerminalSize::Large,
+            iteration_changes: Some(std::sync::Arc::new(changes)),
+            selected_change: 1,
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "Changed since iteration 3 started (2 files)"));
+        assert!(contains(&rows, "    src/app.rs |  15 ++++++++++++---"));
+        assert!(contains(&rows, "  ▸ logo.png   | Bin"));
+
+        let diff = FileDiff {
+            path: "src/app.rs".to_string(),
+            lines: Ok(vec!["@@ -1,2 +1,2 @@".to_string(), " fn main() {".to_string(), "-    old();".to_string(), "+    new();".to_string()]),
+            truncated: true,
+        };
+        let snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::ChangeDiff,
+            ralph_size: RalphTerminalSize::Large,
+            change_diff: Some(std::sync::Arc::new(diff)),
+            ..demo_snapshot()
+        };
+        let mut terminal = Terminal::new(TestBackend::new(120, 48)).unwrap();
+        terminal.draw(|frame| {
+            draw_running(frame, &snapshot);
+        }).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..48).map(|y| (0..120).map(|x| buffer[(x, y)].symbol()).collect()).collect();
+        assert!(contains(&rows, "src/app.rs  first 1000 lines  Esc: back to the changes list"));
+        assert!(contains(&rows, "… diff cut off at 1000 lines: press o to open the file in $EDITOR"));
+        for (text, color) in [("-    old();", RED_ERROR), ("+    new();", GREEN_SUCCESS), ("@@ -1,2", CYAN_PRIMARY)] {
+            let y = rows.iter().position(|row| row.contains(text)).unwrap();
+            let x = rows[y][..rows[y].find(text).unwrap()].chars().count() as u16;
+            assert_eq!(buffer[(x, y as u16)].fg, color, "{}", text);
+        }
+    }
+
+    #[test]
+    fn diff_view_shows_session_start_next_to_now() {
+        let now = demo_snapshot().prd.unwrap();
+        let mut start = now.clone();
+        start.user_stories[0].passes = false;
+        start.user_stories[0].acceptance_criteria[0].passes = false;
+        let mut snapshot = AppSnapshot {
+            ralph_view_mode: RalphViewMode::Diff,
+            prd_diff: crate::prd_diff::diff(&start, &now),
+            ..demo_snapshot()
+        };
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        assert!(contains(&rows, "prd.json since session start (1 of 2 stories changed)"));
+        snapshot.ralph_scroll_offset = 5;
+        let rows = render_rows(120, 48, |frame| {
+            draw_running(frame, &snapshot);
+        });
+        let status = rows.iter().find(|row| row.contains("○ open (0/1)")).expect("status row");
+        assert!(status.contains("✓ passing (1/1)"));
+        assert!(status.find("○ open").unwrap() < status.find("✓ passing").unwrap());
+        assert!(contains(&rows, "US-001 Login form"));
+    }
+}
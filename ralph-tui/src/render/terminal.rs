@@ -0,0 +1,246 @@
+//! Conversion of the vt100 emulator screen into styled ratatui lines, and the
+//! cells of a drawn frame that show a link from the agent's output
+
+use ratatui::{
+    buffer::{Buffer, Cell},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+
+use super::widgets::display_width;
+use crate::links::{trim_url, URL};
+
+/// Convert vt100::Color to ratatui::Color
+pub fn vt100_to_ratatui_color(color: vt100::Color) -> Color {
+    match color {
+        vt100::Color::Default => Color::Reset,
+        vt100::Color::Idx(idx) => Color::Indexed(idx),
+        vt100::Color::Rgb(r, g, b) => Color::Rgb(r, g, b),
+    }
+}
+
+/// Render the VT100 screen to a Vec of ratatui Lines (styled text)
+/// This function renders the visible content of the terminal emulator.
+/// Neighbouring cells with the same style share one span, so a mostly blank
+/// screen costs a few spans per row rather than one per cell.
+pub fn render_vt100_screen(screen: &vt100::Screen) -> Vec<Line<'static>> {
+    let (rows, cols) = screen.size();
+    let mut lines = Vec::with_capacity(rows as usize);
+
+    // Render each visible row
+    for row in 0..rows {
+        let mut spans: Vec<Span<'static>> = Vec::new();
+        let mut run = String::new();
+        let mut run_style = Style::default();
+        let mut col = 0u16;
+
+        while col < cols {
+            if let Some(cell) = screen.cell(row, col) {
+                let contents = cell.contents();
+
+                // Skip wide character continuations
+                if cell.is_wide_continuation() {
+                    col += 1;
+                    continue;
+                }
+
+                let display_str = if contents.is_empty() { " " } else { contents };
+
+                let mut style = Style::default();
+                style = style.fg(vt100_to_ratatui_color(cell.fgcolor()));
+                style = style.bg(vt100_to_ratatui_color(cell.bgcolor()));
+
+                if cell.bold() {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+                if cell.dim() {
+                    style = style.add_modifier(Modifier::DIM);
+                }
+                if cell.italic() {
+                    style = style.add_modifier(Modifier::ITALIC);
+                }
+                if cell.underline() {
+                    style = style.add_modifier(Modifier::UNDERLINED);
+                }
+                if cell.inverse() {
+                    style = style.add_modifier(Modifier::REVERSED);
+                }
+
+                if style != run_style && !run.is_empty() {
+                    spans.push(Span::styled(std::mem::take(&mut run), run_style));
+                }
+                run_style = style;
+                run.push_str(display_str);
+
+                // Wide characters take 2 columns
+                if cell.is_wide() {
+                    col += 2;
+                } else {
+                    col += 1;
+                }
+            } else {
+                if run_style != Style::default() && !run.is_empty() {
+                    spans.push(Span::styled(std::mem::take(&mut run), run_style));
+                }
+                run_style = Style::default();
+                run.push(' ');
+                col += 1;
+            }
+        }
+        // Trailing blanks look the same as the cleared buffer, so skip painting them
+        if run_style.bg.is_none_or(|bg| bg == Color::Reset)
+            && !run_style.add_modifier.intersects(Modifier::UNDERLINED | Modifier::REVERSED)
+        {
+            run.truncate(run.trim_end_matches(' ').len());
+        }
+        if !run.is_empty() {
+            spans.push(Span::styled(run, run_style));
+        }
+        lines.push(Line::from(spans));
+    }
+
+    lines
+}
+
+/// Cells of a drawn frame showing one link, to be written again as an OSC 8 hyperlink
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperlinkRun {
+    pub url: String,
+    pub cells: Vec<(u16, u16, Cell)>,
+}
+
+/// Where a drawn frame shows one of `links` in full
+/// ratatui measures a cell's width from its whole symbol, so a cell can't carry the
+/// OSC 8 escape itself; the main loop writes these cells again, wrapped in the escape,
+/// after the frame is drawn. Links the agent only gave a label for (OSC 8 around other
+/// text) aren't on screen as URLs and are left alone.
+pub fn hyperlink_runs(buffer: &Buffer, links: &[String]) -> Vec<HyperlinkRun> {
+    let mut runs = Vec::new();
+    if links.is_empty() {
+        return runs;
+    }
+    let area = buffer.area;
+    for y in area.top()..area.bottom() {
+        // The row's text, with the column each byte of it is drawn in
+        let mut text = String::new();
+        let mut columns = Vec::new();
+        let mut x = area.left();
+        while x < area.right() {
+            let symbol = buffer[(x, y)].symbol();
+            columns.extend(std::iter::repeat_n(x, symbol.len()));
+            text.push_str(symbol);
+            x += (display_width(symbol) as u16).max(1);
+        }
+        for found in URL.find_iter(&text) {
+            let url = trim_url(found.as_str());
+            if !links.iter().any(|link| link == url) {
+                continue;
+            }
+            let (first, last) = (columns[found.start()], columns[found.start() + url.len() - 1]);
+            runs.push(HyperlinkRun {
+                url: url.to_string(),
+                cells: (first..=last).map(|x| (x, y, buffer[(x, y)].clone())).collect(),
+            });
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Raw output fixtures (`<name>.ansi`) and the lines they render to (`<name>.json`)
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/vt100");
+
+    /// Set to 1 to rewrite the goldens from the current rendering instead of checking it
+    const UPDATE_GOLDENS_ENV: &str = "RALPH_UPDATE_GOLDENS";
+
+    /// Screen size the fixtures are rendered at
+    const GOLDEN_ROWS: u16 = 12;
+    const GOLDEN_COLS: u16 = 72;
+
+    /// Rendered lines as JSON: one list of spans per row, each span's text with its
+    /// colors and modifiers spelled out so a lost attribute shows up in the diff
+    fn lines_json(lines: &[Line]) -> serde_json::Value {
+        let color = |color: Option<Color>| color.map_or_else(|| "none".to_string(), |c| c.to_string());
+        let rows = lines.iter().map(|line| {
+            let spans = line.spans.iter().map(|span| {
+                let modifiers: Vec<&str> = span.style.add_modifier.iter_names().map(|(name, _)| name).collect();
+                serde_json::json!({
+                    "text": span.content,
+                    "fg": color(span.style.fg),
+                    "bg": color(span.style.bg),
+                    "modifiers": modifiers,
+                })
+            });
+            serde_json::Value::Array(spans.collect())
+        });
+        serde_json::Value::Array(rows.collect())
+    }
+
+    #[test]
+    fn fixtures_render_as_their_goldens() {
+        let update = std::env::var(UPDATE_GOLDENS_ENV).is_ok_and(|v| v == "1");
+        let mut fixtures: Vec<_> = std::fs::read_dir(GOLDEN_DIR)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ansi"))
+            .collect();
+        fixtures.sort();
+        assert!(!fixtures.is_empty(), "no fixtures in {}", GOLDEN_DIR);
+
+        for fixture in fixtures {
+            let mut parser = vt100::Parser::new(GOLDEN_ROWS, GOLDEN_COLS, 0);
+            parser.process(&std::fs::read(&fixture).unwrap());
+            let rendered = serde_json::to_string_pretty(&lines_json(&render_vt100_screen(parser.screen()))).unwrap() + "\n";
+            let golden = fixture.with_extension("json");
+            if update {
+                std::fs::write(&golden, &rendered).unwrap();
+                continue;
+            }
+            let expected = std::fs::read_to_string(&golden)
+                .unwrap_or_else(|_| panic!("{} has no golden; run with {}=1 to write it", fixture.display(), UPDATE_GOLDENS_ENV));
+            assert_eq!(
+                rendered,
+                expected,
+                "{} renders differently from its golden (run with {}=1 to accept the change)",
+                fixture.display(),
+                UPDATE_GOLDENS_ENV
+            );
+        }
+    }
+
+    #[test]
+    fn same_styled_cells_share_a_span() {
+        let mut parser = vt100::Parser::new(2, 8, 0);
+        parser.process(b"ab\x1b[31mcd\x1b[0me");
+        let lines = render_vt100_screen(parser.screen());
+        let spans: Vec<(&str, Option<Color>)> = lines[0].spans.iter().map(|s| (s.content.as_ref(), s.style.fg)).collect();
+        assert_eq!(
+            spans,
+            vec![("ab", Some(Color::Reset)), ("cd", Some(Color::Indexed(1))), ("e", Some(Color::Reset))]
+        );
+        assert!(lines[1].spans.is_empty());
+
+        // Blanks that show (reverse video) are kept
+        parser.process(b"\r\n\x1b[7m  ");
+        assert_eq!(render_vt100_screen(parser.screen())[1].spans[0].content, "  ");
+    }
+
+    #[test]
+    fn collected_links_on_screen_become_hyperlink_runs() {
+        use ratatui::layout::Rect;
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 40, 2));
+        buffer.set_string(0, 0, "中 see https://x.dev/a.", Style::default().fg(Color::Cyan));
+        buffer.set_string(0, 1, "https://x.dev/…", Style::default());
+
+        let runs = hyperlink_runs(&buffer, &["https://x.dev/a".to_string()]);
+        assert_eq!(runs.len(), 1, "the truncated copy isn't linked");
+        assert_eq!(runs[0].url, "https://x.dev/a");
+        let columns: Vec<u16> = runs[0].cells.iter().map(|&(x, _, _)| x).collect();
+        assert_eq!(columns, (7..22).collect::<Vec<u16>>());
+        assert_eq!(runs[0].cells[0].2.fg, Color::Cyan);
+        assert!(hyperlink_runs(&buffer, &[]).is_empty());
+    }
+}
@@ -0,0 +1,1309 @@
+//! Reusable widgets for the ralph-tui panels: stat cards, story cards,
+//! the footer, and small text helpers
+
+use std::time::Duration;
+
+use ratatui::{
+    Frame,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Gauge, Paragraph},
+};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::app::{AppSnapshot, IterationState, Mode, QueueStatus};
+use crate::keymap::KeyAction;
+use crate::lanes::LaneSnapshot;
+use crate::prd::{AcceptanceCriterion, Blocker, Burndown};
+use crate::pty::AgentActivity;
+use crate::testrun::TestResult;
+use crate::theme::*;
+use crate::tripwire::TripwireAlert;
+
+/// Marks text shortened by truncate_to_width and truncate_start_to_width
+const ELLIPSIS: &str = "…";
+
+/// Shown in place of branch and git details outside a repository
+pub const NOT_GIT_REPO: &str = "— (not a git repo)";
+
+/// Wrap text to `max_width` columns, paragraph by paragraph
+/// Newlines in the text are kept, and continuation lines keep the paragraph's
+/// leading indentation. Words longer than a line are broken with a trailing `-`.
+/// Paragraphs that already fit are returned unchanged.
+pub fn wrap_text(text: &str, max_width: usize) -> Vec<String> {
+    if max_width == 0 {
+        return vec![text.to_string()];
+    }
+
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        wrap_paragraph(paragraph, max_width, &mut lines);
+    }
+    lines
+}
+
+/// Wrap one line of text (no newlines) into `lines`
+fn wrap_paragraph(paragraph: &str, max_width: usize, lines: &mut Vec<String>) {
+    if paragraph.chars().count() <= max_width {
+        lines.push(paragraph.to_string());
+        return;
+    }
+
+    // Indent continuation lines like the first one, unless that leaves too little room
+    let indent_len = paragraph.len() - paragraph.trim_start().len();
+    let indent = &paragraph[..indent_len];
+    let indent = if indent.chars().count() * 2 > max_width { "" } else { indent };
+    let indent_width = indent.chars().count();
+
+    let mut current = indent.to_string();
+    let mut current_width = indent_width;
+    let mut has_word = false;
+    for word in paragraph.split_whitespace() {
+        let word_width = word.chars().count();
+        let separator = usize::from(has_word);
+        if current_width + separator + word_width <= max_width {
+            if has_word {
+                current.push(' ');
+            }
+            current.push_str(word);
+            current_width += separator + word_width;
+            has_word = true;
+            continue;
+        }
+
+        if has_word {
+            lines.push(std::mem::replace(&mut current, indent.to_string()));
+            current_width = indent_width;
+        }
+        // Hard-break a word that doesn't fit on a line of its own
+        let room = max_width - indent_width;
+        let mut rest = word;
+        while rest.chars().count() > room {
+            let (take, marker) = if room >= 2 { (room - 1, "-") } else { (room, "") };
+            let split = rest.char_indices().nth(take).map_or(rest.len(), |(i, _)| i);
+            lines.push(format!("{}{}{}", indent, &rest[..split], marker));
+            rest = &rest[split..];
+        }
+        current.push_str(rest);
+        current_width += rest.chars().count();
+        has_word = true;
+    }
+    lines.push(current);
+}
+
+/// Format duration as MM:SS
+pub fn format_duration(duration: Duration) -> String {
+    let total_secs = duration.as_secs();
+    let mins = total_secs / 60;
+    let secs = total_secs % 60;
+    format!("{:02}:{:02}", mins, secs)
+}
+
+/// Format a Unix timestamp as a UTC wall-clock time (HH:MM)
+pub fn format_clock_utc(unix_secs: u64) -> String {
+    let secs_of_day = unix_secs % 86_400;
+    format!("{:02}:{:02}", secs_of_day / 3600, secs_of_day / 60 % 60)
+}
+
+/// Render iteration and completion stat cards in a given area
+/// Returns the widgets to be rendered: (left_card, right_card)
+/// A budget cut short with `_` reads "3/3 (reduced)".
+pub fn render_stat_cards(
+    area: Rect,
+    current_iteration: u32,
+    max_iterations: u32,
+    reduced: bool,
+    (completed, total): (usize, usize),
+    human: Option<(usize, usize)>,
+    frame: &mut Frame,
+) {
+    // Split area horizontally for two cards with a small gap
+    let card_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ])
+        .split(area);
+
+    // Left card: Iterations
+    let iter_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(BORDER_SUBTLE))
+        .style(Style::default().bg(BG_SECONDARY));
+
+    let mut iter_spans = vec![
+        Span::styled("⏱ ", Style::default().fg(CYAN_PRIMARY)),
+        Span::styled(
+            format!("{}/{}", current_iteration, max_iterations),
+            Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+        ),
+    ];
+    if reduced {
+        iter_spans.push(Span::styled(" (reduced)", Style::default().fg(AMBER_WARNING)));
+    }
+    let iter_content = vec![
+        Line::from(iter_spans),
+        Line::from(vec![
+            Span::styled("ITERATIONS", Style::default().fg(TEXT_MUTED)),
+        ]),
+    ];
+
+    let iter_paragraph = Paragraph::new(iter_content)
+        .block(iter_block)
+        .alignment(Alignment::Center);
+
+    frame.render_widget(iter_paragraph, card_layout[0]);
+
+    // Right card: Completed
+    let comp_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(BORDER_SUBTLE))
+        .style(Style::default().bg(BG_SECONDARY));
+
+    // With human-assigned stories the card splits into "5/9 agent" over "2/3 human"
+    let comp_content = match human {
+        None => vec![
+            Line::from(vec![
+                Span::styled("◎ ", Style::default().fg(CYAN_PRIMARY)),
+                Span::styled(
+                    format!("{}/{}", completed, total),
+                    Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+                ),
+            ]),
+            Line::from(vec![
+                Span::styled("COMPLETED", Style::default().fg(TEXT_MUTED)),
+            ]),
+        ],
+        Some((human_completed, human_total)) => vec![
+            Line::from(vec![
+                Span::styled("◎ ", Style::default().fg(CYAN_PRIMARY)),
+                Span::styled(
+                    format!("{}/{}", completed, total),
+                    Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(" agent", Style::default().fg(TEXT_MUTED)),
+            ]),
+            Line::from(vec![
+                Span::styled(format!("{} ", HUMAN_GLYPH), Style::default().fg(AMBER_WARNING)),
+                Span::styled(
+                    format!("{}/{}", human_completed, human_total),
+                    Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(" human", Style::default().fg(TEXT_MUTED)),
+            ]),
+        ],
+    };
+
+    let comp_paragraph = Paragraph::new(comp_content)
+        .block(comp_block)
+        .alignment(Alignment::Center);
+
+    frame.render_widget(comp_paragraph, card_layout[1]);
+}
+
+/// Marks stories assigned to a person rather than the agent
+pub const HUMAN_GLYPH: &str = "👤";
+
+/// Story state for rendering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoryState {
+    Completed,
+    Active,
+    Pending,
+}
+
+/// Render the collapsible summary row for completed stories (e.g. "▸ 14 completed")
+pub fn render_completed_group_row(
+    area: Rect,
+    count: usize,
+    expanded: bool,
+    selected: bool,
+    frame: &mut Frame,
+) {
+    let arrow = if expanded { "▾" } else { "▸" };
+    let text_style = if selected {
+        Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(TEXT_SECONDARY)
+    };
+
+    let line = Line::from(vec![
+        Span::styled(format!(" {} ", arrow), Style::default().fg(GREEN_SUCCESS)),
+        Span::styled(format!("{} completed", count), text_style),
+    ]);
+    let bg_color = if selected { BG_TERTIARY } else { BG_SECONDARY };
+    let paragraph = Paragraph::new(line).style(Style::default().bg(bg_color));
+    frame.render_widget(paragraph, area);
+}
+
+/// Maximum acceptance criteria lines shown on an expanded story card
+pub const EXPANDED_CARD_CRITERIA: usize = 3;
+
+/// Height of a story card:
+/// - Completed/Pending: 3 lines (border + content + border)
+/// - Active: 5 lines (border + title + progress bar + percentage + border)
+/// - Expanded: plus one line per shown criterion (up to EXPANDED_CARD_CRITERIA)
+pub fn story_card_height(state: StoryState, expanded_criteria: usize) -> u16 {
+    let base = if state == StoryState::Active { 5 } else { 3 };
+    base + expanded_criteria.min(EXPANDED_CARD_CRITERIA) as u16
+}
+
+/// Scroll offset that keeps `selected` visible given the real height of every row
+/// Reserves one line each for the "more above" / "more below" indicators when shown.
+pub fn scroll_to_selected(heights: &[u16], selected: usize, offset: usize, available: u16) -> usize {
+    if heights.is_empty() {
+        return 0;
+    }
+    let selected = selected.min(heights.len() - 1);
+    let mut offset = offset.min(selected);
+    while offset < selected {
+        let above = if offset > 0 { 1 } else { 0 };
+        let below = if selected + 1 < heights.len() { 1 } else { 0 };
+        let used: u16 = heights[offset..=selected].iter().sum();
+        if used + above + below <= available {
+            break;
+        }
+        offset += 1;
+    }
+    offset
+}
+
+/// Render a single user story card
+/// Card height is given by story_card_height(); `criteria` lists the acceptance
+/// criteria shown inline when the card is expanded (empty when collapsed)
+#[allow(clippy::too_many_arguments)]
+pub fn render_story_card(
+    area: Rect,
+    story_id: &str,
+    story_title: &str,
+    criteria: &[AcceptanceCriterion],
+    state: StoryState,
+    tick: u64,
+    progress_percent: u16,
+    criteria_passed: usize,
+    criteria_total: usize,
+    stalled_attempts: u32,
+    stuck_threshold: u32,
+    criteria_history: &[usize],
+    history_flat: bool,
+    completed_iteration: Option<u32>,
+    targeted: bool,
+    assignee: Option<&str>,
+    skipped: bool,
+    selected: bool,
+    frame: &mut Frame,
+) {
+    // Determine colors based on state
+    // For active state, use pulsing indicator color
+    let (indicator, indicator_color, text_color, bg_color) = match state {
+        StoryState::Completed => ("●", GREEN_SUCCESS, CYAN_PRIMARY, BG_SECONDARY),
+        StoryState::Active => {
+            let pulse_color = get_pulse_color(tick, GREEN_ACTIVE, CYAN_DIM);
+            ("●", pulse_color, CYAN_PRIMARY, BG_TERTIARY)
+        }
+        StoryState::Pending => ("○", TEXT_MUTED, TEXT_SECONDARY, BG_SECONDARY),
+    };
+
+    // Use highlight border for selected card, normal for others
+    // A stuck active story overrides it: amber at the threshold, red at twice the threshold
+    let stuck_color = stuck_story_color(stalled_attempts, stuck_threshold);
+    let border_color = match (state, stuck_color) {
+        (StoryState::Active, Some(color)) => color,
+        _ if selected => CYAN_PRIMARY,
+        _ => BORDER_SUBTLE,
+    };
+
+    // Create card block with rounded borders
+    let card_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(border_color))
+        .style(Style::default().bg(bg_color));
+
+    // Format story ID as #XX (extract numeric part)
+    let story_num = story_id.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let formatted_id = format!("#{}", story_num);
+
+    // Build card content - single line with indicator, ID, and truncated title
+    let inner_width = area.width.saturating_sub(4) as usize; // Account for borders and padding
+    // Targeted stories (--stories / Space) get a ◎ after the ID
+    let target_mark = if targeted { "◎ " } else { "" };
+    // Stories assigned to a person get the person glyph and their name ("human" shows the glyph alone)
+    let human_mark = match assignee {
+        Some(name) if name.eq_ignore_ascii_case("human") => format!("{} ", HUMAN_GLYPH),
+        Some(name) => format!("{} {} ", HUMAN_GLYPH, name),
+        None => String::new(),
+    };
+    let prefix = format!("{} {} {}{}", indicator, formatted_id, target_mark, human_mark);
+    let prefix_len = Span::raw(prefix.as_str()).width();
+    // Completed cards note the iteration the story was completed in (e.g. "✓ iter 4"),
+    // stories skipped for this session get a muted "skipped" tag
+    let completed_note = match (state, completed_iteration) {
+        (StoryState::Completed, Some(iteration)) => Some(format!(" ✓ iter {}", iteration)),
+        (StoryState::Pending, _) if skipped => Some(" skipped".to_string()),
+        _ => None,
+    };
+    let note_len = completed_note.as_deref().map(display_width).unwrap_or(0);
+    let available_title_width = inner_width.saturating_sub(prefix_len + note_len);
+    let truncated_title = truncate_to_width(story_title, available_title_width);
+
+    let mut title_spans = vec![
+        Span::styled(format!("{} ", indicator), Style::default().fg(indicator_color)),
+        Span::styled(format!("{} ", formatted_id), Style::default().fg(text_color).add_modifier(Modifier::BOLD)),
+        Span::styled(target_mark, Style::default().fg(CYAN_PRIMARY)),
+        Span::styled(human_mark, Style::default().fg(AMBER_WARNING)),
+        Span::styled(truncated_title, Style::default().fg(text_color)),
+    ];
+    if let Some(note) = completed_note {
+        title_spans.push(Span::styled(note, Style::default().fg(TEXT_MUTED)));
+    }
+    let title_line = Line::from(title_spans);
+
+    // Inline acceptance criteria for expanded cards (✓ passed, ○ pending)
+    let criteria_lines: Vec<Line> = criteria
+        .iter()
+        .take(EXPANDED_CARD_CRITERIA)
+        .map(|criterion| {
+            let (marker, marker_color) = if criterion.passes {
+                ("✓", GREEN_SUCCESS)
+            } else {
+                ("○", TEXT_MUTED)
+            };
+            let description = truncate_to_width(&criterion.description, inner_width.saturating_sub(4));
+            Line::from(vec![
+                Span::styled(format!("  {} ", marker), Style::default().fg(marker_color)),
+                Span::styled(description, Style::default().fg(TEXT_SECONDARY)),
+            ])
+        })
+        .collect();
+
+    // For active state, show progress bar and percentage
+    if state == StoryState::Active {
+        // Render block first to get inner area
+        let inner_area = card_block.inner(area);
+        frame.render_widget(card_block, area);
+
+        // Split inner area: title (1 line), progress bar (1 line), percentage (1 line), criteria
+        let inner_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1), // Title line
+                Constraint::Length(1), // Progress bar
+                Constraint::Length(1), // Percentage
+                Constraint::Min(0),    // Expanded criteria
+            ])
+            .split(inner_area);
+
+        // Render title
+        let title_paragraph = Paragraph::new(vec![title_line]);
+        frame.render_widget(title_paragraph, inner_layout[0]);
+
+        // Render progress bar (Gauge widget), with the criteria sparkline to its right
+        let spark = sparkline(criteria_history, criteria_total);
+        let gauge_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(if spark.is_empty() { 0 } else { spark.chars().count() as u16 + 1 }),
+            ])
+            .split(inner_layout[1]);
+        let gauge = Gauge::default()
+            .gauge_style(Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY))
+            .percent(progress_percent)
+            .label(""); // No label on the gauge itself
+        frame.render_widget(gauge, gauge_layout[0]);
+        // Amber once the count has stayed flat for --flat-after
+        let spark_color = if history_flat { AMBER_WARNING } else { GREEN_ACTIVE };
+        frame.render_widget(
+            Paragraph::new(Span::styled(format!(" {}", spark), Style::default().fg(spark_color))),
+            gauge_layout[1],
+        );
+
+        // Render criteria count below the progress bar (e.g., "2/5 criteria")
+        let criteria_text = format!("{}/{} criteria ({}%)", criteria_passed, criteria_total, progress_percent);
+        let mut percent_spans = vec![Span::styled(criteria_text, Style::default().fg(TEXT_MUTED))];
+        // Show the attempt number once the story has gone an iteration without progress
+        if stalled_attempts > 0 {
+            percent_spans.push(Span::styled(
+                format!(" · attempt {}", stalled_attempts + 1),
+                Style::default().fg(stuck_color.unwrap_or(TEXT_MUTED)),
+            ));
+        }
+        let percent_line = Line::from(percent_spans);
+        let percent_paragraph = Paragraph::new(vec![percent_line]);
+        frame.render_widget(percent_paragraph, inner_layout[2]);
+        frame.render_widget(Paragraph::new(criteria_lines), inner_layout[3]);
+    } else {
+        // Completed and Pending states - title line, plus criteria when expanded
+        let mut lines = vec![title_line];
+        lines.extend(criteria_lines);
+        let paragraph = Paragraph::new(lines)
+            .block(card_block);
+        frame.render_widget(paragraph, area);
+    }
+}
+
+/// One block character per value, scaled to `max` (e.g. "▁▁▃▃▅")
+pub fn sparkline(values: &[usize], max: usize) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    values
+        .iter()
+        .map(|&value| BARS[(value.min(max) * (BARS.len() - 1)).checked_div(max).unwrap_or(0)])
+        .collect()
+}
+
+/// Terminal columns `text` takes up (CJK and most emoji take two, combining marks none)
+pub fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Truncate `text` to at most `width` columns (ending in "…" when shortened)
+pub fn truncate_to_width(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    // Longest prefix that leaves a column for the ellipsis
+    let room = width - display_width(ELLIPSIS);
+    let end = text
+        .char_indices()
+        .map(|(i, c)| i + c.len_utf8())
+        .take_while(|&end| display_width(&text[..end]) <= room)
+        .last()
+        .unwrap_or(0);
+    format!("{}{}", &text[..end], ELLIPSIS)
+}
+
+/// Truncate `text` to at most `width` columns from the front (starting with "…" when shortened)
+/// Used where the end matters most, such as file paths.
+pub fn truncate_start_to_width(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let room = width - display_width(ELLIPSIS);
+    let start = text
+        .char_indices()
+        .rev()
+        .map(|(i, _)| i)
+        .take_while(|&start| display_width(&text[start..]) <= room)
+        .last()
+        .unwrap_or(text.len());
+    format!("{}{}", ELLIPSIS, &text[start..])
+}
+
+/// Pad `text` with spaces to `width` columns (text already that wide is returned unchanged)
+pub fn pad_to_width(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(display_width(text))))
+}
+
+/// Build the footer line so it always fits on one line of `width` columns
+/// A tip goes in the free space after the mode text. Degrades in order: drop the
+/// tip, drop the session ID, shorten the keybindings to just "<quit_key> quit",
+/// then truncate the mode text.
+pub fn footer_line(
+    width: u16,
+    session_id: &str,
+    mode_text: &str,
+    keybindings_text: &str,
+    tip: Option<&str>,
+    quit_key: &str,
+) -> Line<'static> {
+    let width = width as usize;
+    // Too narrow for anything but the lead and trailing spaces
+    if width < 3 {
+        return Line::from(Span::styled(" ".repeat(width), Style::default().bg(BG_SECONDARY)));
+    }
+    let session_prefix = format!(" Session ID {} │ ", session_id);
+    let session_width = display_width(&session_prefix);
+    let mode_width = display_width(mode_text);
+    // Trailing space after the keybindings
+    let fits = |used: usize| used < width;
+
+    let show_session = fits(session_width + mode_width + display_width(keybindings_text));
+    // Three spaces after the mode, at least two before the keybindings
+    let tip = tip.filter(|tip| fits(session_width + mode_width + 5 + display_width(tip) + display_width(keybindings_text)));
+    let tip_width = tip.map_or(0, |tip| 3 + display_width(tip));
+    let keybindings = if show_session || fits(1 + mode_width + display_width(keybindings_text)) {
+        keybindings_text.to_string()
+    } else {
+        truncate_to_width(&format!("{} quit", quit_key), width - 2)
+    };
+    let keys_width = display_width(&keybindings);
+    let lead_width = if show_session { session_width } else { 1 };
+    let mode = truncate_to_width(mode_text, width.saturating_sub(lead_width + keys_width + 2));
+
+    let used = lead_width + display_width(&mode) + tip_width + keys_width + 1;
+    let fill_width = width.saturating_sub(used);
+
+    let mut spans = Vec::new();
+    if show_session {
+        spans.push(Span::styled(" Session ID ", Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)));
+        spans.push(Span::styled(session_id.to_string(), Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY)));
+        spans.push(Span::styled(" │ ", Style::default().fg(BORDER_SUBTLE).bg(BG_SECONDARY)));
+    } else {
+        spans.push(Span::styled(" ", Style::default().bg(BG_SECONDARY)));
+    }
+    spans.push(Span::styled(mode, Style::default().fg(CYAN_PRIMARY).bg(BG_SECONDARY)));
+    if let Some(tip) = tip {
+        spans.push(Span::styled(format!("   {}", tip), Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)));
+    }
+    // Fill remaining space with background color
+    spans.push(Span::styled(" ".repeat(fill_width), Style::default().bg(BG_SECONDARY)));
+    spans.push(Span::styled(keybindings, Style::default().fg(TEXT_MUTED).bg(BG_SECONDARY)));
+    spans.push(Span::styled(" ", Style::default().bg(BG_SECONDARY)));
+    Line::from(spans)
+}
+
+/// Render the progress.txt tail strip; the last `highlighted` lines were just appended
+pub fn render_progress_strip(area: Rect, tail: &[String], highlighted: usize, frame: &mut Frame) {
+    let block = Block::default()
+        .title(Span::styled(" progress.txt ", Style::default().fg(TEXT_MUTED)))
+        .borders(Borders::TOP)
+        .border_style(Style::default().fg(BORDER_SUBTLE))
+        .style(Style::default().bg(BG_PRIMARY));
+    let first_new = tail.len().saturating_sub(highlighted);
+    let lines: Vec<Line> = tail
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let style = if index >= first_new {
+                Style::default().fg(GREEN_ACTIVE).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().fg(TEXT_SECONDARY)
+            };
+            Line::from(Span::styled(line.clone(), style))
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Render the bottom footer bar: session ID on the left, mode in the middle, keybindings on the right
+pub fn render_footer(
+    area: Rect,
+    session_id: &str,
+    mode_text: &str,
+    keybindings_text: &str,
+    tip: Option<&str>,
+    quit_key: &str,
+    frame: &mut Frame,
+) {
+    let footer = Paragraph::new(footer_line(area.width, session_id, mode_text, keybindings_text, tip, quit_key))
+        .style(Style::default().bg(BG_SECONDARY));
+    frame.render_widget(footer, area);
+}
+
+/// Time left in the --max-runtime budget (e.g. "⌛ Budget: 42:10 left of 360:00")
+/// Turns amber in the last 10% of the budget and once it is spent.
+pub fn runtime_budget_line(snapshot: &AppSnapshot) -> Option<Line<'static>> {
+    let limit = snapshot.max_runtime?;
+    let remaining = limit.saturating_sub(snapshot.session_elapsed);
+    let color = if remaining * 10 <= limit { AMBER_WARNING } else { TEXT_PRIMARY };
+    let text = if remaining.is_zero() {
+        "limit reached - wrapping up".to_string()
+    } else {
+        format!("{} left of {}", format_duration(remaining), format_duration(limit))
+    };
+    Some(Line::from(vec![
+        Span::styled("⌛ Budget: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(text, Style::default().fg(color)),
+    ]))
+}
+
+/// Ahead/behind badge for the merge target (e.g. "⎇ main  ↑4 ↓27")
+/// Turns amber once the branch is `behind_warn` commits behind, with a hint to sync.
+pub fn drift_badge_line(snapshot: &AppSnapshot) -> Option<Line<'static>> {
+    if snapshot.not_git_repo {
+        let target = snapshot.prd.as_ref()?.merge_target.clone().filter(|t| !t.is_empty())?;
+        return Some(Line::from(vec![
+            Span::styled("⎇ ", Style::default().fg(CYAN_PRIMARY)),
+            Span::styled(target, Style::default().fg(TEXT_SECONDARY)),
+            Span::styled(format!("  {}", NOT_GIT_REPO), Style::default().fg(TEXT_MUTED)),
+        ]));
+    }
+    let target = snapshot.merge_target.as_ref()?;
+    let drift = snapshot.drift?;
+    let warn = snapshot.behind_warn > 0 && drift.behind >= snapshot.behind_warn;
+    let count_style = if warn {
+        Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(TEXT_MUTED)
+    };
+
+    let mut spans = vec![
+        Span::styled("⎇ ", Style::default().fg(CYAN_PRIMARY)),
+        Span::styled(target.clone(), Style::default().fg(TEXT_SECONDARY)),
+        Span::styled(format!("  ↑{} ↓{}", drift.ahead, drift.behind), count_style),
+    ];
+    let note = if let Some((_, strategy)) = snapshot.sync_running {
+        Some(format!(" · {}...", strategy.label()))
+    } else if snapshot.sync_requested {
+        Some(" · sync queued".to_string())
+    } else if warn {
+        Some(format!(" · {} to sync", snapshot.keymap.label(KeyAction::Sync)))
+    } else {
+        None
+    };
+    if let Some(note) = note {
+        spans.push(Span::styled(note, Style::default().fg(TEXT_MUTED)));
+    }
+    Some(Line::from(spans))
+}
+
+/// Batch queue for the status panel: one "✓ name  done" line per task
+/// Empty unless several task directories are queued.
+pub fn queue_lines(snapshot: &AppSnapshot) -> Vec<Line<'static>> {
+    if snapshot.queue.len() < 2 {
+        return Vec::new();
+    }
+    let mut lines = vec![Line::from(Span::styled(
+        "☰ Queue",
+        Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+    ))];
+    for entry in &snapshot.queue {
+        let color = match entry.status {
+            QueueStatus::Done => GREEN_SUCCESS,
+            QueueStatus::Failed => RED_ERROR,
+            QueueStatus::Running => CYAN_PRIMARY,
+            QueueStatus::Pending | QueueStatus::Skipped => TEXT_MUTED,
+        };
+        let chained = if entry.chained { "↳ " } else { "" };
+        lines.push(Line::from(vec![
+            Span::styled(format!("  {} ", entry.status.symbol()), Style::default().fg(color)),
+            Span::styled(format!("{}{}", chained, entry.name()), Style::default().fg(TEXT_SECONDARY)),
+            Span::styled(format!("  {}", entry.status.label()), Style::default().fg(color)),
+        ]));
+    }
+    lines
+}
+
+/// Status chip for the Claude panel title: WORKING (pulsing green), IDLE (amber), STARTING (gray)
+pub fn agent_activity_chip(activity: AgentActivity, tick: u64) -> Span<'static> {
+    match activity {
+        AgentActivity::Working => Span::styled(
+            " WORKING ",
+            Style::default().fg(get_pulse_color(tick, GREEN_ACTIVE, CYAN_DIM)).add_modifier(Modifier::BOLD),
+        ),
+        AgentActivity::Idle => Span::styled(
+            " IDLE — awaiting input ",
+            Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD),
+        ),
+        AgentActivity::Starting => Span::styled(" STARTING ", Style::default().fg(TEXT_MUTED)),
+    }
+}
+
+/// Test-run badge for the status panel: "Tests: 42 ✓ / 1 ✗" (red when anything failed)
+pub fn test_result_line(result: TestResult) -> Line<'static> {
+    let color = if result.failed > 0 { RED_ERROR } else { GREEN_SUCCESS };
+    Line::from(vec![
+        Span::styled("Tests: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(
+            format!("{} ✓ / {} ✗", result.passed, result.failed),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(format!(" ({})", result.framework), Style::default().fg(TEXT_MUTED)),
+    ])
+}
+
+/// Burndown for the status panel: the estimate left after the session's time so far,
+/// and when it is done at this session's rate (once a story with an estimate passed)
+pub fn burndown_lines(burndown: Burndown, elapsed: Duration) -> Vec<Line<'static>> {
+    let unit = burndown.unit;
+    let color = if burndown.remaining == 0.0 { GREEN_SUCCESS } else { TEXT_PRIMARY };
+    let mut lines = vec![Line::from(vec![
+        Span::styled("Burndown: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(
+            format!("{} of {} left", unit.format(burndown.remaining), unit.format(burndown.total)),
+            Style::default().fg(color),
+        ),
+        Span::styled(format!(" after {}", format_duration(elapsed)), Style::default().fg(TEXT_MUTED)),
+    ])];
+    if let Some(finish) = burndown.projected_finish {
+        lines.push(Line::from(Span::styled(
+            format!("  Finish ~{} UTC at {}/h", format_clock_utc(finish), unit.format(burndown.rate_per_hour)),
+            Style::default().fg(TEXT_SECONDARY),
+        )));
+    }
+    lines
+}
+
+/// Compaction badge for the status panel: "⚠ Compactions: 2" in amber
+/// Compactions usually mean the agent is running low on context for the task.
+pub fn compaction_line(compactions: u32) -> Line<'static> {
+    Line::from(vec![
+        Span::styled("⚠ Compactions: ", Style::default().fg(AMBER_WARNING).add_modifier(Modifier::BOLD)),
+        Span::styled(compactions.to_string(), Style::default().fg(AMBER_WARNING)),
+    ])
+}
+
+/// How close to the rotation threshold progress.txt's size turns amber
+pub const ROTATION_WARN_LINES: usize = 20;
+
+/// progress.txt size for the status panel: "progress.txt: 284/300 lines", amber within
+/// ROTATION_WARN_LINES of the rotation threshold (no threshold: just the count), then
+/// "  +37 this iter" once the iteration added lines
+pub fn progress_file_lines(count: usize, threshold: u32, this_iteration: Option<usize>) -> Vec<Line<'static>> {
+    let threshold = threshold as usize;
+    let (size, color) = match threshold {
+        0 => (format!("{} lines", count), TEXT_PRIMARY),
+        _ if count + ROTATION_WARN_LINES >= threshold => (format!("{}/{} lines", count, threshold), AMBER_WARNING),
+        _ => (format!("{}/{} lines", count, threshold), TEXT_PRIMARY),
+    };
+    let mut lines = vec![Line::from(vec![
+        Span::styled("progress.txt: ", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(size, Style::default().fg(color)),
+    ])];
+    if let Some(added) = this_iteration.filter(|&added| added > 0) {
+        lines.push(Line::from(Span::styled(format!("  +{} this iter", added), Style::default().fg(TEXT_SECONDARY))));
+    }
+    lines
+}
+
+/// Blockers listed in the "Needs attention" section before it summarizes the rest
+const ATTENTION_SECTION_ROWS: usize = 3;
+
+/// Height of the "Needs attention" section (0 when there are no blockers)
+pub fn attention_section_height(blockers: usize) -> u16 {
+    match blockers {
+        0 => 0,
+        n if n > ATTENTION_SECTION_ROWS => ATTENTION_SECTION_ROWS as u16 + 3, // Borders + "… N more"
+        n => n as u16 + 2,
+    }
+}
+
+/// Red-bordered "Needs attention (N)" section listing open blockers and questions
+pub fn render_attention_section(area: Rect, blockers: &[Blocker], selected: bool, frame: &mut Frame) {
+    let title_style = Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD);
+    let title = if selected {
+        Line::from(vec![
+            Span::styled(format!(" ▸ Needs attention ({}) ", blockers.len()), title_style),
+            Span::styled("⏎ Details ", Style::default().fg(TEXT_MUTED)),
+        ])
+    } else {
+        Line::from(Span::styled(format!(" Needs attention ({}) ", blockers.len()), title_style))
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(RED_ERROR))
+        .style(Style::default().bg(if selected { BG_TERTIARY } else { BG_SECONDARY }));
+
+    let width = area.width.saturating_sub(4) as usize;
+    let mut lines: Vec<Line> = blockers
+        .iter()
+        .take(ATTENTION_SECTION_ROWS)
+        .map(|blocker| {
+            let text = format!("{}: {}", blocker.label, blocker.text);
+            Line::from(Span::styled(truncate_to_width(&text, width), Style::default().fg(TEXT_PRIMARY)))
+        })
+        .collect();
+    if blockers.len() > ATTENTION_SECTION_ROWS {
+        lines.push(Line::from(Span::styled(
+            format!("… {} more", blockers.len() - ATTENTION_SECTION_ROWS),
+            Style::default().fg(TEXT_MUTED),
+        )));
+    }
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Red banner shown across the top of the Claude terminal while input is being dropped
+pub fn render_input_stalled_banner(area: Rect, frame: &mut Frame) {
+    let banner = Line::from(vec![
+        Span::styled(" ⚠ agent not reading input", Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(" - keystrokes are being dropped", Style::default().fg(TEXT_PRIMARY)),
+    ]);
+    frame.render_widget(Paragraph::new(banner).style(Style::default().bg(RED_ERROR)), area);
+}
+
+/// Render the red "reader error" banner over the last line of the Claude terminal
+pub fn render_reader_error_banner(area: Rect, frame: &mut Frame) {
+    let banner = Line::from(vec![
+        Span::styled(" ⚠ internal reader error", Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(" — output may be incomplete", Style::default().fg(TEXT_PRIMARY)),
+    ]);
+    frame.render_widget(Paragraph::new(banner).style(Style::default().bg(RED_ERROR)), area);
+}
+
+/// Render one --parallel lane as a bordered Claude pane titled with its stories and iteration
+/// The focused lane (keyboard input in Claude mode) gets the active border.
+pub fn render_lane_pane(area: Rect, lane: &LaneSnapshot, mode: Mode, tick: u64, frame: &mut Frame) {
+    let status = match lane.state {
+        IterationState::Completed => Span::styled("✓ DONE", Style::default().fg(GREEN_SUCCESS).add_modifier(Modifier::BOLD)),
+        IterationState::Running => agent_activity_chip(lane.agent_activity, tick),
+        IterationState::WaitingDelay | IterationState::NeedsRestart => {
+            Span::styled("waiting", Style::default().fg(TEXT_MUTED))
+        }
+        IterationState::Aborted => Span::styled("stopped", Style::default().fg(TEXT_MUTED)),
+    };
+    let mut title = vec![Span::raw(format!(
+        " >_ {} · {} · iter {} ",
+        lane.label,
+        lane.stories.join(", "),
+        lane.iteration
+    ))];
+    if lane.focused && mode == Mode::Claude {
+        title.push(Span::styled("[ACTIVE]", Style::default().fg(CYAN_PRIMARY)));
+        title.push(Span::raw(" "));
+    }
+    title.push(status);
+    let border_style = if lane.focused {
+        Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(BORDER_SUBTLE)
+    };
+    let block = Block::default()
+        .title(Line::from(title))
+        .borders(Borders::ALL)
+        .border_style(border_style)
+        .style(Style::default().bg(BG_PRIMARY));
+
+    let content_area = block.inner(area);
+    frame.render_widget(block, area);
+
+    // Keep the most recent output in view
+    let content_height = content_area.height as usize;
+    let scroll = lane.lines.len().saturating_sub(content_height) as u16;
+    frame.render_widget(Paragraph::new(lane.lines.clone()).scroll((scroll, 0)), content_area);
+}
+
+/// Render the quick actions menu for `story_id` centered in `area`, `selected` highlighted
+pub fn render_story_menu(area: Rect, story_id: &str, actions: &[&str], selected: usize, frame: &mut Frame) {
+    let width = (actions.iter().map(|a| a.chars().count()).max().unwrap_or(0) as u16 + 8).min(area.width);
+    let height = (actions.len() as u16 + 4).min(area.height);
+    let menu_area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default()
+        .title(format!(" {} ", story_id))
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(CYAN_PRIMARY))
+        .style(Style::default().bg(BG_SECONDARY));
+
+    let mut lines: Vec<Line> = actions
+        .iter()
+        .enumerate()
+        .map(|(i, action)| {
+            if i == selected {
+                Line::from(Span::styled(
+                    format!(" ▶ {}", action),
+                    Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+                ))
+            } else {
+                Line::from(Span::styled(format!("   {}", action), Style::default().fg(TEXT_PRIMARY)))
+            }
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled(" ⏎", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(" Run  ", Style::default().fg(TEXT_MUTED)),
+        Span::styled("Esc", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+        Span::styled(" Close", Style::default().fg(TEXT_MUTED)),
+    ]));
+
+    frame.render_widget(Clear, menu_area);
+    frame.render_widget(Paragraph::new(lines).block(block), menu_area);
+}
+
+/// Red modal for a command that matched a dangerous pattern, centered over `area`
+/// It says plainly that the command may already have run: the modal can't stop it.
+pub fn render_tripwire_modal(area: Rect, alert: &TripwireAlert, queued: usize, frame: &mut Frame) {
+    let width = area.width.min(72);
+    let text_width = width.saturating_sub(4) as usize;
+    let mut lines = vec![Line::from(Span::styled(
+        format!(" Matched \"{}\"{}:", alert.pattern, alert.lane.map(|lane| format!(" in lane {}", lane + 1)).unwrap_or_default()),
+        Style::default().fg(TEXT_SECONDARY),
+    ))];
+    lines.extend(wrap_text(&alert.command, text_width.saturating_sub(2)).into_iter().map(|line| {
+        Line::from(Span::styled(format!("   {}", line), Style::default().fg(TEXT_PRIMARY).add_modifier(Modifier::BOLD)))
+    }));
+    lines.push(Line::from(""));
+    lines.extend(
+        wrap_text("The agent may already be running this command, or have finished it.", text_width)
+            .into_iter()
+            .map(|line| Line::from(Span::styled(format!(" {}", line), Style::default().fg(AMBER_WARNING)))),
+    );
+    lines.push(Line::from(""));
+    let key = |key: &'static str| Span::styled(key, Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD));
+    let label = |label: &'static str| Span::styled(label, Style::default().fg(TEXT_MUTED));
+    lines.push(Line::from(vec![
+        key(" r"),
+        label(" Let it run  "),
+        key("c"),
+        label(" Send Ctrl+C to agent  "),
+        key("p"),
+        label(" Pause loop"),
+    ]));
+    if queued > 0 {
+        lines.push(Line::from(Span::styled(format!(" {} more waiting", queued), Style::default().fg(TEXT_MUTED))));
+    }
+
+    let height = (lines.len() as u16 + 2).min(area.height);
+    let modal_area = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default()
+        .title(Span::styled(" ⚠ Dangerous command ", Style::default().fg(RED_ERROR).add_modifier(Modifier::BOLD)))
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(RED_ERROR))
+        .style(Style::default().bg(BG_SECONDARY));
+    frame.render_widget(Clear, modal_area);
+    frame.render_widget(Paragraph::new(lines).block(block), modal_area);
+}
+
+/// Render the sync error screen (e.g. merge conflicts) over the given area
+pub fn render_pause_screen(area: Rect, title: &str, message: &str, dismiss: Option<&str>, frame: &mut Frame) {
+    let block = Block::default()
+        .title(title.to_string())
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(RED_ERROR))
+        .style(Style::default().bg(BG_SECONDARY));
+
+    let mut lines: Vec<Line> = message
+        .lines()
+        .map(|line| Line::from(Span::styled(format!(" {}", line), Style::default().fg(TEXT_PRIMARY))))
+        .collect();
+    if let Some(dismiss) = dismiss {
+        lines.push(Line::from(""));
+        lines.push(Line::from(vec![
+            Span::styled(" Esc", Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD)),
+            Span::styled(format!(" {}", dismiss), Style::default().fg(TEXT_MUTED)),
+        ]));
+    }
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Warning color for a story stalled for `stalled_attempts` iterations
+/// None below the threshold, amber at the threshold, red at twice the threshold.
+pub fn stuck_story_color(stalled_attempts: u32, stuck_threshold: u32) -> Option<Color> {
+    if stuck_threshold == 0 {
+        None
+    } else if stalled_attempts >= stuck_threshold * 2 {
+        Some(RED_ERROR)
+    } else if stalled_attempts >= stuck_threshold {
+        Some(AMBER_WARNING)
+    } else {
+        None
+    }
+}
+
+/// Render progress stat cards (stories left + completion %) in a given area
+pub fn render_progress_cards(
+    area: Rect,
+    completed: usize,
+    total: usize,
+    frame: &mut Frame,
+) {
+    // Split area horizontally for two cards
+    let card_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(50),
+            Constraint::Percentage(50),
+        ])
+        .split(area);
+
+    // Left card: Stories Left
+    let stories_left = total.saturating_sub(completed);
+    let left_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(BORDER_SUBTLE))
+        .style(Style::default().bg(BG_SECONDARY));
+
+    let left_content = vec![
+        Line::from(vec![
+            Span::styled("◇ ", Style::default().fg(CYAN_PRIMARY)),
+            Span::styled(
+                format!("{}", stories_left),
+                Style::default().fg(CYAN_PRIMARY).add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled("STORIES LEFT", Style::default().fg(TEXT_MUTED)),
+        ]),
+    ];
+
+    let left_paragraph = Paragraph::new(left_content)
+        .block(left_block)
+        .alignment(Alignment::Center);
+
+    frame.render_widget(left_paragraph, card_layout[0]);
+
+    // Right card: Progress percentage
+    let progress_pct = if total > 0 {
+        (completed as f32 / total as f32 * 100.0) as u8
+    } else {
+        0
+    };
+
+    let right_block = Block::default()
+        .borders(Borders::ALL)
+        .border_set(ROUNDED_BORDERS)
+        .border_style(Style::default().fg(BORDER_SUBTLE))
+        .style(Style::default().bg(BG_SECONDARY));
+
+    let progress_color = if progress_pct == 100 {
+        GREEN_SUCCESS
+    } else {
+        CYAN_PRIMARY
+    };
+
+    let right_content = vec![
+        Line::from(vec![
+            Span::styled("⟠ ", Style::default().fg(progress_color)),
+            Span::styled(
+                format!("{}%", progress_pct),
+                Style::default().fg(progress_color).add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled("PROGRESS", Style::default().fg(TEXT_MUTED)),
+        ]),
+    ];
+
+    let right_paragraph = Paragraph::new(right_content)
+        .block(right_block)
+        .alignment(Alignment::Center);
+
+    frame.render_widget(right_paragraph, card_layout[1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{Terminal, backend::TestBackend};
+    use crate::prd::EstimateUnit;
+
+    #[test]
+    fn burndown_shows_the_projection_once_estimates_burn() {
+        let text = |lines: Vec<Line>| lines.iter().map(|line| line.to_string()).collect::<Vec<_>>();
+        let mut burndown =
+            Burndown { remaining: 5.5, total: 8.5, unit: EstimateUnit::Points, rate_per_hour: 6.0, projected_finish: Some(3_300) };
+        assert_eq!(
+            text(burndown_lines(burndown, Duration::from_secs(1800))),
+            ["Burndown: 5.5 pt of 8.5 pt left after 30:00", "  Finish ~00:55 UTC at 6 pt/h"]
+        );
+        burndown.projected_finish = None;
+        assert_eq!(burndown_lines(burndown, Duration::ZERO).len(), 1);
+    }
+
+    /// Render the footer at `width` columns and return the single rendered row
+    fn render_footer_row(width: u16, session_id: &str, mode_text: &str, keybindings_text: &str) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(width, 1)).unwrap();
+        terminal
+            .draw(|frame| render_footer(frame.area(), session_id, mode_text, keybindings_text, None, "^Q", frame))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..width).map(|x| buffer[(x, 0)].symbol().to_string()).collect()
+    }
+
+    /// Deterministic mix of short words, long tokens, indentation and newlines
+    fn wrap_samples() -> Vec<String> {
+        let pieces = [
+            "a", "word", "  ", "\n", "https://example.com/a/very/long/path/to/some/resource?q=1", "indented",
+            "\n    ", "x", "supercalifragilistic", "é", "\n\n", "mid",
+        ];
+        let mut seed = 7u64;
+        (0..200)
+            .map(|_| {
+                let mut text = String::new();
+                for _ in 0..(seed % 12) {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    text.push_str(pieces[(seed >> 33) as usize % pieces.len()]);
+                    text.push(' ');
+                }
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                text
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sparkline_scales_to_the_criteria_total() {
+        assert_eq!(sparkline(&[0, 0, 1, 2, 4], 4), "▁▁▂▄█");
+        // Counts above the total are clamped, and a zero total doesn't divide
+        assert_eq!(sparkline(&[5], 4), "█");
+        assert_eq!(sparkline(&[0, 0], 0), "▁▁");
+        assert_eq!(sparkline(&[], 3), "");
+    }
+
+    #[test]
+    fn wrapped_lines_never_exceed_width() {
+        for text in wrap_samples() {
+            for width in 1..40 {
+                for line in wrap_text(&text, width) {
+                    assert!(line.chars().count() <= width, "{:?} at width {} gave {:?}", text, width, line);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn short_text_round_trips_unchanged() {
+        for text in wrap_samples() {
+            let width = text.lines().map(|l| l.chars().count()).max().unwrap_or(0).max(1);
+            assert_eq!(wrap_text(&text, width).join("\n"), text);
+        }
+        assert_eq!(wrap_text("", 10), vec![""]);
+    }
+
+    #[test]
+    fn wrap_keeps_newlines_indent_and_breaks_long_tokens() {
+        assert_eq!(wrap_text("First line\n\nSecond", 20), vec!["First line", "", "Second"]);
+        assert_eq!(
+            wrap_text("  - indented item that wraps", 14),
+            vec!["  - indented", "  item that", "  wraps"]
+        );
+        assert_eq!(
+            wrap_text("see https://example.com/long", 10),
+            vec!["see", "https://e-", "xample.co-", "m/long"]
+        );
+    }
+
+    #[test]
+    fn footer_at_120_columns_shows_everything() {
+        let row = render_footer_row(120, "RL-04242", "Claude Mode", "^O: Ralph Mode | ^Q: Quit");
+        assert_eq!(
+            row,
+            format!(" Session ID RL-04242 │ Claude Mode{}^O: Ralph Mode | ^Q: Quit ", " ".repeat(60))
+        );
+    }
+
+    #[test]
+    fn footer_at_80_columns_drops_long_session_id_first() {
+        let row = render_footer_row(
+            80,
+            "RL-04242-resumed-from-previous-session",
+            "Claude Mode",
+            "^O: Ralph Mode | ^Q: Quit",
+        );
+        assert_eq!(row, format!(" Claude Mode{}^O: Ralph Mode | ^Q: Quit ", " ".repeat(42)));
+    }
+
+    #[test]
+    fn footer_at_60_columns_uses_compact_keybindings() {
+        let row = render_footer_row(
+            60,
+            "RL-04242",
+            "Claude Mode",
+            "^Q: Quit | Waiting for next iteration, ^O: Ralph Mode, i: Claude Mode",
+        );
+        assert_eq!(row, format!(" Claude Mode{}^Q quit ", " ".repeat(40)));
+    }
+
+    #[test]
+    fn footer_truncates_mode_text_when_nothing_else_fits() {
+        let line = footer_line(14, "RL-04242", "Claude Mode", "^O: Ralph Mode | ^Q: Quit", None, "^Q");
+        assert_eq!(line.width(), 14);
+        assert_eq!(line.to_string(), " Cla… ^Q quit ");
+    }
+
+    #[test]
+    fn footer_tip_is_dropped_before_the_session_id() {
+        let tip = "Tip: press i to type directly to the agent";
+        let line = footer_line(120, "RL-04242", "Ralph Mode", "i: Claude Mode | ^Q: Quit", Some(tip), "^Q");
+        assert_eq!(
+            line.to_string(),
+            format!(" Session ID RL-04242 │ Ralph Mode   {}{}i: Claude Mode | ^Q: Quit ", tip, " ".repeat(16))
+        );
+        // Too narrow for the tip: the footer is as it would be without one
+        let line = footer_line(100, "RL-04242", "Ralph Mode", "i: Claude Mode | ^Q: Quit", Some(tip), "^Q");
+        assert_eq!(line, footer_line(100, "RL-04242", "Ralph Mode", "i: Claude Mode | ^Q: Quit", None, "^Q"));
+        assert!(line.to_string().starts_with(" Session ID RL-04242 │ Ralph Mode "));
+    }
+
+    /// CJK, emoji (with a ZWJ family and a variation selector) and combining marks
+    const WIDE_SAMPLES: [&str; 5] = [
+        "ログイン画面のエラー表示を改善する",
+        "Ship 🚀 the 🎉 release notes 📝",
+        "Family 👨‍👩‍👧‍👦 profiles ❤️ sync",
+        "Cafe\u{301} re\u{301}sume\u{301} na\u{308}ive",
+        "混合 mixed テキスト with ascii",
+    ];
+
+    #[test]
+    fn truncation_fits_display_width() {
+        for text in WIDE_SAMPLES {
+            for width in 0..40 {
+                for truncated in [truncate_to_width(text, width), truncate_start_to_width(text, width)] {
+                    assert!(
+                        display_width(&truncated) <= width,
+                        "{:?} at width {} gave {:?}",
+                        text,
+                        width,
+                        truncated
+                    );
+                }
+            }
+        }
+        // The ellipsis takes a column, and a wide character that won't fit beside it is dropped
+        assert_eq!(truncate_to_width("ログイン画面", 6), "ログ…");
+        assert_eq!(truncate_to_width("ログイン画面", 5), "ログ…");
+        assert_eq!(truncate_start_to_width("src/ログイン.rs", 9), "…イン.rs");
+        // Combining marks stay with their letter
+        assert_eq!(truncate_to_width("Cafe\u{301} time", 6), "Cafe\u{301} …");
+        assert_eq!(pad_to_width("画面", 6), "画面  ");
+        assert_eq!(pad_to_width("画面", 3), "画面");
+    }
+
+    #[test]
+    fn story_card_title_stays_inside_the_border() {
+        for title in WIDE_SAMPLES {
+            let mut terminal = Terminal::new(TestBackend::new(24, 3)).unwrap();
+            terminal
+                .draw(|frame| {
+                    render_story_card(
+                        frame.area(), "US-007", title, &[], StoryState::Pending, 0, 0, 0, 0, 0, 3, &[], false, None,
+                        false, None, false, false, frame,
+                    )
+                })
+                .unwrap();
+            let buffer = terminal.backend().buffer();
+            let row: String = (0..24).map(|x| buffer[(x, 1)].symbol().to_string()).collect();
+            assert!(row.ends_with(" │"), "{:?} overflowed into the border: {:?}", title, row);
+            assert!(row.contains('…'), "{:?} was not marked as truncated: {:?}", title, row);
+        }
+    }
+
+    #[test]
+    fn footer_with_wide_mode_text_fills_exactly() {
+        for width in 0..100 {
+            let line = footer_line(width, "RL-04242", "Claude モード 🚀", "^O: Ralph Mode | ^Q: Quit", None, "^Q");
+            assert!(line.width() <= width as usize, "footer overflows at width {}", width);
+        }
+        // The fill pads to the full width, so the keybindings stay right-aligned
+        let line = footer_line(60, "RL-04242", "Claude モード", "^O: Ralph Mode | ^Q: Quit", None, "^Q");
+        assert_eq!(line.width(), 60);
+        assert!(line.to_string().ends_with("^O: Ralph Mode | ^Q: Quit "));
+    }
+
+    #[test]
+    fn footer_always_fits_one_line() {
+        for width in 0..140 {
+            let line = footer_line(width, "RL-04242", "Claude Mode", "^O: Ralph Mode | ^Q: Quit", None, "^Q");
+            assert!(line.width() <= width as usize, "footer overflows at width {}", width);
+        }
+    }
+}
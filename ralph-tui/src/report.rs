@@ -0,0 +1,361 @@
+//! Iterations each story took, for the completion summary and report.json
+//!
+//! The App counts every iteration a story was the active one (persisted in
+//! `.ralph-session.json`, so resumed sessions keep adding to it). On exit the
+//! counts are printed as a bar chart, completed stories first with the most
+//! expensive on top and unfinished ones last (even those never worked on), and
+//! written to the task's
+//! report.json alongside the session totals. When stories carry an `estimate`,
+//! each one is also set against the agent time it was active for. report.json
+//! also lists the stories the session changed in prd.json (as in the `D` view),
+//! and the evidence the agent recorded on each story's passed criteria.
+
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::app::SessionState;
+use crate::digest::{CriterionEvidence, criterion_evidence};
+use crate::prd::{EstimateUnit, Prd};
+use crate::prd_diff::{self, CriterionDiff, StoryChange};
+use crate::render::widgets::{display_width, pad_to_width};
+
+/// Session report, written to the task directory on exit
+pub const REPORT_FILE: &str = "report.json";
+
+/// Narrowest terminal that gets bars; below it the chart is a plain table
+pub const CHART_MIN_WIDTH: usize = 40;
+
+/// Bar character, one per step of the scale
+const BAR: &str = "█";
+
+/// Marks a story that doesn't pass (shown in red on a terminal)
+const UNFINISHED: &str = " (unfinished)";
+
+/// Iterations a story was active, and whether it passes now
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryIterations {
+    pub id: String,
+    pub iterations: u32,
+    pub completed: bool,
+}
+
+/// A story's estimate next to the agent time it took
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryEstimate {
+    pub id: String,
+    pub estimate: f64,
+    /// Seconds of agent time the story was the active one (all sessions)
+    pub actual_secs: u64,
+    pub completed: bool,
+}
+
+/// A story the session changed in prd.json: its status and criteria marks at session
+/// start and now (None: not in that PRD)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedStory {
+    pub id: String,
+    pub title: String,
+    pub change: StoryChange,
+    pub before: Option<bool>,
+    pub after: Option<bool>,
+    pub criteria: Vec<CriterionDiff>,
+}
+
+/// Evidence (and verifiedAt) the agent recorded on a story's passed criteria
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoryEvidence {
+    pub id: String,
+    pub criteria: Vec<CriterionEvidence>,
+}
+
+/// Contents of report.json
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionReport {
+    pub session_id: String,
+    pub iterations: u32,
+    pub stories_completed: usize,
+    pub stories_total: usize,
+    /// Same order as the completion summary's chart
+    pub story_iterations: Vec<StoryIterations>,
+    /// Left out when no story has an estimate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate_unit: Option<EstimateUnit>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub story_estimates: Vec<StoryEstimate>,
+    /// Stories whose status or criteria changed since the session started
+    pub changed_stories: Vec<ChangedStory>,
+    /// Stories with evidence on their passed criteria, in priority order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub story_evidence: Vec<StoryEvidence>,
+}
+
+/// Stories with evidence on at least one passed criterion, in priority order
+pub fn story_evidence(prd: &Prd) -> Vec<StoryEvidence> {
+    prd.stories_by_priority()
+        .into_iter()
+        .map(|story| StoryEvidence { id: story.id.clone(), criteria: criterion_evidence(story) })
+        .filter(|story| !story.criteria.is_empty())
+        .collect()
+}
+
+/// The stories that changed between `start` and `now`, in prd_diff::diff's order
+pub fn changed_stories(start: &Prd, now: &Prd) -> Vec<ChangedStory> {
+    prd_diff::diff(start, now)
+        .into_iter()
+        .filter_map(|diff| {
+            let change = diff.change();
+            (change != StoryChange::Unchanged).then_some(ChangedStory {
+                change,
+                before: diff.before,
+                after: diff.after,
+                id: diff.id,
+                title: diff.title,
+                criteria: diff.criteria,
+            })
+        })
+        .collect()
+}
+
+/// Completed stories that took at least one iteration, by iterations (most first), then
+/// every unfinished story the run counts the same way (those never worked on at the bottom)
+pub fn story_iterations(prd: &Prd, session: &SessionState) -> Vec<StoryIterations> {
+    let mut stories: Vec<StoryIterations> = prd
+        .user_stories
+        .iter()
+        .filter_map(|story| {
+            let iterations = session.story_iterations.get(&story.id).copied().unwrap_or(0);
+            let listed = if story.passes { iterations > 0 } else { prd.is_counted(story) };
+            listed.then(|| StoryIterations { id: story.id.clone(), iterations, completed: story.passes })
+        })
+        .collect();
+    stories.sort_by(|a, b| (!a.completed, b.iterations, &a.id).cmp(&(!b.completed, a.iterations, &b.id)));
+    stories
+}
+
+/// Stories with an estimate, in priority order, with the agent time each was active
+pub fn story_estimates(prd: &Prd, session: &SessionState) -> Vec<StoryEstimate> {
+    prd.stories_by_priority()
+        .into_iter()
+        .filter(|story| prd.is_counted(story))
+        .filter_map(|story| {
+            Some(StoryEstimate {
+                id: story.id.clone(),
+                estimate: story.estimate?,
+                actual_secs: session.story_seconds.get(&story.id).copied().unwrap_or(0),
+                completed: story.passes,
+            })
+        })
+        .collect()
+}
+
+/// One line per story: the estimate, the actual agent time and, for estimates in
+/// minutes, how far off the estimate was
+pub fn estimate_lines(stories: &[StoryEstimate], unit: EstimateUnit) -> Vec<String> {
+    let id_width = stories.iter().map(|s| display_width(&s.id)).max().unwrap_or(0);
+    let estimates: Vec<String> = stories.iter().map(|s| unit.format(s.estimate)).collect();
+    let estimate_width = estimates.iter().map(|e| e.len()).max().unwrap_or(0);
+    stories
+        .iter()
+        .zip(estimates)
+        .map(|(story, estimate)| {
+            let actual_mins = story.actual_secs.div_ceil(60);
+            let mut line = format!(
+                "  {}  est {:>w$}  actual {} min",
+                pad_to_width(&story.id, id_width),
+                estimate,
+                actual_mins,
+                w = estimate_width
+            );
+            if unit == EstimateUnit::Minutes && story.completed {
+                let off = actual_mins as f64 - story.estimate;
+                if off != 0.0 {
+                    let word = if off > 0.0 { "over" } else { "under" };
+                    line.push_str(&format!(" ({} {})", unit.format(off.abs()), word));
+                }
+            }
+            if !story.completed {
+                line.push_str(UNFINISHED);
+            }
+            line
+        })
+        .collect()
+}
+
+/// One line per story: the id, a bar scaled to the most iterations and the count
+/// Narrower than CHART_MIN_WIDTH, the bars are left out.
+pub fn chart_lines(stories: &[StoryIterations], width: usize) -> Vec<String> {
+    let id_width = stories.iter().map(|s| display_width(&s.id)).max().unwrap_or(0);
+    let most = stories.iter().map(|s| s.iterations).max().unwrap_or(0);
+    let count_width = most.to_string().len();
+    let suffix_width = if stories.iter().all(|s| s.completed) { 0 } else { UNFINISHED.len() };
+    // "  <id>  <bar> <count><suffix>"
+    let bar_room = width.saturating_sub(2 + id_width + 2 + 1 + count_width + suffix_width);
+    stories
+        .iter()
+        .map(|story| {
+            let id = pad_to_width(&story.id, id_width);
+            let unfinished = if story.completed { "" } else { UNFINISHED };
+            if width < CHART_MIN_WIDTH || bar_room == 0 {
+                let noun = if story.iterations == 1 { "iteration" } else { "iterations" };
+                return format!("  {}  {} {}{}", id, story.iterations, noun, unfinished);
+            }
+            let bar = match story.iterations {
+                0 => 0,
+                n => (n as usize * bar_room).div_ceil(most as usize).max(1),
+            };
+            format!("  {}  {} {}{}", id, BAR.repeat(bar), story.iterations, unfinished)
+        })
+        .collect()
+}
+
+/// Write report.json to the task directory
+pub fn write_report(task_dir: &Path, report: &SessionReport) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(report).map_err(io::Error::other)?;
+    std::fs::write(task_dir.join(REPORT_FILE), content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_are_set_against_agent_time() {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "", "estimateUnit": "minutes", "userStories": [
+                {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": true, "notes": "", "estimate": 30},
+                {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": true, "notes": "", "estimate": 45},
+                {"id": "US-3", "title": "", "description": "", "acceptanceCriteria": [], "priority": 3, "passes": false, "notes": ""},
+                {"id": "US-40", "title": "", "description": "", "acceptanceCriteria": [], "priority": 4, "passes": false, "notes": "", "estimate": 120}
+            ]}"#,
+        )
+        .unwrap();
+        let mut session = SessionState::default();
+        session.story_seconds.insert("US-1".to_string(), 42 * 60);
+        session.story_seconds.insert("US-2".to_string(), 45 * 60);
+        session.story_seconds.insert("US-40".to_string(), 59);
+        let stories = story_estimates(&prd, &session);
+        assert_eq!(stories.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), ["US-2", "US-1", "US-40"]);
+        assert_eq!(
+            estimate_lines(&stories, prd.estimate_unit),
+            [
+                "  US-2   est  45 min  actual 45 min",
+                "  US-1   est  30 min  actual 42 min (12 min over)",
+                "  US-40  est 120 min  actual 1 min (unfinished)",
+            ]
+        );
+    }
+
+    fn story(id: &str, iterations: u32, completed: bool) -> StoryIterations {
+        StoryIterations { id: id.to_string(), iterations, completed }
+    }
+
+    #[test]
+    fn completed_stories_come_first_by_iterations() {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": [
+                {"id": "US-1", "title": "", "description": "", "acceptanceCriteria": [], "priority": 1, "passes": true, "notes": ""},
+                {"id": "US-2", "title": "", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": false, "notes": ""},
+                {"id": "US-3", "title": "", "description": "", "acceptanceCriteria": [], "priority": 3, "passes": true, "notes": ""},
+                {"id": "US-4", "title": "", "description": "", "acceptanceCriteria": [], "priority": 4, "passes": true, "notes": ""},
+                {"id": "US-5", "title": "", "description": "", "acceptanceCriteria": [], "priority": 5, "passes": false, "notes": ""}
+            ]}"#,
+        )
+        .unwrap();
+        let mut session = SessionState::default();
+        session.story_iterations.insert("US-1".to_string(), 2);
+        session.story_iterations.insert("US-2".to_string(), 9);
+        session.story_iterations.insert("US-3".to_string(), 5);
+        // US-4 passed without an iteration of its own: not listed; US-5 never ran: last
+        let stories = story_iterations(&prd, &session);
+        assert_eq!(
+            stories,
+            [story("US-3", 5, true), story("US-1", 2, true), story("US-2", 9, false), story("US-5", 0, false)]
+        );
+        assert_eq!(chart_lines(&stories, 40)[3], "  US-5   0 (unfinished)");
+
+        // Unfinished stories outside the targeted set aren't part of the run
+        let mut prd = prd;
+        prd.targeted_stories = ["US-2".to_string()].into();
+        assert!(!story_iterations(&prd, &session).iter().any(|s| s.id == "US-5"));
+    }
+
+    #[test]
+    fn report_lists_the_stories_the_session_changed() {
+        let prd = |us1: bool, us2: &str| -> Prd {
+            serde_json::from_str(&format!(
+                r#"{{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": [
+                    {{"id": "US-1", "title": "Login", "description": "", "acceptanceCriteria": [{{"description": "a", "passes": {us1}}}], "priority": 1, "passes": {us1}, "notes": ""}}
+                    {us2}
+                ]}}"#
+            ))
+            .unwrap()
+        };
+        let start = prd(false, r#", {"id": "US-2", "title": "Logout", "description": "", "acceptanceCriteria": [], "priority": 2, "passes": false, "notes": ""}"#);
+        let now = prd(true, "");
+        let changed = changed_stories(&start, &now);
+        assert_eq!(changed.iter().map(|s| (s.id.as_str(), s.change)).collect::<Vec<_>>(), [
+            ("US-1", StoryChange::Completed),
+            ("US-2", StoryChange::Removed),
+        ]);
+        assert!(changed_stories(&now, &now).is_empty());
+
+        let json = serde_json::to_value(&changed[0]).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"id": "US-1", "title": "Login", "change": "completed", "before": false, "after": true,
+                "criteria": [{"label": "1", "before": false, "after": true}]})
+        );
+    }
+
+    #[test]
+    fn report_carries_criterion_evidence_per_story() {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": [
+                {"id": "US-2", "title": "", "description": "", "priority": 2, "passes": true, "notes": "", "acceptanceCriteria": [
+                    {"id": "AC-1", "description": "a", "passes": true, "evidence": "cargo test: 12 passed", "verifiedAt": "2026-10-01T09:30:00Z"},
+                    {"description": "b", "passes": true, "evidence": "screenshots/login.png"},
+                    {"description": "c", "passes": false, "evidence": "not yet"}
+                ]},
+                {"id": "US-1", "title": "", "description": "", "priority": 1, "passes": true, "notes": "", "acceptanceCriteria": ["a"]}
+            ]}"#,
+        )
+        .unwrap();
+        let stories = story_evidence(&prd);
+        assert_eq!(stories.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&stories[0]).unwrap(),
+            serde_json::json!({"id": "US-2", "criteria": [
+                {"criterion": "AC-1", "evidence": "cargo test: 12 passed", "verifiedAt": "2026-10-01T09:30:00Z"},
+                {"criterion": "2", "evidence": "screenshots/login.png"}
+            ]})
+        );
+    }
+
+    #[test]
+    fn chart_scales_bars_and_degrades_to_a_table() {
+        let stories = [story("US-10", 8, true), story("US-2", 2, true), story("US-7", 1, false)];
+        assert_eq!(
+            chart_lines(&stories, 53),
+            [
+                "  US-10  █████████████████████████████ 8",
+                "  US-2   ████████ 2",
+                "  US-7   ████ 1 (unfinished)",
+            ]
+        );
+        // The longest bar fills the width, leaving room for the unfinished mark
+        assert_eq!(display_width(&chart_lines(&stories, 53)[0]), 40);
+        assert_eq!(display_width(&chart_lines(&stories[..2], 40)[0]), 40);
+        assert_eq!(
+            chart_lines(&stories, 30),
+            ["  US-10  8 iterations", "  US-2   2 iterations", "  US-7   1 iteration (unfinished)"]
+        );
+        assert!(chart_lines(&[], 80).is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+//! Where the agent is allowed to write
+//!
+//! The targets of Write/Edit activities are resolved the way the agent sees
+//! them: relative to its working directory, with `~` expanded and symlinks
+//! followed as far as the path exists (a file being created doesn't yet). A
+//! target outside the repository root and prd.json's `allowedPaths` is out of
+//! scope; the App flags it in the activity feed and a banner, and can pause
+//! the loop at the next iteration boundary (`pauseOnOutOfScope`).
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::editor::extract_file_path;
+
+/// Activity kinds that change files
+pub const WRITE_ACTIONS: [&str; 2] = ["Write", "Edit"];
+
+/// Directories the agent may write under, resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    roots: Vec<PathBuf>,
+}
+
+impl Scope {
+    /// `root` (the repository, or the agent's directory outside git) plus `allowed`
+    /// (relative to `root`, absolute, or under `~`)
+    pub fn new(root: &Path, allowed: &[String], home: Option<&Path>) -> Self {
+        let mut roots = vec![resolve(root, home)];
+        roots.extend(allowed.iter().map(|dir| resolve(&root.join(expand_home(dir, home)), home)));
+        Scope { roots }
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// The resolved path an activity target names, if it is outside the scope
+    /// `cwd` is the agent's working directory, which relative paths are printed against.
+    pub fn outside(&self, target: &str, cwd: &Path, home: Option<&Path>) -> Option<PathBuf> {
+        let (path, _) = extract_file_path(target)?;
+        let path = resolve(&cwd.join(expand_home(&path, home)), home);
+        (!self.contains(&path)).then_some(path)
+    }
+}
+
+/// `~` and `~/…` under `home` (other paths unchanged)
+fn expand_home(path: &str, home: Option<&Path>) -> PathBuf {
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => home.join(rest.trim_start_matches('/')),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Absolute `path` with symlinks followed through its longest existing ancestor;
+/// the part that doesn't exist yet is normalized lexically
+pub fn resolve(path: &Path, home: Option<&Path>) -> PathBuf {
+    let path = expand_home(&path.to_string_lossy(), home);
+    for ancestor in path.ancestors() {
+        if let Ok(existing) = ancestor.canonicalize() {
+            let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+            return normalize(&existing.join(rest));
+        }
+    }
+    normalize(&path)
+}
+
+/// Drop `.` and apply `..` without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    /// tmp/{repo/src, sibling, home}, with repo/shared a symlink to sibling
+    fn layout(name: &str) -> TempDir {
+        let base = TempDir::new(&format!("scope-{}", name));
+        for dir in ["repo/src", "sibling", "home"] {
+            std::fs::create_dir_all(base.join(dir)).unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(base.join("sibling"), base.join("repo/shared")).unwrap();
+        base
+    }
+
+    #[test]
+    fn paths_in_the_repository_are_in_scope() {
+        let base = layout("in-repo");
+        let (repo, home) = (base.join("repo"), base.join("home"));
+        let scope = Scope::new(&repo, &[], Some(&home));
+        // Relative to the agent's working directory, including new files and a subdirectory cwd
+        assert_eq!(scope.outside("src/main.rs", &repo, Some(&home)), None);
+        assert_eq!(scope.outside("Edit(src/new/mod.rs)", &repo, Some(&home)), None);
+        assert_eq!(scope.outside("../Cargo.toml", &repo.join("src"), Some(&home)), None);
+        assert_eq!(scope.outside(&format!("{}/README.md", repo.display()), &repo, Some(&home)), None);
+    }
+
+    #[test]
+    fn sibling_directories_are_out_of_scope_unless_allowed() {
+        let base = layout("sibling");
+        let (repo, home) = (base.join("repo"), base.join("home"));
+        let scope = Scope::new(&repo, &[], Some(&home));
+        let sibling = base.join("sibling").canonicalize().unwrap();
+        assert_eq!(scope.outside("../sibling/notes.md", &repo, Some(&home)), Some(sibling.join("notes.md")));
+        // A symlink inside the repository that leads out of it
+        #[cfg(unix)]
+        assert_eq!(scope.outside("shared/notes.md", &repo, Some(&home)), Some(sibling.join("notes.md")));
+
+        let allowed = Scope::new(&repo, &["../sibling".to_string()], Some(&home));
+        assert_eq!(allowed.outside("../sibling/notes.md", &repo, Some(&home)), None);
+        assert_eq!(allowed.outside("shared/notes.md", &repo, Some(&home)), None);
+    }
+
+    #[test]
+    fn home_directory_paths_are_out_of_scope() {
+        let base = layout("home");
+        let (repo, home) = (base.join("repo"), base.join("home"));
+        let scope = Scope::new(&repo, &[], Some(&home));
+        let resolved_home = home.canonicalize().unwrap();
+        assert_eq!(scope.outside("~/.bashrc", &repo, Some(&home)), Some(resolved_home.join(".bashrc")));
+        assert_eq!(
+            scope.outside(&format!("{}/.ssh/config", home.display()), &repo, Some(&home)),
+            Some(resolved_home.join(".ssh/config"))
+        );
+        // Activities without a file path are not checked
+        assert_eq!(scope.outside("updating the config", &repo, Some(&home)), None);
+    }
+}
@@ -0,0 +1,97 @@
+//! Shell-style splitting of free-text agent arguments (`--agent-args`, prd.json `agentArgs`)
+
+/// Split `input` into words the way a POSIX shell would (without expansions)
+/// Single quotes are literal, double quotes allow `\"` and `\\`, and a backslash
+/// outside quotes escapes the next character. Errors on an unterminated quote.
+pub fn split_shell_words(input: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    // Whether a word has started (so '' and "" produce an empty argument)
+    let mut in_word = false;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => current.push(c),
+                            Some(c) => {
+                                current.push('\\');
+                                current.push(c);
+                            }
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(c) = chars.next() {
+                    current.push(c);
+                }
+            }
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_plain_words() {
+        assert_eq!(
+            split_shell_words("--model sonnet   --permission-mode plan").unwrap(),
+            vec!["--model", "sonnet", "--permission-mode", "plan"]
+        );
+        assert!(split_shell_words("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_quoted_segments_with_spaces_together() {
+        assert_eq!(
+            split_shell_words(r#"--append-system-prompt "Be terse, no emojis" --name 'my task'"#).unwrap(),
+            vec!["--append-system-prompt", "Be terse, no emojis", "--name", "my task"]
+        );
+        assert_eq!(
+            split_shell_words(r#"--msg="say \"hi\"" it\'s ''"#).unwrap(),
+            vec![r#"--msg=say "hi""#, "it's", ""]
+        );
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        assert!(split_shell_words("--name 'oops").is_err());
+        assert!(split_shell_words(r#"--name "oops"#).is_err());
+    }
+}
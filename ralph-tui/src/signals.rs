@@ -0,0 +1,111 @@
+//! Termination signals (SIGTERM, SIGHUP, and SIGINT while not in raw mode)
+//!
+//! A handler thread records the signal and immediately terminates the agent's
+//! process group, so Claude never outlives ralph-tui as an orphan that keeps
+//! editing files. While the TUI loop is running it notices `received()` on its
+//! next tick, logs the interrupted iteration and restores the terminal itself;
+//! otherwise (startup prompts, or a loop stuck in $EDITOR past the grace period)
+//! the handler restores the terminal and exits on its own.
+
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+#[cfg(unix)]
+use std::time::{Duration, Instant};
+
+use crate::lanes::MAX_LANES;
+
+/// Signal number received (0 = none)
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+/// Process group of the running agent in each lane (0 = none; only lane 0 outside --parallel)
+static CHILD_PGIDS: [AtomicI32; MAX_LANES] = [const { AtomicI32::new(0) }; MAX_LANES];
+/// Whether the TUI loop is running and will handle shutdown itself
+static LOOP_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// How long the TUI loop gets to shut down cleanly before the handler exits
+#[cfg(unix)]
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(3);
+/// How long the agent gets to exit after SIGTERM before it is sent SIGKILL
+#[cfg(unix)]
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// Install the signal handler thread; `restore` puts the host terminal back
+#[cfg(unix)]
+pub fn install(restore: fn()) -> std::io::Result<()> {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut signals = Signals::new([SIGTERM, SIGHUP, SIGINT])?;
+    std::thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            RECEIVED.store(signal, Ordering::SeqCst);
+            terminate_child();
+            if LOOP_ACTIVE.load(Ordering::SeqCst) {
+                std::thread::sleep(SHUTDOWN_GRACE);
+            }
+            restore();
+            std::process::exit(128 + signal);
+        }
+    });
+    Ok(())
+}
+
+/// Signals are not handled on this platform
+#[cfg(not(unix))]
+pub fn install(_restore: fn()) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// The termination signal received, if any
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+/// Record the running agent (portable-pty starts it as a session leader, so its
+/// pid is also its process group id)
+pub fn set_child(pid: Option<u32>) {
+    set_lane_child(0, pid);
+}
+
+/// Record the running agent of one --parallel lane
+pub fn set_lane_child(lane: usize, pid: Option<u32>) {
+    let pgid = pid.and_then(|pid| i32::try_from(pid).ok()).unwrap_or(0);
+    if let Some(slot) = CHILD_PGIDS.get(lane) {
+        slot.store(pgid, Ordering::SeqCst);
+    }
+}
+
+/// Mark whether the TUI loop is running (and restores the terminal on shutdown)
+pub fn set_loop_active(active: bool) {
+    LOOP_ACTIVE.store(active, Ordering::SeqCst);
+}
+
+/// SIGTERM every agent's process group, escalating to SIGKILL if any lingers
+#[cfg(unix)]
+fn terminate_child() {
+    let pgids: Vec<i32> = CHILD_PGIDS
+        .iter()
+        .map(|slot| slot.load(Ordering::SeqCst))
+        .filter(|pgid| *pgid > 0)
+        .collect();
+    if pgids.is_empty() {
+        return;
+    }
+    for pgid in &pgids {
+        // SAFETY: kill() has no memory-safety preconditions
+        unsafe { libc::kill(-pgid, libc::SIGTERM) };
+    }
+    // Signal 0 only checks whether any process in the group is left
+    let alive = |pgid: &&i32| unsafe { libc::kill(-**pgid, 0) } == 0;
+    let deadline = Instant::now() + KILL_GRACE;
+    while Instant::now() < deadline {
+        if !pgids.iter().any(|pgid| alive(&pgid)) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    for pgid in pgids.iter().filter(alive) {
+        unsafe { libc::kill(-pgid, libc::SIGKILL) };
+    }
+}
@@ -0,0 +1,258 @@
+//! `ralph-tui status`: one line (or JSON) describing a task, for scripts and status bars
+//!
+//! The counts come from prd.json, limited to the stories the last session
+//! targeted; the iteration from the running session's recovery snapshot in
+//! `.ralph-session.json`. A session holds `.ralph-lock` (its pid) while it runs,
+//! so a task is running only while that process is alive.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::app::SessionState;
+use crate::prd::Prd;
+
+/// Lock file inside the task directory, holding the pid of the session running the task
+pub const LOCK_FILE: &str = ".ralph-lock";
+
+/// Line printed when `--format` isn't given
+pub const DEFAULT_STATUS_FORMAT: &str = "{done}/{total} {pct}% iter={iter} active={story_id}";
+
+/// Placeholders `--format` understands
+pub const PLACEHOLDERS: [&str; 8] = ["task", "state", "done", "total", "pct", "iter", "story_id", "story_title"];
+
+/// Exit codes of `ralph-tui status`
+pub const EXIT_RUNNING: i32 = 0;
+pub const EXIT_COMPLETE: i32 = 3;
+pub const EXIT_IDLE: i32 = 4;
+
+/// Held by a running session; removes the lock file when dropped
+pub struct SessionLock {
+    path: PathBuf,
+}
+
+impl SessionLock {
+    /// Write this process's pid to the task's lock file (a failed write only loses `status`'s running state)
+    pub fn acquire(task_dir: &Path) -> Self {
+        let path = task_dir.join(LOCK_FILE);
+        let _ = std::fs::write(&path, format!("{}\n", std::process::id()));
+        Self { path }
+    }
+}
+
+impl Drop for SessionLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Pid in the task's lock file, if that process is still alive (a crashed session leaves a stale lock)
+pub fn lock_owner(task_dir: &Path) -> Option<u32> {
+    let pid: u32 = std::fs::read_to_string(task_dir.join(LOCK_FILE)).ok()?.trim().parse().ok()?;
+    process_alive(pid).then_some(pid)
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks; EPERM means it exists but belongs to someone else
+    let alive = unsafe { libc::kill(pid, 0) } == 0;
+    alive || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
+
+/// Where a task stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    Running,
+    Complete,
+    Idle,
+}
+
+impl TaskState {
+    pub fn label(self) -> &'static str {
+        match self {
+            TaskState::Running => "running",
+            TaskState::Complete => "complete",
+            TaskState::Idle => "idle",
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            TaskState::Running => EXIT_RUNNING,
+            TaskState::Complete => EXIT_COMPLETE,
+            TaskState::Idle => EXIT_IDLE,
+        }
+    }
+}
+
+/// A task's status (also the `--json` output)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskStatus {
+    pub task: String,
+    pub state: TaskState,
+    pub done: usize,
+    pub total: usize,
+    pub pct: usize,
+    /// Iteration of the running session (or the one a crashed session reached)
+    pub iter: Option<u32>,
+    pub story_id: Option<String>,
+    pub story_title: Option<String>,
+    pub pid: Option<u32>,
+}
+
+impl TaskStatus {
+    /// Status of a task at `iter` (if a session is running or has left a snapshot)
+    pub fn new(task: String, prd: &Prd, iter: Option<u32>, pid: Option<u32>) -> Self {
+        let (done, total) = (prd.completed_count(), prd.story_total());
+        let story = prd.current_story();
+        let state = if pid.is_some() {
+            TaskState::Running
+        } else if prd.all_stories_pass() {
+            TaskState::Complete
+        } else {
+            TaskState::Idle
+        };
+        Self {
+            task,
+            state,
+            done,
+            total,
+            pct: (done * 100).checked_div(total).unwrap_or(0),
+            iter,
+            story_id: story.map(|s| s.id.clone()),
+            story_title: story.map(|s| s.title.clone()),
+            pid,
+        }
+    }
+
+    /// Read a task directory's prd.json, session state and lock
+    pub fn load(task_dir: &Path, prd_path: &Path) -> std::io::Result<Self> {
+        let mut prd = Prd::load(&prd_path.to_path_buf())?;
+        let session = SessionState::load(task_dir);
+        prd.targeted_stories = session.targeted_stories.clone();
+        let task = task_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let iter = session.recovery.as_ref().map(|recovery| recovery.current_iteration);
+        Ok(Self::new(task, &prd, iter, lock_owner(task_dir)))
+    }
+
+    /// Value of a placeholder ("-" when the task has none)
+    fn value(&self, placeholder: &str) -> Option<String> {
+        let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+        Some(match placeholder {
+            "task" => self.task.clone(),
+            "state" => self.state.label().to_string(),
+            "done" => self.done.to_string(),
+            "total" => self.total.to_string(),
+            "pct" => self.pct.to_string(),
+            "iter" => or_dash(self.iter.map(|iter| iter.to_string())),
+            "story_id" => or_dash(self.story_id.clone()),
+            "story_title" => or_dash(self.story_title.clone()),
+            _ => return None,
+        })
+    }
+
+    /// Fill `format`'s `{placeholder}`s; `{{` and `}}` are literal braces
+    /// An unknown or unclosed placeholder is an error naming it.
+    pub fn format(&self, format: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    out.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    out.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("Unclosed placeholder {{{}", name)),
+                        }
+                    }
+                    let value = self.value(&name).ok_or_else(|| {
+                        format!("Unknown placeholder {{{}}} (expected one of: {})", name, PLACEHOLDERS.join(", "))
+                    })?;
+                    out.push_str(&value);
+                }
+                c => out.push(c),
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn prd(passes: [bool; 3]) -> Prd {
+        let stories: Vec<String> = passes
+            .iter()
+            .enumerate()
+            .map(|(i, passes)| {
+                format!(
+                    r#"{{"id": "US-00{0}", "title": "Story {0}", "description": "", "acceptanceCriteria": [], "priority": {0}, "passes": {1}, "notes": ""}}"#,
+                    i + 1,
+                    passes
+                )
+            })
+            .collect();
+        serde_json::from_str(&format!(
+            r#"{{"project": "demo", "taskDir": "tasks/demo", "type": "feature", "description": "", "userStories": [{}]}}"#,
+            stories.join(",")
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn formats_placeholders_and_rejects_unknown_ones() {
+        let status = TaskStatus::new("login".to_string(), &prd([true, false, false]), Some(4), Some(42));
+        assert_eq!(status.state, TaskState::Running);
+        assert_eq!(status.format(DEFAULT_STATUS_FORMAT).unwrap(), "1/3 33% iter=4 active=US-002");
+        assert_eq!(status.format("{task} [{state}] {{{story_title}}}").unwrap(), "login [running] {Story 2}");
+
+        assert_eq!(
+            status.format("{done} {stories}").unwrap_err(),
+            "Unknown placeholder {stories} (expected one of: task, state, done, total, pct, iter, story_id, story_title)"
+        );
+        assert_eq!(status.format("{done").unwrap_err(), "Unclosed placeholder {done");
+    }
+
+    #[test]
+    fn state_and_exit_code_follow_lock_and_stories() {
+        let idle = TaskStatus::new("t".to_string(), &prd([true, false, false]), None, None);
+        assert_eq!((idle.state, idle.state.exit_code()), (TaskState::Idle, EXIT_IDLE));
+        assert_eq!(idle.format("iter={iter}").unwrap(), "iter=-");
+
+        let complete = TaskStatus::new("t".to_string(), &prd([true, true, true]), None, None);
+        assert_eq!((complete.state, complete.state.exit_code()), (TaskState::Complete, EXIT_COMPLETE));
+        assert_eq!(complete.format("{pct}% active={story_id}").unwrap(), "100% active=-");
+
+        // A lock only counts while its process is alive
+        let task_dir = TempDir::new("status-lock");
+        {
+            let _lock = SessionLock::acquire(&task_dir);
+            assert_eq!(lock_owner(&task_dir), Some(std::process::id()));
+        }
+        assert_eq!(lock_owner(&task_dir), None);
+        std::fs::write(task_dir.join(LOCK_FILE), format!("{}\n", u32::MAX)).unwrap();
+        assert_eq!(lock_owner(&task_dir), None);
+    }
+}
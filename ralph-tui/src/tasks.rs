@@ -0,0 +1,570 @@
+//! Active tasks, for the picker shown when ralph-tui starts without a task
+//!
+//! An active task is a directory with a prd.json under one of the task roots
+//! (`archived` is skipped). The roots are `--task-root`, else $RALPH_TASK_ROOTS, else
+//! `task_root` in ralph.toml, else `./tasks`; with more than one, the picker shows
+//! each task's path inside its root, followed by the root. Besides the PRD's description, story counts and type,
+//! the picker shows its branchName, when progress.txt last changed and whether a
+//! live session holds the task's lock. `o` cycles the sort order; a running task
+//! is only opened when its number is entered with a trailing `!`.
+//!
+//! Archiving (`ralph-tui archive`, or `a<N>` in the picker) moves a task to
+//! `archived/<name>-<YYYYMMDD-HHMM>` under its root; unarchiving (`ralph-tui
+//! unarchive`, or `u` in the picker) moves it back without the suffix. A running
+//! task is never moved, and the `nextTask` of any task in the same root (or its
+//! `archived/`) that named the moved task is rewritten to follow it.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::chain::{resolve_next_task, task_identity};
+use crate::digest::format_datetime_utc;
+use crate::journal::{read_prd_value, set_prd_value};
+use crate::render::widgets::{pad_to_width, truncate_to_width};
+use crate::status::lock_owner;
+
+/// Directory under a task root holding archived tasks
+pub const ARCHIVE_DIR: &str = "archived";
+
+/// Task root scanned when none is configured
+pub const DEFAULT_TASK_ROOT: &str = "tasks";
+
+/// Red "RUNNING" tag for a terminal
+const ANSI_RED: &str = "\x1b[31m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// Find active tasks (directories with prd.json, excluding archived) under the given roots
+pub fn find_active_tasks(roots: &[PathBuf]) -> Vec<PathBuf> {
+    let mut tasks = Vec::new();
+
+    for tasks_dir in roots {
+        // Look for prd.json files in each root's subdirectories
+        let Ok(entries) = std::fs::read_dir(tasks_dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            // Skip archived directory
+            if path.file_name().is_some_and(|n| n == ARCHIVE_DIR) {
+                continue;
+            }
+            if path.is_dir() {
+                let prd_path = path.join("prd.json");
+                if prd_path.exists() {
+                    tasks.push(path);
+                }
+            }
+        }
+    }
+
+    tasks.sort();
+    tasks.dedup();
+    tasks
+}
+
+/// Active tasks under the given roots, with what the picker shows about each
+pub fn discover_tasks(roots: &[PathBuf]) -> Vec<TaskInfo> {
+    find_active_tasks(roots).iter().map(|dir| TaskInfo::load(dir).with_root(roots)).collect()
+}
+
+/// Task roots in a `--task-root` or `task_root` value (comma-separated)
+pub fn parse_task_roots(value: &str) -> Vec<PathBuf> {
+    value.split(',').map(str::trim).filter(|root| !root.is_empty()).map(PathBuf::from).collect()
+}
+
+/// A task argument as given on the command line: a relative path is looked up under
+/// each root in order, and taken from the current directory when no root has it
+pub fn resolve_in_roots(arg: &Path, roots: &[PathBuf]) -> PathBuf {
+    if arg.is_relative() {
+        let found = roots.iter().map(|root| root.join(arg)).find(|path| path.exists());
+        if let Some(path) = found {
+            return path;
+        }
+    }
+    arg.to_path_buf()
+}
+
+/// One task in the picker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub dir: PathBuf,
+    pub description: String,
+    pub completed: usize,
+    pub total: usize,
+    pub prd_type: String,
+    /// prd.json's branchName
+    pub branch: Option<String>,
+    /// When progress.txt was last written (None before the first run)
+    pub last_run: Option<SystemTime>,
+    /// Pid of the live session running the task
+    pub running: Option<u32>,
+    /// Root the task was found under, when several are scanned
+    pub root: Option<PathBuf>,
+}
+
+impl TaskInfo {
+    pub fn load(dir: &Path) -> Self {
+        let content = std::fs::read_to_string(dir.join("prd.json")).unwrap_or_default();
+        let mut info = TaskInfo {
+            dir: dir.to_path_buf(),
+            description: "Unable to parse prd.json".to_string(),
+            completed: 0,
+            total: 0,
+            prd_type: "unknown".to_string(),
+            branch: None,
+            last_run: std::fs::metadata(dir.join("progress.txt")).and_then(|m| m.modified()).ok(),
+            running: lock_owner(dir),
+            root: None,
+        };
+        // Read loosely, so a PRD that doesn't fully parse still shows what it has
+        if let Ok(prd) = serde_json::from_str::<serde_json::Value>(&content) {
+            let description = prd.get("description").and_then(|v| v.as_str()).unwrap_or("No description");
+            info.description = truncate_to_width(description, 50);
+            let stories = prd.get("userStories").and_then(|v| v.as_array());
+            info.total = stories.map(|arr| arr.len()).unwrap_or(0);
+            info.completed = stories
+                .map(|arr| arr.iter().filter(|s| s.get("passes").and_then(|v| v.as_bool()).unwrap_or(false)).count())
+                .unwrap_or(0);
+            info.prd_type = prd.get("type").and_then(|v| v.as_str()).unwrap_or("feature").to_string();
+            info.branch = prd.get("branchName").and_then(|v| v.as_str()).filter(|b| !b.is_empty()).map(str::to_string);
+        }
+        info
+    }
+
+    /// Note which of `roots` the task is under (only when there is more than one)
+    pub fn with_root(mut self, roots: &[PathBuf]) -> Self {
+        if roots.len() > 1 {
+            self.root = roots.iter().find(|root| self.dir.starts_with(root)).cloned();
+        }
+        self
+    }
+
+    /// Share of stories completed, 0-100 (0 without stories)
+    pub fn percent(&self) -> usize {
+        (self.completed * 100).checked_div(self.total).unwrap_or(0)
+    }
+
+    /// The picker's line for the task, numbered from 1 (a second line holds the description)
+    pub fn line(&self, number: usize, now: SystemTime, color: bool) -> String {
+        // Padded by display columns so CJK and emoji names keep the counts aligned
+        let name = match self.root.as_ref().and_then(|root| self.dir.strip_prefix(root).ok()) {
+            Some(name) => name.display().to_string(),
+            None => self.dir.display().to_string(),
+        };
+        let mut line = format!("  {}) {} [{}/{}] ({})", number, pad_to_width(&name, 35), self.completed, self.total, self.prd_type);
+        if let Some(ref root) = self.root {
+            line.push_str(&format!("  in {}", root.display()));
+        }
+        if let Some(last_run) = self.last_run {
+            line.push_str(&format!("  {}", format_age(now.duration_since(last_run).unwrap_or_default())));
+        }
+        if let Some(ref branch) = self.branch {
+            line.push_str(&format!("  {}", branch));
+        }
+        if self.running.is_some() && color {
+            line.push_str(&format!("  {}RUNNING{}", ANSI_RED, ANSI_RESET));
+        } else if self.running.is_some() {
+            line.push_str("  RUNNING");
+        }
+        line
+    }
+}
+
+/// "just now", "5m ago", "2h ago", "3d ago"
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3_600 => format!("{}m ago", secs / 60),
+        3_600..86_400 => format!("{}h ago", secs / 3_600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+/// Order of the picker's list (o cycles through them)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TaskSort {
+    #[default]
+    Name,
+    Recency,    // Most recently run first, never-run tasks last
+    Completion, // Most complete first
+}
+
+impl TaskSort {
+    pub fn next(self) -> Self {
+        match self {
+            TaskSort::Name => TaskSort::Recency,
+            TaskSort::Recency => TaskSort::Completion,
+            TaskSort::Completion => TaskSort::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TaskSort::Name => "name",
+            TaskSort::Recency => "last run",
+            TaskSort::Completion => "completion",
+        }
+    }
+}
+
+/// Sort the picker's list, ties broken by name
+pub fn sort_tasks(tasks: &mut [TaskInfo], sort: TaskSort) {
+    match sort {
+        TaskSort::Name => tasks.sort_by(|a, b| a.dir.cmp(&b.dir)),
+        TaskSort::Recency => tasks.sort_by(|a, b| b.last_run.cmp(&a.last_run).then_with(|| a.dir.cmp(&b.dir))),
+        TaskSort::Completion => tasks.sort_by(|a, b| b.percent().cmp(&a.percent()).then_with(|| a.dir.cmp(&b.dir))),
+    }
+}
+
+/// What was entered at the picker's prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskChoice {
+    /// Index into the list (not opened if the task is running)
+    Pick(usize),
+    /// Index entered with a trailing `!`: opened even while running
+    Force(usize),
+    /// Index entered after `a`: archive the task
+    Archive(usize),
+    /// `u`: pick an archived task to bring back
+    Unarchive,
+    CycleSort,
+}
+
+/// Parse the picker's input for a list of `count` tasks
+pub fn parse_task_choice(input: &str, count: usize) -> Result<TaskChoice, &'static str> {
+    let input = input.trim();
+    match input {
+        "o" => return Ok(TaskChoice::CycleSort),
+        "u" => return Ok(TaskChoice::Unarchive),
+        _ => {}
+    }
+    let (number, choice): (&str, fn(usize) -> TaskChoice) = if let Some(number) = input.strip_prefix('a') {
+        (number.trim_start(), TaskChoice::Archive)
+    } else if let Some(number) = input.strip_suffix('!') {
+        (number.trim_end(), TaskChoice::Force)
+    } else {
+        (input, TaskChoice::Pick)
+    };
+    let number: usize = number.parse().map_err(|_| "Invalid selection")?;
+    if number < 1 || number > count {
+        return Err("Selection out of range");
+    }
+    Ok(choice(number - 1))
+}
+
+/// A task's `nextTask` naming a task that moves, and the value that follows it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainRef {
+    /// Task whose prd.json holds the nextTask
+    pub task: PathBuf,
+    pub old: String,
+    pub new: String,
+}
+
+/// Moving a task into or out of `archived/`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskMove {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub chain_refs: Vec<ChainRef>,
+}
+
+impl TaskMove {
+    /// Move the task, then point the chain references at its new place
+    pub fn apply(&self) -> io::Result<()> {
+        if let Some(parent) = self.to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(&self.from, &self.to)?;
+        for chain_ref in &self.chain_refs {
+            let prd_path = chain_ref.task.join("prd.json");
+            set_prd_value(&chain_ref.task, &prd_path, "/nextTask", serde_json::Value::String(chain_ref.new.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// What the move does (or, for a dry run, would do), one line each
+    pub fn describe(&self, dry_run: bool) -> Vec<String> {
+        let verb = if dry_run { "Would move" } else { "Moved" };
+        let mut lines = vec![format!("{} {} → {}", verb, self.from.display(), self.to.display())];
+        for chain_ref in &self.chain_refs {
+            lines.push(format!(
+                "warning: {}'s nextTask {} {} → {}",
+                chain_ref.task.display(),
+                if dry_run { "would change" } else { "changed" },
+                chain_ref.old,
+                chain_ref.new
+            ));
+        }
+        lines
+    }
+}
+
+/// Plan archiving the task in `task_dir` at `now`
+/// Refused for a directory without prd.json, an archived task and a running one.
+pub fn plan_archive(task_dir: &Path, now: SystemTime) -> Result<TaskMove, String> {
+    check_movable(task_dir)?;
+    let root = parent_dir(task_dir);
+    if root.file_name().is_some_and(|n| n == ARCHIVE_DIR) {
+        return Err(format!("{} is already archived", task_dir.display()));
+    }
+    let name = task_name(task_dir)?;
+    let now = now.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let to = root.join(ARCHIVE_DIR).join(format!("{}-{}", name, archive_stamp(now)));
+    plan_move(task_dir, to, &root)
+}
+
+/// Plan moving the archived task in `task_dir` back under its root, without the
+/// archive's timestamp suffix
+pub fn plan_unarchive(task_dir: &Path) -> Result<TaskMove, String> {
+    check_movable(task_dir)?;
+    let archive = parent_dir(task_dir);
+    if archive.file_name().is_none_or(|n| n != ARCHIVE_DIR) {
+        return Err(format!("{} is not in an {}/ directory", task_dir.display(), ARCHIVE_DIR));
+    }
+    let name = task_name(task_dir)?;
+    let root = parent_dir(&archive);
+    plan_move(task_dir, root.join(strip_archive_stamp(&name)), &root)
+}
+
+fn check_movable(task_dir: &Path) -> Result<(), String> {
+    if !task_dir.join("prd.json").is_file() {
+        return Err(format!("{} has no prd.json", task_dir.display()));
+    }
+    match lock_owner(task_dir) {
+        Some(pid) => Err(format!("{} is running (pid {}); stop that session first", task_dir.display(), pid)),
+        None => Ok(()),
+    }
+}
+
+fn plan_move(from: &Path, to: PathBuf, root: &Path) -> Result<TaskMove, String> {
+    if to.exists() {
+        return Err(format!("{} already exists", to.display()));
+    }
+    let chain_refs = chain_refs_to(from, &to, root);
+    Ok(TaskMove { from: from.to_path_buf(), to, chain_refs })
+}
+
+/// nextTask values in the root's tasks (active and archived) that name `from`, rewritten for `to`
+fn chain_refs_to(from: &Path, to: &Path, root: &Path) -> Vec<ChainRef> {
+    let target = task_identity(from);
+    let mut refs = Vec::new();
+    for task in find_active_tasks(&[root.to_path_buf(), root.join(ARCHIVE_DIR)]) {
+        if task_identity(&task) == target {
+            continue;
+        }
+        let Ok(prd) = read_prd_value(&task.join("prd.json")) else {
+            continue;
+        };
+        let Some(old) = prd.get("nextTask").and_then(|v| v.as_str()).map(str::trim).filter(|n| !n.is_empty()) else {
+            continue;
+        };
+        if task_identity(&resolve_next_task(&task, old)) != target {
+            continue;
+        }
+        // nextTask is relative to the directory holding the task, like resolve_next_task reads it
+        let relative = to.strip_prefix(parent_dir(&task)).ok().filter(|_| !Path::new(old).is_absolute());
+        let new = match relative {
+            Some(relative) => relative.display().to_string(),
+            None => std::path::absolute(to).unwrap_or_else(|_| to.to_path_buf()).display().to_string(),
+        };
+        refs.push(ChainRef { task, old: old.to_string(), new });
+    }
+    refs
+}
+
+/// Directory holding `dir` (`.` for a bare name)
+fn parent_dir(dir: &Path) -> PathBuf {
+    match dir.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+fn task_name(task_dir: &Path) -> Result<String, String> {
+    task_dir
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .ok_or_else(|| format!("{} doesn't name a task directory", task_dir.display()))
+}
+
+/// Suffix of an archived task's directory: YYYYMMDD-HHMM (UTC)
+fn archive_stamp(unix_secs: u64) -> String {
+    format_datetime_utc(unix_secs).replace(['-', ':'], "").replace(' ', "-")
+}
+
+/// An archived task's name without its archive_stamp suffix
+fn strip_archive_stamp(name: &str) -> &str {
+    let is_stamp = |stamp: &str| {
+        let bytes = stamp.as_bytes();
+        bytes.len() == 14 && bytes[0] == b'-' && bytes[9] == b'-'
+            && bytes.iter().enumerate().all(|(i, b)| i == 0 || i == 9 || b.is_ascii_digit())
+    };
+    match name.len().checked_sub(14) {
+        Some(at) if name.is_char_boundary(at) && is_stamp(&name[at..]) && at > 0 => &name[..at],
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn task(name: &str, completed: usize, total: usize, ran_secs_ago: Option<u64>) -> TaskInfo {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        TaskInfo {
+            dir: PathBuf::from(name),
+            description: String::new(),
+            completed,
+            total,
+            prd_type: "feature".to_string(),
+            branch: None,
+            last_run: ran_secs_ago.map(|secs| now - Duration::from_secs(secs)),
+            running: None,
+            root: None,
+        }
+    }
+
+    #[test]
+    fn loads_branch_last_run_and_lock() {
+        let dir = TempDir::new("tasks-load");
+        std::fs::create_dir_all(dir.join("login")).unwrap();
+        std::fs::create_dir_all(dir.join("archived/old")).unwrap();
+        std::fs::write(
+            dir.join("login/prd.json"),
+            r#"{"description": "Login", "type": "bugfix", "branchName": "ralph/login",
+                "userStories": [{"passes": true}, {"passes": false}]}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("archived/old/prd.json"), "{}").unwrap();
+
+        let tasks = discover_tasks(&[dir.to_path_buf()]);
+        assert_eq!(tasks.len(), 1);
+        let info = &tasks[0];
+        assert_eq!((info.completed, info.total, info.percent()), (1, 2, 50));
+        assert_eq!((info.prd_type.as_str(), info.branch.as_deref()), ("bugfix", Some("ralph/login")));
+        assert_eq!((info.last_run, info.running), (None, None));
+
+        std::fs::write(dir.join("login/progress.txt"), "").unwrap();
+        std::fs::write(dir.join("login").join(crate::status::LOCK_FILE), format!("{}\n", std::process::id())).unwrap();
+        let info = TaskInfo::load(&dir.join("login"));
+        assert!(info.last_run.is_some());
+        assert_eq!(info.running, Some(std::process::id()));
+    }
+
+    #[test]
+    fn lines_show_age_branch_and_running_tag() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut info = task("tasks/login", 1, 4, Some(2 * 3_600 + 59));
+        info.branch = Some("ralph/login".to_string());
+        assert_eq!(info.line(2, now, false), format!("  2) {:35} [1/4] (feature)  2h ago  ralph/login", "tasks/login"));
+        info.running = Some(42);
+        assert!(info.line(2, now, false).ends_with("ralph/login  RUNNING"));
+        assert!(info.line(2, now, true).ends_with("\x1b[31mRUNNING\x1b[0m"));
+        assert!(!task("tasks/new", 0, 0, None).line(1, now, false).contains("ago"));
+
+        // With several roots, the path inside the root is followed by the root
+        let roots = [PathBuf::from("tasks"), PathBuf::from("automation/ralph-tasks")];
+        let info = task("automation/ralph-tasks/sync", 0, 2, None).with_root(&roots);
+        assert_eq!(info.line(3, now, false), format!("  3) {:35} [0/2] (feature)  in automation/ralph-tasks", "sync"));
+        assert_eq!(task("tasks/new", 0, 0, None).with_root(&roots[..1]).root, None);
+
+        assert_eq!(format_age(Duration::from_secs(59)), "just now");
+        assert_eq!(format_age(Duration::from_secs(5 * 60)), "5m ago");
+        assert_eq!(format_age(Duration::from_secs(3 * 86_400 + 7)), "3d ago");
+    }
+
+    #[test]
+    fn task_arguments_are_looked_up_in_the_roots_first() {
+        let dir = TempDir::new("tasks-roots");
+        std::fs::create_dir_all(dir.join("a/login")).unwrap();
+        std::fs::create_dir_all(dir.join("b/login")).unwrap();
+        std::fs::create_dir_all(dir.join("b/search")).unwrap();
+        let roots = parse_task_roots(&format!(" {0}/a, ,{0}/b ", dir.display()));
+        assert_eq!(roots, [dir.join("a"), dir.join("b")]);
+
+        assert_eq!(resolve_in_roots(Path::new("login"), &roots), dir.join("a/login"));
+        assert_eq!(resolve_in_roots(Path::new("search"), &roots), dir.join("b/search"));
+        assert_eq!(resolve_in_roots(Path::new("tasks/other"), &roots), PathBuf::from("tasks/other"));
+        assert_eq!(resolve_in_roots(&dir.join("b/login"), &roots[..1]), dir.join("b/login"));
+    }
+
+    #[test]
+    fn sorts_by_name_recency_and_completion() {
+        let mut tasks =
+            vec![task("c", 1, 1, None), task("a", 0, 4, Some(600)), task("b", 1, 2, Some(60)), task("d", 0, 0, Some(60))];
+        let order = |tasks: &[TaskInfo]| tasks.iter().map(|t| t.dir.display().to_string()).collect::<Vec<_>>().join("");
+        sort_tasks(&mut tasks, TaskSort::Recency);
+        assert_eq!(order(&tasks), "bdac");
+        sort_tasks(&mut tasks, TaskSort::Completion);
+        assert_eq!(order(&tasks), "cbad");
+        sort_tasks(&mut tasks, TaskSort::Name);
+        assert_eq!(order(&tasks), "abcd");
+        assert_eq!(TaskSort::Completion.next(), TaskSort::Name);
+    }
+
+    #[test]
+    fn parses_numbers_forced_picks_and_sort() {
+        assert_eq!(parse_task_choice(" 2\n", 3), Ok(TaskChoice::Pick(1)));
+        assert_eq!(parse_task_choice("3!", 3), Ok(TaskChoice::Force(2)));
+        assert_eq!(parse_task_choice("o", 3), Ok(TaskChoice::CycleSort));
+        assert_eq!(parse_task_choice("a 2", 3), Ok(TaskChoice::Archive(1)));
+        assert_eq!(parse_task_choice("u", 3), Ok(TaskChoice::Unarchive));
+        assert_eq!(parse_task_choice("a4", 3), Err("Selection out of range"));
+        assert_eq!(parse_task_choice("4", 3), Err("Selection out of range"));
+        assert_eq!(parse_task_choice("x", 3), Err("Invalid selection"));
+    }
+
+    #[test]
+    fn archives_and_unarchives_following_chain_references() {
+        let root = TempDir::new("tasks-archive");
+        let write_task = |dir: &Path, next: &str| {
+            std::fs::create_dir_all(dir).unwrap();
+            let prd = r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": []"#;
+            std::fs::write(dir.join("prd.json"), format!(r#"{}, "nextTask": "{}"}}"#, prd, next)).unwrap();
+        };
+        let next_task = |dir: &Path| read_prd_value(&dir.join("prd.json")).unwrap()["nextTask"].as_str().unwrap().to_string();
+        write_task(&root.join("backend"), "frontend");
+        write_task(&root.join("frontend"), "");
+        write_task(&root.join(ARCHIVE_DIR).join("api-20260101-0900"), "../frontend");
+
+        // A dry run only plans the move
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_792_161_000); // 2026-10-16 14:30 UTC
+        let plan = plan_archive(&root.join("frontend"), now).unwrap();
+        let archived = root.join(ARCHIVE_DIR).join("frontend-20261016-1430");
+        assert_eq!(plan.to, archived);
+        let backend = root.join("backend").display().to_string();
+        let warning = format!("warning: {}'s nextTask would change frontend → archived/frontend-20261016-1430", backend);
+        assert_eq!(plan.describe(true)[2], warning);
+        assert_eq!(plan.chain_refs[0].new, "frontend-20261016-1430");
+        assert!(root.join("frontend").exists());
+
+        plan.apply().unwrap();
+        assert!(archived.join("prd.json").is_file() && !root.join("frontend").exists());
+        assert_eq!(next_task(&root.join("backend")), "archived/frontend-20261016-1430");
+        assert_eq!(next_task(&root.join(ARCHIVE_DIR).join("api-20260101-0900")), "frontend-20261016-1430");
+        let err = plan_archive(&archived, SystemTime::now()).unwrap_err();
+        assert_eq!(err, format!("{} is already archived", archived.display()));
+
+        let plan = plan_unarchive(&archived).unwrap();
+        assert_eq!(plan.to, root.join("frontend"));
+        plan.apply().unwrap();
+        assert_eq!(next_task(&root.join("backend")), "frontend");
+        assert!(plan_unarchive(&root.join("frontend")).unwrap_err().ends_with("is not in an archived/ directory"));
+
+        // A running task stays put
+        std::fs::write(root.join("backend").join(crate::status::LOCK_FILE), format!("{}\n", std::process::id())).unwrap();
+        assert!(plan_archive(&root.join("backend"), SystemTime::now()).unwrap_err().contains("is running"));
+    }
+
+    #[test]
+    fn archive_stamps_come_off_when_unarchived() {
+        assert_eq!(archive_stamp(1_792_161_000), "20261016-1430");
+        assert_eq!(strip_archive_stamp("login-20261016-1430"), "login");
+        assert_eq!(strip_archive_stamp("login-v2"), "login-v2");
+        assert_eq!(strip_archive_stamp("-20261016-1430"), "-20261016-1430");
+    }
+}
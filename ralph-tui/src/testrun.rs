@@ -0,0 +1,107 @@
+//! Test-run summaries parsed from the agent's output
+//!
+//! Each supported framework is one row in `TEST_SUMMARY_FORMATS`: a regex that
+//! picks out its summary line. Pass/fail counts are then read from the
+//! "N passed" / "N failed" phrases every supported format shares, so adding a
+//! framework is usually just a new row.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+/// Summary line patterns by framework
+const TEST_SUMMARY_FORMATS: &[(&str, &str)] = &[
+    // test result: ok. 42 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out
+    ("cargo", r"test result: (?:ok|FAILED)\. \d+ passed; \d+ failed"),
+    // ===== 1 failed, 42 passed in 0.12s =====
+    ("pytest", r"^=+ .*\b\d+ (?:passed|failed)\b.* in [\d.]+s"),
+    // Tests:       1 failed, 42 passed, 43 total
+    ("jest", r"^Tests:\s+.*\b\d+ total"),
+];
+
+static SUMMARY_PATTERNS: LazyLock<Vec<(&'static str, Regex)>> = LazyLock::new(|| {
+    TEST_SUMMARY_FORMATS
+        .iter()
+        .map(|(framework, pattern)| (*framework, Regex::new(pattern).expect("test summary pattern is valid")))
+        .collect()
+});
+static PASSED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+) passed").expect("valid regex"));
+static FAILED: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(\d+) failed").expect("valid regex"));
+
+/// Pass/fail counts from one test run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestResult {
+    pub framework: &'static str,
+    pub passed: u32,
+    pub failed: u32,
+}
+
+/// Parse a single (ANSI-stripped) output line as a test summary
+/// Empty summaries (0 passed, 0 failed) are ignored so that e.g. cargo's doc-test
+/// line doesn't hide the real result printed just before it.
+pub fn parse_test_summary(line: &str) -> Option<TestResult> {
+    let line = line.trim();
+    let framework = SUMMARY_PATTERNS
+        .iter()
+        .find(|(_, pattern)| pattern.is_match(line))
+        .map(|(framework, _)| *framework)?;
+    let count = |pattern: &Regex| {
+        pattern
+            .captures(line)
+            .and_then(|c| c[1].parse::<u32>().ok())
+            .unwrap_or(0)
+    };
+    let result = TestResult {
+        framework,
+        passed: count(&PASSED),
+        failed: count(&FAILED),
+    };
+    (result.passed + result.failed > 0).then_some(result)
+}
+
+/// The last test summary in a block of (ANSI-stripped) output
+pub fn last_test_summary(text: &str) -> Option<TestResult> {
+    text.lines().rev().find_map(parse_test_summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(framework: &'static str, passed: u32, failed: u32) -> Option<TestResult> {
+        Some(TestResult { framework, passed, failed })
+    }
+
+    #[test]
+    fn parses_cargo_summaries() {
+        assert_eq!(
+            parse_test_summary("test result: ok. 42 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.15s"),
+            result("cargo", 42, 0)
+        );
+        assert_eq!(
+            parse_test_summary("test result: FAILED. 41 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out"),
+            result("cargo", 41, 1)
+        );
+        // Doc tests with nothing to run don't replace the real result
+        let output = "test result: FAILED. 3 passed; 2 failed; 0 ignored\n   Doc-tests x\ntest result: ok. 0 passed; 0 failed; 0 ignored\n";
+        assert_eq!(last_test_summary(output), result("cargo", 3, 2));
+    }
+
+    #[test]
+    fn parses_pytest_summaries() {
+        assert_eq!(parse_test_summary("============ 42 passed in 0.51s ============"), result("pytest", 42, 0));
+        assert_eq!(
+            parse_test_summary("===== 1 failed, 42 passed, 2 warnings in 3.20s ====="),
+            result("pytest", 42, 1)
+        );
+        assert_eq!(parse_test_summary("=== test session starts ==="), None);
+    }
+
+    #[test]
+    fn parses_jest_summaries() {
+        assert_eq!(parse_test_summary("Tests:       1 failed, 42 passed, 43 total"), result("jest", 42, 1));
+        assert_eq!(parse_test_summary("Tests:       7 passed, 7 total"), result("jest", 7, 0));
+        // The suites line isn't the test count
+        assert_eq!(parse_test_summary("Test Suites: 1 failed, 3 passed, 4 total"), None);
+    }
+}
@@ -0,0 +1,49 @@
+//! Helpers shared by the unit and integration tests
+//!
+//! Tests that touch the filesystem each get a `TempDir`: a fresh directory under
+//! the system temp directory, removed with everything in it when the value is
+//! dropped, so a failing assertion doesn't leave it behind.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Directories handed out so far in this process (keeps reused names apart)
+static CREATED: AtomicU32 = AtomicU32::new(0);
+
+/// A scratch directory that is deleted on drop
+#[derive(Debug)]
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create `ralph-<name>-<pid>-<n>` under the system temp directory
+    pub fn new(name: &str) -> Self {
+        let n = CREATED.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("ralph-{}-{}-{}", name, std::process::id(), n));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
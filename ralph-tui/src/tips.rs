@@ -0,0 +1,114 @@
+//! Tips in the footer, for features new users tend to miss
+//!
+//! In Ralph mode with no view open, the footer's free space between the mode and
+//! the keybindings shows one tip at a time, changing every TIP_ROTATE_SECS. A tip
+//! names the key bound to its action and only shows where it applies (the links tip
+//! once the agent printed a link, say). Once an action's key was pressed this
+//! session its tip is retired. The footer drops the tip before anything else when
+//! space runs short; `hide_tips = true` in ralph.toml turns tips off.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::app::{Mode, RalphViewMode};
+use crate::keymap::{KeyAction, KeyMap};
+
+/// How long each tip stays in the footer
+pub const TIP_ROTATE_SECS: u64 = 30;
+
+/// When a tip applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum When {
+    Always,
+    HasStories,
+    HasLinks,
+    HasErrors,
+}
+
+/// Tips in rotation order: the action whose key they name, when they apply, and the
+/// text after "press <key> to"
+const TIPS: [(KeyAction, When, &str); 9] = [
+    (KeyAction::ToggleStoryDetails, When::HasStories, "view acceptance criteria for the selected story"),
+    (KeyAction::EnterClaudeMode, When::Always, "type directly to the agent"),
+    (KeyAction::ToggleProgress, When::Always, "read the agent's progress.txt"),
+    (KeyAction::ToggleIterations, When::Always, "see how each iteration ended"),
+    (KeyAction::ToggleChanges, When::Always, "review the files changed this iteration"),
+    (KeyAction::ToggleActivity, When::Always, "list what the agent did recently"),
+    (KeyAction::ToggleLinks, When::HasLinks, "open a link from the agent's output"),
+    (KeyAction::ToggleErrors, When::HasErrors, "list this iteration's error lines"),
+    (KeyAction::UndoMenu, When::Always, "undo a change to prd.json"),
+];
+
+/// What the footer tip depends on
+pub struct TipContext<'a> {
+    pub mode: Mode,
+    pub view: RalphViewMode,
+    pub keymap: &'a KeyMap,
+    // Actions whose key was pressed this session
+    pub used: &'a HashSet<KeyAction>,
+    pub has_stories: bool,
+    pub has_links: bool,
+    pub has_errors: bool,
+    pub session_elapsed: Duration,
+}
+
+/// The tip for the footer right now (None outside Ralph mode's main view, or once
+/// every tip that applies was acted on)
+pub fn footer_tip(context: &TipContext) -> Option<String> {
+    if context.mode != Mode::Ralph || context.view != RalphViewMode::Normal {
+        return None;
+    }
+    let applies = |when: When| match when {
+        When::Always => true,
+        When::HasStories => context.has_stories,
+        When::HasLinks => context.has_links,
+        When::HasErrors => context.has_errors,
+    };
+    let tips: Vec<_> = TIPS
+        .iter()
+        .filter(|(action, when, _)| applies(*when) && !context.used.contains(action))
+        .filter(|(action, _, _)| !context.keymap.chords(*action).is_empty())
+        .collect();
+    if tips.is_empty() {
+        return None;
+    }
+    let (action, _, text) = tips[(context.session_elapsed.as_secs() / TIP_ROTATE_SECS) as usize % tips.len()];
+    Some(format!("Tip: press {} to {}", context.keymap.label(*action), text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tips_rotate_and_retire_once_used() {
+        let keymap = KeyMap::default();
+        let mut used = HashSet::new();
+        let tip = |used: &HashSet<KeyAction>, secs: u64, view: RalphViewMode| {
+            footer_tip(&TipContext {
+                mode: Mode::Ralph,
+                view,
+                keymap: &keymap,
+                used,
+                has_stories: true,
+                has_links: false,
+                has_errors: false,
+                session_elapsed: Duration::from_secs(secs),
+            })
+        };
+        assert_eq!(
+            tip(&used, 0, RalphViewMode::Normal).as_deref(),
+            Some("Tip: press s to view acceptance criteria for the selected story")
+        );
+        assert_eq!(tip(&used, 31, RalphViewMode::Normal).as_deref(), Some("Tip: press i to type directly to the agent"));
+        // Seven tips apply without links or errors, so the eighth period starts over
+        assert_eq!(tip(&used, 7 * TIP_ROTATE_SECS, RalphViewMode::Normal), tip(&used, 0, RalphViewMode::Normal));
+        // Not while a view is open
+        assert_eq!(tip(&used, 0, RalphViewMode::Progress), None);
+
+        used.insert(KeyAction::ToggleStoryDetails);
+        assert_eq!(tip(&used, 0, RalphViewMode::Normal).as_deref(), Some("Tip: press i to type directly to the agent"));
+        used.extend(TIPS.iter().map(|(action, _, _)| *action));
+        assert_eq!(tip(&used, 0, RalphViewMode::Normal), None);
+    }
+}
@@ -0,0 +1,147 @@
+//! Tripwire for destructive shell commands
+//!
+//! The agent runs with its permission prompts skipped, so nothing can stop a
+//! command before it starts. Instead, each Bash activity parsed from the agent's
+//! output is matched against dangerous patterns (`dangerous_commands` in
+//! ralph.toml, or the defaults below), and a match raises a modal asking whether
+//! to let it run, interrupt the agent or pause the loop. By the time the modal
+//! shows, the command may already be running or finished.
+//!
+//! A pattern matches when its words appear in order within one command of a
+//! pipeline or `&&`/`;` chain, ignoring case and surrounding quotes, so
+//! `git push --force` also catches `git push origin main --force`. Words are
+//! compared whole: `git push --force` doesn't match `--force-with-lease`. A word
+//! ending in `*` matches any word it starts, e.g. `mkfs*` matches `mkfs.ext4`.
+
+/// Patterns used when ralph.toml doesn't set `dangerous_commands`
+pub const DEFAULT_DANGEROUS_COMMANDS: &[&str] = &[
+    "rm -rf",
+    "rm -fr",
+    "git push --force",
+    "git push -f",
+    "git reset --hard",
+    "git clean -fd",
+    "DROP TABLE",
+    "DROP DATABASE",
+    "TRUNCATE TABLE",
+    "mkfs*",
+];
+
+/// Characters that separate the commands of a chain or pipeline
+const COMMAND_SEPARATORS: [char; 4] = [';', '|', '&', '\n'];
+
+/// Characters trimmed from each word (quoting and grouping)
+const WORD_TRIM: [char; 6] = ['"', '\'', '`', '(', ')', ','];
+
+/// Dangerous command patterns, each split into lowercase words
+#[derive(Debug, Clone, Default)]
+pub struct Tripwire {
+    patterns: Vec<(String, Vec<String>)>,
+}
+
+impl Tripwire {
+    pub fn new(patterns: &[String]) -> Self {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| (pattern.clone(), words(pattern)))
+            .filter(|(_, words)| !words.is_empty())
+            .collect();
+        Self { patterns }
+    }
+
+    /// The first pattern `command` matches
+    pub fn check(&self, command: &str) -> Option<&str> {
+        let commands: Vec<Vec<String>> = command.split(COMMAND_SEPARATORS).map(words).collect();
+        self.patterns
+            .iter()
+            .find(|(_, pattern)| commands.iter().any(|command| contains_in_order(command, pattern)))
+            .map(|(pattern, _)| pattern.as_str())
+    }
+}
+
+/// Lowercase words of `text`, with quotes and brackets trimmed
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(WORD_TRIM).to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Whether a command word matches a pattern word (a trailing `*` matches any rest)
+fn word_matches(word: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => word.starts_with(prefix),
+        None => word == pattern,
+    }
+}
+
+/// Whether `pattern`'s words appear in `command` in order, starting with the first one
+fn contains_in_order(command: &[String], pattern: &[String]) -> bool {
+    command.iter().enumerate().filter(|(_, word)| word_matches(word, &pattern[0])).any(|(start, _)| {
+        let mut rest = command[start + 1..].iter();
+        pattern[1..].iter().all(|p| rest.any(|word| word_matches(word, p)))
+    })
+}
+
+/// A command that tripped the wire, waiting for an answer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TripwireAlert {
+    pub command: String,
+    pub pattern: String,
+    /// --parallel lane whose agent ran it (None for the single agent)
+    pub lane: Option<usize>,
+}
+
+/// What the user chose in the tripwire modal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TripwireAnswer {
+    LetItRun,
+    Interrupt, // Send Ctrl+C to the agent that ran it
+    PauseLoop, // Hold the loop at the next iteration boundary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Tripwire {
+        Tripwire::new(&DEFAULT_DANGEROUS_COMMANDS.iter().map(|p| p.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn matches_default_patterns_in_chains_and_quotes() {
+        let tripwire = defaults();
+        assert_eq!(tripwire.check("rm -rf target/"), Some("rm -rf"));
+        assert_eq!(tripwire.check("cd build && sudo rm -rf ."), Some("rm -rf"));
+        assert_eq!(tripwire.check("git push origin main --force"), Some("git push --force"));
+        assert_eq!(tripwire.check("git -C repo push -f"), Some("git push -f"));
+        assert_eq!(tripwire.check(r#"psql -c "drop table users;""#), Some("DROP TABLE"));
+        assert_eq!(tripwire.check("(mkfs.ext4 /dev/sdb1)"), Some("mkfs*"));
+        assert_eq!(tripwire.check("mkfs /dev/sdb1"), Some("mkfs*"));
+    }
+
+    #[test]
+    fn ignores_safe_lookalikes() {
+        let tripwire = defaults();
+        for command in [
+            "rm -r build",
+            "cargo test",
+            "git push --force-with-lease",
+            "git push origin main",
+            "git log | grep force",
+            "echo push; git status --force",
+            "ls -rf",
+            "",
+        ] {
+            assert_eq!(tripwire.check(command), None, "{:?}", command);
+        }
+    }
+
+    #[test]
+    fn custom_patterns_replace_the_defaults() {
+        let tripwire = Tripwire::new(&["terraform destroy".to_string(), "  ".to_string()]);
+        assert_eq!(tripwire.check("terraform -chdir=infra destroy -auto-approve"), Some("terraform destroy"));
+        assert_eq!(tripwire.check("rm -rf /"), None);
+        assert_eq!(Tripwire::new(&[]).check("rm -rf /"), None);
+    }
+}
@@ -0,0 +1,245 @@
+//! Acceptance criteria that ralph-tui can check itself (`verify` in prd.json)
+//!
+//! A v2.0 criterion may carry a `verify` shell command, e.g. `cargo test login`.
+//! `V` in the story details runs the selected story's commands, and with
+//! `verify_criteria = true` in ralph.toml every unpassed criterion's command also
+//! runs at each iteration boundary. Commands run one after another on a
+//! background thread, with `sh -c` from the repository root and the iteration's
+//! RALPH_* variables, and are killed after their timeout. Exit 0 marks the
+//! criterion passed in prd.json with the tail of its output (stdout and stderr)
+//! as the evidence; any other outcome is only reported, so a failing command
+//! never un-passes a criterion.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::hooks::HookStatus;
+use crate::prd::Prd;
+
+/// How long a verify command may run when ralph.toml doesn't set `verify_timeout_secs`
+pub const DEFAULT_VERIFY_TIMEOUT_SECS: u64 = 120;
+
+/// Lines of a passing command's output kept as the criterion's evidence
+pub const EVIDENCE_TAIL_LINES: usize = 20;
+
+/// How often a running command is checked for having exited
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A criterion to verify, and the command that does it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyTarget {
+    pub story_id: String,
+    /// Index in the story's acceptanceCriteria
+    pub criterion: usize,
+    /// The criterion's id, or its 1-based number
+    pub label: String,
+    pub command: String,
+}
+
+/// The criteria of `story_id` (every story when None) that have a verify command,
+/// leaving out passed ones when `unpassed_only`
+pub fn verify_targets(prd: &Prd, story_id: Option<&str>, unpassed_only: bool) -> Vec<VerifyTarget> {
+    prd.user_stories
+        .iter()
+        .filter(|story| story_id.is_none_or(|id| story.id == id))
+        .flat_map(|story| {
+            story.acceptance_criteria.iter().enumerate().filter_map(move |(i, criterion)| {
+                let command = criterion.verify.clone()?;
+                if unpassed_only && criterion.passes {
+                    return None;
+                }
+                Some(VerifyTarget { story_id: story.id.clone(), criterion: i, label: criterion.label(i), command })
+            })
+        })
+        .collect()
+}
+
+/// How one verify command ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyResult {
+    pub target: VerifyTarget,
+    pub status: HookStatus,
+    /// Last EVIDENCE_TAIL_LINES lines of stdout and stderr, interleaved as written
+    pub output_tail: String,
+    pub ran: Duration,
+}
+
+impl VerifyResult {
+    pub fn passed(&self) -> bool {
+        self.status == HookStatus::Exited(Some(0))
+    }
+
+    /// Evidence recorded for a passing command: the command line, then its output tail
+    pub fn evidence(&self) -> String {
+        if self.output_tail.is_empty() {
+            format!("$ {}", self.target.command)
+        } else {
+            format!("$ {}\n{}", self.target.command, self.output_tail)
+        }
+    }
+}
+
+/// Verify commands running on a background thread, one after another
+pub struct VerifyJob {
+    /// Commands not reported yet
+    pub remaining: usize,
+    receiver: Receiver<VerifyResult>,
+}
+
+impl VerifyJob {
+    /// Run each target's command from `repo` with the given environment, killing it after `timeout`
+    pub fn start(targets: Vec<VerifyTarget>, repo: PathBuf, env: Vec<(String, String)>, timeout: Duration) -> Self {
+        let remaining = targets.len();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for target in targets {
+                let started = Instant::now();
+                let (status, output_tail) = run_captured(&target.command, &repo, &env, timeout);
+                let result = VerifyResult { target, status, output_tail, ran: started.elapsed() };
+                if sender.send(result).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { remaining, receiver }
+    }
+
+    /// Results that came in since the last call
+    pub fn try_results(&mut self) -> Vec<VerifyResult> {
+        let mut results = Vec::new();
+        while self.remaining > 0 {
+            match self.receiver.try_recv() {
+                Ok(result) => results.push(result),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.remaining = 0;
+                    break;
+                }
+            }
+            self.remaining -= 1;
+        }
+        results
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+/// Run `command` with `sh -c` in `cwd`, returning how it ended and the tail of its output
+/// Both streams go to one temp file, so their lines keep the order they were written in.
+fn run_captured(command: &str, cwd: &Path, env: &[(String, String)], timeout: Duration) -> (HookStatus, String) {
+    let output_path = std::env::temp_dir().join(format!(
+        "ralph-verify-{}-{}.log",
+        std::process::id(),
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0)
+    ));
+    let status = match File::create(&output_path).and_then(|file| Ok((file.try_clone()?, file))) {
+        Ok((stdout, stderr)) => {
+            let spawned = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .current_dir(cwd)
+                .envs(env.iter().cloned())
+                .stdin(Stdio::null())
+                .stdout(stdout)
+                .stderr(stderr)
+                .spawn();
+            match spawned {
+                Ok(mut child) => {
+                    let deadline = Instant::now() + timeout;
+                    loop {
+                        match child.try_wait() {
+                            Ok(Some(status)) => break HookStatus::Exited(status.code()),
+                            Ok(None) if Instant::now() >= deadline => {
+                                let _ = child.kill();
+                                let _ = child.wait();
+                                break HookStatus::TimedOut;
+                            }
+                            Ok(None) => thread::sleep(POLL_INTERVAL),
+                            Err(e) => break HookStatus::Failed(e.to_string()),
+                        }
+                    }
+                }
+                Err(e) => HookStatus::Failed(e.to_string()),
+            }
+        }
+        Err(e) => HookStatus::Failed(e.to_string()),
+    };
+    let output = std::fs::read(&output_path).map(|bytes| String::from_utf8_lossy(&bytes).to_string()).unwrap_or_default();
+    let _ = std::fs::remove_file(&output_path);
+    (status, output_tail(&output, EVIDENCE_TAIL_LINES))
+}
+
+/// The last `lines` non-blank lines of `output`
+fn output_tail(output: &str, lines: usize) -> String {
+    let kept: Vec<&str> = output.lines().map(str::trim_end).filter(|line| !line.is_empty()).collect();
+    kept[kept.len().saturating_sub(lines)..].join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn targets_are_criteria_with_commands() {
+        let prd: Prd = serde_json::from_str(
+            r#"{"project": "p", "taskDir": "t", "type": "feature", "description": "", "userStories": [
+                {"id": "US-1", "title": "", "description": "", "priority": 1, "passes": false, "notes": "", "acceptanceCriteria": [
+                    "plain",
+                    {"id": "AC-1", "description": "tests pass", "passes": true, "verify": "cargo test"},
+                    {"description": "health", "passes": false, "verify": "curl -f localhost/health"},
+                    {"id": "AC-3", "description": "blank", "passes": false, "verify": "  "}]},
+                {"id": "US-2", "title": "", "description": "", "priority": 2, "passes": false, "notes": "", "acceptanceCriteria": [
+                    {"id": "AC-1", "description": "lint", "passes": false, "verify": "cargo clippy"}]}
+            ]}"#,
+        )
+        .unwrap();
+        let labels = |targets: Vec<VerifyTarget>| -> Vec<String> {
+            targets.iter().map(|t| format!("{} {} {}", t.story_id, t.label, t.command)).collect()
+        };
+        assert_eq!(labels(verify_targets(&prd, Some("US-1"), false)), ["US-1 AC-1 cargo test", "US-1 3 curl -f localhost/health"]);
+        assert_eq!(labels(verify_targets(&prd, None, true)), ["US-1 3 curl -f localhost/health", "US-2 AC-1 cargo clippy"]);
+    }
+
+    #[test]
+    fn runs_commands_in_order_and_keeps_the_output_tail() {
+        let dir = TempDir::new("verify-run");
+        std::fs::write(dir.join("marker"), "").unwrap();
+        let target = |label: &str, command: &str| VerifyTarget {
+            story_id: "US-1".to_string(),
+            criterion: 0,
+            label: label.to_string(),
+            command: command.to_string(),
+        };
+        let targets = vec![
+            target("a", "test -f marker && seq 1 30 && echo \"to stderr $RALPH_ITERATION\" >&2"),
+            target("b", "echo nope; exit 4"),
+            target("c", "sleep 5"),
+        ];
+        let env = vec![("RALPH_ITERATION".to_string(), "7".to_string())];
+        let mut job = VerifyJob::start(targets, dir.to_path_buf(), env, Duration::from_millis(300));
+        let mut results = Vec::new();
+        let started = Instant::now();
+        while !job.is_done() && started.elapsed() < Duration::from_secs(10) {
+            results.extend(job.try_results());
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(results.len(), 3);
+
+        assert!(results[0].passed());
+        let tail: Vec<&str> = results[0].output_tail.lines().collect();
+        assert_eq!(tail.len(), EVIDENCE_TAIL_LINES);
+        assert_eq!((tail[0], tail[EVIDENCE_TAIL_LINES - 1]), ("12", "to stderr 7"));
+        assert!(results[0].evidence().starts_with("$ test -f marker"));
+
+        assert_eq!(results[1].status, HookStatus::Exited(Some(4)));
+        assert_eq!(results[1].output_tail, "nope");
+        assert_eq!(results[2].status, HookStatus::TimedOut);
+    }
+}
@@ -0,0 +1,334 @@
+//! The core loop end to end: spawn the agent, end iterations, restart, stop on completion
+//!
+//! A shell script stands in for the agent. It is launched through an agent
+//! profile (ralph.toml in a temporary $HOME) and scripted per run.
+
+#![cfg(unix)]
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+#[path = "../src/testutil.rs"]
+mod testutil;
+
+use testutil::TempDir;
+
+const PRD: &str = r#"{"project": "p", "taskDir": "task", "type": "feature", "description": "",
+    "userStories": [{"id": "US-001", "title": "First", "description": "",
+        "acceptanceCriteria": ["a"], "priority": 1, "passes": false, "notes": ""}]}"#;
+
+/// Temporary home, task directory and fake agent for one test
+struct Fixture {
+    dir: TempDir,
+    task_dir: PathBuf,
+    agent: PathBuf,
+}
+
+impl Fixture {
+    /// Set up a task with PRD and a `fake` agent profile running `script` (a shell script body)
+    /// The script sees the run number in `$n`, and `{task}` is replaced with the task directory.
+    fn new(name: &str, script: &str) -> Self {
+        let dir = TempDir::new(&format!("loop-{}", name));
+        let task_dir = dir.join("task");
+        let config_dir = dir.join("home/.config/ralph");
+        std::fs::create_dir_all(&task_dir).unwrap();
+        std::fs::create_dir_all(&config_dir).unwrap();
+        std::fs::write(task_dir.join("prd.json"), PRD).unwrap();
+
+        // Every run first bumps the run counter into $n
+        let agent = dir.join("fake-agent");
+        let counter = "n=$(cat '{count}' 2>/dev/null || echo 0)\nn=$((n + 1))\necho $n > '{count}'";
+        let script = format!("#!/bin/sh\n{}\n{}", counter, script)
+            .replace("{count}", &dir.join("runs").display().to_string())
+            .replace("{task}", &task_dir.display().to_string());
+        std::fs::write(&agent, script).unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&agent, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let fixture = Self { dir, task_dir, agent };
+        fixture.set_agent_args("\"{prompt}\"");
+        fixture
+    }
+
+    /// Replace the `fake` profile's argument template (a TOML array body)
+    fn set_agent_args(&self, args: &str) {
+        std::fs::write(
+            self.dir.join("home/.config/ralph/ralph.toml"),
+            format!("[agents.fake]\nbinary = \"{}\"\nargs = [{}]\n", self.agent.display(), args),
+        )
+        .unwrap();
+    }
+
+    /// Times the agent was started
+    fn runs(&self) -> u32 {
+        std::fs::read_to_string(self.dir.join("runs"))
+            .ok()
+            .and_then(|n| n.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// `iteration=N source=S state=X` from each line of the iteration log
+    fn iterations(&self) -> Vec<String> {
+        std::fs::read_to_string(self.task_dir.join(".ralph-iterations.log"))
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| line.split_whitespace().nth(1).is_some_and(|field| field.starts_with("iteration=")))
+            .map(|line| line.split_whitespace().skip(1).take(3).collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    /// Run ralph-tui on the task inside a PTY until it exits
+    /// Returns the exit code and everything it printed.
+    fn run(&self, extra_args: &[&str]) -> (u32, String) {
+        // ralph-tui needs a terminal, so run it inside a PTY
+        let pair = native_pty_system()
+            .openpty(PtySize { rows: 30, cols: 100, pixel_width: 0, pixel_height: 0 })
+            .unwrap();
+        let mut cmd = CommandBuilder::new(env!("CARGO_BIN_EXE_ralph-tui"));
+        cmd.arg(&self.task_dir);
+        cmd.args(["--yes", "--agent", "fake"]);
+        cmd.args(extra_args);
+        cmd.cwd(self.dir.as_os_str());
+        cmd.env("HOME", self.dir.join("home"));
+        let mut ralph = pair.slave.spawn_command(cmd).unwrap();
+        drop(pair.slave);
+
+        // Drain the TUI's output so it never blocks on a full PTY
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let mut reader = pair.master.try_clone_reader().unwrap();
+        let sink = Arc::clone(&output);
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                sink.lock().unwrap().extend_from_slice(&buf[..n]);
+            }
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let status = loop {
+            if let Some(status) = ralph.try_wait().unwrap() {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = ralph.kill();
+                panic!("ralph-tui did not exit; iterations so far: {:?}", self.iterations());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        };
+        // Let the reader catch the last output
+        std::thread::sleep(Duration::from_millis(200));
+        let output = String::from_utf8_lossy(&output.lock().unwrap()).to_string();
+        (status.exit_code(), output)
+    }
+}
+
+/// Write done.json next to prd.json: the PRD with its only story passing
+fn write_passing_prd(task_dir: &Path) {
+    std::fs::write(task_dir.join("done.json"), PRD.replace(r#""passes": false"#, r#""passes": true"#)).unwrap();
+}
+
+#[test]
+fn loop_restarts_until_the_agent_reports_completion() {
+    // Run 1 ends through the stop hook's control file (the agent keeps running),
+    // run 2 just exits, run 3 marks the story done and prints the completion sentinel
+    let fixture = Fixture::new(
+        "complete",
+        r#"case $n in
+  1) echo 'working on US-001'
+     printf '{"signal": "iteration-complete"}' > '{task}/.ralph-signal'
+     exec sleep 30 ;;
+  2) echo 'still working'
+     exit 0 ;;
+  *) cat '{task}/done.json' > '{task}/prd.json'
+     echo '<promise>COMPLETE</promise>'
+     exit 0 ;;
+esac
+"#,
+    );
+    write_passing_prd(&fixture.task_dir);
+
+    let (code, _) = fixture.run(&[]);
+    assert_eq!(code, 0);
+    assert_eq!(
+        fixture.iterations(),
+        [
+            "iteration=1 source=control-file state=NeedsRestart",
+            "iteration=2 source=child-exited state=NeedsRestart",
+            "iteration=3 source=child-exited state=Completed",
+        ]
+    );
+    // Completion stops the loop: no fourth agent
+    assert_eq!(fixture.runs(), 3);
+    let prd = std::fs::read_to_string(fixture.task_dir.join("prd.json")).unwrap();
+    assert!(prd.contains(r#""passes": true"#));
+}
+
+#[test]
+fn loop_stops_at_the_iteration_limit() {
+    let fixture = Fixture::new("limit", "echo 'no progress'\nexit 0\n");
+
+    let (code, _) = fixture.run(&["--iterations", "2"]);
+    assert_eq!(code, 0);
+    assert_eq!(fixture.runs(), 2);
+    let iterations = fixture.iterations();
+    assert_eq!(iterations.len(), 2, "{:?}", iterations);
+    assert!(iterations.iter().all(|line| line.contains("source=child-exited")), "{:?}", iterations);
+}
+
+#[test]
+fn once_reports_the_outcome_of_a_single_iteration() {
+    let fixture = Fixture::new("once", "echo 'one step'\nexit 0\n");
+
+    let (code, output) = fixture.run(&["--once"]);
+    assert_eq!(code, 2);
+    assert!(output.contains("result=needs_more stories=0/1"), "{:?}", output);
+    assert_eq!(fixture.iterations(), ["iteration=1 source=child-exited state=NeedsRestart"]);
+    assert_eq!(fixture.runs(), 1);
+}
+
+#[test]
+fn passed_story_ends_a_silent_iteration_but_not_a_streaming_one() {
+    // The agent marks the story done, keeps printing for 3 seconds, then hangs without
+    // ever signalling the end of its turn
+    let fixture = Fixture::new(
+        "prd-idle",
+        r#"cat '{task}/done.json' > '{task}/prd.json'
+i=0
+while [ $i -lt 15 ]; do echo "still streaming $i"; sleep 0.2; i=$((i + 1)); done
+exec sleep 30
+"#,
+    );
+    write_passing_prd(&fixture.task_dir);
+
+    let (code, _) = fixture.run(&["--prd-idle", "2", "--linger", "0"]);
+    assert_eq!(code, 0);
+    assert_eq!(fixture.runs(), 1);
+    let log = std::fs::read_to_string(fixture.task_dir.join(".ralph-iterations.log")).unwrap();
+    let line = log.lines().find(|line| line.contains(" iteration=1 ")).unwrap();
+    assert!(line.contains("source=prd-progress+idle"), "{}", line);
+    // Not before the output stopped, and well before the agent would have exited
+    let duration: u64 = line.split("duration_secs=").nth(1).and_then(|rest| rest.split(' ').next()).unwrap().parse().unwrap();
+    assert!((4..30).contains(&duration), "{}", line);
+}
+
+#[test]
+fn prompt_file_is_removed_once_the_agent_exits() {
+    // The agent notes where its prompt file is and checks that it holds the prompt
+    let fixture = Fixture::new(
+        "prompt-file",
+        r#"echo "$1" > '{task}/prompt-path'
+grep -q 'prd.json' "$1" && echo found > '{task}/prompt-seen'
+exit 0
+"#,
+    );
+    fixture.set_agent_args("\"{prompt_file}\"");
+
+    let (code, _) = fixture.run(&["--iterations", "1"]);
+    assert_eq!(code, 0);
+    assert!(fixture.task_dir.join("prompt-seen").exists());
+    let path = std::fs::read_to_string(fixture.task_dir.join("prompt-path")).unwrap();
+    assert!(path.contains("ralph_prompt_"), "{}", path);
+    assert!(!Path::new(path.trim()).exists(), "{} was left behind", path.trim());
+}
+
+#[test]
+fn prompt_too_long_for_argv_is_pasted_intact() {
+    // Like Claude Code: a moment to start up, raw mode and bracketed paste on, then read
+    // whatever is typed in (noting anything typed before it was ready)
+    let fixture = Fixture::new(
+        "long-prompt",
+        r#"echo $# > '{task}/argc'
+exec 3<&0
+sleep 1
+stty raw -echo min 0 time 0
+dd bs=65536 count=1 <&3 > '{task}/early' 2>/dev/null
+stty min 1
+printf '\033[?2004h> '
+cat <&3 > '{task}/typed' &
+i=0
+while ! grep -q 'END-OF-PROMPT' '{task}/typed' 2>/dev/null && [ $i -lt 100 ]; do sleep 0.1; i=$((i + 1)); done
+sleep 0.5
+kill $!
+exit 0
+"#,
+    );
+    // Over ARGV_PROMPT_LIMIT, with blank lines and a line longer than a canonical-mode
+    // terminal keeps (4095 bytes)
+    let mut prompt_md: String = (0..600).map(|i| format!("Step {}: keep this instruction intact.\n\n", i)).collect();
+    prompt_md.push_str(&"long ".repeat(1000));
+    prompt_md.push_str("\nEND-OF-PROMPT");
+    assert!(prompt_md.len() > 16 * 1024);
+    std::fs::write(fixture.task_dir.join("prompt.md"), &prompt_md).unwrap();
+
+    let (code, _) = fixture.run(&["--iterations", "1"]);
+    assert_eq!(code, 0);
+    // Left out of the command line, and typed in as one bracketed paste followed by Enter
+    assert_eq!(std::fs::read_to_string(fixture.task_dir.join("argc")).unwrap().trim(), "0");
+    let early = std::fs::read_to_string(fixture.task_dir.join("early")).unwrap();
+    assert!(early.is_empty(), "{} bytes typed before the agent was ready", early.len());
+    let typed = std::fs::read_to_string(fixture.task_dir.join("typed")).unwrap();
+    assert!(typed.starts_with("\x1b[200~# Ralph Agent Instructions\n"), "{:?}", &typed[..typed.len().min(80)]);
+    assert!(typed.ends_with(&format!("{}\x1b[201~\r", prompt_md)), "{} bytes typed", typed.len());
+}
+
+/// Send a GET for `path` to the metrics server; None until it answers
+fn http_get(port: u16, path: &str) -> Option<String> {
+    use std::io::Write;
+    let mut stream = std::net::TcpStream::connect(("127.0.0.1", port)).ok()?;
+    stream.set_read_timeout(Some(Duration::from_secs(2))).ok()?;
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).ok()?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).ok()?;
+    Some(response)
+}
+
+#[test]
+fn metrics_endpoint_is_scraped_during_the_run() {
+    // Run 1 ends quickly; run 2 stays up long enough to be scraped, then completes
+    let fixture = Fixture::new(
+        "metrics",
+        r#"case $n in
+  1) echo 'first pass'
+     exit 0 ;;
+  *) sleep 4
+     cat '{task}/done.json' > '{task}/prd.json'
+     echo '<promise>COMPLETE</promise>'
+     exit 0 ;;
+esac
+"#,
+    );
+    write_passing_prd(&fixture.task_dir);
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+    // Scrape in the background until the first iteration is counted
+    let scraper = std::thread::spawn(move || {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while Instant::now() < deadline {
+            if let Some(metrics) = http_get(port, "/metrics")
+                && metrics.contains("\nralph_iterations_total 1\n")
+            {
+                return Some((metrics, http_get(port, "/status")?));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        None
+    });
+
+    let (code, _) = fixture.run(&["--metrics-port", &port.to_string()]);
+    assert_eq!(code, 0);
+    let (metrics, status) = scraper.join().unwrap().expect("metrics endpoint never reported an iteration");
+    assert!(metrics.starts_with("HTTP/1.1 200 OK\r\n"), "{}", metrics);
+    assert!(metrics.contains("\nralph_iteration_duration_seconds_count 1\n"), "{}", metrics);
+    assert!(status.contains(r#""state": "running""#), "{}", status);
+    assert!(status.contains(r#""storyId": "US-001""#), "{}", status);
+    // The server stops with the session
+    assert!(http_get(port, "/metrics").is_none());
+}
@@ -0,0 +1,113 @@
+//! SIGTERM handling: ralph-tui must not leave the agent running as an orphan
+
+#![cfg(unix)]
+
+use std::io::Read;
+use std::time::{Duration, Instant};
+
+use portable_pty::{native_pty_system, CommandBuilder, PtySize};
+
+#[path = "../src/testutil.rs"]
+mod testutil;
+
+use testutil::TempDir;
+
+/// Wait up to `timeout` for `check` to succeed
+fn wait_for(timeout: Duration, mut check: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if check() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+/// Whether `pid` is still running (zombies awaiting reaping count as dead)
+fn is_alive(pid: i32) -> bool {
+    match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        // State is the field after the parenthesised command name
+        Ok(stat) => stat.rsplit(')').next().is_some_and(|rest| !rest.trim_start().starts_with('Z')),
+        // Gone (or no procfs): probe with signal 0
+        Err(_) => unsafe { libc::kill(pid, 0) == 0 },
+    }
+}
+
+#[test]
+fn sigterm_kills_agent_and_exits() {
+    let dir = TempDir::new("sigterm");
+    let task_dir = dir.join("task");
+    let bin_dir = dir.join("bin");
+    std::fs::create_dir_all(&task_dir).unwrap();
+    std::fs::create_dir_all(&bin_dir).unwrap();
+    std::fs::write(
+        task_dir.join("prd.json"),
+        r#"{"project": "p", "taskDir": "task", "type": "feature", "description": "",
+            "userStories": [{"id": "US-001", "title": "First", "description": "",
+                "acceptanceCriteria": ["a"], "priority": 1, "passes": false, "notes": ""}]}"#,
+    )
+    .unwrap();
+
+    // Stand-in agent: answers the preflight's --version, otherwise records its pid and waits to be killed
+    let pid_file = dir.join("agent.pid");
+    let agent = bin_dir.join("claude");
+    std::fs::write(
+        &agent,
+        format!(
+            "#!/bin/sh\n[ \"$1\" = --version ] && echo 1.0.0 && exit 0\necho $$ > '{}'\nexec sleep 60\n",
+            pid_file.display()
+        ),
+    )
+    .unwrap();
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&agent, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    // ralph-tui needs a terminal, so run it inside a PTY
+    let pair = native_pty_system()
+        .openpty(PtySize { rows: 30, cols: 100, pixel_width: 0, pixel_height: 0 })
+        .unwrap();
+    let mut cmd = CommandBuilder::new(env!("CARGO_BIN_EXE_ralph-tui"));
+    cmd.arg(&task_dir);
+    cmd.arg("--yes");
+    cmd.cwd(dir.as_os_str());
+    let path = std::env::var("PATH").unwrap_or_default();
+    cmd.env("PATH", format!("{}:{}", bin_dir.display(), path));
+    let mut ralph = pair.slave.spawn_command(cmd).unwrap();
+    drop(pair.slave);
+
+    // Drain the TUI's output so it never blocks on a full PTY
+    let mut reader = pair.master.try_clone_reader().unwrap();
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while matches!(reader.read(&mut buf), Ok(n) if n > 0) {}
+    });
+
+    assert!(
+        wait_for(Duration::from_secs(10), || {
+            std::fs::read_to_string(&pid_file).is_ok_and(|s| s.trim().parse::<i32>().is_ok())
+        }),
+        "agent never started"
+    );
+    let agent_pid: i32 = std::fs::read_to_string(&pid_file).unwrap().trim().parse().unwrap();
+    assert!(is_alive(agent_pid));
+
+    let ralph_pid = ralph.process_id().unwrap() as i32;
+    unsafe { libc::kill(ralph_pid, libc::SIGTERM) };
+
+    let mut status = None;
+    assert!(
+        wait_for(Duration::from_secs(10), || {
+            status = ralph.try_wait().unwrap();
+            status.is_some()
+        }),
+        "ralph-tui did not exit on SIGTERM"
+    );
+    assert_eq!(status.unwrap().exit_code(), 128 + libc::SIGTERM as u32);
+    assert!(wait_for(Duration::from_secs(5), || !is_alive(agent_pid)), "agent survived SIGTERM");
+
+    let log = std::fs::read_to_string(task_dir.join(".ralph-iterations.log")).unwrap_or_default();
+    assert!(log.contains("source=signal"), "iteration log: {:?}", log);
+}